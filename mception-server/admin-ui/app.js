@@ -0,0 +1,374 @@
+"use strict";
+
+// Minimal admin dashboard talking to the MCePtion Admin API under /admin.
+
+const API = "/admin";
+
+let currentConfig = { leaf_mcps: {}, agents: {} };
+
+function setStatus(message, isError) {
+  const el = document.getElementById("status");
+  el.textContent = message || "";
+  el.className = isError ? "error" : "";
+}
+
+// Admin token for servers started with --admin-token, kept for the session
+const TOKEN_KEY = "mception-admin-token";
+
+// Version of the admin API the dashboard is written against
+const API_VERSION = "2";
+
+async function api(method, path, body) {
+  const options = { method, headers: { "X-Mception-Api-Version": API_VERSION } };
+  if (body !== undefined) {
+    options.headers["Content-Type"] = "application/json";
+    options.body = JSON.stringify(body);
+  }
+  let token = sessionStorage.getItem(TOKEN_KEY);
+  if (token) {
+    options.headers["Authorization"] = "Bearer " + token;
+  }
+  let response = await fetch(API + path, options);
+  if (response.status === 401) {
+    token = window.prompt("Admin token");
+    if (token) {
+      sessionStorage.setItem(TOKEN_KEY, token);
+      options.headers["Authorization"] = "Bearer " + token;
+      response = await fetch(API + path, options);
+    }
+  }
+  const text = await response.text();
+  const data = text ? JSON.parse(text) : null;
+  if (!response.ok) {
+    const message = data && data.error ? data.error.message : response.statusText;
+    throw new Error(method + " " + path + " failed (" + response.status + "): " + message);
+  }
+  return data;
+}
+
+function el(tag, text, attrs) {
+  const node = document.createElement(tag);
+  if (text !== undefined && text !== null) {
+    node.textContent = String(text);
+  }
+  for (const [key, value] of Object.entries(attrs || {})) {
+    node.setAttribute(key, value);
+  }
+  return node;
+}
+
+function sortedIds(map) {
+  return Object.keys(map || {}).sort();
+}
+
+// Tabs
+
+function showTab(name) {
+  for (const button of document.querySelectorAll("nav button")) {
+    button.classList.toggle("active", button.dataset.tab === name);
+  }
+  for (const section of document.querySelectorAll(".tab")) {
+    section.classList.toggle("active", section.id === "tab-" + name);
+  }
+  if (name === "audit") {
+    loadAudit().catch((e) => setStatus(e.message, true));
+  }
+}
+
+// Configuration (MCPs and agents)
+
+async function loadConfig() {
+  currentConfig = await api("GET", "/config");
+  renderMcps();
+  renderAgents();
+}
+
+function renderMcps() {
+  const rows = document.getElementById("mcp-rows");
+  rows.replaceChildren();
+  const editSelect = document.getElementById("mcp-edit-id");
+  editSelect.replaceChildren();
+
+  for (const id of replicaOrder(currentConfig.leaf_mcps)) {
+    const mcp = currentConfig.leaf_mcps[id];
+    const tr = el("tr");
+    tr.append(
+      el("td", id),
+      el("td", mcp.name || ""),
+      el("td", mcp.replica_group || ""),
+      el("td", mcp.region || ""),
+      el("td", mcp.transport ? mcp.transport.type : ""),
+      el("td", mcp.is_local ? "yes" : "no"),
+      el("td", mcp.reachable_by_agent ? "yes" : "no"),
+    );
+    const actions = el("td");
+    const del = el("button", "Delete", { class: "danger" });
+    del.addEventListener("click", () => deleteMcp(id));
+    actions.append(del);
+    tr.append(actions);
+    rows.append(tr);
+
+    editSelect.append(el("option", id, { value: id }));
+  }
+  fillEditForm();
+
+  const createMcps = document.getElementById("agent-create-mcps");
+  createMcps.replaceChildren(...mcpCheckboxes([], null));
+}
+
+// Leaf MCP ids with the replicas of a group next to each other, ungrouped ones first
+function replicaOrder(mcps) {
+  const group = (id) => mcps[id].replica_group || "";
+  return sortedIds(mcps).sort((a, b) => group(a).localeCompare(group(b)));
+}
+
+function fillEditForm() {
+  const id = document.getElementById("mcp-edit-id").value;
+  const textarea = document.querySelector("#mcp-edit textarea[name=config]");
+  const mcp = currentConfig.leaf_mcps[id];
+  textarea.value = mcp ? JSON.stringify(mcp, null, 2) : "";
+}
+
+function mcpCheckboxes(selected, onToggle) {
+  const ids = sortedIds(currentConfig.leaf_mcps).concat(sortedIds(currentConfig.agents));
+  return ids.map((id) => {
+    const label = el("label");
+    const box = el("input", null, { type: "checkbox", value: id });
+    box.checked = selected.includes(id);
+    if (onToggle) {
+      box.addEventListener("change", () => onToggle(id, box.checked, box));
+    }
+    label.append(box, document.createTextNode(" " + id));
+    return label;
+  });
+}
+
+function renderAgents() {
+  const list = document.getElementById("agent-list");
+  list.replaceChildren();
+
+  for (const agentId of sortedIds(currentConfig.agents)) {
+    const agent = currentConfig.agents[agentId];
+    const card = el("div", null, { class: "agent" });
+    card.append(el("h4", agentId + (agent.is_connected ? " (connected)" : "")));
+
+    const boxes = el("div", null, { class: "checkboxes" });
+    boxes.append(
+      ...mcpCheckboxes(agent.allowed_mcp_ids || [], (mcpId, checked, box) =>
+        toggleAllowedMcp(agentId, mcpId, checked).catch((e) => {
+          box.checked = !checked;
+          setStatus(e.message, true);
+        }),
+      ),
+    );
+    card.append(boxes);
+
+    const del = el("button", "Delete agent", { class: "danger" });
+    del.addEventListener("click", () => deleteAgent(agentId));
+    card.append(del);
+    list.append(card);
+  }
+}
+
+async function deleteMcp(id) {
+  const reason = prompt("Reason for deleting leaf MCP '" + id + "'?");
+  if (reason === null) {
+    return;
+  }
+  try {
+    await api("DELETE", "/leaf/" + encodeURIComponent(id), {
+      reason: reason || null,
+    });
+    setStatus("Deleted leaf MCP '" + id + "'");
+    await loadConfig();
+  } catch (e) {
+    setStatus(e.message, true);
+  }
+}
+
+async function deleteAgent(agentId) {
+  const reason = prompt("Reason for deleting agent '" + agentId + "'?");
+  if (reason === null) {
+    return;
+  }
+  try {
+    await api("DELETE", "/agent/" + encodeURIComponent(agentId), {
+      reason: reason || null,
+    });
+    setStatus("Deleted agent '" + agentId + "'");
+    await loadConfig();
+  } catch (e) {
+    setStatus(e.message, true);
+  }
+}
+
+async function toggleAllowedMcp(agentId, mcpId, allowed) {
+  const body = { mcp_id: mcpId, reason: "Changed via admin UI" };
+  if (allowed) {
+    await api("POST", "/agent/" + encodeURIComponent(agentId) + "/allowed_mcps", body);
+  } else {
+    await api("DELETE", "/agent/" + encodeURIComponent(agentId) + "/allowed_mcps", body);
+  }
+  setStatus((allowed ? "Allowed '" : "Removed '") + mcpId + "' for agent '" + agentId + "'");
+  await loadConfig();
+}
+
+async function createMcp(event) {
+  event.preventDefault();
+  const form = event.target;
+  const data = new FormData(form);
+  const id = data.get("id").trim() || null;
+  const target = data.get("target").trim();
+
+  const transport =
+    data.get("transport") === "stdio"
+      ? {
+          type: "stdio",
+          command: target,
+          args: data.get("args").split(/\s+/).filter(Boolean),
+          env: null,
+        }
+      : { type: "https", url: target, headers: null };
+
+  try {
+    const result = await api("POST", "/leaf", {
+      id,
+      config: {
+        name: data.get("name") || null,
+        description: data.get("description") || null,
+        instructions: data.get("instructions") || null,
+        transport,
+        is_local: data.get("is_local") === "on",
+        reachable_by_agent: data.get("reachable_by_agent") === "on",
+        config: {},
+      },
+      reason: data.get("reason") || null,
+    });
+    form.reset();
+    setStatus("Created leaf MCP '" + result.id + "'");
+    await loadConfig();
+  } catch (e) {
+    setStatus(e.message, true);
+  }
+}
+
+async function updateMcp(event) {
+  event.preventDefault();
+  const data = new FormData(event.target);
+  const id = data.get("id");
+  let config;
+  try {
+    config = JSON.parse(data.get("config"));
+  } catch (e) {
+    setStatus("Invalid JSON: " + e.message, true);
+    return;
+  }
+  try {
+    await api("PUT", "/leaf/" + encodeURIComponent(id) + "/config", {
+      config,
+      reason: data.get("reason") || null,
+    });
+    setStatus("Updated leaf MCP '" + id + "'");
+    await loadConfig();
+  } catch (e) {
+    setStatus(e.message, true);
+  }
+}
+
+async function createAgent(event) {
+  event.preventDefault();
+  const form = event.target;
+  const data = new FormData(form);
+  const agentId = data.get("agent_id").trim() || null;
+  const allowed = Array.from(
+    form.querySelectorAll("#agent-create-mcps input:checked"),
+  ).map((box) => box.value);
+
+  try {
+    const result = await api("POST", "/agent", {
+      agent_id: agentId,
+      name: data.get("name").trim() || null,
+      allowed_mcp_ids: allowed,
+    });
+    form.reset();
+    setStatus("Created agent '" + result.agent_id + "'");
+    await loadConfig();
+  } catch (e) {
+    setStatus(e.message, true);
+  }
+}
+
+// Audit log
+
+function targetLabel(target) {
+  switch (target.type) {
+    case "leaf_mcp":
+    case "agent":
+      return target.type + ":" + target.id;
+    case "agent_allowed_mcp":
+      return "agent:" + target.agent_id + " -> " + target.mcp_id;
+    case "bundle":
+      return "bundle:" + target.name;
+    default:
+      return target.type;
+  }
+}
+
+async function loadAudit() {
+  const data = new FormData(document.getElementById("audit-filter"));
+  const action = (data.get("action") || "").toLowerCase();
+  const target = (data.get("target") || "").toLowerCase();
+  const actor = (data.get("actor") || "").toLowerCase();
+  const limit = parseInt(data.get("limit"), 10) || 100;
+
+  const entries = (await api("GET", "/audit")).entries
+    .filter((entry) => !action || entry.action.type.includes(action))
+    .filter((entry) => !target || targetLabel(entry.target).toLowerCase().includes(target))
+    .filter((entry) => !actor || (entry.actor || "").toLowerCase().includes(actor))
+    .sort((a, b) => b.timestamp.localeCompare(a.timestamp))
+    .slice(0, limit);
+
+  const rows = document.getElementById("audit-rows");
+  rows.replaceChildren(
+    ...entries.map((entry) => {
+      const tr = el("tr");
+      tr.append(
+        el("td", entry.timestamp),
+        el("td", entry.action.type),
+        el("td", targetLabel(entry.target)),
+        el("td", entry.actor || ""),
+        el("td", entry.reason || ""),
+      );
+      return tr;
+    }),
+  );
+}
+
+// Backups
+
+async function createBackup() {
+  try {
+    const result = await api("POST", "/config/backup");
+    document.getElementById("backup-results").append(el("li", result.backup_path));
+    setStatus("Backup created");
+  } catch (e) {
+    setStatus(e.message, true);
+  }
+}
+
+document.addEventListener("DOMContentLoaded", () => {
+  for (const button of document.querySelectorAll("nav button")) {
+    button.addEventListener("click", () => showTab(button.dataset.tab));
+  }
+  document.getElementById("mcp-create").addEventListener("submit", createMcp);
+  document.getElementById("mcp-edit").addEventListener("submit", updateMcp);
+  document.getElementById("mcp-edit-id").addEventListener("change", fillEditForm);
+  document.getElementById("agent-create").addEventListener("submit", createAgent);
+  document.getElementById("audit-filter").addEventListener("submit", (event) => {
+    event.preventDefault();
+    loadAudit().catch((e) => setStatus(e.message, true));
+  });
+  document.getElementById("backup-create").addEventListener("click", createBackup);
+
+  loadConfig().catch((e) => setStatus(e.message, true));
+});
@@ -1,6 +1,19 @@
 pub mod commands;
+pub mod doctor;
 
+use chrono::{DateTime, Utc};
 use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
+
+use crate::core::labels::{self, LabelSelector};
+use crate::services::api_versions;
+use crate::services::audit_buffer;
+use crate::services::auth::AdminToken;
+use crate::services::config_limits;
+use crate::services::discovery::ClientKind;
+use crate::services::idempotency;
+use crate::services::ids::{IdGenerator, NanoIds, PrefixCounterIds, SlugIds, UuidIds};
+use crate::services::internals;
 
 #[derive(Parser)]
 #[command(name = "mception-server")]
@@ -15,6 +28,29 @@ pub struct Cli {
     #[arg(short, long, default_value = "audit.log")]
     pub audit_log: String,
 
+    /// Rotate the audit log file before it grows beyond this size, e.g.
+    /// `10M`. Rotated segments are gzip-compressed as `<file>.1.gz` and so on.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub audit_max_size: Option<u64>,
+
+    /// Rotate the audit log file once it holds this many entries
+    #[arg(long, value_name = "ENTRIES")]
+    pub audit_max_entries: Option<usize>,
+
+    /// Delete audit log entries older than this many days, checked hourly.
+    /// Rotated segments are deleted once all their entries are this old.
+    #[arg(long, value_name = "DAYS")]
+    pub audit_retention_days: Option<u32>,
+
+    /// Where the configuration and audit log are stored
+    #[arg(long, value_enum, default_value = "file", global = true)]
+    pub storage: StorageBackend,
+
+    /// SQLite database with the configuration and audit log, for `--storage sqlite`
+    /// (will be created if it doesn't exist)
+    #[arg(long, default_value = "mception.db")]
+    pub database_url: String,
+
     /// Server bind address
     #[arg(long, default_value = "0.0.0.0")]
     pub host: String,
@@ -23,19 +59,231 @@ pub struct Cli {
     #[arg(short, long, default_value = "8080")]
     pub port: u16,
 
+    /// Indentation of the saved configuration file
+    #[arg(long, value_enum, default_value = "pretty")]
+    pub config_style: ConfigStyle,
+
+    /// Gzip-compress configuration backups
+    #[arg(long)]
+    pub backup_compress: bool,
+
+    /// Write full backups or diffs against the latest full backup
+    #[arg(long, value_enum, default_value = "full")]
+    pub backup_mode: BackupMode,
+
+    /// Number of differential backups after which a new full backup is written
+    #[arg(long, default_value = "10")]
+    pub backup_full_every: usize,
+
+    /// Number of backups to keep, older ones are pruned after each backup
+    #[arg(long)]
+    pub backup_keep: Option<usize>,
+
+    /// Scheme generating ids of leaf MCPs and agents created without one
+    #[arg(long, value_enum, default_value = "slug")]
+    pub id_scheme: IdScheme,
+
+    /// How far an audit entry's timestamp may lag its predecessor's before a
+    /// clock skew warning is logged, e.g. `1s`
+    #[arg(long, default_value = "1s", value_parser = parse_period)]
+    pub audit_max_clock_skew: chrono::Duration,
+
+    /// Audit entries held back while the audit storage fails, under the
+    /// audit policy's `buffer` failure policy. Further ones are lost.
+    #[arg(long, default_value_t = audit_buffer::DEFAULT_CAPACITY)]
+    pub audit_buffer_capacity: usize,
+
+    /// Journal of configuration mutations not yet saved to the config file
+    #[arg(long, default_value = "config.journal")]
+    pub journal: String,
+
+    /// Agent availability data file path (will be created if it doesn't exist)
+    #[arg(long, default_value = "availability.json")]
+    pub availability_file: String,
+
+    /// How long an agent counts as connected after it last fetched its
+    /// configuration or its WebSocket was last alive, e.g. `90s`
+    #[arg(long, default_value = "90s", value_parser = parse_period)]
+    pub agent_staleness: chrono::Duration,
+
+    /// How long before their expiry agent tokens and grants are reported at
+    /// `/admin/expiring`, in metrics, to the agents and the expiry webhook,
+    /// e.g. `72h`
+    #[arg(long, default_value = "72h", value_parser = parse_period)]
+    pub expiry_window: chrono::Duration,
+
+    /// URL to post an event to when an agent token or grant enters the
+    /// expiry window and when it expires
+    #[arg(long)]
+    pub expiry_webhook: Option<String>,
+
+    /// Summarize the agent tokens and grants expiring within the window in
+    /// the audit log once a day
+    #[arg(long)]
+    pub audit_expiry_summary: bool,
+
+    /// How often agents' WebSockets are pinged. An agent missing two pongs in
+    /// a row is disconnected, e.g. `30s`
+    #[arg(long, default_value = "30s", value_parser = parse_period)]
+    pub agent_ping_interval: chrono::Duration,
+
+    /// How long a forwarded request may take by default, for leaf MCPs
+    /// without `timeout_ms` or `config.timeout` of their own, e.g. `30s`
+    #[arg(long, default_value = "30s", value_parser = parse_period)]
+    pub leaf_timeout: chrono::Duration,
+
+    /// Consecutive failed forwards to a leaf MCP that open its circuit
+    /// breaker, refusing further forwards for `--breaker-cooldown`
+    #[arg(long, default_value_t = 5)]
+    pub breaker_failure_threshold: u32,
+
+    /// How long an open circuit breaker refuses forwards before it lets a
+    /// probe through, e.g. `30s`
+    #[arg(long, default_value = "30s", value_parser = parse_period)]
+    pub breaker_cooldown: chrono::Duration,
+
+    /// Seconds between background health probes of the leaf MCPs, shown by
+    /// `GET /admin/status`. 0 disables probing.
+    #[arg(long, default_value_t = 60)]
+    pub health_interval_secs: u64,
+
+    /// How long agent availability data is kept, e.g. `30d`
+    #[arg(long, default_value = "30d", value_parser = parse_period)]
+    pub availability_retention: chrono::Duration,
+
+    /// Sessions of HTTPS leaf MCPs, kept to resume them after a restart
+    /// instead of initializing each leaf MCP again
+    #[arg(long, default_value = "leaf-sessions.json")]
+    pub leaf_session_file: String,
+
+    /// How long answers to admin requests with an `Idempotency-Key` are
+    /// replayed to retries, e.g. `24h`
+    #[arg(long, default_value = "24h", value_parser = parse_period)]
+    pub idempotency_window: chrono::Duration,
+
+    /// Answers to admin requests with an `Idempotency-Key` kept at most, the
+    /// oldest is evicted beyond it
+    #[arg(long, default_value_t = idempotency::DEFAULT_CAPACITY)]
+    pub idempotency_capacity: usize,
+
+    /// Persist answers to admin requests with an `Idempotency-Key` to this
+    /// file, so retries across a restart are replayed too
+    #[arg(long, value_name = "FILE")]
+    pub idempotency_file: Option<String>,
+
+    /// Record of the current run, replaced by the shutdown report on a clean stop
+    #[arg(long, default_value = "last-shutdown.json")]
+    pub shutdown_report_file: String,
+
+    /// Don't write the shutdown report, the previous run is then reported as unknown
+    #[arg(long)]
+    pub no_shutdown_report: bool,
+
+    /// How long in-flight requests may take to finish once shutdown begins, e.g. `30s`.
+    /// The server exits with status 1 if they didn't.
+    #[arg(long, alias = "shutdown-timeout", default_value = "30s", value_parser = parse_period)]
+    pub drain_timeout: chrono::Duration,
+
+    /// Allow injecting latency and errors into leaf MCP forwarding via the admin API
+    #[arg(long)]
+    pub enable_fault_injection: bool,
+
+    /// Append a JSON line for each request forwarded to a leaf MCP to this
+    /// file, so deletes can be judged by recent usage. Off by default.
+    #[arg(long, value_name = "FILE")]
+    pub access_log: Option<String>,
+
+    /// Leave out who made the changes in agents' own change feeds
+    /// (`GET /agent/<id>/changes`)
+    #[arg(long)]
+    pub mask_change_actors: bool,
+
+    /// Leaf MCP tool listings kept in memory, the longest cached is evicted beyond it
+    #[arg(long, default_value_t = internals::DEFAULT_TOOL_CACHE_CAPACITY)]
+    pub tool_cache_capacity: usize,
+
+    /// Forwarded requests that may wait for agents' answers at the same time,
+    /// further ones are rejected with `503`
+    #[arg(long, default_value_t = internals::DEFAULT_MAX_PENDING_AGENT_REQUESTS)]
+    pub max_pending_agent_requests: usize,
+
+    /// Forwarded requests that may wait for the same agent's answers at the
+    /// same time, further ones are rejected with `503`
+    #[arg(long, default_value_t = internals::DEFAULT_MAX_PENDING_REQUESTS_PER_AGENT)]
+    pub max_pending_requests_per_agent: usize,
+
+    /// Requests per minute each agent may make to the agent and leaf MCP
+    /// routes, further ones are rejected with `429`. Leaf MCP calls naming no
+    /// agent are counted per client address. Unlimited without.
+    #[arg(long, value_name = "PER_MINUTE")]
+    pub agent_rate_limit: Option<u32>,
+
+    /// Largest `config` of a leaf MCP or agent, in bytes of JSON, larger ones
+    /// are refused with `422`
+    #[arg(long, default_value_t = config_limits::DEFAULT_MAX_BYTES)]
+    pub max_config_bytes: usize,
+
+    /// Deepest nesting of objects and arrays in a leaf MCP's or agent's `config`
+    #[arg(long, default_value_t = config_limits::DEFAULT_MAX_DEPTH)]
+    pub max_config_depth: usize,
+
+    /// Most object keys in a leaf MCP's or agent's `config`, counted at every level
+    #[arg(long, default_value_t = config_limits::DEFAULT_MAX_KEYS)]
+    pub max_config_keys: usize,
+
+    /// Bearer token the admin API requires, as `<token>` or as `<actor>=<token>`
+    /// to audit its requests as `<actor>`. Repeat it, or separate tokens in the
+    /// environment variable with commas, to accept several. Without one the
+    /// admin API is open.
+    #[arg(
+        long = "admin-token",
+        env = "MCEPTION_ADMIN_TOKEN",
+        value_delimiter = ',',
+        value_parser = AdminToken::parse,
+        hide_env_values = true
+    )]
+    pub admin_tokens: Vec<AdminToken>,
+
+    /// Admin API version served to clients that don't send the
+    /// `X-Mception-Api-Version` header, e.g. `1` to keep requiring the
+    /// `should_*` flags for older scripts
+    #[arg(long, value_parser = api_versions::parse_version, default_value_t = api_versions::CURRENT_VERSION)]
+    pub api_compat: u32,
+
+    /// Apply pending schema migrations when the server starts, or refuse to
+    /// start until they were applied with the `migrate` command
+    #[arg(long, value_enum, default_value = "on-start")]
+    pub migrate: MigrateMode,
+
+    /// Accept a reviewed gap between the audit log and the configuration on
+    /// start, recording the acknowledgement so it is no longer warned about
+    #[arg(long)]
+    pub acknowledge_consistency_gap: bool,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
 
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Default)]
 pub enum Commands {
     /// Start the MCePtion server (default)
+    #[default]
     Start,
     /// Show current configuration
     ShowConfig {
-        /// Output format
-        #[arg(short, long, default_value = "pretty")]
-        format: OutputFormat,
+        /// Output format (default: from the extension of `--output`, else pretty)
+        #[arg(short, long)]
+        format: Option<OutputFormat>,
+        /// Show the configuration as it was at this RFC 3339 timestamp (read-only)
+        #[arg(long)]
+        as_of: Option<DateTime<Utc>>,
+        /// Show the leaf MCPs and agents of included files too, merged as the
+        /// server loads them
+        #[arg(long)]
+        resolved: bool,
+        /// Write the configuration to FILE as JSON or YAML instead of printing it
+        #[arg(short, long, value_name = "FILE", conflicts_with = "as_of")]
+        output: Option<PathBuf>,
     },
     /// Show audit log entries
     ShowAudit {
@@ -54,7 +302,406 @@ pub enum Commands {
         /// Filter by actor
         #[arg(long)]
         actor: Option<String>,
+        /// Only entries at or after this time (RFC 3339)
+        #[arg(long)]
+        since: Option<DateTime<Utc>>,
+        /// Only entries before this time (RFC 3339)
+        #[arg(long)]
+        until: Option<DateTime<Utc>>,
+    },
+    /// Apply pending storage schema migrations and exit
+    Migrate {
+        /// Only check for pending migrations, exiting non-zero if there are any
+        #[arg(long)]
+        check: bool,
+    },
+    /// Scan the audit log for corrupt entries and check its hash chain. Exits
+    /// 0 if clean, 2 if corrupt, 3 if no entry could be recovered and 4 if an
+    /// entry doesn't link to the one before
+    VerifyAudit {
+        /// Output format
+        #[arg(short, long, default_value = "pretty")]
+        format: OutputFormat,
+    },
+    /// Write a copy of the audit log with only its valid entries, leaving the
+    /// original untouched. Exits 0 if clean, 2 if repaired and 3 if unrecoverable
+    RepairAudit {
+        /// Path of the cleaned copy (default: `<audit log>.repaired`)
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Output format
+        #[arg(short, long, default_value = "pretty")]
+        format: OutputFormat,
+    },
+    /// Reconstruct the configuration from the audit log alone, replaying its
+    /// leaf MCP, agent, grant, bundle and policy changes on an empty one.
+    /// With `--verify` exits 2 if it differs from the stored configuration
+    Replay {
+        /// Audit log file to replay (default: the server's audit log)
+        #[arg(long, value_name = "FILE")]
+        audit: Option<String>,
+        /// Write the reconstructed configuration to FILE as JSON or YAML
+        #[arg(short, long, value_name = "FILE")]
+        output: PathBuf,
+        /// Only replay entries up to this RFC 3339 timestamp
+        #[arg(long)]
+        until: Option<DateTime<Utc>>,
+        /// Compare the reconstruction with the stored configuration
+        #[arg(long)]
+        verify: bool,
+        /// Output format
+        #[arg(short, long, default_value = "pretty")]
+        format: OutputFormat,
+    },
+    /// Report agent availability from the availability data file
+    Availability {
+        /// Report window, e.g. `7d`
+        #[arg(long, default_value = "7d", value_parser = parse_period)]
+        since: chrono::Duration,
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "pretty")]
+        format: ReportFormat,
+    },
+    /// Explain whether an agent may use an MCP, and a tool on it, listing
+    /// every rule considered
+    Explain {
+        /// Agent to check
+        agent_id: String,
+        /// Leaf MCP or agent the agent wants to use
+        #[arg(long)]
+        mcp: String,
+        /// Tool on the MCP
+        #[arg(long)]
+        tool: Option<String>,
+        /// Output format
+        #[arg(short, long, default_value = "pretty")]
+        format: OutputFormat,
+    },
+    /// Find MCP servers configured in MCP clients on this machine and register
+    /// them as leaf MCPs. Run it while the server is stopped, or upload the
+    /// client config to `POST /admin/discover` of a running server instead.
+    Discover {
+        /// Client to read, or `path` to read FILE (default: all clients)
+        #[arg(long, value_enum)]
+        from: Option<DiscoverSource>,
+        /// Client config file, with `--from path`
+        #[arg(required_if_eq("from", "path"))]
+        file: Option<String>,
+        /// Register the new MCPs without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+        /// Output format
+        #[arg(short, long, default_value = "pretty")]
+        format: OutputFormat,
+    },
+    /// Delete leaf MCPs (or agents) by tag or id. Without `--confirm` only
+    /// shows what would be deleted along with the token confirming it.
+    DeleteMcps {
+        /// Delete everything with this tag in its `config.tags`
+        #[arg(long, required_unless_present = "ids", conflicts_with = "ids")]
+        tag: Option<String>,
+        /// Delete these ids, comma separated
+        #[arg(long, value_delimiter = ',')]
+        ids: Option<Vec<String>>,
+        /// Delete agents instead of leaf MCPs
+        #[arg(long)]
+        agents: bool,
+        /// Only show the plan (the default without `--confirm`)
+        #[arg(long, conflicts_with = "confirm")]
+        dry_run: bool,
+        /// Confirmation token from the dry run
+        #[arg(long)]
+        confirm: Option<String>,
+        /// Reason recorded in the audit log
+        #[arg(long)]
+        reason: Option<String>,
+        /// Output format
+        #[arg(short, long, default_value = "pretty")]
+        format: OutputFormat,
+    },
+    /// List the leaf MCPs, like `GET /admin/leaf`
+    ListMcps {
+        /// Only leaf MCPs whose labels match, e.g. `team=payments,env!=prod`
+        #[arg(long)]
+        label: Option<LabelSelector>,
+        /// Output format
+        #[arg(short, long, default_value = "pretty")]
+        format: OutputFormat,
+        /// URL of a running server to show the health of the leaf MCPs from,
+        /// as of its last background probes
+        #[arg(long)]
+        server: Option<String>,
+    },
+    /// List the agents, like `GET /admin/agent`
+    ListAgents {
+        /// Only agents whose labels match, e.g. `team=payments`
+        #[arg(long)]
+        label: Option<LabelSelector>,
+        /// Output format
+        #[arg(short, long, default_value = "pretty")]
+        format: OutputFormat,
+    },
+    /// Register a leaf MCP, run with the server stopped. Checked like
+    /// `POST /admin/leaf`.
+    AddMcp {
+        /// Id of the leaf MCP (default: generated from the name)
+        id: Option<String>,
+        #[arg(long)]
+        name: Option<String>,
+        /// Description for administrators
+        #[arg(long)]
+        description: Option<String>,
+        /// Command of a stdio leaf MCP
+        #[arg(long, required_unless_present = "url", conflicts_with = "url")]
+        command: Option<String>,
+        /// Argument of the stdio command, repeated for each argument
+        #[arg(long = "arg", requires = "command", allow_hyphen_values = true)]
+        args: Vec<String>,
+        /// URL of an HTTPS leaf MCP
+        #[arg(long)]
+        url: Option<String>,
+        /// Reach the URL with the legacy SSE transport instead
+        #[arg(long, requires = "url")]
+        sse: bool,
+        /// Label as `key=value`, repeated for each label
+        #[arg(long = "label", value_parser = labels::parse_label)]
+        labels: Vec<(String, String)>,
+        /// Reason recorded in the audit log
+        #[arg(long)]
+        reason: Option<String>,
+        /// Output format
+        #[arg(short, long, default_value = "pretty")]
+        format: OutputFormat,
+    },
+    /// Delete a leaf MCP along with its grants, run with the server stopped
+    RemoveMcp {
+        id: String,
+        /// Reason recorded in the audit log
+        #[arg(long)]
+        reason: Option<String>,
+        /// Output format
+        #[arg(short, long, default_value = "pretty")]
+        format: OutputFormat,
+    },
+    /// Register an agent, run with the server stopped. Checked like
+    /// `POST /admin/agent`.
+    AddAgent {
+        /// Id of the agent (default: generated from the name)
+        agent_id: Option<String>,
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long)]
+        description: Option<String>,
+        /// MCPs the agent may use, comma separated
+        #[arg(long, value_delimiter = ',')]
+        allow: Vec<String>,
+        /// Label as `key=value`, repeated for each label
+        #[arg(long = "label", value_parser = labels::parse_label)]
+        labels: Vec<(String, String)>,
+        /// Reason recorded in the audit log
+        #[arg(long)]
+        reason: Option<String>,
+        /// Output format
+        #[arg(short, long, default_value = "pretty")]
+        format: OutputFormat,
+    },
+    /// Delete an agent, run with the server stopped
+    RemoveAgent {
+        agent_id: String,
+        /// Reason recorded in the audit log
+        #[arg(long)]
+        reason: Option<String>,
+        /// Output format
+        #[arg(short, long, default_value = "pretty")]
+        format: OutputFormat,
+    },
+    /// Allow an agent to use an MCP, run with the server stopped
+    AllowMcp {
+        agent_id: String,
+        /// Leaf MCP, agent or `bundle:<name>` to allow
+        mcp_id: String,
+        /// Reason recorded in the audit log
+        #[arg(long)]
+        reason: Option<String>,
+        /// Output format
+        #[arg(short, long, default_value = "pretty")]
+        format: OutputFormat,
+    },
+    /// Stop allowing an agent to use an MCP, run with the server stopped
+    DisallowMcp {
+        agent_id: String,
+        mcp_id: String,
+        /// Reason recorded in the audit log
+        #[arg(long)]
+        reason: Option<String>,
+        /// Output format
+        #[arg(short, long, default_value = "pretty")]
+        format: OutputFormat,
+    },
+    /// Replace the configuration with one written by `show-config --output`,
+    /// run with the server stopped. Checked like `POST /admin/config/restore`,
+    /// backing up the configuration first.
+    Import {
+        /// JSON file, or YAML for a `.yaml` or `.yml` file
+        input: PathBuf,
+        /// Add and overwrite the leaf MCPs, agents and bundles of the file,
+        /// keeping the others
+        #[arg(long)]
+        merge: bool,
+        /// Reason recorded in the audit log
+        #[arg(long)]
+        reason: Option<String>,
+        /// Output format
+        #[arg(short, long, default_value = "pretty")]
+        format: OutputFormat,
     },
+    /// Check the registered leaf MCPs against the registration policy. Exits 0
+    /// if all are allowed and 2 if some are not
+    Validate {
+        /// Check against the policy in this JSON file instead, e.g. before
+        /// setting it with `PUT /admin/policy`
+        #[arg(long)]
+        policy_report: Option<String>,
+        /// Output format
+        #[arg(short, long, default_value = "pretty")]
+        format: OutputFormat,
+    },
+    /// Check the environment: resolved flags, storage paths, a running server
+    /// and leaf MCPs. Exits 0 if all checks pass, 1 on warnings and 2 on failures
+    Doctor {
+        /// Output format
+        #[arg(short, long, default_value = "pretty")]
+        format: OutputFormat,
+    },
+    /// Set the instructions agents get for a leaf MCP on a running server
+    SetInstructions {
+        /// Leaf MCP to update
+        leaf_mcp_id: String,
+        /// Instructions text
+        #[arg(required_unless_present_any = ["file", "clear"], conflicts_with_all = ["file", "clear"])]
+        instructions: Option<String>,
+        /// Read the instructions from this file
+        #[arg(long, conflicts_with = "clear")]
+        file: Option<String>,
+        /// Remove the instructions
+        #[arg(long)]
+        clear: bool,
+        /// Reason recorded in the audit log
+        #[arg(long)]
+        reason: Option<String>,
+        /// URL of the running server
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+    },
+    /// Change the log level of a running server
+    SetLogLevel {
+        /// Base level, e.g. `debug`
+        level: String,
+        /// Additional per-target directives, e.g. `mception_server::services=trace`
+        #[arg(long)]
+        filter: Option<String>,
+        /// Revert to the default level after this long, e.g. `15m`
+        #[arg(long)]
+        duration: Option<String>,
+        /// Reason recorded in the audit log
+        #[arg(long)]
+        reason: Option<String>,
+        /// URL of the running server
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+    },
+}
+
+impl Commands {
+    /// Whether the command works on the loaded configuration
+    pub fn loads_configuration(&self) -> bool {
+        !matches!(
+            self,
+            Commands::Migrate { .. }
+                | Commands::VerifyAudit { .. }
+                | Commands::RepairAudit { .. }
+                | Commands::Availability { .. }
+                | Commands::Doctor { .. }
+                | Commands::SetInstructions { .. }
+                | Commands::SetLogLevel { .. }
+                // Checks the included files before loading
+                | Commands::Validate { .. }
+        )
+    }
+}
+
+#[derive(Clone, Copy, clap::ValueEnum, Debug, PartialEq, Eq)]
+pub enum BackupMode {
+    Full,
+    Differential,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum, Debug, PartialEq, Eq)]
+pub enum ConfigStyle {
+    Pretty,
+    Compact,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum, Debug, PartialEq, Eq)]
+pub enum IdScheme {
+    Slug,
+    Uuid,
+    Nanoid,
+    PrefixCounter,
+}
+
+impl IdScheme {
+    pub fn generator(self) -> Box<dyn IdGenerator> {
+        match self {
+            IdScheme::Slug => Box::new(SlugIds),
+            IdScheme::Uuid => Box::new(UuidIds),
+            IdScheme::Nanoid => Box::new(NanoIds),
+            IdScheme::PrefixCounter => Box::new(PrefixCounterIds),
+        }
+    }
+}
+
+#[derive(Clone, Copy, clap::ValueEnum, Debug, PartialEq, Eq)]
+pub enum DiscoverSource {
+    Claude,
+    Vscode,
+    Cursor,
+    Path,
+}
+
+impl DiscoverSource {
+    /// Clients whose well-known config locations are scanned, none for `path`
+    pub fn clients(source: Option<DiscoverSource>) -> Vec<ClientKind> {
+        match source {
+            None => ClientKind::all().to_vec(),
+            Some(DiscoverSource::Claude) => vec![ClientKind::Claude],
+            Some(DiscoverSource::Vscode) => vec![ClientKind::Vscode],
+            Some(DiscoverSource::Cursor) => vec![ClientKind::Cursor],
+            Some(DiscoverSource::Path) => Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, clap::ValueEnum, Debug, PartialEq, Eq)]
+pub enum MigrateMode {
+    OnStart,
+    RequireCurrent,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum, Debug, PartialEq, Eq)]
+pub enum StorageBackend {
+    /// `--config` and `--audit-log` files
+    File,
+    /// The `--database-url` database, requires the `sqlite` feature
+    Sqlite,
+}
+
+/// Output formats of tabular reports
+#[derive(Clone, Copy, clap::ValueEnum, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Pretty,
+    Json,
+    Csv,
 }
 
 #[derive(Clone, clap::ValueEnum, Debug)]
@@ -65,8 +712,53 @@ pub enum OutputFormat {
     Table,
 }
 
-impl Default for Commands {
-    fn default() -> Self {
-        Commands::Start
+impl OutputFormat {
+    /// Format of a file by its extension: `.json`, or `.yaml`/`.yml`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// Bytes, optionally with a `K`, `M` or `G` suffix (powers of 1024)
+fn parse_size(value: &str) -> Result<u64, String> {
+    let trimmed = value.trim();
+    let (number, unit) = match trimmed.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((at, _)) => trimmed.split_at(at),
+        None => (trimmed, ""),
+    };
+    let factor = match unit.trim().to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => {
+            return Err(format!(
+                "invalid size '{}', expected e.g. `512K` or `10M`",
+                value
+            ));
+        }
+    };
+    match number.parse::<u64>() {
+        Ok(number) if number > 0 => number
+            .checked_mul(factor)
+            .ok_or_else(|| format!("size '{}' is too large", value)),
+        _ => Err(format!(
+            "invalid size '{}', expected e.g. `512K` or `10M`",
+            value
+        )),
+    }
+}
+
+fn parse_period(value: &str) -> Result<chrono::Duration, String> {
+    match crate::core::duration::parse_duration(value) {
+        Some(duration) if duration > chrono::Duration::zero() => Ok(duration),
+        _ => Err(format!(
+            "invalid duration '{}', expected e.g. `12h` or `7d`",
+            value
+        )),
     }
 }
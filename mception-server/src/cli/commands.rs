@@ -1,25 +1,118 @@
 use crate::{
-    cli::{Commands, OutputFormat},
-    core::{AuditLogEntry, AuditTarget, ServerConfig},
-    services::ConfigService,
-    storage::providers::{AuditStorage, ConfigStorage},
+    cli::{Commands, DiscoverSource, OutputFormat, ReportFormat},
+    core::{
+        AuditLogEntry, AuditQuery, AuditScanReport, AuditTarget, CreateAgentRequest, LeafMcpConfig,
+        McpTransport, ReverseRequestPolicy, ServerConfig, StdioSandbox,
+    },
+    services::{
+        ConfigService,
+        auth::AdminToken,
+        authorization::AccessDecision,
+        availability::{self, FleetAvailability},
+        bulk::{BulkDeleteOutcome, BulkDeletePlan, BulkKind, BulkSelection},
+        discovery::{self, Discovery},
+        history::{self, Replay},
+        registration_policy::ReportEntry,
+        safety::LeafMcpSafety,
+        sandbox,
+    },
+    storage::audit_chain::AuditChainReport,
+    storage::includes::FragmentCheck,
+    storage::providers::{AuditStorage, ConfigStorage, FileAuditStorage},
 };
+use chrono::{DateTime, Utc};
 use serde_json;
+use std::collections::BTreeMap;
+
+/// A request to a running server's admin API, with the admin token if one is given
+fn admin_request(
+    request: reqwest::RequestBuilder,
+    admin_token: Option<&AdminToken>,
+) -> reqwest::RequestBuilder {
+    match admin_token {
+        Some(token) => token.authorize(request),
+        None => request,
+    }
+}
+
+/// Exit code of the audit commands when corruption was found (and repaired)
+const EXIT_AUDIT_CORRUPT: i32 = 2;
+/// Exit code of the audit commands when no entry could be recovered
+const EXIT_AUDIT_UNRECOVERABLE: i32 = 3;
+/// Exit code of `verify-audit` when an entry doesn't link to the one before
+const EXIT_AUDIT_CHAIN_BROKEN: i32 = 4;
+/// Exit code of `validate` when registered leaf MCPs violate the policy
+const EXIT_POLICY_VIOLATED: i32 = 2;
+/// Exit code of `replay --verify` when the reconstruction differs from the
+/// stored configuration
+const EXIT_REPLAY_DIVERGED: i32 = 2;
 
 pub async fn handle_command(
     command: Commands,
     config_service: &ConfigService,
     config_storage: &dyn ConfigStorage,
     audit_storage: &dyn AuditStorage,
+    audit_log_path: &str,
+    availability_path: &str,
+    admin_token: Option<&AdminToken>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     match command {
         Commands::Start => {
             // This is handled in main.rs - just return Ok for now
             Ok(())
         }
-        Commands::ShowConfig { format } => {
-            let config = config_storage.load_config().await?;
-            display_config(&config, format).await
+        Commands::ShowConfig {
+            format,
+            as_of: Some(at),
+            ..
+        } => {
+            let historical = config_service.configuration_as_of(at).await?;
+            let format = format.unwrap_or(OutputFormat::Pretty);
+            match format {
+                OutputFormat::Json => {
+                    println!("{}", serde_json::to_string_pretty(&historical)?);
+                    Ok(())
+                }
+                _ => {
+                    println!("Read-only view as of {}", historical.as_of);
+                    match &historical.source.snapshot {
+                        Some(snapshot) => println!(
+                            "Source: backup {} ({}) + {} audit entries",
+                            snapshot,
+                            historical.source.snapshot_time,
+                            historical.source.replayed_entries
+                        ),
+                        None => println!(
+                            "Source: empty configuration ({}) + {} audit entries",
+                            historical.source.snapshot_time, historical.source.replayed_entries
+                        ),
+                    }
+                    if !historical.source.skipped_entry_ids.is_empty() {
+                        println!(
+                            "Skipped entries: {}",
+                            historical.source.skipped_entry_ids.join(", ")
+                        );
+                    }
+                    println!();
+                    display_config(&historical.config, format).await
+                }
+            }
+        }
+        Commands::ShowConfig {
+            format,
+            as_of: None,
+            resolved,
+            output,
+        } => {
+            let config = if resolved {
+                config_storage.load_config().await?
+            } else {
+                config_storage.load_unresolved_config().await?
+            };
+            match output {
+                Some(path) => export_config(&config, &path, format),
+                None => display_config(&config, format.unwrap_or(OutputFormat::Pretty)).await,
+            }
         }
         Commands::ShowAudit {
             format,
@@ -27,11 +120,512 @@ pub async fn handle_command(
             action,
             target,
             actor,
+            since,
+            until,
         } => {
-            let entries = audit_storage.load_entries().await?;
-            let filtered_entries = filter_audit_entries(entries, limit, action, target, actor);
+            let query = AuditQuery {
+                since,
+                until,
+                action,
+                target_type: target,
+                actor,
+            };
+            let entries = audit_storage.load_entries_filtered(&query).await?;
+            let filtered_entries = filter_audit_entries(entries, limit);
             display_audit_entries(&filtered_entries, format).await
         }
+        Commands::VerifyAudit { format } => {
+            let report = audit_storage.verify().await?;
+            let chain = audit_storage.verify_chain().await?;
+            display_audit_verification(&report, &chain, format)?;
+            exit_for_audit_scan(&report);
+            if !chain.is_intact() {
+                std::process::exit(EXIT_AUDIT_CHAIN_BROKEN);
+            }
+            Ok(())
+        }
+        Commands::RepairAudit { output, format } => {
+            let output = output.unwrap_or_else(|| format!("{}.repaired", audit_log_path));
+            let report = audit_storage.repair(&output).await?;
+            display_audit_scan(&report, format)?;
+            exit_for_audit_scan(&report);
+            Ok(())
+        }
+        Commands::Replay {
+            audit,
+            output,
+            until,
+            verify,
+            format,
+        } => {
+            let entries = match &audit {
+                Some(path) => FileAuditStorage::new(path).load_entries().await?,
+                None => audit_storage.load_entries().await?,
+            };
+            let replay = history::replay(&entries, until);
+            export_config(&replay.config, &output, None)?;
+            let divergence = if verify {
+                let live = config_storage.load_unresolved_config().await?;
+                Some(history::divergence(&replay.config, &live))
+            } else {
+                None
+            };
+            display_replay(&replay, divergence.as_ref(), config_storage, format)?;
+            if let Some(Some(_)) = divergence {
+                std::process::exit(EXIT_REPLAY_DIVERGED);
+            }
+            Ok(())
+        }
+        Commands::Availability { since, format } => {
+            let report = availability::read_fleet(std::path::Path::new(availability_path), since)?;
+            display_availability(&report, format)
+        }
+        Commands::Explain {
+            agent_id,
+            mcp,
+            tool,
+            format,
+        } => {
+            let decision = config_service
+                .explain_access(&agent_id, &mcp, tool.as_deref())
+                .await?;
+            display_access_decision(&decision, format)
+        }
+        Commands::Discover {
+            from,
+            file,
+            yes,
+            format,
+        } => {
+            let discovery = match &file {
+                Some(file) => discovery::parse_file(std::path::Path::new(file))?,
+                None => discovery::scan(&DiscoverSource::clients(from))?,
+            };
+            let discovery = config_service.check_discovered(discovery).await;
+            display_discovery(&discovery, format)?;
+
+            let new = discovery.new_candidates().count();
+            if new == 0 || !(yes || confirm(&format!("Register {} new leaf MCPs?", new))?) {
+                return Ok(());
+            }
+            let ids = config_service
+                .register_discovered(discovery, Some("admin".to_string()))
+                .await?;
+            eprintln!("Registered {}", ids.join(", "));
+            Ok(())
+        }
+        Commands::Validate {
+            policy_report,
+            format,
+        } => {
+            let checks = config_storage.check_includes().await?;
+            let invalid = checks
+                .iter()
+                .filter(|check| !check.errors.is_empty())
+                .count();
+            display_include_checks(&checks, invalid, &format)?;
+            if invalid > 0 {
+                return Err(format!("{} included files are invalid", invalid).into());
+            }
+            config_service.load_configuration().await?;
+
+            let policy = match policy_report {
+                Some(path) => serde_json::from_str(&std::fs::read_to_string(&path)?)
+                    .map_err(|e| format!("{} is not a valid registration policy: {}", path, e))?,
+                None => match config_service.registration_policy().await {
+                    Some(policy) => policy,
+                    None => {
+                        eprintln!("No registration policy is set, every leaf MCP is allowed");
+                        return Ok(());
+                    }
+                },
+            };
+            let violations = config_service.registration_policy_report(&policy).await?;
+            display_policy_report(&violations, format)?;
+            if !violations.is_empty() {
+                std::process::exit(EXIT_POLICY_VIOLATED);
+            }
+            Ok(())
+        }
+        Commands::DeleteMcps {
+            tag,
+            ids,
+            agents,
+            dry_run: _,
+            confirm,
+            reason,
+            format,
+        } => {
+            let kind = if agents {
+                BulkKind::Agent
+            } else {
+                BulkKind::LeafMcp
+            };
+            let selection = match tag {
+                Some(tag) => BulkSelection::Tag(tag),
+                None => BulkSelection::Ids(ids.unwrap_or_default()),
+            };
+            let Some(token) = confirm else {
+                let plan = config_service.plan_bulk_delete(kind, &selection).await?;
+                return display_bulk_delete_plan(&plan, format);
+            };
+            match config_service
+                .bulk_delete(kind, &selection, &token, Some("admin".to_string()), reason)
+                .await?
+            {
+                BulkDeleteOutcome::Deleted {
+                    correlation_id,
+                    plan,
+                } => {
+                    println!(
+                        "Deleted {} (correlation id {})",
+                        plan.ids.join(", "),
+                        correlation_id
+                    );
+                    Ok(())
+                }
+                BulkDeleteOutcome::PlanChanged { plan, reason } => {
+                    display_bulk_delete_plan(&plan, format)?;
+                    Err(format!(
+                        "Not deleted, {}; review the plan above and confirm it with its token",
+                        reason
+                    )
+                    .into())
+                }
+            }
+        }
+        Commands::ListMcps {
+            label,
+            format,
+            server,
+        } => {
+            let health = match server {
+                Some(server) => {
+                    let request = reqwest::Client::new()
+                        .get(format!("{}/admin/status", server.trim_end_matches('/')));
+                    let response = admin_request(request, admin_token).send().await?;
+                    if !response.status().is_success() {
+                        return Err(format!("Server responded with {}", response.status()).into());
+                    }
+                    let status: serde_json::Value = response.json().await?;
+                    Some(status["health"]["leaf_mcps"].clone())
+                }
+                None => None,
+            };
+            let config = config_service.get_configuration().await;
+            let leaf_mcps = config
+                .leaf_mcps
+                .iter()
+                .filter(|(_, mcp)| {
+                    label
+                        .as_ref()
+                        .is_none_or(|label| label.matches(&mcp.labels))
+                })
+                .map(|(id, mcp)| {
+                    let mut listed = serde_json::to_value(mcp.redacted()).unwrap_or_default();
+                    if let Some(health) = &health {
+                        listed["health"] = health[id].clone();
+                    }
+                    (id.clone(), listed)
+                })
+                .collect();
+            display_listing("leaf_mcps", "Leaf MCPs", leaf_mcps, format)
+        }
+        Commands::ListAgents { label, format } => {
+            let config = config_service.get_configuration().await;
+            let agents = config
+                .agents
+                .iter()
+                .filter(|(_, agent)| {
+                    label
+                        .as_ref()
+                        .is_none_or(|label| label.matches(&agent.labels))
+                })
+                .map(|(id, agent)| {
+                    let listed = serde_json::to_value(agent.redacted()).unwrap_or_default();
+                    (id.clone(), listed)
+                })
+                .collect();
+            display_listing("agents", "Agents", agents, format)
+        }
+        Commands::AddMcp {
+            id,
+            name,
+            description,
+            command,
+            args,
+            url,
+            sse,
+            labels,
+            reason,
+            format,
+        } => {
+            let transport = match (command, url) {
+                (Some(command), _) => McpTransport::Stdio {
+                    command,
+                    args,
+                    env: None,
+                    sandbox: StdioSandbox::default(),
+                },
+                (None, Some(url)) if sse => McpTransport::Sse { url, headers: None },
+                (None, Some(url)) => McpTransport::Https { url, headers: None },
+                (None, None) => return Err("Either --command or --url is required".into()),
+            };
+            let config = LeafMcpConfig {
+                id: String::new(),
+                name,
+                description,
+                instructions: None,
+                transport,
+                is_local: false,
+                reachable_by_agent: false,
+                config: serde_json::json!({}),
+                reverse_requests: ReverseRequestPolicy::default(),
+                region: None,
+                replica_group: None,
+                timeout_ms: None,
+                retry: None,
+                header_policy: None,
+                max_concurrent_requests: None,
+                labels: labels.into_iter().collect(),
+                revision: 0,
+            };
+            let id = config_service
+                .create_leaf_mcp(id, config, Some("admin".to_string()), reason)
+                .await?;
+            display_change(
+                &serde_json::json!({
+                    "success": true,
+                    "id": id,
+                    "message": format!("Leaf MCP '{}' created successfully", id)
+                }),
+                format,
+            )
+        }
+        Commands::RemoveMcp { id, reason, format } => {
+            config_service
+                .delete_leaf_mcp(&id, Some("admin".to_string()), reason)
+                .await?;
+            display_change(
+                &serde_json::json!({
+                    "success": true,
+                    "message": format!("Leaf MCP '{}' deleted successfully", id)
+                }),
+                format,
+            )
+        }
+        Commands::AddAgent {
+            agent_id,
+            name,
+            description,
+            allow,
+            labels,
+            reason,
+            format,
+        } => {
+            let agent_id = config_service
+                .create_agent_with_description(
+                    CreateAgentRequest {
+                        agent_id,
+                        name,
+                        allowed_mcp_ids: allow,
+                        labels: labels.into_iter().collect(),
+                        should_create: None,
+                    },
+                    description,
+                    Some("admin".to_string()),
+                    reason,
+                )
+                .await?;
+            display_change(
+                &serde_json::json!({
+                    "success": true,
+                    "agent_id": agent_id,
+                    "message": format!("Agent '{}' created successfully", agent_id)
+                }),
+                format,
+            )
+        }
+        Commands::RemoveAgent {
+            agent_id,
+            reason,
+            format,
+        } => {
+            config_service
+                .delete_agent(&agent_id, Some("admin".to_string()), reason)
+                .await?;
+            display_change(
+                &serde_json::json!({
+                    "success": true,
+                    "message": format!("Agent '{}' deleted successfully", agent_id)
+                }),
+                format,
+            )
+        }
+        Commands::AllowMcp {
+            agent_id,
+            mcp_id,
+            reason,
+            format,
+        } => {
+            config_service
+                .add_agent_allowed_mcp(&agent_id, &mcp_id, Some("admin".to_string()), reason)
+                .await?;
+            display_change(
+                &serde_json::json!({
+                    "success": true,
+                    "message": format!("MCP '{}' added to agent '{}' allowed list", mcp_id, agent_id)
+                }),
+                format,
+            )
+        }
+        Commands::DisallowMcp {
+            agent_id,
+            mcp_id,
+            reason,
+            format,
+        } => {
+            let remaining = config_service
+                .remove_agent_allowed_mcp(&agent_id, &mcp_id, Some("admin".to_string()), reason)
+                .await?;
+            display_change(
+                &serde_json::json!({
+                    "success": true,
+                    "message": format!("MCP '{}' removed from agent '{}' allowed list", mcp_id, agent_id),
+                    "remaining_sources": remaining
+                }),
+                format,
+            )
+        }
+        Commands::Import {
+            input,
+            merge,
+            reason,
+            format,
+        } => {
+            let imported = read_config_file(&input)?;
+            let summary = config_service
+                .import_configuration(imported, merge, Some("admin".to_string()), reason)
+                .await?;
+            display_change(
+                &serde_json::json!({
+                    "success": true,
+                    "summary": summary,
+                    "message": format!(
+                        "Configuration {}, previous configuration backed up as '{}'",
+                        if summary.merge { "merged" } else { "replaced" },
+                        summary.previous_backup
+                    )
+                }),
+                format,
+            )
+        }
+        Commands::Doctor { .. } => {
+            // Handled in main.rs before any storage is touched
+            Ok(())
+        }
+        Commands::SetInstructions {
+            leaf_mcp_id,
+            instructions,
+            file,
+            clear: _,
+            reason,
+            server,
+        } => {
+            let instructions = match file {
+                Some(file) => Some(std::fs::read_to_string(file)?.trim_end().to_string()),
+                None => instructions,
+            };
+            let request = reqwest::Client::new().put(format!(
+                "{}/admin/leaf/{}/config",
+                server.trim_end_matches('/'),
+                leaf_mcp_id
+            ));
+            let response = admin_request(request, admin_token)
+                .json(&serde_json::json!({
+                    "config": { "instructions": instructions },
+                    "reason": reason,
+                    "should_update": true,
+                }))
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                return Err(format!("Server responded with {}", response.status()).into());
+            }
+
+            match instructions {
+                Some(_) => println!("Instructions of leaf MCP '{}' updated", leaf_mcp_id),
+                None => println!("Instructions of leaf MCP '{}' removed", leaf_mcp_id),
+            }
+            Ok(())
+        }
+        Commands::SetLogLevel {
+            level,
+            filter,
+            duration,
+            reason,
+            server,
+        } => {
+            let request = reqwest::Client::new()
+                .put(format!("{}/admin/logging", server.trim_end_matches('/')));
+            let response = admin_request(request, admin_token)
+                .json(&serde_json::json!({
+                    "level": level,
+                    "filter": filter,
+                    "duration": duration,
+                    "reason": reason,
+                }))
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                return Err(format!("Server responded with {}", response.status()).into());
+            }
+
+            let settings: serde_json::Value = response.json().await?;
+            println!(
+                "Log directives: {}",
+                settings["directives"].as_str().unwrap_or_default()
+            );
+            if let Some(revert_at) = settings["revert_at"].as_str() {
+                println!("Reverting to defaults at {}", revert_at);
+            }
+            Ok(())
+        }
+        Commands::Migrate { check } => {
+            let status = config_service.migration_status().await?;
+            println!(
+                "Storage {}: schema version {} (current: {})",
+                status.storage, status.current_version, status.target_version
+            );
+
+            if check {
+                if status.pending.is_empty() {
+                    println!("No pending migrations");
+                    return Ok(());
+                }
+                for migration in &status.pending {
+                    println!(
+                        "  pending v{}: {}",
+                        migration.version, migration.description
+                    );
+                }
+                return Err(format!("{} pending migration(s)", status.pending.len()).into());
+            }
+
+            let applied = config_service
+                .run_migrations(Some("system".to_string()))
+                .await?;
+            if applied.is_empty() {
+                println!("No pending migrations");
+            }
+            for migration in &applied {
+                println!(
+                    "  applied v{}: {}",
+                    migration.version, migration.description
+                );
+            }
+            Ok(())
+        }
     }
 }
 
@@ -49,6 +643,9 @@ async fn display_config(
             println!("Version: {}", config.metadata.version);
             println!("Created: {}", config.metadata.created_at);
             println!("Last Modified: {}", config.metadata.last_modified);
+            if !config.includes.is_empty() {
+                println!("Includes: {}", config.includes.join(", "));
+            }
             println!();
 
             println!("Leaf MCPs ({}):", config.leaf_mcps.len());
@@ -59,6 +656,21 @@ async fn display_config(
                     "    Local: {}, Reachable: {}",
                     mcp.is_local, mcp.reachable_by_agent
                 );
+                if !mcp.labels.is_empty() {
+                    println!("    Labels: {}", display_labels(&mcp.labels));
+                }
+                if let Some(timeout_ms) = mcp.timeout_ms {
+                    println!("    Timeout: {} ms", timeout_ms);
+                }
+                if let McpTransport::Stdio { sandbox, .. } = &mcp.transport
+                    && !sandbox.is_empty()
+                {
+                    let restrictions = sandbox::describe(sandbox)
+                        .into_iter()
+                        .map(|r| format!("{}={} ({})", r.restriction, r.value, r.mechanism))
+                        .collect::<Vec<_>>();
+                    println!("    Sandbox: {}", restrictions.join(", "));
+                }
             }
             println!();
 
@@ -71,16 +683,15 @@ async fn display_config(
                 );
                 println!("    Connected: {}", agent.is_connected);
                 println!("    Allowed MCPs: {:?}", agent.allowed_mcp_ids);
+                if !agent.labels.is_empty() {
+                    println!("    Labels: {}", display_labels(&agent.labels));
+                }
                 if let Some(last_seen) = agent.last_seen {
                     println!("    Last Seen: {}", last_seen);
                 }
             }
         }
-        OutputFormat::Yaml => {
-            // For now, use JSON format as yaml crate would need to be added
-            println!("# YAML output not implemented, showing JSON:");
-            println!("{}", serde_json::to_string_pretty(config)?);
-        }
+        OutputFormat::Yaml => print_yaml(config)?,
         OutputFormat::Table => {
             println!("MCePtion Server Configuration Summary");
             println!("=====================================");
@@ -111,6 +722,564 @@ async fn display_config(
     Ok(())
 }
 
+fn display_audit_scan(
+    report: &AuditScanReport,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Pretty | OutputFormat::Table => {
+            println!("Audit log: {} ({} bytes)", report.path, report.total_bytes);
+            println!("Valid entries: {}", report.valid_entries);
+            println!("Salvaged entries: {}", report.salvaged_entries);
+            println!("Corrupt regions: {}", report.corrupt_regions.len());
+            for region in &report.corrupt_regions {
+                println!(
+                    "  - offset {}, {} bytes: {}",
+                    region.offset, region.length, region.reason
+                );
+            }
+            if let Some(output) = &report.output {
+                println!("Cleaned copy written to {}", output);
+            }
+        }
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(report)?);
+        }
+        OutputFormat::Yaml => print_yaml(report)?,
+    }
+    Ok(())
+}
+
+/// Show the corruption scan of the audit log followed by its hash chain
+fn display_audit_verification(
+    report: &AuditScanReport,
+    chain: &AuditChainReport,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let verification = {
+        let mut verification = serde_json::to_value(report)?;
+        verification["chain"] = serde_json::to_value(chain)?;
+        verification
+    };
+    match format {
+        OutputFormat::Pretty | OutputFormat::Table => {
+            display_audit_scan(report, format)?;
+            if chain.legacy_entries > 0 {
+                println!("Entries before the hash chain: {}", chain.legacy_entries);
+            }
+            match (&chain.broken_link, &chain.chain_root) {
+                (Some(broken), _) => {
+                    println!(
+                        "Hash chain: broken at line {} (sequence {}, {}): {}",
+                        broken.entry.line,
+                        broken.entry.sequence,
+                        broken
+                            .entry
+                            .timestamp
+                            .map(|timestamp| timestamp.to_rfc3339())
+                            .unwrap_or_else(|| "no timestamp".to_string()),
+                        broken.reason
+                    );
+                }
+                (None, Some(root)) => {
+                    println!("Hash chain: intact from line {}", root.line);
+                }
+                (None, None) => println!("Hash chain: no hashed entries"),
+            }
+            for restart in &chain.restarts {
+                println!(
+                    "Hash chain: restarted by a repair at line {} (sequence {})",
+                    restart.line, restart.sequence
+                );
+            }
+        }
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&verification)?);
+        }
+        OutputFormat::Yaml => print_yaml(&verification)?,
+    }
+    Ok(())
+}
+
+/// Show what a replay applied and couldn't, and with `--verify` where it
+/// diverges from the stored configuration
+fn display_replay(
+    replay: &Replay,
+    divergence: Option<&Option<serde_json::Value>>,
+    config_storage: &dyn ConfigStorage,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let report = {
+        let mut report = serde_json::to_value(replay)?;
+        if let Some(divergence) = divergence {
+            report["verify"] = serde_json::json!({
+                "against": config_storage.location(),
+                "diverged": divergence.is_some(),
+                "paths": divergence.as_ref().map(history::divergent_paths).unwrap_or_default(),
+                "patch": divergence,
+            });
+        }
+        report
+    };
+    match format {
+        OutputFormat::Pretty | OutputFormat::Table => {
+            match replay.until {
+                Some(until) => println!(
+                    "Replayed {} audit entries up to {}",
+                    replay.replayed_entries, until
+                ),
+                None => println!("Replayed {} audit entries", replay.replayed_entries),
+            }
+            if !replay.unapplied.is_empty() {
+                println!("Could not apply {} entries:", replay.unapplied.len());
+                for entry in &replay.unapplied {
+                    println!(
+                        "  - #{} {} ({}, {:?} on {:?}): {}",
+                        entry.sequence,
+                        entry.id,
+                        entry.timestamp,
+                        entry.action,
+                        entry.target,
+                        entry.reason
+                    );
+                }
+            }
+            match divergence {
+                Some(None) => println!("Matches {}", config_storage.location()),
+                Some(Some(patch)) => {
+                    println!("Diverges from {} in:", config_storage.location());
+                    for path in history::divergent_paths(patch) {
+                        println!("  - {}", path);
+                    }
+                }
+                None => {}
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        OutputFormat::Yaml => print_yaml(&report)?,
+    }
+    Ok(())
+}
+
+fn display_access_decision(
+    decision: &AccessDecision,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Pretty | OutputFormat::Table => {
+            let subject = match &decision.tool {
+                Some(tool) => format!("tool '{}' on '{}'", tool, decision.mcp_id),
+                None => format!("'{}'", decision.mcp_id),
+            };
+            println!(
+                "Agent '{}' {} {}",
+                decision.agent_id,
+                if decision.allowed {
+                    "may use"
+                } else {
+                    "may not use"
+                },
+                subject
+            );
+            if let Some(rule) = &decision.winning_rule {
+                println!("Winning rule: {}", rule);
+            }
+            for evaluation in &decision.trace {
+                println!(
+                    "  [{}] {:?} {}: {}",
+                    if evaluation.matched { "x" } else { " " },
+                    evaluation.kind,
+                    evaluation.rule,
+                    evaluation.detail
+                );
+            }
+        }
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(decision)?);
+        }
+        OutputFormat::Yaml => print_yaml(decision)?,
+    }
+    Ok(())
+}
+
+/// Print the outcome of a change, as the admin API answers the same change
+/// Leaf MCPs or agents keyed by id, as listed by the admin API under `key`
+fn display_listing(
+    key: &str,
+    title: &str,
+    entries: BTreeMap<String, serde_json::Value>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let listing = serde_json::json!({ "count": entries.len(), key: entries });
+    match format {
+        OutputFormat::Pretty | OutputFormat::Table => {
+            println!("{} ({}):", title, entries.len());
+            for (id, entry) in &entries {
+                let name = entry["name"].as_str().unwrap_or("(no name)");
+                let labels: BTreeMap<String, String> =
+                    serde_json::from_value(entry["labels"].clone()).unwrap_or_default();
+                let mut line = format!("  - {}: {}", id, name);
+                if !labels.is_empty() {
+                    line.push_str(&format!(" [{}]", display_labels(&labels)));
+                }
+                // Only leaf MCPs listed with `--server` have a health
+                if let Some(health) = entry.get("health") {
+                    let health = match health["healthy"].as_bool() {
+                        Some(true) => "healthy".to_string(),
+                        Some(false) => format!(
+                            "unhealthy: {}",
+                            health["last_error"].as_str().unwrap_or("no answer")
+                        ),
+                        None => "not probed yet".to_string(),
+                    };
+                    line.push_str(&format!(" ({})", health));
+                }
+                println!("{}", line);
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&listing)?),
+        OutputFormat::Yaml => print_yaml(&listing)?,
+    }
+    Ok(())
+}
+
+/// Labels as `key=value`, comma separated
+fn display_labels(labels: &BTreeMap<String, String>) -> String {
+    labels
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn display_change(
+    outcome: &serde_json::Value,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Pretty | OutputFormat::Table => {
+            println!("{}", outcome["message"].as_str().unwrap_or_default());
+        }
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(outcome)?);
+        }
+        OutputFormat::Yaml => print_yaml(outcome)?,
+    }
+    Ok(())
+}
+
+fn display_bulk_delete_plan(
+    plan: &BulkDeletePlan,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Pretty | OutputFormat::Table => {
+            if plan.ids.is_empty() {
+                println!("Nothing matches {}", plan.selection);
+                return Ok(());
+            }
+            let kind = match plan.kind {
+                BulkKind::LeafMcp => "leaf MCPs",
+                BulkKind::Agent => "agents",
+            };
+            println!(
+                "Would delete {} {} ({}):",
+                plan.ids.len(),
+                kind,
+                plan.selection
+            );
+            for id in &plan.ids {
+                println!("  {}", id);
+            }
+            for (agent, lost) in &plan.affected_agents {
+                println!("Agent '{}' loses {}", agent, lost.join(", "));
+            }
+            for bundle in &plan.affected_bundles {
+                println!("Bundle '{}' loses members", bundle);
+            }
+            for safety in plan.safety.values() {
+                display_safety(safety);
+            }
+            println!("Confirm with --confirm {}", plan.confirmation_token);
+        }
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(plan)?);
+        }
+        OutputFormat::Yaml => print_yaml(plan)?,
+    }
+    Ok(())
+}
+
+fn display_safety(safety: &LeafMcpSafety) {
+    let (Some(total_calls), Some(unidentified_calls)) =
+        (safety.total_calls, safety.unidentified_calls)
+    else {
+        println!(
+            "Usage of '{}': unknown, no access log is enabled (--access-log)",
+            safety.leaf_mcp_id
+        );
+        return;
+    };
+    let last_used = |at: Option<DateTime<Utc>>| {
+        at.map_or("never used".to_string(), |at| {
+            format!("last used {}", at.format("%Y-%m-%d %H:%M:%S UTC"))
+        })
+    };
+    println!(
+        "Usage of '{}' since {}: {}, {} calls, {}",
+        safety.leaf_mcp_id,
+        safety.since.format("%Y-%m-%d"),
+        safety.risk.as_str(),
+        total_calls,
+        last_used(safety.last_used_at)
+    );
+    for agent in &safety.agents {
+        let granted_by = if agent.granted_by.is_empty() {
+            "no longer allowed".to_string()
+        } else {
+            format!("via {}", agent.granted_by.join(", "))
+        };
+        println!(
+            "  Agent '{}' ({}): {} calls, {}",
+            agent.agent_id,
+            granted_by,
+            agent.calls.unwrap_or_default(),
+            last_used(agent.last_used_at)
+        );
+    }
+    if unidentified_calls > 0 {
+        println!("  Unidentified callers: {} calls", unidentified_calls);
+    }
+}
+
+fn display_discovery(
+    discovery: &Discovery,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Pretty | OutputFormat::Table => {
+            if discovery.candidates.is_empty() && discovery.skipped.is_empty() {
+                println!("No MCP servers found");
+            }
+            for candidate in &discovery.candidates {
+                let transport = match &candidate.config.transport {
+                    McpTransport::Stdio { command, args, .. } => {
+                        format!("stdio: {} {}", command, args.join(" "))
+                    }
+                    McpTransport::Https { url, .. } => format!("https: {}", url),
+                    McpTransport::Sse { url, .. } => format!("sse: {}", url),
+                    McpTransport::Builtin { kind } => format!("builtin: {:?}", kind),
+                };
+                match &candidate.duplicate_of {
+                    Some(existing) => println!(
+                        "  [=] {} ({}), same as {}",
+                        candidate.name, transport, existing
+                    ),
+                    None => println!("  [+] {} ({})", candidate.name, transport),
+                }
+                println!("      from {}", candidate.source);
+            }
+            for skipped in &discovery.skipped {
+                println!(
+                    "  [!] {} skipped: {}\n      from {}",
+                    skipped.name, skipped.reason, skipped.source
+                );
+            }
+        }
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(discovery)?);
+        }
+        OutputFormat::Yaml => print_yaml(discovery)?,
+    }
+    Ok(())
+}
+
+fn display_policy_report(
+    violations: &[ReportEntry],
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Pretty | OutputFormat::Table => {
+            if violations.is_empty() {
+                println!("All leaf MCPs are allowed by the policy");
+            }
+            for entry in violations {
+                println!("  [!] {} violates {}", entry.leaf_mcp_id, entry.violation);
+            }
+        }
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(violations)?);
+        }
+        OutputFormat::Yaml => print_yaml(violations)?,
+    }
+    Ok(())
+}
+
+/// Report the included files. Structured formats only report them if one is
+/// invalid, to keep the output a single document.
+fn display_include_checks(
+    checks: &[FragmentCheck],
+    invalid: usize,
+    format: &OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Pretty | OutputFormat::Table => {
+            for check in checks {
+                if check.errors.is_empty() {
+                    println!(
+                        "  [ok] {}: {} leaf MCPs, {} agents",
+                        check.file, check.leaf_mcps, check.agents
+                    );
+                }
+                for error in &check.errors {
+                    println!("  [!] {}: {}", check.file, error);
+                }
+            }
+        }
+        OutputFormat::Json if invalid > 0 => {
+            println!("{}", serde_json::to_string_pretty(checks)?);
+        }
+        OutputFormat::Yaml if invalid > 0 => print_yaml(checks)?,
+        OutputFormat::Json | OutputFormat::Yaml => {}
+    }
+    Ok(())
+}
+
+/// Ask a yes/no question on stderr, defaulting to no
+fn confirm(question: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    eprint!("{} [y/N] ", question);
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+fn display_availability(
+    report: &FleetAvailability,
+    format: ReportFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let percent = |percent: Option<f64>| {
+        percent
+            .map(|percent| format!("{:.2}", percent))
+            .unwrap_or_default()
+    };
+    match format {
+        ReportFormat::Json => println!("{}", serde_json::to_string_pretty(report)?),
+        ReportFormat::Csv => {
+            println!(
+                "agent_id,since,until,uptime_percent,connected_seconds,disconnected_seconds,unknown_seconds,longest_outage_start,longest_outage_seconds"
+            );
+            for agent in &report.agents {
+                println!(
+                    "{},{},{},{},{},{},{},{},{}",
+                    agent.agent_id,
+                    agent.since.to_rfc3339(),
+                    agent.until.to_rfc3339(),
+                    percent(agent.uptime_percent),
+                    agent.connected_seconds,
+                    agent.disconnected_seconds,
+                    agent.unknown_seconds,
+                    agent
+                        .longest_outage
+                        .as_ref()
+                        .map(|outage| outage.start.to_rfc3339())
+                        .unwrap_or_default(),
+                    agent
+                        .longest_outage
+                        .as_ref()
+                        .map(|outage| outage.seconds)
+                        .unwrap_or_default()
+                );
+            }
+        }
+        ReportFormat::Pretty => {
+            let display_percent = |uptime: Option<f64>| match uptime {
+                Some(_) => format!("{}%", percent(uptime)),
+                None => "unknown".to_string(),
+            };
+            println!("Agent availability {} - {}", report.since, report.until);
+            println!(
+                "Mean uptime: {} (server not running for {}s)",
+                display_percent(report.mean_uptime_percent),
+                report.unknown_seconds
+            );
+            for agent in &report.agents {
+                println!(
+                    "  - {}: {} up",
+                    agent.agent_id,
+                    display_percent(agent.uptime_percent)
+                );
+                if let Some(outage) = &agent.longest_outage {
+                    println!(
+                        "    Longest outage: {}s from {}",
+                        outage.seconds, outage.start
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write the configuration to `path`, as JSON unless `format` or the
+/// extension of `path` asks for YAML
+fn export_config(
+    config: &ServerConfig,
+    path: &std::path::Path,
+    format: Option<OutputFormat>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let content = match format
+        .or_else(|| OutputFormat::from_path(path))
+        .unwrap_or(OutputFormat::Json)
+    {
+        OutputFormat::Json => format!("{}\n", serde_json::to_string_pretty(config)?),
+        #[cfg(feature = "yaml")]
+        OutputFormat::Yaml => serde_yaml::to_string(config)?,
+        #[cfg(not(feature = "yaml"))]
+        OutputFormat::Yaml => return Err("writing YAML requires the `yaml` feature".into()),
+        OutputFormat::Pretty | OutputFormat::Table => {
+            return Err("the configuration can only be written as json or yaml".into());
+        }
+    };
+    std::fs::write(path, content)?;
+    eprintln!("Configuration written to {}", path.display());
+    Ok(())
+}
+
+/// Read a configuration written by [`export_config`]
+fn read_config_file(path: &std::path::Path) -> Result<ServerConfig, Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(path)?;
+    match OutputFormat::from_path(path) {
+        #[cfg(feature = "yaml")]
+        Some(OutputFormat::Yaml) => Ok(serde_yaml::from_str(&content)?),
+        #[cfg(not(feature = "yaml"))]
+        Some(OutputFormat::Yaml) => Err("reading YAML requires the `yaml` feature".into()),
+        _ => Ok(serde_json::from_str(&content)?),
+    }
+}
+
+/// Print a value as YAML, or as JSON when built without the `yaml` feature
+fn print_yaml<T: serde::Serialize + ?Sized>(value: &T) -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "yaml")]
+    print!("{}", serde_yaml::to_string(value)?);
+    #[cfg(not(feature = "yaml"))]
+    {
+        println!("# YAML output requires the `yaml` feature, showing JSON:");
+        println!("{}", serde_json::to_string_pretty(value)?);
+    }
+    Ok(())
+}
+
+/// Exit with a code distinguishing clean, corrupt/repaired and unrecoverable audit logs
+fn exit_for_audit_scan(report: &AuditScanReport) {
+    if report.is_clean() {
+        return;
+    }
+    if report.valid_entries == 0 {
+        std::process::exit(EXIT_AUDIT_UNRECOVERABLE);
+    }
+    std::process::exit(EXIT_AUDIT_CORRUPT);
+}
+
 async fn display_audit_entries(
     entries: &[AuditLogEntry],
     format: OutputFormat,
@@ -124,6 +1293,7 @@ async fn display_audit_entries(
             println!("======================");
             for entry in entries {
                 println!("ID: {}", entry.id);
+                println!("Sequence: {}", entry.sequence);
                 println!("Timestamp: {}", entry.timestamp);
                 println!("Action: {:?}", entry.action);
                 println!("Target: {:?}", entry.target);
@@ -139,13 +1309,14 @@ async fn display_audit_entries(
                 println!("---");
             }
         }
-        OutputFormat::Yaml => {
-            println!("# YAML output not implemented, showing JSON:");
-            println!("{}", serde_json::to_string_pretty(entries)?);
-        }
+        OutputFormat::Yaml => print_yaml(entries)?,
         OutputFormat::Table => {
-            println!("| Timestamp           | Action | Target Type | Target ID | Actor | Reason");
-            println!("| ------------------- | ------ | ----------- | --------- | ----- | ------");
+            println!(
+                "| Seq | Timestamp           | Action | Target Type | Target ID | Actor | Reason"
+            );
+            println!(
+                "| --- | ------------------- | ------ | ----------- | --------- | ----- | ------"
+            );
             for entry in entries {
                 let target_info = match &entry.target {
                     AuditTarget::LeafMcp { id } => ("LeafMcp", id.as_str()),
@@ -154,10 +1325,19 @@ async fn display_audit_entries(
                         agent_id,
                         mcp_id: _,
                     } => ("AgentMcp", agent_id.as_str()),
+                    AuditTarget::AgentToolFilter {
+                        agent_id,
+                        mcp_id: _,
+                    } => ("AgentTools", agent_id.as_str()),
+                    AuditTarget::Bundle { name } => ("Bundle", name.as_str()),
                     AuditTarget::Server => ("Server", ""),
+                    AuditTarget::RegistrationPolicy => ("Policy", ""),
+                    AuditTarget::AuditPolicy => ("Audit policy", ""),
+                    AuditTarget::ConfirmationPolicy => ("Confirmation policy", ""),
                 };
                 println!(
-                    "| {} | {:?} | {} | {} | {} | {}",
+                    "| {} | {} | {:?} | {} | {} | {} | {}",
+                    entry.sequence,
                     entry.timestamp.format("%Y-%m-%d %H:%M:%S"),
                     entry.action,
                     target_info.0,
@@ -171,54 +1351,11 @@ async fn display_audit_entries(
     Ok(())
 }
 
-fn filter_audit_entries(
-    entries: Vec<AuditLogEntry>,
-    limit: Option<usize>,
-    action_filter: Option<String>,
-    target_filter: Option<String>,
-    actor_filter: Option<String>,
-) -> Vec<AuditLogEntry> {
-    let mut filtered: Vec<AuditLogEntry> = entries
-        .into_iter()
-        .filter(|entry| {
-            // Filter by action
-            if let Some(action) = &action_filter {
-                let action_str = format!("{:?}", entry.action).to_lowercase();
-                if !action_str.contains(&action.to_lowercase()) {
-                    return false;
-                }
-            }
-
-            // Filter by target type
-            if let Some(target) = &target_filter {
-                let target_str = match &entry.target {
-                    AuditTarget::LeafMcp { .. } => "leafmcp",
-                    AuditTarget::Agent { .. } => "agent",
-                    AuditTarget::AgentAllowedMcp { .. } => "agentallowedmcp",
-                    AuditTarget::Server => "server",
-                };
-                if !target_str.contains(&target.to_lowercase()) {
-                    return false;
-                }
-            }
-
-            // Filter by actor
-            if let Some(actor) = &actor_filter {
-                if let Some(entry_actor) = &entry.actor {
-                    if !entry_actor.to_lowercase().contains(&actor.to_lowercase()) {
-                        return false;
-                    }
-                } else {
-                    return false;
-                }
-            }
-
-            true
-        })
-        .collect();
+fn filter_audit_entries(entries: Vec<AuditLogEntry>, limit: Option<usize>) -> Vec<AuditLogEntry> {
+    let mut filtered = entries;
 
-    // Sort by timestamp (newest first)
-    filtered.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    // Sort by sequence (newest first), timestamps may run backwards
+    filtered.sort_by_key(|entry| std::cmp::Reverse(entry.sequence));
 
     // Apply limit
     if let Some(limit) = limit {
@@ -0,0 +1,641 @@
+use crate::{
+    cli::{Cli, OutputFormat, StorageBackend},
+    core::{CONFIG_SCHEMA_VERSION, McpTransport, ServerConfig},
+    services::sandbox,
+    storage::{
+        includes,
+        journal::ConfigJournal,
+        migrations,
+        providers::{AuditStorage, FileAuditStorage},
+    },
+};
+use clap::{ArgMatches, ValueEnum, parser::ValueSource};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long to wait for a running server to answer
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Outcome of a single check, ordered from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Skip,
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl CheckStatus {
+    /// Process exit code when this is the worst finding
+    pub fn exit_code(self) -> i32 {
+        match self {
+            CheckStatus::Skip | CheckStatus::Pass => 0,
+            CheckStatus::Warn => 1,
+            CheckStatus::Fail => 2,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            CheckStatus::Skip => "SKIP",
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// A resolved CLI setting and where its value came from
+#[derive(Debug, Clone, Serialize)]
+pub struct Setting {
+    pub name: String,
+    pub value: String,
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorReport {
+    pub settings: Vec<Setting>,
+    pub checks: Vec<Check>,
+    pub worst: CheckStatus,
+}
+
+impl DoctorReport {
+    fn push(&mut self, name: impl Into<String>, status: CheckStatus, detail: impl Into<String>) {
+        self.worst = self.worst.max(status);
+        self.checks.push(Check {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+        });
+    }
+}
+
+/// Inspect the environment the server would run in, without creating or
+/// modifying anything
+pub async fn run(cli: &Cli, matches: &ArgMatches) -> DoctorReport {
+    let mut report = DoctorReport {
+        settings: settings(cli, matches),
+        checks: Vec::new(),
+        worst: CheckStatus::Pass,
+    };
+
+    let config = match cli.storage {
+        StorageBackend::File => check_config_file(&mut report, &cli.config),
+        StorageBackend::Sqlite => {
+            check_database(&mut report, &cli.database_url);
+            None
+        }
+    };
+    check_journal(&mut report, &cli.journal, config.as_ref()).await;
+    if cli.storage == StorageBackend::File {
+        check_audit_log(&mut report, &cli.audit_log).await;
+    }
+    report.push(
+        "instance_lock",
+        CheckStatus::Skip,
+        "the server does not take an instance lock",
+    );
+    check_running_server(&mut report, cli, config.as_ref()).await;
+    if cli.enable_fault_injection {
+        report.push(
+            "fault_injection",
+            CheckStatus::Warn,
+            "--enable-fault-injection lets the admin API fail and delay forwarded requests",
+        );
+    }
+    report.push(
+        "admin_token",
+        CheckStatus::Skip,
+        "the admin API has no authentication configured",
+    );
+    if let Some(config) = &config {
+        check_leaf_mcps(&mut report, config);
+    }
+
+    report
+}
+
+pub fn display(
+    report: &DoctorReport,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(report)?),
+        _ => {
+            println!("Settings:");
+            for setting in &report.settings {
+                println!(
+                    "  {} = {} ({})",
+                    setting.name, setting.value, setting.source
+                );
+            }
+            println!();
+            println!("Checks:");
+            for check in &report.checks {
+                println!(
+                    "  [{}] {}: {}",
+                    check.status.label(),
+                    check.name,
+                    check.detail
+                );
+            }
+            println!();
+            println!("Result: {}", report.worst.label());
+        }
+    }
+    Ok(())
+}
+
+fn settings(cli: &Cli, matches: &ArgMatches) -> Vec<Setting> {
+    let values = [
+        ("config", cli.config.clone()),
+        ("audit_log", cli.audit_log.clone()),
+        ("storage", value_name(cli.storage)),
+        ("database_url", cli.database_url.clone()),
+        ("host", cli.host.clone()),
+        ("port", cli.port.to_string()),
+        ("config_style", value_name(cli.config_style)),
+        ("backup_compress", cli.backup_compress.to_string()),
+        ("backup_mode", value_name(cli.backup_mode)),
+        ("backup_full_every", cli.backup_full_every.to_string()),
+        (
+            "backup_keep",
+            cli.backup_keep
+                .map(|keep| keep.to_string())
+                .unwrap_or_else(|| "unlimited".to_string()),
+        ),
+        ("id_scheme", value_name(cli.id_scheme)),
+        ("journal", cli.journal.clone()),
+        ("availability_file", cli.availability_file.clone()),
+        (
+            "availability_retention",
+            format!("{}s", cli.availability_retention.num_seconds()),
+        ),
+        ("shutdown_report_file", cli.shutdown_report_file.clone()),
+        ("no_shutdown_report", cli.no_shutdown_report.to_string()),
+        (
+            "drain_timeout",
+            format!("{}s", cli.drain_timeout.num_seconds()),
+        ),
+        (
+            "enable_fault_injection",
+            cli.enable_fault_injection.to_string(),
+        ),
+        ("migrate", value_name(cli.migrate)),
+    ];
+
+    values
+        .into_iter()
+        .map(|(name, value)| Setting {
+            name: name.to_string(),
+            value,
+            source: match matches.value_source(name) {
+                Some(ValueSource::CommandLine) => "command line",
+                Some(ValueSource::EnvVariable) => "environment",
+                Some(ValueSource::DefaultValue) => "default",
+                _ => "unset",
+            }
+            .to_string(),
+        })
+        .collect()
+}
+
+fn value_name(value: impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
+
+/// Check the configuration file and return it if it could be parsed
+fn check_config_file(report: &mut DoctorReport, path: &str) -> Option<ServerConfig> {
+    let path = Path::new(path);
+    if !path.exists() {
+        check_creatable(report, "config_file", path);
+        return None;
+    }
+
+    let raw = match std::fs::read_to_string(path) {
+        Ok(raw) => raw,
+        Err(e) => {
+            report.push(
+                "config_file",
+                CheckStatus::Fail,
+                format!("{}: {}", path.display(), e),
+            );
+            return None;
+        }
+    };
+    check_writable(report, "config_file", path);
+
+    let value: Value = match serde_json::from_str(&raw) {
+        Ok(value) => value,
+        Err(e) => {
+            report.push(
+                "config_schema",
+                CheckStatus::Fail,
+                format!("{} is not valid JSON: {}", path.display(), e),
+            );
+            return None;
+        }
+    };
+    let version = migrations::schema_version(&value);
+    match version.cmp(&CONFIG_SCHEMA_VERSION) {
+        std::cmp::Ordering::Equal => report.push(
+            "config_schema",
+            CheckStatus::Pass,
+            format!("schema version {}", version),
+        ),
+        std::cmp::Ordering::Less => report.push(
+            "config_schema",
+            CheckStatus::Warn,
+            format!(
+                "schema version {} has pending migrations to {}, run `mception-server migrate`",
+                version, CONFIG_SCHEMA_VERSION
+            ),
+        ),
+        std::cmp::Ordering::Greater => report.push(
+            "config_schema",
+            CheckStatus::Fail,
+            format!(
+                "schema version {} is newer than this server supports ({})",
+                version, CONFIG_SCHEMA_VERSION
+            ),
+        ),
+    }
+
+    match serde_json::from_value::<ServerConfig>(value) {
+        Ok(mut config) => {
+            if !config.includes.is_empty() {
+                check_includes(report, path, &mut config);
+            }
+            Some(config)
+        }
+        Err(e) => {
+            report.push(
+                "config_parse",
+                CheckStatus::Fail,
+                format!(
+                    "{} does not match the configuration format: {}",
+                    path.display(),
+                    e
+                ),
+            );
+            None
+        }
+    }
+}
+
+/// Merge the files the configuration includes into it, as the server would
+fn check_includes(report: &mut DoctorReport, path: &Path, config: &mut ServerConfig) {
+    let config_dir = includes::config_dir(path);
+    let resolved = includes::expand(&config_dir, &config.includes).and_then(|files| {
+        let fragments = files
+            .into_iter()
+            .map(|file| {
+                let fragment = std::fs::read_to_string(&file)
+                    .map_err(|e| e.to_string())
+                    .and_then(|content| serde_json::from_str(&content).map_err(|e| e.to_string()))
+                    .map_err(|e| {
+                        format!(
+                            "Included file {} is invalid: {}",
+                            includes::display(&config_dir, &file),
+                            e
+                        )
+                    })?;
+                Ok((file, fragment))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        includes::resolve(config, path, fragments)
+    });
+    match resolved {
+        Ok(sources) => report.push(
+            "config_includes",
+            CheckStatus::Pass,
+            format!("{} included files", sources.fragments.len()),
+        ),
+        Err(e) => report.push("config_includes", CheckStatus::Fail, e),
+    }
+}
+
+async fn check_journal(report: &mut DoctorReport, path: &str, config: Option<&ServerConfig>) {
+    if !Path::new(path).exists() {
+        check_creatable(report, "journal", Path::new(path));
+        return;
+    }
+    check_writable(report, "journal", Path::new(path));
+
+    let revision = config
+        .map(|config| config.metadata.revision)
+        .unwrap_or_default();
+    match ConfigJournal::new(path).entries().await {
+        Ok(entries) => {
+            let pending = entries
+                .iter()
+                .filter(|entry| entry.revision > revision)
+                .count();
+            if pending == 0 {
+                report.push("journal_pending", CheckStatus::Pass, "no unsaved mutations")
+            } else {
+                report.push(
+                    "journal_pending",
+                    CheckStatus::Warn,
+                    format!(
+                        "{} mutations missing from the configuration file will be replayed on start",
+                        pending
+                    ),
+                )
+            }
+        }
+        Err(e) => report.push("journal_pending", CheckStatus::Fail, e.to_string()),
+    }
+}
+
+async fn check_audit_log(report: &mut DoctorReport, path: &str) {
+    if !Path::new(path).exists() {
+        check_creatable(report, "audit_log", Path::new(path));
+        return;
+    }
+    check_writable(report, "audit_log", Path::new(path));
+
+    match FileAuditStorage::new(path).verify().await {
+        Ok(scan) if scan.is_clean() => report.push(
+            "audit_integrity",
+            CheckStatus::Pass,
+            format!("{} valid entries", scan.valid_entries),
+        ),
+        Ok(scan) => report.push(
+            "audit_integrity",
+            CheckStatus::Warn,
+            format!(
+                "{} corrupt regions, run `mception-server verify-audit` for details",
+                scan.corrupt_regions.len()
+            ),
+        ),
+        Err(e) => report.push("audit_integrity", CheckStatus::Fail, e.to_string()),
+    }
+}
+
+/// Check the SQLite database without opening it, which would create or
+/// migrate it
+fn check_database(report: &mut DoctorReport, path: &str) {
+    let path = Path::new(path);
+    if path.exists() {
+        check_writable(report, "database", path);
+    } else {
+        check_creatable(report, "database", path);
+    }
+    report.push(
+        "config_file",
+        CheckStatus::Skip,
+        "the configuration is stored in the database",
+    );
+}
+
+/// Check that an existing file can be opened for writing, without changing it
+fn check_writable(report: &mut DoctorReport, name: &str, path: &Path) {
+    match std::fs::OpenOptions::new().append(true).open(path) {
+        Ok(_) => report.push(
+            name,
+            CheckStatus::Pass,
+            format!("{} is writable", path.display()),
+        ),
+        Err(e) => report.push(
+            name,
+            CheckStatus::Fail,
+            format!("{} is not writable: {}", path.display(), e),
+        ),
+    }
+}
+
+/// Check that a missing file could be created by the server
+fn check_creatable(report: &mut DoctorReport, name: &str, path: &Path) {
+    let Some(dir) = existing_ancestor(path) else {
+        report.push(
+            name,
+            CheckStatus::Fail,
+            format!("{} has no existing parent directory", path.display()),
+        );
+        return;
+    };
+    let writable = std::fs::metadata(&dir).is_ok_and(|metadata| !metadata.permissions().readonly());
+    if writable {
+        report.push(
+            name,
+            CheckStatus::Warn,
+            format!("{} does not exist yet and will be created", path.display()),
+        );
+    } else {
+        report.push(
+            name,
+            CheckStatus::Fail,
+            format!(
+                "{} does not exist and {} is not writable",
+                path.display(),
+                dir.display()
+            ),
+        );
+    }
+}
+
+fn existing_ancestor(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .skip(1)
+        .map(|dir| {
+            if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            }
+        })
+        .find(|dir| dir.is_dir())
+        .map(Path::to_path_buf)
+}
+
+async fn check_running_server(report: &mut DoctorReport, cli: &Cli, config: Option<&ServerConfig>) {
+    let host = match cli.host.as_str() {
+        "0.0.0.0" | "::" => "127.0.0.1",
+        host => host,
+    };
+    let url = format!("http://{}:{}/admin/status", host, cli.port);
+
+    let mut request = reqwest::Client::new().get(&url);
+    if let Some(token) = cli.admin_tokens.first() {
+        request = token.authorize(request);
+    }
+    let status: Value = match request.timeout(PING_TIMEOUT).send().await {
+        Ok(response) if response.status().is_success() => match response.json().await {
+            Ok(status) => status,
+            Err(e) => {
+                report.push(
+                    "running_server",
+                    CheckStatus::Warn,
+                    format!("{} answered with an invalid status: {}", url, e),
+                );
+                return;
+            }
+        },
+        Ok(response) => {
+            report.push(
+                "running_server",
+                CheckStatus::Warn,
+                format!("{} answered with {}", url, response.status()),
+            );
+            return;
+        }
+        Err(_) => {
+            report.push(
+                "running_server",
+                CheckStatus::Skip,
+                format!("no server answering at {}", url),
+            );
+            return;
+        }
+    };
+
+    report.push(
+        "running_server",
+        CheckStatus::Pass,
+        format!(
+            "version {} at {}",
+            status["server_version"].as_str().unwrap_or("unknown"),
+            url
+        ),
+    );
+
+    // Compare the storage the server uses with the one the flags point at
+    let server_config = status["config_path"].as_str().map(PathBuf::from);
+    match server_config {
+        Some(server_path) if same_file(&server_path, Path::new(&cli.config)) => report.push(
+            "server_config_path",
+            CheckStatus::Pass,
+            format!("server uses {}", server_path.display()),
+        ),
+        Some(server_path) => report.push(
+            "server_config_path",
+            CheckStatus::Fail,
+            format!(
+                "server uses {} but --config points at {}",
+                server_path.display(),
+                cli.config
+            ),
+        ),
+        None => report.push(
+            "server_config_path",
+            CheckStatus::Warn,
+            format!(
+                "server uses {}",
+                status["config_storage"]
+                    .as_str()
+                    .unwrap_or("unknown storage")
+            ),
+        ),
+    }
+
+    let server_revision = status["revision"].as_u64().unwrap_or_default();
+    match config {
+        Some(config) if config.metadata.revision == server_revision => report.push(
+            "server_revision",
+            CheckStatus::Pass,
+            format!("server and file are at revision {}", server_revision),
+        ),
+        Some(config) => report.push(
+            "server_revision",
+            CheckStatus::Warn,
+            format!(
+                "server is at revision {} but the file is at {}",
+                server_revision, config.metadata.revision
+            ),
+        ),
+        None => report.push(
+            "server_revision",
+            CheckStatus::Skip,
+            "no local configuration to compare with",
+        ),
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Static health of each leaf MCP: whether it could be started or reached
+fn check_leaf_mcps(report: &mut DoctorReport, config: &ServerConfig) {
+    if config.leaf_mcps.is_empty() {
+        report.push("leaf_mcps", CheckStatus::Pass, "no leaf MCPs configured");
+        return;
+    }
+
+    for (id, leaf) in &config.leaf_mcps {
+        let name = format!("leaf:{}", id);
+        match &leaf.transport {
+            McpTransport::Stdio { .. } if leaf.is_local => report.push(
+                name,
+                CheckStatus::Skip,
+                "stdio MCP runs on the agent system",
+            ),
+            McpTransport::Stdio {
+                command, sandbox, ..
+            } => {
+                if let Err(e) = sandbox::validate(sandbox) {
+                    report.push(name, CheckStatus::Fail, format!("sandbox: {}", e));
+                } else if !command_exists(command) {
+                    report.push(
+                        name,
+                        CheckStatus::Fail,
+                        format!("command '{}' not found", command),
+                    );
+                } else {
+                    report.push(name, CheckStatus::Pass, format!("stdio: {}", command));
+                }
+            }
+            McpTransport::Builtin { .. } => {
+                report.push(name, CheckStatus::Pass, "builtin MCP runs in-process")
+            }
+            McpTransport::Https { url, .. } | McpTransport::Sse { url, .. } => {
+                match reqwest::Url::parse(url) {
+                    Ok(parsed) if parsed.scheme() == "https" => {
+                        let kind = match leaf.transport {
+                            McpTransport::Sse { .. } => "sse",
+                            _ => "https",
+                        };
+                        report.push(name, CheckStatus::Pass, format!("{}: {}", kind, url))
+                    }
+                    Ok(_) => report.push(
+                        name,
+                        CheckStatus::Warn,
+                        format!("{} does not use https", url),
+                    ),
+                    Err(e) => report.push(
+                        name,
+                        CheckStatus::Fail,
+                        format!("invalid url '{}': {}", url, e),
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Whether `command` is a path to a file or found on `PATH`
+fn command_exists(command: &str) -> bool {
+    if command.contains(std::path::MAIN_SEPARATOR) {
+        return Path::new(command).is_file();
+    }
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(command).is_file()))
+}
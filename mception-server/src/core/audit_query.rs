@@ -0,0 +1,68 @@
+use super::{AuditAction, AuditLogEntry, AuditTarget};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Filter over audit log entries, shared by `GET /admin/audit` and
+/// `mception-server show-audit`. Text filters match case-insensitively
+/// anywhere in the action, target type or actor, named as in the entries'
+/// JSON, e.g. `remove_allowed_mcp` or `leaf_mcp`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditQuery {
+    /// Only entries at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only entries before this time
+    pub until: Option<DateTime<Utc>>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    /// Entries without actor never match
+    pub actor: Option<String>,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, entry: &AuditLogEntry) -> bool {
+        let contains =
+            |value: &str, filter: &str| value.to_lowercase().contains(&filter.to_lowercase());
+        if self.since.is_some_and(|since| entry.timestamp < since) {
+            return false;
+        }
+        if self.until.is_some_and(|until| entry.timestamp >= until) {
+            return false;
+        }
+        if let Some(action) = &self.action
+            && !contains(&action_name(&entry.action), action)
+        {
+            return false;
+        }
+        if let Some(target_type) = &self.target_type
+            && !contains(&target_type_name(&entry.target), target_type)
+        {
+            return false;
+        }
+        match &self.actor {
+            Some(actor) => entry
+                .actor
+                .as_deref()
+                .is_some_and(|entry_actor| contains(entry_actor, actor)),
+            None => true,
+        }
+    }
+}
+
+/// Name of the action the `action` filter matches against
+pub fn action_name(action: &AuditAction) -> String {
+    type_tag(action)
+}
+
+/// Name of the target's type the `target_type` filter matches against
+pub fn target_type_name(target: &AuditTarget) -> String {
+    type_tag(target)
+}
+
+/// The `type` tag `value` serializes with, so filters match the names
+/// entries are listed with
+fn type_tag(value: &impl Serialize) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value["type"].as_str().map(str::to_string))
+        .unwrap_or_default()
+}
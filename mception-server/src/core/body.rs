@@ -0,0 +1,39 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+
+/// How the `body` of a [`crate::core::ForwardingMessage`] is encoded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BodyEncoding {
+    /// The body as text, the only encoding agents knew at first
+    #[default]
+    Utf8,
+    /// Standard base64 with padding of the body's bytes
+    Base64,
+}
+
+impl BodyEncoding {
+    pub fn is_utf8(&self) -> bool {
+        *self == BodyEncoding::Utf8
+    }
+
+    /// `bytes` as a message body: as text if they are UTF-8, so agents not
+    /// knowing about encodings still read it, and base64 otherwise
+    pub fn encode(bytes: Vec<u8>) -> (String, BodyEncoding) {
+        match String::from_utf8(bytes) {
+            Ok(text) => (text, BodyEncoding::Utf8),
+            Err(e) => (STANDARD.encode(e.as_bytes()), BodyEncoding::Base64),
+        }
+    }
+
+    /// The bytes of a message body encoded this way
+    pub fn decode(self, body: String) -> Result<Vec<u8>, String> {
+        match self {
+            BodyEncoding::Utf8 => Ok(body.into_bytes()),
+            BodyEncoding::Base64 => STANDARD
+                .decode(body)
+                .map_err(|_| "body is not valid base64".to_string()),
+        }
+    }
+}
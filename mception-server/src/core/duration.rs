@@ -0,0 +1,19 @@
+use chrono::Duration;
+
+/// Parse a duration like `500ms`, `90s`, `10m`, `1h` or `7d`; a bare number is taken as seconds
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, "s"),
+    };
+    let number: i64 = number.parse().ok()?;
+    match unit {
+        "ms" => Duration::try_milliseconds(number),
+        "s" => Duration::try_seconds(number),
+        "m" => Duration::try_minutes(number),
+        "h" => Duration::try_hours(number),
+        "d" => Duration::try_days(number),
+        _ => None,
+    }
+}
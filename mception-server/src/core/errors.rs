@@ -1,3 +1,5 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Json, Response};
 use std::fmt;
 
 /// Common result type used throughout the application
@@ -20,6 +22,12 @@ pub enum StorageError {
     NotFound(String),
     AlreadyExists(String),
     Corruption(String),
+    /// The database backend failed, e.g. SQLite
+    Database(String),
+    /// Another process saved a newer configuration in the meantime
+    Conflict(String),
+    /// An update expected an older revision of the entity than the current one
+    StaleRevision(String),
 }
 
 /// Errors related to configuration management
@@ -27,7 +35,6 @@ pub enum StorageError {
 pub enum ConfigurationError {
     InvalidConfiguration(String),
     MissingRequiredField(String),
-    ConflictingSettings(String),
 }
 
 /// Errors related to network operations
@@ -36,6 +43,8 @@ pub enum NetworkError {
     ConnectionFailed(String),
     Timeout(String),
     InvalidUrl(String),
+    /// Refused to stay within a resource limit, retrying later may succeed
+    Overloaded(String),
 }
 
 /// Errors related to data validation
@@ -44,6 +53,89 @@ pub enum ValidationError {
     InvalidFormat(String),
     ValueOutOfRange(String),
     RequiredFieldMissing(String),
+    /// Not allowed by the registration policy, naming the violated rule
+    PolicyViolation(String),
+    /// Agents would reference each other in a cycle, naming the chain
+    CircularReference(String),
+    /// A `config` would grow beyond the server's limits, naming them
+    LimitExceeded(String),
+    /// A `config` doesn't satisfy the configured JSON Schema, naming where
+    SchemaViolation(String),
+}
+
+impl MceptionError {
+    /// Short name of the error for API clients, e.g. `already_exists`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MceptionError::Storage(err) => match err {
+                StorageError::Io(_) => "io",
+                StorageError::Serialization(_) => "serialization",
+                StorageError::NotFound(_) => "not_found",
+                StorageError::AlreadyExists(_) => "already_exists",
+                StorageError::Corruption(_) => "corruption",
+                StorageError::Database(_) => "database",
+                StorageError::Conflict(_) => "conflict",
+                StorageError::StaleRevision(_) => "stale_revision",
+            },
+            MceptionError::Configuration(err) => match err {
+                ConfigurationError::InvalidConfiguration(_) => "invalid_configuration",
+                ConfigurationError::MissingRequiredField(_) => "missing_required_field",
+            },
+            MceptionError::Network(err) => match err {
+                NetworkError::ConnectionFailed(_) => "connection_failed",
+                NetworkError::Timeout(_) => "timeout",
+                NetworkError::InvalidUrl(_) => "invalid_url",
+                NetworkError::Overloaded(_) => "overloaded",
+            },
+            MceptionError::Validation(err) => match err {
+                ValidationError::InvalidFormat(_) => "invalid_format",
+                ValidationError::ValueOutOfRange(_) => "value_out_of_range",
+                ValidationError::RequiredFieldMissing(_) => "required_field_missing",
+                ValidationError::PolicyViolation(_) => "policy_violation",
+                ValidationError::CircularReference(_) => "circular_reference",
+                ValidationError::LimitExceeded(_) => "limit_exceeded",
+                ValidationError::SchemaViolation(_) => "schema_violation",
+            },
+        }
+    }
+
+    /// HTTP status the error is answered with
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            MceptionError::Storage(StorageError::NotFound(_)) => StatusCode::NOT_FOUND,
+            MceptionError::Storage(StorageError::AlreadyExists(_)) => StatusCode::CONFLICT,
+            MceptionError::Storage(StorageError::Conflict(_)) => StatusCode::CONFLICT,
+            MceptionError::Storage(StorageError::StaleRevision(_)) => StatusCode::CONFLICT,
+            MceptionError::Validation(ValidationError::PolicyViolation(_)) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            MceptionError::Validation(ValidationError::LimitExceeded(_)) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            MceptionError::Validation(ValidationError::SchemaViolation(_)) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            MceptionError::Validation(_) => StatusCode::BAD_REQUEST,
+            MceptionError::Network(NetworkError::Timeout(_)) => StatusCode::GATEWAY_TIMEOUT,
+            MceptionError::Network(NetworkError::Overloaded(_)) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Body of API error responses: `{"error": {"kind": "...", "message": "..."}}`
+pub fn error_body(kind: &str, message: impl fmt::Display) -> serde_json::Value {
+    serde_json::json!({ "error": { "kind": kind, "message": message.to_string() } })
+}
+
+impl IntoResponse for MceptionError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        if status == StatusCode::INTERNAL_SERVER_ERROR {
+            tracing::error!("{}", self);
+        }
+        (status, Json(error_body(self.kind(), &self))).into_response()
+    }
 }
 
 // Implement From traits for common error conversions
@@ -90,8 +182,13 @@ impl fmt::Display for StorageError {
             StorageError::Io(err) => write!(f, "IO error: {}", err),
             StorageError::Serialization(err) => write!(f, "Serialization error: {}", err),
             StorageError::NotFound(resource) => write!(f, "Resource not found: {}", resource),
-            StorageError::AlreadyExists(resource) => write!(f, "Resource already exists: {}", resource),
+            StorageError::AlreadyExists(resource) => {
+                write!(f, "Resource already exists: {}", resource)
+            }
             StorageError::Corruption(details) => write!(f, "Data corruption detected: {}", details),
+            StorageError::Database(details) => write!(f, "Database error: {}", details),
+            StorageError::Conflict(details) => write!(f, "Conflicting change: {}", details),
+            StorageError::StaleRevision(details) => write!(f, "Stale revision: {}", details),
         }
     }
 }
@@ -99,9 +196,12 @@ impl fmt::Display for StorageError {
 impl fmt::Display for ConfigurationError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            ConfigurationError::InvalidConfiguration(details) => write!(f, "Invalid configuration: {}", details),
-            ConfigurationError::MissingRequiredField(field) => write!(f, "Missing required field: {}", field),
-            ConfigurationError::ConflictingSettings(details) => write!(f, "Conflicting settings: {}", details),
+            ConfigurationError::InvalidConfiguration(details) => {
+                write!(f, "Invalid configuration: {}", details)
+            }
+            ConfigurationError::MissingRequiredField(field) => {
+                write!(f, "Missing required field: {}", field)
+            }
         }
     }
 }
@@ -112,6 +212,7 @@ impl fmt::Display for NetworkError {
             NetworkError::ConnectionFailed(details) => write!(f, "Connection failed: {}", details),
             NetworkError::Timeout(details) => write!(f, "Operation timed out: {}", details),
             NetworkError::InvalidUrl(url) => write!(f, "Invalid URL: {}", url),
+            NetworkError::Overloaded(details) => write!(f, "Overloaded: {}", details),
         }
     }
 }
@@ -120,8 +221,20 @@ impl fmt::Display for ValidationError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ValidationError::InvalidFormat(details) => write!(f, "Invalid format: {}", details),
-            ValidationError::ValueOutOfRange(details) => write!(f, "Value out of range: {}", details),
-            ValidationError::RequiredFieldMissing(field) => write!(f, "Required field missing: {}", field),
+            ValidationError::ValueOutOfRange(details) => {
+                write!(f, "Value out of range: {}", details)
+            }
+            ValidationError::RequiredFieldMissing(field) => {
+                write!(f, "Required field missing: {}", field)
+            }
+            ValidationError::PolicyViolation(details) => {
+                write!(f, "Registration policy violation: {}", details)
+            }
+            ValidationError::CircularReference(chain) => {
+                write!(f, "Circular agent reference: {}", chain)
+            }
+            ValidationError::LimitExceeded(details) => write!(f, "Limit exceeded: {}", details),
+            ValidationError::SchemaViolation(details) => write!(f, "Schema violation: {}", details),
         }
     }
 }
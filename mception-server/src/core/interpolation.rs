@@ -0,0 +1,78 @@
+use crate::core::{ConfigurationError, MceptionError, MceptionResult};
+
+/// A `${NAME}` placeholder in a text, or a `$${NAME}` escaping one
+struct Placeholder {
+    start: usize,
+    end: usize,
+    escaped: bool,
+}
+
+/// Whether `text` holds a `${NAME}` placeholder that isn't escaped
+pub fn has_placeholder(text: &str) -> bool {
+    let mut rest = text;
+    while let Some(found) = next_placeholder(rest) {
+        if !found.escaped {
+            return true;
+        }
+        rest = &rest[found.end..];
+    }
+    false
+}
+
+/// `text` with each `${NAME}` placeholder replaced by `lookup` of the name,
+/// and each `$${NAME}` by the literal `${NAME}`. `$` not starting a
+/// placeholder is kept as is, and so are placeholders in the values looked
+/// up. Fails on the first name `lookup` doesn't know.
+pub fn interpolate(text: &str, lookup: impl Fn(&str) -> Option<String>) -> MceptionResult<String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(found) = next_placeholder(rest) {
+        result.push_str(&rest[..found.start]);
+        if found.escaped {
+            result.push_str(&rest[found.start + 1..found.end]);
+        } else {
+            let name = &rest[found.start + 2..found.end - 1];
+            let value = lookup(name).ok_or_else(|| {
+                MceptionError::Configuration(ConfigurationError::MissingRequiredField(format!(
+                    "environment variable {} is not set",
+                    name
+                )))
+            })?;
+            result.push_str(&value);
+        }
+        rest = &rest[found.end..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// [`interpolate`] from the server's environment
+pub fn resolve(text: &str) -> MceptionResult<String> {
+    interpolate(text, |name| std::env::var(name).ok())
+}
+
+/// The first placeholder in `text`, `${` to `}` around a name of letters,
+/// digits and underscores not starting with a digit, with its escaping `$`
+fn next_placeholder(text: &str) -> Option<Placeholder> {
+    let mut offset = 0;
+    while let Some(found) = text[offset..].find("${") {
+        let start = offset + found;
+        let length = text[start + 2..].find('}')?;
+        let name = &text[start + 2..start + 2 + length];
+        let valid = name
+            .chars()
+            .next()
+            .is_some_and(|first| first == '_' || first.is_ascii_alphabetic())
+            && name.chars().all(|c| c == '_' || c.is_ascii_alphanumeric());
+        if valid {
+            let escaped = text[..start].ends_with('$');
+            return Some(Placeholder {
+                start: if escaped { start - 1 } else { start },
+                end: start + 3 + length,
+                escaped,
+            });
+        }
+        offset = start + 2;
+    }
+    None
+}
@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// One term of a [`LabelSelector`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelTerm {
+    /// `key=value`: the label is set to the value
+    Equals(String, String),
+    /// `key!=value`: the label is not set, or set to another value
+    NotEquals(String, String),
+}
+
+impl LabelTerm {
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        match self {
+            LabelTerm::Equals(key, value) => labels.get(key) == Some(value),
+            LabelTerm::NotEquals(key, value) => labels.get(key) != Some(value),
+        }
+    }
+}
+
+impl fmt::Display for LabelTerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LabelTerm::Equals(key, value) => write!(f, "{}={}", key, value),
+            LabelTerm::NotEquals(key, value) => write!(f, "{}!={}", key, value),
+        }
+    }
+}
+
+/// Comma-separated terms like `env=staging,team!=payments`, all of which
+/// have to hold for labels to match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelSelector {
+    pub terms: Vec<LabelTerm>,
+}
+
+impl LabelSelector {
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.terms.iter().all(|term| term.matches(labels))
+    }
+}
+
+impl FromStr for LabelSelector {
+    type Err = String;
+
+    fn from_str(selector: &str) -> Result<Self, String> {
+        let terms = selector
+            .split(',')
+            .map(|term| {
+                let term = term.trim();
+                let (key, value, negated) = match term.split_once("!=") {
+                    Some((key, value)) => (key, value, true),
+                    None => match term.split_once('=') {
+                        Some((key, value)) => (key, value, false),
+                        None => {
+                            return Err(format!(
+                                "label selector term '{}' is neither key=value nor key!=value",
+                                term
+                            ));
+                        }
+                    },
+                };
+                let (key, value) = (key.trim(), value.trim());
+                validate_key(key)?;
+                validate_value(key, value)?;
+                let (key, value) = (key.to_string(), value.to_string());
+                Ok(if negated {
+                    LabelTerm::NotEquals(key, value)
+                } else {
+                    LabelTerm::Equals(key, value)
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { terms })
+    }
+}
+
+impl fmt::Display for LabelSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, term) in self.terms.iter().enumerate() {
+            if index > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", term)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a single `key=value` label, as given on the command line
+pub fn parse_label(label: &str) -> Result<(String, String), String> {
+    let (key, value) = label
+        .split_once('=')
+        .ok_or_else(|| format!("label '{}' is not key=value", label))?;
+    validate_key(key)?;
+    validate_value(key, value)?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Check labels can be selected: keys are non-empty and neither contain
+/// `=`, `!`, `,` nor surrounding whitespace, values contain no `,`
+pub fn validate(labels: &BTreeMap<String, String>) -> Result<(), String> {
+    for (key, value) in labels {
+        validate_key(key)?;
+        validate_value(key, value)?;
+    }
+    Ok(())
+}
+
+fn validate_key(key: &str) -> Result<(), String> {
+    if key.is_empty() {
+        return Err("label keys must not be empty".to_string());
+    }
+    if key.trim() != key || key.contains(['=', '!', ',']) {
+        return Err(format!(
+            "label key '{}' must not contain '=', '!', ',' or surrounding whitespace",
+            key
+        ));
+    }
+    Ok(())
+}
+
+fn validate_value(key: &str, value: &str) -> Result<(), String> {
+    if value.trim() != value || value.contains(',') {
+        return Err(format!(
+            "value of label '{}' must not contain ',' or surrounding whitespace",
+            key
+        ));
+    }
+    Ok(())
+}
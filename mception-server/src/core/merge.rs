@@ -0,0 +1,81 @@
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Map, Value};
+
+/// Apply an RFC 7386 JSON Merge Patch to `target` in place.
+///
+/// `null` values remove fields, objects are merged recursively and any other
+/// value (including arrays) replaces the target value.
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch_map) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(target_map) = target else {
+        unreachable!("target was just replaced with an object");
+    };
+
+    for (key, value) in patch_map {
+        if value.is_null() {
+            target_map.remove(key);
+        } else {
+            merge_patch(target_map.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// Compute a JSON Merge Patch that turns `source` into `target`.
+///
+/// Merge patches can't express "set to null", so fields that become `null`
+/// are emitted as removals. Callers that need an exact round trip should
+/// verify the result with [`merge_patch`].
+pub fn diff(source: &Value, target: &Value) -> Value {
+    match (source, target) {
+        (Value::Object(source_map), Value::Object(target_map)) => {
+            let mut patch = Map::new();
+            for key in source_map.keys() {
+                if !target_map.contains_key(key) {
+                    patch.insert(key.clone(), Value::Null);
+                }
+            }
+            for (key, target_value) in target_map {
+                match source_map.get(key) {
+                    Some(source_value) if source_value == target_value => {}
+                    Some(source_value) if source_value.is_object() && target_value.is_object() => {
+                        patch.insert(key.clone(), diff(source_value, target_value));
+                    }
+                    _ => {
+                        patch.insert(key.clone(), target_value.clone());
+                    }
+                }
+            }
+            Value::Object(patch)
+        }
+        _ => target.clone(),
+    }
+}
+
+/// Apply a partial update to a configuration entity as a JSON Merge Patch,
+/// see [`merge_patch`]: `null` clears a field, objects such as `config` are
+/// merged and anything else replaces the field. The `immutable` fields can't
+/// be changed by the update; setting them to their current value is allowed.
+pub fn apply_update<T>(current: &T, updates: &Value, immutable: &[&str]) -> Result<T, String>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let mut value = serde_json::to_value(current).map_err(|e| e.to_string())?;
+    if let Some(field) = immutable.iter().find(|field| {
+        updates
+            .get(**field)
+            .is_some_and(|update| Some(update) != value.get(**field))
+    }) {
+        return Err(format!("{} can't be changed", field));
+    }
+    if updates.is_object() {
+        merge_patch(&mut value, updates);
+    }
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
@@ -1,6 +1,16 @@
+pub mod audit_query;
+pub mod body;
+pub mod duration;
 pub mod errors;
+pub mod interpolation;
+pub mod labels;
+pub mod merge;
+pub mod pagination;
+pub mod testing;
 pub mod types;
 
 // Re-export commonly used types
+pub use audit_query::AuditQuery;
+pub use body::BodyEncoding;
 pub use errors::*;
 pub use types::*;
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Largest page that can be requested
+pub const MAX_LIMIT: usize = 1000;
+
+/// A cursor is stale once the list's revision moved on by more than this
+pub const MAX_REVISION_LAG: u64 = 1000;
+
+/// Pagination query parameters shared by all list endpoints. Without any of
+/// them the whole list is returned.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PageQuery {
+    /// Maximum number of items, at most [`MAX_LIMIT`]
+    pub limit: Option<usize>,
+    /// Number of items to skip, for simple cases where the list doesn't change
+    pub offset: Option<usize>,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+}
+
+/// Position after the last item of a page. Encoded opaquely for clients.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor {
+    /// The list the cursor belongs to, e.g. `audit`
+    pub list: String,
+    /// Sort key of the last item
+    pub key: String,
+    /// ID of the last item, breaking ties between equal sort keys
+    pub id: String,
+    /// Revision of the list the page was read at
+    pub revision: u64,
+}
+
+impl Cursor {
+    /// Hex encoding of the cursor's JSON
+    pub fn encode(&self) -> String {
+        serde_json::to_vec(self)
+            .unwrap_or_default()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    pub fn decode(value: &str) -> Result<Self, PageError> {
+        let invalid =
+            || PageError::InvalidCursor("not a cursor returned by this server".to_string());
+        if !value.len().is_multiple_of(2) || !value.is_ascii() {
+            return Err(invalid());
+        }
+        let bytes = (0..value.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&value[index..index + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| invalid())?;
+        serde_json::from_slice(&bytes).map_err(|_| invalid())
+    }
+}
+
+/// A page of a list
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Number of items in the whole list
+    pub total: usize,
+    /// Position of the page's first item in the whole list
+    pub offset: usize,
+    /// Cursor for the next page, `None` on the last page
+    pub next_cursor: Option<String>,
+    /// Revision of the list the page was read at
+    pub revision: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PageError {
+    InvalidQuery(String),
+    InvalidCursor(String),
+    /// The list changed too much since the cursor was issued, start over
+    StaleCursor {
+        cursor_revision: u64,
+        current_revision: u64,
+    },
+}
+
+impl fmt::Display for PageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PageError::InvalidQuery(details) => write!(f, "Invalid pagination: {}", details),
+            PageError::InvalidCursor(details) => write!(f, "Invalid cursor: {}", details),
+            PageError::StaleCursor {
+                cursor_revision,
+                current_revision,
+            } => write!(
+                f,
+                "Cursor was issued at revision {} but the list is at revision {}, restart from the first page without a cursor",
+                cursor_revision, current_revision
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PageError {}
+
+/// Slice one page out of a list. Items are ordered by their `(sort key, id)`
+/// from `key`, and a cursor continues after the item it was issued for, so
+/// items added or removed between pages are never skipped or repeated.
+pub fn paginate<T>(
+    list: &str,
+    mut items: Vec<T>,
+    key: impl Fn(&T) -> (String, String),
+    revision: u64,
+    query: &PageQuery,
+) -> Result<Page<T>, PageError> {
+    let limit = match query.limit {
+        Some(0) => {
+            return Err(PageError::InvalidQuery(
+                "limit must be at least 1".to_string(),
+            ));
+        }
+        Some(limit) if limit > MAX_LIMIT => {
+            return Err(PageError::InvalidQuery(format!(
+                "limit must be at most {}",
+                MAX_LIMIT
+            )));
+        }
+        limit => limit,
+    };
+
+    items.sort_by_cached_key(|item| key(item));
+
+    let start = match (&query.cursor, query.offset) {
+        (Some(_), Some(_)) => {
+            return Err(PageError::InvalidQuery(
+                "offset and cursor can't be combined".to_string(),
+            ));
+        }
+        (Some(cursor), None) => {
+            let cursor = Cursor::decode(cursor)?;
+            if cursor.list != list {
+                return Err(PageError::InvalidCursor(format!(
+                    "cursor belongs to '{}', not '{}'",
+                    cursor.list, list
+                )));
+            }
+            if cursor.revision > revision || revision - cursor.revision > MAX_REVISION_LAG {
+                return Err(PageError::StaleCursor {
+                    cursor_revision: cursor.revision,
+                    current_revision: revision,
+                });
+            }
+            let last = (cursor.key, cursor.id);
+            items.partition_point(|item| key(item) <= last)
+        }
+        (None, offset) => offset.unwrap_or(0).min(items.len()),
+    };
+
+    let end = limit.map_or(items.len(), |limit| (start + limit).min(items.len()));
+    let next_cursor = (end < items.len() && end > start).then(|| {
+        let (key, id) = key(&items[end - 1]);
+        Cursor {
+            list: list.to_string(),
+            key,
+            id,
+            revision,
+        }
+        .encode()
+    });
+
+    Ok(Page {
+        total: items.len(),
+        offset: start,
+        items: items.drain(start..end).collect(),
+        next_cursor,
+        revision,
+    })
+}
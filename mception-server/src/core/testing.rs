@@ -0,0 +1,17 @@
+use std::path::PathBuf;
+
+/// Path of the test fixture `name` in this crate's `tests/fixtures`, e.g.
+/// `golden/server_config.json`
+pub fn fixture_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+/// Content of the fixture `name`. Panics if it can't be read, fixtures are
+/// part of the source tree.
+pub fn load_fixture(name: &str) -> String {
+    let path = fixture_path(name);
+    std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("failed to read fixture {}: {}", path.display(), e))
+}
@@ -1,22 +1,72 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use crate::core::body::BodyEncoding;
+use crate::core::interpolation::has_placeholder;
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 
 /// Configuration for a leaf MCP (Model Context Protocol) server
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LeafMcpConfig {
+    /// Same as the key in `leaf_mcps`, filled in on create
+    #[serde(default)]
     pub id: String,
     pub name: Option<String>,
+    /// For administrators, never shown to agents
     pub description: Option<String>,
+    /// Guidance for agents on when and how to use the MCP, passed to them verbatim
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
     pub transport: McpTransport,
     /// If the leaf MCP is hosted on the Agent system, not the server system
     pub is_local: bool,
     /// Whether the MCP is reachable by agents directly
     pub reachable_by_agent: bool,
     /// Additional configuration specific to the MCP
+    #[serde(default)]
     pub config: serde_json::Value,
+    /// How requests the leaf MCP sends to its client are answered
+    #[serde(default, skip_serializing_if = "ReverseRequestPolicy::is_default")]
+    pub reverse_requests: ReverseRequestPolicy,
+    /// Region the leaf MCP runs in, preferred by agents in the same region
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    /// Leaf MCPs in the same replica group are interchangeable, and agents
+    /// are routed to one of them, see [`crate::services::replicas`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replica_group: Option<String>,
+    /// Bound for each forwarded request in milliseconds, taking precedence
+    /// over `timeout` in `config` and the server's `--leaf-timeout`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Retrying forwarded requests that failed transiently, not retried without
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+    /// Incoming headers passed on to an HTTPS leaf MCP besides those of the
+    /// MCP protocol, none without
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header_policy: Option<HeaderPolicy>,
+    /// Requests forwarded to the leaf MCP at once, further ones wait. Small
+    /// for stdio leaf MCPs and generous for remote ones without, see
+    /// [`crate::services::concurrency`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_requests: Option<u32>,
+    /// Labels to group leaf MCPs by, e.g. `team=payments`, see
+    /// [`crate::core::labels`]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Server revision the leaf MCP was last changed at, 0 if it wasn't
+    /// changed since revisions were recorded per entity
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub revision: u64,
 }
 
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+/// Maximum length of leaf MCP instructions, in bytes
+pub const MAX_INSTRUCTIONS_LEN: usize = 4096;
+
 /// Transport configuration for MCP connections
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
@@ -24,12 +74,262 @@ pub enum McpTransport {
     Stdio {
         command: String,
         args: Vec<String>,
-        env: Option<HashMap<String, String>>,
+        env: Option<BTreeMap<String, String>>,
+        /// Optional restrictions applied when spawning the process
+        #[serde(flatten)]
+        sandbox: StdioSandbox,
     },
     Https {
         url: String,
-        headers: Option<HashMap<String, String>>,
+        headers: Option<BTreeMap<String, String>>,
     },
+    /// The legacy HTTP+SSE transport: an event stream opened at `url` names
+    /// the endpoint messages are posted to and carries the responses
+    Sse {
+        url: String,
+        headers: Option<BTreeMap<String, String>>,
+    },
+    /// An MCP implemented in-process by the server, for demos and tests
+    Builtin { kind: BuiltinMcpKind },
+}
+
+/// In-process MCP implementations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuiltinMcpKind {
+    /// Tools `echo`, `sleep_ms` and `fail_with` for exercising agents
+    Echo,
+}
+
+impl LeafMcpConfig {
+    /// The leaf MCP as shown in API responses and the audit log, see
+    /// [`McpTransport::redacted`]
+    pub fn redacted(&self) -> Self {
+        Self {
+            transport: self.transport.redacted(),
+            ..self.clone()
+        }
+    }
+}
+
+impl McpTransport {
+    /// The transport with the values of secret-looking headers and `env`
+    /// variables, and values with `${NAME}` placeholders, replaced by
+    /// [`REDACTED`]
+    pub fn redacted(&self) -> Self {
+        match self {
+            McpTransport::Stdio {
+                command,
+                args,
+                env,
+                sandbox,
+            } => McpTransport::Stdio {
+                command: command.clone(),
+                args: args.iter().map(|arg| redacted_value(None, arg)).collect(),
+                env: env.as_ref().map(redacted_values),
+                sandbox: sandbox.clone(),
+            },
+            McpTransport::Https { url, headers } => McpTransport::Https {
+                url: url.clone(),
+                headers: headers.as_ref().map(redacted_values),
+            },
+            McpTransport::Sse { url, headers } => McpTransport::Sse {
+                url: url.clone(),
+                headers: headers.as_ref().map(redacted_values),
+            },
+            McpTransport::Builtin { .. } => self.clone(),
+        }
+    }
+
+    /// Take the values that are [`REDACTED`], e.g. in a configuration that
+    /// was read through the admin API, from `previous` where it has them
+    pub fn restore_redacted(&mut self, previous: &McpTransport) {
+        match (self, previous) {
+            (
+                McpTransport::Stdio { args, env, .. },
+                McpTransport::Stdio {
+                    args: previous_args,
+                    env: previous_env,
+                    ..
+                },
+            ) => {
+                for (arg, previous) in args.iter_mut().zip(previous_args) {
+                    if arg == REDACTED {
+                        arg.clone_from(previous);
+                    }
+                }
+                if let (Some(env), Some(previous)) = (env, previous_env) {
+                    restore_redacted_values(env, previous);
+                }
+            }
+            (
+                McpTransport::Https { headers, .. },
+                McpTransport::Https {
+                    headers: previous, ..
+                },
+            )
+            | (
+                McpTransport::Sse { headers, .. },
+                McpTransport::Sse {
+                    headers: previous, ..
+                },
+            ) => {
+                if let (Some(headers), Some(previous)) = (headers, previous) {
+                    restore_redacted_values(headers, previous);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// A transport update as written to the audit log, with the values
+    /// [`Self::redacted`] hides replaced in the JSON Merge Patch
+    pub fn redact_update(update: &mut serde_json::Value) {
+        for field in ["headers", "env"] {
+            if let Some(values) = update.get_mut(field).and_then(|v| v.as_object_mut()) {
+                for (name, value) in values.iter_mut() {
+                    if let Some(text) = value.as_str() {
+                        *value = redacted_value(Some(name), text).into();
+                    }
+                }
+            }
+        }
+        if let Some(args) = update.get_mut("args").and_then(|v| v.as_array_mut()) {
+            for arg in args.iter_mut() {
+                if let Some(text) = arg.as_str() {
+                    *arg = redacted_value(None, text).into();
+                }
+            }
+        }
+    }
+}
+
+/// `value`, or [`REDACTED`] if it is resolved from the environment or its
+/// `name` looks like a secret's
+fn redacted_value(name: Option<&str>, value: &str) -> String {
+    if has_placeholder(value) || name.is_some_and(is_secret_key) {
+        REDACTED.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn redacted_values(values: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    values
+        .iter()
+        .map(|(name, value)| (name.clone(), redacted_value(Some(name), value)))
+        .collect()
+}
+
+fn restore_redacted_values(
+    values: &mut BTreeMap<String, String>,
+    previous: &BTreeMap<String, String>,
+) {
+    for (name, value) in values.iter_mut() {
+        if value == REDACTED
+            && let Some(previous) = previous.get(name)
+        {
+            value.clone_from(previous);
+        }
+    }
+}
+
+/// Sandboxing options for spawned stdio MCP processes
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StdioSandbox {
+    /// Working directory of the spawned process
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    /// Run the process as this user (unix only, requires a privileged server)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_as_user: Option<String>,
+    /// Don't inherit the server's environment, only the configured `env` is passed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub clear_env: bool,
+    /// Memory limit, enforced via cgroups when available, otherwise RLIMIT_AS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_memory_mb: Option<u64>,
+    /// Relative CPU weight, requires a delegated cgroup v2 hierarchy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_shares: Option<u64>,
+    /// Absolute paths the process may access. Not enforced by the server itself, but
+    /// exported as `MCEPTION_ALLOWED_PATHS` (colon separated) for wrapper scripts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_paths: Vec<String>,
+}
+
+impl StdioSandbox {
+    pub fn is_empty(&self) -> bool {
+        *self == StdioSandbox::default()
+    }
+}
+
+/// Handling of server-initiated requests of a leaf MCP, e.g. `sampling/createMessage`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReverseRequestPolicy {
+    /// Answer with a JSON-RPC error saying the request is not supported
+    #[default]
+    Reject,
+    /// Relay to the agent the forwarded call came from, if it is connected and
+    /// declared the capability, rejecting otherwise
+    Relay,
+}
+
+impl ReverseRequestPolicy {
+    pub fn is_default(&self) -> bool {
+        *self == ReverseRequestPolicy::default()
+    }
+}
+
+/// How forwarded requests to a leaf MCP are retried, see
+/// [`crate::services::retry`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Attempts including the first one
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for each further one
+    #[serde(default = "default_backoff_ms")]
+    pub backoff_ms: u64,
+    /// Failures that are retried
+    #[serde(default = "default_retry_on")]
+    pub retry_on: BTreeSet<RetryOn>,
+    /// Retry `tools/call` too, for leaf MCPs whose tools are safe to call twice
+    #[serde(default)]
+    pub tool_calls: bool,
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_backoff_ms() -> u64 {
+    100
+}
+
+fn default_retry_on() -> BTreeSet<RetryOn> {
+    BTreeSet::from([RetryOn::LeafUnreachable])
+}
+
+/// Which headers of a forwarded request are passed on to an HTTPS leaf MCP,
+/// see [`crate::services::https::passed_through`]. Its configured `headers`
+/// are always sent, replacing incoming ones of the same name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderPolicy {
+    /// Names of incoming headers to pass on, in any case, e.g. `x-request-id`
+    #[serde(default)]
+    pub passthrough: Vec<String>,
+}
+
+/// Classes of failures of a forwarded request that can be retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryOn {
+    /// The leaf MCP couldn't be reached, e.g. its connection was reset
+    LeafUnreachable,
+    /// The leaf MCP's transport gave up waiting, within the request deadline
+    LeafTimeout,
 }
 
 /// Represents an MCP tool definition
@@ -53,38 +353,801 @@ pub struct AgentConfig {
     /// Last time the agent was seen
     pub last_seen: Option<DateTime<Utc>>,
     /// Additional configuration for the agent
+    #[serde(default)]
+    pub config: serde_json::Value,
+    /// Token the agent authenticates with as `Authorization: Bearer <token>`.
+    /// Agents without one are not authenticated. Never shown, see [`AgentConfig::redacted`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_token: Option<String>,
+    /// When `auth_token` stops being accepted, never if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_token_expires_at: Option<DateTime<Utc>>,
+    /// Region the agent runs in, to pick the nearest replica of a leaf MCP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    /// Which tools the agent may call, by the ID of the MCP they belong to.
+    /// Every tool of an allowed MCP without a filter may be called.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tool_filters: BTreeMap<String, ToolFilter>,
+    /// Where entries of `allowed_mcp_ids` were granted from, for entries not
+    /// just granted directly. An entry stays allowed until every source
+    /// granting it is removed.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub grant_sources: BTreeMap<String, BTreeSet<GrantSource>>,
+    /// When entries of `allowed_mcp_ids` stop allowing what they grant, for
+    /// entries that expire. Expired entries are kept until they are removed.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub grant_expiry: BTreeMap<String, DateTime<Utc>>,
+    /// Labels to group agents by, like [`LeafMcpConfig::labels`]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Server revision the agent was last changed at, like
+    /// [`LeafMcpConfig::revision`]. Connecting doesn't change it.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub revision: u64,
+}
+
+/// Where a grant of an agent came from
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum GrantSource {
+    /// Granted to the agent itself, `direct`
+    Direct,
+    /// Granted to a group the agent is in, `group:<id>`
+    Group(String),
+    /// Granted to agents with a tag, `tag:<name>`
+    Tag(String),
+    /// Granted by the template agents are created from, `default-template`
+    DefaultTemplate,
+    /// Granted by an approved access request, `approval:<id>`
+    Approval(String),
+}
+
+impl std::fmt::Display for GrantSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GrantSource::Direct => write!(f, "direct"),
+            GrantSource::Group(id) => write!(f, "group:{}", id),
+            GrantSource::Tag(name) => write!(f, "tag:{}", name),
+            GrantSource::DefaultTemplate => write!(f, "default-template"),
+            GrantSource::Approval(id) => write!(f, "approval:{}", id),
+        }
+    }
+}
+
+impl std::str::FromStr for GrantSource {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let named = |prefix: &str| {
+            source
+                .strip_prefix(prefix)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+        };
+        match source {
+            "direct" => Ok(GrantSource::Direct),
+            "default-template" => Ok(GrantSource::DefaultTemplate),
+            _ => named("group:")
+                .map(GrantSource::Group)
+                .or_else(|| named("tag:").map(GrantSource::Tag))
+                .or_else(|| named("approval:").map(GrantSource::Approval))
+                .ok_or_else(|| {
+                    format!(
+                        "unknown grant source '{}', expected direct, group:<id>, tag:<name>, default-template or approval:<id>",
+                        source
+                    )
+                }),
+        }
+    }
+}
+
+impl TryFrom<String> for GrantSource {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        source.parse()
+    }
+}
+
+impl From<GrantSource> for String {
+    fn from(source: GrantSource) -> Self {
+        source.to_string()
+    }
+}
+
+/// Tools of an MCP an agent may call, as `*` and `?` glob patterns over
+/// tool names. A tool matching a `deny` pattern is refused even if allowed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ToolFilter {
+    /// Only tools matching one of these, every tool if `None`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+}
+
+/// What secrets are replaced with wherever configurations are shown
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Parts of names of JSON keys, query parameters, headers and environment
+/// variables holding secrets
+const SECRET_KEYS: &[&str] = &[
+    "authorization",
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "api_key",
+    "api-key",
+    "apikey",
+    "access_key",
+    "private_key",
+    "cookie",
+    "credential",
+];
+
+/// Whether values under this JSON key, query parameter, header or
+/// environment variable are secrets
+pub fn is_secret_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    SECRET_KEYS.iter().any(|secret| key.contains(secret))
+}
+
+impl AgentConfig {
+    /// The agent as shown in API responses and the audit log, with its token
+    /// replaced by [`REDACTED`]
+    pub fn redacted(&self) -> Self {
+        Self {
+            auth_token: self.auth_token.as_ref().map(|_| REDACTED.to_string()),
+            ..self.clone()
+        }
+    }
+
+    /// Whether the agent has a token that expired by `now`
+    pub fn auth_token_expired(&self, now: DateTime<Utc>) -> bool {
+        self.auth_token.is_some() && self.auth_token_expires_at.is_some_and(|at| at <= now)
+    }
+
+    /// Sources of an `allowed_mcp_ids` entry, none if it isn't one
+    pub fn grant_sources_of(&self, grant: &str) -> BTreeSet<GrantSource> {
+        if !self.allowed_mcp_ids.iter().any(|allowed| allowed == grant) {
+            return BTreeSet::new();
+        }
+        self.grant_sources
+            .get(grant)
+            .filter(|sources| !sources.is_empty())
+            .cloned()
+            .unwrap_or_else(|| BTreeSet::from([GrantSource::Direct]))
+    }
+
+    /// Add a source of `grant`, allowing it if it wasn't. Returns whether the
+    /// source is new.
+    pub fn grant(&mut self, grant: &str, source: GrantSource) -> bool {
+        let mut sources = self.grant_sources_of(grant);
+        if !sources.insert(source) {
+            return false;
+        }
+        if !self.allowed_mcp_ids.iter().any(|allowed| allowed == grant) {
+            self.allowed_mcp_ids.push(grant.to_string());
+        }
+        self.set_grant_sources(grant, sources);
+        true
+    }
+
+    /// Remove a source of `grant`, and the grant with its last source.
+    /// Returns the sources left, `None` if `source` didn't grant it.
+    pub fn revoke_source(
+        &mut self,
+        grant: &str,
+        source: &GrantSource,
+    ) -> Option<BTreeSet<GrantSource>> {
+        let mut sources = self.grant_sources_of(grant);
+        if !sources.remove(source) {
+            return None;
+        }
+        if sources.is_empty() {
+            self.revoke(grant);
+        } else {
+            self.set_grant_sources(grant, sources.clone());
+        }
+        Some(sources)
+    }
+
+    /// Remove `grant` whatever granted it, e.g. when the MCP is deleted
+    pub fn revoke(&mut self, grant: &str) {
+        self.allowed_mcp_ids.retain(|allowed| allowed != grant);
+        self.grant_sources.remove(grant);
+        self.grant_expiry.remove(grant);
+    }
+
+    /// Whether `grant` expired by `now`
+    pub fn grant_expired(&self, grant: &str, now: DateTime<Utc>) -> bool {
+        self.grant_expiry.get(grant).is_some_and(|at| *at <= now)
+    }
+
+    /// Bring `grant_sources` in line with `allowed_mcp_ids`, e.g. after the
+    /// list was replaced as a whole: entries without sources, like all
+    /// entries written before grants had sources, are direct grants, and
+    /// sources and expiries of entries no longer allowed are forgotten.
+    /// Returns whether anything changed.
+    pub fn migrate_grant_sources(&mut self) -> bool {
+        let allowed = &self.allowed_mcp_ids;
+        let before = self.grant_sources.len();
+        self.grant_sources.retain(|grant, sources| {
+            allowed.contains(grant) && !sources.iter().all(|source| *source == GrantSource::Direct)
+        });
+        let expiring = self.grant_expiry.len();
+        self.grant_expiry.retain(|grant, _| allowed.contains(grant));
+        self.grant_sources.len() != before || self.grant_expiry.len() != expiring
+    }
+
+    /// Direct grants are kept without sources, leaving allow lists that
+    /// only hold direct grants as they were written
+    fn set_grant_sources(&mut self, grant: &str, sources: BTreeSet<GrantSource>) {
+        if sources.iter().all(|source| *source == GrantSource::Direct) {
+            self.grant_sources.remove(grant);
+        } else {
+            self.grant_sources.insert(grant.to_string(), sources);
+        }
+    }
+}
+
+/// Tags in the free-form `config` of a leaf MCP or agent, e.g. `{"tags": ["project-x"]}`
+pub fn config_tags(config: &serde_json::Value) -> impl Iterator<Item = &str> {
+    config
+        .get("tags")
+        .and_then(serde_json::Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(serde_json::Value::as_str)
+}
+
+/// Prefix of agent allow-list entries that grant a whole bundle
+pub const BUNDLE_PREFIX: &str = "bundle:";
+
+/// A named group of leaf MCPs that can be granted to agents as a unit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleConfig {
+    pub name: String,
+    pub description: Option<String>,
+    /// IDs of the leaf MCPs in this bundle
+    pub members: Vec<String>,
+    /// Incremented whenever the membership changes
+    pub version: u64,
+}
+
+/// Remote MCP configuration served to an agent at `GET /agent/<agent_id>/config`.
+/// Third-party agents depend on this shape, change it only compatibly.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentRemoteConfig {
+    pub agent_id: String,
+    /// Allowed MCPs, with bundle grants resolved to their members
+    pub mcps: BTreeMap<String, RemoteMcpEntry>,
+    /// Bundles granted to the agent
+    pub bundles: BTreeMap<String, RemoteBundle>,
+    /// Expiry of the agent's token, if it expires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_token: Option<RemoteExpiry>,
+    pub metadata: RemoteConfigMetadata,
+}
+
+/// When an agent's token or grant expires, so the agent can alert its operators
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteExpiry {
+    pub expires_at: DateTime<Utc>,
+    /// Whether it expires within the server's warning window
+    pub expires_soon: bool,
+}
+
+/// A single MCP an agent may use
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteMcpEntry {
+    pub kind: RemoteMcpKind,
+    pub name: Option<String>,
+    /// Description of an agent. Leaf MCPs give agents their `instructions` instead.
+    pub description: Option<String>,
+    /// Guidance on when and how to use the MCP
+    #[serde(default)]
+    pub instructions: Option<String>,
+    /// How the agent connects to the MCP
+    pub connection: McpConnection,
+    /// MCP protocol version a stdio leaf MCP's running process answered
+    /// `initialize` with, `None` while none runs
+    pub protocol_hint: Option<String>,
+    /// Additional configuration specific to the MCP
     pub config: serde_json::Value,
+    /// Replica group of a leaf MCP, which is the replica picked for the agent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replica_group: Option<String>,
+    /// Tools the agent may call, if not every tool of the MCP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_filter: Option<ToolFilter>,
+    /// When the grants allowing the MCP expire, if they all do
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<RemoteExpiry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoteMcpKind {
+    LeafMcp,
+    Agent,
+}
+
+/// How an agent connects to an MCP in its remote configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum McpConnection {
+    /// The agent spawns the MCP process itself
+    DirectStdio {
+        command: String,
+        args: Vec<String>,
+        env: Option<BTreeMap<String, String>>,
+    },
+    /// The agent talks to the MCP's HTTP endpoint directly
+    DirectHttp {
+        url: String,
+        headers: Option<BTreeMap<String, String>>,
+    },
+    /// The agent goes through the MCePtion server. `forward_url` is relative
+    /// to the server URL the configuration was fetched from.
+    Proxied {
+        forward_url: String,
+        auth: ProxyAuth,
+    },
+}
+
+/// Credentials an agent presents to proxied endpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyAuth {
+    /// The agent's own token
+    AgentToken,
+}
+
+/// A bundle granted to an agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteBundle {
+    pub version: u64,
+    pub members: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteConfigMetadata {
+    pub last_updated: DateTime<Utc>,
+    pub version: String,
+    /// Revision of the configuration, as in the agent's `config_changed` events
+    #[serde(default)]
+    pub revision: u64,
+}
+
+impl McpConnection {
+    /// Derive how an agent reaches a leaf MCP. Stdio MCPs hosted on the agent
+    /// system are spawned by the agent, HTTPS MCPs reachable by the agent are
+    /// called directly and everything else is proxied through the server.
+    /// Direct connections carry the transport [`McpTransport::redacted`], the
+    /// agent supplies secrets itself.
+    pub fn for_leaf_mcp(mcp: &LeafMcpConfig) -> Self {
+        match mcp.transport.redacted() {
+            McpTransport::Stdio {
+                command, args, env, ..
+            } if mcp.is_local => McpConnection::DirectStdio { command, args, env },
+            McpTransport::Https { url, headers } if mcp.reachable_by_agent => {
+                McpConnection::DirectHttp { url, headers }
+            }
+            _ => McpConnection::Proxied {
+                forward_url: format!("/leaf/{}/forwarding", mcp.id),
+                auth: ProxyAuth::AgentToken,
+            },
+        }
+    }
+
+    /// Other agents are always reached through the server
+    pub fn for_agent(agent_id: &str) -> Self {
+        McpConnection::Proxied {
+            forward_url: format!("/agent/{}/forwarding", agent_id),
+            auth: ProxyAuth::AgentToken,
+        }
+    }
 }
 
 /// Complete server configuration containing all MCPs and agents
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
     /// All leaf MCP configurations
-    pub leaf_mcps: HashMap<String, LeafMcpConfig>,
+    pub leaf_mcps: BTreeMap<String, LeafMcpConfig>,
     /// All MCeption Agent configurations
-    pub agents: HashMap<String, AgentConfig>,
+    pub agents: BTreeMap<String, AgentConfig>,
+    /// Named leaf MCP bundles, granted to agents as `bundle:<name>`
+    #[serde(default)]
+    pub bundles: BTreeMap<String, BundleConfig>,
+    /// Which leaf MCPs may be registered, anything may without a policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registration_policy: Option<RegistrationPolicy>,
+    /// Which audit entries are written, per actor
+    #[serde(default, skip_serializing_if = "AuditPolicy::is_empty")]
+    pub audit: AuditPolicy,
+    /// Which dangerous admin actions need a confirmation token
+    #[serde(default, skip_serializing_if = "ConfirmationPolicy::is_default")]
+    pub confirmation: ConfirmationPolicy,
+    /// Files contributing further leaf MCPs and agents, relative to the
+    /// configuration file's directory, e.g. `mcps/*.json`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub includes: Vec<String>,
+    /// JSON Schemas by id, which `config_schema` can hold the `config` of
+    /// leaf MCPs and agents to
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub config_schemas: BTreeMap<String, serde_json::Value>,
+    #[serde(default, skip_serializing_if = "ConfigSchemaRefs::is_empty")]
+    pub config_schema: ConfigSchemaRefs,
+    /// External stores agents' remote configurations are mirrored to, by id
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sync_targets: BTreeMap<String, SyncTarget>,
     /// Server metadata
     pub metadata: ServerMetadata,
 }
 
+/// Which audit entries are written. Without rules everything is.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AuditPolicy {
+    /// Rules for the requests of an actor, e.g. a monitoring bot's token
+    #[serde(default)]
+    pub actor_rules: BTreeMap<String, ActorAuditRule>,
+    /// What happens to a change whose audit entry can't be written
+    #[serde(default, skip_serializing_if = "AuditFailurePolicy::is_default")]
+    pub on_failure: AuditFailurePolicy,
+}
+
+impl AuditPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.actor_rules.is_empty() && self.on_failure.is_default()
+    }
+}
+
+/// How the server carries on while the audit storage can't be written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditFailurePolicy {
+    /// Refuse the change, so nothing happens unaudited
+    #[default]
+    Fail,
+    /// Keep the entry in memory and write it once the storage recovers.
+    /// Entries beyond the buffer's capacity are lost and counted.
+    Buffer,
+    /// Log the entry as an error and carry on without it
+    Drop,
+}
+
+impl AuditFailurePolicy {
+    pub fn is_default(&self) -> bool {
+        *self == Self::Fail
+    }
+}
+
+/// Which of an actor's audit entries are written. Deletions and changes of
+/// the audit policy are written regardless.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActorAuditRule {
+    /// Write the actor's reads
+    #[serde(default = "default_log_reads")]
+    pub log_reads: bool,
+    /// Share of the actor's other mutations written, from 0 to 1
+    #[serde(default = "default_sample_mutations")]
+    pub sample_mutations: f64,
+}
+
+fn default_log_reads() -> bool {
+    true
+}
+
+fn default_sample_mutations() -> f64 {
+    1.0
+}
+
+/// Admin actions that are only carried out when repeated with the
+/// confirmation token their first request was answered with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfirmationPolicy {
+    #[serde(default = "default_confirmed_actions")]
+    pub actions: BTreeSet<ConfirmedAction>,
+    /// How long a confirmation token is valid
+    #[serde(default = "default_confirmation_ttl_secs")]
+    pub token_ttl_secs: u64,
+}
+
+impl Default for ConfirmationPolicy {
+    fn default() -> Self {
+        Self {
+            actions: default_confirmed_actions(),
+            token_ttl_secs: default_confirmation_ttl_secs(),
+        }
+    }
+}
+
+impl ConfirmationPolicy {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn token_ttl(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.token_ttl_secs.try_into().unwrap_or(i64::MAX))
+    }
+}
+
+fn default_confirmed_actions() -> BTreeSet<ConfirmedAction> {
+    BTreeSet::from([ConfirmedAction::Restore, ConfirmedAction::BulkDelete])
+}
+
+fn default_confirmation_ttl_secs() -> u64 {
+    120
+}
+
+/// Dangerous admin actions a confirmation can be required for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfirmedAction {
+    /// Replacing the configuration from an export or a backup
+    Restore,
+    /// Deleting leaf MCPs or agents by ids or tag, the plan is shown without
+    BulkDelete,
+    DeleteLeafMcp,
+    DeleteAgent,
+}
+
+/// Ids of the `config_schemas` the `config` of leaf MCPs and agents has to
+/// satisfy when created or updated
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConfigSchemaRefs {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leaf_mcps: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agents: Option<String>,
+}
+
+impl ConfigSchemaRefs {
+    pub fn is_empty(&self) -> bool {
+        self.leaf_mcps.is_none() && self.agents.is_none()
+    }
+}
+
+/// Where agents' remote configurations are pushed to whenever they change
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SyncTarget {
+    /// Writes `<agent id>.json` into `directory`, removing it with the agent
+    File { directory: String },
+    /// `PUT`s each configuration to `url` with `{agent_id}` replaced, or
+    /// `/<agent id>` appended without it, and `DELETE`s it with the agent.
+    /// With a `signing_secret` requests carry an HMAC-SHA256 of their body.
+    Http {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        headers: Option<BTreeMap<String, String>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signing_secret: Option<String>,
+    },
+}
+
+impl SyncTarget {
+    /// The target as shown in API responses, with header values and the
+    /// signing secret replaced by [`REDACTED`]
+    pub fn redacted(&self) -> Self {
+        match self {
+            SyncTarget::File { .. } => self.clone(),
+            SyncTarget::Http {
+                url,
+                headers,
+                signing_secret,
+            } => SyncTarget::Http {
+                url: url.clone(),
+                headers: headers.as_ref().map(|headers| {
+                    headers
+                        .keys()
+                        .map(|name| (name.clone(), REDACTED.to_string()))
+                        .collect()
+                }),
+                signing_secret: signing_secret.as_ref().map(|_| REDACTED.to_string()),
+            },
+        }
+    }
+}
+
+/// Which stdio commands and HTTPS domains leaf MCPs may use. Once a policy
+/// is set, everything it doesn't allow is denied. Built-in MCPs are always allowed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistrationPolicy {
+    /// Allowed stdio commands, exact or as a prefix ending in `*`, e.g. `npx`
+    /// or `/opt/mcp/bin/*`
+    #[serde(default)]
+    pub stdio_commands: Vec<String>,
+    /// Allowed hosts of HTTPS leaf MCPs, exact or with subdomains as
+    /// `*.example.com`
+    #[serde(default)]
+    pub https_domains: Vec<String>,
+}
+
+/// Schema version of configurations written by this server
+pub const CONFIG_SCHEMA_VERSION: u32 = 1;
+
 /// Metadata about the server configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerMetadata {
     pub version: String,
+    /// Schema version of the stored configuration, 0 for configurations
+    /// written before schema versioning was introduced
+    #[serde(default)]
+    pub schema_version: u32,
     pub created_at: DateTime<Utc>,
     pub last_modified: DateTime<Utc>,
+    /// Incremented by every committed mutation
+    #[serde(default)]
+    pub revision: u64,
+}
+
+/// A single schema migration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationInfo {
+    /// Schema version the migration upgrades to
+    pub version: u32,
+    pub description: String,
+}
+
+/// Schema migration state of a storage backend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationStatus {
+    pub storage: String,
+    pub current_version: u32,
+    pub target_version: u32,
+    /// Migrations that still have to be applied, in order
+    pub pending: Vec<MigrationInfo>,
+}
+
+/// Kind of a configuration backup
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupKind {
+    /// A complete copy of the configuration
+    Full,
+    /// Only the JSON diff against a previous full backup
+    Differential,
+}
+
+/// Information about a stored configuration backup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub name: String,
+    pub kind: BackupKind,
+    /// The full backup a differential backup depends on
+    pub base: Option<String>,
+    pub compressed: bool,
+    /// Whether the backup can't be read, e.g. a differential backup whose
+    /// base is gone
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub corrupt: bool,
+    /// Size of the backup on disk
+    pub size_bytes: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Format of the timestamp in backup names, e.g. `20240102_030405_678`
+pub const BACKUP_TIMESTAMP_FORMAT: &str = "%Y%m%d_%H%M%S_%3f";
+
+impl BackupInfo {
+    /// When the backup named `name` was taken, from the timestamp in its
+    /// name, e.g. `config.json.backup.20240102_030405_678.gz`. Unlike
+    /// the file's modification time it survives copying the backup.
+    pub fn taken_at(name: &str) -> Option<DateTime<Utc>> {
+        let (_, rest) = name.rsplit_once("backup.")?;
+        let timestamp = rest.get(..19)?;
+        chrono::NaiveDateTime::parse_from_str(timestamp, BACKUP_TIMESTAMP_FORMAT)
+            .ok()
+            .map(|taken_at| taken_at.and_utc())
+    }
+}
+
+/// A read-only view of the configuration as it was at a past point in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoricalConfig {
+    pub as_of: DateTime<Utc>,
+    /// Always true, historical views can't be modified
+    pub read_only: bool,
+    pub source: HistorySource,
+    pub config: ServerConfig,
+}
+
+/// Where a historical configuration was reconstructed from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistorySource {
+    /// Backup used as the starting point, `None` when replayed from the empty initial config
+    pub snapshot: Option<String>,
+    pub snapshot_time: DateTime<Utc>,
+    /// Revision of the snapshot, the entries written at later revisions are
+    /// replayed on top of it
+    #[serde(default)]
+    pub snapshot_revision: u64,
+    /// Number of audit entries replayed on top of the snapshot
+    pub replayed_entries: usize,
+    /// ID of the last audit entry applied, identifying the revision
+    pub last_entry_id: Option<String>,
+    /// Audit entries that could not be replayed
+    pub skipped_entry_ids: Vec<String>,
+}
+
+/// A region of the audit log that couldn't be parsed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorruptRegion {
+    /// Byte offset of the region in the audit log
+    pub offset: u64,
+    pub length: u64,
+    pub reason: String,
+}
+
+/// Result of scanning an audit log for corruption
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditScanReport {
+    pub path: String,
+    pub total_bytes: u64,
+    /// Entries that parsed, including salvaged ones
+    pub valid_entries: usize,
+    /// Entries recovered from lines that also contained NUL padding or other garbage
+    pub salvaged_entries: usize,
+    pub corrupt_regions: Vec<CorruptRegion>,
+    /// Where the cleaned copy was written, for repairs
+    pub output: Option<String>,
+}
+
+impl AuditScanReport {
+    pub fn is_clean(&self) -> bool {
+        self.corrupt_regions.is_empty() && self.salvaged_entries == 0
+    }
 }
 
 /// An entry in the audit log tracking configuration changes
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuditLogEntry {
     pub id: String,
+    /// Position in the audit log, assigned by the storage when appending.
+    /// Strictly increasing, unlike timestamps, which follow the wall clock.
+    /// 0 until assigned; entries written before sequences get theirs on load.
+    #[serde(default)]
+    pub sequence: u64,
     pub timestamp: DateTime<Utc>,
     pub action: AuditAction,
     pub actor: Option<String>, // Agent ID or "admin" or "system"
     pub target: AuditTarget,
     pub reason: Option<String>,
     pub details: serde_json::Value,
+    /// Shared by the entries of one bulk operation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// `Idempotency-Key` of the admin request the entry was written for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// Revisions of the leaf MCP or agent before and after an update
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<RevisionChange>,
+    /// Revision of the configuration the entry was written at, which a
+    /// change recorded by the entry is part of. Entries written before
+    /// have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_revision: Option<u64>,
+    /// `hash` of the entry before, chaining the entries so a changed or
+    /// removed one is noticed, see [`crate::storage::audit_chain`]. Set by
+    /// the storage when appending, `None` for the first entry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prev_hash: Option<String>,
+    /// Digest of the entry and `prev_hash`, set by the storage when
+    /// appending. Entries written before the chain have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+/// An entity's revision before and after a change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevisionChange {
+    pub from: u64,
+    pub to: u64,
 }
 
 /// Types of actions that can be audited
@@ -97,6 +1160,45 @@ pub enum AuditAction {
     Delete,
     AddAllowedMcp,
     RemoveAllowedMcp,
+    Migrate,
+    EnableDebugCapture,
+    DisableDebugCapture,
+    SetLogLevel,
+    InjectFaults,
+    ClearFaults,
+    /// Marks where the configuration stops reflecting earlier entries
+    Discontinuity,
+    ConsistencyGap,
+    /// An agent connected or disconnected, written on transitions only
+    ConnectionChange,
+    /// An admin used a deprecated feature of the admin API, written once per
+    /// admin and feature while the server runs
+    DeprecatedUse,
+    /// A retried admin request was answered from its idempotency key's
+    /// stored answer, or refused
+    IdempotentRetry,
+    /// The server stopped, with its shutdown report
+    Shutdown,
+    /// A dangerous admin action was challenged for a confirmation token, or
+    /// carried out with one
+    Confirmation,
+    /// An agent or client exceeded its rate limit, written once until it
+    /// keeps within the limit again
+    RateLimited,
+    /// Audit entries were lost while the audit storage failed, written once
+    /// it recovered
+    EntriesLost,
+    /// A leaf MCP's circuit breaker opened, let a probe through, closed or
+    /// was reset
+    CircuitBreaker,
+    /// An admin called a leaf MCP's tool through the admin API
+    CallTool,
+    /// Agent tokens and grants expiring within the warning window, written
+    /// daily when enabled and any expire
+    ExpirySummary,
+    /// `repair-audit` dropped corrupt entries here and started the hash
+    /// chain anew, see [`crate::storage::audit_chain::reanchor`]
+    AuditRepair,
 }
 
 /// Targets that can be acted upon and audited
@@ -106,83 +1208,272 @@ pub enum AuditTarget {
     LeafMcp { id: String },
     Agent { id: String },
     AgentAllowedMcp { agent_id: String, mcp_id: String },
+    AgentToolFilter { agent_id: String, mcp_id: String },
+    Bundle { name: String },
+    RegistrationPolicy,
+    AuditPolicy,
+    ConfirmationPolicy,
     Server,
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
-            leaf_mcps: HashMap::new(),
-            agents: HashMap::new(),
+            leaf_mcps: BTreeMap::new(),
+            agents: BTreeMap::new(),
+            bundles: BTreeMap::new(),
+            registration_policy: None,
+            audit: AuditPolicy::default(),
+            confirmation: ConfirmationPolicy::default(),
+            includes: Vec::new(),
+            config_schemas: BTreeMap::new(),
+            config_schema: ConfigSchemaRefs::default(),
+            sync_targets: BTreeMap::new(),
             metadata: ServerMetadata {
                 version: "0.1.0".to_string(),
+                schema_version: CONFIG_SCHEMA_VERSION,
                 created_at: Utc::now(),
                 last_modified: Utc::now(),
+                revision: 0,
             },
         }
     }
 }
 
 impl ServerConfig {
+    /// The configuration as shown in API responses, see [`AgentConfig::redacted`]
+    /// and [`LeafMcpConfig::redacted`]
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        for leaf in config.leaf_mcps.values_mut() {
+            *leaf = leaf.redacted();
+        }
+        for agent in config.agents.values_mut() {
+            *agent = agent.redacted();
+        }
+        for target in config.sync_targets.values_mut() {
+            *target = target.redacted();
+        }
+        config
+    }
+
     pub fn update_last_modified(&mut self) {
         self.metadata.last_modified = Utc::now();
+        self.metadata.revision += 1;
+    }
+
+    /// [`Self::update_last_modified`] for a change of a leaf MCP, which
+    /// is marked as changed at the new revision. Returns the revision.
+    pub fn update_leaf_mcp_revision(&mut self, id: &str) -> u64 {
+        self.update_last_modified();
+        let revision = self.metadata.revision;
+        if let Some(leaf) = self.leaf_mcps.get_mut(id) {
+            leaf.revision = revision;
+        }
+        revision
+    }
+
+    /// [`Self::update_last_modified`] for a change of an agent, like
+    /// [`Self::update_leaf_mcp_revision`]
+    pub fn update_agent_revision(&mut self, agent_id: &str) -> u64 {
+        self.update_last_modified();
+        let revision = self.metadata.revision;
+        if let Some(agent) = self.agents.get_mut(agent_id) {
+            agent.revision = revision;
+        }
+        revision
+    }
+
+    /// Whether an allow-list entry refers to an existing leaf MCP, agent or bundle
+    pub fn has_mcp(&self, mcp_id: &str) -> bool {
+        match mcp_id.strip_prefix(BUNDLE_PREFIX) {
+            Some(bundle) => self.bundles.contains_key(bundle),
+            None => self.leaf_mcps.contains_key(mcp_id) || self.agents.contains_key(mcp_id),
+        }
+    }
+
+    /// Agents whose allow-list grants the given bundle
+    pub fn agents_with_bundle(&self, bundle: &str) -> Vec<String> {
+        let grant = format!("{}{}", BUNDLE_PREFIX, bundle);
+        self.agents
+            .iter()
+            .filter(|(_, agent)| agent.allowed_mcp_ids.contains(&grant))
+            .map(|(id, _)| id.clone())
+            .collect()
     }
 }
 
 // Request/Response types for the API
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateLeafMcpRequest {
-    pub id: String,
+    /// Generated from the name when omitted
+    #[serde(default)]
+    pub id: Option<String>,
     pub config: LeafMcpConfig,
     pub reason: Option<String>,
-    pub should_create: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub should_create: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UpdateLeafMcpRequest {
     pub config: serde_json::Value, // Partial update
     pub reason: Option<String>,
-    pub should_update: bool,
+    /// Revision of the leaf MCP the update is based on. The update is
+    /// refused if the leaf MCP changed since.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_revision: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub should_update: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DeleteLeafMcpRequest {
     pub reason: Option<String>,
-    pub should_delete_mcp: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub should_delete_mcp: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateBundleRequest {
+    pub name: String,
+    pub description: Option<String>,
+    pub members: Vec<String>,
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub should_create: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateBundleRequest {
+    pub description: Option<String>,
+    /// Replaces the bundle's members when given
+    pub members: Option<Vec<String>>,
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub should_update: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteBundleRequest {
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub should_delete_bundle: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateAgentRequest {
-    pub agent_id: String,
+    /// Generated from the name when omitted
+    #[serde(default)]
+    pub agent_id: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
     pub allowed_mcp_ids: Vec<String>,
-    pub should_create: bool,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub should_create: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UpdateAgentRequest {
     pub config: serde_json::Value, // Partial update
     pub reason: Option<String>,
-    pub should_update: bool,
+    /// Revision of the agent the update is based on, like
+    /// [`UpdateLeafMcpRequest::expected_revision`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_revision: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub should_update: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AddAgentAllowedMcpRequest {
     pub mcp_id: String,
     pub reason: Option<String>,
-    pub should_add_mcp_id: bool,
+    /// What grants the MCP, direct if not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<GrantSource>,
+    /// When the grant stops allowing the MCP. Given for an MCP already
+    /// granted, it moves the grant's expiry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub should_add_mcp_id: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddAgentAllowedMcpsByLabelRequest {
+    /// Label selector like `team=payments,env!=prod` the leaf MCPs to
+    /// grant have to match
+    pub selector: String,
+    pub reason: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RemoveAgentAllowedMcpRequest {
     pub mcp_id: String,
     pub reason: Option<String>,
-    pub should_remove_mcp_id: bool,
+    /// The source whose grant is removed, direct if not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<GrantSource>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub should_remove_mcp_id: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DeleteAgentRequest {
     pub reason: Option<String>,
-    pub should_delete_mcp: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub should_delete_mcp: Option<bool>,
+}
+
+/// Bulk delete of leaf MCPs or agents. Without `confirm` only the plan is
+/// returned, whose `confirmation_token` confirms exactly that plan, once and
+/// within the confirmation policy's TTL.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkDeleteRequest {
+    pub ids: Vec<String>,
+    pub confirm: Option<String>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RestoreBackupRequest {
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub should_restore: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportConfigRequest {
+    pub config: ServerConfig,
+    /// Add and overwrite the imported leaf MCPs, agents and bundles, keeping
+    /// the others, instead of replacing the configuration
+    #[serde(default)]
+    pub merge: bool,
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub should_restore: Option<bool>,
+}
+
+/// Leaf MCPs, agents or bundles an import added, changed and removed
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportCounts {
+    pub added: usize,
+    pub changed: usize,
+    pub removed: usize,
+}
+
+/// What importing a configuration changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportSummary {
+    pub merge: bool,
+    /// Backup of the configuration as it was before the import
+    pub previous_backup: String,
+    pub leaf_mcps: ImportCounts,
+    pub agents: ImportCounts,
+    pub bundles: ImportCounts,
 }
 
 // WebSocket forwarding types
@@ -192,13 +1483,25 @@ pub enum ForwardingMessage {
     Request {
         request_id: String,
         url_params: String,
-        headers: HashMap<String, String>,
+        headers: BTreeMap<String, String>,
         body: Option<String>,
+        /// How `body` is encoded, as text if left out
+        #[serde(default, skip_serializing_if = "BodyEncoding::is_utf8")]
+        encoding: BodyEncoding,
+        /// Remaining time budget of the agent in milliseconds, like `X-Mception-Deadline-Ms`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        deadline_ms: Option<u64>,
     },
     Response {
         request_id: String,
         status_code: u16,
-        headers: HashMap<String, String>,
+        headers: BTreeMap<String, String>,
         body: Option<String>,
+        /// How `body` is encoded, as text if left out
+        #[serde(default, skip_serializing_if = "BodyEncoding::is_utf8")]
+        encoding: BodyEncoding,
     },
+    /// Sent to an agent when a revision changed its remote configuration, so
+    /// it fetches it again
+    ConfigChanged { revision: u64 },
 }
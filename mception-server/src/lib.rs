@@ -0,0 +1,118 @@
+//! MCePtion Server as a library, for mounting its HTTP API inside another axum app.
+//!
+//! Optional subsystems are behind cargo features, see `Cargo.toml`.
+
+pub mod cli;
+pub mod core;
+pub mod routes;
+pub mod services;
+pub mod storage;
+
+use axum::{Extension, Router, middleware};
+use std::sync::Arc;
+
+use crate::services::api_versions::{self, ApiVersioning};
+use crate::services::auth::{self, AdminToken};
+use crate::services::{ConfigService, ConnectionService};
+use crate::services::{confirmation, health, idempotency, rate_limit};
+
+/// Which parts of the HTTP API [`build_router`] mounts
+#[derive(Debug, Clone)]
+pub struct RouterOptions {
+    /// Admin API under `/admin`
+    pub admin_api: bool,
+    /// Bundled dashboard under `/admin/ui`
+    #[cfg(feature = "admin-ui")]
+    pub admin_ui: bool,
+    /// Agent runtime routes under `/agent`
+    pub agent_api: bool,
+    /// Leaf MCP forwarding under `/leaf`
+    pub leaf_forwarding: bool,
+    /// Bearer tokens the admin API and `/metrics` require, open without any
+    pub admin_tokens: Vec<AdminToken>,
+    /// Admin API version served to requests that don't ask for one
+    pub api_version: u32,
+}
+
+impl Default for RouterOptions {
+    fn default() -> Self {
+        Self {
+            admin_api: true,
+            #[cfg(feature = "admin-ui")]
+            admin_ui: true,
+            agent_api: true,
+            leaf_forwarding: true,
+            admin_tokens: Vec::new(),
+            api_version: api_versions::CURRENT_VERSION,
+        }
+    }
+}
+
+/// Build the server's router around a loaded configuration service
+pub fn build_router(config_service: Arc<ConfigService>, options: RouterOptions) -> Router {
+    let admin_auth =
+        middleware::from_fn_with_state(Arc::new(options.admin_tokens), auth::require_admin_token);
+    // Everything but the health probes and the dashboard waits for the configuration
+    let loading =
+        middleware::from_fn_with_state(config_service.clone(), health::reject_while_loading);
+    let mut admin = Router::new();
+    if options.admin_api {
+        let versioning = middleware::from_fn_with_state(
+            Arc::new(ApiVersioning::new(options.api_version)),
+            api_versions::negotiate,
+        );
+        let idempotency =
+            middleware::from_fn_with_state(config_service.clone(), idempotency::deduplicate);
+        // Outside of idempotency, so challenges aren't replayed to confirmed retries
+        let confirmation = middleware::from_fn_with_state(
+            config_service.clone(),
+            confirmation::require_confirmation,
+        );
+        admin = admin.merge(
+            routes::admin::router()
+                .layer(idempotency)
+                .layer(confirmation)
+                .layer(versioning)
+                .layer(admin_auth.clone())
+                .layer(loading.clone()),
+        );
+    }
+    #[cfg(feature = "admin-ui")]
+    if options.admin_ui {
+        admin = admin.merge(routes::ui::router().layer(admin_auth.clone()));
+    }
+
+    let mut app = Router::new()
+        .nest("/admin", admin)
+        .merge(routes::health::router());
+    if options.agent_api {
+        let rate_limit = middleware::from_fn_with_state(
+            config_service.clone(),
+            rate_limit::limit_agent_requests,
+        );
+        app = app.nest(
+            "/agent",
+            routes::agent::router()
+                .layer(rate_limit)
+                .layer(loading.clone()),
+        );
+    }
+    if options.leaf_forwarding {
+        let rate_limit =
+            middleware::from_fn_with_state(config_service.clone(), rate_limit::limit_leaf_requests);
+        app = app.nest(
+            "/leaf",
+            routes::leaf::router()
+                .layer(rate_limit)
+                .layer(loading.clone()),
+        );
+    }
+    if options.admin_api {
+        app = app.merge(routes::metrics::router().layer(admin_auth).layer(loading));
+    }
+    let limits = config_service.limits();
+    let connections = ConnectionService::with_max_pending(limits.pending_agent_requests)
+        .with_max_pending_per_connection(limits.pending_requests_per_agent);
+    app.layer(Extension(config_service))
+        .layer(Extension(Arc::new(connections)))
+}
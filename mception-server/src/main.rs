@@ -1,78 +1,273 @@
-mod cli;
-mod core;
-mod routes;
-mod services;
-mod storage;
-
-use axum::{Extension, Router};
-use clap::Parser;
-use cli::{Cli, Commands};
+use clap::{CommandFactory, FromArgMatches};
+use mception_server::cli::{
+    self, BackupMode, Cli, Commands, ConfigStyle, MigrateMode, StorageBackend,
+};
+use mception_server::{RouterOptions, build_router, services};
+use std::future::IntoFuture;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tracing::{debug, error, info};
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::{EnvFilter, layer::SubscriberExt, reload, util::SubscriberInitExt};
 
-use crate::services::ConfigService;
-use crate::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::services::ConfigService;
+use mception_server::services::access_log::AccessLog;
+use mception_server::services::audit_buffer;
+use mception_server::services::availability::{self, AvailabilityTracker};
+use mception_server::services::config_limits::ConfigLimits;
+use mception_server::services::confirmation::Confirmations;
+use mception_server::services::expiry;
+use mception_server::services::health;
+use mception_server::services::idempotency::IdempotencyStore;
+use mception_server::services::internals::ResourceLimits;
+use mception_server::services::leaf_sessions::{self, LeafSessions};
+use mception_server::services::logging::LogControl;
+use mception_server::services::shutdown::{self, RunRecord};
+use mception_server::services::sync;
+use mception_server::storage::journal::ConfigJournal;
+use mception_server::storage::providers::{
+    AuditRotation, AuditStorage, BackupOptions, ConfigStorage, FileAuditStorage, FileConfigStorage,
+};
+
+/// How often the running server prunes the audit log, with `--audit-retention-days`
+const AUDIT_PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
 
 #[tokio::main]
 async fn main() {
-    // Initialize tracing with more explicit configuration
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
+    // Initialize tracing behind a reload layer so the filter can be changed at runtime
+    let (filter, filter_handle) =
+        reload::Layer::new(EnvFilter::new(services::logging::DEFAULT_DIRECTIVES));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
 
-    // Ensure parent directories exist for config file
-    if let Some(parent) = std::path::Path::new(&cli.config).parent() {
-        if !parent.exists() {
-            debug!("Creating config directory: {:?}", parent);
-            if let Err(e) = std::fs::create_dir_all(parent) {
-                eprintln!("Failed to create config directory: {}", e);
-                std::process::exit(1);
-            }
+    // Inspect the environment before anything gets created
+    if let Some(Commands::Doctor { format }) = &cli.command {
+        let report = cli::doctor::run(&cli, &matches).await;
+        if let Err(e) = cli::doctor::display(&report, format.clone()) {
+            error!("Error executing command: {}", e);
+            std::process::exit(1);
         }
+        std::process::exit(report.worst.exit_code());
     }
 
-    // Ensure parent directories exist for audit log file
-    if let Some(parent) = std::path::Path::new(&cli.audit_log).parent() {
-        if !parent.exists() {
-            debug!("Creating audit log directory: {:?}", parent);
-            if let Err(e) = std::fs::create_dir_all(parent) {
-                error!("Failed to create audit log directory: {}", e);
+    let (config_storage, audit_storage, audit_log_path) = match open_storage(&cli) {
+        Ok(storage) => storage,
+        Err(e) => {
+            error!("Failed to open storage: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let command = cli.command.take().unwrap_or_default();
+
+    let mut config_service = ConfigService::new(config_storage.clone(), audit_storage.clone())
+        .with_log_control(LogControl::new(filter_handle))
+        .with_journal(ConfigJournal::new(&cli.journal))
+        .with_id_generator(cli.id_scheme.generator())
+        .with_max_clock_skew(cli.audit_max_clock_skew)
+        .with_audit_buffer_capacity(cli.audit_buffer_capacity)
+        .with_agent_staleness(cli.agent_staleness)
+        .with_agent_ping_interval(cli.agent_ping_interval)
+        .with_expiry_window(cli.expiry_window)
+        .with_expiry_webhook(cli.expiry_webhook.clone())
+        .with_default_leaf_timeout(cli.leaf_timeout.to_std().unwrap_or_default())
+        .with_circuit_breakers(
+            cli.breaker_failure_threshold,
+            cli.breaker_cooldown.to_std().unwrap_or_default(),
+        )
+        .with_health_interval(Duration::from_secs(cli.health_interval_secs))
+        .with_limits(ResourceLimits {
+            tool_cache_entries: cli.tool_cache_capacity,
+            pending_agent_requests: cli.max_pending_agent_requests,
+            pending_requests_per_agent: cli.max_pending_requests_per_agent,
+        })
+        .with_config_limits(ConfigLimits {
+            max_bytes: cli.max_config_bytes,
+            max_depth: cli.max_config_depth,
+            max_keys: cli.max_config_keys,
+        })
+        .with_agent_rate_limit(cli.agent_rate_limit);
+    // Only the running server records availability, each start begins a new session
+    if let Commands::Start = command {
+        match AvailabilityTracker::open(&cli.availability_file, cli.availability_retention) {
+            Ok(tracker) => config_service = config_service.with_availability(tracker),
+            Err(e) => {
+                error!("Failed to load availability data: {}", e);
                 std::process::exit(1);
             }
         }
+        match LeafSessions::open(&cli.leaf_session_file) {
+            Ok(sessions) => config_service = config_service.with_leaf_sessions(sessions),
+            Err(e) => warn!("Failed to load leaf MCP sessions, initializing anew: {}", e),
+        }
+        let idempotency = IdempotencyStore::new(cli.idempotency_window, cli.idempotency_capacity);
+        let idempotency = match &cli.idempotency_file {
+            Some(path) => match idempotency.persisted(path) {
+                Ok(idempotency) => idempotency,
+                Err(e) => {
+                    error!("Failed to load idempotency keys: {}", e);
+                    std::process::exit(1);
+                }
+            },
+            None => idempotency,
+        };
+        config_service = config_service.with_idempotency(idempotency);
+    }
+    // Confirmed in a later run than the dry run, so tokens are signed with
+    // what both runs share
+    if let Commands::DeleteMcps { .. } = command {
+        config_service = config_service
+            .with_confirmations(Confirmations::with_secret(config_storage.location()));
+    }
+    if cli.enable_fault_injection {
+        config_service = config_service.with_fault_injection();
+    }
+    if let Some(access_log) = &cli.access_log {
+        config_service = config_service.with_access_log(AccessLog::new(access_log));
     }
+    if cli.mask_change_actors {
+        config_service = config_service.with_masked_change_actors();
+    }
+    let config_service = Arc::new(config_service);
 
-    // Initialize storage providers with explicit CLI-provided paths
-    let config_storage = Arc::new(FileConfigStorage::new(&cli.config));
-    let audit_storage = Arc::new(FileAuditStorage::new(&cli.audit_log));
-    let config_service = Arc::new(ConfigService::new(
-        config_storage.clone(),
-        audit_storage.clone(),
-    ));
+    // Bring the storage schema up to date before the server loads it
+    if let Commands::Start = command {
+        match cli.migrate {
+            MigrateMode::OnStart => match config_service
+                .run_migrations(Some("system".to_string()))
+                .await
+            {
+                Ok(applied) => {
+                    for migration in applied {
+                        info!(
+                            "Applied migration to schema version {}: {}",
+                            migration.version, migration.description
+                        );
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to apply migrations: {}", e);
+                    std::process::exit(1);
+                }
+            },
+            MigrateMode::RequireCurrent => match config_service.migration_status().await {
+                Ok(status) if status.pending.is_empty() => {}
+                Ok(status) => {
+                    error!(
+                        "Storage {} is at schema version {} but {} is required, run `mception-server migrate` first",
+                        status.storage, status.current_version, status.target_version
+                    );
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    error!("Failed to check migration status: {}", e);
+                    std::process::exit(1);
+                }
+            },
+        }
+    }
 
-    // Load existing configuration
-    if let Err(e) = config_service.load_configuration().await {
+    // Load existing configuration, unless the command works on the storage
+    // directly. The server loads it once it listens, see below.
+    if command.loads_configuration()
+        && !matches!(command, Commands::Start)
+        && let Err(e) = config_service.load_configuration().await
+    {
         error!("Failed to load configuration: {}", e);
         std::process::exit(1);
     }
 
     // Handle CLI commands
-    match cli.command.unwrap_or_default() {
+    match command {
         Commands::Start => {
+            // Bind first and report "loading" on the health probes meanwhile,
+            // refusing everything else with 503
+            config_service.lifecycle().begin_loading();
+            let loading_service = config_service.clone();
+            let acknowledge_gap = cli.acknowledge_consistency_gap;
+            let audit_expiry_summary = cli.audit_expiry_summary;
+            tokio::spawn(async move {
+                let started = std::time::Instant::now();
+                if let Err(e) = loading_service.load_configuration().await {
+                    error!("Failed to load configuration: {}", e);
+                    std::process::exit(1);
+                }
+                check_consistency(&loading_service, acknowledge_gap).await;
+                loading_service.validate_configuration().await;
+                loading_service.lifecycle().finish_loading();
+                info!("Configuration loaded in {:?}", started.elapsed());
+                tokio::spawn(sync::watch(loading_service.clone()));
+                tokio::spawn(expiry::watch(loading_service.clone()));
+                if audit_expiry_summary {
+                    tokio::spawn(expiry::summarize(loading_service.clone()));
+                }
+                let resumed = leaf_sessions::resume(loading_service).await;
+                if resumed > 0 {
+                    info!("Resumed {} leaf MCP sessions", resumed);
+                }
+            });
+            // Keep the audit log within its retention
+            if cli.audit_retention_days.is_some() && cli.storage != StorageBackend::File {
+                error!(
+                    "--audit-retention-days needs --storage file, the SQLite audit log is append-only"
+                );
+                std::process::exit(1);
+            }
+            if let Some(days) = cli.audit_retention_days {
+                let retention = chrono::Duration::days(days.into());
+                let audit_storage = audit_storage.clone();
+                tokio::spawn(async move {
+                    let mut interval = tokio::time::interval(AUDIT_PRUNE_INTERVAL);
+                    loop {
+                        interval.tick().await;
+                        match audit_storage
+                            .prune_entries(chrono::Utc::now() - retention)
+                            .await
+                        {
+                            Ok(0) => {}
+                            Ok(pruned) => info!(
+                                "Pruned {} audit log entries older than {} days",
+                                pruned, days
+                            ),
+                            Err(e) => error!("Failed to prune the audit log: {}", e),
+                        }
+                    }
+                });
+            }
             info!("Starting server...");
             // Start the server
-            start_server(config_service, cli.host, cli.port).await;
+            let report_path = (!cli.no_shutdown_report)
+                .then(|| std::path::PathBuf::from(&cli.shutdown_report_file));
+            let drain_timeout = cli.drain_timeout.to_std().unwrap_or_default();
+            start_server(
+                config_service,
+                cli.host,
+                cli.port,
+                drain_timeout,
+                report_path,
+                RouterOptions {
+                    admin_tokens: cli.admin_tokens,
+                    api_version: cli.api_compat,
+                    ..RouterOptions::default()
+                },
+            )
+            .await;
         }
         _command => {
             // Handle other commands
             if let Err(e) = cli::commands::handle_command(
                 _command,
-                &*config_service,
+                &config_service,
                 config_storage.as_ref(),
                 audit_storage.as_ref(),
+                &audit_log_path,
+                &cli.availability_file,
+                cli.admin_tokens.first(),
             )
             .await
             {
@@ -83,15 +278,198 @@ async fn main() {
     }
 }
 
-async fn start_server(config_service: Arc<ConfigService>, host: String, port: u16) {
-    let app = Router::new()
-        // Admin API routes (no /admin prefix per README spec)
-        .nest("/admin", routes::admin::router())
-        // Agent runtime routes (with /agent prefix)
-        .nest("/agent", routes::agent::router())
-        // Leaf MCP forwarding routes (with /leaf prefix)
-        .nest("/leaf", routes::leaf::router())
-        .layer(Extension(config_service.clone()));
+/// Configuration and audit log storage selected by `--storage`, and the path
+/// repaired audit logs are named after
+type Storage = (Arc<dyn ConfigStorage>, Arc<dyn AuditStorage>, String);
+
+fn open_storage(cli: &Cli) -> Result<Storage, String> {
+    match cli.storage {
+        StorageBackend::File => {
+            // Ensure parent directories exist for config file
+            if let Some(parent) = std::path::Path::new(&cli.config).parent()
+                && !parent.exists()
+            {
+                debug!("Creating config directory: {:?}", parent);
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create config directory: {}", e))?;
+            }
+
+            // Ensure parent directories exist for audit log file
+            if let Some(parent) = std::path::Path::new(&cli.audit_log).parent()
+                && !parent.exists()
+            {
+                debug!("Creating audit log directory: {:?}", parent);
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create audit log directory: {}", e))?;
+            }
+
+            // Initialize storage providers with explicit CLI-provided paths
+            let config_storage = FileConfigStorage::new(&cli.config)
+                .with_backup_options(BackupOptions {
+                    compress: cli.backup_compress,
+                    differential: cli.backup_mode == BackupMode::Differential,
+                    full_every: cli.backup_full_every,
+                    keep: cli.backup_keep,
+                })
+                .with_compact(cli.config_style == ConfigStyle::Compact);
+            Ok((
+                Arc::new(config_storage),
+                Arc::new(
+                    FileAuditStorage::new(&cli.audit_log).with_rotation(AuditRotation {
+                        max_bytes: cli.audit_max_size,
+                        max_entries: cli.audit_max_entries,
+                    }),
+                ),
+                cli.audit_log.clone(),
+            ))
+        }
+        #[cfg(feature = "sqlite")]
+        StorageBackend::Sqlite => {
+            use mception_server::storage::providers::{SqliteAuditStorage, SqliteConfigStorage};
+
+            let config_storage = SqliteConfigStorage::open(&cli.database_url)
+                .map_err(|e| e.to_string())?
+                .with_keep_backups(cli.backup_keep);
+            let audit_storage =
+                SqliteAuditStorage::open(&cli.database_url).map_err(|e| e.to_string())?;
+            Ok((
+                Arc::new(config_storage),
+                Arc::new(audit_storage),
+                cli.database_url.clone(),
+            ))
+        }
+        #[cfg(not(feature = "sqlite"))]
+        StorageBackend::Sqlite => {
+            Err("built without the `sqlite` feature, use `--storage file`".to_string())
+        }
+    }
+}
+
+/// Warn when the audit log records configuration changes the loaded
+/// configuration doesn't reflect, e.g. after a backup was copied over it
+async fn check_consistency(config_service: &ConfigService, acknowledge: bool) {
+    let report = if acknowledge {
+        config_service
+            .acknowledge_consistency_gap(Some("admin".to_string()))
+            .await
+    } else {
+        config_service.check_consistency().await
+    };
+    let report = match report {
+        Ok(report) if report.consistent => return,
+        Ok(report) => report,
+        Err(e) => {
+            warn!(
+                "Failed to compare the configuration with the audit log: {}",
+                e
+            );
+            return;
+        }
+    };
+    if acknowledge {
+        info!(
+            "Acknowledged {} audited change(s) missing from configuration revision {}",
+            report.gap.len(),
+            report.revision
+        );
+        return;
+    }
+
+    warn!(
+        "CONSISTENCY GAP: the audit log records {} configuration change(s) after the configuration was last modified ({}, revision {}). \
+         The configuration may have been restored from a backup or edited outside the server.",
+        report.gap.len(),
+        report.last_modified.to_rfc3339(),
+        report.revision
+    );
+    for entry in &report.gap {
+        warn!(
+            "  {} {} {:?} {:?}",
+            entry.timestamp.to_rfc3339(),
+            entry.id,
+            entry.action,
+            entry.target
+        );
+    }
+    warn!(
+        "Review these entries, then start with --acknowledge-consistency-gap to silence this warning"
+    );
+    if let Err(e) = config_service.record_consistency_gap(&report).await {
+        warn!("Failed to record the consistency gap: {}", e);
+    }
+}
+
+async fn start_server(
+    config_service: Arc<ConfigService>,
+    host: String,
+    port: u16,
+    drain_timeout: Duration,
+    report_path: Option<PathBuf>,
+    router_options: RouterOptions,
+) {
+    // A `running` record left behind by the previous run means it crashed
+    if let Some(path) = &report_path {
+        match shutdown::read_record(path) {
+            Ok(record) => {
+                if let Some(RunRecord::Running { started_at, pid }) = &record {
+                    warn!(
+                        "The previous run (pid {}, started {}) did not shut down cleanly",
+                        pid, started_at
+                    );
+                }
+                config_service.lifecycle().set_previous_run(record);
+            }
+            Err(e) => warn!("Failed to read {}: {}", path.display(), e),
+        }
+        let running = RunRecord::Running {
+            started_at: config_service.lifecycle().started_at(),
+            pid: std::process::id(),
+        };
+        if let Err(e) = shutdown::write_record(path, &running) {
+            error!("Failed to write {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+
+    // Write audit entries held back while the audit storage failed once it recovers
+    tokio::spawn(audit_buffer::retry(config_service.clone()));
+
+    // Persist agent availability periodically, so a crash loses at most one interval
+    let checkpoint_service = config_service.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(availability::CHECKPOINT_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = checkpoint_service.checkpoint_availability().await {
+                error!("Failed to persist agent availability: {}", e);
+            }
+        }
+    });
+
+    // Flip agents that stopped contacting the server to disconnected
+    let sweep_service = config_service.clone();
+    tokio::spawn(async move {
+        let period = (sweep_service.agent_staleness() / 2)
+            .to_std()
+            .unwrap_or_default()
+            .max(std::time::Duration::from_secs(1));
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            sweep_service.sweep_stale_agents().await;
+        }
+    });
+
+    // Probe leaf MCPs, so their health is known before anything is forwarded
+    let health_interval = config_service.health_interval();
+    if !health_interval.is_zero() {
+        tokio::spawn(health::watch(config_service.clone(), health_interval));
+    }
+
+    if router_options.admin_tokens.is_empty() {
+        warn!("No admin token configured, the admin API is open to anyone reaching it");
+    }
+    let app = build_router(config_service.clone(), router_options);
 
     let addr = SocketAddr::from((
         host.parse::<std::net::IpAddr>()
@@ -103,5 +481,96 @@ async fn start_server(config_service: Arc<ConfigService>, host: String, port: u1
     info!("Listening on http://{}", addr);
 
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+
+    // On a signal, stop accepting connections and forwards, then give
+    // in-flight requests `drain_timeout` to finish
+    let (reason_tx, reason_rx) = tokio::sync::oneshot::channel();
+    let lifecycle_service = config_service.clone();
+    let signal = shutdown_signal();
+    let mut server = tokio::spawn(
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(async move {
+            let reason = signal.await;
+            info!(
+                "Received {}, draining for up to {:?}",
+                reason, drain_timeout
+            );
+            lifecycle_service.lifecycle().begin_draining();
+            let _ = reason_tx.send(reason);
+        })
+        .into_future(),
+    );
+
+    let (mut aborted, mut timed_out) = (0, false);
+    // A drain without requests in flight ends the server right after the
+    // signal, so the signal is checked first to report it as the reason
+    let reason = tokio::select! {
+        biased;
+        Ok(reason) = reason_rx => {
+            if tokio::time::timeout(drain_timeout, &mut server).await.is_err() {
+                aborted = config_service.lifecycle().in_flight_forwards();
+                timed_out = true;
+                warn!("Drain timeout passed, aborting {} in-flight forwards", aborted);
+                server.abort();
+            }
+            reason
+        }
+        result = &mut server => {
+            match result {
+                Ok(Ok(())) => "server stopped".to_string(),
+                Ok(Err(e)) => format!("server error: {}", e),
+                Err(e) => format!("server task failed: {}", e),
+            }
+        }
+    };
+
+    let report = config_service.shutdown(reason, aborted, timed_out).await;
+    if let Some(path) = &report_path
+        && let Err(e) = shutdown::write_record(path, &RunRecord::Stopped(report.clone()))
+    {
+        error!("Failed to write {}: {}", path.display(), e);
+    }
+    info!(
+        report = %serde_json::to_string(&report).unwrap_or_default(),
+        "Shutdown report"
+    );
+    // Tell supervisors requests were cut off
+    if timed_out {
+        std::process::exit(1);
+    }
+}
+
+/// Wait for Ctrl-C or, on Unix, SIGTERM. Returns the signal's name.
+///
+/// On Unix the signals are caught from the call on, not from when the future
+/// is first polled, so a SIGTERM arriving while the server starts serving
+/// drains it instead of killing it.
+fn shutdown_signal() -> impl Future<Output = String> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        let interrupt = signal(SignalKind::interrupt());
+        let terminate = signal(SignalKind::terminate());
+        async move {
+            match (interrupt, terminate) {
+                (Ok(mut interrupt), Ok(mut terminate)) => tokio::select! {
+                    _ = interrupt.recv() => "SIGINT".to_string(),
+                    _ = terminate.recv() => "SIGTERM".to_string(),
+                },
+                (Err(e), _) | (_, Err(e)) => {
+                    warn!("Failed to listen for SIGINT and SIGTERM: {}", e);
+                    let _ = tokio::signal::ctrl_c().await;
+                    "SIGINT".to_string()
+                }
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    async {
+        let _ = tokio::signal::ctrl_c().await;
+        "Ctrl-C".to_string()
+    }
 }
@@ -1,37 +1,163 @@
 use axum::{
     Router,
-    extract::{Extension, Path},
-    http::StatusCode,
-    response::Json,
+    body::Body,
+    extract::{Extension, Path, Query},
+    http::{StatusCode, header},
+    response::{IntoResponse, Json, Response},
     routing::{delete, get, post, put},
 };
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 use tracing::error;
 
 use crate::core::{
-    AddAgentAllowedMcpRequest, CreateAgentRequest, CreateLeafMcpRequest, DeleteAgentRequest,
-    DeleteLeafMcpRequest, LeafMcpConfig, RemoveAgentAllowedMcpRequest, UpdateAgentRequest,
-    UpdateLeafMcpRequest,
+    AddAgentAllowedMcpRequest, AddAgentAllowedMcpsByLabelRequest, AgentConfig, AuditPolicy,
+    AuditQuery, BUNDLE_PREFIX, BulkDeleteRequest, BundleConfig, ConfirmationPolicy,
+    CreateAgentRequest, CreateBundleRequest, CreateLeafMcpRequest, DeleteAgentRequest,
+    DeleteBundleRequest, DeleteLeafMcpRequest, GrantSource, HistoricalConfig, ImportConfigRequest,
+    MceptionError, McpTransport, NetworkError, RegistrationPolicy, RemoveAgentAllowedMcpRequest,
+    RestoreBackupRequest, StorageError, ToolFilter, UpdateAgentRequest, UpdateBundleRequest,
+    UpdateLeafMcpRequest, ValidationError, duration, error_body,
+    labels::LabelSelector,
+    pagination::{self, PageError, PageQuery},
+};
+use crate::services::api_versions::{self, ApiRequest};
+use crate::services::auth::Actor;
+use crate::services::bulk::{BulkDeleteOutcome, BulkKind, BulkSelection};
+use crate::services::fault_injection::{self, FaultSpec};
+use crate::services::health;
+use crate::services::https::StreamedResponse;
+use crate::services::ids::IdKind;
+use crate::services::revision::{self, ConfigRevision};
+use crate::services::safety::LeafMcpSafety;
+use crate::services::tools::{self, ToolCall};
+use crate::services::{
+    ConfigService, ConnectionService, debug_capture, discovery, logging, sandbox, sync,
 };
-use crate::services::ConfigService;
 
 type ServiceExtension = Extension<Arc<ConfigService>>;
 
+/// Error status with a JSON body explaining it, for errors answered
+/// differently than their [`MceptionError`] would be
+type ApiError = (StatusCode, Json<Value>);
+
+/// Answer an error with its own status, for handlers also answering others
+fn api_error(e: MceptionError) -> ApiError {
+    error_with_status(e.status_code(), e)
+}
+
+/// Answer an error with another status than its own
+fn error_with_status(status: StatusCode, e: MceptionError) -> ApiError {
+    (status, Json(error_body(e.kind(), e)))
+}
+
+pub(crate) fn page_error(e: PageError) -> ApiError {
+    let (status, kind) = match e {
+        PageError::StaleCursor { .. } => (StatusCode::GONE, "stale_cursor"),
+        PageError::InvalidQuery(_) => (StatusCode::BAD_REQUEST, "invalid_query"),
+        PageError::InvalidCursor(_) => (StatusCode::BAD_REQUEST, "invalid_cursor"),
+    };
+    (status, Json(error_body(kind, e)))
+}
+
+/// Refuse requests whose `should_*` safeguard isn't set
+/// Check a `should_*` flag. From version 2 of the API on a request confirms
+/// itself, and the flag is deprecated.
+fn require_confirmation(
+    api: &ApiRequest,
+    confirmed: Option<bool>,
+    field: &str,
+) -> Result<(), MceptionError> {
+    if confirmed.is_some() {
+        api.use_deprecated(&api_versions::CONFIRMATION_FLAGS);
+    }
+    match confirmed {
+        Some(true) => Ok(()),
+        None if api.version >= api_versions::CONFIRMATION_FLAGS.replaced_in => Ok(()),
+        _ => Err(ValidationError::RequiredFieldMissing(format!("{} must be true", field)).into()),
+    }
+}
+
+fn invalid(message: String) -> MceptionError {
+    ValidationError::InvalidFormat(message).into()
+}
+
+/// Parse an optional duration like `10m`, which must be positive and at most `max`
+fn window(
+    duration: Option<&str>,
+    default: chrono::Duration,
+    max: chrono::Duration,
+) -> Result<chrono::Duration, MceptionError> {
+    let window = match duration {
+        Some(duration) => duration::parse_duration(duration)
+            .ok_or_else(|| invalid(format!("Invalid duration '{}'", duration)))?,
+        None => default,
+    };
+    if window <= chrono::Duration::zero() || window > max {
+        return Err(ValidationError::ValueOutOfRange(format!(
+            "duration must be positive and at most {} seconds",
+            max.num_seconds()
+        ))
+        .into());
+    }
+    Ok(window)
+}
+
 pub fn router() -> Router {
     Router::new()
         // Leaf MCP endpoints
+        .route("/leaf", get(list_leaf_mcps))
         .route("/leaf", post(create_leaf_mcp))
+        .route("/leaf", delete(delete_leaf_mcps_by_tag))
+        .route("/leaf/bulk_delete", post(bulk_delete_leaf_mcps))
+        .route("/discover", post(discover_leaf_mcps))
         .route("/leaf/{leaf_mcp_id}/config", get(read_leaf_mcp_config))
         .route("/leaf/{leaf_mcp_id}/config", put(update_leaf_mcp_config))
         .route("/leaf/{leaf_mcp_id}", delete(delete_leaf_mcp))
         .route("/leaf/{leaf_mcp_id}/tools", get(read_leaf_mcp_tools))
+        .route(
+            "/leaf/{leaf_mcp_id}/tools/{tool_name}/call",
+            post(call_leaf_mcp_tool),
+        )
+        .route(
+            "/leaf/{leaf_mcp_id}/resources",
+            get(list_leaf_mcp_resources),
+        )
+        .route(
+            "/leaf/{leaf_mcp_id}/resources/read",
+            post(read_leaf_mcp_resource),
+        )
+        .route("/leaf/{leaf_mcp_id}/sandbox", get(read_leaf_mcp_sandbox))
+        .route("/leaf/{leaf_mcp_id}/session", get(read_leaf_mcp_session))
+        .route("/leaf/{leaf_mcp_id}/status", get(read_leaf_mcp_status))
+        .route(
+            "/leaf/{leaf_mcp_id}/status/reset",
+            post(reset_leaf_mcp_circuit_breaker),
+        )
+        .route("/leaf/{leaf_mcp_id}/safety", get(read_leaf_mcp_safety))
+        .route("/leaf/{leaf_mcp_id}/debug", post(enable_leaf_mcp_debug))
+        .route("/leaf/{leaf_mcp_id}/debug", delete(disable_leaf_mcp_debug))
+        .route(
+            "/leaf/{leaf_mcp_id}/debug/capture",
+            get(read_leaf_mcp_debug_capture),
+        )
+        .route("/leaf/{leaf_mcp_id}/faults", get(read_leaf_mcp_faults))
+        .route("/leaf/{leaf_mcp_id}/faults", post(inject_leaf_mcp_faults))
+        .route("/leaf/{leaf_mcp_id}/faults", delete(clear_leaf_mcp_faults))
+        .route("/replicas", get(list_replica_groups))
         // MCeption Agent endpoints
+        .route("/agent", get(list_agents))
         .route("/agent", post(create_agent))
+        .route("/agent", delete(delete_agents_by_tag))
+        .route("/agent/bulk_delete", post(bulk_delete_agents))
         .route("/agent/{agent_id}/config", get(read_agent_config))
         .route("/agent/{agent_id}/config", put(update_agent_config))
         .route("/agent/{agent_id}", delete(delete_agent))
         .route("/agent/{agent_id}/tools", get(read_agent_tools))
+        .route("/agent/{agent_id}/tools/call", post(call_agent_tool))
         .route(
             "/agent/{agent_id}/allowed_mcps",
             post(add_agent_allowed_mcps),
@@ -40,292 +166,1933 @@ pub fn router() -> Router {
             "/agent/{agent_id}/allowed_mcps",
             delete(remove_agent_allowed_mcps),
         )
+        .route(
+            "/agent/{agent_id}/allowed_mcps/by_label",
+            post(add_agent_allowed_mcps_by_label),
+        )
+        .route(
+            "/agent/{agent_id}/allowed_mcps/{mcp_id}/tools",
+            put(set_agent_tool_filter),
+        )
+        .route(
+            "/agent/{agent_id}/availability",
+            get(read_agent_availability),
+        )
+        .route("/availability", get(read_fleet_availability))
+        .route("/agent/{agent_id}/explain", get(explain_agent_access))
+        // Bundle endpoints
+        .route("/bundle", post(create_bundle))
+        .route("/bundle", get(list_bundles))
+        .route("/bundle/{bundle_name}", get(read_bundle))
+        .route("/bundle/{bundle_name}", put(update_bundle))
+        .route("/bundle/{bundle_name}", delete(delete_bundle))
         // System endpoints
+        .route("/versions", get(get_api_versions))
+        .route("/health/deep", get(get_deep_health))
+        .route("/status", get(get_server_status))
+        .route("/internals", get(get_internals))
+        .route("/expiring", get(get_expiring))
+        .route("/ids/suggest", get(suggest_id))
+        .route("/last-shutdown", get(read_last_shutdown))
+        .route("/consistency", get(get_consistency))
+        .route("/sync", get(get_sync_status))
+        .route("/sync/{sync_id}/run", post(run_sync_target))
+        .route("/policy", get(get_registration_policy))
+        .route("/policy", put(set_registration_policy))
+        .route("/policy/report", post(report_registration_policy))
+        .route("/policy/audit", get(get_audit_policy))
+        .route("/policy/audit", put(set_audit_policy))
+        .route("/policy/confirmation", get(get_confirmation_policy))
+        .route("/policy/confirmation", put(set_confirmation_policy))
+        .route("/logging", get(get_logging))
+        .route("/logging", put(set_logging))
+        .route("/graph", get(get_config_graph))
         .route("/config", get(get_server_config))
+        .route("/config/revision", get(get_config_revision))
+        .route("/config/revision/watch", get(watch_config_revision))
         .route("/config/backup", post(backup_server_config))
+        .route("/config/restore", post(import_server_config))
+        .route("/config/backups", get(list_config_backups))
+        .route(
+            "/config/backups/{backup_name}/restore",
+            post(restore_config_backup),
+        )
+        .route("/config/asof", get(get_config_as_of))
+        .route("/config/asof/leaf/{leaf_mcp_id}", get(get_leaf_mcp_as_of))
+        .route("/config/asof/agent/{agent_id}", get(get_agent_as_of))
         .route("/audit", get(get_audit_logs))
+        .route("/audit/verify", get(verify_audit_chain))
 }
 
 // Leaf MCP handlers
 async fn create_leaf_mcp(
     Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Extension(api): Extension<ApiRequest>,
     Json(request): Json<CreateLeafMcpRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    if !request.should_create {
-        return Err(StatusCode::BAD_REQUEST);
-    }
+) -> Result<Json<Value>, MceptionError> {
+    require_confirmation(&api, request.should_create, "should_create")?;
 
-    match service
-        .create_leaf_mcp(
-            request.id.clone(),
-            request.config,
-            Some("admin".to_string()),
-            request.reason,
-        )
-        .await
+    let id = service
+        .create_leaf_mcp(request.id, request.config, Some(actor), request.reason)
+        .await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "id": id,
+        "message": format!("Leaf MCP '{}' created successfully", id)
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct LeafMcpListQuery {
+    /// `stdio`, `https` or `builtin`
+    transport: Option<String>,
+    is_local: Option<bool>,
+    /// Label selector like `env=staging,team!=payments`
+    label: Option<String>,
+    fields: Option<String>,
+}
+
+/// Parse the `label` selector of a listing
+fn label_selector(selector: Option<&str>) -> Result<Option<LabelSelector>, MceptionError> {
+    selector
+        .map(|selector| selector.parse().map_err(invalid))
+        .transpose()
+}
+
+/// Every leaf MCP keyed by id, optionally filtered and cut to some fields
+async fn list_leaf_mcps(
+    Extension(service): ServiceExtension,
+    Query(query): Query<LeafMcpListQuery>,
+) -> Result<Json<Value>, MceptionError> {
+    if let Some(transport) = &query.transport
+        && !["stdio", "https", "builtin"].contains(&transport.as_str())
     {
-        Ok(()) => Ok(Json(serde_json::json!({
-            "success": true,
-            "message": format!("Leaf MCP '{}' created successfully", request.id)
-        }))),
-        Err(e) => {
-            error!("Error creating leaf MCP: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
+        return Err(invalid(format!(
+            "Unknown transport '{}', expected stdio, https or builtin",
+            transport
+        )));
+    }
+    let selector = label_selector(query.label.as_deref())?;
+    let mcps = service
+        .list_leaf_mcps()
+        .await?
+        .into_iter()
+        .filter(|(_, mcp)| {
+            query
+                .transport
+                .as_ref()
+                .is_none_or(|transport| transport == transport_type(&mcp.transport))
+                && query
+                    .is_local
+                    .is_none_or(|is_local| is_local == mcp.is_local)
+                && selector
+                    .as_ref()
+                    .is_none_or(|selector| selector.matches(&mcp.labels))
+        });
+    let leaf_mcps = listing(
+        mcps.map(|(id, mcp)| (id, mcp.redacted())),
+        query.fields.as_deref(),
+    );
+    Ok(Json(serde_json::json!({
+        "count": leaf_mcps.len(),
+        "leaf_mcps": leaf_mcps
+    })))
+}
+
+fn transport_type(transport: &McpTransport) -> &'static str {
+    match transport {
+        McpTransport::Stdio { .. } => "stdio",
+        McpTransport::Https { .. } => "https",
+        McpTransport::Sse { .. } => "sse",
+        McpTransport::Builtin { .. } => "builtin",
     }
 }
 
+/// Entries keyed by id, in id order, with only the comma separated top-level
+/// `fields` of each if given
+fn listing<T: Serialize>(
+    entries: impl Iterator<Item = (String, T)>,
+    fields: Option<&str>,
+) -> serde_json::Map<String, Value> {
+    let fields: Option<Vec<&str>> = fields.map(|fields| {
+        fields
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .collect()
+    });
+    entries
+        .map(|(id, entry)| {
+            let mut value = serde_json::to_value(entry).unwrap_or_default();
+            if let (Some(fields), Value::Object(object)) = (&fields, &mut value) {
+                object.retain(|key, _| fields.contains(&key.as_str()));
+            }
+            (id, value)
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+struct ReadLeafMcpQuery {
+    /// Show the stored secrets and `${NAME}` placeholders instead of redacting them
+    #[serde(default)]
+    reveal: bool,
+}
+
 async fn read_leaf_mcp_config(
     Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
     Path(leaf_mcp_id): Path<String>,
-) -> Result<Json<LeafMcpConfig>, StatusCode> {
-    match service
-        .get_leaf_mcp(&leaf_mcp_id, Some("admin".to_string()))
-        .await
-    {
-        Ok(config) => Ok(Json(config)),
-        Err(_) => Err(StatusCode::NOT_FOUND),
-    }
+    Query(query): Query<ReadLeafMcpQuery>,
+) -> Result<Json<Value>, MceptionError> {
+    let config = if query.reveal {
+        service.reveal_leaf_mcp(&leaf_mcp_id, Some(actor)).await?
+    } else {
+        service
+            .get_leaf_mcp(&leaf_mcp_id, Some(actor))
+            .await?
+            .redacted()
+    };
+    let mut body = serde_json::to_value(&config).unwrap_or_default();
+    // Always given, to be sent back as `expected_revision`
+    body["revision"] = config.revision.into();
+    Ok(Json(body))
 }
 
 async fn update_leaf_mcp_config(
     Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Extension(api): Extension<ApiRequest>,
     Path(leaf_mcp_id): Path<String>,
     Json(request): Json<UpdateLeafMcpRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    if !request.should_update {
-        return Err(StatusCode::BAD_REQUEST);
-    }
+) -> Result<Json<Value>, ApiError> {
+    require_confirmation(&api, request.should_update, "should_update").map_err(api_error)?;
 
     match service
-        .update_leaf_mcp(
+        .update_leaf_mcp_at(
             &leaf_mcp_id,
             request.config,
-            Some("admin".to_string()),
+            request.expected_revision,
+            Some(actor),
             request.reason,
         )
         .await
     {
-        Ok(()) => Ok(Json(serde_json::json!({
+        Ok(revision) => Ok(Json(serde_json::json!({
             "success": true,
-            "message": format!("Leaf MCP '{}' updated successfully", leaf_mcp_id)
+            "message": format!("Leaf MCP '{}' updated successfully", leaf_mcp_id),
+            "revision": revision
         }))),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(e @ MceptionError::Storage(StorageError::StaleRevision(_))) => {
+            let current = service
+                .get_configuration()
+                .await
+                .leaf_mcps
+                .remove(&leaf_mcp_id)
+                .map(|leaf| leaf.redacted());
+            Err(stale_revision(e, current))
+        }
+        Err(e) => Err(api_error(e)),
+    }
+}
+
+/// Answer an update based on an outdated revision with the current state of
+/// the entity, for the client to apply its change to
+fn stale_revision(e: MceptionError, current: Option<impl Serialize>) -> ApiError {
+    let mut body = error_body(e.kind(), &e);
+    body["current"] = serde_json::to_value(current).unwrap_or_default();
+    if let Some(current) = body["current"].as_object_mut() {
+        current.entry("revision").or_insert(0.into());
     }
+    (e.status_code(), Json(body))
 }
 
 async fn delete_leaf_mcp(
     Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Extension(api): Extension<ApiRequest>,
     Path(leaf_mcp_id): Path<String>,
     Json(request): Json<DeleteLeafMcpRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    if !request.should_delete_mcp {
-        return Err(StatusCode::BAD_REQUEST);
+) -> Result<Json<Value>, MceptionError> {
+    require_confirmation(&api, request.should_delete_mcp, "should_delete_mcp")?;
+
+    service
+        .delete_leaf_mcp(&leaf_mcp_id, Some(actor), request.reason)
+        .await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": format!("Leaf MCP '{}' deleted successfully", leaf_mcp_id)
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolsQuery {
+    /// List the tools again instead of using the cached listing
+    #[serde(default)]
+    refresh: bool,
+    /// Only tools whose name, or `<mcp id>/<tool>`, matches this glob
+    filter: Option<String>,
+}
+
+async fn read_leaf_mcp_tools(
+    Extension(service): ServiceExtension,
+    Path(leaf_mcp_id): Path<String>,
+    Query(query): Query<ToolsQuery>,
+) -> Result<Json<Value>, ApiError> {
+    match service.leaf_mcp_tools(&leaf_mcp_id, query.refresh).await {
+        Ok(listing) => Ok(Json(serde_json::json!(listing))),
+        Err(e @ MceptionError::Storage(StorageError::NotFound(_))) => {
+            Err(error_with_status(StatusCode::NOT_FOUND, e))
+        }
+        // The leaf MCP couldn't be asked or gave no usable answer
+        Err(e) => Err(error_with_status(StatusCode::BAD_GATEWAY, e)),
+    }
+}
+
+/// Call a leaf MCP's tool with the arguments in the body, once they satisfy
+/// the tool's `parameters` schema. Arguments that don't are refused with
+/// every place they fail, without reaching the leaf MCP.
+async fn call_leaf_mcp_tool(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Path((leaf_mcp_id, tool_name)): Path<(String, String)>,
+    Json(arguments): Json<Value>,
+) -> Result<Json<Value>, ApiError> {
+    call_tool(&service, leaf_mcp_id, tool_name, arguments, actor).await
+}
+
+/// Call a leaf MCP's tool once its arguments match the tool's schema
+async fn call_tool(
+    service: &ConfigService,
+    leaf_mcp_id: String,
+    tool_name: String,
+    arguments: Value,
+    actor: String,
+) -> Result<Json<Value>, ApiError> {
+    let tool = match service.leaf_mcp_tool(&leaf_mcp_id, &tool_name).await {
+        Ok(tool) => tool,
+        Err(e @ MceptionError::Storage(StorageError::NotFound(_))) => {
+            return Err(error_with_status(StatusCode::NOT_FOUND, e));
+        }
+        // The leaf MCP couldn't list its tools
+        Err(e) => return Err(error_with_status(StatusCode::BAD_GATEWAY, e)),
+    };
+    let errors = tools::argument_errors(&tool, &arguments);
+    if !errors.is_empty() {
+        let e = MceptionError::Validation(ValidationError::SchemaViolation(format!(
+            "{} of tool '{}'",
+            errors.join(", "),
+            tool_name
+        )));
+        let mut body = error_body(e.kind(), &e);
+        body["error"]["errors"] = serde_json::json!(errors);
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(body)));
     }
 
+    let call = ToolCall {
+        name: tool_name,
+        arguments,
+    };
     match service
-        .delete_leaf_mcp(&leaf_mcp_id, Some("admin".to_string()), request.reason)
+        .call_leaf_mcp_tool(&leaf_mcp_id, &call, Some(actor))
         .await
     {
-        Ok(()) => Ok(Json(serde_json::json!({
-            "success": true,
-            "message": format!("Leaf MCP '{}' deleted successfully", leaf_mcp_id)
+        Ok(result) => Ok(Json(serde_json::json!({
+            "leaf_mcp_id": leaf_mcp_id,
+            "tool": call.name,
+            "result": result
         }))),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(e) => Err(leaf_request_error(e)),
     }
 }
 
-async fn read_leaf_mcp_tools(
-    Extension(_service): ServiceExtension,
-    Path(_leaf_mcp_id): Path<String>,
-) -> Result<Json<Value>, StatusCode> {
-    // TODO: Implement actual MCP tool forwarding
-    // For now, return empty tools list
+/// Answer a failed request of the server's own to a leaf MCP
+fn leaf_request_error(e: MceptionError) -> ApiError {
+    match e {
+        MceptionError::Storage(StorageError::NotFound(_)) => {
+            error_with_status(StatusCode::NOT_FOUND, e)
+        }
+        MceptionError::Network(NetworkError::Timeout(_)) => {
+            error_with_status(StatusCode::GATEWAY_TIMEOUT, e)
+        }
+        // The leaf MCP couldn't be asked or gave no usable answer
+        _ => error_with_status(StatusCode::BAD_GATEWAY, e),
+    }
+}
+
+/// The result of a leaf MCP's JSON-RPC response. A JSON-RPC error is
+/// answered with `502` and the error as the leaf MCP sent it in
+/// `error.jsonrpc_error`.
+fn leaf_result(leaf_mcp_id: &str, mut response: Value) -> Result<Value, ApiError> {
+    if let Some(error) = response.get_mut("error") {
+        let message = format!(
+            "Leaf MCP '{}' answered with error {}: {}",
+            leaf_mcp_id,
+            error["code"],
+            error["message"].as_str().unwrap_or_default()
+        );
+        let mut body = error_body("leaf_error", message);
+        body["error"]["jsonrpc_error"] = error.take();
+        return Err((StatusCode::BAD_GATEWAY, Json(body)));
+    }
+    match response.get_mut("result") {
+        Some(result) => Ok(result.take()),
+        None => Err(error_with_status(
+            StatusCode::BAD_GATEWAY,
+            NetworkError::ConnectionFailed(format!(
+                "Leaf MCP '{}' answered without a result",
+                leaf_mcp_id
+            ))
+            .into(),
+        )),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ResourcesQuery {
+    /// `nextCursor` of the previous page
+    cursor: Option<String>,
+}
+
+/// Resources of a leaf MCP, one page of its `resources/list` result as it is
+async fn list_leaf_mcp_resources(
+    Extension(service): ServiceExtension,
+    Path(leaf_mcp_id): Path<String>,
+    Query(query): Query<ResourcesQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let params = match query.cursor {
+        Some(cursor) => serde_json::json!({ "cursor": cursor }),
+        None => serde_json::json!({}),
+    };
+    let response = service
+        .leaf_mcp_request(&leaf_mcp_id, "resources/list", params)
+        .await
+        .map_err(leaf_request_error)?;
+    Ok(Json(leaf_result(&leaf_mcp_id, response)?))
+}
+
+#[derive(Debug, Deserialize)]
+struct ReadResourceRequest {
+    uri: String,
+}
+
+/// Contents of a leaf MCP's resource, its `resources/read` result as it is.
+/// The result of an HTTPS leaf MCP is passed on as it arrives, so a large
+/// resource is neither held in memory nor delayed until it is complete.
+async fn read_leaf_mcp_resource(
+    Extension(service): ServiceExtension,
+    Path(leaf_mcp_id): Path<String>,
+    Json(request): Json<ReadResourceRequest>,
+) -> Result<Response, ApiError> {
+    let response = service
+        .leaf_mcp_request_streamed(
+            &leaf_mcp_id,
+            "resources/read",
+            serde_json::json!({ "uri": request.uri }),
+        )
+        .await
+        .map_err(leaf_request_error)?;
+    match response {
+        StreamedResponse::Result(result) => Ok((
+            [(header::CONTENT_TYPE, "application/json")],
+            Body::from_stream(result),
+        )
+            .into_response()),
+        StreamedResponse::Whole(response) => {
+            Ok(Json(leaf_result(&leaf_mcp_id, response)?).into_response())
+        }
+    }
+}
+
+async fn read_leaf_mcp_sandbox(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Path(leaf_mcp_id): Path<String>,
+) -> Result<Json<Value>, MceptionError> {
+    let config = service.get_leaf_mcp(&leaf_mcp_id, Some(actor)).await?;
+
+    let restrictions = match &config.transport {
+        McpTransport::Stdio { sandbox, .. } => sandbox::describe(sandbox),
+        McpTransport::Https { .. } | McpTransport::Sse { .. } | McpTransport::Builtin { .. } => {
+            Vec::new()
+        }
+    };
+
     Ok(Json(serde_json::json!({
-        "tools": []
+        "leaf_mcp_id": leaf_mcp_id,
+        "restrictions": restrictions
     })))
 }
 
-// MCeption Agent handlers
-async fn create_agent(
+/// The session an HTTPS leaf MCP's tools are listed in, and whether it was
+/// resumed from the previous run
+async fn read_leaf_mcp_session(
     Extension(service): ServiceExtension,
-    Json(request): Json<CreateAgentRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    if !request.should_create {
-        return Err(StatusCode::BAD_REQUEST);
+    Path(leaf_mcp_id): Path<String>,
+) -> Result<Json<Value>, MceptionError> {
+    let session = service.leaf_mcp_session(&leaf_mcp_id).await?;
+    Ok(Json(serde_json::json!({
+        "leaf_mcp_id": leaf_mcp_id,
+        "session": session
+    })))
+}
+
+async fn read_leaf_mcp_status(
+    Extension(service): ServiceExtension,
+    Path(leaf_mcp_id): Path<String>,
+) -> Result<Json<Value>, MceptionError> {
+    let process = service.leaf_mcp_process(&leaf_mcp_id).await?;
+    let circuit_breaker = service.leaf_mcp_circuit_breaker(&leaf_mcp_id).await?;
+    let concurrency = service.leaf_mcp_concurrency(&leaf_mcp_id).await?;
+    let health = service.health_statuses().get(&leaf_mcp_id).await;
+    Ok(Json(serde_json::json!({
+        "leaf_mcp_id": leaf_mcp_id,
+        "process": process,
+        "circuit_breaker": circuit_breaker,
+        "concurrency": concurrency,
+        "health": health
+    })))
+}
+
+async fn reset_leaf_mcp_circuit_breaker(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Path(leaf_mcp_id): Path<String>,
+) -> Result<Json<Value>, MceptionError> {
+    let circuit_breaker = service
+        .reset_circuit_breaker(&leaf_mcp_id, Some(actor))
+        .await?;
+    Ok(Json(serde_json::json!({
+        "leaf_mcp_id": leaf_mcp_id,
+        "circuit_breaker": circuit_breaker
+    })))
+}
+
+async fn read_leaf_mcp_safety(
+    Extension(service): ServiceExtension,
+    Path(leaf_mcp_id): Path<String>,
+) -> Result<Json<LeafMcpSafety>, MceptionError> {
+    Ok(Json(service.leaf_mcp_safety(&leaf_mcp_id).await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct DebugCaptureQuery {
+    duration: Option<String>,
+    max_bytes: Option<usize>,
+}
+
+async fn enable_leaf_mcp_debug(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Path(leaf_mcp_id): Path<String>,
+    Query(query): Query<DebugCaptureQuery>,
+) -> Result<Json<Value>, MceptionError> {
+    let window = window(
+        query.duration.as_deref(),
+        debug_capture::DEFAULT_WINDOW,
+        debug_capture::MAX_WINDOW,
+    )?;
+    let max_bytes = query.max_bytes.unwrap_or(debug_capture::DEFAULT_MAX_BYTES);
+    if max_bytes == 0 || max_bytes > debug_capture::MAX_MAX_BYTES {
+        return Err(ValidationError::ValueOutOfRange(format!(
+            "max_bytes must be between 1 and {}",
+            debug_capture::MAX_MAX_BYTES
+        ))
+        .into());
     }
 
-    match service
-        .create_agent(
-            request.agent_id.clone(),
-            request.allowed_mcp_ids,
-            Some("admin".to_string()),
+    let state = service
+        .enable_debug_capture(&leaf_mcp_id, window, max_bytes, Some(actor))
+        .await?;
+
+    // Auto-disable once the window has passed
+    let expiry_service = service.clone();
+    let expiry_leaf_id = leaf_mcp_id.clone();
+    let session_id = state.session_id.clone();
+    tokio::spawn(async move {
+        if let Ok(window) = window.to_std() {
+            tokio::time::sleep(window).await;
+        }
+        if let Err(e) = expiry_service
+            .expire_debug_capture(&expiry_leaf_id, &session_id)
+            .await
+        {
+            error!(
+                "Error expiring debug capture for '{}': {}",
+                expiry_leaf_id, e
+            );
+        }
+    });
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "leaf_mcp_id": leaf_mcp_id,
+        "session_id": state.session_id,
+        "expires_at": state.expires_at,
+        "max_bytes": state.max_bytes
+    })))
+}
+
+async fn disable_leaf_mcp_debug(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Path(leaf_mcp_id): Path<String>,
+) -> Result<Json<Value>, MceptionError> {
+    let was_active = service
+        .disable_debug_capture(&leaf_mcp_id, Some(actor))
+        .await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "was_active": was_active
+    })))
+}
+
+async fn read_leaf_mcp_debug_capture(
+    Extension(service): ServiceExtension,
+    Path(leaf_mcp_id): Path<String>,
+) -> Result<Json<Value>, MceptionError> {
+    match service.debug_captures().get(&leaf_mcp_id).await {
+        Some(capture) => Ok(Json(serde_json::json!({
+            "leaf_mcp_id": leaf_mcp_id,
+            "capture": capture
+        }))),
+        None => Err(StorageError::NotFound(format!(
+            "No debug capture for leaf MCP '{}'",
+            leaf_mcp_id
+        ))
+        .into()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct InjectFaultsRequest {
+    #[serde(flatten)]
+    faults: FaultSpec,
+    /// How long to inject faults, e.g. `10m`
+    duration: Option<String>,
+    reason: Option<String>,
+}
+
+async fn inject_leaf_mcp_faults(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Path(leaf_mcp_id): Path<String>,
+    Json(request): Json<InjectFaultsRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let window = window(
+        request.duration.as_deref(),
+        fault_injection::DEFAULT_WINDOW,
+        fault_injection::MAX_WINDOW,
+    )
+    .map_err(api_error)?;
+
+    let state = service
+        .inject_faults(
+            &leaf_mcp_id,
+            request.faults,
+            window,
+            Some(actor),
+            request.reason,
         )
         .await
-    {
-        Ok(()) => Ok(Json(serde_json::json!({
-            "success": true,
-            "message": format!("Agent '{}' created successfully", request.agent_id)
-        }))),
-        Err(e) => {
-            error!("Error creating agent: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        .map_err(fault_injection_error)?;
+
+    // Stop injecting once the window has passed
+    let expiry_service = service.clone();
+    let expiry_leaf_id = leaf_mcp_id.clone();
+    let session_id = state.session_id.clone();
+    tokio::spawn(async move {
+        if let Ok(window) = window.to_std() {
+            tokio::time::sleep(window).await;
         }
+        if let Err(e) = expiry_service
+            .expire_faults(&expiry_leaf_id, &session_id)
+            .await
+        {
+            error!("Error expiring faults for '{}': {}", expiry_leaf_id, e);
+        }
+    });
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "leaf_mcp_id": leaf_mcp_id,
+        "faults": state
+    })))
+}
+
+async fn clear_leaf_mcp_faults(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Path(leaf_mcp_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let was_active = service
+        .clear_faults(&leaf_mcp_id, Some(actor))
+        .await
+        .map_err(fault_injection_error)?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "was_active": was_active
+    })))
+}
+
+async fn read_leaf_mcp_faults(
+    Extension(service): ServiceExtension,
+    Path(leaf_mcp_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let faults = service
+        .faults(&leaf_mcp_id)
+        .await
+        .map_err(fault_injection_error)?;
+    Ok(Json(serde_json::json!({
+        "leaf_mcp_id": leaf_mcp_id,
+        "faults": faults
+    })))
+}
+
+/// Fault injection is forbidden unless the server was started with it enabled
+fn fault_injection_error(e: MceptionError) -> ApiError {
+    match e {
+        MceptionError::Configuration(_) => error_with_status(StatusCode::FORBIDDEN, e),
+        e => api_error(e),
     }
 }
 
+// MCeption Agent handlers
+async fn create_agent(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Extension(api): Extension<ApiRequest>,
+    Json(request): Json<CreateAgentRequest>,
+) -> Result<Json<Value>, MceptionError> {
+    require_confirmation(&api, request.should_create, "should_create")?;
+
+    let agent_id = service
+        .create_agent_with_description(request, None, Some(actor), None)
+        .await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "agent_id": agent_id,
+        "message": format!("Agent '{}' created successfully", agent_id)
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct AgentListQuery {
+    connected: Option<bool>,
+    /// Label selector, like [`LeafMcpListQuery::label`]
+    label: Option<String>,
+    fields: Option<String>,
+}
+
+/// Every agent keyed by id, tokens redacted, optionally filtered and cut to
+/// some fields
+async fn list_agents(
+    Extension(service): ServiceExtension,
+    Query(query): Query<AgentListQuery>,
+) -> Result<Json<Value>, MceptionError> {
+    let selector = label_selector(query.label.as_deref())?;
+    let agents = service
+        .list_agents()
+        .await?
+        .into_iter()
+        .filter(|(_, agent)| {
+            query
+                .connected
+                .is_none_or(|connected| connected == agent.is_connected)
+                && selector
+                    .as_ref()
+                    .is_none_or(|selector| selector.matches(&agent.labels))
+        })
+        .map(|(id, agent)| (id, agent.redacted()));
+    let agents = listing(agents, query.fields.as_deref());
+    Ok(Json(serde_json::json!({
+        "count": agents.len(),
+        "agents": agents
+    })))
+}
+
 async fn read_agent_config(
     Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
     Path(agent_id): Path<String>,
-) -> Result<Json<Value>, StatusCode> {
-    match service
-        .get_agent(&agent_id, Some("admin".to_string()))
-        .await
-    {
-        Ok(config) => Ok(Json(serde_json::json!({
-            "allowed_mcp_ids": config.allowed_mcp_ids,
-            "is_connected": config.is_connected,
-            "last_seen": config.last_seen,
-            "config": config.config
-        }))),
-        Err(_) => Err(StatusCode::NOT_FOUND),
-    }
+) -> Result<Json<Value>, MceptionError> {
+    let config = service.get_agent(&agent_id, Some(actor)).await?;
+    let grant_sources: BTreeMap<&String, BTreeSet<GrantSource>> = config
+        .allowed_mcp_ids
+        .iter()
+        .map(|grant| (grant, config.grant_sources_of(grant)))
+        .collect();
+    Ok(Json(serde_json::json!({
+        "allowed_mcp_ids": config.allowed_mcp_ids,
+        "grant_sources": grant_sources,
+        "grant_expiry": config.grant_expiry,
+        "is_connected": config.is_connected,
+        "last_seen": config.last_seen,
+        "config": config.config,
+        "labels": config.labels,
+        "has_auth_token": config.auth_token.is_some(),
+        "auth_token_expires_at": config.auth_token_expires_at,
+        "revision": config.revision
+    })))
 }
 
 async fn update_agent_config(
     Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Extension(api): Extension<ApiRequest>,
     Path(agent_id): Path<String>,
     Json(request): Json<UpdateAgentRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    if !request.should_update {
-        return Err(StatusCode::BAD_REQUEST);
-    }
+) -> Result<Json<Value>, ApiError> {
+    require_confirmation(&api, request.should_update, "should_update").map_err(api_error)?;
 
     match service
-        .update_agent(
+        .update_agent_at(
             &agent_id,
             request.config,
-            Some("admin".to_string()),
+            request.expected_revision,
+            Some(actor),
             request.reason,
         )
         .await
     {
-        Ok(()) => Ok(Json(serde_json::json!({
+        Ok(revision) => Ok(Json(serde_json::json!({
             "success": true,
-            "message": format!("Agent '{}' updated successfully", agent_id)
+            "message": format!("Agent '{}' updated successfully", agent_id),
+            "revision": revision
         }))),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(e @ MceptionError::Storage(StorageError::StaleRevision(_))) => {
+            let current = service
+                .get_configuration()
+                .await
+                .agents
+                .get(&agent_id)
+                .map(AgentConfig::redacted);
+            Err(stale_revision(e, current))
+        }
+        Err(e) => Err(api_error(e)),
     }
 }
 
 async fn delete_agent(
     Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Extension(api): Extension<ApiRequest>,
     Path(agent_id): Path<String>,
     Json(request): Json<DeleteAgentRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    if !request.should_delete_mcp {
-        return Err(StatusCode::BAD_REQUEST);
-    }
-
-    match service
-        .delete_agent(&agent_id, Some("admin".to_string()), request.reason)
-        .await
-    {
-        Ok(()) => Ok(Json(serde_json::json!({
-            "success": true,
-            "message": format!("Agent '{}' deleted successfully", agent_id)
-        }))),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
-}
+) -> Result<Json<Value>, MceptionError> {
+    require_confirmation(&api, request.should_delete_mcp, "should_delete_mcp")?;
 
-async fn read_agent_tools(
-    Extension(_service): ServiceExtension,
-    Path(_agent_id): Path<String>,
-) -> Result<Json<Value>, StatusCode> {
-    // TODO: Implement actual agent tool forwarding
-    // For now, return empty tools list
+    service
+        .delete_agent(&agent_id, Some(actor), request.reason)
+        .await?;
     Ok(Json(serde_json::json!({
-        "tools": []
+        "success": true,
+        "message": format!("Agent '{}' deleted successfully", agent_id)
     })))
 }
 
-async fn add_agent_allowed_mcps(
+/// Tools of the MCPs an agent may use, grouped by MCP id and shaped by the
+/// agent's settings
+#[derive(Debug, Deserialize)]
+struct AgentToolCall {
+    /// The tool's name in the agent's listing, `<mcp_id>/<tool>`
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+/// Call a tool by its name in the agent's merged listing, if the agent may
+async fn call_agent_tool(
     Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
     Path(agent_id): Path<String>,
-    Json(request): Json<AddAgentAllowedMcpRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    if !request.should_add_mcp_id {
-        return Err(StatusCode::BAD_REQUEST);
+    Json(call): Json<AgentToolCall>,
+) -> Result<Json<Value>, ApiError> {
+    let Some((mcp_id, tool_name)) = tools::split_namespaced(&call.name) else {
+        return Err(api_error(invalid(format!(
+            "Tool name '{}' is not of the form '<mcp_id>/<tool>'",
+            call.name
+        ))));
+    };
+    let decision = service
+        .explain_access(&agent_id, mcp_id, Some(tool_name))
+        .await
+        .map_err(api_error)?;
+    if !decision.allowed {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(error_body(
+                "not_allowed",
+                format!(
+                    "Agent '{}' may not call tool '{}' of '{}'",
+                    agent_id, tool_name, mcp_id
+                ),
+            )),
+        ));
     }
-
-    match service
-        .add_agent_allowed_mcp(
-            &agent_id,
-            &request.mcp_id,
-            Some("admin".to_string()),
-            request.reason,
-        )
+    if !service
+        .get_configuration()
         .await
+        .leaf_mcps
+        .contains_key(mcp_id)
     {
-        Ok(()) => Ok(Json(serde_json::json!({
-            "success": true,
-            "message": format!("MCP '{}' added to agent '{}' allowed list", request.mcp_id, agent_id)
-        }))),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        return Err(api_error(invalid(format!(
+            "'{}' is an agent, only tools of leaf MCPs can be called here",
+            mcp_id
+        ))));
     }
+    let arguments = match call.arguments {
+        Value::Null => serde_json::json!({}),
+        arguments => arguments,
+    };
+    call_tool(
+        &service,
+        mcp_id.to_string(),
+        tool_name.to_string(),
+        arguments,
+        actor,
+    )
+    .await
 }
 
-async fn remove_agent_allowed_mcps(
+async fn read_agent_tools(
     Extension(service): ServiceExtension,
+    Extension(connections): Extension<Arc<ConnectionService>>,
     Path(agent_id): Path<String>,
-    Json(request): Json<RemoveAgentAllowedMcpRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    if !request.should_remove_mcp_id {
-        return Err(StatusCode::BAD_REQUEST);
+    Query(query): Query<ToolsQuery>,
+) -> Result<Json<Value>, MceptionError> {
+    let tools = service
+        .agent_tools(&agent_id, &connections, query.refresh, query.filter)
+        .await?;
+    Ok(Json(serde_json::json!(tools)))
+}
+
+#[derive(Debug, Deserialize)]
+struct AvailabilityQuery {
+    /// Report window, e.g. `7d` (default)
+    since: Option<String>,
+}
+
+impl AvailabilityQuery {
+    fn window(&self) -> Result<chrono::Duration, ApiError> {
+        let since = self.since.as_deref().unwrap_or("7d");
+        match duration::parse_duration(since) {
+            Some(window) if window > chrono::Duration::zero() => Ok(window),
+            _ => Err(api_error(invalid(format!("Invalid period '{}'", since)))),
+        }
     }
+}
 
-    match service
-        .remove_agent_allowed_mcp(
+/// Availability is only reported when the server tracks it
+fn availability_error(e: MceptionError) -> ApiError {
+    match e {
+        MceptionError::Storage(StorageError::NotFound(_)) => api_error(e),
+        e => error_with_status(StatusCode::NOT_IMPLEMENTED, e),
+    }
+}
+
+async fn read_agent_availability(
+    Extension(service): ServiceExtension,
+    Path(agent_id): Path<String>,
+    Query(query): Query<AvailabilityQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let report = service
+        .agent_availability(&agent_id, query.window()?)
+        .await
+        .map_err(availability_error)?;
+    Ok(Json(serde_json::to_value(report).unwrap_or_default()))
+}
+
+async fn read_fleet_availability(
+    Extension(service): ServiceExtension,
+    Query(query): Query<AvailabilityQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let report = service
+        .fleet_availability(query.window()?)
+        .await
+        .map_err(availability_error)?;
+    Ok(Json(serde_json::to_value(report).unwrap_or_default()))
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplainQuery {
+    mcp: String,
+    tool: Option<String>,
+}
+
+async fn explain_agent_access(
+    Extension(service): ServiceExtension,
+    Path(agent_id): Path<String>,
+    Query(query): Query<ExplainQuery>,
+) -> Result<Json<Value>, MceptionError> {
+    let decision = service
+        .explain_access(&agent_id, &query.mcp, query.tool.as_deref())
+        .await?;
+    Ok(Json(serde_json::to_value(decision).unwrap_or_default()))
+}
+
+async fn add_agent_allowed_mcps(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Extension(api): Extension<ApiRequest>,
+    Path(agent_id): Path<String>,
+    Json(request): Json<AddAgentAllowedMcpRequest>,
+) -> Result<Json<Value>, MceptionError> {
+    require_confirmation(&api, request.should_add_mcp_id, "should_add_mcp_id")?;
+
+    let source = request.source.unwrap_or(GrantSource::Direct);
+    service
+        .grant_agent_mcp(
             &agent_id,
             &request.mcp_id,
-            Some("admin".to_string()),
+            source.clone(),
+            request.expires_at,
+            Some(actor),
             request.reason,
         )
+        .await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": format!("MCP '{}' added to agent '{}' allowed list", request.mcp_id, agent_id),
+        "source": source
+    })))
+}
+
+/// Grant an agent every leaf MCP matching a label selector at once
+async fn add_agent_allowed_mcps_by_label(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Path(agent_id): Path<String>,
+    Json(request): Json<AddAgentAllowedMcpsByLabelRequest>,
+) -> Result<Json<Value>, MceptionError> {
+    let selector: LabelSelector = request.selector.parse().map_err(invalid)?;
+    let granted = service
+        .grant_agent_mcps_by_label(&agent_id, &selector, Some(actor), request.reason)
+        .await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": format!(
+            "{} MCPs matching '{}' added to agent '{}' allowed list",
+            granted.len(),
+            selector,
+            agent_id
+        ),
+        "granted": granted
+    })))
+}
+
+async fn remove_agent_allowed_mcps(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Extension(api): Extension<ApiRequest>,
+    Path(agent_id): Path<String>,
+    Json(request): Json<RemoveAgentAllowedMcpRequest>,
+) -> Result<Json<Value>, MceptionError> {
+    require_confirmation(&api, request.should_remove_mcp_id, "should_remove_mcp_id")?;
+
+    let source = request.source.unwrap_or(GrantSource::Direct);
+    let remaining = service
+        .revoke_agent_mcp(
+            &agent_id,
+            &request.mcp_id,
+            &source,
+            Some(actor),
+            request.reason,
+        )
+        .await?;
+    if remaining.is_empty() {
+        return Ok(Json(serde_json::json!({
+            "success": true,
+            "message": format!("MCP '{}' removed from agent '{}' allowed list", request.mcp_id, agent_id),
+            "remaining_sources": remaining
+        })));
+    }
+
+    // Other sources still grant the MCP, say how the agent may use it now
+    let access = if request.mcp_id.starts_with(BUNDLE_PREFIX) {
+        None
+    } else {
+        Some(
+            service
+                .explain_access(&agent_id, &request.mcp_id, None)
+                .await?,
+        )
+    };
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": format!(
+            "Removed the {} grant of MCP '{}' from agent '{}', which is still granted through {}",
+            source,
+            request.mcp_id,
+            agent_id,
+            remaining.iter().map(GrantSource::to_string).collect::<Vec<_>>().join(", ")
+        ),
+        "remaining_sources": remaining,
+        "access": access
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct SetToolFilterRequest {
+    /// The new filter, or null to allow every tool of the MCP again
+    filter: Option<ToolFilter>,
+    reason: Option<String>,
+}
+
+/// Set which tools of an allowed MCP the agent may call
+async fn set_agent_tool_filter(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Path((agent_id, mcp_id)): Path<(String, String)>,
+    Json(request): Json<SetToolFilterRequest>,
+) -> Result<Json<Value>, MceptionError> {
+    service
+        .set_agent_tool_filter(
+            &agent_id,
+            &mcp_id,
+            request.filter.clone(),
+            Some(actor),
+            request.reason,
+        )
+        .await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "agent_id": agent_id,
+        "mcp_id": mcp_id,
+        "filter": request.filter
+    })))
+}
+
+// Bundle handlers
+
+async fn create_bundle(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Extension(api): Extension<ApiRequest>,
+    Json(request): Json<CreateBundleRequest>,
+) -> Result<Json<Value>, MceptionError> {
+    require_confirmation(&api, request.should_create, "should_create")?;
+
+    service
+        .create_bundle(
+            request.name.clone(),
+            request.description,
+            request.members,
+            Some(actor),
+            request.reason,
+        )
+        .await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": format!("Bundle '{}' created successfully", request.name)
+    })))
+}
+
+/// Leaf MCPs sharing a replica group, grouped, with their region and health
+async fn list_replica_groups(Extension(service): ServiceExtension) -> Json<Value> {
+    Json(serde_json::json!({ "groups": service.replica_groups().await }))
+}
+
+async fn list_bundles(
+    Extension(service): ServiceExtension,
+    Query(query): Query<PageQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let revision = service.revision().await;
+    let page = pagination::paginate(
+        "bundles",
+        service.list_bundles().await,
+        |bundle| (bundle.name.clone(), bundle.name.clone()),
+        revision,
+        &query,
+    )
+    .map_err(page_error)?;
+    Ok(Json(serde_json::json!({
+        "bundles": page.items,
+        "next_cursor": page.next_cursor
+    })))
+}
+
+async fn read_bundle(
+    Extension(service): ServiceExtension,
+    Path(bundle_name): Path<String>,
+) -> Result<Json<BundleConfig>, MceptionError> {
+    Ok(Json(service.get_bundle(&bundle_name).await?))
+}
+
+async fn update_bundle(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Extension(api): Extension<ApiRequest>,
+    Path(bundle_name): Path<String>,
+    Json(request): Json<UpdateBundleRequest>,
+) -> Result<Json<Value>, MceptionError> {
+    require_confirmation(&api, request.should_update, "should_update")?;
+
+    service
+        .update_bundle(
+            &bundle_name,
+            request.description,
+            request.members,
+            Some(actor),
+            request.reason,
+        )
+        .await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": format!("Bundle '{}' updated successfully", bundle_name)
+    })))
+}
+
+async fn delete_bundle(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Extension(api): Extension<ApiRequest>,
+    Path(bundle_name): Path<String>,
+    Json(request): Json<DeleteBundleRequest>,
+) -> Result<Json<Value>, MceptionError> {
+    require_confirmation(&api, request.should_delete_bundle, "should_delete_bundle")?;
+
+    service
+        .delete_bundle(&bundle_name, Some(actor), request.reason)
+        .await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": format!("Bundle '{}' deleted successfully", bundle_name)
+    })))
+}
+
+// System handlers
+
+/// How the previous run ended, with its shutdown report if it stopped cleanly
+async fn read_last_shutdown(Extension(service): ServiceExtension) -> Json<Value> {
+    Json(serde_json::to_value(service.lifecycle().previous_run()).unwrap_or_default())
+}
+
+async fn get_consistency(
+    Extension(service): ServiceExtension,
+) -> Result<Json<Value>, MceptionError> {
+    let report = service.check_consistency().await?;
+    Ok(Json(serde_json::to_value(report).unwrap_or_default()))
+}
+
+/// The configured sync targets with how their last runs went
+async fn get_sync_status(Extension(service): ServiceExtension) -> Json<Value> {
+    let targets = service.get_configuration().await.sync_targets;
+    let mut statuses = service.sync_targets().statuses().await;
+    let targets: serde_json::Map<String, Value> = targets
+        .into_iter()
+        .map(|(id, target)| {
+            let status = statuses.remove(&id);
+            let entry = serde_json::json!({ "target": target.redacted(), "status": status });
+            (id, entry)
+        })
+        .collect();
+    Json(serde_json::json!({ "targets": targets }))
+}
+
+/// Push every agent's configuration to a sync target now
+async fn run_sync_target(
+    Extension(service): ServiceExtension,
+    Path(sync_id): Path<String>,
+) -> Result<Json<Value>, MceptionError> {
+    let mut statuses = sync::run(&service, Some(&sync_id), true).await?;
+    Ok(Json(serde_json::json!({
+        "sync_id": sync_id,
+        "status": statuses.remove(&sync_id)
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct BulkTagQuery {
+    tag: String,
+    confirm: Option<String>,
+    reason: Option<String>,
+}
+
+async fn delete_leaf_mcps_by_tag(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Query(query): Query<BulkTagQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let selection = BulkSelection::Tag(query.tag);
+    bulk_delete(
+        &service,
+        BulkKind::LeafMcp,
+        selection,
+        query.confirm,
+        query.reason,
+        actor,
+    )
+    .await
+}
+
+async fn bulk_delete_leaf_mcps(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Json(request): Json<BulkDeleteRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let selection = BulkSelection::Ids(request.ids);
+    bulk_delete(
+        &service,
+        BulkKind::LeafMcp,
+        selection,
+        request.confirm,
+        request.reason,
+        actor,
+    )
+    .await
+}
+
+async fn delete_agents_by_tag(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Query(query): Query<BulkTagQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let selection = BulkSelection::Tag(query.tag);
+    bulk_delete(
+        &service,
+        BulkKind::Agent,
+        selection,
+        query.confirm,
+        query.reason,
+        actor,
+    )
+    .await
+}
+
+async fn bulk_delete_agents(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Json(request): Json<BulkDeleteRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let selection = BulkSelection::Ids(request.ids);
+    bulk_delete(
+        &service,
+        BulkKind::Agent,
+        selection,
+        request.confirm,
+        request.reason,
+        actor,
+    )
+    .await
+}
+
+/// Dry run without `confirm`, otherwise delete if the token confirms the current plan
+async fn bulk_delete(
+    service: &ConfigService,
+    kind: BulkKind,
+    selection: BulkSelection,
+    confirm: Option<String>,
+    reason: Option<String>,
+    actor: String,
+) -> Result<Json<Value>, ApiError> {
+    let Some(token) = confirm else {
+        let plan = service
+            .plan_bulk_delete(kind, &selection)
+            .await
+            .map_err(api_error)?;
+        return Ok(Json(serde_json::json!({ "dry_run": true, "plan": plan })));
+    };
+    match service
+        .bulk_delete(kind, &selection, &token, Some(actor), reason)
         .await
+        .map_err(api_error)?
     {
-        Ok(()) => Ok(Json(serde_json::json!({
+        BulkDeleteOutcome::Deleted {
+            correlation_id,
+            plan,
+        } => Ok(Json(serde_json::json!({
             "success": true,
-            "message": format!("MCP '{}' removed from agent '{}' allowed list", request.mcp_id, agent_id)
+            "deleted": plan.ids,
+            "correlation_id": correlation_id
         }))),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        BulkDeleteOutcome::PlanChanged { plan, reason } => {
+            let mut body = error_body(
+                "plan_changed",
+                format!(
+                    "Not deleted, {}; review the current plan and confirm it with its token",
+                    reason
+                ),
+            );
+            body["plan"] = serde_json::json!(plan);
+            Err((StatusCode::CONFLICT, Json(body)))
+        }
     }
 }
 
-// System handlers
-async fn get_server_config(
+#[derive(Debug, Deserialize)]
+struct DiscoverQuery {
+    /// Name of the uploaded file, recorded as the source of the candidates
+    source: Option<String>,
+    /// Register the new candidates instead of only listing them
+    #[serde(default)]
+    register: bool,
+}
+
+/// Convert an uploaded MCP client config into leaf MCP candidates
+async fn discover_leaf_mcps(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Query(query): Query<DiscoverQuery>,
+    body: String,
+) -> Result<Json<Value>, MceptionError> {
+    let source = query.source.unwrap_or_else(|| "upload".to_string());
+    let discovery = discovery::parse(&source, &body).map_err(invalid)?;
+    let discovery = service.check_discovered(discovery).await;
+    let registered = if query.register {
+        service
+            .register_discovered(discovery.clone(), Some(actor))
+            .await?
+    } else {
+        Vec::new()
+    };
+    Ok(Json(serde_json::json!({
+        "candidates": discovery.candidates,
+        "skipped": discovery.skipped,
+        "registered": registered
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct SuggestIdQuery {
+    kind: IdKind,
+    name: Option<String>,
+}
+
+/// The id a create without an id would get now, without creating anything
+async fn suggest_id(
     Extension(service): ServiceExtension,
-) -> Result<Json<Value>, StatusCode> {
+    Query(query): Query<SuggestIdQuery>,
+) -> Result<Json<Value>, MceptionError> {
+    let id = service
+        .suggest_id(query.kind, query.name.as_deref())
+        .await?;
+    Ok(Json(serde_json::json!({
+        "id": id,
+        "kind": query.kind,
+        "scheme": service.id_scheme()
+    })))
+}
+
+async fn get_internals(
+    Extension(service): ServiceExtension,
+    Extension(connections): Extension<Arc<ConnectionService>>,
+) -> Json<Value> {
+    Json(serde_json::json!(service.internals(&connections).await))
+}
+
+/// Agent tokens and grants expiring within the warning window, or already
+/// expired
+async fn get_expiring(Extension(service): ServiceExtension) -> Json<Value> {
+    let expiring = service.expiring().await;
+    Json(serde_json::json!({
+        "window_seconds": service.expiry_window().num_seconds(),
+        "tokens": expiring.tokens,
+        "grants": expiring.grants
+    }))
+}
+
+/// Versions of the admin API this server serves and the deprecated features
+async fn get_api_versions(Extension(api): Extension<ApiRequest>) -> Json<Value> {
+    Json(serde_json::json!({
+        "current": api_versions::CURRENT_VERSION,
+        "default": api.default_version,
+        "requested": api.version,
+        "supported": api_versions::SUPPORTED_VERSIONS,
+        "header": api_versions::API_VERSION_HEADER,
+        "deprecations": api_versions::DEPRECATIONS,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct DeepHealthQuery {
+    /// How long each leaf MCP may take to answer, e.g. `2s` (default 5 seconds)
+    timeout: Option<String>,
+}
+
+/// Probe every leaf MCP and count the connected agents
+async fn get_deep_health(
+    Extension(service): ServiceExtension,
+    Query(query): Query<DeepHealthQuery>,
+) -> Result<Json<health::DeepHealth>, MceptionError> {
+    let timeout = match query.timeout.as_deref() {
+        Some(timeout) => duration::parse_duration(timeout)
+            .and_then(|timeout| timeout.to_std().ok())
+            .ok_or_else(|| invalid(format!("Invalid timeout '{}'", timeout)))?
+            .min(health::MAX_PROBE_TIMEOUT),
+        None => health::DEFAULT_PROBE_TIMEOUT,
+    };
+    Ok(Json(health::deep(service, timeout).await))
+}
+
+/// Identity and revision of the running server, e.g. for `mception-server doctor`
+async fn get_server_status(Extension(service): ServiceExtension) -> Json<Value> {
     let config = service.get_configuration().await;
-    Ok(Json(serde_json::to_value(&config).unwrap_or_default()))
+    let (config_storage, audit_storage) = service.storage_locations();
+    let fault_injection = service.fault_injection_status().await;
+    let health_interval = service.health_interval();
+    Json(serde_json::json!({
+        "server_version": env!("CARGO_PKG_VERSION"),
+        "config_storage": config_storage,
+        "audit_storage": audit_storage,
+        "config_path": service.config_path(),
+        "schema_version": config.metadata.schema_version,
+        "revision": config.metadata.revision,
+        "leaf_mcps": config.leaf_mcps.len(),
+        "agents": config.agents.len(),
+        "health": {
+            "enabled": !health_interval.is_zero(),
+            "interval_secs": health_interval.as_secs(),
+            "leaf_mcps": service.health_statuses().all().await
+        },
+        "audit_clock_skew_events": service.lifecycle().clock_skew_events(),
+        "fault_injection": {
+            "enabled": fault_injection.is_some(),
+            "active": fault_injection.unwrap_or_default()
+        }
+    }))
 }
 
-async fn backup_server_config(
+async fn get_registration_policy(Extension(service): ServiceExtension) -> Json<Value> {
+    Json(serde_json::json!({ "policy": service.registration_policy().await }))
+}
+
+#[derive(Debug, Deserialize)]
+struct SetRegistrationPolicyRequest {
+    /// The new policy, or null to allow every leaf MCP again
+    policy: Option<RegistrationPolicy>,
+    reason: Option<String>,
+}
+
+/// Set the registration policy. Registered leaf MCPs it does not allow are
+/// kept and listed as violations.
+async fn set_registration_policy(
     Extension(service): ServiceExtension,
-) -> Result<Json<Value>, StatusCode> {
-    match service.backup_configuration().await {
-        Ok(backup_path) => Ok(Json(serde_json::json!({
-            "success": true,
-            "backup_path": backup_path,
-            "message": "Configuration backup created successfully"
-        }))),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    Extension(Actor(actor)): Extension<Actor>,
+    Json(request): Json<SetRegistrationPolicyRequest>,
+) -> Result<Json<Value>, MceptionError> {
+    let violations = service
+        .set_registration_policy(request.policy.clone(), Some(actor), request.reason)
+        .await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "policy": request.policy,
+        "violations": violations
+    })))
+}
+
+/// The registered leaf MCPs a policy would not allow, without setting it
+async fn report_registration_policy(
+    Extension(service): ServiceExtension,
+    Json(policy): Json<RegistrationPolicy>,
+) -> Result<Json<Value>, MceptionError> {
+    let violations = service.registration_policy_report(&policy).await?;
+    Ok(Json(serde_json::json!({ "violations": violations })))
+}
+
+async fn get_audit_policy(Extension(service): ServiceExtension) -> Json<Value> {
+    Json(serde_json::json!({
+        "policy": service.audit_policy().await,
+        "skipped_entries": service.skipped_audit_entries(),
+        "storage": service.audit_buffer().usage()
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct SetAuditPolicyRequest {
+    policy: AuditPolicy,
+    reason: Option<String>,
+}
+
+/// Set the per-actor audit rules and what happens while the audit storage
+/// fails. Deletions and the change itself are always audited.
+async fn set_audit_policy(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Json(request): Json<SetAuditPolicyRequest>,
+) -> Result<Json<Value>, MceptionError> {
+    service
+        .set_audit_policy(request.policy.clone(), Some(actor), request.reason)
+        .await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "policy": request.policy
+    })))
+}
+
+async fn get_confirmation_policy(Extension(service): ServiceExtension) -> Json<Value> {
+    Json(serde_json::json!({ "policy": service.confirmation_policy().await }))
+}
+
+#[derive(Debug, Deserialize)]
+struct SetConfirmationPolicyRequest {
+    policy: ConfirmationPolicy,
+    reason: Option<String>,
+}
+
+/// Set which dangerous actions need a confirmation token
+async fn set_confirmation_policy(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Json(request): Json<SetConfirmationPolicyRequest>,
+) -> Result<Json<Value>, MceptionError> {
+    service
+        .set_confirmation_policy(request.policy.clone(), Some(actor), request.reason)
+        .await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "policy": request.policy
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct SetLoggingRequest {
+    level: String,
+    filter: Option<String>,
+    /// Revert to the default directives after this long, e.g. `15m`
+    duration: Option<String>,
+    reason: Option<String>,
+}
+
+/// Runtime log control is not available in every setup, e.g. tests
+fn logging_error(e: MceptionError) -> ApiError {
+    match e {
+        MceptionError::Configuration(_) => error_with_status(StatusCode::NOT_IMPLEMENTED, e),
+        e => api_error(e),
+    }
+}
+
+async fn get_logging(Extension(service): ServiceExtension) -> Result<Json<Value>, ApiError> {
+    let settings = service.log_settings().await.map_err(logging_error)?;
+    Ok(Json(serde_json::to_value(settings).unwrap_or_default()))
+}
+
+async fn set_logging(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Json(request): Json<SetLoggingRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let revert_after = match request.duration.as_deref() {
+        Some(duration) => match duration::parse_duration(duration) {
+            Some(after) if after > chrono::Duration::zero() => Some(after),
+            _ => {
+                return Err(api_error(invalid(format!(
+                    "Invalid duration '{}'",
+                    duration
+                ))));
+            }
+        },
+        None => None,
+    };
+    let directives = logging::directives(&request.level, request.filter.as_deref());
+
+    let settings = service
+        .set_log_directives(&directives, revert_after, Some(actor), request.reason)
+        .await
+        .map_err(logging_error)?;
+
+    if let (Some(after), Some(session_id)) = (revert_after, settings.session_id.clone()) {
+        let revert_service = service.clone();
+        tokio::spawn(async move {
+            if let Ok(after) = after.to_std() {
+                tokio::time::sleep(after).await;
+            }
+            if let Err(e) = revert_service.revert_log_directives(&session_id).await {
+                error!("Error reverting log directives: {}", e);
+            }
+        });
+    }
+
+    Ok(Json(serde_json::to_value(settings).unwrap_or_default()))
+}
+
+/// Nodes and edges of the agent → MCP access graph. Bundle grants and bundle
+/// membership are separate edge kinds so they can be rendered distinctly.
+async fn get_config_graph(Extension(service): ServiceExtension) -> Json<Value> {
+    let config = service.get_configuration().await;
+
+    let mut nodes = Vec::new();
+    for id in config.leaf_mcps.keys() {
+        nodes.push(serde_json::json!({ "id": id, "kind": "leaf_mcp" }));
+    }
+    for id in config.agents.keys() {
+        nodes.push(serde_json::json!({ "id": id, "kind": "agent" }));
+    }
+    for bundle in config.bundles.values() {
+        nodes.push(serde_json::json!({
+            "id": format!("{}{}", BUNDLE_PREFIX, bundle.name),
+            "kind": "bundle",
+            "version": bundle.version
+        }));
+    }
+
+    let mut edges = Vec::new();
+    for (agent_id, agent) in &config.agents {
+        for mcp_id in &agent.allowed_mcp_ids {
+            let kind = if mcp_id.starts_with(BUNDLE_PREFIX) {
+                "bundle_grant"
+            } else {
+                "allowed_mcp"
+            };
+            edges.push(serde_json::json!({ "from": agent_id, "to": mcp_id, "kind": kind }));
+        }
+    }
+    for bundle in config.bundles.values() {
+        for member in &bundle.members {
+            edges.push(serde_json::json!({
+                "from": format!("{}{}", BUNDLE_PREFIX, bundle.name),
+                "to": member,
+                "kind": "bundle_member"
+            }));
+        }
     }
+
+    Json(serde_json::json!({
+        "nodes": nodes,
+        "edges": edges
+    }))
+}
+
+async fn get_server_config(Extension(service): ServiceExtension) -> Json<Value> {
+    let config = service.get_configuration().await;
+    Json(serde_json::to_value(config.redacted()).unwrap_or_default())
+}
+
+/// Revision, modification time and hash of the configuration, to notice
+/// changes without fetching it
+async fn get_config_revision(Extension(service): ServiceExtension) -> Json<ConfigRevision> {
+    Json(service.config_revision().await)
+}
+
+#[derive(Debug, Deserialize)]
+struct WatchRevisionQuery {
+    /// Revision the caller knows, the current one when omitted
+    since: Option<u64>,
+    /// How long to wait for a change, e.g. `60s` (default 30 seconds)
+    timeout: Option<String>,
+}
+
+/// Long poll answering once the revision differs from `since`, or with
+/// `changed: false` when the timeout elapsed first
+async fn watch_config_revision(
+    Extension(service): ServiceExtension,
+    Query(query): Query<WatchRevisionQuery>,
+) -> Result<Json<Value>, MceptionError> {
+    let timeout = match query.timeout.as_deref() {
+        Some(timeout) => duration::parse_duration(timeout)
+            .and_then(|timeout| timeout.to_std().ok())
+            .ok_or_else(|| invalid(format!("Invalid timeout '{}'", timeout)))?
+            .min(revision::MAX_WATCH_TIMEOUT),
+        None => revision::DEFAULT_WATCH_TIMEOUT,
+    };
+    let since = match query.since {
+        Some(since) => since,
+        None => service.revision().await,
+    };
+
+    let (current, changed) = service.watch_revision(since, timeout).await;
+    let mut body = serde_json::json!(current);
+    body["changed"] = Value::Bool(changed);
+    Ok(Json(body))
+}
+
+async fn backup_server_config(
+    Extension(service): ServiceExtension,
+) -> Result<Json<Value>, MceptionError> {
+    let backup_path = service.backup_configuration().await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "backup_path": backup_path,
+        "message": "Configuration backup created successfully"
+    })))
+}
+
+async fn list_config_backups(
+    Extension(service): ServiceExtension,
+    Query(query): Query<PageQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let backups = service.list_backups().await.map_err(api_error)?;
+    // Backups are only ever added or pruned, so their count serves as revision
+    let revision = backups.len() as u64;
+    let page = pagination::paginate(
+        "backups",
+        backups,
+        |backup| {
+            (
+                backup
+                    .created_at
+                    .to_rfc3339_opts(SecondsFormat::Nanos, true),
+                backup.name.clone(),
+            )
+        },
+        revision,
+        &query,
+    )
+    .map_err(page_error)?;
+    Ok(Json(serde_json::json!({
+        "backups": page.items,
+        "next_cursor": page.next_cursor
+    })))
+}
+
+async fn restore_config_backup(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Extension(api): Extension<ApiRequest>,
+    Path(backup_name): Path<String>,
+    Json(request): Json<RestoreBackupRequest>,
+) -> Result<Json<Value>, MceptionError> {
+    require_confirmation(&api, request.should_restore, "should_restore")?;
+
+    service
+        .restore_backup(&backup_name, Some(actor), request.reason)
+        .await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": format!("Configuration restored from backup '{}'", backup_name)
+    })))
+}
+
+/// Replace the configuration with a previously exported one, or merge it in
+async fn import_server_config(
+    Extension(service): ServiceExtension,
+    Extension(Actor(actor)): Extension<Actor>,
+    Extension(api): Extension<ApiRequest>,
+    Json(request): Json<ImportConfigRequest>,
+) -> Result<Json<Value>, MceptionError> {
+    require_confirmation(&api, request.should_restore, "should_restore")?;
+
+    let summary = service
+        .import_configuration(request.config, request.merge, Some(actor), request.reason)
+        .await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "summary": summary,
+        "message": format!(
+            "Configuration {}, previous configuration backed up as '{}'",
+            if summary.merge { "merged" } else { "replaced" },
+            summary.previous_backup
+        )
+    })))
 }
 
-async fn get_audit_logs(Extension(service): ServiceExtension) -> Result<Json<Value>, StatusCode> {
-    match service.get_audit_logs().await {
-        Ok(logs) => Ok(Json(serde_json::to_value(&logs).unwrap_or_default())),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+#[derive(Debug, Deserialize)]
+struct AsOfQuery {
+    at: DateTime<Utc>,
+}
+
+async fn historical_config(
+    service: &ConfigService,
+    at: DateTime<Utc>,
+) -> Result<HistoricalConfig, ApiError> {
+    match service.configuration_as_of(at).await {
+        Ok(mut historical) => {
+            historical.config = historical.config.redacted();
+            Ok(historical)
+        }
+        Err(e @ MceptionError::Storage(StorageError::NotFound(_))) => {
+            let earliest = service.history_start().await.ok();
+            let (status, Json(mut body)) = api_error(e);
+            body["earliest_available"] = serde_json::json!(earliest);
+            Err((status, Json(body)))
+        }
+        Err(e) => {
+            error!("Error reconstructing configuration as of {}: {}", at, e);
+            Err(api_error(e))
+        }
     }
 }
+
+async fn get_config_as_of(
+    Extension(service): ServiceExtension,
+    Query(query): Query<AsOfQuery>,
+) -> Result<Json<HistoricalConfig>, ApiError> {
+    Ok(Json(historical_config(&service, query.at).await?))
+}
+
+async fn get_leaf_mcp_as_of(
+    Extension(service): ServiceExtension,
+    Path(leaf_mcp_id): Path<String>,
+    Query(query): Query<AsOfQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let historical = historical_config(&service, query.at).await?;
+    let Some(leaf_mcp) = historical.config.leaf_mcps.get(&leaf_mcp_id) else {
+        return Err(api_error(
+            StorageError::NotFound(format!(
+                "Leaf MCP '{}' did not exist at {}",
+                leaf_mcp_id, query.at
+            ))
+            .into(),
+        ));
+    };
+
+    Ok(Json(serde_json::json!({
+        "as_of": historical.as_of,
+        "read_only": true,
+        "source": historical.source,
+        "leaf_mcp": leaf_mcp
+    })))
+}
+
+async fn get_agent_as_of(
+    Extension(service): ServiceExtension,
+    Path(agent_id): Path<String>,
+    Query(query): Query<AsOfQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let historical = historical_config(&service, query.at).await?;
+    let Some(agent) = historical.config.agents.get(&agent_id) else {
+        return Err(api_error(
+            StorageError::NotFound(format!(
+                "Agent '{}' did not exist at {}",
+                agent_id, query.at
+            ))
+            .into(),
+        ));
+    };
+
+    Ok(Json(serde_json::json!({
+        "as_of": historical.as_of,
+        "read_only": true,
+        "source": historical.source,
+        "agent": agent
+    })))
+}
+
+async fn get_audit_logs(
+    Extension(service): ServiceExtension,
+    Query(query): Query<PageQuery>,
+    Query(filter): Query<AuditQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let entries = service
+        .get_audit_logs_filtered(&filter)
+        .await
+        .map_err(api_error)?;
+    // The audit log is append-only, so the last sequence serves as revision
+    let revision = entries.last().map_or(0, |entry| entry.sequence);
+    // Sequences order entries even where the clock ran backwards
+    let page = pagination::paginate(
+        "audit",
+        entries,
+        |entry| (format!("{:020}", entry.sequence), entry.id.clone()),
+        revision,
+        &query,
+    )
+    .map_err(page_error)?;
+    Ok(Json(serde_json::json!({
+        "total": page.total,
+        "returned": page.items.len(),
+        "offset": page.offset,
+        "entries": page.items,
+        "next_cursor": page.next_cursor
+    })))
+}
+
+/// The hash chain of the audit log, with the first entry not linking to the
+/// one before
+async fn verify_audit_chain(Extension(service): ServiceExtension) -> Result<Json<Value>, ApiError> {
+    let report = service.verify_audit_chain().await.map_err(api_error)?;
+    let mut body = serde_json::to_value(&report).unwrap_or_default();
+    body["intact"] = Value::Bool(report.is_intact());
+    Ok(Json(body))
+}
@@ -1,20 +1,47 @@
 use axum::{
     Router,
-    extract::{Extension, Path},
-    http::StatusCode,
-    response::Json,
+    body::Bytes,
+    extract::{
+        Extension, Path, Query, RawQuery,
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code},
+    },
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header},
+    response::{
+        IntoResponse, Json, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
     routing::{any, get},
 };
-use serde_json::Value;
+use chrono::{DateTime, Utc};
+use futures_util::stream;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{BTreeMap, VecDeque};
+use std::convert::Infallible;
 use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
 
-use crate::services::ConfigService;
+use super::admin::page_error;
+use crate::core::ForwardingMessage;
+use crate::core::pagination::{self, PageQuery};
+use crate::services::agent_changes::AgentChange;
+use crate::services::config_events;
+use crate::services::connections::{AgentRequest, AgentResponse, DEFAULT_AGENT_TIMEOUT};
+use crate::services::deadline::{self, DEADLINE_HEADER};
+use crate::services::forwarding_error::{self, ForwardingError, ForwardingErrorCode};
+use crate::services::{ConfigService, ConnectionService, auth, https};
 
 type ServiceExtension = Extension<Arc<ConfigService>>;
 
+type ConnectionsExtension = Extension<Arc<ConnectionService>>;
+
 pub fn router() -> Router {
     Router::new()
         .route("/{agent_id}/config", get(get_agent_config))
+        .route("/{agent_id}/changes", get(get_agent_changes))
+        .route("/{agent_id}/changes/stream", get(stream_agent_changes))
+        .route("/{agent_id}/events", get(stream_agent_events))
         .route("/{agent_id}/forwarding", any(agent_forwarding))
         .route("/{agent_id}/forwarding_ws", any(agent_forwarding_ws))
 }
@@ -22,25 +49,451 @@ pub fn router() -> Router {
 async fn get_agent_config(
     Extension(service): ServiceExtension,
     Path(agent_id): Path<String>,
-) -> Result<Json<Value>, StatusCode> {
+    headers: HeaderMap,
+) -> Response {
+    if let Err(response) = authenticate(&service, &agent_id, &headers).await {
+        return response;
+    }
     match service.get_agent_remote_config(&agent_id).await {
-        Ok(config) => Ok(Json(config)),
-        Err(_) => Err(StatusCode::NOT_FOUND),
+        Ok(config) => {
+            service.mark_agent_seen(&agent_id).await;
+            Json(config).into_response()
+        }
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangesQuery {
+    /// Only changes at or after this time
+    since: Option<DateTime<Utc>>,
+}
+
+/// How often a change stream checks whether the server is shutting down
+const STREAM_DRAIN_CHECK: Duration = Duration::from_secs(1);
+
+/// The agent's own change feed, for its operators without admin access:
+/// grants, token rotations and updates of the MCPs it may use, paginated
+/// like `GET /admin/audit`
+async fn get_agent_changes(
+    Extension(service): ServiceExtension,
+    Path(agent_id): Path<String>,
+    Query(page): Query<PageQuery>,
+    Query(query): Query<ChangesQuery>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(response) = authenticate(&service, &agent_id, &headers).await {
+        return response;
+    }
+    let changes = match service.agent_changes(&agent_id, query.since).await {
+        Ok(changes) => changes,
+        Err(e) => return e.into_response(),
+    };
+    // Changes are only ever appended, like the audit log they come from
+    let revision = changes.last().map_or(0, |change| change.sequence);
+    match pagination::paginate(
+        "changes",
+        changes,
+        |change| (format!("{:020}", change.sequence), change.id.clone()),
+        revision,
+        &page,
+    ) {
+        Ok(page) => Json(json!({
+            "agent_id": agent_id,
+            "actors_masked": service.masks_change_actors(),
+            "total": page.total,
+            "returned": page.items.len(),
+            "offset": page.offset,
+            "changes": page.items,
+            "next_cursor": page.next_cursor
+        }))
+        .into_response(),
+        Err(e) => page_error(e).into_response(),
+    }
+}
+
+/// Where a change stream is at
+struct ChangeStream {
+    service: Arc<ConfigService>,
+    agent_id: String,
+    /// Derived but not yet sent
+    pending: VecDeque<AgentChange>,
+    /// Sequence of the last change sent
+    sent: u64,
+    /// Configuration revision the pending changes were derived at
+    revision: u64,
+}
+
+/// [`get_agent_changes`] as server-sent events: the changes since `since`,
+/// then each further change as it is committed. Ends when the agent is
+/// deleted or the server shuts down.
+async fn stream_agent_changes(
+    Extension(service): ServiceExtension,
+    Path(agent_id): Path<String>,
+    Query(query): Query<ChangesQuery>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(response) = authenticate(&service, &agent_id, &headers).await {
+        return response;
     }
+    // Read before the changes, so none committed in between are missed
+    let revision = service.revision().await;
+    let changes = match service.agent_changes(&agent_id, query.since).await {
+        Ok(changes) => changes,
+        Err(e) => return e.into_response(),
+    };
+    let state = ChangeStream {
+        service,
+        agent_id,
+        pending: changes.into(),
+        sent: 0,
+        revision,
+    };
+    let events = stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(change) = state.pending.pop_front() {
+                state.sent = change.sequence;
+                let event = Event::default()
+                    .event("change")
+                    .id(&change.id)
+                    .json_data(&change)
+                    .expect("changes serialize");
+                return Some((Ok::<_, Infallible>(event), state));
+            }
+            if state.service.lifecycle().is_draining() {
+                return None;
+            }
+            let (current, changed) = state
+                .service
+                .watch_revision(state.revision, STREAM_DRAIN_CHECK)
+                .await;
+            if !changed {
+                continue;
+            }
+            state.revision = current.revision;
+            // Gone with the agent
+            let changes = state
+                .service
+                .agent_changes(&state.agent_id, None)
+                .await
+                .ok()?;
+            let sent = state.sent;
+            state
+                .pending
+                .extend(changes.into_iter().filter(|change| change.sequence > sent));
+        }
+    });
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
 }
 
+/// `config_changed` events with the revision whenever one changes the agent's
+/// remote configuration, so it fetches it again instead of polling. Ends
+/// after the agent is deleted or when the server shuts down.
+async fn stream_agent_events(
+    Extension(service): ServiceExtension,
+    Path(agent_id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(response) = authenticate(&service, &agent_id, &headers).await {
+        return response;
+    }
+    // Subscribed before the check, so no change after it is missed
+    let receiver = service.subscribe_config_changes();
+    if !service
+        .get_configuration()
+        .await
+        .agents
+        .contains_key(&agent_id)
+    {
+        return error_response(StatusCode::NOT_FOUND, "Agent not found");
+    }
+    let events = stream::unfold(
+        (service, receiver, agent_id),
+        |(service, mut receiver, agent_id)| async move {
+            loop {
+                if service.lifecycle().is_draining()
+                    || !service
+                        .get_configuration()
+                        .await
+                        .agents
+                        .contains_key(&agent_id)
+                {
+                    return None;
+                }
+                let next = config_events::next_for(&service, &mut receiver, &agent_id);
+                let Ok(revision) = tokio::time::timeout(STREAM_DRAIN_CHECK, next).await else {
+                    continue;
+                };
+                let revision = revision?;
+                let event = Event::default()
+                    .event("config_changed")
+                    .id(revision.to_string())
+                    .json_data(json!({ "agent_id": agent_id, "revision": revision }))
+                    .expect("events serialize");
+                return Some((Ok::<_, Infallible>(event), (service, receiver, agent_id)));
+            }
+        },
+    );
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Refuse requests acting as an agent with a token that don't present it
+async fn authenticate(
+    service: &ConfigService,
+    agent_id: &str,
+    headers: &HeaderMap,
+) -> Result<(), Response> {
+    let config = service.get_configuration().await;
+    match config.agents.get(agent_id) {
+        Some(agent) if !auth::agent_authorized(agent, headers) => Err(auth::unauthorized(
+            &format!("A valid token of agent '{}' is required", agent_id),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn error_response(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(json!({ "error": message.into() }))).into_response()
+}
+
+/// Pass a request on to the agent over its WebSocket and relay the agent's
+/// response. Fails as `leaf_unreachable` with `503` if the agent is not
+/// connected or disconnects, and as `leaf_timeout` with `504` if it doesn't
+/// answer within its deadline.
 async fn agent_forwarding(
-    Extension(_service): ServiceExtension,
-    Path(_agent_id): Path<String>,
-) -> Result<Json<Value>, StatusCode> {
-    // TODO: Implement MCP query forwarding to agents via WebSocket
-    Err(StatusCode::NOT_IMPLEMENTED)
+    Extension(service): ServiceExtension,
+    Extension(connections): ConnectionsExtension,
+    Path(agent_id): Path<String>,
+    RawQuery(query): RawQuery,
+    mut headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let lifecycle = service.lifecycle();
+    let Some(_in_flight) = lifecycle.start_forward() else {
+        lifecycle.record_failure("shutting_down");
+        return ForwardingError::new(
+            ForwardingErrorCode::ProxyOverloaded,
+            &agent_id,
+            "Server is shutting down",
+        )
+        .into_response();
+    };
+
+    if !service
+        .get_configuration()
+        .await
+        .agents
+        .contains_key(&agent_id)
+    {
+        return ForwardingError::new(
+            ForwardingErrorCode::NotAllowed,
+            &agent_id,
+            "Agent not found",
+        )
+        .with_status(StatusCode::NOT_FOUND)
+        .into_response();
+    }
+    if let Err(e) = forwarding_error::check_caller(&service, &mut headers, &agent_id, &body).await {
+        lifecycle.record_failure(e.code.as_str());
+        return e.into_response();
+    }
+    let invalid = |detail: &str| {
+        ForwardingError::new(ForwardingErrorCode::InvalidRequest, &agent_id, detail).into_response()
+    };
+    let timeout = match deadline::from_headers(&headers) {
+        Ok(agent_deadline) => agent_deadline.map_or(DEFAULT_AGENT_TIMEOUT, |agent_deadline| {
+            agent_deadline.min(DEFAULT_AGENT_TIMEOUT)
+        }),
+        Err(e) => return invalid(&e),
+    };
+    let body = Some(body.to_vec()).filter(|body| !body.is_empty());
+
+    let request = AgentRequest {
+        url_params: query.unwrap_or_default(),
+        headers: request_headers(&headers),
+        body,
+    };
+    match connections.forward(&agent_id, request, timeout).await {
+        Ok(response) => relay(&agent_id, response),
+        Err(e) => {
+            let mut error = ForwardingError::from_error(e, &agent_id);
+            // The agent is there, just not connected right now
+            if error.code == ForwardingErrorCode::LeafUnreachable {
+                error = error.with_status(StatusCode::SERVICE_UNAVAILABLE);
+            }
+            lifecycle.record_failure(error.code.as_str());
+            error.into_response()
+        }
+    }
 }
 
+/// Incoming headers to pass on to the agent, without the connection-specific
+/// ones. The deadline is sent as the request's `deadline_ms` instead.
+fn request_headers(incoming: &HeaderMap) -> BTreeMap<String, String> {
+    incoming
+        .iter()
+        .filter(|(name, _)| {
+            !https::is_hop_by_hop(name)
+                && *name != header::HOST
+                && *name != header::CONTENT_LENGTH
+                && *name != DEADLINE_HEADER
+        })
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+/// The HTTP response for an agent's answer
+fn relay(agent_id: &str, response: AgentResponse) -> Response {
+    let Ok(status) = StatusCode::from_u16(response.status_code) else {
+        return ForwardingError::new(
+            ForwardingErrorCode::LeafError,
+            agent_id,
+            format!(
+                "Agent answered with invalid status {}",
+                response.status_code
+            ),
+        )
+        .with_status(StatusCode::BAD_GATEWAY)
+        .into_response();
+    };
+    let mut headers = HeaderMap::new();
+    for (name, value) in &response.headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::try_from(name.as_str()),
+            HeaderValue::try_from(value.as_str()),
+        ) && !https::is_hop_by_hop(&name)
+            && name != header::CONTENT_LENGTH
+        {
+            headers.insert(name, value);
+        }
+    }
+    (status, headers, response.body.unwrap_or_default()).into_response()
+}
+
+/// Accept an agent's WebSocket, through which forwarded requests are sent to
+/// it as [`ForwardingMessage::Request`] and answered with
+/// [`ForwardingMessage::Response`]. Changes of its remote configuration are
+/// pushed as [`ForwardingMessage::ConfigChanged`].
 async fn agent_forwarding_ws(
-    Extension(_service): ServiceExtension,
-    Path(_agent_id): Path<String>,
-) -> Result<Json<Value>, StatusCode> {
-    // TODO: Implement WebSocket connection for agent forwarding
-    Err(StatusCode::NOT_IMPLEMENTED)
+    Extension(service): ServiceExtension,
+    Extension(connections): ConnectionsExtension,
+    Path(agent_id): Path<String>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    if let Err(response) = authenticate(&service, &agent_id, &headers).await {
+        return response;
+    }
+    if !service
+        .get_configuration()
+        .await
+        .agents
+        .contains_key(&agent_id)
+    {
+        return error_response(StatusCode::NOT_FOUND, "Agent not found");
+    }
+    service.mark_agent_seen(&agent_id).await;
+    upgrade.on_upgrade(move |socket| serve_agent_socket(service, connections, agent_id, socket))
+}
+
+/// Reason of the close frame agents' WebSockets get when the server drains
+pub const SHUTDOWN_CLOSE_REASON: &str = "server shutting down";
+
+/// Pings in a row an agent's socket may leave unanswered before the agent is
+/// disconnected
+const MAX_MISSED_PONGS: u32 = 2;
+
+/// Relay messages between the registry and an agent's socket until either
+/// side closes it, the agent stops answering pings or the server drains. The
+/// agent is seen on every frame it sends, at most once a second.
+async fn serve_agent_socket(
+    service: Arc<ConfigService>,
+    connections: Arc<ConnectionService>,
+    agent_id: String,
+    mut socket: WebSocket,
+) {
+    let mut registration = connections.register(&agent_id);
+    let mut changes = service.subscribe_config_changes();
+    let ping_interval = service
+        .agent_ping_interval()
+        .to_std()
+        .unwrap_or(Duration::from_secs(1));
+    let mut pings =
+        tokio::time::interval_at(tokio::time::Instant::now() + ping_interval, ping_interval);
+    let mut missed_pongs = 0;
+    let mut last_seen = std::time::Instant::now();
+    let mut cause = "disconnected";
+    let drained = service.lifecycle().drained();
+    tokio::pin!(drained);
+    loop {
+        tokio::select! {
+            _ = &mut drained => {
+                let close = CloseFrame {
+                    code: close_code::AWAY,
+                    reason: SHUTDOWN_CLOSE_REASON.into(),
+                };
+                let _ = socket.send(Message::Close(Some(close))).await;
+                break;
+            }
+            _ = pings.tick() => {
+                if missed_pongs >= MAX_MISSED_PONGS {
+                    warn!(
+                        "Agent '{}' missed {} pings, disconnecting it",
+                        agent_id, missed_pongs
+                    );
+                    cause = "unresponsive";
+                    break;
+                }
+                missed_pongs += 1;
+                if socket.send(Message::Ping(Bytes::new())).await.is_err() {
+                    break;
+                }
+            }
+            changed = config_events::next_for(&service, &mut changes, &agent_id) => {
+                let Some(revision) = changed else { break };
+                let message = ForwardingMessage::ConfigChanged { revision };
+                let text = serde_json::to_string(&message).expect("forwarding messages serialize");
+                if socket.send(Message::Text(text.into())).await.is_err() {
+                    break;
+                }
+            }
+            outgoing = registration.outgoing.recv() => {
+                // Closed when the agent connected again on another socket
+                let Some(message) = outgoing else { break };
+                let text = serde_json::to_string(&message).expect("forwarding messages serialize");
+                if socket.send(Message::Text(text.into())).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => match incoming {
+                Some(Ok(message)) if !matches!(message, Message::Close(_)) => {
+                    // Any frame shows the agent is alive, not only pongs. Pings
+                    // are answered by axum.
+                    missed_pongs = 0;
+                    if last_seen.elapsed() >= Duration::from_secs(1) {
+                        last_seen = std::time::Instant::now();
+                        service.mark_agent_seen(&agent_id).await;
+                    }
+                    let Message::Text(text) = message else { continue };
+                    match serde_json::from_str::<ForwardingMessage>(&text) {
+                        Ok(message) => {
+                            connections.deliver(&agent_id, registration.connection_id, message)
+                        }
+                        Err(e) => warn!(
+                            "Agent '{}' sent an invalid forwarding message: {}",
+                            agent_id, e
+                        ),
+                    }
+                }
+                Some(Ok(_)) | Some(Err(_)) | None => break,
+            },
+        }
+    }
+    if connections.unregister(&agent_id, registration.connection_id) {
+        service.mark_agent_disconnected(&agent_id, cause).await;
+    }
 }
@@ -0,0 +1,34 @@
+use axum::{
+    Json, Router,
+    extract::Extension,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use std::sync::Arc;
+
+use crate::services::{ConfigService, health};
+
+/// Unauthenticated probes, e.g. for Kubernetes
+pub fn router() -> Router {
+    Router::new()
+        .route("/health", get(liveness))
+        .route("/ready", get(readiness))
+}
+
+/// 200 while the process serves requests at all
+async fn liveness(Extension(service): Extension<Arc<ConfigService>>) -> Json<serde_json::Value> {
+    Json(health::liveness(&service))
+}
+
+/// Like `/health`, but 503 while the configuration is loading and once the
+/// server is draining, so no requests are routed to it then
+async fn readiness(Extension(service): Extension<Arc<ConfigService>>) -> Response {
+    let lifecycle = service.lifecycle();
+    let status = if lifecycle.is_loading() || lifecycle.is_draining() {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    (status, Json(health::liveness(&service))).into_response()
+}
@@ -1,14 +1,24 @@
 use axum::{
     Router,
+    body::Bytes,
     extract::{Extension, Path},
-    http::StatusCode,
-    response::Json,
+    http::{HeaderMap, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
     routing::any,
 };
+use chrono::Utc;
 use serde_json::Value;
 use std::sync::Arc;
 
-use crate::services::ConfigService;
+use crate::core::{LeafMcpConfig, McpTransport, ValidationError};
+use crate::services::access_log::AccessEntry;
+use crate::services::circuit_breaker::Outcome;
+use crate::services::deadline::{self, Deadline};
+use crate::services::debug_capture::CaptureDirection;
+use crate::services::fault_injection;
+use crate::services::forwarding_error::{self, ForwardingError, ForwardingErrorCode};
+use crate::services::https::{self, Forwarded};
+use crate::services::{ConfigService, builtin_mcp, replicas, retry};
 
 type ServiceExtension = Extension<Arc<ConfigService>>;
 
@@ -17,10 +27,324 @@ pub fn router() -> Router {
 }
 
 async fn leaf_mcp_forwarding(
-    Extension(_service): ServiceExtension,
-    Path(_leaf_mcp_id): Path<String>,
-) -> Result<Json<Value>, StatusCode> {
-    // TODO: Implement MCP query forwarding to leaf MCPs
-    // This should forward requests to the actual MCP server (STDIO or HTTPS)
-    Err(StatusCode::NOT_IMPLEMENTED)
+    Extension(service): ServiceExtension,
+    Path(leaf_mcp_id): Path<String>,
+    method: Method,
+    mut headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let lifecycle = service.lifecycle();
+    let Some(_in_flight) = lifecycle.start_forward() else {
+        lifecycle.record_failure("shutting_down");
+        return ForwardingError::new(
+            ForwardingErrorCode::ProxyOverloaded,
+            &leaf_mcp_id,
+            "Server is shutting down",
+        )
+        .into_response();
+    };
+
+    let agent_deadline = match deadline::from_headers(&headers) {
+        Ok(agent_deadline) => agent_deadline,
+        Err(e) => {
+            return ForwardingError::new(ForwardingErrorCode::InvalidRequest, &leaf_mcp_id, e)
+                .into_response();
+        }
+    };
+    let caller =
+        match forwarding_error::check_caller(&service, &mut headers, &leaf_mcp_id, &body).await {
+            Ok(caller) => caller,
+            Err(e) => {
+                lifecycle.record_failure(e.code.as_str());
+                return e.into_response();
+            }
+        };
+    // Calls to a replicated leaf MCP go to the replica picked for the caller
+    let requested = leaf_mcp_id;
+    let leaf_mcp_id = service.select_replica(&requested, caller.as_deref()).await;
+    let leaf = service
+        .get_configuration()
+        .await
+        .leaf_mcps
+        .get(&leaf_mcp_id)
+        .cloned();
+    let leaf_timeout = leaf
+        .as_ref()
+        .map_or(service.default_leaf_timeout(), |leaf| {
+            deadline::leaf_timeout(leaf, service.default_leaf_timeout())
+        });
+    let deadline = Deadline::new(agent_deadline, leaf_timeout);
+
+    let captures = service.debug_captures();
+    captures
+        .record(&leaf_mcp_id, CaptureDirection::Request, None, &body)
+        .await;
+
+    let rpc_method = serde_json::from_slice::<Value>(&body)
+        .ok()
+        .and_then(|message| message["method"].as_str().map(str::to_string));
+
+    // Injected faults apply before the real transport, within the deadline
+    let fault = service.next_fault(&leaf_mcp_id).await;
+    let call = async {
+        // A leaf MCP that keeps failing is not waited for until its cooldown passed
+        if let Err(retry_in) = service.admit_forward(&leaf_mcp_id).await {
+            return Err(ForwardingError::new(
+                ForwardingErrorCode::CircuitOpen,
+                &leaf_mcp_id,
+                "Circuit breaker is open after repeated failures",
+            )
+            .with("retry_in_ms", serde_json::json!(retry_in.as_millis())));
+        }
+        if let Some(fault) = fault {
+            tokio::time::sleep(fault.latency).await;
+            if fault.fail {
+                return Err(ForwardingError::new(
+                    ForwardingErrorCode::LeafUnreachable,
+                    &leaf_mcp_id,
+                    "Injected fault",
+                )
+                .with_status(StatusCode::SERVICE_UNAVAILABLE)
+                .with("injected", true));
+            }
+        }
+        let Some(leaf) = &leaf else {
+            return Err(ForwardingError::new(
+                ForwardingErrorCode::NotAllowed,
+                &leaf_mcp_id,
+                format!("Leaf MCP '{}' not found", leaf_mcp_id),
+            )
+            .with_status(StatusCode::NOT_FOUND));
+        };
+        // Transient failures are retried as the leaf MCP's policy allows
+        retry::retried(
+            leaf.retry.as_ref(),
+            &leaf_mcp_id,
+            rpc_method.as_deref(),
+            || {
+                forward(
+                    &service,
+                    &leaf_mcp_id,
+                    leaf,
+                    &deadline,
+                    method.clone(),
+                    &headers,
+                    body.clone(),
+                )
+            },
+        )
+        .await
+    };
+
+    // Calls beyond the leaf MCP's concurrency limit wait for a slot within
+    // the deadline, and are refused once it passes
+    let slot = match &leaf {
+        Some(leaf) => {
+            service
+                .concurrency_limits()
+                .acquire(&leaf_mcp_id, leaf, deadline.remaining())
+                .await
+        }
+        None => Ok(None),
+    };
+    // The leaf call is dropped, and thereby cancelled, once the deadline passes
+    let result: Result<Forwarded, ForwardingError> = match slot {
+        Ok(_slot) => match deadline.run(call).await {
+            Ok(result) => result,
+            Err(exceeded) => Err(ForwardingError::new(
+                ForwardingErrorCode::LeafTimeout,
+                &leaf_mcp_id,
+                "Deadline exceeded",
+            )
+            .with("bound", serde_json::json!(exceeded.bound))
+            .with("budget_ms", serde_json::json!(exceeded.budget_ms))),
+        },
+        Err(limit) => {
+            // Never started, done with what it borrows
+            drop(call);
+            Err(ForwardingError::new(
+                ForwardingErrorCode::ProxyOverloaded,
+                &leaf_mcp_id,
+                format!("{} requests to the leaf MCP are already in flight", limit),
+            )
+            .with_status(StatusCode::TOO_MANY_REQUESTS)
+            .with("max_concurrent_requests", limit))
+        }
+    };
+
+    let health = service.replica_health();
+    let (status, response_body) = match &result {
+        Ok(forwarded) => {
+            health.record_success(&leaf_mcp_id).await;
+            service.record_forward(&leaf_mcp_id, Outcome::Success).await;
+            (forwarded.status, forwarded.body.clone())
+        }
+        Err(e) => {
+            if matches!(
+                e.code,
+                ForwardingErrorCode::LeafUnreachable | ForwardingErrorCode::LeafTimeout
+            ) {
+                health.record_failure(&leaf_mcp_id).await;
+                service.record_forward(&leaf_mcp_id, Outcome::Failure).await;
+            } else if !matches!(
+                e.code,
+                // Refused before the breaker admitted them
+                ForwardingErrorCode::CircuitOpen | ForwardingErrorCode::ProxyOverloaded
+            ) {
+                service
+                    .record_forward(&leaf_mcp_id, Outcome::Inconclusive)
+                    .await;
+            }
+            lifecycle.record_failure(failure_cause(e));
+            (e.status, Bytes::from(e.body().to_string()))
+        }
+    };
+    captures
+        .record(
+            &leaf_mcp_id,
+            CaptureDirection::Response,
+            Some(status.as_u16()),
+            &response_body,
+        )
+        .await;
+    service
+        .record_access(AccessEntry {
+            at: Utc::now(),
+            mcp: leaf_mcp_id.clone(),
+            agent: caller,
+            method: rpc_method,
+            status: status.as_u16(),
+        })
+        .await;
+
+    let mut response = match result {
+        Ok(forwarded) => (forwarded.status, forwarded.headers, forwarded.body).into_response(),
+        Err(error) => error.into_response(),
+    };
+    if let Some(fault) = fault {
+        response.headers_mut().insert(
+            fault_injection::FAULT_HEADER,
+            HeaderValue::from_static(fault.header_value()),
+        );
+    }
+    if leaf_mcp_id != requested
+        && let Ok(replica) = HeaderValue::from_str(&leaf_mcp_id)
+    {
+        response
+            .headers_mut()
+            .insert(replicas::REPLICA_HEADER, replica);
+    }
+    response
+}
+
+/// Cause of a failed forward, for the shutdown report
+fn failure_cause(error: &ForwardingError) -> &'static str {
+    if error.context.get("injected") == Some(&Value::Bool(true)) {
+        return "injected_fault";
+    }
+    error.code.as_str()
+}
+
+/// Forward a request to the leaf MCP. HTTPS leaves receive the request as is,
+/// along with the remaining budget as `deadline::DEADLINE_HEADER`, and their
+/// response is relayed as is. Leaves that don't answer in time are cut off by
+/// the caller's deadline.
+async fn forward(
+    service: &ConfigService,
+    leaf_mcp_id: &str,
+    leaf: &LeafMcpConfig,
+    deadline: &Deadline,
+    method: Method,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Result<Forwarded, ForwardingError> {
+    let message = || {
+        serde_json::from_slice::<Value>(&body).map_err(|e| {
+            ForwardingError::from_error(
+                ValidationError::InvalidFormat(e.to_string()).into(),
+                leaf_mcp_id,
+            )
+        })
+    };
+
+    let (message, mut forwarded) = match &leaf.transport {
+        // Notifications have no response
+        McpTransport::Builtin { kind } => {
+            let message = message()?;
+            let response = builtin_mcp::handle(*kind, &message)
+                .await
+                .unwrap_or(Value::Null);
+            (Some(message), Forwarded::json(&response))
+        }
+        McpTransport::Stdio { .. } => {
+            let message = message()?;
+            let response = service
+                .stdio_processes()
+                .forward(leaf_mcp_id, leaf, &message)
+                .await
+                .map_err(|e| ForwardingError::from_error(e, leaf_mcp_id))?;
+            (
+                Some(message),
+                Forwarded::json(&response.unwrap_or(Value::Null)),
+            )
+        }
+        McpTransport::Sse { url, .. } => {
+            let message = message()?;
+            let response = service
+                .sse_connections()
+                .forward(leaf_mcp_id, leaf, &message)
+                .await
+                .map_err(|e| {
+                    ForwardingError::from_error(e, leaf_mcp_id)
+                        .with("upstream", https::redact_url(url))
+                })?;
+            (
+                Some(message),
+                Forwarded::json(&response.unwrap_or(Value::Null)),
+            )
+        }
+        McpTransport::Https {
+            url,
+            headers: configured,
+        } => {
+            let forwarded = service
+                .https_forwarder()
+                .forward(
+                    url,
+                    configured.as_ref(),
+                    method,
+                    &https::passed_through(headers, leaf.header_policy.as_ref()),
+                    &deadline.header_value(),
+                    body.clone(),
+                )
+                .await
+                .map_err(|e| {
+                    // Named without its credentials
+                    ForwardingError::from_error(e, leaf_mcp_id)
+                        .with("upstream", https::redact_url(url))
+                })?;
+            (message().ok(), forwarded)
+        }
+    };
+
+    if let Some(message) = message
+        && message["method"] == "initialize"
+        && let Some(instructions) = &leaf.instructions
+        && let Some(mut response) = forwarded.json_body()
+        && let Some(result) = response.get_mut("result")
+    {
+        add_instructions(result, instructions);
+        forwarded.set_json_body(&response);
+    }
+    Ok(forwarded)
+}
+
+/// Put the configured instructions into an `initialize` result, ahead of the
+/// leaf MCP's own, so the agent's model sees them
+fn add_instructions(result: &mut Value, instructions: &str) {
+    let combined = match result["instructions"].as_str() {
+        Some(own) if !own.is_empty() => format!("{}\n\n{}", instructions, own),
+        _ => instructions.to_string(),
+    };
+    result["instructions"] = Value::String(combined);
 }
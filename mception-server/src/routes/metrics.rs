@@ -0,0 +1,27 @@
+use axum::{
+    Router,
+    extract::Extension,
+    http::header,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use std::sync::Arc;
+
+use crate::services::{ConfigService, ConnectionService};
+
+pub fn router() -> Router {
+    Router::new().route("/metrics", get(metrics))
+}
+
+/// Usage of the in-memory structures in the Prometheus text format
+async fn metrics(
+    Extension(service): Extension<Arc<ConfigService>>,
+    Extension(connections): Extension<Arc<ConnectionService>>,
+) -> Response {
+    let internals = service.internals(&connections).await;
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        internals.metrics(),
+    )
+        .into_response()
+}
@@ -1,3 +1,7 @@
 pub mod admin;
 pub mod agent;
+pub mod health;
 pub mod leaf;
+pub mod metrics;
+#[cfg(feature = "admin-ui")]
+pub mod ui;
@@ -0,0 +1,88 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// One request forwarded to a leaf MCP, a JSON line of the access log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessEntry {
+    pub at: DateTime<Utc>,
+    /// The leaf MCP that served it, a replica rather than the one asked for
+    pub mcp: String,
+    /// The calling agent, if it identified itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+    /// JSON-RPC method, if the body was a JSON-RPC request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    pub status: u16,
+}
+
+/// Calls of one caller to a leaf MCP
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallerUsage {
+    pub calls: u64,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Append-only log of the requests forwarded to leaf MCPs, enabled with
+/// `--access-log`
+#[derive(Debug)]
+pub struct AccessLog {
+    path: PathBuf,
+    /// Keeps appended lines whole
+    writing: Mutex<()>,
+}
+
+impl AccessLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            writing: Mutex::new(()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub async fn record(&self, entry: &AccessEntry) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let _writing = self.writing.lock().await;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(&line).await
+    }
+
+    /// Calls to `mcp` since `since` per calling agent, `None` for calls of
+    /// unidentified callers. A missing log has no calls, lines that don't
+    /// parse are skipped.
+    pub async fn usage(
+        &self,
+        mcp: &str,
+        since: DateTime<Utc>,
+    ) -> std::io::Result<BTreeMap<Option<String>, CallerUsage>> {
+        let content = match tokio::fs::read_to_string(&self.path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let mut usage: BTreeMap<Option<String>, CallerUsage> = BTreeMap::new();
+        for entry in content
+            .lines()
+            .filter_map(|line| serde_json::from_str::<AccessEntry>(line).ok())
+            .filter(|entry| entry.mcp == mcp && entry.at >= since)
+        {
+            let caller = usage.entry(entry.agent).or_default();
+            caller.calls += 1;
+            caller.last_used_at = caller.last_used_at.max(Some(entry.at));
+        }
+        Ok(usage)
+    }
+}
@@ -0,0 +1,221 @@
+use crate::core::{AuditAction, AuditLogEntry, AuditTarget, BUNDLE_PREFIX, ServerConfig};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+/// What changed for an agent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    GrantAdded,
+    GrantRemoved,
+    /// An MCP the agent was allowed to use was updated
+    McpUpdated,
+    /// The agent's own name, description or settings were updated
+    AgentUpdated,
+    TokenRotated,
+    TokenRemoved,
+    /// The tools the agent may call of an MCP were set or reset
+    ToolFilterChanged,
+}
+
+/// One entry of an agent's change feed, `GET /agent/<id>/changes`
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentChange {
+    /// `<audit entry id>-<n>`, an audit entry can make several changes
+    pub id: String,
+    /// Sequence of the audit entry the change was derived from
+    pub sequence: u64,
+    pub at: DateTime<Utc>,
+    pub kind: ChangeKind,
+    /// The MCP granted, revoked or updated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mcp_id: Option<String>,
+    /// Paths of the updated fields, e.g. `transport.url`, never their values
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+    pub reason: Option<String>,
+    /// `None` when actors are masked
+    pub actor: Option<String>,
+}
+
+/// The changes to `agent_id` in the audit log `entries`, in sequence order.
+/// Grants are replayed from the agent's creation on to tell which MCPs it
+/// was allowed to use at each point; MCPs in bundles are judged by the
+/// bundles' current members. Only changes at or after `since` are returned.
+pub fn derive(
+    entries: &[AuditLogEntry],
+    agent_id: &str,
+    config: &ServerConfig,
+    since: Option<DateTime<Utc>>,
+    mask_actors: bool,
+) -> Vec<AgentChange> {
+    let mut allowed: Vec<String> = Vec::new();
+    let mut changes = Vec::new();
+    for entry in entries {
+        let mut derived: Vec<(ChangeKind, Option<String>, Vec<String>)> = Vec::new();
+        match (&entry.action, &entry.target) {
+            (AuditAction::Create, AuditTarget::Agent { id }) if id == agent_id => {
+                allowed = mcp_ids(entry.details.get("allowed_mcp_ids"));
+                for mcp_id in &allowed {
+                    derived.push(grant(ChangeKind::GrantAdded, mcp_id));
+                }
+            }
+            (AuditAction::Delete, AuditTarget::Agent { id }) if id == agent_id => {
+                allowed.clear();
+            }
+            (AuditAction::Update, AuditTarget::Agent { id }) if id == agent_id => {
+                let Some(updates) = entry.details.as_object() else {
+                    continue;
+                };
+                if updates.contains_key("allowed_mcp_ids") {
+                    let updated = mcp_ids(updates.get("allowed_mcp_ids"));
+                    for mcp_id in updated.iter().filter(|id| !allowed.contains(id)) {
+                        derived.push(grant(ChangeKind::GrantAdded, mcp_id));
+                    }
+                    for mcp_id in allowed.iter().filter(|id| !updated.contains(id)) {
+                        derived.push(grant(ChangeKind::GrantRemoved, mcp_id));
+                    }
+                    allowed = updated;
+                }
+                match updates.get("auth_token") {
+                    Some(Value::Null) => derived.push((ChangeKind::TokenRemoved, None, vec![])),
+                    Some(_) => derived.push((ChangeKind::TokenRotated, None, vec![])),
+                    None => {}
+                }
+                let mut fields = Vec::new();
+                for (field, value) in updates {
+                    if field != "allowed_mcp_ids" && field != "auth_token" {
+                        field_paths(field, value, &mut fields);
+                    }
+                }
+                if !fields.is_empty() {
+                    derived.push((ChangeKind::AgentUpdated, None, fields));
+                }
+            }
+            (
+                AuditAction::AddAllowedMcp,
+                AuditTarget::AgentAllowedMcp {
+                    agent_id: id,
+                    mcp_id,
+                },
+                // Another source of an existing grant changes nothing
+            ) if id == agent_id && !allowed.contains(mcp_id) => {
+                allowed.push(mcp_id.clone());
+                derived.push(grant(ChangeKind::GrantAdded, mcp_id));
+            }
+            // Grants of every leaf MCP matching a label selector
+            (AuditAction::AddAllowedMcp, AuditTarget::Agent { id }) if id == agent_id => {
+                for mcp_id in mcp_ids(entry.details.get("mcp_ids")) {
+                    if !allowed.contains(&mcp_id) {
+                        derived.push(grant(ChangeKind::GrantAdded, &mcp_id));
+                        allowed.push(mcp_id);
+                    }
+                }
+            }
+            (
+                AuditAction::RemoveAllowedMcp,
+                AuditTarget::AgentAllowedMcp {
+                    agent_id: id,
+                    mcp_id,
+                },
+                // Nor does removing a source when others remain
+            ) if id == agent_id && !still_granted(entry) => {
+                allowed.retain(|allowed| allowed != mcp_id);
+                derived.push(grant(ChangeKind::GrantRemoved, mcp_id));
+            }
+            (
+                AuditAction::Update | AuditAction::Delete,
+                AuditTarget::AgentToolFilter {
+                    agent_id: id,
+                    mcp_id,
+                },
+            ) if id == agent_id => {
+                derived.push(grant(ChangeKind::ToolFilterChanged, mcp_id));
+            }
+            (AuditAction::Update, AuditTarget::LeafMcp { id }) if grants(config, &allowed, id) => {
+                let mut fields = Vec::new();
+                if let Some(updates) = entry.details.as_object() {
+                    for (field, value) in updates {
+                        field_paths(field, value, &mut fields);
+                    }
+                }
+                derived.push((ChangeKind::McpUpdated, Some(id.clone()), fields));
+            }
+            // Deleting a leaf MCP takes it out of every agent's grants
+            (AuditAction::Delete, AuditTarget::LeafMcp { id }) if allowed.contains(id) => {
+                allowed.retain(|allowed| allowed != id);
+                derived.push(grant(ChangeKind::GrantRemoved, id));
+            }
+            _ => {}
+        }
+
+        if since.is_some_and(|since| entry.timestamp < since) {
+            continue;
+        }
+        for (at, (kind, mcp_id, fields)) in derived.into_iter().enumerate() {
+            changes.push(AgentChange {
+                id: format!("{}-{}", entry.id, at),
+                sequence: entry.sequence,
+                at: entry.timestamp,
+                kind,
+                mcp_id,
+                fields,
+                reason: entry.reason.clone(),
+                actor: if mask_actors {
+                    None
+                } else {
+                    entry.actor.clone()
+                },
+            });
+        }
+    }
+    changes
+}
+
+fn grant(kind: ChangeKind, mcp_id: &str) -> (ChangeKind, Option<String>, Vec<String>) {
+    (kind, Some(mcp_id.to_string()), Vec::new())
+}
+
+fn mcp_ids(value: Option<&Value>) -> Vec<String> {
+    value
+        .and_then(Value::as_array)
+        .map(|ids| {
+            ids.iter()
+                .filter_map(|id| Some(id.as_str()?.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Whether `allowed` grants `mcp_id`, directly or through a bundle
+fn grants(config: &ServerConfig, allowed: &[String], mcp_id: &str) -> bool {
+    allowed
+        .iter()
+        .any(|allowed| match allowed.strip_prefix(BUNDLE_PREFIX) {
+            Some(bundle) => config
+                .bundles
+                .get(bundle)
+                .is_some_and(|bundle| bundle.members.iter().any(|member| member == mcp_id)),
+            None => allowed == mcp_id,
+        })
+}
+
+/// The paths to the leaves of a merge patch, down through objects
+fn field_paths(path: &str, value: &Value, fields: &mut Vec<String>) {
+    match value.as_object() {
+        Some(object) if !object.is_empty() => {
+            for (field, value) in object {
+                field_paths(&format!("{}.{}", path, field), value, fields);
+            }
+        }
+        _ => fields.push(path.to_string()),
+    }
+}
+
+/// Whether a removed grant source left others granting the MCP
+fn still_granted(entry: &AuditLogEntry) -> bool {
+    entry.details["remaining_sources"]
+        .as_array()
+        .is_some_and(|sources| !sources.is_empty())
+}
@@ -0,0 +1,194 @@
+use crate::core::error_body;
+use crate::services::ConfigService;
+use crate::services::auth::{Actor, DEFAULT_ADMIN_ACTOR};
+use axum::extract::{Request, State};
+use axum::http::{HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Json, Response};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// Request header naming the version of the admin API a client is written
+/// against, and response header naming the version a request was served with
+pub const API_VERSION_HEADER: &str = "x-mception-api-version";
+
+/// Versions of the admin API this server serves, oldest first
+pub const SUPPORTED_VERSIONS: &[u32] = &[1, 2];
+
+/// Version served to requests without [`API_VERSION_HEADER`], unless the
+/// server pins another one with `--api-compat`
+pub const CURRENT_VERSION: u32 = 2;
+
+/// A request field or endpoint that is going away. Requests using it are
+/// answered with `Deprecation` and `Sunset` headers.
+#[derive(Debug, Serialize)]
+pub struct Deprecation {
+    pub id: &'static str,
+    pub description: &'static str,
+    /// First version of the API that does without it
+    pub replaced_in: u32,
+    /// RFC 3339
+    pub deprecated_at: &'static str,
+    /// When it stops being accepted, RFC 3339
+    pub sunset_at: &'static str,
+}
+
+impl Deprecation {
+    fn deprecated_at(&self) -> DateTime<Utc> {
+        self.deprecated_at.parse().unwrap_or_default()
+    }
+
+    fn sunset_at(&self) -> DateTime<Utc> {
+        self.sunset_at.parse().unwrap_or_default()
+    }
+}
+
+/// The `should_*` flags of admin requests
+pub const CONFIRMATION_FLAGS: Deprecation = Deprecation {
+    id: "confirmation_flags",
+    description: "`should_*` flags confirming admin requests. From version 2 on a request \
+                  confirms itself by being sent; a flag sent anyway must still be true.",
+    replaced_in: 2,
+    deprecated_at: "2026-10-15T00:00:00Z",
+    sunset_at: "2027-04-15T00:00:00Z",
+};
+
+/// Every deprecation of the admin API
+pub const DEPRECATIONS: &[&Deprecation] = &[&CONFIRMATION_FLAGS];
+
+/// Parse a supported API version, for `--api-compat`
+pub fn parse_version(value: &str) -> Result<u32, String> {
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|version| SUPPORTED_VERSIONS.contains(version))
+        .ok_or_else(|| {
+            format!(
+                "unsupported API version '{}', supported are {}",
+                value,
+                supported_list()
+            )
+        })
+}
+
+fn supported_list() -> String {
+    SUPPORTED_VERSIONS
+        .iter()
+        .map(u32::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Version served by default and the deprecations each actor was warned about
+#[derive(Debug)]
+pub struct ApiVersioning {
+    default_version: u32,
+    warned: Mutex<HashSet<(String, &'static str)>>,
+}
+
+impl ApiVersioning {
+    pub fn new(default_version: u32) -> Self {
+        Self {
+            default_version,
+            warned: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Whether this is the first time `actor` used `deprecation`
+    fn first_use(&self, actor: &str, deprecation: &'static Deprecation) -> bool {
+        self.warned
+            .lock()
+            .unwrap()
+            .insert((actor.to_string(), deprecation.id))
+    }
+}
+
+/// The version an admin request is served with, attached by [`negotiate`].
+/// Handlers report the deprecated features a request uses on it.
+#[derive(Debug, Clone)]
+pub struct ApiRequest {
+    pub version: u32,
+    /// Version served to requests without [`API_VERSION_HEADER`]
+    pub default_version: u32,
+    used: Arc<Mutex<Vec<&'static Deprecation>>>,
+}
+
+impl ApiRequest {
+    pub fn use_deprecated(&self, deprecation: &'static Deprecation) {
+        let mut used = self.used.lock().unwrap();
+        if !used.iter().any(|used| used.id == deprecation.id) {
+            used.push(deprecation);
+        }
+    }
+}
+
+/// Middleware serving admin requests with the version they ask for in
+/// [`API_VERSION_HEADER`], refusing unsupported ones with 400. Responses to
+/// requests using deprecated features get `Deprecation` and `Sunset` headers,
+/// and the first such request of each actor is logged and audited.
+pub async fn negotiate(
+    State(versioning): State<Arc<ApiVersioning>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let version = match request.headers().get(API_VERSION_HEADER) {
+        None => versioning.default_version,
+        Some(value) => match parse_version(value.to_str().unwrap_or_default()) {
+            Ok(version) => version,
+            Err(message) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(error_body("unsupported_api_version", message)),
+                )
+                    .into_response();
+            }
+        },
+    };
+    let api = ApiRequest {
+        version,
+        default_version: versioning.default_version,
+        used: Arc::default(),
+    };
+    request.extensions_mut().insert(api.clone());
+    let actor = request
+        .extensions()
+        .get::<Actor>()
+        .map_or(DEFAULT_ADMIN_ACTOR.to_string(), |actor| actor.0.clone());
+    let service = request.extensions().get::<Arc<ConfigService>>().cloned();
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert(API_VERSION_HEADER, HeaderValue::from(version));
+
+    let used = api.used.lock().unwrap().clone();
+    if let Some(deprecated_at) = used.iter().map(|d| d.deprecated_at()).min() {
+        let sunset_at = used.iter().map(|d| d.sunset_at()).min().unwrap_or_default();
+        if let Ok(value) = HeaderValue::from_str(&format!("@{}", deprecated_at.timestamp())) {
+            headers.insert("deprecation", value);
+        }
+        if let Ok(value) =
+            HeaderValue::from_str(&sunset_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        {
+            headers.insert("sunset", value);
+        }
+    }
+    for deprecation in used {
+        if !versioning.first_use(&actor, deprecation) {
+            continue;
+        }
+        warn!(
+            "Admin API client of '{}' uses {} (deprecated, removed after {})",
+            actor, deprecation.id, deprecation.sunset_at
+        );
+        if let Some(service) = &service {
+            service
+                .audit_deprecated_use(&actor, deprecation.id, version)
+                .await;
+        }
+    }
+    response
+}
@@ -0,0 +1,171 @@
+use crate::core::{AuditLogEntry, MceptionResult};
+use crate::services::ConfigService;
+use crate::storage::providers::AuditStorage;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::warn;
+
+/// Default number of audit entries held back while the audit storage fails
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// How often held back entries are retried
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// State of the audit storage as seen by the writes to it
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditBufferUsage {
+    /// The last write to the audit storage failed, or entries are still held back
+    pub degraded: bool,
+    /// Entries held back, waiting for the audit storage to recover
+    pub buffered: usize,
+    pub capacity: usize,
+    /// Entries held back since the start, including ones written since
+    pub buffered_total: u64,
+    /// Entries lost since the start, under the `drop` policy or beyond the
+    /// buffer's capacity
+    pub dropped_total: u64,
+}
+
+/// Audit entries held back under the `buffer` failure policy, in the order
+/// they were appended, and what was lost while the audit storage failed
+#[derive(Debug)]
+pub struct AuditBuffer {
+    entries: Mutex<VecDeque<AuditLogEntry>>,
+    capacity: usize,
+    /// Held while writing held back entries, so they keep their order
+    flushing: tokio::sync::Mutex<()>,
+    degraded: AtomicBool,
+    buffered: AtomicU64,
+    dropped: AtomicU64,
+    /// Entries lost since the loss was last recorded in the audit log
+    unrecorded_losses: AtomicU64,
+}
+
+impl Default for AuditBuffer {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl AuditBuffer {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::new()),
+            capacity,
+            flushing: tokio::sync::Mutex::new(()),
+            degraded: AtomicBool::new(false),
+            buffered: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            unrecorded_losses: AtomicU64::new(0),
+        }
+    }
+
+    /// Hold back an entry the audit storage couldn't take. Returns false,
+    /// counting it as lost, when the buffer is full.
+    pub fn hold(&self, entry: AuditLogEntry) -> bool {
+        let mut entries = self.entries.lock().expect("audit buffer lock poisoned");
+        if entries.len() >= self.capacity {
+            drop(entries);
+            self.record_loss();
+            return false;
+        }
+        entries.push_back(entry);
+        self.buffered.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Count an entry that won't be written
+    pub fn record_loss(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        self.unrecorded_losses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Note that a write to the audit storage failed
+    pub fn mark_failed(&self) {
+        self.degraded.store(true, Ordering::Relaxed);
+    }
+
+    /// Note that a write to the audit storage succeeded. Once nothing is held
+    /// back anymore, returns the entries lost since the last time, which the
+    /// caller records.
+    pub fn mark_written(&self) -> u64 {
+        if !self.is_empty() {
+            return 0;
+        }
+        self.degraded.store(false, Ordering::Relaxed);
+        self.unrecorded_losses.swap(0, Ordering::Relaxed)
+    }
+
+    /// Count lost entries again whose loss couldn't be recorded
+    pub fn restore_losses(&self, lost: u64) {
+        self.unrecorded_losses.fetch_add(lost, Ordering::Relaxed);
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries
+            .lock()
+            .expect("audit buffer lock poisoned")
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write the held back entries oldest first, stopping at the first
+    /// failure with that entry still held. Returns how many were written.
+    pub async fn flush(&self, storage: &dyn AuditStorage) -> MceptionResult<usize> {
+        let _flushing = self.flushing.lock().await;
+        let mut written = 0;
+        loop {
+            let Some(entry) = self
+                .entries
+                .lock()
+                .expect("audit buffer lock poisoned")
+                .front()
+                .cloned()
+            else {
+                return Ok(written);
+            };
+            storage.append_entry(&entry).await?;
+            self.entries
+                .lock()
+                .expect("audit buffer lock poisoned")
+                .pop_front();
+            written += 1;
+        }
+    }
+
+    pub fn usage(&self) -> AuditBufferUsage {
+        AuditBufferUsage {
+            degraded: self.is_degraded(),
+            buffered: self.len(),
+            capacity: self.capacity,
+            buffered_total: self.buffered.load(Ordering::Relaxed),
+            dropped_total: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Retry the held back entries every [`RETRY_INTERVAL`], so they are written
+/// once the audit storage recovers even if nothing else is audited
+pub async fn retry(service: Arc<ConfigService>) {
+    let mut interval = tokio::time::interval(RETRY_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = service.flush_audit_buffer().await {
+            warn!(
+                "The audit storage still fails, {} audit entries are held back: {}",
+                service.audit_buffer().len(),
+                e
+            );
+        }
+    }
+}
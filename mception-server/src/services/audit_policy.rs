@@ -0,0 +1,102 @@
+use crate::core::{AuditAction, AuditFailurePolicy, AuditPolicy, AuditTarget};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Whether an entry is written whatever the actor rules say: deletions,
+/// confirmations, entries marking gaps in the log and changes of the audit
+/// policy itself
+pub fn always_logged(action: &AuditAction, target: &AuditTarget) -> bool {
+    matches!(
+        action,
+        AuditAction::Delete
+            | AuditAction::Migrate
+            | AuditAction::Discontinuity
+            | AuditAction::ConsistencyGap
+            | AuditAction::EntriesLost
+            | AuditAction::Confirmation
+    ) || matches!(target, AuditTarget::AuditPolicy)
+}
+
+/// Check that every rule's share of mutations is between 0 and 1
+pub fn validate(policy: &AuditPolicy) -> Result<(), String> {
+    for (actor, rule) in &policy.actor_rules {
+        if actor.is_empty() {
+            return Err("actor_rules may not contain an empty actor".to_string());
+        }
+        if !(0.0..=1.0).contains(&rule.sample_mutations) {
+            return Err(format!(
+                "sample_mutations of actor '{}' must be between 0 and 1, got {}",
+                actor, rule.sample_mutations
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Decides which audit entries are written under an [`AuditPolicy`]. Mutations
+/// are sampled evenly rather than randomly: with `sample_mutations` of `0.1`
+/// an actor's 1st, 11th, 21st, ... mutation is written.
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    policy: Mutex<AuditPolicy>,
+    /// Mutations seen per sampled actor since the policy was set
+    mutations: Mutex<HashMap<String, u64>>,
+    skipped: AtomicU64,
+}
+
+impl AuditFilter {
+    pub fn set_policy(&self, policy: AuditPolicy) {
+        *self.policy.lock().expect("audit policy lock poisoned") = policy;
+        self.mutations
+            .lock()
+            .expect("audit sample lock poisoned")
+            .clear();
+    }
+
+    /// Whether to write an entry of `actor`, counting it as skipped otherwise
+    pub fn should_log(
+        &self,
+        action: &AuditAction,
+        target: &AuditTarget,
+        actor: Option<&str>,
+    ) -> bool {
+        if always_logged(action, target) {
+            return true;
+        }
+        let policy = self.policy.lock().expect("audit policy lock poisoned");
+        let Some(rule) = actor.and_then(|actor| policy.actor_rules.get(actor)) else {
+            return true;
+        };
+        let logged = match action {
+            AuditAction::Read => rule.log_reads,
+            _ => {
+                let mut mutations = self.mutations.lock().expect("audit sample lock poisoned");
+                let seen = mutations
+                    .entry(actor.unwrap_or_default().to_string())
+                    .or_default();
+                *seen += 1;
+                // Written whenever the sampled share crosses a whole entry
+                let n = *seen as f64;
+                (n * rule.sample_mutations).ceil() > ((n - 1.0) * rule.sample_mutations).ceil()
+            }
+        };
+        if !logged {
+            self.skipped.fetch_add(1, Ordering::Relaxed);
+        }
+        logged
+    }
+
+    /// What happens to a change whose audit entry can't be written
+    pub fn on_failure(&self) -> AuditFailurePolicy {
+        self.policy
+            .lock()
+            .expect("audit policy lock poisoned")
+            .on_failure
+    }
+
+    /// Entries not written because of actor rules, since the start
+    pub fn skipped(&self) -> u64 {
+        self.skipped.load(Ordering::Relaxed)
+    }
+}
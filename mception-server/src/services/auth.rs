@@ -0,0 +1,159 @@
+use crate::core::{AgentConfig, REDACTED, ServerConfig, error_body};
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Json, Response};
+use chrono::Utc;
+use std::fmt;
+use std::sync::Arc;
+
+/// Actor recorded for admin requests when the admin API is not protected or
+/// a token was given without a name
+pub const DEFAULT_ADMIN_ACTOR: &str = "admin";
+
+/// A token accepted by the admin API, and the actor its requests are audited as
+#[derive(Clone)]
+pub struct AdminToken {
+    pub actor: String,
+    token: String,
+}
+
+impl AdminToken {
+    /// Parse `<actor>=<token>`, or a bare `<token>` for [`DEFAULT_ADMIN_ACTOR`]
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (actor, token) = match value.split_once('=') {
+            Some((actor, token)) => (actor.trim(), token),
+            None => (DEFAULT_ADMIN_ACTOR, value),
+        };
+        if actor.is_empty() || token.is_empty() {
+            return Err("admin tokens must be `<token>` or `<actor>=<token>`".to_string());
+        }
+        Ok(Self {
+            actor: actor.to_string(),
+            token: token.to_string(),
+        })
+    }
+}
+
+impl AdminToken {
+    /// Send `request` to the admin API with this token
+    pub fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        request.bearer_auth(&self.token)
+    }
+}
+
+impl fmt::Debug for AdminToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdminToken")
+            .field("actor", &self.actor)
+            .field("token", &REDACTED)
+            .finish()
+    }
+}
+
+/// Who an admin request is audited as, set by [`require_admin_token`]
+#[derive(Debug, Clone)]
+pub struct Actor(pub String);
+
+/// Middleware refusing admin requests without one of `tokens` as bearer
+/// token with 401. Without tokens the admin API is open. Either way the
+/// request's [`Actor`] is attached for the audit log.
+pub async fn require_admin_token(
+    State(tokens): State<Arc<Vec<AdminToken>>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let actor = if tokens.is_empty() {
+        DEFAULT_ADMIN_ACTOR
+    } else {
+        match admin_actor(&tokens, request.headers()) {
+            Some(actor) => actor,
+            None => return unauthorized("A valid admin token is required"),
+        }
+    };
+    request.extensions_mut().insert(Actor(actor.to_string()));
+    next.run(request).await
+}
+
+/// 401 answer asking for a bearer token
+pub fn unauthorized(message: &str) -> Response {
+    let mut response = (
+        StatusCode::UNAUTHORIZED,
+        Json(error_body("unauthorized", message)),
+    )
+        .into_response();
+    response
+        .headers_mut()
+        .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    response
+}
+
+/// The actor of the admin token presented as bearer token, if any matches
+pub fn admin_actor<'a>(tokens: &'a [AdminToken], headers: &HeaderMap) -> Option<&'a str> {
+    let presented = bearer(headers)?;
+    tokens
+        .iter()
+        .find(|token| token_matches(&token.token, presented))
+        .map(|token| token.actor.as_str())
+}
+
+/// The token of an `Authorization: Bearer <token>` header
+pub fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+/// Whether a request may act as `agent`: agents with a token have to present
+/// it before it expires, agents without one are open
+pub fn agent_authorized(agent: &AgentConfig, headers: &HeaderMap) -> bool {
+    match &agent.auth_token {
+        Some(token) => {
+            bearer(headers).is_some_and(|presented| token_matches(token, presented))
+                && !agent.auth_token_expired(Utc::now())
+        }
+        None => true,
+    }
+}
+
+/// The agent whose token was presented. While no agent has a token,
+/// agents aren't authenticated and requests are anonymous (`Ok(None)`),
+/// whatever they carry. Once any agent has a token, requests without one and
+/// unknown and expired tokens are refused.
+pub fn authenticated_agent<'a>(
+    config: &'a ServerConfig,
+    headers: &HeaderMap,
+) -> Result<Option<&'a str>, String> {
+    let mut with_token = config
+        .agents
+        .iter()
+        .filter_map(|(agent_id, agent)| Some((agent_id.as_str(), agent.auth_token.as_deref()?)))
+        .peekable();
+    if with_token.peek().is_none() {
+        return Ok(None);
+    }
+    let presented = bearer(headers).ok_or_else(|| "An agent token is required".to_string())?;
+    let agent_id = with_token
+        .find(|(_, token)| token_matches(token, presented))
+        .map(|(agent_id, _)| agent_id)
+        .ok_or_else(|| "Invalid agent token".to_string())?;
+    if config.agents[agent_id].auth_token_expired(Utc::now()) {
+        return Err(format!("The token of agent '{}' expired", agent_id));
+    }
+    Ok(Some(agent_id))
+}
+
+/// Compare tokens in time independent of where they differ
+pub(crate) fn token_matches(expected: &str, presented: &str) -> bool {
+    let (expected, presented) = (expected.as_bytes(), presented.as_bytes());
+    expected.len() == presented.len()
+        && expected
+            .iter()
+            .zip(presented)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
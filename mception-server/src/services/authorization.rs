@@ -0,0 +1,228 @@
+use crate::core::{AgentConfig, BUNDLE_PREFIX, GrantSource, ServerConfig, ToolFilter};
+use crate::services::tool_shaping::glob;
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Header naming the agent a forwarded call comes from. Calls naming an agent
+/// are only forwarded to MCPs the agent may use.
+pub const AGENT_HEADER: &str = "x-mception-agent-id";
+
+/// Kind of rule considered when deciding whether an agent may use an MCP
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleKind {
+    /// The MCP has to exist as a leaf MCP or agent
+    Target,
+    /// An `allowed_mcp_ids` entry naming the MCP
+    DirectGrant,
+    /// An `allowed_mcp_ids` entry `bundle:<name>` whose bundle contains the MCP
+    BundleGrant,
+    /// The agent's tool filter for an allowed MCP, see [`ToolFilter`]
+    ToolRule,
+}
+
+/// One rule considered for a decision
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleEvaluation {
+    pub kind: RuleKind,
+    /// The rule as configured, e.g. the `allowed_mcp_ids` entry
+    pub rule: String,
+    pub matched: bool,
+    pub detail: String,
+    /// Where a grant came from, see [`AgentConfig::grant_sources`]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<GrantSource>,
+}
+
+/// Whether an agent may use an MCP (and tool), with every rule considered
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessDecision {
+    pub agent_id: String,
+    pub mcp_id: String,
+    pub tool: Option<String>,
+    pub allowed: bool,
+    /// The first grant that matched
+    pub winning_rule: Option<String>,
+    pub trace: Vec<RuleEvaluation>,
+}
+
+/// Decide whether `agent` may use `mcp_id`, and `tool` on it if given.
+///
+/// This is the single place access is decided: the remote configuration lists
+/// exactly the MCPs allowed here, so explanations can't diverge from it.
+pub fn evaluate(
+    config: &ServerConfig,
+    agent: &AgentConfig,
+    mcp_id: &str,
+    tool: Option<&str>,
+) -> AccessDecision {
+    let mut trace = Vec::new();
+
+    let target = if config.leaf_mcps.contains_key(mcp_id) {
+        Some("leaf MCP")
+    } else if config.agents.contains_key(mcp_id) {
+        Some("agent")
+    } else {
+        None
+    };
+    trace.push(RuleEvaluation {
+        kind: RuleKind::Target,
+        rule: mcp_id.to_string(),
+        matched: target.is_some(),
+        detail: match target {
+            Some(kind) => format!("'{}' is a {}", mcp_id, kind),
+            None => format!("'{}' is neither a leaf MCP nor an agent", mcp_id),
+        },
+        sources: Vec::new(),
+    });
+
+    let mut winning_rule = None;
+    if target.is_some() {
+        let now = Utc::now();
+        for grant in &agent.allowed_mcp_ids {
+            let mut evaluation = evaluate_grant(config, grant, mcp_id);
+            evaluation.sources = agent.grant_sources_of(grant).into_iter().collect();
+            if evaluation.matched && agent.grant_expired(grant, now) {
+                evaluation.matched = false;
+                evaluation.detail = format!(
+                    "{}, but the grant expired at {}",
+                    evaluation.detail, agent.grant_expiry[grant]
+                );
+            }
+            if evaluation.matched && winning_rule.is_none() {
+                winning_rule = Some(grant.clone());
+            }
+            trace.push(evaluation);
+        }
+    }
+
+    let mut tool_allowed = true;
+    if let Some(tool) = tool
+        && winning_rule.is_some()
+    {
+        let evaluation = evaluate_tool(agent.tool_filters.get(mcp_id), tool);
+        tool_allowed = evaluation.matched;
+        trace.push(evaluation);
+    }
+
+    AccessDecision {
+        agent_id: agent.agent_id.clone(),
+        mcp_id: mcp_id.to_string(),
+        tool: tool.map(str::to_string),
+        allowed: winning_rule.is_some() && tool_allowed,
+        winning_rule,
+        trace,
+    }
+}
+
+/// Whether `filter` lets an agent call `tool`, every tool without a filter
+pub fn tool_allowed(filter: Option<&ToolFilter>, tool: &str) -> bool {
+    evaluate_tool(filter, tool).matched
+}
+
+fn evaluate_tool(filter: Option<&ToolFilter>, tool: &str) -> RuleEvaluation {
+    let (matched, detail) = match filter {
+        None => (true, "no tool filter, every tool is allowed".to_string()),
+        Some(filter) => {
+            if let Some(pattern) = filter.deny.iter().find(|pattern| glob(pattern, tool)) {
+                (false, format!("denied by pattern '{}'", pattern))
+            } else {
+                match &filter.allow {
+                    None => (true, "no allow list, not denied".to_string()),
+                    Some(allow) => match allow.iter().find(|pattern| glob(pattern, tool)) {
+                        Some(pattern) => (true, format!("allowed by pattern '{}'", pattern)),
+                        None => (false, "matches none of the allowed patterns".to_string()),
+                    },
+                }
+            }
+        }
+    };
+    RuleEvaluation {
+        kind: RuleKind::ToolRule,
+        rule: tool.to_string(),
+        matched,
+        detail,
+        sources: Vec::new(),
+    }
+}
+
+/// The agent a forwarded call names as its caller, if any
+pub fn caller(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AGENT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|agent_id| !agent_id.is_empty())
+}
+
+/// All MCPs (leaf MCPs and agents) `agent` may use
+pub fn allowed_mcps(config: &ServerConfig, agent: &AgentConfig) -> Vec<String> {
+    config
+        .leaf_mcps
+        .keys()
+        .chain(config.agents.keys())
+        .filter(|mcp_id| evaluate(config, agent, mcp_id, None).allowed)
+        .cloned()
+        .collect()
+}
+
+/// When `agent` stops being allowed `mcp_id` as its grants expire, `None`
+/// if a grant allowing it doesn't expire or none allows it
+pub fn grant_expiry(
+    config: &ServerConfig,
+    agent: &AgentConfig,
+    mcp_id: &str,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let mut latest = None;
+    for grant in &agent.allowed_mcp_ids {
+        if agent.grant_expired(grant, now) || !evaluate_grant(config, grant, mcp_id).matched {
+            continue;
+        }
+        let expires_at = agent.grant_expiry.get(grant)?;
+        latest = latest.max(Some(*expires_at));
+    }
+    latest
+}
+
+fn evaluate_grant(config: &ServerConfig, grant: &str, mcp_id: &str) -> RuleEvaluation {
+    match grant.strip_prefix(BUNDLE_PREFIX) {
+        Some(name) => {
+            let (matched, detail) = match config.bundles.get(name) {
+                Some(bundle) if bundle.members.iter().any(|member| member == mcp_id) => (
+                    true,
+                    format!(
+                        "bundle '{}' (version {}) contains '{}'",
+                        name, bundle.version, mcp_id
+                    ),
+                ),
+                Some(bundle) => (
+                    false,
+                    format!(
+                        "bundle '{}' (version {}) does not contain '{}'",
+                        name, bundle.version, mcp_id
+                    ),
+                ),
+                None => (false, format!("bundle '{}' does not exist", name)),
+            };
+            RuleEvaluation {
+                kind: RuleKind::BundleGrant,
+                rule: grant.to_string(),
+                matched,
+                detail,
+                sources: Vec::new(),
+            }
+        }
+        None => RuleEvaluation {
+            kind: RuleKind::DirectGrant,
+            rule: grant.to_string(),
+            matched: grant == mcp_id,
+            detail: if grant == mcp_id {
+                format!("grants '{}' directly", mcp_id)
+            } else {
+                format!("grants '{}', not '{}'", grant, mcp_id)
+            },
+            sources: Vec::new(),
+        },
+    }
+}
@@ -0,0 +1,299 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::sync::RwLock;
+
+/// An agent counts as connected while it contacts the server at least this often
+pub const HEARTBEAT_GRACE: Duration = Duration::seconds(90);
+
+/// How often an agent's WebSocket is pinged to tell it is still alive
+pub const PING_INTERVAL: Duration = Duration::seconds(30);
+
+/// How often the running server persists its availability data
+pub const CHECKPOINT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Default retention of availability intervals
+pub const DEFAULT_RETENTION: Duration = Duration::days(30);
+
+/// A closed span of time as `[start, end]` unix timestamps in seconds
+type Span = (i64, i64);
+
+/// On-disk availability data. Spans are stored as unix second pairs to keep
+/// the file small.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct AvailabilityData {
+    /// Spans during which the server was running
+    sessions: Vec<Span>,
+    /// Spans during which each agent was connected
+    agents: BTreeMap<String, Vec<Span>>,
+}
+
+/// A span of time in a report
+#[derive(Debug, Clone, Serialize)]
+pub struct AvailabilityInterval {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub seconds: i64,
+}
+
+/// Availability of one agent over a time window
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentAvailability {
+    pub agent_id: String,
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    /// Connected time as a percentage of the time the server was running,
+    /// `None` if the server wasn't running in the window at all
+    pub uptime_percent: Option<f64>,
+    pub connected_seconds: i64,
+    pub disconnected_seconds: i64,
+    /// Time the server wasn't running, e.g. across restarts
+    pub unknown_seconds: i64,
+    pub longest_outage: Option<AvailabilityInterval>,
+    pub connected: Vec<AvailabilityInterval>,
+    pub unknown: Vec<AvailabilityInterval>,
+}
+
+/// Availability of all agents over a time window
+#[derive(Debug, Clone, Serialize)]
+pub struct FleetAvailability {
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    /// Mean uptime of the agents with known uptime
+    pub mean_uptime_percent: Option<f64>,
+    pub unknown_seconds: i64,
+    pub agents: Vec<AgentAvailability>,
+}
+
+/// Connected spans per agent, derived from agent contacts and persisted so
+/// reports survive restarts
+#[derive(Debug)]
+pub struct AvailabilityTracker {
+    path: PathBuf,
+    retention: Duration,
+    data: RwLock<AvailabilityData>,
+}
+
+impl AvailabilityTracker {
+    /// Load the persisted data and start a new server session
+    pub fn open(path: impl Into<PathBuf>, retention: Duration) -> std::io::Result<Self> {
+        let path = path.into();
+        let mut data = read(&path)?;
+        let now = Utc::now().timestamp();
+        data.sessions.push((now, now));
+        Ok(Self {
+            path,
+            retention,
+            data: RwLock::new(data),
+        })
+    }
+
+    /// Record that an agent contacted the server
+    pub async fn touch(&self, agent_id: &str) {
+        let now = Utc::now().timestamp();
+        let mut data = self.data.write().await;
+        extend_session(&mut data, now);
+
+        // Spans never reach back across a restart, that time is unknown
+        let session_start = data.sessions.last().map(|(start, _)| *start).unwrap_or(now);
+        let spans = data.agents.entry(agent_id.to_string()).or_default();
+        match spans.last_mut() {
+            Some((_, end))
+                if *end >= session_start && now - *end <= HEARTBEAT_GRACE.num_seconds() =>
+            {
+                *end = now
+            }
+            _ => spans.push((now, now)),
+        }
+    }
+
+    /// Number of agents currently connected
+    pub async fn connected_agents(&self) -> usize {
+        let now = Utc::now().timestamp();
+        let data = self.data.read().await;
+        let session_start = data.sessions.last().map(|(start, _)| *start).unwrap_or(now);
+        data.agents
+            .values()
+            .filter_map(|spans| spans.last())
+            .filter(|(_, end)| *end >= session_start && now - *end <= HEARTBEAT_GRACE.num_seconds())
+            .count()
+    }
+
+    /// Prune data older than the retention window and write it to disk
+    pub async fn checkpoint(&self) -> std::io::Result<()> {
+        let now = Utc::now().timestamp();
+        let mut data = self.data.write().await;
+        extend_session(&mut data, now);
+
+        let cutoff = now - self.retention.num_seconds();
+        data.sessions.retain(|(_, end)| *end >= cutoff);
+        for spans in data.agents.values_mut() {
+            spans.retain(|(_, end)| *end >= cutoff);
+        }
+        data.agents.retain(|_, spans| !spans.is_empty());
+
+        let content = serde_json::to_vec(&*data)?;
+        let temp = self.path.with_extension("tmp");
+        tokio::fs::write(&temp, content).await?;
+        tokio::fs::rename(&temp, &self.path).await
+    }
+
+    /// Availability of an agent over the last `window`
+    pub async fn agent(&self, agent_id: &str, window: Duration) -> AgentAvailability {
+        let mut data = self.data.read().await.clone();
+        extend_session(&mut data, Utc::now().timestamp());
+        agent_report(&data, agent_id, window)
+    }
+
+    /// Availability of the given agents over the last `window`
+    pub async fn fleet(&self, agent_ids: &[String], window: Duration) -> FleetAvailability {
+        let mut data = self.data.read().await.clone();
+        extend_session(&mut data, Utc::now().timestamp());
+        fleet_report(&data, agent_ids, window)
+    }
+}
+
+/// Availability of all agents in a persisted availability file, e.g. for the
+/// CLI while the server isn't running
+pub fn read_fleet(path: &Path, window: Duration) -> std::io::Result<FleetAvailability> {
+    let data = read(path)?;
+    let agent_ids: Vec<String> = data.agents.keys().cloned().collect();
+    Ok(fleet_report(&data, &agent_ids, window))
+}
+
+fn read(path: &Path) -> std::io::Result<AvailabilityData> {
+    match std::fs::read(path) {
+        Ok(content) => Ok(serde_json::from_slice(&content)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(AvailabilityData::default()),
+        Err(e) => Err(e),
+    }
+}
+
+/// The current session lasts at least until `now`
+fn extend_session(data: &mut AvailabilityData, now: i64) {
+    match data.sessions.last_mut() {
+        Some((_, end)) => *end = (*end).max(now),
+        None => data.sessions.push((now, now)),
+    }
+}
+
+fn fleet_report(
+    data: &AvailabilityData,
+    agent_ids: &[String],
+    window: Duration,
+) -> FleetAvailability {
+    let (since, until) = bounds(data, window);
+    let agents: Vec<AgentAvailability> = agent_ids
+        .iter()
+        .map(|agent_id| agent_report(data, agent_id, window))
+        .collect();
+    let known: Vec<f64> = agents
+        .iter()
+        .filter_map(|agent| agent.uptime_percent)
+        .collect();
+
+    FleetAvailability {
+        since: timestamp(since),
+        until: timestamp(until),
+        mean_uptime_percent: (!known.is_empty())
+            .then(|| known.iter().sum::<f64>() / known.len() as f64),
+        unknown_seconds: total(&subtract(
+            &[(since, until)],
+            &clip(&data.sessions, since, until),
+        )),
+        agents,
+    }
+}
+
+fn agent_report(data: &AvailabilityData, agent_id: &str, window: Duration) -> AgentAvailability {
+    let (since, until) = bounds(data, window);
+    let known = clip(&data.sessions, since, until);
+    let unknown = subtract(&[(since, until)], &known);
+    let connected = clip(
+        data.agents
+            .get(agent_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default(),
+        since,
+        until,
+    );
+    let disconnected = subtract(&known, &connected);
+
+    let connected_seconds = total(&connected);
+    let disconnected_seconds = total(&disconnected);
+    let known_seconds = connected_seconds + disconnected_seconds;
+
+    AgentAvailability {
+        agent_id: agent_id.to_string(),
+        since: timestamp(since),
+        until: timestamp(until),
+        uptime_percent: (known_seconds > 0)
+            .then(|| connected_seconds as f64 * 100.0 / known_seconds as f64),
+        connected_seconds,
+        disconnected_seconds,
+        unknown_seconds: total(&unknown),
+        longest_outage: disconnected
+            .iter()
+            .max_by_key(|(start, end)| end - start)
+            .map(interval),
+        connected: connected.iter().map(interval).collect(),
+        unknown: unknown.iter().map(interval).collect(),
+    }
+}
+
+/// The report window: `window` up to the end of the latest server session
+fn bounds(data: &AvailabilityData, window: Duration) -> (i64, i64) {
+    let until = data
+        .sessions
+        .last()
+        .map(|(_, end)| *end)
+        .unwrap_or_else(|| Utc::now().timestamp());
+    (until - window.num_seconds(), until)
+}
+
+/// Spans cut to `[since, until]`, dropping the empty ones
+fn clip(spans: &[Span], since: i64, until: i64) -> Vec<Span> {
+    spans
+        .iter()
+        .map(|(start, end)| ((*start).max(since), (*end).min(until)))
+        .filter(|(start, end)| start < end)
+        .collect()
+}
+
+/// The parts of `spans` not covered by `cover`. Both must be sorted and non-overlapping.
+fn subtract(spans: &[Span], cover: &[Span]) -> Vec<Span> {
+    let mut result = Vec::new();
+    for &(start, end) in spans {
+        let mut cursor = start;
+        for &(cover_start, cover_end) in cover {
+            if cover_end <= cursor || cover_start >= end {
+                continue;
+            }
+            if cover_start > cursor {
+                result.push((cursor, cover_start));
+            }
+            cursor = cursor.max(cover_end);
+        }
+        if cursor < end {
+            result.push((cursor, end));
+        }
+    }
+    result
+}
+
+fn total(spans: &[Span]) -> i64 {
+    spans.iter().map(|(start, end)| end - start).sum()
+}
+
+fn interval(&(start, end): &Span) -> AvailabilityInterval {
+    AvailabilityInterval {
+        start: timestamp(start),
+        end: timestamp(end),
+        seconds: end - start,
+    }
+}
+
+fn timestamp(seconds: i64) -> DateTime<Utc> {
+    Utc.timestamp_opt(seconds, 0).single().unwrap_or_default()
+}
@@ -0,0 +1,124 @@
+use crate::core::BuiltinMcpKind;
+use serde_json::{Value, json};
+use std::time::Duration;
+
+/// MCP protocol version spoken by the built-in MCPs
+const PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// Longest sleep `sleep_ms` accepts, so a demo can't tie up the server forever
+const MAX_SLEEP: Duration = Duration::from_secs(600);
+
+// JSON-RPC error codes
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Handle a JSON-RPC message for a built-in MCP in-process. Returns `None` for
+/// notifications, which have no response.
+pub async fn handle(kind: BuiltinMcpKind, message: &Value) -> Option<Value> {
+    let id = message.get("id").cloned()?;
+    let method = message["method"].as_str().unwrap_or_default();
+    let params = &message["params"];
+
+    let result = match kind {
+        BuiltinMcpKind::Echo => echo(method, params).await,
+    };
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": message }
+        }),
+    })
+}
+
+/// Tools of a built-in MCP, as returned by `tools/list`
+pub fn tools(kind: BuiltinMcpKind) -> Value {
+    match kind {
+        BuiltinMcpKind::Echo => json!([
+            {
+                "name": "echo",
+                "description": "Return the given text",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": { "text": { "type": "string" } },
+                    "required": ["text"]
+                }
+            },
+            {
+                "name": "sleep_ms",
+                "description": "Wait for the given number of milliseconds before answering",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": { "ms": { "type": "integer", "minimum": 0 } },
+                    "required": ["ms"]
+                }
+            },
+            {
+                "name": "fail_with",
+                "description": "Fail with the given JSON-RPC error code and message, or with a tool error if `tool_error` is set",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "code": { "type": "integer" },
+                        "message": { "type": "string" },
+                        "tool_error": { "type": "boolean" }
+                    }
+                }
+            }
+        ]),
+    }
+}
+
+async fn echo(method: &str, params: &Value) -> Result<Value, (i64, String)> {
+    match method {
+        "initialize" => Ok(json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": { "tools": {} },
+            "serverInfo": { "name": "mception-echo", "version": env!("CARGO_PKG_VERSION") }
+        })),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": tools(BuiltinMcpKind::Echo) })),
+        "tools/call" => {
+            let arguments = &params["arguments"];
+            match params["name"].as_str().unwrap_or_default() {
+                "echo" => Ok(text_result(
+                    arguments["text"].as_str().unwrap_or_default(),
+                    false,
+                )),
+                "sleep_ms" => {
+                    let ms = arguments["ms"].as_u64().ok_or((
+                        INVALID_PARAMS,
+                        "ms must be a non-negative integer".to_string(),
+                    ))?;
+                    let sleep = Duration::from_millis(ms).min(MAX_SLEEP);
+                    tokio::time::sleep(sleep).await;
+                    Ok(text_result(
+                        &format!("slept {} ms", sleep.as_millis()),
+                        false,
+                    ))
+                }
+                "fail_with" => {
+                    let message = arguments["message"]
+                        .as_str()
+                        .unwrap_or("Failure requested by fail_with")
+                        .to_string();
+                    if arguments["tool_error"].as_bool().unwrap_or(false) {
+                        Ok(text_result(&message, true))
+                    } else {
+                        Err((arguments["code"].as_i64().unwrap_or(-32000), message))
+                    }
+                }
+                name => Err((INVALID_PARAMS, format!("Unknown tool '{}'", name))),
+            }
+        }
+        method => Err((METHOD_NOT_FOUND, format!("Method '{}' not found", method))),
+    }
+}
+
+fn text_result(text: &str, is_error: bool) -> Value {
+    json!({
+        "content": [{ "type": "text", "text": text }],
+        "isError": is_error
+    })
+}
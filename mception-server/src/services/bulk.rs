@@ -0,0 +1,167 @@
+use crate::core::{BUNDLE_PREFIX, ServerConfig, config_tags};
+use crate::services::safety::LeafMcpSafety;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// What a bulk operation applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkKind {
+    LeafMcp,
+    Agent,
+}
+
+/// Entities selected for a bulk operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BulkSelection {
+    Ids(Vec<String>),
+    /// Every leaf MCP or agent with this tag in its `config.tags`
+    Tag(String),
+}
+
+impl fmt::Display for BulkSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BulkSelection::Ids(ids) => write!(f, "ids {}", ids.join(", ")),
+            BulkSelection::Tag(tag) => write!(f, "tag '{}'", tag),
+        }
+    }
+}
+
+/// Exactly what a bulk delete removes, as shown by its dry run
+#[derive(Debug, Clone, Serialize)]
+pub struct BulkDeletePlan {
+    pub kind: BulkKind,
+    pub selection: String,
+    /// Entities deleted, sorted
+    pub ids: Vec<String>,
+    /// Remaining agents losing access, with the deleted ids they were allowed
+    /// to use, directly or through a bundle
+    pub affected_agents: BTreeMap<String, Vec<String>>,
+    /// Bundles losing members
+    pub affected_bundles: Vec<String>,
+    /// Who references and lately used each deleted leaf MCP
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub safety: BTreeMap<String, LeafMcpSafety>,
+    /// Configuration revision the plan was made at
+    pub revision: u64,
+    /// Confirms this plan once, until it expires. Any configuration change
+    /// invalidates it. Issued by [`Confirmations::issue_for_plan`], empty
+    /// until then.
+    ///
+    /// [`Confirmations::issue_for_plan`]: crate::services::confirmation::Confirmations::issue_for_plan
+    pub confirmation_token: String,
+}
+
+/// Result of a confirmed bulk delete
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BulkDeleteOutcome {
+    Deleted {
+        /// Shared by the audit entries of the deleted entities
+        correlation_id: String,
+        plan: BulkDeletePlan,
+    },
+    /// The token doesn't confirm the current plan, which is returned for
+    /// review with a new token
+    PlanChanged {
+        plan: BulkDeletePlan,
+        /// Why the token was refused
+        reason: String,
+    },
+}
+
+/// Plan deleting the selected leaf MCPs or agents. Unknown ids are an error,
+/// a tag matching nothing gives an empty plan.
+pub fn plan(
+    config: &ServerConfig,
+    kind: BulkKind,
+    selection: &BulkSelection,
+) -> Result<BulkDeletePlan, String> {
+    let ids: BTreeSet<String> = match selection {
+        BulkSelection::Ids(ids) => {
+            for id in ids {
+                let exists = match kind {
+                    BulkKind::LeafMcp => config.leaf_mcps.contains_key(id),
+                    BulkKind::Agent => config.agents.contains_key(id),
+                };
+                if !exists {
+                    return Err(format!("{} '{}' not found", kind.label(), id));
+                }
+            }
+            ids.iter().cloned().collect()
+        }
+        BulkSelection::Tag(tag) => match kind {
+            BulkKind::LeafMcp => config
+                .leaf_mcps
+                .iter()
+                .filter(|(_, leaf)| config_tags(&leaf.config).any(|t| t == tag))
+                .map(|(id, _)| id.clone())
+                .collect(),
+            BulkKind::Agent => config
+                .agents
+                .iter()
+                .filter(|(_, agent)| config_tags(&agent.config).any(|t| t == tag))
+                .map(|(id, _)| id.clone())
+                .collect(),
+        },
+    };
+
+    let affected_bundles: Vec<String> = match kind {
+        BulkKind::LeafMcp => config
+            .bundles
+            .iter()
+            .filter(|(_, bundle)| bundle.members.iter().any(|member| ids.contains(member)))
+            .map(|(name, _)| name.clone())
+            .collect(),
+        BulkKind::Agent => Vec::new(),
+    };
+
+    let mut affected_agents = BTreeMap::new();
+    for (agent_id, agent) in &config.agents {
+        if kind == BulkKind::Agent && ids.contains(agent_id) {
+            continue;
+        }
+        let mut lost = BTreeSet::new();
+        for allowed in &agent.allowed_mcp_ids {
+            match allowed.strip_prefix(BUNDLE_PREFIX) {
+                Some(bundle) if kind == BulkKind::LeafMcp => {
+                    if let Some(bundle) = config.bundles.get(bundle) {
+                        lost.extend(bundle.members.iter().filter(|m| ids.contains(*m)).cloned());
+                    }
+                }
+                Some(_) => {}
+                None if ids.contains(allowed) => {
+                    lost.insert(allowed.clone());
+                }
+                None => {}
+            }
+        }
+        if !lost.is_empty() {
+            affected_agents.insert(agent_id.clone(), lost.into_iter().collect());
+        }
+    }
+
+    let ids: Vec<String> = ids.into_iter().collect();
+    let revision = config.metadata.revision;
+    Ok(BulkDeletePlan {
+        kind,
+        selection: selection.to_string(),
+        confirmation_token: String::new(),
+        ids,
+        affected_agents,
+        affected_bundles,
+        safety: BTreeMap::new(),
+        revision,
+    })
+}
+
+impl BulkKind {
+    fn label(self) -> &'static str {
+        match self {
+            BulkKind::LeafMcp => "Leaf MCP",
+            BulkKind::Agent => "Agent",
+        }
+    }
+}
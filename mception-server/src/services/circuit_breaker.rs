@@ -0,0 +1,224 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Consecutive failed forwards that open a leaf MCP's breaker, unless set
+/// otherwise with `--breaker-failure-threshold`
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+
+/// How long an open breaker refuses forwards before letting a probe through,
+/// unless set otherwise with `--breaker-cooldown`
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// State of a leaf MCP's circuit breaker
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Forwards go through
+    #[default]
+    Closed,
+    /// Forwards are refused until the cooldown has passed
+    Open,
+    /// A single probe goes through, deciding whether the breaker closes again
+    HalfOpen,
+}
+
+impl BreakerState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BreakerState::Closed => "closed",
+            BreakerState::Open => "open",
+            BreakerState::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// A change of a breaker's state, to log and audit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    pub from: BreakerState,
+    pub to: BreakerState,
+    /// Consecutive failed forwards at the time
+    pub consecutive_failures: u32,
+}
+
+/// How a forward to a leaf MCP went, as far as its breaker is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The leaf MCP answered
+    Success,
+    /// The leaf MCP couldn't be reached or didn't answer in time
+    Failure,
+    /// The forward failed before reaching the leaf MCP
+    Inconclusive,
+}
+
+/// A breaker's state as shown by the admin API
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakerStatus {
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    pub failure_threshold: u32,
+    /// When the breaker last opened, while it isn't closed
+    pub opened_at: Option<DateTime<Utc>>,
+    /// Time left until a probe is let through, while it is open
+    pub retry_in_ms: Option<u128>,
+}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    state: BreakerState,
+    consecutive_failures: u32,
+    /// When the breaker opened, for the cooldown and to show
+    opened: Option<(Instant, DateTime<Utc>)>,
+    /// When the probe of a half-open breaker was let through. A probe that
+    /// never reports back, e.g. because its caller went away, is replaced by
+    /// another after the cooldown.
+    probe_started: Option<Instant>,
+}
+
+/// Circuit breakers of leaf MCPs: after `failure_threshold` consecutive
+/// failed forwards a leaf MCP's breaker opens and forwards to it are refused
+/// right away for `cooldown`. Then one probe is let through; the breaker
+/// closes if it succeeds and opens again if it fails.
+#[derive(Debug)]
+pub struct CircuitBreakers {
+    failure_threshold: u32,
+    cooldown: Duration,
+    breakers: Mutex<HashMap<String, Breaker>>,
+}
+
+impl Default for CircuitBreakers {
+    fn default() -> Self {
+        Self::new(DEFAULT_FAILURE_THRESHOLD, DEFAULT_COOLDOWN)
+    }
+}
+
+impl CircuitBreakers {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            breakers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether a forward to the leaf MCP may go through. Refused with the
+    /// time left until a probe is let through. Lets the probe through once
+    /// the cooldown of an open breaker has passed, with the transition to
+    /// half-open.
+    pub fn admit(&self, leaf_id: &str) -> Result<Option<Transition>, Duration> {
+        let mut breakers = self.breakers.lock().unwrap();
+        let Some(breaker) = breakers.get_mut(leaf_id) else {
+            return Ok(None);
+        };
+        let now = Instant::now();
+        match breaker.state {
+            BreakerState::Closed => Ok(None),
+            BreakerState::Open => {
+                let opened = breaker.opened.map_or(now, |(at, _)| at);
+                let elapsed = now.duration_since(opened);
+                if elapsed < self.cooldown {
+                    return Err(self.cooldown - elapsed);
+                }
+                breaker.probe_started = Some(now);
+                Ok(Some(breaker.set_state(BreakerState::HalfOpen)))
+            }
+            BreakerState::HalfOpen => match breaker.probe_started {
+                Some(started) if now.duration_since(started) < self.cooldown => {
+                    Err(self.cooldown - now.duration_since(started))
+                }
+                _ => {
+                    breaker.probe_started = Some(now);
+                    Ok(None)
+                }
+            },
+        }
+    }
+
+    /// Count the outcome of a forward that was admitted, with the transition
+    /// it caused, if any
+    pub fn record(&self, leaf_id: &str, outcome: Outcome) -> Option<Transition> {
+        let mut breakers = self.breakers.lock().unwrap();
+        match outcome {
+            Outcome::Success => {
+                // Most forwards succeed to a closed breaker, nothing to keep
+                let breaker = breakers.remove(leaf_id)?;
+                (breaker.state != BreakerState::Closed).then_some(Transition {
+                    from: breaker.state,
+                    to: BreakerState::Closed,
+                    consecutive_failures: 0,
+                })
+            }
+            Outcome::Failure => {
+                let breaker = breakers.entry(leaf_id.to_string()).or_default();
+                breaker.consecutive_failures += 1;
+                let opens = match breaker.state {
+                    BreakerState::Closed => breaker.consecutive_failures >= self.failure_threshold,
+                    BreakerState::HalfOpen => true,
+                    // A forward admitted before the breaker opened
+                    BreakerState::Open => false,
+                };
+                if !opens {
+                    return None;
+                }
+                breaker.opened = Some((Instant::now(), Utc::now()));
+                breaker.probe_started = None;
+                Some(breaker.set_state(BreakerState::Open))
+            }
+            Outcome::Inconclusive => {
+                if let Some(breaker) = breakers.get_mut(leaf_id) {
+                    breaker.probe_started = None;
+                }
+                None
+            }
+        }
+    }
+
+    /// Close the breaker, with the transition if it wasn't closed
+    pub fn reset(&self, leaf_id: &str) -> Option<Transition> {
+        let breaker = self.breakers.lock().unwrap().remove(leaf_id)?;
+        (breaker.state != BreakerState::Closed).then_some(Transition {
+            from: breaker.state,
+            to: BreakerState::Closed,
+            consecutive_failures: breaker.consecutive_failures,
+        })
+    }
+
+    /// Drop the breaker of a deleted leaf MCP
+    pub fn forget(&self, leaf_id: &str) {
+        self.breakers.lock().unwrap().remove(leaf_id);
+    }
+
+    pub fn status(&self, leaf_id: &str) -> BreakerStatus {
+        let breakers = self.breakers.lock().unwrap();
+        let breaker = breakers.get(leaf_id);
+        let state = breaker.map_or(BreakerState::Closed, |breaker| breaker.state);
+        let opened = breaker
+            .and_then(|breaker| breaker.opened)
+            .filter(|_| state != BreakerState::Closed);
+        BreakerStatus {
+            state,
+            consecutive_failures: breaker.map_or(0, |breaker| breaker.consecutive_failures),
+            failure_threshold: self.failure_threshold,
+            opened_at: opened.map(|(_, at)| at),
+            retry_in_ms: opened
+                .filter(|_| state == BreakerState::Open)
+                .map(|(at, _)| self.cooldown.saturating_sub(at.elapsed()).as_millis()),
+        }
+    }
+}
+
+impl Breaker {
+    fn set_state(&mut self, to: BreakerState) -> Transition {
+        let from = std::mem::replace(&mut self.state, to);
+        Transition {
+            from,
+            to,
+            consecutive_failures: self.consecutive_failures,
+        }
+    }
+}
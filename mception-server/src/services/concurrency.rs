@@ -0,0 +1,142 @@
+use crate::core::{LeafMcpConfig, McpTransport};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Concurrent requests to a stdio leaf MCP without a limit of its own, as a
+/// single process only handles a few at once
+pub const DEFAULT_STDIO_LIMIT: u32 = 4;
+
+/// Concurrent requests to an HTTPS or SSE leaf MCP without a limit of its own
+pub const DEFAULT_REMOTE_LIMIT: u32 = 64;
+
+/// How many requests may be in flight to the leaf MCP at once: its own
+/// `max_concurrent_requests`, or the default for its transport. Builtin leaf
+/// MCPs aren't limited.
+pub fn limit(leaf: &LeafMcpConfig) -> Option<u32> {
+    match &leaf.transport {
+        McpTransport::Builtin { .. } => leaf.max_concurrent_requests,
+        McpTransport::Stdio { .. } => {
+            Some(leaf.max_concurrent_requests.unwrap_or(DEFAULT_STDIO_LIMIT))
+        }
+        McpTransport::Https { .. } | McpTransport::Sse { .. } => {
+            Some(leaf.max_concurrent_requests.unwrap_or(DEFAULT_REMOTE_LIMIT))
+        }
+    }
+}
+
+/// Requests in flight to a leaf MCP, as shown by the admin API
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConcurrencyStatus {
+    pub in_flight: usize,
+    /// Requests waiting for one in flight to finish
+    pub waiting: usize,
+    /// Unlimited if `None`
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug)]
+struct Slots {
+    limit: u32,
+    semaphore: Arc<Semaphore>,
+    waiting: Arc<AtomicUsize>,
+}
+
+/// Slots for requests in flight to leaf MCPs, one semaphore per leaf MCP
+#[derive(Debug, Default)]
+pub struct ConcurrencyLimits {
+    slots: Mutex<HashMap<String, Slots>>,
+}
+
+impl ConcurrencyLimits {
+    /// Take a slot for a request to the leaf MCP, waiting up to `wait` while
+    /// all are taken, freed when the permit is dropped. No permit is needed
+    /// without a limit. Refused with the limit once `wait` has passed.
+    pub async fn acquire(
+        &self,
+        leaf_id: &str,
+        leaf: &LeafMcpConfig,
+        wait: Duration,
+    ) -> Result<Option<OwnedSemaphorePermit>, u32> {
+        let Some(limit) = limit(leaf) else {
+            return Ok(None);
+        };
+        let (semaphore, waiting) = {
+            let mut slots = self.slots.lock().unwrap();
+            let slots = slots
+                .entry(leaf_id.to_string())
+                .and_modify(|slots| {
+                    // Requests in flight under the old limit finish on the old
+                    // semaphore
+                    if slots.limit != limit {
+                        *slots = Slots::new(limit);
+                    }
+                })
+                .or_insert_with(|| Slots::new(limit));
+            (slots.semaphore.clone(), slots.waiting.clone())
+        };
+        if let Ok(permit) = semaphore.clone().try_acquire_owned() {
+            return Ok(Some(permit));
+        }
+        let waited = Waiting::new(waiting);
+        let acquired = tokio::time::timeout(wait, semaphore.acquire_owned()).await;
+        drop(waited);
+        match acquired {
+            Ok(Ok(permit)) => Ok(Some(permit)),
+            _ => Err(limit),
+        }
+    }
+
+    /// Drop the slots of a deleted leaf MCP
+    pub fn forget(&self, leaf_id: &str) {
+        self.slots.lock().unwrap().remove(leaf_id);
+    }
+
+    pub fn status(&self, leaf_id: &str, leaf: &LeafMcpConfig) -> ConcurrencyStatus {
+        let limit = limit(leaf);
+        let slots = self.slots.lock().unwrap();
+        let Some(slots) = slots
+            .get(leaf_id)
+            .filter(|slots| Some(slots.limit) == limit)
+        else {
+            return ConcurrencyStatus {
+                limit,
+                ..Default::default()
+            };
+        };
+        ConcurrencyStatus {
+            in_flight: slots.limit as usize - slots.semaphore.available_permits(),
+            waiting: slots.waiting.load(Ordering::Relaxed),
+            limit,
+        }
+    }
+}
+
+/// Counts a request as waiting for a slot, also when its caller goes away
+struct Waiting(Arc<AtomicUsize>);
+
+impl Waiting {
+    fn new(waiting: Arc<AtomicUsize>) -> Self {
+        waiting.fetch_add(1, Ordering::Relaxed);
+        Self(waiting)
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Slots {
+    fn new(limit: u32) -> Self {
+        Self {
+            limit,
+            semaphore: Arc::new(Semaphore::new(limit as usize)),
+            waiting: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
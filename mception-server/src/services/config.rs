@@ -1,19 +1,129 @@
+use crate::core::labels::{self, LabelSelector};
+use crate::core::merge;
 use crate::core::{
-    AgentConfig, AuditAction, AuditLogEntry, AuditTarget, LeafMcpConfig, MceptionError,
-    MceptionResult, ServerConfig, StorageError, ValidationError,
+    AgentConfig, AgentRemoteConfig, AuditAction, AuditFailurePolicy, AuditLogEntry, AuditPolicy,
+    AuditQuery, AuditTarget, BUNDLE_PREFIX, BackupInfo, BundleConfig, ConfigurationError,
+    ConfirmationPolicy, CreateAgentRequest, GrantSource, HistoricalConfig, HistorySource,
+    ImportCounts, ImportSummary, LeafMcpConfig, MAX_INSTRUCTIONS_LEN, MceptionError,
+    MceptionResult, McpConnection, McpTool, McpTransport, MigrationInfo, MigrationStatus, REDACTED,
+    RegistrationPolicy, RemoteBundle, RemoteConfigMetadata, RemoteExpiry, RemoteMcpEntry,
+    RemoteMcpKind, RevisionChange, ServerConfig, ServerMetadata, StorageError, ToolFilter,
+    ValidationError,
 };
+use crate::services::access_log::{AccessEntry, AccessLog};
+use crate::services::agent_changes::{self, AgentChange};
+use crate::services::audit_buffer::AuditBuffer;
+use crate::services::audit_policy::{self, AuditFilter};
+use crate::services::authorization::{self, AccessDecision};
+use crate::services::availability::{
+    self, AgentAvailability, AvailabilityTracker, FleetAvailability,
+};
+use crate::services::bulk::{self, BulkDeleteOutcome, BulkDeletePlan, BulkKind, BulkSelection};
+use crate::services::circuit_breaker::{
+    BreakerState, BreakerStatus, CircuitBreakers, Outcome, Transition,
+};
+use crate::services::concurrency::{ConcurrencyLimits, ConcurrencyStatus};
+use crate::services::config_events::{self, ConfigChanged};
+use crate::services::config_limits::{self, BlobSize, ConfigLimits};
+use crate::services::confirmation::{self, Confirmations, Rejection};
+use crate::services::connections::{ConnectionService, DEFAULT_AGENT_TIMEOUT};
+use crate::services::consistency::{self, ConsistencyReport, Discontinuity};
+use crate::services::cycles;
+use crate::services::debug_capture::{CaptureState, DebugCaptures};
+use crate::services::discovery::{self, Discovery};
+use crate::services::expiry::{self, Expiring, ExpiryNotifier};
+use crate::services::fault_injection::{FaultInjections, FaultSpec, FaultState, InjectedFault};
+use crate::services::health::{self, HealthStatuses};
+use crate::services::https::{HttpsForwarder, StreamedResponse};
+use crate::services::idempotency::{self, IdempotencyStore};
+use crate::services::ids::{self, IdGenerator, IdKind, SlugIds};
+use crate::services::internals::{ExpiryUsage, Internals, ResourceLimits};
+use crate::services::leaf_sessions::{LeafSessions, SessionInfo};
+use crate::services::logging::{LogControl, LogSettings};
+use crate::services::rate_limit::{self, RateLimiter};
+use crate::services::registration_policy::{self, ReportEntry};
+use crate::services::replicas::{self, Candidate, ReplicaGroup, ReplicaHealth};
+use crate::services::revision::ConfigRevision;
+use crate::services::safety::{self, LeafMcpSafety};
+use crate::services::shutdown::{Lifecycle, ShutdownReport};
+use crate::services::sse::SseConnections;
+use crate::services::stdio::{ProcessStatus, StdioProcesses};
+use crate::services::sync::SyncTargets;
+use crate::services::tool_shaping::{self, Shaping};
+use crate::services::tools::{self, AgentTools, ToolCache, ToolCall, ToolListing};
+use crate::services::{deadline, history, https, retry, sandbox};
+use crate::storage::audit_chain::AuditChainReport;
+use crate::storage::journal::{self, ConfigChange, ConfigJournal, JournalEntry};
 use crate::storage::providers::{AuditStorage, ConfigStorage};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::error;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock, broadcast, watch};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
+/// Default tolerance for audit timestamps running backwards
+pub const DEFAULT_MAX_CLOCK_SKEW: chrono::Duration = chrono::Duration::seconds(1);
+
 /// The main service for managing MCeption server configuration and operations
 pub struct ConfigService {
     config: Arc<RwLock<ServerConfig>>,
     config_storage: Arc<dyn ConfigStorage>,
     audit_storage: Arc<dyn AuditStorage>,
+    debug_captures: DebugCaptures,
+    stdio_processes: StdioProcesses,
+    sse_connections: SseConnections,
+    https_forwarder: HttpsForwarder,
+    tool_cache: ToolCache,
+    leaf_sessions: LeafSessions,
+    idempotency: IdempotencyStore,
+    confirmations: Confirmations,
+    rate_limiter: RateLimiter,
+    limits: ResourceLimits,
+    config_limits: ConfigLimits,
+    lifecycle: Lifecycle,
+    log_control: Option<LogControl>,
+    availability: Option<AvailabilityTracker>,
+    /// Only present when the server was started with fault injection enabled
+    fault_injections: Option<FaultInjections>,
+    journal: Option<ConfigJournal>,
+    id_generator: Box<dyn IdGenerator>,
+    /// How far an audit entry's timestamp may lag its predecessor's before it is reported
+    max_clock_skew: chrono::Duration,
+    /// How long an agent counts as connected after it was last seen
+    agent_staleness: chrono::Duration,
+    /// How often agents' WebSockets are pinged
+    agent_ping_interval: chrono::Duration,
+    /// How long before their expiry agent tokens and grants are reported
+    expiry_window: chrono::Duration,
+    /// Sends the events of tokens and grants about to expire or expiring
+    expiry_notifier: ExpiryNotifier,
+    /// Bound for forwarded requests to leaf MCPs without their own timeout
+    default_leaf_timeout: std::time::Duration,
+    /// The configuration as of the last commit, held while committing
+    committed: Mutex<ServerConfig>,
+    /// Revision of the last commit, for watchers waiting on changes
+    revisions: watch::Sender<u64>,
+    /// Each committed revision with the agents it concerns
+    config_changes: broadcast::Sender<ConfigChanged>,
+    /// Applies the configuration's audit policy
+    audit_filter: AuditFilter,
+    /// Entries held back while the audit storage fails
+    audit_buffer: AuditBuffer,
+    replica_health: ReplicaHealth,
+    circuit_breakers: CircuitBreakers,
+    /// Slots for requests in flight to leaf MCPs
+    concurrency_limits: ConcurrencyLimits,
+    /// Outcomes of the background probes of leaf MCPs
+    health_statuses: HealthStatuses,
+    /// How often leaf MCPs are probed in the background, zero if they aren't
+    health_interval: std::time::Duration,
+    /// Only present when the server was started with `--access-log`
+    access_log: Option<AccessLog>,
+    /// Leave out who made the changes in agents' change feeds
+    mask_change_actors: bool,
+    sync_targets: SyncTargets,
 }
 
 impl ConfigService {
@@ -25,23 +135,521 @@ impl ConfigService {
             config: Arc::new(RwLock::new(ServerConfig::default())),
             config_storage,
             audit_storage,
+            debug_captures: DebugCaptures::default(),
+            stdio_processes: StdioProcesses::default(),
+            sse_connections: SseConnections::default(),
+            https_forwarder: HttpsForwarder::default(),
+            tool_cache: ToolCache::default(),
+            leaf_sessions: LeafSessions::default(),
+            idempotency: IdempotencyStore::default(),
+            confirmations: Confirmations::default(),
+            rate_limiter: RateLimiter::default(),
+            limits: ResourceLimits::default(),
+            config_limits: ConfigLimits::default(),
+            lifecycle: Lifecycle::default(),
+            log_control: None,
+            availability: None,
+            fault_injections: None,
+            journal: None,
+            id_generator: Box::new(SlugIds),
+            max_clock_skew: DEFAULT_MAX_CLOCK_SKEW,
+            agent_staleness: availability::HEARTBEAT_GRACE,
+            agent_ping_interval: availability::PING_INTERVAL,
+            expiry_window: expiry::DEFAULT_WARNING_WINDOW,
+            expiry_notifier: ExpiryNotifier::default(),
+            default_leaf_timeout: deadline::DEFAULT_LEAF_TIMEOUT,
+            committed: Mutex::new(ServerConfig::default()),
+            revisions: watch::Sender::new(0),
+            config_changes: broadcast::channel(config_events::CAPACITY).0,
+            audit_filter: AuditFilter::default(),
+            audit_buffer: AuditBuffer::default(),
+            replica_health: ReplicaHealth::default(),
+            circuit_breakers: CircuitBreakers::default(),
+            concurrency_limits: ConcurrencyLimits::default(),
+            health_statuses: HealthStatuses::default(),
+            health_interval: health::DEFAULT_HEALTH_INTERVAL,
+            access_log: None,
+            mask_change_actors: false,
+            sync_targets: SyncTargets::default(),
+        }
+    }
+
+    /// Cap the in-memory structures that grow with use
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.tool_cache = ToolCache::with_capacity(limits.tool_cache_entries);
+        self.limits = limits;
+        self
+    }
+
+    pub fn limits(&self) -> ResourceLimits {
+        self.limits
+    }
+
+    /// Bound the free-form `config` of leaf MCPs and agents
+    pub fn with_config_limits(mut self, config_limits: ConfigLimits) -> Self {
+        self.config_limits = config_limits;
+        self
+    }
+
+    pub fn config_limits(&self) -> ConfigLimits {
+        self.config_limits
+    }
+
+    /// Hold back at most `capacity` audit entries under the `buffer` failure policy
+    pub fn with_audit_buffer_capacity(mut self, capacity: usize) -> Self {
+        self.audit_buffer = AuditBuffer::with_capacity(capacity);
+        self
+    }
+
+    /// Entries held back while the audit storage fails
+    pub fn audit_buffer(&self) -> &AuditBuffer {
+        &self.audit_buffer
+    }
+
+    /// Refuse a `config` that grows beyond the limits or, when it changed,
+    /// violates the schema the configuration references for its kind.
+    /// `before` is the `config` an update replaces.
+    fn check_config_blob(
+        &self,
+        server_config: &ServerConfig,
+        schema_id: Option<&String>,
+        subject: &str,
+        before: Option<&serde_json::Value>,
+        after: &serde_json::Value,
+    ) -> MceptionResult<()> {
+        let exceeded = self.config_limits.grown(
+            before.map(BlobSize::of).unwrap_or_default(),
+            BlobSize::of(after),
+        );
+        if !exceeded.is_empty() {
+            return Err(MceptionError::Validation(ValidationError::LimitExceeded(
+                format!("The config of {} {}", subject, exceeded.join(", ")),
+            )));
+        }
+        if before == Some(after) {
+            return Ok(());
+        }
+        if let Some(schema_id) = schema_id
+            && let Some(schema) = server_config.config_schemas.get(schema_id)
+        {
+            config_limits::check_schema(schema, after).map_err(|e| {
+                MceptionError::Validation(ValidationError::SchemaViolation(format!(
+                    "{} of {} (schema '{}')",
+                    e, subject, schema_id
+                )))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Approximate usage of the in-memory structures, along with the
+    /// agent connections kept outside of the service
+    pub async fn internals(&self, connections: &ConnectionService) -> Internals {
+        let expiring = self.expiring().await;
+        let expired_tokens = expiring.tokens.iter().filter(|token| token.expired).count();
+        let expired_grants = expiring.grants.iter().filter(|grant| grant.expired).count();
+        Internals {
+            tool_cache: self.tool_cache.usage(),
+            agent_connections: connections.usage(),
+            debug_captures: self.debug_captures.usage().await,
+            stdio_processes: self.stdio_processes.usage(),
+            audit: self.audit_buffer.usage(),
+            expiry: ExpiryUsage {
+                tokens_expiring: expiring.tokens.len() - expired_tokens,
+                tokens_expired: expired_tokens,
+                grants_expiring: expiring.grants.len() - expired_grants,
+                grants_expired: expired_grants,
+                window_seconds: self.expiry_window.num_seconds(),
+            },
         }
     }
 
-    /// Load configuration from storage
+    /// Enable runtime changes of the log filter
+    pub fn with_log_control(mut self, log_control: LogControl) -> Self {
+        self.log_control = Some(log_control);
+        self
+    }
+
+    /// Track agent availability
+    pub fn with_availability(mut self, availability: AvailabilityTracker) -> Self {
+        self.availability = Some(availability);
+        self
+    }
+
+    /// Allow injecting faults into leaf MCP forwarding
+    /// Record each request forwarded to a leaf MCP in `access_log`
+    pub fn with_access_log(mut self, access_log: AccessLog) -> Self {
+        self.access_log = Some(access_log);
+        self
+    }
+
+    /// Leave out who made the changes in agents' change feeds
+    pub fn with_masked_change_actors(mut self) -> Self {
+        self.mask_change_actors = true;
+        self
+    }
+
+    pub fn masks_change_actors(&self) -> bool {
+        self.mask_change_actors
+    }
+
+    pub fn with_fault_injection(mut self) -> Self {
+        self.fault_injections = Some(FaultInjections::default());
+        self
+    }
+
+    /// Issue and check confirmation tokens with `confirmations`
+    pub fn with_confirmations(mut self, confirmations: Confirmations) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
+
+    /// Journal each mutation before acknowledging it, and replay journaled
+    /// mutations missing from the stored configuration when loading it
+    pub fn with_journal(mut self, journal: ConfigJournal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Generate ids of leaf MCPs and agents created without one with this scheme
+    pub fn with_id_generator(mut self, id_generator: Box<dyn IdGenerator>) -> Self {
+        self.id_generator = id_generator;
+        self
+    }
+
+    /// Report audit entries timestamped more than `max_clock_skew` before
+    /// their predecessor
+    pub fn with_max_clock_skew(mut self, max_clock_skew: chrono::Duration) -> Self {
+        self.max_clock_skew = max_clock_skew;
+        self
+    }
+
+    /// Mark agents not seen for `agent_staleness` as disconnected
+    pub fn with_agent_staleness(mut self, agent_staleness: chrono::Duration) -> Self {
+        self.agent_staleness = agent_staleness;
+        self
+    }
+
+    /// Report agent tokens and grants expiring within `expiry_window`
+    pub fn with_expiry_window(mut self, expiry_window: chrono::Duration) -> Self {
+        self.expiry_window = expiry_window;
+        self
+    }
+
+    /// Post the events of expiring tokens and grants to `webhook` as JSON
+    pub fn with_expiry_webhook(mut self, webhook: Option<String>) -> Self {
+        self.expiry_notifier = ExpiryNotifier::new(webhook);
+        self
+    }
+
+    /// Ping agents' WebSockets every `agent_ping_interval`
+    pub fn with_agent_ping_interval(mut self, agent_ping_interval: chrono::Duration) -> Self {
+        self.agent_ping_interval = agent_ping_interval;
+        self
+    }
+
+    /// Open a leaf MCP's circuit breaker after `failure_threshold`
+    /// consecutive failed forwards, for `cooldown`
+    pub fn with_circuit_breakers(
+        mut self,
+        failure_threshold: u32,
+        cooldown: std::time::Duration,
+    ) -> Self {
+        self.circuit_breakers = CircuitBreakers::new(failure_threshold, cooldown);
+        self
+    }
+
+    /// Probe leaf MCPs in the background every `health_interval`, or not at
+    /// all if it is zero
+    pub fn with_health_interval(mut self, health_interval: std::time::Duration) -> Self {
+        self.health_interval = health_interval;
+        self
+    }
+
+    /// Bound forwarded requests to leaf MCPs without a timeout of their own
+    /// by `default_leaf_timeout`
+    pub fn with_default_leaf_timeout(mut self, default_leaf_timeout: std::time::Duration) -> Self {
+        self.default_leaf_timeout = default_leaf_timeout;
+        self
+    }
+
+    /// Keep HTTPS leaf MCP sessions in these, e.g. persisted across restarts
+    pub fn with_leaf_sessions(mut self, leaf_sessions: LeafSessions) -> Self {
+        self.leaf_sessions = leaf_sessions;
+        self
+    }
+
+    /// Keep the answers to admin requests with an idempotency key in `store`
+    pub fn with_idempotency(mut self, store: IdempotencyStore) -> Self {
+        self.idempotency = store;
+        self
+    }
+
+    pub fn idempotency(&self) -> &IdempotencyStore {
+        &self.idempotency
+    }
+
+    pub fn confirmations(&self) -> &Confirmations {
+        &self.confirmations
+    }
+
+    /// Limit the requests of each agent to the agent and leaf MCP routes to
+    /// `per_minute`, unless its `config` sets another limit
+    pub fn with_agent_rate_limit(mut self, per_minute: Option<u32>) -> Self {
+        self.rate_limiter = RateLimiter::new(per_minute);
+        self
+    }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    /// Locations of the configuration and audit log storage
+    pub fn storage_locations(&self) -> (String, String) {
+        (
+            self.config_storage.location(),
+            self.audit_storage.location(),
+        )
+    }
+
+    /// File the configuration is stored in, absolute, if it is kept in one
+    pub fn config_path(&self) -> Option<std::path::PathBuf> {
+        self.config_storage
+            .path()
+            .and_then(|path| std::path::absolute(path).ok())
+    }
+
+    /// Load configuration from storage, replaying journaled mutations it is missing
     pub async fn load_configuration(&self) -> MceptionResult<()> {
-        let config = self.config_storage.load_config().await?;
+        let mut committed = self.committed.lock().await;
+        let mut config = self.config_storage.load_config().await?;
+
+        if let Some(journal) = &self.journal {
+            let replayed = Self::replay_journal(journal, &mut config).await?;
+            if replayed > 0 {
+                info!(
+                    "Replayed {} mutations from {} up to revision {}",
+                    replayed,
+                    journal.location(),
+                    config.metadata.revision
+                );
+                self.config_storage.save_config(&config).await?;
+            }
+            journal.truncate().await?;
+        }
+
+        // Agents reconnect to this run, whatever the configuration says
+        for agent in config.agents.values_mut() {
+            agent.is_connected = false;
+        }
+
+        // Allow lists written before grants had sources hold direct grants,
+        // which need no sources recorded
+        let migrated = config
+            .agents
+            .values_mut()
+            .map(AgentConfig::migrate_grant_sources)
+            .filter(|changed| *changed)
+            .count();
+        if migrated > 0 {
+            info!("Dropped stale grant sources of {} agents", migrated);
+        }
+
+        *committed = config.clone();
+        self.revisions.send_replace(config.metadata.revision);
+        self.audit_filter.set_policy(config.audit.clone());
         *self.config.write().await = config;
         Ok(())
     }
 
+    /// Apply the journal entries newer than the configuration's revision.
+    /// Returns the number of entries applied.
+    async fn replay_journal(
+        journal: &ConfigJournal,
+        config: &mut ServerConfig,
+    ) -> MceptionResult<usize> {
+        let saved_revision = config.metadata.revision;
+        let mut replayed = 0;
+        for entry in journal.entries().await? {
+            if entry.revision <= config.metadata.revision {
+                continue;
+            }
+
+            entry.apply(config);
+            if let Err(e) = Self::validate_changes(config, &entry.changes) {
+                return Err(MceptionError::Configuration(
+                    ConfigurationError::InvalidConfiguration(format!(
+                        "Journal entry for revision {} ({}) in {} can't be replayed: {}. \
+                         Fix or remove the entry, or move the journal aside to start from \
+                         the saved configuration at revision {}",
+                        entry.revision,
+                        entry.operation,
+                        journal.location(),
+                        e,
+                        saved_revision
+                    )),
+                ));
+            }
+            replayed += 1;
+        }
+        Ok(replayed)
+    }
+
+    /// Validate the objects written by journaled changes, as the mutations did
+    fn validate_changes(config: &ServerConfig, changes: &[ConfigChange]) -> MceptionResult<()> {
+        let mismatch = |key: &str, id: &str| {
+            MceptionError::Validation(ValidationError::InvalidFormat(format!(
+                "'{}' is stored under '{}'",
+                id, key
+            )))
+        };
+        for change in changes {
+            match change {
+                ConfigChange::PutLeafMcp { id, config: leaf } => leaf.validate(id)?,
+                ConfigChange::PutAgent { id, config: agent } => {
+                    if &agent.agent_id != id {
+                        return Err(mismatch(id, &agent.agent_id));
+                    }
+                    if let Some(mcp_id) = agent
+                        .allowed_mcp_ids
+                        .iter()
+                        .find(|mcp_id| !config.has_mcp(mcp_id))
+                    {
+                        return Err(MceptionError::Validation(ValidationError::InvalidFormat(
+                            format!("MCP with ID '{}' does not exist", mcp_id),
+                        )));
+                    }
+                }
+                ConfigChange::PutBundle {
+                    name,
+                    config: bundle,
+                } => {
+                    if &bundle.name != name {
+                        return Err(mismatch(name, &bundle.name));
+                    }
+                    Self::validate_bundle_members(config, &bundle.members)?;
+                }
+                ConfigChange::SetRegistrationPolicy { policy } => {
+                    if let Some(policy) = policy {
+                        registration_policy::validate(policy).map_err(|e| {
+                            MceptionError::Validation(ValidationError::InvalidFormat(e))
+                        })?;
+                    }
+                }
+                ConfigChange::SetAuditPolicy { policy } => {
+                    audit_policy::validate(policy).map_err(|e| {
+                        MceptionError::Validation(ValidationError::InvalidFormat(e))
+                    })?;
+                }
+                ConfigChange::DeleteLeafMcp { .. }
+                | ConfigChange::DeleteAgent { .. }
+                | ConfigChange::DeleteBundle { .. } => {}
+            }
+        }
+        Ok(())
+    }
+
     /// Save current configuration to storage
     pub async fn save_configuration(&self) -> MceptionResult<()> {
-        let config = self.config.read().await;
-        self.config_storage.save_config(&*config).await?;
+        self.commit("save").await
+    }
+
+    /// Make the in-memory configuration durable after a mutation: journal
+    /// what changed since the last commit, then save the full configuration.
+    /// Once journaled, a failed save is recovered from the journal.
+    async fn commit(&self, operation: &str) -> MceptionResult<()> {
+        let mut committed = self.committed.lock().await;
+        let config = self.config.read().await.clone();
+
+        let Some(journal) = &self.journal else {
+            self.config_storage.save_config(&config).await?;
+            self.publish(&committed, &config);
+            *committed = config;
+            return Ok(());
+        };
+
+        // Concurrent mutations may already have been journaled by an earlier commit
+        let changes = journal::diff(&committed, &config);
+        if !changes.is_empty() {
+            journal
+                .append(&JournalEntry {
+                    revision: config.metadata.revision,
+                    timestamp: config.metadata.last_modified,
+                    operation: operation.to_string(),
+                    changes,
+                })
+                .await?;
+        }
+
+        match self.config_storage.save_config(&config).await {
+            Ok(()) => {
+                if let Err(e) = journal.truncate().await {
+                    warn!("Failed to truncate {}: {}", journal.location(), e);
+                }
+            }
+            Err(e) => warn!(
+                "Failed to save configuration, revision {} is kept in {}: {}",
+                config.metadata.revision,
+                journal.location(),
+                e
+            ),
+        }
+        self.publish(&committed, &config);
+        *committed = config;
         Ok(())
     }
 
+    /// Notify watchers of the revision committed after `before`, and the
+    /// agents it concerns of the change
+    fn publish(&self, before: &ServerConfig, after: &ServerConfig) {
+        self.revisions.send_replace(after.metadata.revision);
+        let agents = config_events::affected_agents(before, after);
+        if !agents.is_empty() {
+            // Without subscribers nobody needs to know
+            let _ = self.config_changes.send(ConfigChanged {
+                revision: after.metadata.revision,
+                agents,
+            });
+        }
+    }
+
+    /// Revision of the current configuration
+    pub async fn revision(&self) -> u64 {
+        self.config.read().await.metadata.revision
+    }
+
+    /// Revision, modification time and hash of the current configuration
+    pub async fn config_revision(&self) -> ConfigRevision {
+        ConfigRevision::of(&*self.config.read().await)
+    }
+
+    /// Notified of each revision committed
+    pub fn subscribe_revisions(&self) -> watch::Receiver<u64> {
+        self.revisions.subscribe()
+    }
+
+    /// Notified of each revision committed that changes an agent's remote
+    /// configuration, see [`config_events::affected_agents`]
+    pub fn subscribe_config_changes(&self) -> broadcast::Receiver<ConfigChanged> {
+        self.config_changes.subscribe()
+    }
+
+    /// What was pushed to the configuration's sync targets, see [`super::sync::run`]
+    pub fn sync_targets(&self) -> &SyncTargets {
+        &self.sync_targets
+    }
+
+    /// Wait until a revision other than `since` is committed, or `timeout`
+    /// elapsed. Returns the revision then current and whether it changed.
+    pub async fn watch_revision(&self, since: u64, timeout: Duration) -> (ConfigRevision, bool) {
+        let mut revisions = self.revisions.subscribe();
+        let changed = matches!(
+            tokio::time::timeout(timeout, revisions.wait_for(|&revision| revision != since)).await,
+            Ok(Ok(_))
+        );
+        (self.config_revision().await, changed)
+    }
+
     /// Get a read-only copy of the current server configuration
     pub async fn get_configuration(&self) -> ServerConfig {
         self.config.read().await.clone()
@@ -52,191 +660,2649 @@ impl ConfigService {
         self.config_storage.backup_config().await
     }
 
-    /// Log an audit entry
-    async fn audit_log(
+    /// List the stored configuration backups, oldest first
+    pub async fn list_backups(&self) -> MceptionResult<Vec<BackupInfo>> {
+        self.config_storage.list_backups().await
+    }
+
+    /// Replace the current configuration with the one stored in a backup
+    pub async fn restore_backup(
         &self,
-        action: AuditAction,
-        target: AuditTarget,
+        name: &str,
         actor: Option<String>,
         reason: Option<String>,
-        details: serde_json::Value,
     ) -> MceptionResult<()> {
-        let entry = AuditLogEntry {
-            id: Uuid::new_v4().to_string(),
-            timestamp: Utc::now(),
-            action,
+        let mut server_config = self.config.write().await;
+        let mut restored = self.config_storage.load_backup(name).await?;
+        // The restored configuration is a new revision, so older journal entries never apply to it
+        restored.metadata.revision = server_config.metadata.revision;
+        restored.update_last_modified();
+        let summary = serde_json::json!({
+            "backup": name,
+            "leaf_mcps": restored.leaf_mcps.len(),
+            "agents": restored.agents.len(),
+        });
+        let metadata = restored.metadata.clone();
+        *server_config = restored;
+        drop(server_config);
+
+        self.mark_discontinuity(
+            Discontinuity::BackupRestore,
+            actor.clone(),
+            &metadata,
+            serde_json::json!({ "backup": name }),
+        )
+        .await?;
+        self.audit_log(
+            AuditAction::Update,
+            AuditTarget::Server,
             actor,
-            target,
             reason,
-            details,
-        };
+            summary,
+        )
+        .await?;
 
-        self.audit_storage.append_entry(&entry).await?;
+        self.commit("restore_backup").await?;
         Ok(())
     }
 
-    // Leaf MCP operations
-
-    /// Create a new leaf MCP configuration
-    pub async fn create_leaf_mcp(
+    /// Replace the configuration with an imported one, or with `merge` add and
+    /// overwrite its leaf MCPs, agents and bundles. The result is validated as
+    /// a whole and nothing is applied when it's invalid. The configuration is
+    /// backed up before.
+    pub async fn import_configuration(
         &self,
-        id: String,
-        config: LeafMcpConfig,
+        imported: ServerConfig,
+        merge: bool,
         actor: Option<String>,
         reason: Option<String>,
-    ) -> MceptionResult<()> {
-        // Validation
-        if id.trim().is_empty() {
-            return Err(MceptionError::Validation(ValidationError::InvalidFormat(
-                "MCP ID cannot be empty".to_string(),
-            )));
+    ) -> MceptionResult<ImportSummary> {
+        let mut server_config = self.config.write().await;
+        let mut result = Self::imported_configuration(&server_config, imported, merge)?;
+        Self::validate_import(&result)?;
+
+        let previous_backup = self.config_storage.backup_config().await?;
+        let summary = Self::import_summary(&server_config, &result, merge, previous_backup);
+        result.update_last_modified();
+        let metadata = result.metadata.clone();
+        *server_config = result;
+        drop(server_config);
+
+        self.mark_discontinuity(
+            Discontinuity::Import,
+            actor.clone(),
+            &metadata,
+            serde_json::json!({ "previous_backup": summary.previous_backup }),
+        )
+        .await?;
+        self.audit_log(
+            AuditAction::Update,
+            AuditTarget::Server,
+            actor,
+            reason,
+            serde_json::to_value(&summary).unwrap_or_default(),
+        )
+        .await?;
+
+        self.commit("import_configuration").await?;
+        Ok(summary)
+    }
+
+    /// The configuration an import results in
+    fn imported_configuration(
+        current: &ServerConfig,
+        mut imported: ServerConfig,
+        merge: bool,
+    ) -> MceptionResult<ServerConfig> {
+        for (id, leaf) in &mut imported.leaf_mcps {
+            if leaf.id.is_empty() {
+                leaf.id = id.clone();
+            }
+            // Exports through the admin API redact secrets, keep the ones known
+            if let Some(existing) = current.leaf_mcps.get(id) {
+                leaf.transport.restore_redacted(&existing.transport);
+            }
+        }
+        for (id, agent) in &mut imported.agents {
+            let existing = current.agents.get(id);
+            // Connections belong to this run, not to the exported configuration
+            agent.is_connected = existing.is_some_and(|agent| agent.is_connected);
+            // Exports through the admin API redact tokens, keep the ones known
+            if agent.auth_token.as_deref() == Some(REDACTED) {
+                agent.auth_token = existing
+                    .and_then(|agent| agent.auth_token.clone())
+                    .ok_or_else(|| {
+                        MceptionError::Validation(ValidationError::InvalidFormat(format!(
+                            "Token of agent '{}' is redacted and not known",
+                            id
+                        )))
+                    })
+                    .map(Some)?;
+            }
         }
 
-        let mut server_config = self.config.write().await;
+        let mut result = if merge {
+            let mut result = current.clone();
+            result.leaf_mcps.extend(imported.leaf_mcps);
+            result.agents.extend(imported.agents);
+            result.bundles.extend(imported.bundles);
+            result
+        } else {
+            imported
+        };
+        // The result is a new revision, so older journal entries never apply to it
+        result.metadata = current.metadata.clone();
+        Ok(result)
+    }
 
-        if server_config.leaf_mcps.contains_key(&id) {
-            return Err(MceptionError::Storage(StorageError::AlreadyExists(
-                format!("Leaf MCP with ID '{}' already exists", id),
+    /// Check an imported configuration for everything mutations refuse
+    fn validate_import(config: &ServerConfig) -> MceptionResult<()> {
+        if let Some(id) = config
+            .leaf_mcps
+            .keys()
+            .find(|id| config.agents.contains_key(*id))
+        {
+            return Err(MceptionError::Validation(ValidationError::InvalidFormat(
+                format!("'{}' is both a leaf MCP and an agent", id),
             )));
         }
+        // Every object, as if it was written anew
+        Self::validate_changes(config, &journal::diff(&ServerConfig::default(), config))?;
+        if let Some(chain) = cycles::find_cycles(config).first() {
+            return Err(MceptionError::Validation(
+                ValidationError::CircularReference(cycles::describe(chain)),
+            ));
+        }
+        Ok(())
+    }
 
-        server_config.leaf_mcps.insert(id.clone(), config.clone());
-        server_config.update_last_modified();
+    fn import_summary(
+        before: &ServerConfig,
+        after: &ServerConfig,
+        merge: bool,
+        previous_backup: String,
+    ) -> ImportSummary {
+        let mut summary = ImportSummary {
+            merge,
+            previous_backup,
+            leaf_mcps: ImportCounts::default(),
+            agents: ImportCounts::default(),
+            bundles: ImportCounts::default(),
+        };
+        for change in journal::diff(before, after) {
+            let (counts, existed) = match &change {
+                ConfigChange::PutLeafMcp { id, .. } => (
+                    &mut summary.leaf_mcps,
+                    Some(before.leaf_mcps.contains_key(id)),
+                ),
+                ConfigChange::PutAgent { id, .. } => {
+                    (&mut summary.agents, Some(before.agents.contains_key(id)))
+                }
+                ConfigChange::PutBundle { name, .. } => (
+                    &mut summary.bundles,
+                    Some(before.bundles.contains_key(name)),
+                ),
+                ConfigChange::DeleteLeafMcp { .. } => (&mut summary.leaf_mcps, None),
+                ConfigChange::DeleteAgent { .. } => (&mut summary.agents, None),
+                ConfigChange::DeleteBundle { .. } => (&mut summary.bundles, None),
+                ConfigChange::SetRegistrationPolicy { .. }
+                | ConfigChange::SetAuditPolicy { .. } => {
+                    continue;
+                }
+            };
+            match existed {
+                Some(true) => counts.changed += 1,
+                Some(false) => counts.added += 1,
+                None => counts.removed += 1,
+            }
+        }
+        summary
+    }
 
-        // Release the lock before async operations
-        drop(server_config);
+    // Consistency
+
+    /// Compare the configuration with the audit log
+    pub async fn check_consistency(&self) -> MceptionResult<ConsistencyReport> {
+        let metadata = self.config.read().await.metadata.clone();
+        let entries = self.audit_storage.load_entries().await?;
+        Ok(consistency::check(&metadata, &entries))
+    }
+
+    /// Record a detected gap in the audit log, unless its newest entry was
+    /// already reported. Returns whether an entry was written.
+    pub async fn record_consistency_gap(&self, report: &ConsistencyReport) -> MceptionResult<bool> {
+        let entries = self.audit_storage.load_entries().await?;
+        let Some(newest) = report.gap.last() else {
+            return Ok(false);
+        };
+        if report.already_recorded(&entries) {
+            return Ok(false);
+        }
 
         self.audit_log(
-            AuditAction::Create,
-            AuditTarget::LeafMcp { id: id.clone() },
-            actor,
-            reason,
-            serde_json::to_value(&config).unwrap_or_default(),
+            AuditAction::ConsistencyGap,
+            AuditTarget::Server,
+            Some("system".to_string()),
+            Some("Consistency gap detected".to_string()),
+            serde_json::json!({
+                "revision": report.revision,
+                "last_modified": report.last_modified,
+                "gap_entries": report.gap.len(),
+                "oldest_entry_id": report.gap[0].id,
+                "newest_entry_id": newest.id,
+            }),
         )
         .await?;
+        Ok(true)
+    }
 
-        self.save_configuration().await?;
-        Ok(())
+    /// Accept the current gap after reviewing it, so it is no longer reported
+    pub async fn acknowledge_consistency_gap(
+        &self,
+        actor: Option<String>,
+    ) -> MceptionResult<ConsistencyReport> {
+        let report = self.check_consistency().await?;
+        if !report.consistent {
+            let metadata = self.config.read().await.metadata.clone();
+            self.mark_discontinuity(
+                Discontinuity::Acknowledged,
+                actor,
+                &metadata,
+                serde_json::json!({ "gap_entries": report.gap.len() }),
+            )
+            .await?;
+        }
+        Ok(report)
     }
 
-    /// Read a leaf MCP configuration
-    pub async fn get_leaf_mcp(
+    /// Write a marker entry explaining why the configuration doesn't reflect
+    /// the audit entries before it
+    async fn mark_discontinuity(
         &self,
-        id: &str,
+        discontinuity: Discontinuity,
         actor: Option<String>,
-    ) -> MceptionResult<LeafMcpConfig> {
-        let config = self.config.read().await;
-        let mcp_config = config
-            .leaf_mcps
-            .get(id)
-            .ok_or_else(|| {
-                MceptionError::Storage(StorageError::NotFound(format!(
-                    "Leaf MCP with ID '{}' not found",
-                    id
-                )))
-            })?
-            .clone();
+        metadata: &ServerMetadata,
+        mut details: serde_json::Value,
+    ) -> MceptionResult<()> {
+        details["discontinuity"] = serde_json::json!(discontinuity);
+        details["revision"] = metadata.revision.into();
+        details["last_modified"] = serde_json::json!(metadata.last_modified);
+        self.audit_log(
+            AuditAction::Discontinuity,
+            AuditTarget::Server,
+            actor,
+            Some(discontinuity.explanation().to_string()),
+            details,
+        )
+        .await
+    }
+
+    // Lifecycle
+
+    /// Uptime, draining state and in-flight forwards of the running server
+    pub fn lifecycle(&self) -> &Lifecycle {
+        &self.lifecycle
+    }
+
+    /// Persist what has to survive the shutdown and report on it. Call after
+    /// the server stopped serving; `aborted` is the number of forwarded
+    /// requests still running when draining gave up, `timed_out` whether it
+    /// did. Agents are saved as disconnected and the shutdown is audited.
+    pub async fn shutdown(&self, reason: String, aborted: u64, timed_out: bool) -> ShutdownReport {
+        if self.availability.is_some()
+            && let Err(e) = self.checkpoint_availability().await
+        {
+            error!("Failed to persist agent availability: {}", e);
+        }
+        let disconnected = self
+            .config
+            .write()
+            .await
+            .agents
+            .values_mut()
+            .filter_map(|agent| std::mem::take(&mut agent.is_connected).then_some(()))
+            .count();
+        // Stopped before the configuration was loaded, there's nothing to save
+        if !self.lifecycle.is_loading()
+            && let Err(e) = self.save_configuration().await
+        {
+            error!("Failed to save configuration: {}", e);
+        }
+
+        self.stdio_processes.stop_all().await;
+        self.sse_connections.stop_all().await;
+
+        // Entries still held back are lost with the process
+        if let Err(e) = self.flush_audit_buffer().await {
+            let held = self.audit_buffer.len();
+            self.lifecycle.record_unsent_audit_entries(held as u64);
+            error!("Failed to write {} held back audit entries: {}", held, e);
+        }
+
+        let connected_agents = match &self.availability {
+            Some(availability) => Some(availability.connected_agents().await),
+            None => None,
+        };
+        let revision = self.config.read().await.metadata.revision;
+        let report = self
+            .lifecycle
+            .report(reason, connected_agents, aborted, timed_out, revision);
+        let mut details = serde_json::to_value(&report).unwrap_or_default();
+        details["agents_disconnected"] = disconnected.into();
+        if let Err(e) = self
+            .audit_log(
+                AuditAction::Shutdown,
+                AuditTarget::Server,
+                Some("system".to_string()),
+                Some(report.reason.clone()),
+                details,
+            )
+            .await
+        {
+            error!("Failed to audit the shutdown: {}", e);
+        }
+        report
+    }
+
+    /// Running processes of stdio leaf MCPs
+    pub fn stdio_processes(&self) -> &StdioProcesses {
+        &self.stdio_processes
+    }
+
+    /// Open event streams of SSE leaf MCPs
+    pub fn sse_connections(&self) -> &SseConnections {
+        &self.sse_connections
+    }
+
+    /// Client forwarding requests to HTTPS leaf MCPs
+    pub fn https_forwarder(&self) -> &HttpsForwarder {
+        &self.https_forwarder
+    }
+
+    /// Sessions of HTTPS leaf MCPs reused when listing their tools
+    pub fn leaf_sessions(&self) -> &LeafSessions {
+        &self.leaf_sessions
+    }
+
+    /// The session a leaf MCP's tools are listed in, if any
+    pub async fn leaf_mcp_session(&self, leaf_mcp_id: &str) -> MceptionResult<Option<SessionInfo>> {
+        if !self.config.read().await.leaf_mcps.contains_key(leaf_mcp_id) {
+            return Err(MceptionError::Storage(StorageError::NotFound(format!(
+                "Leaf MCP with ID '{}' not found",
+                leaf_mcp_id
+            ))));
+        }
+        Ok(self.leaf_sessions.info(leaf_mcp_id))
+    }
+
+    /// Status of a stdio leaf MCP's process, `None` until one was started
+    pub async fn leaf_mcp_process(
+        &self,
+        leaf_mcp_id: &str,
+    ) -> MceptionResult<Option<ProcessStatus>> {
+        if !self.config.read().await.leaf_mcps.contains_key(leaf_mcp_id) {
+            return Err(MceptionError::Storage(StorageError::NotFound(format!(
+                "Leaf MCP with ID '{}' not found",
+                leaf_mcp_id
+            ))));
+        }
+        Ok(self.stdio_processes.status(leaf_mcp_id))
+    }
+
+    /// Tools of a leaf MCP, listed by the leaf MCP itself. A listing younger
+    /// than [`tools::TOOL_CACHE_TTL`] is reused unless `refresh` is set.
+    pub async fn leaf_mcp_tools(
+        &self,
+        leaf_mcp_id: &str,
+        refresh: bool,
+    ) -> MceptionResult<ToolListing> {
+        let leaf = self
+            .config
+            .read()
+            .await
+            .leaf_mcps
+            .get(leaf_mcp_id)
+            .cloned()
+            .ok_or_else(|| {
+                MceptionError::Storage(StorageError::NotFound(format!(
+                    "Leaf MCP with ID '{}' not found",
+                    leaf_mcp_id
+                )))
+            })?;
+        if !refresh && let Some(listing) = self.tool_cache.get(leaf_mcp_id, &leaf) {
+            return Ok(listing);
+        }
+
+        let listing = tools::fetch(
+            leaf_mcp_id,
+            &leaf,
+            &self.stdio_processes,
+            &self.sse_connections,
+            &self.https_forwarder,
+            &self.leaf_sessions,
+        )
+        .await?;
+        self.tool_cache.insert(leaf_mcp_id, &leaf, &listing);
+        Ok(listing)
+    }
+
+    /// A tool of a leaf MCP by its name, from the listing of
+    /// [`Self::leaf_mcp_tools`]. A cached listing without the tool is
+    /// refreshed, the tool may have been added since.
+    pub async fn leaf_mcp_tool(
+        &self,
+        leaf_mcp_id: &str,
+        tool_name: &str,
+    ) -> MceptionResult<McpTool> {
+        let find = |listing: ToolListing| {
+            listing
+                .tools
+                .into_iter()
+                .find(|tool| tool.name == tool_name)
+        };
+        let listing = self.leaf_mcp_tools(leaf_mcp_id, false).await?;
+        let cached = listing.cached;
+        let tool = match find(listing) {
+            None if cached => find(self.leaf_mcp_tools(leaf_mcp_id, true).await?),
+            tool => tool,
+        };
+        tool.ok_or_else(|| {
+            MceptionError::Storage(StorageError::NotFound(format!(
+                "Leaf MCP '{}' has no tool '{}'",
+                leaf_mcp_id, tool_name
+            )))
+        })
+    }
+
+    /// Call a tool of a leaf MCP for an admin and return the call's result,
+    /// see [`tools::send`]. Arguments aren't checked, see
+    /// [`tools::argument_errors`]. The call is audited with the tool's name but
+    /// without its arguments, which may hold secrets.
+    pub async fn call_leaf_mcp_tool(
+        &self,
+        leaf_mcp_id: &str,
+        call: &ToolCall,
+        actor: Option<String>,
+    ) -> MceptionResult<serde_json::Value> {
+        let leaf = self
+            .config
+            .read()
+            .await
+            .leaf_mcps
+            .get(leaf_mcp_id)
+            .cloned()
+            .ok_or_else(|| {
+                MceptionError::Storage(StorageError::NotFound(format!(
+                    "Leaf MCP with ID '{}' not found",
+                    leaf_mcp_id
+                )))
+            })?;
+        let result = tools::send(
+            leaf_mcp_id,
+            &leaf,
+            &self.stdio_processes,
+            &self.sse_connections,
+            &self.https_forwarder,
+            &self.leaf_sessions,
+            &call.request(),
+        )
+        .await
+        .and_then(tools::result);
+        let details = serde_json::json!({
+            "tool": call.name,
+            "is_error": result.as_ref().ok().map(|result| result["isError"] == true),
+            "error": result.as_ref().err().map(ToString::to_string)
+        });
+        if let Err(e) = self
+            .audit_log(
+                AuditAction::CallTool,
+                AuditTarget::LeafMcp {
+                    id: leaf_mcp_id.to_string(),
+                },
+                actor,
+                None,
+                details,
+            )
+            .await
+        {
+            warn!(
+                "Failed to audit tool call on leaf MCP '{}': {}",
+                leaf_mcp_id, e
+            );
+        }
+        result
+    }
+
+    /// Send `method` with `params` to a leaf MCP for an admin and return its
+    /// JSON-RPC response as it is, with any error the leaf MCP answered, see
+    /// [`tools::send`]
+    pub async fn leaf_mcp_request(
+        &self,
+        leaf_mcp_id: &str,
+        method: &str,
+        params: serde_json::Value,
+    ) -> MceptionResult<serde_json::Value> {
+        let (leaf, request) = self
+            .leaf_jsonrpc_request(leaf_mcp_id, method, params)
+            .await?;
+        tools::send(
+            leaf_mcp_id,
+            &leaf,
+            &self.stdio_processes,
+            &self.sse_connections,
+            &self.https_forwarder,
+            &self.leaf_sessions,
+            &request,
+        )
+        .await
+    }
+
+    /// Send `method` with `params` to a leaf MCP like
+    /// [`Self::leaf_mcp_request`], passing its `result` on as it arrives
+    /// where the transport allows, see [`tools::send_streamed`]
+    pub async fn leaf_mcp_request_streamed(
+        &self,
+        leaf_mcp_id: &str,
+        method: &str,
+        params: serde_json::Value,
+    ) -> MceptionResult<StreamedResponse> {
+        let (leaf, request) = self
+            .leaf_jsonrpc_request(leaf_mcp_id, method, params)
+            .await?;
+        tools::send_streamed(
+            leaf_mcp_id,
+            &leaf,
+            &self.stdio_processes,
+            &self.sse_connections,
+            &self.https_forwarder,
+            &self.leaf_sessions,
+            &request,
+        )
+        .await
+    }
+
+    /// A leaf MCP and the JSON-RPC request of `method` with `params` to it
+    async fn leaf_jsonrpc_request(
+        &self,
+        leaf_mcp_id: &str,
+        method: &str,
+        params: serde_json::Value,
+    ) -> MceptionResult<(LeafMcpConfig, serde_json::Value)> {
+        let leaf = self
+            .config
+            .read()
+            .await
+            .leaf_mcps
+            .get(leaf_mcp_id)
+            .cloned()
+            .ok_or_else(|| {
+                MceptionError::Storage(StorageError::NotFound(format!(
+                    "Leaf MCP with ID '{}' not found",
+                    leaf_mcp_id
+                )))
+            })?;
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params
+        });
+        Ok((leaf, request))
+    }
+
+    /// Tools of every MCP an agent may use. Leaf MCPs are listed as by
+    /// [`Self::leaf_mcp_tools`], agents over their WebSocket. An MCP that
+    /// can't be listed is reported in `errors` without failing the others.
+    /// Tools the agent may not call are left out, see
+    /// [`AgentConfig::tool_filters`]. The listing is then cut down by the
+    /// agent's shaping settings and `filter`, see [`tool_shaping::shape`].
+    pub async fn agent_tools(
+        &self,
+        agent_id: &str,
+        connections: &ConnectionService,
+        refresh: bool,
+        filter: Option<String>,
+    ) -> MceptionResult<AgentTools> {
+        let invalid = |message| MceptionError::Validation(ValidationError::InvalidFormat(message));
+        let (allowed, mut shaping, priorities, tool_filters) = {
+            let config = self.config.read().await;
+            let agent = config.agents.get(agent_id).ok_or_else(|| {
+                MceptionError::Storage(StorageError::NotFound(format!(
+                    "Agent with ID '{}' not found",
+                    agent_id
+                )))
+            })?;
+            let shaping = Shaping::of_agent(&agent.config).map_err(invalid)?;
+            let allowed: Vec<(String, bool)> = authorization::allowed_mcps(&config, agent)
+                .into_iter()
+                .map(|mcp_id| {
+                    let is_agent = config.agents.contains_key(&mcp_id);
+                    (mcp_id, is_agent)
+                })
+                .collect();
+            let priorities: BTreeMap<String, Vec<String>> = allowed
+                .iter()
+                .filter_map(|(mcp_id, _)| {
+                    let leaf = config.leaf_mcps.get(mcp_id)?;
+                    let names =
+                        tool_shaping::names(&leaf.config, tool_shaping::TOOL_PRIORITY_KEY).ok()?;
+                    Some((mcp_id.clone(), names))
+                })
+                .collect();
+            (allowed, shaping, priorities, agent.tool_filters.clone())
+        };
+        shaping.filter = filter;
+
+        let mut aggregated = AgentTools::default();
+        for (mcp_id, is_agent) in allowed {
+            let listing = if is_agent {
+                tools::fetch_agent(&mcp_id, connections, DEFAULT_AGENT_TIMEOUT).await
+            } else {
+                self.leaf_mcp_tools(&mcp_id, refresh).await
+            };
+            match listing {
+                Ok(mut listing) => {
+                    let filter = tool_filters.get(&mcp_id);
+                    listing
+                        .tools
+                        .retain(|tool| authorization::tool_allowed(filter, &tool.name));
+                    aggregated.tools.insert(mcp_id, listing.tools);
+                }
+                Err(e) => {
+                    warn!("Failed to list the tools of '{}': {}", mcp_id, e);
+                    aggregated.errors.insert(mcp_id, e.to_string());
+                }
+            }
+        }
+        aggregated.shaping = tool_shaping::shape(&mut aggregated.tools, &priorities, &shaping);
+        aggregated.merge();
+        Ok(aggregated)
+    }
+
+    // Debug capture
+
+    /// In-memory payload captures of forwarded leaf MCP requests
+    pub fn debug_captures(&self) -> &DebugCaptures {
+        &self.debug_captures
+    }
+
+    /// Start capturing forwarded payloads of a leaf MCP for `window`
+    pub async fn enable_debug_capture(
+        &self,
+        leaf_id: &str,
+        window: chrono::Duration,
+        max_bytes: usize,
+        actor: Option<String>,
+    ) -> MceptionResult<CaptureState> {
+        if !self.config.read().await.leaf_mcps.contains_key(leaf_id) {
+            return Err(MceptionError::Storage(StorageError::NotFound(format!(
+                "Leaf MCP with ID '{}' not found",
+                leaf_id
+            ))));
+        }
+
+        let state = self.debug_captures.enable(leaf_id, window, max_bytes).await;
+        self.audit_log(
+            AuditAction::EnableDebugCapture,
+            AuditTarget::LeafMcp {
+                id: leaf_id.to_string(),
+            },
+            actor,
+            None,
+            serde_json::json!({
+                "session_id": state.session_id,
+                "window_seconds": window.num_seconds(),
+                "max_bytes": max_bytes,
+                "expires_at": state.expires_at,
+            }),
+        )
+        .await?;
+        Ok(state)
+    }
+
+    /// Stop capturing and discard the captured payloads of a leaf MCP
+    pub async fn disable_debug_capture(
+        &self,
+        leaf_id: &str,
+        actor: Option<String>,
+    ) -> MceptionResult<bool> {
+        let was_active = self.debug_captures.disable(leaf_id).await;
+        self.audit_log(
+            AuditAction::DisableDebugCapture,
+            AuditTarget::LeafMcp {
+                id: leaf_id.to_string(),
+            },
+            actor,
+            None,
+            serde_json::json!({ "was_active": was_active, "expired": false }),
+        )
+        .await?;
+        Ok(was_active)
+    }
+
+    /// End a capture session once its window has passed
+    pub async fn expire_debug_capture(
+        &self,
+        leaf_id: &str,
+        session_id: &str,
+    ) -> MceptionResult<()> {
+        if self.debug_captures.expire(leaf_id, session_id).await {
+            self.audit_log(
+                AuditAction::DisableDebugCapture,
+                AuditTarget::LeafMcp {
+                    id: leaf_id.to_string(),
+                },
+                Some("system".to_string()),
+                None,
+                serde_json::json!({ "session_id": session_id, "expired": true }),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    // Fault injection
+
+    fn fault_injections(&self) -> MceptionResult<&FaultInjections> {
+        self.fault_injections.as_ref().ok_or_else(|| {
+            MceptionError::Configuration(ConfigurationError::InvalidConfiguration(
+                "Fault injection is disabled, start the server with --enable-fault-injection"
+                    .to_string(),
+            ))
+        })
+    }
+
+    /// Inject faults into requests forwarded to a leaf MCP for `window`
+    pub async fn inject_faults(
+        &self,
+        leaf_id: &str,
+        spec: FaultSpec,
+        window: chrono::Duration,
+        actor: Option<String>,
+        reason: Option<String>,
+    ) -> MceptionResult<FaultState> {
+        let faults = self.fault_injections()?;
+        if !self.config.read().await.leaf_mcps.contains_key(leaf_id) {
+            return Err(MceptionError::Storage(StorageError::NotFound(format!(
+                "Leaf MCP with ID '{}' not found",
+                leaf_id
+            ))));
+        }
+        spec.validate().map_err(ValidationError::ValueOutOfRange)?;
+
+        let state = faults.inject(leaf_id, spec, window).await;
+        self.audit_log(
+            AuditAction::InjectFaults,
+            AuditTarget::LeafMcp {
+                id: leaf_id.to_string(),
+            },
+            actor,
+            reason,
+            serde_json::json!({
+                "session_id": state.session_id,
+                "latency_ms": state.spec.latency_ms,
+                "error_rate": state.spec.error_rate,
+                "window_seconds": window.num_seconds(),
+                "expires_at": state.expires_at,
+            }),
+        )
+        .await?;
+        Ok(state)
+    }
+
+    /// Stop injecting faults into a leaf MCP
+    pub async fn clear_faults(&self, leaf_id: &str, actor: Option<String>) -> MceptionResult<bool> {
+        let was_active = self.fault_injections()?.clear(leaf_id).await;
+        self.audit_log(
+            AuditAction::ClearFaults,
+            AuditTarget::LeafMcp {
+                id: leaf_id.to_string(),
+            },
+            actor,
+            None,
+            serde_json::json!({ "was_active": was_active, "expired": false }),
+        )
+        .await?;
+        Ok(was_active)
+    }
+
+    /// End a fault injection once its window has passed
+    pub async fn expire_faults(&self, leaf_id: &str, session_id: &str) -> MceptionResult<()> {
+        if self.fault_injections()?.expire(leaf_id, session_id).await {
+            self.audit_log(
+                AuditAction::ClearFaults,
+                AuditTarget::LeafMcp {
+                    id: leaf_id.to_string(),
+                },
+                Some("system".to_string()),
+                None,
+                serde_json::json!({ "session_id": session_id, "expired": true }),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Active fault injection of a leaf MCP
+    pub async fn faults(&self, leaf_id: &str) -> MceptionResult<Option<FaultState>> {
+        Ok(self.fault_injections()?.get(leaf_id).await)
+    }
+
+    /// Whether fault injection is enabled, and the active injections if so
+    pub async fn fault_injection_status(
+        &self,
+    ) -> Option<std::collections::HashMap<String, FaultState>> {
+        match &self.fault_injections {
+            Some(faults) => Some(faults.list().await),
+            None => None,
+        }
+    }
+
+    // Replicas
+
+    /// Health of leaf MCPs as observed while forwarding
+    pub fn replica_health(&self) -> &ReplicaHealth {
+        &self.replica_health
+    }
+
+    /// Replica groups with the health of each replica
+    pub async fn replica_groups(&self) -> Vec<ReplicaGroup> {
+        let unhealthy = self.replica_health.unhealthy().await;
+        replicas::groups(&*self.config.read().await, &unhealthy)
+    }
+
+    /// The leaf MCP to forward a call for `leaf_id` to on behalf of
+    /// `agent_id`. For a leaf MCP in a replica group this is the replica the
+    /// agent may use in its region, or any healthy one, keeping `leaf_id`
+    /// while it's as good as any.
+    pub async fn select_replica(&self, leaf_id: &str, agent_id: Option<&str>) -> String {
+        let unhealthy = self.replica_health.unhealthy().await;
+        let config = self.config.read().await;
+        let Some(group) = config
+            .leaf_mcps
+            .get(leaf_id)
+            .and_then(|leaf| leaf.replica_group.as_deref())
+        else {
+            return leaf_id.to_string();
+        };
+        let agent = agent_id.and_then(|agent_id| config.agents.get(agent_id));
+        let allowed = agent.map(|agent| authorization::allowed_mcps(&config, agent));
+        let candidates: Vec<Candidate> = config
+            .leaf_mcps
+            .iter()
+            .filter(|(id, leaf)| {
+                leaf.replica_group.as_deref() == Some(group)
+                    && allowed.as_ref().is_none_or(|allowed| allowed.contains(id))
+            })
+            .map(|(id, leaf)| Candidate {
+                id,
+                region: leaf.region.as_deref(),
+                healthy: !unhealthy.contains_key(id),
+            })
+            .collect();
+        let region = agent.and_then(|agent| agent.region.as_deref());
+        replicas::select(&candidates, region, Some(leaf_id))
+            .unwrap_or(leaf_id)
+            .to_string()
+    }
+
+    /// The fault to apply to a request about to be forwarded to a leaf MCP
+    pub async fn next_fault(&self, leaf_id: &str) -> Option<InjectedFault> {
+        match &self.fault_injections {
+            Some(faults) => faults.next(leaf_id).await,
+            None => None,
+        }
+    }
+
+    // Agent availability
+
+    fn availability(&self) -> MceptionResult<&AvailabilityTracker> {
+        self.availability.as_ref().ok_or_else(|| {
+            MceptionError::Configuration(ConfigurationError::InvalidConfiguration(
+                "Agent availability tracking is not enabled".to_string(),
+            ))
+        })
+    }
+
+    /// Record that an agent contacted the server, marking it connected. Only
+    /// the transition to connected is audited, not every contact.
+    pub async fn mark_agent_seen(&self, agent_id: &str) {
+        if let Some(availability) = &self.availability {
+            availability.touch(agent_id).await;
+        }
+        let connected = {
+            let mut config = self.config.write().await;
+            let Some(agent) = config.agents.get_mut(agent_id) else {
+                return;
+            };
+            agent.last_seen = Some(Utc::now());
+            !std::mem::replace(&mut agent.is_connected, true)
+        };
+        if connected {
+            self.audit_connection_change(agent_id, true, "seen").await;
+        }
+    }
+
+    /// Mark an agent disconnected, e.g. when its WebSocket closed. `cause` is
+    /// recorded in the audit log.
+    pub async fn mark_agent_disconnected(&self, agent_id: &str, cause: &str) {
+        let disconnected = {
+            let mut config = self.config.write().await;
+            config
+                .agents
+                .get_mut(agent_id)
+                .is_some_and(|agent| std::mem::replace(&mut agent.is_connected, false))
+        };
+        if disconnected {
+            self.audit_connection_change(agent_id, false, cause).await;
+        }
+    }
+
+    /// Mark connected agents not seen for `agent_staleness` as disconnected.
+    /// Returns their ids.
+    pub async fn sweep_stale_agents(&self) -> Vec<String> {
+        let cutoff = Utc::now() - self.agent_staleness;
+        let stale: Vec<String> = {
+            let mut config = self.config.write().await;
+            config
+                .agents
+                .iter_mut()
+                .filter(|(_, agent)| {
+                    agent.is_connected && agent.last_seen.is_none_or(|seen| seen < cutoff)
+                })
+                .map(|(agent_id, agent)| {
+                    agent.is_connected = false;
+                    agent_id.clone()
+                })
+                .collect()
+        };
+        for agent_id in &stale {
+            self.audit_connection_change(agent_id, false, "stale").await;
+        }
+        stale
+    }
+
+    /// How long an agent counts as connected after it was last seen
+    pub fn agent_staleness(&self) -> chrono::Duration {
+        self.agent_staleness
+    }
+
+    /// How often agents' WebSockets are pinged
+    pub fn agent_ping_interval(&self) -> chrono::Duration {
+        self.agent_ping_interval
+    }
+
+    /// Bound for forwarded requests to leaf MCPs without their own timeout
+    pub fn default_leaf_timeout(&self) -> std::time::Duration {
+        self.default_leaf_timeout
+    }
+
+    /// How often leaf MCPs are probed in the background, zero if they aren't
+    pub fn health_interval(&self) -> std::time::Duration {
+        self.health_interval
+    }
+
+    /// Outcomes of the background probes of leaf MCPs
+    pub fn health_statuses(&self) -> &HealthStatuses {
+        &self.health_statuses
+    }
+
+    /// Record that an admin used a deprecated feature of the admin API
+    pub async fn audit_deprecated_use(&self, actor: &str, deprecation: &str, api_version: u32) {
+        if let Err(e) = self
+            .audit_log(
+                AuditAction::DeprecatedUse,
+                AuditTarget::Server,
+                Some(actor.to_string()),
+                None,
+                serde_json::json!({ "deprecation": deprecation, "api_version": api_version }),
+            )
+            .await
+        {
+            warn!(
+                "Failed to audit use of {} by '{}': {}",
+                deprecation, actor, e
+            );
+        }
+    }
+
+    /// Whether a forward to a leaf MCP may go through its circuit breaker,
+    /// refused with the time left until the breaker lets a probe through
+    pub async fn admit_forward(&self, leaf_mcp_id: &str) -> Result<(), std::time::Duration> {
+        if let Some(transition) = self.circuit_breakers.admit(leaf_mcp_id)? {
+            self.audit_breaker_transition(leaf_mcp_id, transition, "system", "cooldown_elapsed")
+                .await;
+        }
+        Ok(())
+    }
+
+    /// Count the outcome of an admitted forward towards the leaf MCP's
+    /// circuit breaker
+    pub async fn record_forward(&self, leaf_mcp_id: &str, outcome: Outcome) {
+        if let Some(transition) = self.circuit_breakers.record(leaf_mcp_id, outcome) {
+            let cause = match (transition.from, outcome) {
+                (BreakerState::HalfOpen, Outcome::Success) => "probe_succeeded",
+                (BreakerState::HalfOpen, _) => "probe_failed",
+                (_, Outcome::Success) => "forward_succeeded",
+                _ => "failure_threshold",
+            };
+            self.audit_breaker_transition(leaf_mcp_id, transition, "system", cause)
+                .await;
+        }
+    }
+
+    /// State of a leaf MCP's circuit breaker
+    pub async fn leaf_mcp_circuit_breaker(
+        &self,
+        leaf_mcp_id: &str,
+    ) -> MceptionResult<BreakerStatus> {
+        if !self.config.read().await.leaf_mcps.contains_key(leaf_mcp_id) {
+            return Err(MceptionError::Storage(StorageError::NotFound(format!(
+                "Leaf MCP with ID '{}' not found",
+                leaf_mcp_id
+            ))));
+        }
+        Ok(self.circuit_breakers.status(leaf_mcp_id))
+    }
+
+    /// Slots for requests in flight to leaf MCPs
+    pub fn concurrency_limits(&self) -> &ConcurrencyLimits {
+        &self.concurrency_limits
+    }
+
+    /// Requests in flight to a leaf MCP and its limit
+    pub async fn leaf_mcp_concurrency(
+        &self,
+        leaf_mcp_id: &str,
+    ) -> MceptionResult<ConcurrencyStatus> {
+        let config = self.config.read().await;
+        let leaf = config.leaf_mcps.get(leaf_mcp_id).ok_or_else(|| {
+            MceptionError::Storage(StorageError::NotFound(format!(
+                "Leaf MCP with ID '{}' not found",
+                leaf_mcp_id
+            )))
+        })?;
+        Ok(self.concurrency_limits.status(leaf_mcp_id, leaf))
+    }
+
+    /// Close a leaf MCP's circuit breaker, e.g. once the leaf MCP is known
+    /// to be back
+    pub async fn reset_circuit_breaker(
+        &self,
+        leaf_mcp_id: &str,
+        actor: Option<String>,
+    ) -> MceptionResult<BreakerStatus> {
+        self.leaf_mcp_circuit_breaker(leaf_mcp_id).await?;
+        if let Some(transition) = self.circuit_breakers.reset(leaf_mcp_id) {
+            let actor = actor.as_deref().unwrap_or("system");
+            self.audit_breaker_transition(leaf_mcp_id, transition, actor, "reset")
+                .await;
+        }
+        Ok(self.circuit_breakers.status(leaf_mcp_id))
+    }
+
+    async fn audit_breaker_transition(
+        &self,
+        leaf_mcp_id: &str,
+        transition: Transition,
+        actor: &str,
+        cause: &str,
+    ) {
+        info!(
+            "Circuit breaker of leaf MCP '{}' is now {} ({}, {} consecutive failures)",
+            leaf_mcp_id,
+            transition.to.as_str(),
+            cause,
+            transition.consecutive_failures
+        );
+        let details = serde_json::json!({
+            "from": transition.from,
+            "to": transition.to,
+            "cause": cause,
+            "consecutive_failures": transition.consecutive_failures
+        });
+        if let Err(e) = self
+            .audit_log(
+                AuditAction::CircuitBreaker,
+                AuditTarget::LeafMcp {
+                    id: leaf_mcp_id.to_string(),
+                },
+                Some(actor.to_string()),
+                None,
+                details,
+            )
+            .await
+        {
+            warn!(
+                "Failed to audit the circuit breaker of leaf MCP '{}': {}",
+                leaf_mcp_id, e
+            );
+        }
+    }
+
+    /// Record that an agent or client exceeded its rate limit
+    pub async fn audit_rate_limited(&self, target: AuditTarget, details: serde_json::Value) {
+        if let Err(e) = self
+            .audit_log(
+                AuditAction::RateLimited,
+                target,
+                Some("system".to_string()),
+                None,
+                details,
+            )
+            .await
+        {
+            warn!("Failed to audit a rate limited caller: {}", e);
+        }
+    }
+
+    pub async fn audit_idempotent_retry(
+        &self,
+        actor: &str,
+        key: &str,
+        outcome: &str,
+        method: &axum::http::Method,
+        path: &str,
+        status: u16,
+    ) {
+        let details = serde_json::json!({
+            "outcome": outcome,
+            "method": method.as_str(),
+            "path": path,
+            "status": status
+        });
+        if let Err(e) = self
+            .audit_log(
+                AuditAction::IdempotentRetry,
+                AuditTarget::Server,
+                Some(actor.to_string()),
+                None,
+                details,
+            )
+            .await
+        {
+            warn!("Failed to audit retry with key '{}': {}", key, e);
+        }
+    }
+
+    async fn audit_connection_change(&self, agent_id: &str, connected: bool, cause: &str) {
+        info!(
+            "Agent '{}' is now {} ({})",
+            agent_id,
+            if connected {
+                "connected"
+            } else {
+                "disconnected"
+            },
+            cause
+        );
+        if let Err(e) = self
+            .audit_log(
+                AuditAction::ConnectionChange,
+                AuditTarget::Agent {
+                    id: agent_id.to_string(),
+                },
+                Some("system".to_string()),
+                None,
+                serde_json::json!({ "connected": connected, "cause": cause }),
+            )
+            .await
+        {
+            warn!(
+                "Failed to audit connection change of agent '{}': {}",
+                agent_id, e
+            );
+        }
+    }
+
+    /// How long before their expiry agent tokens and grants are reported
+    pub fn expiry_window(&self) -> chrono::Duration {
+        self.expiry_window
+    }
+
+    pub fn expiry_notifier(&self) -> &ExpiryNotifier {
+        &self.expiry_notifier
+    }
+
+    /// Agent tokens and grants expiring within the warning window, or expired
+    pub async fn expiring(&self) -> Expiring {
+        let config = self.config.read().await;
+        expiry::expiring(&config, self.expiry_window, Utc::now())
+    }
+
+    /// Summarize the expiring tokens and grants in the audit log, if any
+    /// expire. Returns them.
+    pub async fn audit_expiring(&self) -> Expiring {
+        let expiring = self.expiring().await;
+        if expiring.is_empty() {
+            return expiring;
+        }
+        let details = serde_json::json!({
+            "window_seconds": self.expiry_window.num_seconds(),
+            "tokens": expiring.tokens,
+            "grants": expiring.grants
+        });
+        if let Err(e) = self
+            .audit_log(
+                AuditAction::ExpirySummary,
+                AuditTarget::Server,
+                Some("system".to_string()),
+                None,
+                details,
+            )
+            .await
+        {
+            warn!(
+                "Failed to audit the expiring agent tokens and grants: {}",
+                e
+            );
+        }
+        expiring
+    }
+
+    /// Persist the availability data
+    pub async fn checkpoint_availability(&self) -> MceptionResult<()> {
+        self.availability()?
+            .checkpoint()
+            .await
+            .map_err(|e| StorageError::from(e).into())
+    }
+
+    /// Availability of an agent over the last `window`
+    pub async fn agent_availability(
+        &self,
+        agent_id: &str,
+        window: chrono::Duration,
+    ) -> MceptionResult<AgentAvailability> {
+        if !self.config.read().await.agents.contains_key(agent_id) {
+            return Err(MceptionError::Storage(StorageError::NotFound(format!(
+                "Agent with ID '{}' not found",
+                agent_id
+            ))));
+        }
+        Ok(self.availability()?.agent(agent_id, window).await)
+    }
+
+    /// Availability of all configured agents over the last `window`
+    pub async fn fleet_availability(
+        &self,
+        window: chrono::Duration,
+    ) -> MceptionResult<FleetAvailability> {
+        let agent_ids: Vec<String> = self.config.read().await.agents.keys().cloned().collect();
+        Ok(self.availability()?.fleet(&agent_ids, window).await)
+    }
+
+    // Runtime logging
+
+    fn log_control(&self) -> MceptionResult<&LogControl> {
+        self.log_control.as_ref().ok_or_else(|| {
+            MceptionError::Configuration(ConfigurationError::InvalidConfiguration(
+                "Runtime log control is not available".to_string(),
+            ))
+        })
+    }
+
+    /// Current log filter directives
+    pub async fn log_settings(&self) -> MceptionResult<LogSettings> {
+        Ok(self.log_control()?.settings().await)
+    }
+
+    /// Replace the log filter directives, optionally reverting to the defaults
+    /// after `revert_after`
+    pub async fn set_log_directives(
+        &self,
+        directives: &str,
+        revert_after: Option<chrono::Duration>,
+        actor: Option<String>,
+        reason: Option<String>,
+    ) -> MceptionResult<LogSettings> {
+        let previous = self.log_control()?.settings().await;
+        let settings = self
+            .log_control()?
+            .set(directives, revert_after.map(|after| Utc::now() + after))
+            .await
+            .map_err(|e| {
+                ValidationError::InvalidFormat(format!(
+                    "Invalid log directives '{}': {}",
+                    directives, e
+                ))
+            })?;
+
+        self.audit_log(
+            AuditAction::SetLogLevel,
+            AuditTarget::Server,
+            actor,
+            reason,
+            serde_json::json!({
+                "previous": previous.directives,
+                "directives": settings.directives,
+                "revert_at": settings.revert_at,
+            }),
+        )
+        .await?;
+        Ok(settings)
+    }
+
+    /// Revert the log filter to the defaults once an auto-revert timer fires
+    pub async fn revert_log_directives(&self, session_id: &str) -> MceptionResult<()> {
+        let control = self.log_control()?;
+        let previous = control.settings().await;
+        let reverted = control
+            .revert(session_id)
+            .await
+            .map_err(ConfigurationError::InvalidConfiguration)?;
+        if reverted {
+            self.audit_log(
+                AuditAction::SetLogLevel,
+                AuditTarget::Server,
+                Some("system".to_string()),
+                Some("Automatic revert".to_string()),
+                serde_json::json!({
+                    "previous": previous.directives,
+                    "directives": previous.default_directives,
+                    "revert_at": null,
+                }),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    // Schema migrations
+
+    /// Report the stored schema version and pending migrations
+    pub async fn migration_status(&self) -> MceptionResult<MigrationStatus> {
+        self.config_storage.migration_status().await
+    }
+
+    /// Apply pending schema migrations to the stored configuration and record
+    /// the outcome in the audit log. Call `load_configuration` afterwards to
+    /// pick up the migrated configuration.
+    pub async fn run_migrations(
+        &self,
+        actor: Option<String>,
+    ) -> MceptionResult<Vec<MigrationInfo>> {
+        let status = self.config_storage.migration_status().await?;
+        if status.pending.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let result = self.config_storage.migrate().await;
+        let details = match &result {
+            Ok(applied) => serde_json::json!({
+                "storage": status.storage,
+                "from_version": status.current_version,
+                "to_version": status.target_version,
+                "applied": applied,
+                "outcome": "success",
+            }),
+            Err(e) => serde_json::json!({
+                "storage": status.storage,
+                "from_version": status.current_version,
+                "to_version": status.target_version,
+                "applied": [],
+                "outcome": "failed",
+                "error": e.to_string(),
+            }),
+        };
+        self.audit_log(
+            AuditAction::Migrate,
+            AuditTarget::Server,
+            actor,
+            Some("Schema migration".to_string()),
+            details,
+        )
+        .await?;
+
+        result
+    }
+
+    // Configuration history
+
+    /// Earliest point in time the configuration can be reconstructed for
+    pub async fn history_start(&self) -> MceptionResult<DateTime<Utc>> {
+        let created_at = self.config.read().await.metadata.created_at;
+        let backups = self.config_storage.list_backups().await?;
+        Ok(backups
+            .iter()
+            .map(|backup| backup.created_at)
+            .fold(created_at, DateTime::min))
+    }
+
+    /// Reconstruct the configuration as it was at `at`, starting from the nearest
+    /// backup before that instant and replaying the audit entries recorded after it
+    pub async fn configuration_as_of(&self, at: DateTime<Utc>) -> MceptionResult<HistoricalConfig> {
+        let created_at = self.config.read().await.metadata.created_at;
+        let backups = self.config_storage.list_backups().await?;
+        // By the time in the name, file times change when backups are copied
+        let snapshot = backups
+            .iter()
+            .filter_map(|backup| Some((BackupInfo::taken_at(&backup.name)?, backup)))
+            .filter(|(taken_at, _)| *taken_at <= at)
+            .max_by_key(|(taken_at, _)| *taken_at);
+
+        let (mut config, snapshot_name, snapshot_time) = match snapshot {
+            Some((taken_at, backup)) => (
+                self.config_storage.load_backup(&backup.name).await?,
+                Some(backup.name.clone()),
+                taken_at,
+            ),
+            // Without a backup, replay everything from the empty initial configuration
+            None if at >= created_at => {
+                let mut initial = ServerConfig::default();
+                initial.metadata.created_at = created_at;
+                initial.metadata.last_modified = created_at;
+                (initial, None, created_at)
+            }
+            None => {
+                return Err(MceptionError::Storage(StorageError::NotFound(format!(
+                    "No configuration history available before {}",
+                    self.history_start().await?.to_rfc3339()
+                ))));
+            }
+        };
+
+        let snapshot_revision = if snapshot_name.is_some() {
+            config.metadata.revision
+        } else {
+            0
+        };
+        let mut source = HistorySource {
+            snapshot: snapshot_name,
+            snapshot_time,
+            snapshot_revision,
+            replayed_entries: 0,
+            last_entry_id: None,
+            skipped_entry_ids: Vec::new(),
+        };
+
+        for entry in self.audit_storage.load_entries().await? {
+            // The snapshot holds the changes up to its revision. Entries
+            // written before entries had revisions go by their time.
+            let in_snapshot = match entry.server_revision {
+                Some(revision) => revision <= snapshot_revision,
+                None => entry.timestamp <= snapshot_time,
+            };
+            if in_snapshot || entry.timestamp > at {
+                continue;
+            }
+
+            let applied = match (&entry.action, &entry.target) {
+                // Backup restores replace the whole configuration
+                (AuditAction::Update, AuditTarget::Server) => {
+                    match entry.details.get("backup").and_then(|b| b.as_str()) {
+                        Some(backup) => match self.config_storage.load_backup(backup).await {
+                            Ok(restored) => {
+                                config = restored;
+                                Ok(true)
+                            }
+                            Err(e) => Err(e.to_string()),
+                        },
+                        None if entry.details.get("previous_backup").is_some() => {
+                            Err("imported configurations are not kept for replay".to_string())
+                        }
+                        None => Err("server update without a backup reference".to_string()),
+                    }
+                }
+                _ => history::apply_entry(&mut config, &entry),
+            };
+
+            match applied {
+                Ok(true) => {
+                    source.replayed_entries += 1;
+                    source.last_entry_id = Some(entry.id.clone());
+                    config.metadata.last_modified = entry.timestamp;
+                }
+                Ok(false) => {}
+                Err(reason) => {
+                    warn!(
+                        "Skipping audit entry {} while reconstructing configuration: {}",
+                        entry.id, reason
+                    );
+                    source.skipped_entry_ids.push(entry.id);
+                }
+            }
+        }
+
+        Ok(HistoricalConfig {
+            as_of: at,
+            read_only: true,
+            source,
+            config,
+        })
+    }
+
+    /// Log an audit entry
+    async fn audit_log(
+        &self,
+        action: AuditAction,
+        target: AuditTarget,
+        actor: Option<String>,
+        reason: Option<String>,
+        details: serde_json::Value,
+    ) -> MceptionResult<()> {
+        self.audit_log_correlated(action, target, actor, reason, details, None)
+            .await
+    }
+
+    /// Append an audit entry belonging to a bulk operation
+    async fn audit_log_correlated(
+        &self,
+        action: AuditAction,
+        target: AuditTarget,
+        actor: Option<String>,
+        reason: Option<String>,
+        details: serde_json::Value,
+        correlation_id: Option<String>,
+    ) -> MceptionResult<()> {
+        let mut entry = Self::audit_entry(action, target, actor, reason, details);
+        entry.correlation_id = correlation_id.or(entry.correlation_id);
+        self.append_audit_entry(entry).await
+    }
+
+    /// Append the audit entry of an update taking a leaf MCP or agent from
+    /// one revision to another
+    async fn audit_update(
+        &self,
+        target: AuditTarget,
+        actor: Option<String>,
+        reason: Option<String>,
+        details: serde_json::Value,
+        revision: RevisionChange,
+    ) -> MceptionResult<()> {
+        let mut entry = Self::audit_entry(AuditAction::Update, target, actor, reason, details);
+        entry.revision = Some(revision);
+        self.append_audit_entry(entry).await
+    }
+
+    fn audit_entry(
+        action: AuditAction,
+        target: AuditTarget,
+        actor: Option<String>,
+        reason: Option<String>,
+        details: serde_json::Value,
+    ) -> AuditLogEntry {
+        AuditLogEntry {
+            id: Uuid::new_v4().to_string(),
+            sequence: 0,
+            timestamp: Utc::now(),
+            action,
+            actor,
+            target,
+            reason,
+            details,
+            correlation_id: confirmation::current_correlation_id(),
+            idempotency_key: idempotency::current_key(),
+            revision: None,
+            server_revision: None,
+            prev_hash: None,
+            hash: None,
+        }
+    }
+
+    async fn append_audit_entry(&self, mut entry: AuditLogEntry) -> MceptionResult<()> {
+        if !self
+            .audit_filter
+            .should_log(&entry.action, &entry.target, entry.actor.as_deref())
+        {
+            return Ok(());
+        }
+        // Changes are audited once made, so this is the revision they are part of
+        entry.server_revision = Some(self.config.read().await.metadata.revision);
+
+        // Entries held back while the storage failed go first, in order
+        let written = match self.audit_buffer.flush(self.audit_storage.as_ref()).await {
+            Ok(_) => self.audit_storage.append_entry(&entry).await,
+            Err(e) => Err(e),
+        };
+        let appended = match written {
+            Ok(appended) => appended,
+            Err(e) => return self.audit_write_failed(entry, e),
+        };
+        if let Some(behind) = appended.behind_predecessor
+            && behind > self.max_clock_skew
+        {
+            self.lifecycle.record_clock_skew();
+            warn!(
+                "Audit entry {} (sequence {}) is timestamped {}ms before its predecessor, \
+                 the system clock may have been set back",
+                entry.id,
+                appended.sequence,
+                behind.num_milliseconds()
+            );
+        }
+        self.audit_storage_recovered().await;
+        Ok(())
+    }
+
+    /// Carry on after `entry` couldn't be written, as the audit policy's
+    /// `on_failure` says
+    fn audit_write_failed(&self, entry: AuditLogEntry, e: MceptionError) -> MceptionResult<()> {
+        self.audit_buffer.mark_failed();
+        match self.audit_filter.on_failure() {
+            AuditFailurePolicy::Fail => {
+                self.lifecycle.record_unsent_audit_entries(1);
+                Err(e)
+            }
+            AuditFailurePolicy::Buffer => {
+                let id = entry.id.clone();
+                if self.audit_buffer.hold(entry) {
+                    warn!(
+                        "Audit entry {} held back, the audit storage failed: {}",
+                        id, e
+                    );
+                } else {
+                    self.lifecycle.record_unsent_audit_entries(1);
+                    error!(
+                        "Audit entry {} lost, the audit buffer is full and the audit storage failed: {}",
+                        id, e
+                    );
+                }
+                Ok(())
+            }
+            AuditFailurePolicy::Drop => {
+                self.audit_buffer.record_loss();
+                self.lifecycle.record_unsent_audit_entries(1);
+                // The whole entry, so it can still be recovered from the logs
+                error!(
+                    "Audit entry dropped, the audit storage failed: {}; entry: {}",
+                    e,
+                    serde_json::to_string(&entry).unwrap_or_default()
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Write the audit entries held back while the audit storage failed.
+    /// Returns how many were written.
+    pub async fn flush_audit_buffer(&self) -> MceptionResult<usize> {
+        let written = self
+            .audit_buffer
+            .flush(self.audit_storage.as_ref())
+            .await
+            .inspect_err(|_| self.audit_buffer.mark_failed())?;
+        if written > 0 {
+            info!(
+                "Wrote {} audit entries held back while the audit storage failed",
+                written
+            );
+            self.audit_storage_recovered().await;
+        }
+        Ok(written)
+    }
+
+    /// Leave the degraded state once nothing is held back anymore, recording
+    /// how many entries were lost while it lasted
+    async fn audit_storage_recovered(&self) {
+        let lost = self.audit_buffer.mark_written();
+        if lost == 0 {
+            return;
+        }
+        let entry = Self::audit_entry(
+            AuditAction::EntriesLost,
+            AuditTarget::Server,
+            Some("system".to_string()),
+            Some("Audit entries were lost while the audit storage failed".to_string()),
+            serde_json::json!({ "lost_entries": lost }),
+        );
+        match self.audit_storage.append_entry(&entry).await {
+            Ok(_) => warn!(
+                "{} audit entries were lost while the audit storage failed",
+                lost
+            ),
+            Err(e) => {
+                self.audit_buffer.restore_losses(lost);
+                self.audit_buffer.mark_failed();
+                error!("Failed to record {} lost audit entries: {}", lost, e);
+            }
+        }
+    }
+
+    /// The id for a new leaf MCP or agent: the given one if it is well-formed,
+    /// otherwise a generated one. Generated ids are free while `config` stays locked.
+    fn resolve_id(
+        &self,
+        config: &ServerConfig,
+        id: Option<String>,
+        kind: IdKind,
+        name: Option<&str>,
+    ) -> MceptionResult<String> {
+        let invalid = |message| MceptionError::Validation(ValidationError::InvalidFormat(message));
+        match id {
+            Some(id) => {
+                ids::validate(&id).map_err(invalid)?;
+                Ok(id)
+            }
+            None => ids::generate(self.id_generator.as_ref(), config, kind, name).map_err(invalid),
+        }
+    }
+
+    /// The id a leaf MCP or agent created now without an id would get
+    pub async fn suggest_id(&self, kind: IdKind, name: Option<&str>) -> MceptionResult<String> {
+        let config = self.config.read().await;
+        self.resolve_id(&config, None, kind, name)
+    }
+
+    /// Name of the scheme generating ids
+    pub fn id_scheme(&self) -> &'static str {
+        self.id_generator.scheme()
+    }
+
+    /// Check a leaf MCP against the registration policy, if one is set
+    fn check_registration_policy(
+        server_config: &ServerConfig,
+        leaf: &LeafMcpConfig,
+    ) -> MceptionResult<()> {
+        match &server_config.registration_policy {
+            Some(policy) => registration_policy::check(policy, leaf).map_err(|violation| {
+                MceptionError::Validation(ValidationError::PolicyViolation(violation.to_string()))
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // Registration policy
+
+    /// The policy leaf MCPs are checked against when registered, if any
+    pub async fn registration_policy(&self) -> Option<RegistrationPolicy> {
+        self.config.read().await.registration_policy.clone()
+    }
+
+    /// Set or clear the registration policy. Already registered leaf MCPs are
+    /// kept; the ones the new policy would not allow are returned.
+    pub async fn set_registration_policy(
+        &self,
+        policy: Option<RegistrationPolicy>,
+        actor: Option<String>,
+        reason: Option<String>,
+    ) -> MceptionResult<Vec<ReportEntry>> {
+        if let Some(policy) = &policy {
+            registration_policy::validate(policy)
+                .map_err(|e| MceptionError::Validation(ValidationError::InvalidFormat(e)))?;
+        }
+
+        let mut server_config = self.config.write().await;
+        let violations = policy
+            .as_ref()
+            .map(|policy| registration_policy::report(policy, &server_config))
+            .unwrap_or_default();
+        let previous = std::mem::replace(&mut server_config.registration_policy, policy.clone());
+        server_config.update_last_modified();
+        drop(server_config);
+
+        self.audit_log(
+            AuditAction::Update,
+            AuditTarget::RegistrationPolicy,
+            actor,
+            reason,
+            serde_json::json!({
+                "previous": previous,
+                "policy": policy,
+                "violations": violations,
+            }),
+        )
+        .await?;
+
+        self.commit("set_registration_policy").await?;
+        Ok(violations)
+    }
+
+    /// Which audit entries are written, per actor
+    pub async fn audit_policy(&self) -> AuditPolicy {
+        self.config.read().await.audit.clone()
+    }
+
+    /// Which dangerous admin actions need a confirmation token
+    pub async fn confirmation_policy(&self) -> ConfirmationPolicy {
+        self.config.read().await.confirmation.clone()
+    }
+
+    /// Replace the confirmation policy. Tokens issued before stay valid for
+    /// the actions still named.
+    pub async fn set_confirmation_policy(
+        &self,
+        policy: ConfirmationPolicy,
+        actor: Option<String>,
+        reason: Option<String>,
+    ) -> MceptionResult<()> {
+        if !(1..=3600).contains(&policy.token_ttl_secs) {
+            return Err(MceptionError::Validation(ValidationError::ValueOutOfRange(
+                "token_ttl_secs must be between 1 and 3600".to_string(),
+            )));
+        }
+
+        let mut server_config = self.config.write().await;
+        let previous = std::mem::replace(&mut server_config.confirmation, policy.clone());
+        server_config.update_last_modified();
+        drop(server_config);
+
+        self.audit_log(
+            AuditAction::Update,
+            AuditTarget::ConfirmationPolicy,
+            actor,
+            reason,
+            serde_json::json!({
+                "previous": previous,
+                "policy": policy,
+            }),
+        )
+        .await?;
+
+        self.commit("set_confirmation_policy").await?;
+        Ok(())
+    }
+
+    /// Record a dangerous admin action being challenged or confirmed
+    pub async fn audit_confirmation(
+        &self,
+        actor: &str,
+        correlation_id: &str,
+        details: serde_json::Value,
+    ) {
+        if let Err(e) = self
+            .audit_log_correlated(
+                AuditAction::Confirmation,
+                AuditTarget::Server,
+                Some(actor.to_string()),
+                None,
+                details,
+                Some(correlation_id.to_string()),
+            )
+            .await
+        {
+            warn!("Failed to audit confirmation {}: {}", correlation_id, e);
+        }
+    }
+
+    /// Audit entries not written because of the audit policy, since the start
+    pub fn skipped_audit_entries(&self) -> u64 {
+        self.audit_filter.skipped()
+    }
+
+    /// Replace the audit policy. The change itself is always audited.
+    pub async fn set_audit_policy(
+        &self,
+        policy: AuditPolicy,
+        actor: Option<String>,
+        reason: Option<String>,
+    ) -> MceptionResult<()> {
+        audit_policy::validate(&policy)
+            .map_err(|e| MceptionError::Validation(ValidationError::InvalidFormat(e)))?;
+
+        let mut server_config = self.config.write().await;
+        let previous = std::mem::replace(&mut server_config.audit, policy.clone());
+        server_config.update_last_modified();
+        drop(server_config);
+        self.audit_filter.set_policy(policy.clone());
+
+        self.audit_log(
+            AuditAction::Update,
+            AuditTarget::AuditPolicy,
+            actor,
+            reason,
+            serde_json::json!({
+                "previous": previous,
+                "policy": policy,
+            }),
+        )
+        .await?;
+
+        self.commit("set_audit_policy").await?;
+        Ok(())
+    }
+
+    /// The registered leaf MCPs `policy` would not allow
+    pub async fn registration_policy_report(
+        &self,
+        policy: &RegistrationPolicy,
+    ) -> MceptionResult<Vec<ReportEntry>> {
+        registration_policy::validate(policy)
+            .map_err(|e| MceptionError::Validation(ValidationError::InvalidFormat(e)))?;
+        Ok(registration_policy::report(
+            policy,
+            &*self.config.read().await,
+        ))
+    }
+
+    // Leaf MCP operations
+
+    /// Create a new leaf MCP configuration. Without an id, one is generated
+    /// from its name. Returns the id.
+    pub async fn create_leaf_mcp(
+        &self,
+        id: Option<String>,
+        mut config: LeafMcpConfig,
+        actor: Option<String>,
+        reason: Option<String>,
+    ) -> MceptionResult<String> {
+        let mut server_config = self.config.write().await;
+        let id = self.resolve_id(&server_config, id, IdKind::Mcp, config.name.as_deref())?;
+        config.id = id.clone();
+        config.validate(&id)?;
+        Self::check_registration_policy(&server_config, &config)?;
+        self.check_config_blob(
+            &server_config,
+            server_config.config_schema.leaf_mcps.as_ref(),
+            &format!("leaf MCP '{}'", id),
+            None,
+            &config.config,
+        )?;
+
+        if server_config.leaf_mcps.contains_key(&id) {
+            return Err(MceptionError::Storage(StorageError::AlreadyExists(
+                format!("Leaf MCP with ID '{}' already exists", id),
+            )));
+        }
+
+        server_config.leaf_mcps.insert(id.clone(), config.clone());
+        config.revision = server_config.update_leaf_mcp_revision(&id);
+
+        // Release the lock before async operations
+        drop(server_config);
+
+        self.audit_log(
+            AuditAction::Create,
+            AuditTarget::LeafMcp { id: id.clone() },
+            actor,
+            reason,
+            serde_json::to_value(config.redacted()).unwrap_or_default(),
+        )
+        .await?;
+
+        self.commit("create_leaf_mcp").await?;
+        Ok(id)
+    }
+
+    /// Mark the candidates of a discovery that are already registered
+    pub async fn check_discovered(&self, mut discovery: Discovery) -> Discovery {
+        let config = self.config.read().await;
+        discovery::mark_duplicates(&mut discovery, &config);
+        if let Some(policy) = &config.registration_policy {
+            discovery::skip_disallowed(&mut discovery, policy);
+        }
+        discovery
+    }
+
+    /// Register the candidates of a discovery that are not registered yet,
+    /// generating their ids from their names. Returns the created ids.
+    pub async fn register_discovered(
+        &self,
+        discovery: Discovery,
+        actor: Option<String>,
+    ) -> MceptionResult<Vec<String>> {
+        // Check again, leaf MCPs may have been created since the discovery was shown
+        let discovery = self.check_discovered(discovery).await;
+        let mut ids = Vec::new();
+        for candidate in discovery.new_candidates() {
+            let id = self
+                .create_leaf_mcp(
+                    None,
+                    candidate.config.clone(),
+                    actor.clone(),
+                    Some(format!(
+                        "Discovered '{}' in {}",
+                        candidate.name, candidate.source
+                    )),
+                )
+                .await?;
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+
+    /// Read a leaf MCP configuration
+    pub async fn get_leaf_mcp(
+        &self,
+        id: &str,
+        actor: Option<String>,
+    ) -> MceptionResult<LeafMcpConfig> {
+        let config = self.config.read().await;
+        let mcp_config = config
+            .leaf_mcps
+            .get(id)
+            .ok_or_else(|| {
+                MceptionError::Storage(StorageError::NotFound(format!(
+                    "Leaf MCP with ID '{}' not found",
+                    id
+                )))
+            })?
+            .clone();
+
+        drop(config);
+
+        // Log read access (but don't fail if audit logging fails)
+        if let Err(e) = self
+            .audit_log(
+                AuditAction::Read,
+                AuditTarget::LeafMcp { id: id.to_string() },
+                actor,
+                None,
+                serde_json::Value::Null,
+            )
+            .await
+        {
+            // Log the error but don't propagate it for read operations
+            error!("Failed to log audit entry for read operation: {}", e);
+        }
+
+        Ok(mcp_config)
+    }
+
+    /// A leaf MCP with its secrets and `${NAME}` placeholders as stored,
+    /// never resolved. Unlike other reads it fails if it can't be audited.
+    pub async fn reveal_leaf_mcp(
+        &self,
+        id: &str,
+        actor: Option<String>,
+    ) -> MceptionResult<LeafMcpConfig> {
+        let mcp_config = self
+            .config
+            .read()
+            .await
+            .leaf_mcps
+            .get(id)
+            .cloned()
+            .ok_or_else(|| {
+                MceptionError::Storage(StorageError::NotFound(format!(
+                    "Leaf MCP with ID '{}' not found",
+                    id
+                )))
+            })?;
+        self.audit_log(
+            AuditAction::Read,
+            AuditTarget::LeafMcp { id: id.to_string() },
+            actor,
+            None,
+            serde_json::json!({ "revealed": true }),
+        )
+        .await?;
+        Ok(mcp_config)
+    }
+
+    /// List all leaf MCP configurations
+    pub async fn list_leaf_mcps(&self) -> MceptionResult<Vec<(String, LeafMcpConfig)>> {
+        let config = self.config.read().await;
+        let mcps = config
+            .leaf_mcps
+            .iter()
+            .map(|(id, config)| (id.clone(), config.clone()))
+            .collect();
+        Ok(mcps)
+    }
+
+    /// Update a leaf MCP configuration
+    pub async fn update_leaf_mcp(
+        &self,
+        id: &str,
+        updates: serde_json::Value,
+        actor: Option<String>,
+        reason: Option<String>,
+    ) -> MceptionResult<()> {
+        self.update_leaf_mcp_at(id, updates, None, actor, reason)
+            .await
+            .map(|_| ())
+    }
+
+    /// Update a leaf MCP configuration if it is still at `expected_revision`,
+    /// when given. Returns its new revision.
+    pub async fn update_leaf_mcp_at(
+        &self,
+        id: &str,
+        updates: serde_json::Value,
+        expected_revision: Option<u64>,
+        actor: Option<String>,
+        reason: Option<String>,
+    ) -> MceptionResult<u64> {
+        let mut server_config = self.config.write().await;
+
+        let mcp_config = server_config.leaf_mcps.get_mut(id).ok_or_else(|| {
+            MceptionError::Storage(StorageError::NotFound(format!(
+                "Leaf MCP with ID '{}' not found",
+                id
+            )))
+        })?;
+        let previous_revision = mcp_config.revision;
+        Self::check_revision(
+            &format!("Leaf MCP '{}'", id),
+            previous_revision,
+            expected_revision,
+        )?;
+
+        // Apply partial updates
+        let mut updated: LeafMcpConfig =
+            merge::apply_update(&*mcp_config, &updates, &["id", "revision"])
+                .map_err(|e| MceptionError::Validation(ValidationError::InvalidFormat(e)))?;
+        // Values redacted when the leaf MCP was read stay as they are
+        updated.transport.restore_redacted(&mcp_config.transport);
+        // The merged result, so an update can't leave a broken leaf MCP behind
+        updated.validate(id)?;
+        // Leaf MCPs registered before the policy keep working until they move
+        let moved = serde_json::to_value(&updated.transport).ok()
+            != serde_json::to_value(&mcp_config.transport).ok();
+        // A running process keeps the configuration it was started with
+        let restart = moved || updated.reverse_requests != mcp_config.reverse_requests;
+        if moved {
+            Self::check_registration_policy(&server_config, &updated)?;
+        }
+        self.check_config_blob(
+            &server_config,
+            server_config.config_schema.leaf_mcps.as_ref(),
+            &format!("leaf MCP '{}'", id),
+            Some(&server_config.leaf_mcps[id].config),
+            &updated.config,
+        )?;
+        let mcp_config = server_config.leaf_mcps.get_mut(id).expect("checked above");
+        *mcp_config = updated;
+        let revision = RevisionChange {
+            from: previous_revision,
+            to: server_config.update_leaf_mcp_revision(id),
+        };
+        drop(server_config);
+        if restart {
+            self.stdio_processes.stop(id).await;
+            self.sse_connections.stop(id).await;
+        }
+
+        // Secrets and values resolved from the environment never reach the audit log
+        let mut updates = updates;
+        if let Some(transport) = updates.get_mut("transport") {
+            McpTransport::redact_update(transport);
+        }
+
+        self.audit_update(
+            AuditTarget::LeafMcp { id: id.to_string() },
+            actor,
+            reason,
+            updates,
+            revision,
+        )
+        .await?;
+
+        self.commit("update_leaf_mcp").await?;
+        Ok(revision.to)
+    }
+
+    /// Delete a leaf MCP configuration
+    pub async fn delete_leaf_mcp(
+        &self,
+        id: &str,
+        actor: Option<String>,
+        reason: Option<String>,
+    ) -> MceptionResult<()> {
+        let mut server_config = self.config.write().await;
+
+        let removed_config = Self::remove_leaf_mcp(&mut server_config, id).ok_or_else(|| {
+            MceptionError::Storage(StorageError::NotFound(format!(
+                "Leaf MCP with ID '{}' not found",
+                id
+            )))
+        })?;
+
+        server_config.update_last_modified();
+        drop(server_config);
+        self.leaf_sessions.forget(id);
+        self.circuit_breakers.forget(id);
+        self.concurrency_limits.forget(id);
+        self.health_statuses.forget(id).await;
+        self.stdio_processes.stop(id).await;
+        self.sse_connections.stop(id).await;
+
+        self.audit_log(
+            AuditAction::Delete,
+            AuditTarget::LeafMcp { id: id.to_string() },
+            actor,
+            reason,
+            serde_json::to_value(removed_config.redacted()).unwrap_or_default(),
+        )
+        .await?;
+
+        self.commit("delete_leaf_mcp").await?;
+        Ok(())
+    }
+
+    /// Remove a leaf MCP along with its grants and bundle memberships
+    fn remove_leaf_mcp(config: &mut ServerConfig, id: &str) -> Option<LeafMcpConfig> {
+        let removed = config.leaf_mcps.remove(id)?;
+
+        // Remove from all agents' allowed_mcp_ids and tool filters
+        for agent in config.agents.values_mut() {
+            agent.revoke(id);
+            agent.tool_filters.remove(id);
+        }
+
+        // Remove from all bundles
+        for bundle in config.bundles.values_mut() {
+            if bundle.members.iter().any(|member| member == id) {
+                bundle.members.retain(|member| member != id);
+                bundle.version += 1;
+            }
+        }
+        Some(removed)
+    }
+
+    // Bulk operations
+
+    /// Dry run of a bulk delete: what would be deleted and who is affected
+    pub async fn plan_bulk_delete(
+        &self,
+        kind: BulkKind,
+        selection: &BulkSelection,
+    ) -> MceptionResult<BulkDeletePlan> {
+        let config = self.config.read().await.clone();
+        let mut plan = bulk::plan(&config, kind, selection)
+            .map_err(|e| MceptionError::Storage(StorageError::NotFound(e)))?;
+        plan.confirmation_token = self
+            .confirmations
+            .issue_for_plan(&plan, config.confirmation.token_ttl());
+        if kind == BulkKind::LeafMcp {
+            for id in &plan.ids {
+                let assessed = self.assess_leaf_mcp(&config, id).await?;
+                plan.safety.insert(id.clone(), assessed);
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Agents referencing a leaf MCP and its calls in the last
+    /// [`safety::USAGE_WINDOW`], to judge whether deleting it is safe
+    pub async fn leaf_mcp_safety(&self, id: &str) -> MceptionResult<LeafMcpSafety> {
+        let config = self.config.read().await.clone();
+        if !config.leaf_mcps.contains_key(id) {
+            return Err(MceptionError::Storage(StorageError::NotFound(format!(
+                "Leaf MCP with ID '{}' not found",
+                id
+            ))));
+        }
+        self.assess_leaf_mcp(&config, id).await
+    }
+
+    async fn assess_leaf_mcp(
+        &self,
+        config: &ServerConfig,
+        id: &str,
+    ) -> MceptionResult<LeafMcpSafety> {
+        let now = Utc::now();
+        let since = now - safety::USAGE_WINDOW;
+        let usage = match &self.access_log {
+            Some(access_log) => Some(
+                access_log
+                    .usage(id, since)
+                    .await
+                    .map_err(|e| MceptionError::Storage(StorageError::Io(e)))?,
+            ),
+            None => None,
+        };
+        Ok(safety::assess(config, id, usage, since, now))
+    }
+
+    /// Append a forwarded request to the access log, if enabled. A failed
+    /// write is logged rather than failing the request.
+    pub async fn record_access(&self, entry: AccessEntry) {
+        if let Some(access_log) = &self.access_log
+            && let Err(e) = access_log.record(&entry).await
+        {
+            warn!(
+                "Failed to write access log {}: {}",
+                access_log.path().display(),
+                e
+            );
+        }
+    }
+
+    /// Delete the selected leaf MCPs or agents at once, if `token` confirms the
+    /// current plan. Each deletion gets its own audit entry, sharing one
+    /// correlation id.
+    pub async fn bulk_delete(
+        &self,
+        kind: BulkKind,
+        selection: &BulkSelection,
+        token: &str,
+        actor: Option<String>,
+        reason: Option<String>,
+    ) -> MceptionResult<BulkDeleteOutcome> {
+        let mut server_config = self.config.write().await;
+
+        let mut plan = bulk::plan(&server_config, kind, selection)
+            .map_err(|e| MceptionError::Storage(StorageError::NotFound(e)))?;
+        let planned_correlation_id = match self.confirmations.accept_for_plan(token, &plan) {
+            Ok(correlation_id) => correlation_id,
+            Err(rejection) => {
+                plan.confirmation_token = self
+                    .confirmations
+                    .issue_for_plan(&plan, server_config.confirmation.token_ttl());
+                let reason = match rejection {
+                    Rejection::Mismatched => "the configuration changed since the dry run",
+                    rejection => rejection.reason(),
+                };
+                return Ok(BulkDeleteOutcome::PlanChanged {
+                    plan,
+                    reason: reason.to_string(),
+                });
+            }
+        };
+        if plan.ids.is_empty() {
+            return Err(MceptionError::Validation(ValidationError::InvalidFormat(
+                format!("Nothing matches {}", plan.selection),
+            )));
+        }
+
+        let mut removed = Vec::new();
+        for id in &plan.ids {
+            let (target, details) = match kind {
+                BulkKind::LeafMcp => (
+                    AuditTarget::LeafMcp { id: id.clone() },
+                    serde_json::to_value(
+                        Self::remove_leaf_mcp(&mut server_config, id).map(|leaf| leaf.redacted()),
+                    ),
+                ),
+                BulkKind::Agent => (
+                    AuditTarget::Agent { id: id.clone() },
+                    serde_json::to_value(
+                        server_config
+                            .agents
+                            .remove(id)
+                            .map(|agent| agent.redacted()),
+                    ),
+                ),
+            };
+            removed.push((target, details.unwrap_or_default()));
+        }
+        server_config.update_last_modified();
+        drop(server_config);
+        if kind == BulkKind::LeafMcp {
+            for id in &plan.ids {
+                self.leaf_sessions.forget(id);
+                self.circuit_breakers.forget(id);
+                self.concurrency_limits.forget(id);
+                self.health_statuses.forget(id).await;
+                self.stdio_processes.stop(id).await;
+                self.sse_connections.stop(id).await;
+            }
+        }
+
+        // A confirmed bulk delete continues its confirmation's correlation,
+        // otherwise its dry run's
+        let correlation_id =
+            confirmation::current_correlation_id().unwrap_or(planned_correlation_id);
+        for (target, details) in removed {
+            self.audit_log_correlated(
+                AuditAction::Delete,
+                target,
+                actor.clone(),
+                reason.clone(),
+                details,
+                Some(correlation_id.clone()),
+            )
+            .await?;
+        }
+
+        self.commit(match kind {
+            BulkKind::LeafMcp => "bulk_delete_leaf_mcps",
+            BulkKind::Agent => "bulk_delete_agents",
+        })
+        .await?;
+        Ok(BulkDeleteOutcome::Deleted {
+            correlation_id,
+            plan,
+        })
+    }
+
+    // Bundle operations
+
+    /// Check that all bundle members are existing leaf MCPs
+    fn validate_bundle_members(config: &ServerConfig, members: &[String]) -> MceptionResult<()> {
+        for member in members {
+            if !config.leaf_mcps.contains_key(member) {
+                return Err(MceptionError::Validation(ValidationError::InvalidFormat(
+                    format!("Bundle member '{}' is not an existing leaf MCP", member),
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Refuse an update based on another revision of the entity than its
+    /// current one
+    fn check_revision(entity: &str, current: u64, expected: Option<u64>) -> MceptionResult<()> {
+        match expected {
+            Some(expected) if expected != current => {
+                Err(MceptionError::Storage(StorageError::StaleRevision(
+                    format!("{} is at revision {}, not {}", entity, current, expected),
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Refuse allowed MCPs through which an agent would end up referencing
+    /// itself, e.g. agent `a` allowing agent `b` which allows `a`
+    fn check_no_cycle(
+        config: &ServerConfig,
+        agent_id: &str,
+        allowed_mcp_ids: &[String],
+    ) -> MceptionResult<()> {
+        match cycles::cycle_through(config, agent_id, allowed_mcp_ids) {
+            Some(chain) => Err(MceptionError::Validation(
+                ValidationError::CircularReference(cycles::describe(&chain)),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the loaded configuration for what mutations would have refused,
+    /// e.g. after it was edited by hand, warning about each agent reference
+    /// cycle and each `config` beyond the limits or its schema. Returns the
+    /// cycles.
+    pub async fn validate_configuration(&self) -> Vec<Vec<String>> {
+        let config = self.config.read().await;
+        let cycles = cycles::find_cycles(&config);
+        for chain in &cycles {
+            warn!(
+                "Agents reference each other in a cycle: {}",
+                cycles::describe(chain)
+            );
+        }
+
+        // Loaded as they are, they just can't grow until they are trimmed
+        let blobs = config
+            .leaf_mcps
+            .iter()
+            .map(|(id, mcp)| {
+                let schema = config.config_schema.leaf_mcps.as_ref();
+                (format!("leaf MCP '{}'", id), schema, &mcp.config)
+            })
+            .chain(config.agents.iter().map(|(id, agent)| {
+                let schema = config.config_schema.agents.as_ref();
+                (format!("agent '{}'", id), schema, &agent.config)
+            }));
+        for (subject, schema_id, blob) in blobs {
+            let exceeded = self.config_limits.exceeded(BlobSize::of(blob));
+            if !exceeded.is_empty() {
+                warn!("The config of {} {}", subject, exceeded.join(", "));
+            }
+            if let Some(schema) = schema_id.and_then(|id| config.config_schemas.get(id))
+                && let Err(e) = config_limits::check_schema(schema, blob)
+            {
+                warn!("The config of {} violates its schema: {}", subject, e);
+            }
+        }
+        for schema_id in [
+            &config.config_schema.leaf_mcps,
+            &config.config_schema.agents,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if !config.config_schemas.contains_key(schema_id) {
+                warn!("Config schema '{}' is not defined, not applied", schema_id);
+            }
+        }
+        cycles
+    }
+
+    /// Create a new bundle of leaf MCPs
+    pub async fn create_bundle(
+        &self,
+        name: String,
+        description: Option<String>,
+        members: Vec<String>,
+        actor: Option<String>,
+        reason: Option<String>,
+    ) -> MceptionResult<()> {
+        if name.trim().is_empty() || name.contains(':') {
+            return Err(MceptionError::Validation(ValidationError::InvalidFormat(
+                "Bundle name must be non-empty and must not contain ':'".to_string(),
+            )));
+        }
+
+        let mut server_config = self.config.write().await;
+
+        if server_config.bundles.contains_key(&name) {
+            return Err(MceptionError::Storage(StorageError::AlreadyExists(
+                format!("Bundle '{}' already exists", name),
+            )));
+        }
+        Self::validate_bundle_members(&server_config, &members)?;
+
+        let bundle = BundleConfig {
+            name: name.clone(),
+            description,
+            members: dedup(members),
+            version: 1,
+        };
+        server_config.bundles.insert(name.clone(), bundle.clone());
+        server_config.update_last_modified();
+        drop(server_config);
+
+        self.audit_log(
+            AuditAction::Create,
+            AuditTarget::Bundle { name },
+            actor,
+            reason,
+            serde_json::json!({
+                "bundle": bundle,
+                "affected_agents": [],
+            }),
+        )
+        .await?;
 
-        drop(config);
+        self.commit("create_bundle").await?;
+        Ok(())
+    }
 
-        // Log read access (but don't fail if audit logging fails)
-        if let Err(e) = self
-            .audit_log(
-                AuditAction::Read,
-                AuditTarget::LeafMcp { id: id.to_string() },
-                actor,
-                None,
-                serde_json::Value::Null,
-            )
+    /// Get a bundle
+    pub async fn get_bundle(&self, name: &str) -> MceptionResult<BundleConfig> {
+        self.config
+            .read()
             .await
-        {
-            // Log the error but don't propagate it for read operations
-            error!("Failed to log audit entry for read operation: {}", e);
-        }
-
-        Ok(mcp_config)
+            .bundles
+            .get(name)
+            .cloned()
+            .ok_or_else(|| {
+                MceptionError::Storage(StorageError::NotFound(format!(
+                    "Bundle '{}' not found",
+                    name
+                )))
+            })
     }
 
-    /// List all leaf MCP configurations
-    pub async fn list_leaf_mcps(&self) -> MceptionResult<Vec<(String, LeafMcpConfig)>> {
-        let config = self.config.read().await;
-        let mcps = config
-            .leaf_mcps
-            .iter()
-            .map(|(id, config)| (id.clone(), config.clone()))
-            .collect();
-        Ok(mcps)
+    /// List all bundles
+    pub async fn list_bundles(&self) -> Vec<BundleConfig> {
+        self.config.read().await.bundles.values().cloned().collect()
     }
 
-    /// Update a leaf MCP configuration
-    pub async fn update_leaf_mcp(
+    /// Update a bundle's description and/or members. Membership changes bump the
+    /// bundle version and reach all agents holding the bundle.
+    pub async fn update_bundle(
         &self,
-        id: &str,
-        updates: serde_json::Value,
+        name: &str,
+        description: Option<String>,
+        members: Option<Vec<String>>,
         actor: Option<String>,
         reason: Option<String>,
     ) -> MceptionResult<()> {
         let mut server_config = self.config.write().await;
 
-        let mcp_config = server_config.leaf_mcps.get_mut(id).ok_or_else(|| {
+        if let Some(members) = &members {
+            Self::validate_bundle_members(&server_config, members)?;
+        }
+        let affected_agents = server_config.agents_with_bundle(name);
+
+        let bundle = server_config.bundles.get_mut(name).ok_or_else(|| {
             MceptionError::Storage(StorageError::NotFound(format!(
-                "Leaf MCP with ID '{}' not found",
-                id
+                "Bundle '{}' not found",
+                name
             )))
         })?;
 
-        // Apply partial updates
-        if let serde_json::Value::Object(ref updates_map) = updates {
-            let config_value = serde_json::to_value(&*mcp_config).map_err(|e| {
-                MceptionError::Validation(ValidationError::InvalidFormat(e.to_string()))
-            })?;
-
-            if let serde_json::Value::Object(mut config_map) = config_value {
-                for (key, value) in updates_map {
-                    config_map.insert(key.clone(), value.clone());
-                }
-                *mcp_config = serde_json::from_value(serde_json::Value::Object(config_map))
-                    .map_err(|e| {
-                        MceptionError::Validation(ValidationError::InvalidFormat(e.to_string()))
-                    })?;
+        let (mut added, mut removed) = (Vec::new(), Vec::new());
+        if let Some(members) = members {
+            let members = dedup(members);
+            added = members
+                .iter()
+                .filter(|member| !bundle.members.contains(member))
+                .cloned()
+                .collect();
+            removed = bundle
+                .members
+                .iter()
+                .filter(|member| !members.contains(member))
+                .cloned()
+                .collect();
+            if !added.is_empty() || !removed.is_empty() {
+                bundle.version += 1;
             }
+            bundle.members = members;
+        }
+        if description.is_some() {
+            bundle.description = description;
         }
+        let bundle = bundle.clone();
 
         server_config.update_last_modified();
         drop(server_config);
 
         self.audit_log(
             AuditAction::Update,
-            AuditTarget::LeafMcp { id: id.to_string() },
+            AuditTarget::Bundle {
+                name: name.to_string(),
+            },
             actor,
             reason,
-            updates,
+            serde_json::json!({
+                "bundle": bundle,
+                "added": added,
+                "removed": removed,
+                "affected_agents": affected_agents,
+            }),
         )
         .await?;
 
-        self.save_configuration().await?;
+        self.commit("update_bundle").await?;
         Ok(())
     }
 
-    /// Delete a leaf MCP configuration
-    pub async fn delete_leaf_mcp(
+    /// Delete a bundle and revoke it from all agents holding it
+    pub async fn delete_bundle(
         &self,
-        id: &str,
+        name: &str,
         actor: Option<String>,
         reason: Option<String>,
     ) -> MceptionResult<()> {
         let mut server_config = self.config.write().await;
 
-        let removed_config = server_config.leaf_mcps.remove(id).ok_or_else(|| {
+        let affected_agents = server_config.agents_with_bundle(name);
+        let removed_bundle = server_config.bundles.remove(name).ok_or_else(|| {
             MceptionError::Storage(StorageError::NotFound(format!(
-                "Leaf MCP with ID '{}' not found",
-                id
+                "Bundle '{}' not found",
+                name
             )))
         })?;
 
-        // Remove from all agents' allowed_mcp_ids
+        let grant = format!("{}{}", BUNDLE_PREFIX, name);
         for agent in server_config.agents.values_mut() {
-            agent.allowed_mcp_ids.retain(|mcp_id| mcp_id != id);
+            agent.revoke(&grant);
         }
 
         server_config.update_last_modified();
@@ -244,35 +3310,69 @@ impl ConfigService {
 
         self.audit_log(
             AuditAction::Delete,
-            AuditTarget::LeafMcp { id: id.to_string() },
+            AuditTarget::Bundle {
+                name: name.to_string(),
+            },
             actor,
             reason,
-            serde_json::to_value(&removed_config).unwrap_or_default(),
+            serde_json::json!({
+                "bundle": removed_bundle,
+                "affected_agents": affected_agents,
+            }),
         )
         .await?;
 
-        self.save_configuration().await?;
+        self.commit("delete_bundle").await?;
         Ok(())
     }
 
     // Agent operations
 
-    /// Create a new agent configuration
+    /// Create a new agent configuration. Without an id, one is generated from
+    /// its name. Returns the id.
     pub async fn create_agent(
         &self,
-        agent_id: String,
+        agent_id: Option<String>,
+        name: Option<String>,
         allowed_mcp_ids: Vec<String>,
         actor: Option<String>,
-    ) -> MceptionResult<()> {
-        // Validation
-        if agent_id.trim().is_empty() {
-            return Err(MceptionError::Validation(ValidationError::InvalidFormat(
-                "Agent ID cannot be empty".to_string(),
-            )));
-        }
+    ) -> MceptionResult<String> {
+        self.create_agent_with_description(
+            CreateAgentRequest {
+                agent_id,
+                name,
+                allowed_mcp_ids,
+                labels: BTreeMap::new(),
+                should_create: None,
+            },
+            None,
+            actor,
+            None,
+        )
+        .await
+    }
 
+    /// Create a new agent configuration as requested, with a description,
+    /// recording the reason in the audit log. Returns the id.
+    pub async fn create_agent_with_description(
+        &self,
+        request: CreateAgentRequest,
+        description: Option<String>,
+        actor: Option<String>,
+        reason: Option<String>,
+    ) -> MceptionResult<String> {
+        let CreateAgentRequest {
+            agent_id,
+            name,
+            allowed_mcp_ids,
+            labels,
+            ..
+        } = request;
+        labels::validate(&labels)
+            .map_err(|e| MceptionError::Validation(ValidationError::InvalidFormat(e)))?;
         let mut server_config = self.config.write().await;
 
+        let agent_id = self.resolve_id(&server_config, agent_id, IdKind::Agent, name.as_deref())?;
         if server_config.agents.contains_key(&agent_id) {
             return Err(MceptionError::Storage(StorageError::AlreadyExists(
                 format!("Agent with ID '{}' already exists", agent_id),
@@ -281,29 +3381,36 @@ impl ConfigService {
 
         // Validate that all allowed MCPs exist
         for mcp_id in &allowed_mcp_ids {
-            if !server_config.leaf_mcps.contains_key(mcp_id)
-                && !server_config.agents.contains_key(mcp_id)
-            {
+            if !server_config.has_mcp(mcp_id) {
                 return Err(MceptionError::Validation(ValidationError::InvalidFormat(
                     format!("MCP with ID '{}' does not exist", mcp_id),
                 )));
             }
         }
+        Self::check_no_cycle(&server_config, &agent_id, &allowed_mcp_ids)?;
 
-        let agent_config = AgentConfig {
+        let mut agent_config = AgentConfig {
             agent_id: agent_id.clone(),
-            name: None,
-            description: None,
+            name,
+            description,
             allowed_mcp_ids: allowed_mcp_ids.clone(),
             is_connected: false,
             last_seen: None,
             config: serde_json::Value::Object(serde_json::Map::new()),
+            auth_token: None,
+            auth_token_expires_at: None,
+            region: None,
+            tool_filters: BTreeMap::new(),
+            grant_sources: BTreeMap::new(),
+            grant_expiry: BTreeMap::new(),
+            labels,
+            revision: 0,
         };
 
         server_config
             .agents
             .insert(agent_id.clone(), agent_config.clone());
-        server_config.update_last_modified();
+        agent_config.revision = server_config.update_agent_revision(&agent_id);
         drop(server_config);
 
         self.audit_log(
@@ -312,13 +3419,13 @@ impl ConfigService {
                 id: agent_id.clone(),
             },
             actor,
-            None,
-            serde_json::to_value(&agent_config).unwrap_or_default(),
+            reason,
+            serde_json::to_value(agent_config.redacted()).unwrap_or_default(),
         )
         .await?;
 
-        self.save_configuration().await?;
-        Ok(())
+        self.commit("create_agent").await?;
+        Ok(agent_id)
     }
 
     /// Get an agent configuration
@@ -379,6 +3486,21 @@ impl ConfigService {
         actor: Option<String>,
         reason: Option<String>,
     ) -> MceptionResult<()> {
+        self.update_agent_at(agent_id, updates, None, actor, reason)
+            .await
+            .map(|_| ())
+    }
+
+    /// Update an agent configuration if it is still at `expected_revision`,
+    /// when given. Returns its new revision.
+    pub async fn update_agent_at(
+        &self,
+        agent_id: &str,
+        updates: serde_json::Value,
+        expected_revision: Option<u64>,
+        actor: Option<String>,
+        reason: Option<String>,
+    ) -> MceptionResult<u64> {
         let mut server_config = self.config.write().await;
 
         let agent_config = server_config.agents.get_mut(agent_id).ok_or_else(|| {
@@ -387,40 +3509,59 @@ impl ConfigService {
                 agent_id
             )))
         })?;
+        let previous_revision = agent_config.revision;
+        Self::check_revision(
+            &format!("Agent '{}'", agent_id),
+            previous_revision,
+            expected_revision,
+        )?;
 
         // Apply partial updates
-        if let serde_json::Value::Object(ref updates_map) = updates {
-            let config_value = serde_json::to_value(&*agent_config).map_err(|e| {
-                MceptionError::Validation(ValidationError::InvalidFormat(e.to_string()))
-            })?;
+        let mut updated = merge::apply_update(&*agent_config, &updates, &["agent_id", "revision"])
+            .map_err(|e| MceptionError::Validation(ValidationError::InvalidFormat(e)))?;
+        Shaping::of_agent(&updated.config)
+            .map_err(|e| MceptionError::Validation(ValidationError::InvalidFormat(e)))?;
+        rate_limit::configured(&updated.config)
+            .map_err(|e| MceptionError::Validation(ValidationError::InvalidFormat(e)))?;
+        labels::validate(&updated.labels)
+            .map_err(|e| MceptionError::Validation(ValidationError::InvalidFormat(e)))?;
+        Self::check_no_cycle(&server_config, agent_id, &updated.allowed_mcp_ids)?;
+        self.check_config_blob(
+            &server_config,
+            server_config.config_schema.agents.as_ref(),
+            &format!("agent '{}'", agent_id),
+            Some(&server_config.agents[agent_id].config),
+            &updated.config,
+        )?;
+        updated.migrate_grant_sources();
+        server_config.agents.insert(agent_id.to_string(), updated);
+        let revision = RevisionChange {
+            from: previous_revision,
+            to: server_config.update_agent_revision(agent_id),
+        };
+        drop(server_config);
 
-            if let serde_json::Value::Object(mut config_map) = config_value {
-                for (key, value) in updates_map {
-                    config_map.insert(key.clone(), value.clone());
-                }
-                *agent_config = serde_json::from_value(serde_json::Value::Object(config_map))
-                    .map_err(|e| {
-                        MceptionError::Validation(ValidationError::InvalidFormat(e.to_string()))
-                    })?;
-            }
+        // The token itself never reaches the audit log
+        let mut updates = updates;
+        if let Some(token) = updates.get_mut("auth_token")
+            && !token.is_null()
+        {
+            *token = serde_json::Value::from(REDACTED);
         }
 
-        server_config.update_last_modified();
-        drop(server_config);
-
-        self.audit_log(
-            AuditAction::Update,
+        self.audit_update(
             AuditTarget::Agent {
                 id: agent_id.to_string(),
             },
             actor,
             reason,
             updates,
+            revision,
         )
         .await?;
 
-        self.save_configuration().await?;
-        Ok(())
+        self.commit("update_agent").await?;
+        Ok(revision.to)
     }
 
     /// Delete an agent configuration
@@ -449,32 +3590,48 @@ impl ConfigService {
             },
             actor,
             reason,
-            serde_json::to_value(&removed_config).unwrap_or_default(),
+            serde_json::to_value(removed_config.redacted()).unwrap_or_default(),
         )
         .await?;
 
-        self.save_configuration().await?;
+        self.commit("delete_agent").await?;
         Ok(())
     }
 
-    /// Add an allowed MCP to an agent
+    /// Grant an agent an MCP directly
     pub async fn add_agent_allowed_mcp(
         &self,
         agent_id: &str,
         mcp_id: &str,
         actor: Option<String>,
         reason: Option<String>,
+    ) -> MceptionResult<()> {
+        self.grant_agent_mcp(agent_id, mcp_id, GrantSource::Direct, None, actor, reason)
+            .await
+    }
+
+    /// Grant an agent an MCP through `source`. An MCP the agent already may
+    /// use through other sources gains `source` as well. With `expires_at`
+    /// the grant stops allowing the MCP then, also when it was granted
+    /// already; without, an expiry set before is kept.
+    pub async fn grant_agent_mcp(
+        &self,
+        agent_id: &str,
+        mcp_id: &str,
+        source: GrantSource,
+        expires_at: Option<DateTime<Utc>>,
+        actor: Option<String>,
+        reason: Option<String>,
     ) -> MceptionResult<()> {
         let mut server_config = self.config.write().await;
 
         // Check if MCP exists
-        if !server_config.leaf_mcps.contains_key(mcp_id)
-            && !server_config.agents.contains_key(mcp_id)
-        {
+        if !server_config.has_mcp(mcp_id) {
             return Err(MceptionError::Validation(ValidationError::InvalidFormat(
                 format!("MCP with ID '{}' does not exist", mcp_id),
             )));
         }
+        Self::check_no_cycle(&server_config, agent_id, &[mcp_id.to_string()])?;
 
         let agent_config = server_config.agents.get_mut(agent_id).ok_or_else(|| {
             MceptionError::Storage(StorageError::NotFound(format!(
@@ -483,18 +3640,22 @@ impl ConfigService {
             )))
         })?;
 
-        // Check if MCP is already allowed
-        if agent_config.allowed_mcp_ids.contains(&mcp_id.to_string()) {
+        let moved = expires_at.is_some_and(|expires_at| {
+            agent_config
+                .grant_expiry
+                .insert(mcp_id.to_string(), expires_at)
+                != Some(expires_at)
+        });
+        if !agent_config.grant(mcp_id, source.clone()) && !moved {
             return Err(MceptionError::Storage(StorageError::AlreadyExists(
                 format!(
-                    "MCP '{}' is already allowed for agent '{}'",
-                    mcp_id, agent_id
+                    "MCP '{}' is already allowed for agent '{}' through {}",
+                    mcp_id, agent_id, source
                 ),
             )));
         }
-
-        agent_config.allowed_mcp_ids.push(mcp_id.to_string());
-        server_config.update_last_modified();
+        let sources = agent_config.grant_sources_of(mcp_id);
+        server_config.update_agent_revision(agent_id);
         drop(server_config);
 
         self.audit_log(
@@ -505,22 +3666,94 @@ impl ConfigService {
             },
             actor,
             reason,
-            serde_json::json!({ "mcp_id": mcp_id }),
+            serde_json::json!({
+                "mcp_id": mcp_id,
+                "source": source,
+                "sources": sources,
+                "expires_at": expires_at,
+            }),
         )
         .await?;
 
-        self.save_configuration().await?;
+        self.commit("add_agent_allowed_mcp").await?;
         Ok(())
     }
 
-    /// Remove an allowed MCP from an agent
+    /// Grant an agent every leaf MCP whose labels match `selector` directly,
+    /// in one change audited once. Returns the ids newly granted, in id
+    /// order; ones the agent was already granted directly are skipped.
+    pub async fn grant_agent_mcps_by_label(
+        &self,
+        agent_id: &str,
+        selector: &LabelSelector,
+        actor: Option<String>,
+        reason: Option<String>,
+    ) -> MceptionResult<Vec<String>> {
+        let mut server_config = self.config.write().await;
+        let matching: Vec<String> = server_config
+            .leaf_mcps
+            .iter()
+            .filter(|(_, leaf)| selector.matches(&leaf.labels))
+            .map(|(id, _)| id.clone())
+            .collect();
+        let agent_config = server_config.agents.get_mut(agent_id).ok_or_else(|| {
+            MceptionError::Storage(StorageError::NotFound(format!(
+                "Agent with ID '{}' not found",
+                agent_id
+            )))
+        })?;
+        let granted: Vec<String> = matching
+            .into_iter()
+            .filter(|mcp_id| agent_config.grant(mcp_id, GrantSource::Direct))
+            .collect();
+        if granted.is_empty() {
+            return Ok(granted);
+        }
+        server_config.update_agent_revision(agent_id);
+        drop(server_config);
+
+        self.audit_log(
+            AuditAction::AddAllowedMcp,
+            AuditTarget::Agent {
+                id: agent_id.to_string(),
+            },
+            actor,
+            reason,
+            serde_json::json!({
+                "selector": selector.to_string(),
+                "mcp_ids": granted,
+                "source": GrantSource::Direct,
+            }),
+        )
+        .await?;
+
+        self.commit("add_agent_allowed_mcps_by_label").await?;
+        Ok(granted)
+    }
+
+    /// Remove the direct grant of an MCP from an agent. Returns the sources
+    /// the agent still may use it through.
     pub async fn remove_agent_allowed_mcp(
         &self,
         agent_id: &str,
         mcp_id: &str,
         actor: Option<String>,
         reason: Option<String>,
-    ) -> MceptionResult<()> {
+    ) -> MceptionResult<BTreeSet<GrantSource>> {
+        self.revoke_agent_mcp(agent_id, mcp_id, &GrantSource::Direct, actor, reason)
+            .await
+    }
+
+    /// Remove `source` from an agent's grant of an MCP, and the grant along
+    /// with its last source. Returns the sources left.
+    pub async fn revoke_agent_mcp(
+        &self,
+        agent_id: &str,
+        mcp_id: &str,
+        source: &GrantSource,
+        actor: Option<String>,
+        reason: Option<String>,
+    ) -> MceptionResult<BTreeSet<GrantSource>> {
         let mut server_config = self.config.write().await;
 
         let agent_config = server_config.agents.get_mut(agent_id).ok_or_else(|| {
@@ -530,16 +3763,27 @@ impl ConfigService {
             )))
         })?;
 
-        // Check if MCP is currently allowed
-        if !agent_config.allowed_mcp_ids.contains(&mcp_id.to_string()) {
+        let sources = agent_config.grant_sources_of(mcp_id);
+        if sources.is_empty() {
             return Err(MceptionError::Storage(StorageError::NotFound(format!(
                 "MCP '{}' is not allowed for agent '{}'",
                 mcp_id, agent_id
             ))));
         }
-
-        agent_config.allowed_mcp_ids.retain(|id| id != mcp_id);
-        server_config.update_last_modified();
+        let remaining = agent_config.revoke_source(mcp_id, source).ok_or_else(|| {
+            MceptionError::Storage(StorageError::NotFound(format!(
+                "MCP '{}' is allowed for agent '{}' through {}, not {}",
+                mcp_id,
+                agent_id,
+                sources
+                    .iter()
+                    .map(GrantSource::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                source
+            )))
+        })?;
+        server_config.update_agent_revision(agent_id);
         drop(server_config);
 
         self.audit_log(
@@ -550,11 +3794,88 @@ impl ConfigService {
             },
             actor,
             reason,
-            serde_json::json!({ "mcp_id": mcp_id }),
+            serde_json::json!({
+                "mcp_id": mcp_id,
+                "source": source,
+                "remaining_sources": remaining,
+            }),
         )
         .await?;
 
-        self.save_configuration().await?;
+        self.commit("remove_agent_allowed_mcp").await?;
+        Ok(remaining)
+    }
+
+    /// Set which tools of an allowed MCP an agent may call, or with `None`
+    /// let it call every tool again
+    pub async fn set_agent_tool_filter(
+        &self,
+        agent_id: &str,
+        mcp_id: &str,
+        filter: Option<ToolFilter>,
+        actor: Option<String>,
+        reason: Option<String>,
+    ) -> MceptionResult<()> {
+        let mut server_config = self.config.write().await;
+        let agent = server_config.agents.get(agent_id).ok_or_else(|| {
+            MceptionError::Storage(StorageError::NotFound(format!(
+                "Agent with ID '{}' not found",
+                agent_id
+            )))
+        })?;
+        if !authorization::evaluate(&server_config, agent, mcp_id, None).allowed {
+            return Err(MceptionError::Storage(StorageError::NotFound(format!(
+                "MCP '{}' is not allowed for agent '{}'",
+                mcp_id, agent_id
+            ))));
+        }
+        if let Some(pattern) = filter
+            .iter()
+            .flat_map(|filter| filter.allow.iter().flatten().chain(&filter.deny))
+            .find(|pattern| pattern.is_empty())
+        {
+            return Err(MceptionError::Validation(ValidationError::InvalidFormat(
+                format!("Tool pattern '{}' must not be empty", pattern),
+            )));
+        }
+        if agent.tool_filters.get(mcp_id) == filter.as_ref() {
+            return Ok(());
+        }
+
+        if let Some(agent) = server_config.agents.get_mut(agent_id) {
+            match &filter {
+                Some(filter) => agent
+                    .tool_filters
+                    .insert(mcp_id.to_string(), filter.clone()),
+                None => agent.tool_filters.remove(mcp_id),
+            };
+        }
+        server_config.update_agent_revision(agent_id);
+        drop(server_config);
+
+        let target = AuditTarget::AgentToolFilter {
+            agent_id: agent_id.to_string(),
+            mcp_id: mcp_id.to_string(),
+        };
+        match filter {
+            Some(filter) => {
+                let details = serde_json::json!({ "filter": filter });
+                self.audit_log(AuditAction::Update, target, actor, reason, details)
+                    .await?
+            }
+            None => {
+                self.audit_log(
+                    AuditAction::Delete,
+                    target,
+                    actor,
+                    reason,
+                    serde_json::json!({}),
+                )
+                .await?
+            }
+        }
+
+        self.commit("set_agent_tool_filter").await?;
         Ok(())
     }
 
@@ -563,11 +3884,49 @@ impl ConfigService {
         self.audit_storage.load_entries().await
     }
 
+    /// Get the audit log entries matching `query`
+    pub async fn get_audit_logs_filtered(
+        &self,
+        query: &AuditQuery,
+    ) -> MceptionResult<Vec<AuditLogEntry>> {
+        self.audit_storage.load_entries_filtered(query).await
+    }
+
+    /// Walk the hash chain of the audit log up to its first broken link
+    pub async fn verify_audit_chain(&self) -> MceptionResult<AuditChainReport> {
+        self.audit_storage.verify_chain().await
+    }
+
+    /// The changes to an agent's grants, token and allowed MCPs at or after
+    /// `since`, derived from the audit log
+    pub async fn agent_changes(
+        &self,
+        agent_id: &str,
+        since: Option<DateTime<Utc>>,
+    ) -> MceptionResult<Vec<AgentChange>> {
+        let config = self.get_configuration().await;
+        if !config.agents.contains_key(agent_id) {
+            return Err(MceptionError::Storage(StorageError::NotFound(format!(
+                "Agent with ID '{}' not found",
+                agent_id
+            ))));
+        }
+        let entries = self.audit_storage.load_entries().await?;
+        Ok(agent_changes::derive(
+            &entries,
+            agent_id,
+            &config,
+            since,
+            self.mask_change_actors,
+        ))
+    }
+
     /// Get the remote configuration for an agent (filtered MCPs that the agent is allowed to use)
     pub async fn get_agent_remote_config(
         &self,
         agent_id: &str,
-    ) -> MceptionResult<serde_json::Value> {
+    ) -> MceptionResult<AgentRemoteConfig> {
+        let unhealthy = self.replica_health.unhealthy().await;
         let config = self.config.read().await;
 
         let agent = config.agents.get(agent_id).ok_or_else(|| {
@@ -577,33 +3936,199 @@ impl ConfigService {
             )))
         })?;
 
-        // Build the remote config with only allowed MCPs
-        let mut remote_mcps = serde_json::Map::new();
+        // Versions of the granted bundles, so agents can tell when one changed
+        let mut bundles = BTreeMap::new();
+        for grant in &agent.allowed_mcp_ids {
+            if let Some(name) = grant.strip_prefix(BUNDLE_PREFIX)
+                && let Some(bundle) = config.bundles.get(name)
+            {
+                bundles.insert(
+                    name.to_string(),
+                    RemoteBundle {
+                        version: bundle.version,
+                        members: bundle.members.clone(),
+                    },
+                );
+            }
+        }
+
+        // Of the allowed replicas of a leaf MCP only the one picked for the
+        // agent is listed
+        let allowed = authorization::allowed_mcps(&config, agent);
+        let now = Utc::now();
+        let remote_expiry = |expires_at| RemoteExpiry {
+            expires_at,
+            expires_soon: expires_at <= now + self.expiry_window,
+        };
+        let mut groups: BTreeMap<&str, Vec<Candidate>> = BTreeMap::new();
+        for mcp_id in &allowed {
+            if let Some(mcp_config) = config.leaf_mcps.get(mcp_id)
+                && let Some(group) = &mcp_config.replica_group
+            {
+                groups.entry(group).or_default().push(Candidate {
+                    id: mcp_id,
+                    region: mcp_config.region.as_deref(),
+                    healthy: !unhealthy.contains_key(mcp_id),
+                });
+            }
+        }
+        let picked: Vec<&str> = groups
+            .values()
+            .filter_map(|candidates| replicas::select(candidates, agent.region.as_deref(), None))
+            .collect();
 
-        for mcp_id in &agent.allowed_mcp_ids {
+        // Build the remote config with only allowed MCPs
+        let mut mcps = BTreeMap::new();
+        for mcp_id in &allowed {
             if let Some(mcp_config) = config.leaf_mcps.get(mcp_id) {
-                remote_mcps.insert(
+                if mcp_config.replica_group.is_some() && !picked.contains(&mcp_id.as_str()) {
+                    continue;
+                }
+                mcps.insert(
                     mcp_id.clone(),
-                    serde_json::to_value(mcp_config).unwrap_or_default(),
+                    RemoteMcpEntry {
+                        kind: RemoteMcpKind::LeafMcp,
+                        name: mcp_config.name.clone(),
+                        description: None,
+                        instructions: mcp_config.instructions.clone(),
+                        connection: McpConnection::for_leaf_mcp(mcp_config),
+                        protocol_hint: self.stdio_processes.protocol_version(mcp_id, mcp_config),
+                        config: mcp_config.config.clone(),
+                        replica_group: mcp_config.replica_group.clone(),
+                        tool_filter: agent.tool_filters.get(mcp_id).cloned(),
+                        expiry: authorization::grant_expiry(&config, agent, mcp_id, now)
+                            .map(remote_expiry),
+                    },
                 );
             } else if let Some(agent_config) = config.agents.get(mcp_id) {
                 // Include other agents that this agent can use
-                remote_mcps.insert(
+                mcps.insert(
                     mcp_id.clone(),
-                    serde_json::to_value(agent_config).unwrap_or_default(),
+                    RemoteMcpEntry {
+                        kind: RemoteMcpKind::Agent,
+                        name: agent_config.name.clone(),
+                        description: agent_config.description.clone(),
+                        instructions: None,
+                        connection: McpConnection::for_agent(mcp_id),
+                        protocol_hint: None,
+                        config: agent_config.config.clone(),
+                        replica_group: None,
+                        tool_filter: agent.tool_filters.get(mcp_id).cloned(),
+                        expiry: authorization::grant_expiry(&config, agent, mcp_id, now)
+                            .map(remote_expiry),
+                    },
                 );
             }
         }
 
-        let remote_config = serde_json::json!({
-            "agent_id": agent_id,
-            "mcps": remote_mcps,
-            "metadata": {
-                "last_updated": config.metadata.last_modified,
-                "version": config.metadata.version
-            }
-        });
+        let remote_config = AgentRemoteConfig {
+            agent_id: agent_id.to_string(),
+            mcps,
+            bundles,
+            auth_token: agent
+                .auth_token
+                .as_ref()
+                .and(agent.auth_token_expires_at)
+                .map(remote_expiry),
+            metadata: RemoteConfigMetadata {
+                last_updated: config.metadata.last_modified,
+                version: config.metadata.version.clone(),
+                revision: config.metadata.revision,
+            },
+        };
 
         Ok(remote_config)
     }
+
+    /// Explain whether an agent may use an MCP, and a tool on it if given
+    pub async fn explain_access(
+        &self,
+        agent_id: &str,
+        mcp_id: &str,
+        tool: Option<&str>,
+    ) -> MceptionResult<AccessDecision> {
+        let config = self.config.read().await;
+
+        let agent = config.agents.get(agent_id).ok_or_else(|| {
+            MceptionError::Storage(StorageError::NotFound(format!(
+                "Agent with ID '{}' not found",
+                agent_id
+            )))
+        })?;
+
+        Ok(authorization::evaluate(&config, agent, mcp_id, tool))
+    }
+}
+
+/// Remove duplicate entries, keeping the first occurrence
+impl LeafMcpConfig {
+    /// Check a leaf MCP before it is stored under `id`: the id is safe to
+    /// use in URL paths and matches the config's own, and the transport
+    /// and settings are usable
+    pub fn validate(&self, id: &str) -> MceptionResult<()> {
+        let invalid = |message| MceptionError::Validation(ValidationError::InvalidFormat(message));
+        ids::validate(id).map_err(invalid)?;
+        if self.id != id {
+            return Err(invalid(format!(
+                "'{}' is stored under '{}', the ids must match",
+                self.id, id
+            )));
+        }
+        if let Some(instructions) = &self.instructions
+            && instructions.len() > MAX_INSTRUCTIONS_LEN
+        {
+            return Err(MceptionError::Validation(ValidationError::ValueOutOfRange(
+                format!(
+                    "instructions are {} bytes long, at most {} are allowed",
+                    instructions.len(),
+                    MAX_INSTRUCTIONS_LEN
+                ),
+            )));
+        }
+        deadline::configured_timeout(&self.config).map_err(invalid)?;
+        if self.timeout_ms == Some(0) {
+            return Err(invalid("timeout_ms must be positive".to_string()));
+        }
+        if let Some(policy) = &self.retry {
+            retry::validate(policy).map_err(invalid)?;
+        }
+        if self.max_concurrent_requests == Some(0) {
+            return Err(invalid(
+                "max_concurrent_requests must be positive".to_string(),
+            ));
+        }
+        if let Some(policy) = &self.header_policy {
+            https::validate_header_policy(policy).map_err(invalid)?;
+        }
+        tool_shaping::names(&self.config, tool_shaping::TOOL_PRIORITY_KEY).map_err(invalid)?;
+        if self.replica_group.as_deref() == Some("") {
+            return Err(invalid("replica_group must not be empty".to_string()));
+        }
+        labels::validate(&self.labels).map_err(invalid)?;
+        match &self.transport {
+            McpTransport::Stdio {
+                command, sandbox, ..
+            } => {
+                if command.trim().is_empty() {
+                    return Err(invalid("stdio transport needs a command".to_string()));
+                }
+                sandbox::validate(sandbox)?
+            }
+            McpTransport::Https { url, headers } | McpTransport::Sse { url, headers } => {
+                https::validate(url, headers.as_ref()).map_err(invalid)?
+            }
+            McpTransport::Builtin { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+fn dedup(items: Vec<String>) -> Vec<String> {
+    let mut unique = Vec::with_capacity(items.len());
+    for item in items {
+        if !unique.contains(&item) {
+            unique.push(item);
+        }
+    }
+    unique
 }
@@ -0,0 +1,90 @@
+use crate::core::{AgentConfig, BUNDLE_PREFIX, ServerConfig};
+use crate::services::{ConfigService, authorization};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Committed revisions kept for subscribers that fall behind, beyond which
+/// they only learn the latest revision
+pub const CAPACITY: usize = 256;
+
+/// A committed revision and the agents whose remote configuration it changed
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigChanged {
+    pub revision: u64,
+    pub agents: BTreeSet<String>,
+}
+
+/// The agents whose remote configuration differs between `before` and
+/// `after`: their own settings or grants changed, or an MCP or bundle they
+/// may use did. Added and removed agents are included.
+pub fn affected_agents(before: &ServerConfig, after: &ServerConfig) -> BTreeSet<String> {
+    before
+        .agents
+        .keys()
+        .chain(after.agents.keys())
+        .filter(|agent_id| view(before, agent_id) != view(after, agent_id))
+        .cloned()
+        .collect()
+}
+
+/// The next committed revision that concerns `agent_id`. A receiver that
+/// fell behind gets the current revision, as it may have missed one. `None`
+/// once the service is gone.
+pub async fn next_for(
+    service: &ConfigService,
+    receiver: &mut broadcast::Receiver<ConfigChanged>,
+    agent_id: &str,
+) -> Option<u64> {
+    loop {
+        match receiver.recv().await {
+            Ok(changed) if changed.agents.contains(agent_id) => return Some(changed.revision),
+            Ok(_) => {}
+            Err(RecvError::Lagged(_)) => return Some(*service.subscribe_revisions().borrow()),
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// What an agent's remote configuration is built from
+fn view(config: &ServerConfig, agent_id: &str) -> Option<Value> {
+    let agent = config.agents.get(agent_id)?;
+    let mcps: BTreeMap<String, Option<Value>> = authorization::allowed_mcps(config, agent)
+        .into_iter()
+        .map(|mcp_id| {
+            let mcp = match config.leaf_mcps.get(&mcp_id) {
+                Some(leaf) => serde_json::to_value(leaf).ok(),
+                None => config.agents.get(&mcp_id).and_then(settings),
+            };
+            (mcp_id, mcp)
+        })
+        .collect();
+    let bundles: BTreeMap<&str, Option<Value>> = agent
+        .allowed_mcp_ids
+        .iter()
+        .filter_map(|grant| grant.strip_prefix(BUNDLE_PREFIX))
+        .map(|name| {
+            let bundle = config
+                .bundles
+                .get(name)
+                .and_then(|bundle| serde_json::to_value(bundle).ok());
+            (name, bundle)
+        })
+        .collect();
+    Some(serde_json::json!({
+        "agent": settings(agent),
+        "mcps": mcps,
+        "bundles": bundles,
+    }))
+}
+
+/// An agent as configured, without whether and when it was last seen
+fn settings(agent: &AgentConfig) -> Option<Value> {
+    let mut value = serde_json::to_value(agent).ok()?;
+    if let Some(fields) = value.as_object_mut() {
+        fields.remove("is_connected");
+        fields.remove("last_seen");
+    }
+    Some(value)
+}
@@ -0,0 +1,109 @@
+use crate::services::json_schema;
+use serde_json::Value;
+
+/// Default size of a leaf MCP's or agent's `config` serialized as JSON
+pub const DEFAULT_MAX_BYTES: usize = 64 * 1024;
+
+/// Default nesting of objects and arrays in a `config`
+pub const DEFAULT_MAX_DEPTH: usize = 16;
+
+/// Default number of object keys in a `config`, at every level
+pub const DEFAULT_MAX_KEYS: usize = 1024;
+
+/// How large the free-form `config` of leaf MCPs and agents may grow. Every
+/// save, clone and audit entry carries it.
+#[derive(Debug, Clone, Copy)]
+pub struct ConfigLimits {
+    pub max_bytes: usize,
+    pub max_depth: usize,
+    pub max_keys: usize,
+}
+
+impl Default for ConfigLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_keys: DEFAULT_MAX_KEYS,
+        }
+    }
+}
+
+/// Size, nesting and key count of a `config`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlobSize {
+    pub bytes: usize,
+    pub depth: usize,
+    pub keys: usize,
+}
+
+impl BlobSize {
+    pub fn of(value: &Value) -> Self {
+        let (depth, keys) = shape(value);
+        Self {
+            bytes: serde_json::to_vec(value).map_or(0, |json| json.len()),
+            depth,
+            keys,
+        }
+    }
+}
+
+/// Nesting depth and number of object keys below `value`
+fn shape(value: &Value) -> (usize, usize) {
+    match value {
+        Value::Object(map) => map.values().fold((1, map.len()), |(depth, keys), value| {
+            let (inner_depth, inner_keys) = shape(value);
+            (depth.max(inner_depth + 1), keys + inner_keys)
+        }),
+        Value::Array(items) => items.iter().fold((1, 0), |(depth, keys), value| {
+            let (inner_depth, inner_keys) = shape(value);
+            (depth.max(inner_depth + 1), keys + inner_keys)
+        }),
+        _ => (0, 0),
+    }
+}
+
+impl ConfigLimits {
+    /// The limits `size` exceeds, described
+    pub fn exceeded(&self, size: BlobSize) -> Vec<String> {
+        self.grown(BlobSize::default(), size)
+    }
+
+    /// The limits `after` exceeds where it grew beyond `before`, described.
+    /// A `config` stored before the limits were lowered may stay as large,
+    /// it just can't grow further.
+    pub fn grown(&self, before: BlobSize, after: BlobSize) -> Vec<String> {
+        let mut exceeded = Vec::new();
+        if after.bytes > self.max_bytes && after.bytes > before.bytes {
+            exceeded.push(format!(
+                "is {} bytes as JSON, at most {} are allowed",
+                after.bytes, self.max_bytes
+            ));
+        }
+        if after.depth > self.max_depth && after.depth > before.depth {
+            exceeded.push(format!(
+                "nests {} levels deep, at most {} are allowed",
+                after.depth, self.max_depth
+            ));
+        }
+        if after.keys > self.max_keys && after.keys > before.keys {
+            exceeded.push(format!(
+                "has {} keys, at most {} are allowed",
+                after.keys, self.max_keys
+            ));
+        }
+        exceeded
+    }
+}
+
+/// Check `value` against a JSON Schema, returning where it first fails,
+/// see [`json_schema::errors`]
+pub fn check_schema(schema: &Value, value: &Value) -> Result<(), String> {
+    match json_schema::errors(schema, value, "config")
+        .into_iter()
+        .next()
+    {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,364 @@
+use crate::core::{ConfirmedAction, error_body};
+use crate::services::ConfigService;
+use crate::services::auth::{Actor, DEFAULT_ADMIN_ACTOR, token_matches};
+use crate::services::bulk::BulkDeletePlan;
+use crate::services::sync;
+use axum::body::Body;
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::{Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Json, Response};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Header a dangerous admin request is repeated with to carry it out
+pub const CONFIRM_TOKEN_HEADER: &str = "x-confirm-token";
+
+/// Largest request body hashed, axum's default limit of JSON bodies
+const MAX_BODY: usize = 2 * 1024 * 1024;
+
+tokio::task_local! {
+    /// Correlation id of the confirmed admin request being handled
+    static CURRENT_CORRELATION: String;
+}
+
+/// Correlation id of the confirmed admin request being handled, shared by
+/// its challenge and all entries it writes
+pub fn current_correlation_id() -> Option<String> {
+    CURRENT_CORRELATION.try_with(String::clone).ok()
+}
+
+/// What a confirmation token was issued for, answered with `428`
+#[derive(Debug, Clone, Serialize)]
+pub struct Challenge {
+    pub token: String,
+    pub action: ConfirmedAction,
+    pub method: String,
+    pub path: String,
+    /// Ids, names or tags of what the action applies to
+    pub entities: Vec<String>,
+    pub expires_at: DateTime<Utc>,
+    pub correlation_id: String,
+}
+
+/// Why a confirmation token is refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    Malformed,
+    /// Signed for another request or plan
+    Mismatched,
+    Expired,
+    Used,
+}
+
+impl Rejection {
+    pub fn reason(self) -> &'static str {
+        match self {
+            Rejection::Malformed => "the confirmation token is malformed",
+            Rejection::Mismatched => "the confirmation token was issued for another request",
+            Rejection::Expired => "the confirmation token expired",
+            Rejection::Used => "the confirmation token was already used",
+        }
+    }
+}
+
+/// Issues and checks the confirmation tokens of dangerous admin requests and
+/// of bulk delete plans. Tokens are signed with a secret generated at
+/// startup, so they don't survive a restart, and each is accepted once.
+#[derive(Debug)]
+pub struct Confirmations {
+    secret: String,
+    /// Signatures of accepted tokens until they expire
+    used: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+impl Default for Confirmations {
+    fn default() -> Self {
+        Self::with_secret(format!(
+            "{}{}",
+            Uuid::new_v4().simple(),
+            Uuid::new_v4().simple()
+        ))
+    }
+}
+
+/// The exact request a token is bound to
+struct Bound<'a> {
+    actor: &'a str,
+    method: &'a Method,
+    /// Path and query
+    target: &'a str,
+    body: &'a [u8],
+}
+
+impl Bound<'_> {
+    fn subject(&self) -> String {
+        let body = hex(&Sha256::digest(self.body));
+        format!(
+            "request\n{}\n{}\n{}\n{}",
+            self.actor, self.method, self.target, body
+        )
+    }
+}
+
+/// What confirming a bulk delete plan binds: its entities and the
+/// configuration revision it was made at
+fn plan_subject(plan: &BulkDeletePlan) -> String {
+    let bound = serde_json::json!([plan.kind, plan.ids, plan.revision]);
+    format!("bulk_delete\n{}", bound)
+}
+
+impl Confirmations {
+    /// Sign with `secret` instead, for CLI runs confirming what an earlier
+    /// run planned. Such tokens show the plan was reviewed, they don't
+    /// authenticate anyone.
+    pub fn with_secret(secret: impl Into<String>) -> Self {
+        Self {
+            secret: secret.into(),
+            used: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn sign(&self, subject: &str, expires_at: i64, correlation_id: &str) -> String {
+        let payload = format!("{}\n{}\n{}", subject, expires_at, correlation_id);
+        sync::signature(&self.secret, payload.as_bytes())
+    }
+
+    fn issue(&self, subject: &str, ttl: Duration, correlation_id: &str) -> (String, DateTime<Utc>) {
+        let expires_at = Utc::now() + ttl;
+        let timestamp = expires_at.timestamp();
+        let signature = self.sign(subject, timestamp, correlation_id);
+        (
+            format!("{}.{}.{}", correlation_id, timestamp, signature),
+            expires_at,
+        )
+    }
+
+    /// The correlation id of a valid token for `subject`, or why it's not
+    fn accept(&self, token: &str, subject: &str) -> Result<String, Rejection> {
+        let mut parts = token.splitn(3, '.');
+        let (Some(correlation_id), Some(timestamp), Some(signature)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(Rejection::Malformed);
+        };
+        let expires_at = timestamp
+            .parse::<i64>()
+            .ok()
+            .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+            .ok_or(Rejection::Malformed)?;
+        let expected = self.sign(subject, expires_at.timestamp(), correlation_id);
+        if !token_matches(&expected, signature) {
+            return Err(Rejection::Mismatched);
+        }
+        let now = Utc::now();
+        if expires_at < now {
+            return Err(Rejection::Expired);
+        }
+        let mut used = self.used.lock().unwrap();
+        used.retain(|_, expires_at| *expires_at >= now);
+        if used.insert(signature.to_string(), expires_at).is_some() {
+            return Err(Rejection::Used);
+        }
+        Ok(correlation_id.to_string())
+    }
+
+    /// A token confirming exactly `plan` within `ttl`, under a new
+    /// correlation id
+    pub fn issue_for_plan(&self, plan: &BulkDeletePlan, ttl: Duration) -> String {
+        let correlation_id = Uuid::new_v4().to_string();
+        self.issue(&plan_subject(plan), ttl, &correlation_id).0
+    }
+
+    /// The correlation id of a valid token for `plan`, or why it's not
+    pub fn accept_for_plan(&self, token: &str, plan: &BulkDeletePlan) -> Result<String, Rejection> {
+        self.accept(token, &plan_subject(plan))
+    }
+}
+
+/// The action a request to the admin route `path` carries out, if it can
+/// require a confirmation. Bulk deletes only do without showing their plan.
+fn action_of(method: &Method, path: &str, query: &str, body: &[u8]) -> Option<ConfirmedAction> {
+    match (method, path) {
+        (&Method::POST, "/config/restore" | "/config/backups/{backup_name}/restore") => {
+            Some(ConfirmedAction::Restore)
+        }
+        (&Method::POST, "/leaf/bulk_delete" | "/agent/bulk_delete") => {
+            let confirm = serde_json::from_slice::<serde_json::Value>(body)
+                .is_ok_and(|body| !body["confirm"].is_null());
+            confirm.then_some(ConfirmedAction::BulkDelete)
+        }
+        (&Method::DELETE, "/leaf" | "/agent") => query
+            .split('&')
+            .any(|pair| pair.starts_with("confirm="))
+            .then_some(ConfirmedAction::BulkDelete),
+        (&Method::DELETE, "/leaf/{leaf_mcp_id}") => Some(ConfirmedAction::DeleteLeafMcp),
+        (&Method::DELETE, "/agent/{agent_id}") => Some(ConfirmedAction::DeleteAgent),
+        _ => None,
+    }
+}
+
+/// What an action applies to: the ids in its path, and the ids or tag it selects
+fn entities(matched: &str, path: &str, query: &str, body: &[u8]) -> Vec<String> {
+    let mut entities: Vec<String> = matched
+        .split('/')
+        .zip(path.split('/'))
+        .filter(|(pattern, _)| pattern.starts_with('{'))
+        .map(|(_, segment)| segment.to_string())
+        .collect();
+    if let Ok(body) = serde_json::from_slice::<serde_json::Value>(body) {
+        entities.extend(
+            body["ids"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|id| id.as_str().map(str::to_string)),
+        );
+    }
+    entities.extend(
+        query
+            .split('&')
+            .filter_map(|pair| pair.strip_prefix("tag="))
+            .map(|tag| format!("tag:{}", tag)),
+    );
+    entities
+}
+
+/// Middleware holding back the admin actions the configuration's
+/// confirmation policy names. Without a valid [`CONFIRM_TOKEN_HEADER`] the
+/// request is answered with `428` and a token bound to the actor, method,
+/// path and body; repeating it with the token within the policy's TTL
+/// carries it out. Both are audited under the token's correlation id, which
+/// the action's own entries share.
+pub async fn require_confirmation(
+    State(service): State<Arc<ConfigService>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // Nested under `/admin`, which the request's own path has stripped
+    let Some(matched) = request.extensions().get::<MatchedPath>().map(|matched| {
+        let matched = matched.as_str();
+        matched
+            .strip_prefix("/admin")
+            .unwrap_or(matched)
+            .to_string()
+    }) else {
+        return next.run(request).await;
+    };
+    if !matches!(*request.method(), Method::POST | Method::DELETE) {
+        return next.run(request).await;
+    }
+    let policy = service.confirmation_policy().await;
+    let actor = request
+        .extensions()
+        .get::<Actor>()
+        .map_or(DEFAULT_ADMIN_ACTOR.to_string(), |actor| actor.0.clone());
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let query = request.uri().query().unwrap_or_default().to_string();
+    let token = request
+        .headers()
+        .get(CONFIRM_TOKEN_HEADER)
+        .and_then(|token| token.to_str().ok())
+        .map(str::to_string);
+
+    let (parts, body) = request.into_parts();
+    let body = match axum::body::to_bytes(body, MAX_BODY).await {
+        Ok(body) => body,
+        Err(e) => {
+            return (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                Json(error_body(
+                    "invalid_request",
+                    format!("Failed to read the request body: {}", e),
+                )),
+            )
+                .into_response();
+        }
+    };
+    let request = Request::from_parts(parts, Body::from(body.clone()));
+    let Some(action) = action_of(&method, &matched, &query, &body)
+        .filter(|action| policy.actions.contains(action))
+    else {
+        return next.run(request).await;
+    };
+
+    let target = match query.as_str() {
+        "" => path.clone(),
+        query => format!("{}?{}", path, query),
+    };
+    let bound = Bound {
+        actor: &actor,
+        method: &method,
+        target: &target,
+        body: &body,
+    };
+    let confirmations = service.confirmations();
+    let subject = bound.subject();
+    let rejected = match token
+        .as_deref()
+        .map(|token| confirmations.accept(token, &subject))
+    {
+        Some(Ok(correlation_id)) => {
+            let details = serde_json::json!({
+                "outcome": "confirmed",
+                "action": action,
+                "method": method.as_str(),
+                "path": target,
+            });
+            service
+                .audit_confirmation(&actor, &correlation_id, details)
+                .await;
+            return CURRENT_CORRELATION
+                .scope(correlation_id, next.run(request))
+                .await;
+        }
+        Some(Err(rejection)) => Some(rejection.reason()),
+        None => None,
+    };
+
+    let correlation_id = Uuid::new_v4().to_string();
+    let (token, expires_at) = confirmations.issue(&subject, policy.token_ttl(), &correlation_id);
+    let challenge = Challenge {
+        token,
+        action,
+        method: method.to_string(),
+        path: target.clone(),
+        entities: entities(&matched, &path, &query, &body),
+        expires_at,
+        correlation_id: correlation_id.clone(),
+    };
+    let details = serde_json::json!({
+        "outcome": "challenged",
+        "action": action,
+        "method": challenge.method,
+        "path": challenge.path,
+        "entities": challenge.entities,
+        "expires_at": challenge.expires_at,
+        "rejected": rejected,
+    });
+    service
+        .audit_confirmation(&actor, &correlation_id, details)
+        .await;
+    let message = match rejected {
+        Some(reason) => format!(
+            "Not carried out, {}; repeat the request with the new token",
+            reason
+        ),
+        None => format!(
+            "Repeat the request with the {} header within {}s to carry it out",
+            CONFIRM_TOKEN_HEADER, policy.token_ttl_secs
+        ),
+    };
+    let mut body = error_body("confirmation_required", message);
+    body["confirmation"] = serde_json::json!(challenge);
+    (StatusCode::PRECONDITION_REQUIRED, Json(body)).into_response()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,425 @@
+use crate::core::{BodyEncoding, ForwardingMessage, MceptionError, MceptionResult, NetworkError};
+use crate::services::https::{self, PROTOCOL_VERSION_HEADER, SESSION_HEADER};
+use crate::services::internals::{
+    ConnectionUsage, DEFAULT_MAX_PENDING_AGENT_REQUESTS, DEFAULT_MAX_PENDING_REQUESTS_PER_AGENT,
+};
+use crate::services::stdio;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, warn};
+
+/// Upper bound for an agent to answer a forwarded request
+pub const DEFAULT_AGENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A request to forward to an agent over its WebSocket
+#[derive(Debug, Clone)]
+pub struct AgentRequest {
+    pub url_params: String,
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<Vec<u8>>,
+}
+
+/// An agent's answer to a forwarded request
+#[derive(Debug, Clone)]
+pub struct AgentResponse {
+    pub status_code: u16,
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<Vec<u8>>,
+}
+
+/// A forwarded request waiting for the agent's response
+struct PendingRequest {
+    sender: oneshot::Sender<MceptionResult<AgentResponse>>,
+    timeout: Duration,
+    /// When the request's deadline passed and it is evicted unanswered
+    expires_at: Instant,
+}
+
+type Pending = HashMap<String, PendingRequest>;
+
+/// The WebSocket an agent is connected through
+struct AgentConnection {
+    /// Distinguishes a reconnected agent's socket from the one it replaced
+    id: u64,
+    outgoing: mpsc::UnboundedSender<ForwardingMessage>,
+    /// Forwarded requests waiting for their response, by request id
+    pending: Pending,
+}
+
+/// Agents connected over `/agent/{agent_id}/forwarding_ws`, through which
+/// requests to `/agent/{agent_id}/forwarding` are passed on to them
+pub struct ConnectionService {
+    connections: Mutex<HashMap<String, AgentConnection>>,
+    next_id: AtomicU64,
+    /// Requests that may wait for agents at the same time
+    max_pending: usize,
+    /// Requests that may wait for one agent at the same time
+    max_pending_per_connection: usize,
+    rejected: AtomicU64,
+    evicted: AtomicU64,
+    orphaned: AtomicU64,
+}
+
+impl Default for ConnectionService {
+    fn default() -> Self {
+        Self::with_max_pending(DEFAULT_MAX_PENDING_AGENT_REQUESTS)
+    }
+}
+
+/// A registered agent socket. Messages to send down the socket arrive on
+/// `outgoing`.
+pub struct Registration {
+    pub connection_id: u64,
+    pub outgoing: mpsc::UnboundedReceiver<ForwardingMessage>,
+}
+
+/// Removes a pending request when its caller gives up, e.g. on a timeout
+struct PendingGuard<'a> {
+    service: &'a ConnectionService,
+    agent_id: &'a str,
+    request_id: String,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(connection) = self
+            .service
+            .connections
+            .lock()
+            .unwrap()
+            .get_mut(self.agent_id)
+        {
+            connection.pending.remove(&self.request_id);
+        }
+    }
+}
+
+impl ConnectionService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_pending(max_pending: usize) -> Self {
+        Self {
+            connections: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+            max_pending,
+            max_pending_per_connection: DEFAULT_MAX_PENDING_REQUESTS_PER_AGENT,
+            rejected: AtomicU64::new(0),
+            evicted: AtomicU64::new(0),
+            orphaned: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_max_pending_per_connection(mut self, max_pending: usize) -> Self {
+        self.max_pending_per_connection = max_pending;
+        self
+    }
+
+    pub fn usage(&self) -> ConnectionUsage {
+        let mut connections = self.connections.lock().unwrap();
+        self.evict_expired(&mut connections);
+        ConnectionUsage {
+            connections: connections.len(),
+            pending_requests: connections
+                .values()
+                .map(|connection| connection.pending.len())
+                .sum(),
+            capacity: self.max_pending,
+            capacity_per_connection: self.max_pending_per_connection,
+            rejected: self.rejected.load(Ordering::Relaxed),
+            evicted: self.evicted.load(Ordering::Relaxed),
+            orphaned_responses: self.orphaned.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Fail the requests whose deadline passed as timed out, in case their
+    /// callers didn't, e.g. while their tasks are starved
+    fn evict_expired(&self, connections: &mut HashMap<String, AgentConnection>) {
+        let now = Instant::now();
+        for (agent_id, connection) in connections.iter_mut() {
+            for (_, request) in connection
+                .pending
+                .extract_if(|_, request| request.expires_at <= now)
+            {
+                self.evicted.fetch_add(1, Ordering::Relaxed);
+                let _ = request
+                    .sender
+                    .send(Err(timed_out(agent_id, request.timeout)));
+            }
+        }
+    }
+
+    /// Register an agent's socket, replacing an earlier one of the same agent.
+    /// Requests waiting on the replaced socket fail as disconnected.
+    pub fn register(&self, agent_id: &str) -> Registration {
+        let connection_id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (outgoing, receiver) = mpsc::unbounded_channel();
+        let replaced = self.connections.lock().unwrap().insert(
+            agent_id.to_string(),
+            AgentConnection {
+                id: connection_id,
+                outgoing,
+                pending: Pending::new(),
+            },
+        );
+        if replaced.is_some() {
+            info!(
+                "Agent '{}' reconnected, replacing its previous socket",
+                agent_id
+            );
+        } else {
+            info!("Agent '{}' connected", agent_id);
+        }
+        Registration {
+            connection_id,
+            outgoing: receiver,
+        }
+    }
+
+    /// Remove an agent's socket once it closed, unless it was replaced in the
+    /// meantime. Requests waiting on it fail as disconnected. Returns whether
+    /// the agent is no longer connected.
+    pub fn unregister(&self, agent_id: &str, connection_id: u64) -> bool {
+        let mut connections = self.connections.lock().unwrap();
+        if connections
+            .get(agent_id)
+            .is_some_and(|connection| connection.id == connection_id)
+        {
+            connections.remove(agent_id);
+            info!("Agent '{}' disconnected", agent_id);
+            return true;
+        }
+        false
+    }
+
+    /// Resolve a forwarded request with the agent's response. Responses to
+    /// unknown, abandoned or evicted requests are dropped.
+    pub fn deliver(&self, agent_id: &str, connection_id: u64, message: ForwardingMessage) {
+        let ForwardingMessage::Response {
+            request_id,
+            status_code,
+            headers,
+            body,
+            encoding,
+        } = message
+        else {
+            warn!(
+                "Agent '{}' sent something other than a response over its forwarding socket, ignoring it",
+                agent_id
+            );
+            return;
+        };
+        let waiting = self
+            .connections
+            .lock()
+            .unwrap()
+            .get_mut(agent_id)
+            .filter(|connection| connection.id == connection_id)
+            .and_then(|connection| connection.pending.remove(&request_id));
+        match waiting {
+            Some(waiting) => {
+                let response = body
+                    .map(|body| encoding.decode(body))
+                    .transpose()
+                    .map(|body| AgentResponse {
+                        status_code,
+                        headers,
+                        body,
+                    })
+                    .map_err(|e| {
+                        NetworkError::ConnectionFailed(format!(
+                            "Agent '{}' answered with an invalid body: {}",
+                            agent_id, e
+                        ))
+                        .into()
+                    });
+                let _ = waiting.sender.send(response);
+            }
+            // Mostly answers arriving after the deadline
+            None => {
+                self.orphaned.fetch_add(1, Ordering::Relaxed);
+                debug!(
+                    "Agent '{}' answered unknown or abandoned request '{}', dropping it",
+                    agent_id, request_id
+                );
+            }
+        }
+    }
+
+    /// Send a request down the agent's socket and wait up to `timeout` for
+    /// its response. Fails with [`NetworkError::ConnectionFailed`] if the
+    /// agent is not connected or disconnects before answering, and with
+    /// [`NetworkError::Timeout`] if it doesn't answer in time, evicting the
+    /// request. Fails with [`NetworkError::Overloaded`] while as many requests
+    /// as allowed are waiting for agents, or for this agent.
+    pub async fn forward(
+        &self,
+        agent_id: &str,
+        request: AgentRequest,
+        timeout: Duration,
+    ) -> MceptionResult<AgentResponse> {
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let (sender, receiver) = oneshot::channel();
+        {
+            let mut connections = self.connections.lock().unwrap();
+            self.evict_expired(&mut connections);
+            let pending: usize = connections
+                .values()
+                .map(|connection| connection.pending.len())
+                .sum();
+            if pending >= self.max_pending {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                return Err(NetworkError::Overloaded(format!(
+                    "{} requests are already waiting for agents",
+                    pending
+                ))
+                .into());
+            }
+            let Some(connection) = connections.get_mut(agent_id) else {
+                return Err(not_connected(agent_id));
+            };
+            if connection.pending.len() >= self.max_pending_per_connection {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                return Err(NetworkError::Overloaded(format!(
+                    "{} requests are already waiting for agent '{}'",
+                    connection.pending.len(),
+                    agent_id
+                ))
+                .into());
+            }
+            let (body, encoding) = match request.body {
+                Some(body) => {
+                    let (body, encoding) = BodyEncoding::encode(body);
+                    (Some(body), encoding)
+                }
+                None => (None, BodyEncoding::Utf8),
+            };
+            let message = ForwardingMessage::Request {
+                request_id: request_id.clone(),
+                url_params: request.url_params,
+                headers: request.headers,
+                body,
+                encoding,
+                deadline_ms: Some(timeout.as_millis() as u64),
+            };
+            if connection.outgoing.send(message).is_err() {
+                return Err(not_connected(agent_id));
+            }
+            connection.pending.insert(
+                request_id.clone(),
+                PendingRequest {
+                    sender,
+                    timeout,
+                    expires_at: Instant::now() + timeout,
+                },
+            );
+        }
+        let guard = PendingGuard {
+            service: self,
+            agent_id,
+            request_id,
+        };
+
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(NetworkError::ConnectionFailed(format!(
+                "Agent '{}' disconnected before answering",
+                agent_id
+            ))
+            .into()),
+            Err(_) => {
+                if let Some(connection) = self.connections.lock().unwrap().get_mut(agent_id)
+                    && connection.pending.remove(&guard.request_id).is_some()
+                {
+                    self.evicted.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(timed_out(agent_id, timeout))
+            }
+        }
+    }
+
+    /// Send a single JSON-RPC request of the server's own, e.g. `tools/list`,
+    /// to the MCP the agent exposes, the way [`https::HttpsForwarder::call`]
+    /// does for HTTPS leaf MCPs
+    pub async fn call(
+        &self,
+        agent_id: &str,
+        session: &mut Option<String>,
+        message: &Value,
+        timeout: Duration,
+    ) -> MceptionResult<Value> {
+        let mut headers = BTreeMap::from([
+            ("content-type".to_string(), "application/json".to_string()),
+            (
+                "accept".to_string(),
+                "application/json, text/event-stream".to_string(),
+            ),
+            (
+                PROTOCOL_VERSION_HEADER.to_string(),
+                stdio::PROTOCOL_VERSION.to_string(),
+            ),
+        ]);
+        if let Some(session) = session.as_deref() {
+            headers.insert(SESSION_HEADER.to_string(), session.to_string());
+        }
+        let request = AgentRequest {
+            url_params: String::new(),
+            headers,
+            body: Some(message.to_string().into_bytes()),
+        };
+        let response = self.forward(agent_id, request, timeout).await?;
+
+        let header = |name: &str| {
+            response
+                .headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        };
+        if let Some(id) = header(SESSION_HEADER) {
+            *session = Some(id.to_string());
+        }
+        if !(200..300).contains(&response.status_code) {
+            return Err(NetworkError::ConnectionFailed(format!(
+                "Agent '{}' answered with HTTP {}",
+                agent_id, response.status_code
+            ))
+            .into());
+        }
+        // Notifications are only acknowledged
+        let Some(id) = message.get("id") else {
+            return Ok(Value::Null);
+        };
+        let body = String::from_utf8_lossy(response.body.as_deref().unwrap_or_default());
+        let parsed = if header("content-type").is_some_and(|t| t.starts_with("text/event-stream")) {
+            https::event_stream_message(&body, id)
+        } else {
+            serde_json::from_str(&body).ok()
+        };
+        parsed.ok_or_else(|| {
+            NetworkError::ConnectionFailed(format!(
+                "Agent '{}' answered without a JSON-RPC response",
+                agent_id
+            ))
+            .into()
+        })
+    }
+}
+
+fn timed_out(agent_id: &str, timeout: Duration) -> MceptionError {
+    NetworkError::Timeout(format!(
+        "Agent '{}' did not answer within {} ms",
+        agent_id,
+        timeout.as_millis()
+    ))
+    .into()
+}
+
+fn not_connected(agent_id: &str) -> MceptionError {
+    NetworkError::ConnectionFailed(format!("Agent '{}' is not connected", agent_id)).into()
+}
@@ -0,0 +1,121 @@
+use crate::core::{AuditAction, AuditLogEntry, AuditTarget, ServerMetadata};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+/// How much later than the configuration's `last_modified` an audit entry of
+/// the same mutation may be timestamped, as it is written right after
+pub const CLOCK_TOLERANCE_MS: i64 = 1000;
+
+/// Why the audit log and the configuration are expected to diverge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Discontinuity {
+    /// A backup replaced the configuration
+    BackupRestore,
+    /// An administrator reviewed a detected gap
+    Acknowledged,
+    /// An imported configuration replaced or was merged into the configuration
+    Import,
+}
+
+impl Discontinuity {
+    /// Explanation recorded with the marker entry
+    pub fn explanation(self) -> &'static str {
+        match self {
+            Discontinuity::BackupRestore => {
+                "The configuration was replaced by a backup. Changes audited before this entry \
+                 are not reflected in it."
+            }
+            Discontinuity::Import => {
+                "A configuration was imported. The imported leaf MCPs, agents and bundles \
+                 are not audited individually."
+            }
+            Discontinuity::Acknowledged => {
+                "A gap between the audit log and the configuration was reviewed. Changes \
+                 audited before this entry are not reflected in the configuration."
+            }
+        }
+    }
+}
+
+/// An audited configuration change the configuration doesn't reflect
+#[derive(Debug, Clone, Serialize)]
+pub struct GapEntry {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub action: AuditAction,
+    pub target: AuditTarget,
+}
+
+/// Result of comparing the configuration with the audit log
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsistencyReport {
+    pub consistent: bool,
+    pub revision: u64,
+    pub last_modified: DateTime<Utc>,
+    /// Timestamp of the newest audited configuration change
+    pub newest_change: Option<DateTime<Utc>>,
+    /// Id of the newest discontinuity marker, entries before it are not compared
+    pub marker_id: Option<String>,
+    /// Changes audited after the configuration was last modified, oldest first
+    pub gap: Vec<GapEntry>,
+}
+
+impl ConsistencyReport {
+    /// Whether the newest gap entry was already recorded by a `consistency_gap` entry
+    pub fn already_recorded(&self, entries: &[AuditLogEntry]) -> bool {
+        let Some(newest) = self.gap.last() else {
+            return false;
+        };
+        entries.iter().any(|entry| {
+            matches!(entry.action, AuditAction::ConsistencyGap)
+                && entry.details["newest_entry_id"] == newest.id.as_str()
+        })
+    }
+}
+
+/// Whether an audit action changes the configuration's content
+fn changes_configuration(action: &AuditAction) -> bool {
+    matches!(
+        action,
+        AuditAction::Create
+            | AuditAction::Update
+            | AuditAction::Delete
+            | AuditAction::AddAllowedMcp
+            | AuditAction::RemoveAllowedMcp
+    )
+}
+
+/// Compare the configuration's metadata with the audit log. The audit log is
+/// ahead when it holds configuration changes newer than the configuration,
+/// e.g. after a backup was copied over it.
+pub fn check(metadata: &ServerMetadata, entries: &[AuditLogEntry]) -> ConsistencyReport {
+    let marker = entries
+        .iter()
+        .rposition(|entry| matches!(entry.action, AuditAction::Discontinuity));
+    let compared = &entries[marker.map_or(0, |index| index + 1)..];
+    let cutoff = metadata.last_modified + Duration::milliseconds(CLOCK_TOLERANCE_MS);
+
+    let changes = compared
+        .iter()
+        .filter(|entry| changes_configuration(&entry.action));
+    let gap: Vec<GapEntry> = changes
+        .clone()
+        .filter(|entry| entry.timestamp > cutoff)
+        .map(|entry| GapEntry {
+            id: entry.id.clone(),
+            timestamp: entry.timestamp,
+            action: entry.action.clone(),
+            target: entry.target.clone(),
+        })
+        .collect();
+
+    ConsistencyReport {
+        consistent: gap.is_empty(),
+        revision: metadata.revision,
+        last_modified: metadata.last_modified,
+        newest_change: changes.map(|entry| entry.timestamp).max(),
+        marker_id: marker.map(|index| entries[index].id.clone()),
+        gap,
+    }
+}
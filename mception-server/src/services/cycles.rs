@@ -0,0 +1,76 @@
+use crate::core::ServerConfig;
+use std::collections::BTreeSet;
+
+/// The agents among `allowed_mcp_ids`. Bundles only contain leaf MCPs, so
+/// agents are only referenced directly.
+fn referenced_agents<'a>(
+    config: &'a ServerConfig,
+    allowed_mcp_ids: &'a [String],
+) -> impl Iterator<Item = &'a str> {
+    allowed_mcp_ids
+        .iter()
+        .filter(|mcp_id| config.agents.contains_key(*mcp_id))
+        .map(String::as_str)
+}
+
+/// The chain of agent references leading from `from` to `to`, both included
+fn path(
+    config: &ServerConfig,
+    from: &str,
+    to: &str,
+    visited: &mut BTreeSet<String>,
+) -> Option<Vec<String>> {
+    if from == to {
+        return Some(vec![to.to_string()]);
+    }
+    if !visited.insert(from.to_string()) {
+        return None;
+    }
+    let agent = config.agents.get(from)?;
+    referenced_agents(config, &agent.allowed_mcp_ids).find_map(|next| {
+        let mut chain = path(config, next, to, visited)?;
+        chain.insert(0, from.to_string());
+        Some(chain)
+    })
+}
+
+/// The cycle `agent_id` would be on with `allowed_mcp_ids` as its allowed
+/// MCPs, as the chain of agents from `agent_id` back to itself
+pub fn cycle_through(
+    config: &ServerConfig,
+    agent_id: &str,
+    allowed_mcp_ids: &[String],
+) -> Option<Vec<String>> {
+    // A self-reference is a cycle even before the agent exists
+    if allowed_mcp_ids.iter().any(|mcp_id| mcp_id == agent_id) {
+        return Some(vec![agent_id.to_string(), agent_id.to_string()]);
+    }
+    let mut visited = BTreeSet::new();
+    referenced_agents(config, allowed_mcp_ids).find_map(|next| {
+        let mut chain = path(config, next, agent_id, &mut visited)?;
+        chain.insert(0, agent_id.to_string());
+        Some(chain)
+    })
+}
+
+/// Every cycle of agent references in the configuration, each once and
+/// starting at its smallest agent id
+pub fn find_cycles(config: &ServerConfig) -> Vec<Vec<String>> {
+    let mut seen = BTreeSet::new();
+    let mut cycles = Vec::new();
+    for (agent_id, agent) in &config.agents {
+        let Some(cycle) = cycle_through(config, agent_id, &agent.allowed_mcp_ids) else {
+            continue;
+        };
+        let members: BTreeSet<_> = cycle.iter().cloned().collect();
+        if seen.insert(members) {
+            cycles.push(cycle);
+        }
+    }
+    cycles
+}
+
+/// A chain like `a → b → a`
+pub fn describe(chain: &[String]) -> String {
+    chain.join(" → ")
+}
@@ -0,0 +1,128 @@
+use crate::core::{LeafMcpConfig, duration};
+use axum::http::HeaderMap;
+use serde::Serialize;
+use serde_json::Value;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Header carrying the caller's remaining time budget in milliseconds. Accepted
+/// from agents and forwarded to HTTPS leaf MCPs with the budget left.
+pub const DEADLINE_HEADER: &str = "x-mception-deadline-ms";
+
+/// Upper bound for a single leaf MCP call, unless set otherwise with `--leaf-timeout`
+pub const DEFAULT_LEAF_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Key in a leaf MCP's `config` overriding [`DEFAULT_LEAF_TIMEOUT`], e.g. `"10s"`
+pub const LEAF_TIMEOUT_KEY: &str = "timeout";
+
+/// The timeout set in a leaf MCP's `config`, if any
+pub fn configured_timeout(config: &Value) -> Result<Option<Duration>, String> {
+    let Some(value) = config.get(LEAF_TIMEOUT_KEY) else {
+        return Ok(None);
+    };
+    value
+        .as_str()
+        .and_then(duration::parse_duration)
+        .and_then(|timeout| timeout.to_std().ok())
+        .filter(|timeout| !timeout.is_zero())
+        .map(Some)
+        .ok_or_else(|| {
+            format!(
+                "config.{} must be a positive duration like \"10s\" or \"500ms\"",
+                LEAF_TIMEOUT_KEY
+            )
+        })
+}
+
+/// The timeout set for a leaf MCP, by its `timeout_ms` or else in its
+/// `config`, if any
+pub fn own_timeout(leaf: &LeafMcpConfig) -> Option<Duration> {
+    leaf.timeout_ms
+        .map(Duration::from_millis)
+        .or_else(|| configured_timeout(&leaf.config).ok().flatten())
+}
+
+/// Timeout of a single call to a leaf MCP, as configured or `default`
+pub fn leaf_timeout(leaf: &LeafMcpConfig, default: Duration) -> Duration {
+    own_timeout(leaf).unwrap_or(default)
+}
+
+/// Which limit determined a deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadlineBound {
+    /// The deadline sent by the agent
+    AgentDeadline,
+    /// The timeout configured for the leaf MCP
+    LeafTimeout,
+}
+
+/// The point in time a forwarded request has to be answered by
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    expires_at: Instant,
+    budget: Duration,
+    bound: DeadlineBound,
+}
+
+/// A forwarded request did not finish before its deadline
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct DeadlineExceeded {
+    pub bound: DeadlineBound,
+    pub budget_ms: u128,
+}
+
+impl Deadline {
+    /// Deadline of min(agent deadline, leaf timeout), starting now
+    pub fn new(agent_deadline: Option<Duration>, leaf_timeout: Duration) -> Self {
+        let (budget, bound) = match agent_deadline {
+            Some(agent) if agent < leaf_timeout => (agent, DeadlineBound::AgentDeadline),
+            _ => (leaf_timeout, DeadlineBound::LeafTimeout),
+        };
+        Self {
+            expires_at: Instant::now() + budget,
+            budget,
+            bound,
+        }
+    }
+
+    /// Time left until the deadline
+    pub fn remaining(&self) -> Duration {
+        self.expires_at.saturating_duration_since(Instant::now())
+    }
+
+    /// Remaining budget to send along to HTTPS leaf MCPs in [`DEADLINE_HEADER`]
+    pub fn header_value(&self) -> String {
+        self.remaining().as_millis().to_string()
+    }
+
+    /// Run `future` until the deadline, dropping (and thereby cancelling) it
+    /// once the deadline passes
+    pub async fn run<F: Future>(&self, future: F) -> Result<F::Output, DeadlineExceeded> {
+        tokio::time::timeout_at(self.expires_at, future)
+            .await
+            .map_err(|_| DeadlineExceeded {
+                bound: self.bound,
+                budget_ms: self.budget.as_millis(),
+            })
+    }
+}
+
+/// Read the agent's deadline from [`DEADLINE_HEADER`], if present
+pub fn from_headers(headers: &HeaderMap) -> Result<Option<Duration>, String> {
+    let Some(value) = headers.get(DEADLINE_HEADER) else {
+        return Ok(None);
+    };
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(|ms| Some(Duration::from_millis(ms)))
+        .ok_or_else(|| {
+            format!(
+                "{} must be a non-negative number of milliseconds",
+                DEADLINE_HEADER
+            )
+        })
+}
@@ -0,0 +1,218 @@
+use crate::core::{REDACTED, is_secret_key};
+use crate::services::internals::CaptureUsage;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// Default capture window when none is given
+pub const DEFAULT_WINDOW: Duration = Duration::minutes(10);
+
+/// Longest capture window that can be requested
+pub const MAX_WINDOW: Duration = Duration::hours(1);
+
+/// Default per-payload size limit
+pub const DEFAULT_MAX_BYTES: usize = 4096;
+
+/// Largest per-payload size limit that can be requested
+pub const MAX_MAX_BYTES: usize = 64 * 1024;
+
+/// Number of payloads kept per leaf MCP, older ones are dropped first
+const RING_CAPACITY: usize = 200;
+
+/// Direction of a captured payload, relative to the leaf MCP
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureDirection {
+    Request,
+    Response,
+}
+
+/// A single forwarded payload recorded while capture was enabled
+#[derive(Debug, Clone, Serialize)]
+pub struct CapturedPayload {
+    pub timestamp: DateTime<Utc>,
+    pub direction: CaptureDirection,
+    /// HTTP status of responses
+    pub status: Option<u16>,
+    /// Size of the original payload before redaction and truncation
+    pub size_bytes: usize,
+    pub truncated: bool,
+    pub body: String,
+}
+
+/// State of the debug capture for one leaf MCP
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureState {
+    pub session_id: String,
+    pub enabled: bool,
+    pub enabled_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub max_bytes: usize,
+    pub dropped: usize,
+    pub payloads: VecDeque<CapturedPayload>,
+}
+
+impl CaptureState {
+    fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.enabled && now < self.expires_at
+    }
+}
+
+/// In-memory, bounded capture of forwarded payloads per leaf MCP.
+///
+/// Captures are never written to disk and are lost on restart.
+#[derive(Debug, Default)]
+pub struct DebugCaptures {
+    captures: RwLock<HashMap<String, CaptureState>>,
+}
+
+impl DebugCaptures {
+    pub async fn usage(&self) -> CaptureUsage {
+        let captures = self.captures.read().await;
+        CaptureUsage {
+            captures: captures.len(),
+            payloads: captures.values().map(|state| state.payloads.len()).sum(),
+            approx_bytes: captures
+                .values()
+                .flat_map(|state| &state.payloads)
+                .map(|payload| payload.body.len())
+                .sum(),
+        }
+    }
+
+    /// Start a new capture window for a leaf MCP, discarding any previous capture
+    pub async fn enable(&self, leaf_id: &str, window: Duration, max_bytes: usize) -> CaptureState {
+        let now = Utc::now();
+        let state = CaptureState {
+            session_id: Uuid::new_v4().to_string(),
+            enabled: true,
+            enabled_at: now,
+            expires_at: now + window,
+            max_bytes,
+            dropped: 0,
+            payloads: VecDeque::new(),
+        };
+        self.captures
+            .write()
+            .await
+            .insert(leaf_id.to_string(), state.clone());
+        state
+    }
+
+    /// Stop capturing and discard everything captured for a leaf MCP.
+    /// Returns whether a capture was active.
+    pub async fn disable(&self, leaf_id: &str) -> bool {
+        self.captures
+            .write()
+            .await
+            .remove(leaf_id)
+            .is_some_and(|state| state.is_active(Utc::now()))
+    }
+
+    /// Mark the capture session as ended, keeping its payloads for retrieval.
+    /// Returns false if the session was replaced or disabled in the meantime.
+    pub async fn expire(&self, leaf_id: &str, session_id: &str) -> bool {
+        let mut captures = self.captures.write().await;
+        match captures.get_mut(leaf_id) {
+            Some(state) if state.session_id == session_id && state.enabled => {
+                state.enabled = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Current capture for a leaf MCP, if any
+    pub async fn get(&self, leaf_id: &str) -> Option<CaptureState> {
+        let mut state = self.captures.read().await.get(leaf_id).cloned()?;
+        state.enabled = state.is_active(Utc::now());
+        Some(state)
+    }
+
+    /// Record a forwarded payload if capture is active for the leaf MCP
+    pub async fn record(
+        &self,
+        leaf_id: &str,
+        direction: CaptureDirection,
+        status: Option<u16>,
+        body: &[u8],
+    ) {
+        let now = Utc::now();
+        // Cheap check first so forwarding isn't serialized on the write lock
+        if !self
+            .captures
+            .read()
+            .await
+            .get(leaf_id)
+            .is_some_and(|state| state.is_active(now))
+        {
+            return;
+        }
+
+        let mut captures = self.captures.write().await;
+        let Some(state) = captures.get_mut(leaf_id) else {
+            return;
+        };
+        if !state.is_active(now) {
+            return;
+        }
+
+        let size_bytes = body.len();
+        let (body, truncated) = truncate(redact_payload(body), state.max_bytes);
+        if state.payloads.len() >= RING_CAPACITY {
+            state.payloads.pop_front();
+            state.dropped += 1;
+        }
+        state.payloads.push_back(CapturedPayload {
+            timestamp: now,
+            direction,
+            status,
+            size_bytes,
+            truncated,
+            body,
+        });
+    }
+}
+
+/// Redact secret-looking fields from JSON payloads. Non-JSON payloads are
+/// captured as (lossy) text.
+fn redact_payload(body: &[u8]) -> String {
+    match serde_json::from_slice::<Value>(body) {
+        Ok(mut value) => {
+            redact(&mut value);
+            value.to_string()
+        }
+        Err(_) => String::from_utf8_lossy(body).into_owned(),
+    }
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_secret_key(key) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+fn truncate(mut body: String, max_bytes: usize) -> (String, bool) {
+    if body.len() <= max_bytes {
+        return (body, false);
+    }
+    let mut end = max_bytes;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    body.truncate(end);
+    (body, true)
+}
@@ -0,0 +1,358 @@
+use crate::core::{
+    LeafMcpConfig, McpTransport, RegistrationPolicy, ReverseRequestPolicy, ServerConfig,
+    StdioSandbox,
+};
+use crate::services::registration_policy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Tag added to the `config` of leaf MCPs registered from a client config
+pub const DISCOVERED_TAG: &str = "discovered";
+
+/// MCP client whose configuration lists MCP servers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientKind {
+    /// Claude Desktop, `claude_desktop_config.json`
+    Claude,
+    /// VS Code user settings and workspace `.vscode/mcp.json`
+    Vscode,
+    /// Cursor, `~/.cursor/mcp.json` and workspace `.cursor/mcp.json`
+    Cursor,
+}
+
+impl ClientKind {
+    pub fn all() -> [ClientKind; 3] {
+        [ClientKind::Claude, ClientKind::Vscode, ClientKind::Cursor]
+    }
+
+    /// Well-known config locations of the client on this machine, relative
+    /// ones resolved against the working directory
+    pub fn config_paths(self) -> Vec<PathBuf> {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let app_data = std::env::var_os("APPDATA").map(PathBuf::from);
+        let mut paths = Vec::new();
+        match self {
+            ClientKind::Claude => {
+                if let Some(home) = &home {
+                    paths.push(
+                        home.join("Library/Application Support/Claude/claude_desktop_config.json"),
+                    );
+                    paths.push(home.join(".config/Claude/claude_desktop_config.json"));
+                }
+                if let Some(app_data) = &app_data {
+                    paths.push(app_data.join("Claude").join("claude_desktop_config.json"));
+                }
+            }
+            ClientKind::Vscode => {
+                if let Some(home) = &home {
+                    paths.push(home.join("Library/Application Support/Code/User/settings.json"));
+                    paths.push(home.join(".config/Code/User/settings.json"));
+                }
+                if let Some(app_data) = &app_data {
+                    paths.push(app_data.join("Code").join("User").join("settings.json"));
+                }
+                paths.push(PathBuf::from(".vscode/mcp.json"));
+            }
+            ClientKind::Cursor => {
+                if let Some(home) = &home {
+                    paths.push(home.join(".cursor/mcp.json"));
+                }
+                paths.push(PathBuf::from(".cursor/mcp.json"));
+            }
+        }
+        paths
+    }
+}
+
+/// A leaf MCP found in a client config
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveredMcp {
+    /// Name of the server in the client config
+    pub name: String,
+    /// File or upload the server was found in
+    pub source: String,
+    pub config: LeafMcpConfig,
+    /// Existing leaf MCP, or candidate found earlier, with the same command
+    /// and arguments or URL
+    pub duplicate_of: Option<String>,
+}
+
+/// A server entry that could not be converted
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedEntry {
+    pub name: String,
+    pub source: String,
+    pub reason: String,
+}
+
+/// Result of scanning client configs
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Discovery {
+    pub candidates: Vec<DiscoveredMcp>,
+    pub skipped: Vec<SkippedEntry>,
+}
+
+impl Discovery {
+    /// Candidates that are not registered yet
+    pub fn new_candidates(&self) -> impl Iterator<Item = &DiscoveredMcp> {
+        self.candidates
+            .iter()
+            .filter(|candidate| candidate.duplicate_of.is_none())
+    }
+}
+
+/// Convert the MCP servers of a client config. Understands the `mcpServers`
+/// map of Claude Desktop and Cursor, the `servers` map of `.vscode/mcp.json`
+/// and the `mcp.servers` setting of VS Code. Comments are allowed, as VS Code
+/// settings may contain them.
+pub fn parse(source: &str, content: &str) -> Result<Discovery, String> {
+    let value: Value = serde_json::from_str(&strip_comments(content))
+        .map_err(|e| format!("{} is not a valid client config: {}", source, e))?;
+
+    let servers = value
+        .get("mcpServers")
+        .or_else(|| value.get("servers"))
+        .or_else(|| value.get("mcp.servers"))
+        .or_else(|| value.get("mcp").and_then(|mcp| mcp.get("servers")))
+        .and_then(Value::as_object);
+
+    let mut discovery = Discovery::default();
+    for (name, entry) in servers.into_iter().flatten() {
+        match transport(entry) {
+            Ok(transport) => discovery.candidates.push(DiscoveredMcp {
+                name: name.clone(),
+                source: source.to_string(),
+                config: LeafMcpConfig {
+                    id: String::new(),
+                    name: Some(name.clone()),
+                    description: None,
+                    instructions: None,
+                    transport,
+                    is_local: false,
+                    reachable_by_agent: false,
+                    config: serde_json::json!({
+                        "tags": [DISCOVERED_TAG],
+                        "discovered_from": source,
+                    }),
+                    reverse_requests: ReverseRequestPolicy::default(),
+                    region: None,
+                    replica_group: None,
+                    timeout_ms: None,
+                    retry: None,
+                    header_policy: None,
+                    max_concurrent_requests: None,
+                    labels: BTreeMap::new(),
+                    revision: 0,
+                },
+                duplicate_of: None,
+            }),
+            Err(reason) => discovery.skipped.push(SkippedEntry {
+                name: name.clone(),
+                source: source.to_string(),
+                reason,
+            }),
+        }
+    }
+    Ok(discovery)
+}
+
+/// Read and convert a client config file
+pub fn parse_file(path: &Path) -> Result<Discovery, String> {
+    let source = path.display().to_string();
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", source, e))?;
+    parse(&source, &content)
+}
+
+/// Scan the well-known config locations of the clients, skipping missing files
+pub fn scan(clients: &[ClientKind]) -> Result<Discovery, String> {
+    let mut discovery = Discovery::default();
+    let mut scanned = Vec::new();
+    for path in clients.iter().flat_map(|client| client.config_paths()) {
+        // The workspace config is the user config when run from the home directory
+        let Ok(canonical) = path.canonicalize() else {
+            continue;
+        };
+        if path.is_file() && !scanned.contains(&canonical) {
+            scanned.push(canonical);
+            let found = parse_file(&path)?;
+            discovery.candidates.extend(found.candidates);
+            discovery.skipped.extend(found.skipped);
+        }
+    }
+    Ok(discovery)
+}
+
+/// Mark candidates already registered as leaf MCPs, or found earlier in the scan
+pub fn mark_duplicates(discovery: &mut Discovery, config: &ServerConfig) {
+    let mut known: Vec<(McpTransport, String)> = config
+        .leaf_mcps
+        .iter()
+        .map(|(id, leaf)| (leaf.transport.clone(), id.clone()))
+        .collect();
+    for candidate in discovery.candidates.iter_mut() {
+        candidate.duplicate_of = known
+            .iter()
+            .find(|(transport, _)| same_server(transport, &candidate.config.transport))
+            .map(|(_, id)| id.clone());
+        if candidate.duplicate_of.is_none() {
+            known.push((
+                candidate.config.transport.clone(),
+                format!("{} in {}", candidate.name, candidate.source),
+            ));
+        }
+    }
+}
+
+/// Move candidates the registration policy does not allow to `skipped`
+pub fn skip_disallowed(discovery: &mut Discovery, policy: &RegistrationPolicy) {
+    let candidates = std::mem::take(&mut discovery.candidates);
+    for candidate in candidates {
+        match registration_policy::check(policy, &candidate.config) {
+            Ok(()) => discovery.candidates.push(candidate),
+            Err(violation) => discovery.skipped.push(SkippedEntry {
+                name: candidate.name,
+                source: candidate.source,
+                reason: violation.to_string(),
+            }),
+        }
+    }
+}
+
+/// Whether two transports start the same command with the same arguments, or
+/// connect to the same URL
+fn same_server(a: &McpTransport, b: &McpTransport) -> bool {
+    match (a, b) {
+        (
+            McpTransport::Stdio {
+                command: a_command,
+                args: a_args,
+                ..
+            },
+            McpTransport::Stdio {
+                command: b_command,
+                args: b_args,
+                ..
+            },
+        ) => a_command == b_command && a_args == b_args,
+        (McpTransport::Https { url: a_url, .. }, McpTransport::Https { url: b_url, .. })
+        | (McpTransport::Sse { url: a_url, .. }, McpTransport::Sse { url: b_url, .. }) => {
+            a_url.trim_end_matches('/') == b_url.trim_end_matches('/')
+        }
+        _ => false,
+    }
+}
+
+/// Transport of a client config server entry
+fn transport(entry: &Value) -> Result<McpTransport, String> {
+    let strings = |key: &str| -> Result<Option<BTreeMap<String, String>>, String> {
+        match entry.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::Object(map)) => map
+                .iter()
+                .map(|(name, value)| match value {
+                    Value::String(value) => Ok((name.clone(), value.clone())),
+                    _ => Err(format!("'{}.{}' is not a string", key, name)),
+                })
+                .collect::<Result<_, _>>()
+                .map(Some),
+            Some(_) => Err(format!("'{}' is not an object", key)),
+        }
+    };
+
+    if let Some(command) = entry.get("command") {
+        let command = command
+            .as_str()
+            .filter(|command| !command.is_empty())
+            .ok_or("'command' is not a non-empty string")?;
+        let args = match entry.get("args") {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(args)) => args
+                .iter()
+                .map(|arg| arg.as_str().map(str::to_string))
+                .collect::<Option<_>>()
+                .ok_or("'args' contains a non-string argument")?,
+            Some(_) => return Err("'args' is not an array".to_string()),
+        };
+        let sandbox = StdioSandbox {
+            working_dir: entry.get("cwd").and_then(Value::as_str).map(str::to_string),
+            ..StdioSandbox::default()
+        };
+        return Ok(McpTransport::Stdio {
+            command: command.to_string(),
+            args,
+            env: strings("env")?,
+            sandbox,
+        });
+    }
+
+    if let Some(url) = entry.get("url").or_else(|| entry.get("serverUrl")) {
+        let url = url.as_str().ok_or("'url' is not a string")?;
+        if !url.starts_with("https://") && !url.starts_with("http://") {
+            return Err(format!("URL '{}' is not http(s)", url));
+        }
+        let url = url.to_string();
+        let headers = strings("headers")?;
+        // Clients name the legacy transport `"type": "sse"`
+        if entry.get("type").and_then(Value::as_str) == Some("sse") {
+            return Ok(McpTransport::Sse { url, headers });
+        }
+        return Ok(McpTransport::Https { url, headers });
+    }
+
+    Err("neither 'command' nor 'url' is set".to_string())
+}
+
+/// Remove `//` and `/* */` comments outside of strings, and trailing commas
+fn strip_comments(content: &str) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push(c);
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            ('}' | ']', _) => {
+                let trimmed = out.trim_end().len();
+                if out[..trimmed].ends_with(',') {
+                    out.truncate(trimmed - 1);
+                }
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,226 @@
+use crate::core::ServerConfig;
+use crate::services::ConfigService;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::warn;
+
+/// Default of how long before their expiry agent tokens and grants are reported
+pub const DEFAULT_WARNING_WINDOW: chrono::Duration = chrono::Duration::hours(72);
+
+/// How often the expiry monitor looks for tokens and grants crossing into
+/// the warning window or expiring
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often the expiring tokens and grants are summarized in the audit
+/// log, when enabled
+pub const SUMMARY_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long the expiry webhook may take to answer
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An agent token expiring within the warning window, or already expired
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpiringToken {
+    pub agent_id: String,
+    pub expires_at: DateTime<Utc>,
+    /// Whether the token is refused already
+    pub expired: bool,
+}
+
+/// An `allowed_mcp_ids` entry expiring within the warning window, or
+/// already expired
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpiringGrant {
+    pub agent_id: String,
+    /// The entry, an MCP id or `bundle:<name>`
+    pub grant: String,
+    pub expires_at: DateTime<Utc>,
+    /// Whether the grant no longer allows anything
+    pub expired: bool,
+}
+
+/// Tokens and grants expiring before `now + window`, soonest first
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Expiring {
+    pub tokens: Vec<ExpiringToken>,
+    pub grants: Vec<ExpiringGrant>,
+}
+
+impl Expiring {
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty() && self.grants.is_empty()
+    }
+}
+
+/// What of `config` expires before `now + window`
+pub fn expiring(config: &ServerConfig, window: chrono::Duration, now: DateTime<Utc>) -> Expiring {
+    let until = now + window;
+    let mut tokens: Vec<ExpiringToken> = config
+        .agents
+        .iter()
+        .filter(|(_, agent)| agent.auth_token.is_some())
+        .filter_map(|(agent_id, agent)| {
+            let expires_at = agent.auth_token_expires_at?;
+            (expires_at <= until).then(|| ExpiringToken {
+                agent_id: agent_id.clone(),
+                expires_at,
+                expired: expires_at <= now,
+            })
+        })
+        .collect();
+    tokens.sort_by(|a, b| (a.expires_at, &a.agent_id).cmp(&(b.expires_at, &b.agent_id)));
+    let mut grants: Vec<ExpiringGrant> = config
+        .agents
+        .iter()
+        .flat_map(|(agent_id, agent)| {
+            agent
+                .grant_expiry
+                .iter()
+                .filter(|(grant, expires_at)| {
+                    **expires_at <= until && agent.allowed_mcp_ids.contains(grant)
+                })
+                .map(move |(grant, expires_at)| ExpiringGrant {
+                    agent_id: agent_id.clone(),
+                    grant: grant.clone(),
+                    expires_at: *expires_at,
+                    expired: *expires_at <= now,
+                })
+        })
+        .collect();
+    grants.sort_by(|a, b| {
+        (a.expires_at, &a.agent_id, &a.grant).cmp(&(b.expires_at, &b.agent_id, &b.grant))
+    });
+    Expiring { tokens, grants }
+}
+
+/// A token or grant entering the warning window or expiring
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExpiryEvent {
+    /// `expiring` or `expired`
+    pub event: &'static str,
+    /// `token` or `grant`
+    pub kind: &'static str,
+    pub agent_id: String,
+    /// The `allowed_mcp_ids` entry of a grant
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grant: Option<String>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Which events were sent, so each is sent once per expiry. An expiry that
+/// is moved, e.g. by renewing a token, is notified anew.
+#[derive(Debug, Default)]
+pub struct ExpiryNotifier {
+    /// Where events are posted as JSON, if anywhere
+    webhook: Option<String>,
+    client: reqwest::Client,
+    /// Last event sent by token or grant
+    sent: Mutex<HashMap<(String, Option<String>), ExpiryEvent>>,
+}
+
+impl ExpiryNotifier {
+    pub fn new(webhook: Option<String>) -> Self {
+        Self {
+            webhook,
+            ..Self::default()
+        }
+    }
+
+    /// The events for `expiring` not sent yet. Tokens and grants no longer
+    /// expiring are forgotten.
+    fn pending(&self, expiring: &Expiring) -> Vec<ExpiryEvent> {
+        let current = expiring
+            .tokens
+            .iter()
+            .map(|token| ExpiryEvent {
+                event: if token.expired { "expired" } else { "expiring" },
+                kind: "token",
+                agent_id: token.agent_id.clone(),
+                grant: None,
+                expires_at: token.expires_at,
+            })
+            .chain(expiring.grants.iter().map(|grant| ExpiryEvent {
+                event: if grant.expired { "expired" } else { "expiring" },
+                kind: "grant",
+                agent_id: grant.agent_id.clone(),
+                grant: Some(grant.grant.clone()),
+                expires_at: grant.expires_at,
+            }));
+        let mut sent = self.sent.lock().unwrap();
+        let mut pending = Vec::new();
+        let mut seen = HashMap::new();
+        for event in current {
+            let key = (event.agent_id.clone(), event.grant.clone());
+            if sent.get(&key) != Some(&event) {
+                pending.push(event.clone());
+            }
+            seen.insert(key, event);
+        }
+        *sent = seen;
+        pending
+    }
+
+    /// Log the events and post each to the webhook
+    async fn send(&self, events: &[ExpiryEvent]) {
+        for event in events {
+            let what = match &event.grant {
+                Some(grant) => format!("Grant '{}' of agent '{}'", grant, event.agent_id),
+                None => format!("Token of agent '{}'", event.agent_id),
+            };
+            if event.event == "expired" {
+                warn!("{} expired at {}", what, event.expires_at);
+            } else {
+                warn!("{} expires at {}", what, event.expires_at);
+            }
+            let Some(url) = &self.webhook else {
+                continue;
+            };
+            let sent = self
+                .client
+                .post(url)
+                .timeout(WEBHOOK_TIMEOUT)
+                .json(event)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+            if let Err(e) = sent {
+                warn!("Failed to post an expiry event to {}: {}", url, e);
+            }
+        }
+    }
+}
+
+/// Notify tokens and grants entering the warning window or expiring every
+/// [`CHECK_INTERVAL`]. Runs until the server stops.
+pub async fn watch(service: Arc<ConfigService>) {
+    let mut ticks = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        ticks.tick().await;
+        service.check_expiry().await;
+    }
+}
+
+/// Write a summary of the expiring tokens and grants to the audit log every
+/// [`SUMMARY_INTERVAL`]. Runs until the server stops.
+pub async fn summarize(service: Arc<ConfigService>) {
+    let mut ticks = tokio::time::interval(SUMMARY_INTERVAL);
+    loop {
+        ticks.tick().await;
+        service.audit_expiring().await;
+    }
+}
+
+impl ConfigService {
+    /// Notify what entered the warning window or expired since the last
+    /// check. Returns the events.
+    pub async fn check_expiry(&self) -> Vec<ExpiryEvent> {
+        let expiring = self.expiring().await;
+        let notifier = self.expiry_notifier();
+        let events = notifier.pending(&expiring);
+        notifier.send(&events).await;
+        events
+    }
+}
@@ -0,0 +1,174 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// Response header marking forwarded requests affected by an injected fault
+pub const FAULT_HEADER: &str = "x-mception-fault-injected";
+
+/// Default fault injection window when none is given
+pub const DEFAULT_WINDOW: Duration = Duration::minutes(10);
+
+/// Longest fault injection window that can be requested
+pub const MAX_WINDOW: Duration = Duration::hours(1);
+
+/// Largest latency that can be injected
+pub const MAX_LATENCY_MS: u64 = 60_000;
+
+/// Faults to inject into requests forwarded to a leaf MCP
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FaultSpec {
+    /// Delay added before each request is forwarded
+    #[serde(default)]
+    pub latency_ms: u64,
+    /// Fraction of requests, between 0 and 1, failed without being forwarded
+    #[serde(default)]
+    pub error_rate: f64,
+}
+
+impl FaultSpec {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.latency_ms > MAX_LATENCY_MS {
+            return Err(format!("latency_ms must be at most {}", MAX_LATENCY_MS));
+        }
+        if !(0.0..=1.0).contains(&self.error_rate) {
+            return Err("error_rate must be between 0 and 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Fault injection active for a leaf MCP
+#[derive(Debug, Clone, Serialize)]
+pub struct FaultState {
+    pub session_id: String,
+    #[serde(flatten)]
+    pub spec: FaultSpec,
+    pub enabled_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Requests delayed by injected latency
+    pub delayed_requests: u64,
+    /// Requests failed by an injected error
+    pub failed_requests: u64,
+}
+
+/// The fault applied to one forwarded request
+#[derive(Debug, Clone, Copy)]
+pub struct InjectedFault {
+    pub latency: std::time::Duration,
+    pub fail: bool,
+}
+
+impl InjectedFault {
+    /// Value of [`FAULT_HEADER`] for the affected response
+    pub fn header_value(&self) -> &'static str {
+        match (self.fail, self.latency.is_zero()) {
+            (true, true) => "error",
+            (true, false) => "latency,error",
+            (false, _) => "latency",
+        }
+    }
+}
+
+/// In-memory fault injection per leaf MCP. Faults are never persisted and are
+/// gone after a restart.
+#[derive(Debug, Default)]
+pub struct FaultInjections {
+    faults: RwLock<HashMap<String, FaultState>>,
+}
+
+impl FaultInjections {
+    /// Start injecting faults into a leaf MCP, replacing any previous injection
+    pub async fn inject(&self, leaf_id: &str, spec: FaultSpec, window: Duration) -> FaultState {
+        let now = Utc::now();
+        let state = FaultState {
+            session_id: Uuid::new_v4().to_string(),
+            spec,
+            enabled_at: now,
+            expires_at: now + window,
+            delayed_requests: 0,
+            failed_requests: 0,
+        };
+        self.faults
+            .write()
+            .await
+            .insert(leaf_id.to_string(), state.clone());
+        state
+    }
+
+    /// Stop injecting faults into a leaf MCP. Returns whether an injection was active.
+    pub async fn clear(&self, leaf_id: &str) -> bool {
+        self.faults
+            .write()
+            .await
+            .remove(leaf_id)
+            .is_some_and(|state| Utc::now() < state.expires_at)
+    }
+
+    /// End an injection once its window has passed. Returns false if it was
+    /// replaced or cleared in the meantime.
+    pub async fn expire(&self, leaf_id: &str, session_id: &str) -> bool {
+        let mut faults = self.faults.write().await;
+        if faults
+            .get(leaf_id)
+            .is_some_and(|state| state.session_id == session_id)
+        {
+            faults.remove(leaf_id);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Active injection for a leaf MCP, if any
+    pub async fn get(&self, leaf_id: &str) -> Option<FaultState> {
+        self.faults
+            .read()
+            .await
+            .get(leaf_id)
+            .filter(|state| Utc::now() < state.expires_at)
+            .cloned()
+    }
+
+    /// All active injections
+    pub async fn list(&self) -> HashMap<String, FaultState> {
+        let now = Utc::now();
+        self.faults
+            .read()
+            .await
+            .iter()
+            .filter(|(_, state)| now < state.expires_at)
+            .map(|(leaf_id, state)| (leaf_id.clone(), state.clone()))
+            .collect()
+    }
+
+    /// Decide the fault for a request about to be forwarded to a leaf MCP
+    pub async fn next(&self, leaf_id: &str) -> Option<InjectedFault> {
+        // Cheap check first so forwarding isn't serialized on the write lock
+        self.get(leaf_id).await?;
+
+        let mut faults = self.faults.write().await;
+        let state = faults
+            .get_mut(leaf_id)
+            .filter(|state| Utc::now() < state.expires_at)?;
+        let fault = InjectedFault {
+            latency: std::time::Duration::from_millis(state.spec.latency_ms),
+            fail: random_fraction() < state.spec.error_rate,
+        };
+        if !fault.latency.is_zero() {
+            state.delayed_requests += 1;
+        }
+        if fault.fail {
+            state.failed_requests += 1;
+        }
+        (fault.fail || !fault.latency.is_zero()).then_some(fault)
+    }
+}
+
+/// Uniformly distributed number in `[0, 1)`, from the 62 random bits in the
+/// low half of a v4 UUID (the top two are the variant)
+fn random_fraction() -> f64 {
+    let bits = Uuid::new_v4().as_u64_pair().1 & ((1 << 62) - 1);
+    (bits >> 9) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,286 @@
+use crate::core::{MceptionError, NetworkError, StorageError, ValidationError};
+use crate::services::{ConfigService, auth, authorization};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Json, Response};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// What went wrong with a forwarded call, so agents can tell a down MCP from
+/// a refused or malformed request and retry accordingly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForwardingErrorCode {
+    /// The MCP couldn't be reached or closed before answering
+    LeafUnreachable,
+    /// The MCP didn't answer within the deadline
+    LeafTimeout,
+    /// The MCP doesn't exist or the calling agent may not use it
+    NotAllowed,
+    /// The MCP or its configuration failed otherwise
+    LeafError,
+    /// The server doesn't take the call right now, e.g. while shutting down or
+    /// with too many calls waiting
+    ProxyOverloaded,
+    /// The request itself is malformed and fails the same way when retried
+    InvalidRequest,
+    /// The MCP's circuit breaker is open after repeated failures, so the call
+    /// was refused without trying
+    CircuitOpen,
+}
+
+impl ForwardingErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ForwardingErrorCode::LeafUnreachable => "leaf_unreachable",
+            ForwardingErrorCode::LeafTimeout => "leaf_timeout",
+            ForwardingErrorCode::NotAllowed => "not_allowed",
+            ForwardingErrorCode::LeafError => "leaf_error",
+            ForwardingErrorCode::ProxyOverloaded => "proxy_overloaded",
+            ForwardingErrorCode::InvalidRequest => "invalid_request",
+            ForwardingErrorCode::CircuitOpen => "circuit_open",
+        }
+    }
+
+    /// HTTP status answered for the code, unless a caller picks another
+    pub fn status(self) -> StatusCode {
+        match self {
+            ForwardingErrorCode::LeafUnreachable => StatusCode::BAD_GATEWAY,
+            ForwardingErrorCode::LeafTimeout => StatusCode::GATEWAY_TIMEOUT,
+            ForwardingErrorCode::NotAllowed => StatusCode::FORBIDDEN,
+            ForwardingErrorCode::LeafError => StatusCode::INTERNAL_SERVER_ERROR,
+            ForwardingErrorCode::ProxyOverloaded => StatusCode::SERVICE_UNAVAILABLE,
+            ForwardingErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+            ForwardingErrorCode::CircuitOpen => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    /// Whether the same call may succeed later, unless a caller knows better
+    pub fn retryable(self) -> bool {
+        matches!(
+            self,
+            ForwardingErrorCode::LeafUnreachable
+                | ForwardingErrorCode::LeafTimeout
+                | ForwardingErrorCode::ProxyOverloaded
+                | ForwardingErrorCode::CircuitOpen
+        )
+    }
+
+    /// Code of the JSON-RPC error reporting this failure to an MCP client,
+    /// from the range JSON-RPC reserves for servers
+    pub fn json_rpc_code(self) -> i64 {
+        match self {
+            ForwardingErrorCode::LeafUnreachable => -32010,
+            ForwardingErrorCode::LeafTimeout => -32011,
+            ForwardingErrorCode::NotAllowed => -32012,
+            ForwardingErrorCode::LeafError => -32013,
+            ForwardingErrorCode::ProxyOverloaded => -32014,
+            ForwardingErrorCode::InvalidRequest => -32600,
+            ForwardingErrorCode::CircuitOpen => -32015,
+        }
+    }
+}
+
+/// A failed forwarded call, answered as `{"error": {"code", "retryable",
+/// "leaf_mcp_id", "detail"}}` next to any `context`
+#[derive(Debug, Clone)]
+pub struct ForwardingError {
+    pub status: StatusCode,
+    pub code: ForwardingErrorCode,
+    pub retryable: bool,
+    /// The leaf MCP or agent the call was forwarded to
+    pub leaf_mcp_id: String,
+    pub detail: String,
+    /// Further fields of the body, e.g. the `upstream` URL
+    pub context: Map<String, Value>,
+    /// Id of the JSON-RPC request refused, answered with a JSON-RPC error
+    pub json_rpc_id: Option<Value>,
+}
+
+impl ForwardingError {
+    pub fn new(code: ForwardingErrorCode, leaf_mcp_id: &str, detail: impl Into<String>) -> Self {
+        Self {
+            status: code.status(),
+            code,
+            retryable: code.retryable(),
+            leaf_mcp_id: leaf_mcp_id.to_string(),
+            detail: detail.into(),
+            context: Map::new(),
+            json_rpc_id: None,
+        }
+    }
+
+    /// Classify an error of the transport or the configuration
+    pub fn from_error(error: MceptionError, leaf_mcp_id: &str) -> Self {
+        let (code, status) = match &error {
+            MceptionError::Network(NetworkError::Timeout(_)) => {
+                (ForwardingErrorCode::LeafTimeout, None)
+            }
+            MceptionError::Network(NetworkError::ConnectionFailed(_)) => {
+                (ForwardingErrorCode::LeafUnreachable, None)
+            }
+            MceptionError::Network(NetworkError::Overloaded(_)) => {
+                (ForwardingErrorCode::ProxyOverloaded, None)
+            }
+            // Misconfigured rather than down, retrying doesn't help
+            MceptionError::Network(NetworkError::InvalidUrl(_)) => (
+                ForwardingErrorCode::LeafError,
+                Some(StatusCode::BAD_GATEWAY),
+            ),
+            MceptionError::Storage(StorageError::NotFound(_)) => {
+                (ForwardingErrorCode::NotAllowed, Some(StatusCode::NOT_FOUND))
+            }
+            MceptionError::Validation(ValidationError::InvalidFormat(_)) => {
+                (ForwardingErrorCode::InvalidRequest, None)
+            }
+            _ => (ForwardingErrorCode::LeafError, None),
+        };
+        let detail = match error {
+            MceptionError::Network(
+                NetworkError::Timeout(detail)
+                | NetworkError::ConnectionFailed(detail)
+                | NetworkError::InvalidUrl(detail)
+                | NetworkError::Overloaded(detail),
+            ) => detail,
+            e => e.to_string(),
+        };
+        let forwarding_error = Self::new(code, leaf_mcp_id, detail);
+        match status {
+            Some(status) => forwarding_error.with_status(status),
+            None => forwarding_error,
+        }
+    }
+
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Answer with the JSON-RPC error response to request `id`
+    pub fn for_json_rpc(mut self, id: Value) -> Self {
+        self.json_rpc_id = Some(id);
+        self
+    }
+
+    pub fn with(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.context.insert(key.to_string(), value.into());
+        self
+    }
+
+    pub fn body(&self) -> Value {
+        let mut body = self.context.clone();
+        body.insert(
+            "error".to_string(),
+            serde_json::json!({
+                "code": self.code,
+                "retryable": self.retryable,
+                "leaf_mcp_id": self.leaf_mcp_id,
+                "detail": self.detail,
+            }),
+        );
+        Value::Object(body)
+    }
+
+    /// The failure as the JSON-RPC error response to request `id`
+    pub fn json_rpc(&self, id: Value) -> Value {
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {
+                "code": self.code.json_rpc_code(),
+                "message": self.detail,
+                "data": self.body()["error"],
+            }
+        })
+    }
+}
+
+impl IntoResponse for ForwardingError {
+    fn into_response(self) -> Response {
+        let body = match &self.json_rpc_id {
+            Some(id) => self.json_rpc(id.clone()),
+            None => self.body(),
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+/// Refuse a forwarded call to `mcp_id` if its caller may not use it. The
+/// caller is the agent whose token is presented, see
+/// [`auth::authenticated_agent`], or else the one named by
+/// [`authorization::AGENT_HEADER`]. An agent token is consumed here and not
+/// passed on to the MCP. A `tools/call` in `body` of a tool the caller may
+/// not call is refused with a JSON-RPC error. Returns the caller, if known.
+pub async fn check_caller(
+    service: &ConfigService,
+    headers: &mut HeaderMap,
+    mcp_id: &str,
+    body: &[u8],
+) -> Result<Option<String>, ForwardingError> {
+    let unauthorized = |detail: String| {
+        ForwardingError::new(ForwardingErrorCode::NotAllowed, mcp_id, detail)
+            .with_status(StatusCode::UNAUTHORIZED)
+    };
+    let config = service.get_configuration().await;
+    let authenticated = auth::authenticated_agent(&config, headers)
+        .map_err(unauthorized)?
+        .map(str::to_string);
+    drop(config);
+    let agent_id = match (&authenticated, authorization::caller(headers)) {
+        (Some(authenticated), Some(named)) if authenticated != named => {
+            return Err(unauthorized(format!(
+                "The token presented is not the one of agent '{}'",
+                named
+            )));
+        }
+        (Some(authenticated), _) => authenticated.clone(),
+        (None, Some(named)) => named.to_string(),
+        (None, None) => return Ok(None),
+    };
+    if authenticated.is_some() {
+        headers.remove(header::AUTHORIZATION);
+    }
+    let detail = match service.explain_access(&agent_id, mcp_id, None).await {
+        Ok(decision) if decision.allowed => None,
+        Ok(_) => Some(format!("Agent '{}' may not use '{}'", agent_id, mcp_id)),
+        Err(e) => Some(e.to_string()),
+    };
+    if let Some(detail) = detail {
+        return Err(ForwardingError::new(
+            ForwardingErrorCode::NotAllowed,
+            mcp_id,
+            detail,
+        ));
+    }
+    for (id, tool) in tool_calls(body) {
+        let decision = service.explain_access(&agent_id, mcp_id, Some(&tool)).await;
+        if !decision.is_ok_and(|decision| decision.allowed) {
+            let detail = format!(
+                "Agent '{}' may not call tool '{}' of '{}'",
+                agent_id, tool, mcp_id
+            );
+            return Err(
+                ForwardingError::new(ForwardingErrorCode::NotAllowed, mcp_id, detail)
+                    .with("tool", tool)
+                    .for_json_rpc(id),
+            );
+        }
+    }
+    Ok(Some(agent_id))
+}
+
+/// Id and tool name of each `tools/call` request in a JSON-RPC message or batch
+fn tool_calls(body: &[u8]) -> Vec<(Value, String)> {
+    let messages = match serde_json::from_slice(body) {
+        Ok(Value::Array(messages)) => messages,
+        Ok(message) => vec![message],
+        Err(_) => return Vec::new(),
+    };
+    messages
+        .into_iter()
+        .filter(|message| message["method"] == "tools/call")
+        .filter_map(|message| {
+            let tool = message["params"]["name"].as_str()?.to_string();
+            Some((message["id"].clone(), tool))
+        })
+        .collect()
+}
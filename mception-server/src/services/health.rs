@@ -0,0 +1,326 @@
+use crate::core::{LeafMcpConfig, MceptionError, McpTransport, NetworkError, error_body};
+use crate::services::ConfigService;
+use crate::services::stdio;
+use axum::extract::{Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Json, Response};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tokio::task::JoinSet;
+use tracing::warn;
+
+/// How long a leaf MCP may take to answer a deep health check's probe
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest probe timeout a deep health check may ask for
+pub const MAX_PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeStatus {
+    /// Answered the probe, even with a JSON-RPC error
+    Healthy,
+    /// Couldn't be started or connected to
+    Unreachable,
+    /// Didn't answer within the probe timeout
+    Timeout,
+}
+
+/// Result of probing a leaf MCP
+#[derive(Debug, Clone, Serialize)]
+pub struct McpHealth {
+    pub id: String,
+    /// `builtin`, `stdio` or `https`
+    pub transport: &'static str,
+    pub status: ProbeStatus,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Faults are injected into forwards to it, which probes bypass
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub faults_injected: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProbeCounts {
+    pub healthy: usize,
+    pub unreachable: usize,
+    pub timeout: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentCounts {
+    pub total: usize,
+    pub connected: usize,
+}
+
+/// Answer of `GET /admin/health/deep`
+#[derive(Debug, Clone, Serialize)]
+pub struct DeepHealth {
+    /// `healthy` if every leaf MCP answered, `degraded` otherwise
+    pub status: &'static str,
+    pub checked_at: DateTime<Utc>,
+    pub probe_timeout_ms: u64,
+    pub counts: ProbeCounts,
+    /// By id
+    pub leaf_mcps: Vec<McpHealth>,
+    pub agents: AgentCounts,
+}
+
+/// Probe every leaf MCP at once, each within `timeout`, so a hung one only
+/// delays the report by `timeout`
+pub async fn deep(service: Arc<ConfigService>, timeout: Duration) -> DeepHealth {
+    let config = service.get_configuration().await;
+    let mut probes = JoinSet::new();
+    for (id, leaf) in config.leaf_mcps.clone() {
+        let service = service.clone();
+        probes.spawn(async move {
+            let mut health = probe(&service, &id, &leaf, timeout).await;
+            health.faults_injected = matches!(service.faults(&id).await, Ok(Some(_)));
+            health
+        });
+    }
+
+    let mut leaf_mcps = Vec::with_capacity(config.leaf_mcps.len());
+    while let Some(probed) = probes.join_next().await {
+        if let Ok(health) = probed {
+            leaf_mcps.push(health);
+        }
+    }
+    leaf_mcps.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let mut counts = ProbeCounts::default();
+    for health in &leaf_mcps {
+        match health.status {
+            ProbeStatus::Healthy => counts.healthy += 1,
+            ProbeStatus::Unreachable => counts.unreachable += 1,
+            ProbeStatus::Timeout => counts.timeout += 1,
+        }
+    }
+    DeepHealth {
+        status: if counts.healthy == leaf_mcps.len() {
+            "healthy"
+        } else {
+            "degraded"
+        },
+        checked_at: Utc::now(),
+        probe_timeout_ms: timeout.as_millis() as u64,
+        counts,
+        leaf_mcps,
+        agents: AgentCounts {
+            total: config.agents.len(),
+            connected: config
+                .agents
+                .values()
+                .filter(|agent| agent.is_connected)
+                .count(),
+        },
+    }
+}
+
+/// Ask a leaf MCP whether it's there: `ping` a stdio process, spawning it if
+/// needed, or an SSE leaf MCP, connecting if needed, and send `initialize` to
+/// an HTTPS endpoint
+pub async fn probe(
+    service: &ConfigService,
+    id: &str,
+    leaf: &LeafMcpConfig,
+    timeout: Duration,
+) -> McpHealth {
+    let started = Instant::now();
+    let (transport, outcome) = match &leaf.transport {
+        McpTransport::Builtin { .. } => ("builtin", Ok(())),
+        McpTransport::Stdio { .. } => {
+            let ping = json!({ "jsonrpc": "2.0", "id": "health", "method": "ping" });
+            let forwarded = service.stdio_processes().forward(id, leaf, &ping);
+            ("stdio", within(timeout, forwarded).await)
+        }
+        McpTransport::Sse { .. } => {
+            let ping = json!({ "jsonrpc": "2.0", "id": "health", "method": "ping" });
+            let forwarded = service.sse_connections().forward(id, leaf, &ping);
+            ("sse", within(timeout, forwarded).await)
+        }
+        McpTransport::Https { url, headers } => {
+            let initialize = json!({
+                "jsonrpc": "2.0",
+                "id": "health",
+                "method": "initialize",
+                "params": {
+                    "protocolVersion": stdio::PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": { "name": "mception-server", "version": env!("CARGO_PKG_VERSION") }
+                }
+            });
+            let mut session = None;
+            let called = service.https_forwarder().call(
+                url,
+                headers.as_ref(),
+                &mut session,
+                &initialize,
+                timeout,
+            );
+            ("https", within(timeout, called).await)
+        }
+    };
+
+    let (status, error) = match outcome {
+        Ok(()) => (ProbeStatus::Healthy, None),
+        Err(MceptionError::Network(NetworkError::Timeout(e))) => (ProbeStatus::Timeout, Some(e)),
+        Err(e) => (ProbeStatus::Unreachable, Some(e.to_string())),
+    };
+    McpHealth {
+        id: id.to_string(),
+        transport,
+        status,
+        latency_ms: started.elapsed().as_millis() as u64,
+        error,
+        faults_injected: false,
+    }
+}
+
+/// Whether `answer` completes within `timeout`, whatever it answers
+async fn within<T>(
+    timeout: Duration,
+    answer: impl Future<Output = Result<T, MceptionError>>,
+) -> Result<(), MceptionError> {
+    match tokio::time::timeout(timeout, answer).await {
+        Ok(answered) => answered.map(|_| ()),
+        Err(_) => Err(NetworkError::Timeout(format!(
+            "No answer within {} ms",
+            timeout.as_millis()
+        ))
+        .into()),
+    }
+}
+
+/// How often leaf MCPs are probed in the background, unless set otherwise
+/// with `--health-interval-secs`
+pub const DEFAULT_HEALTH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Outcome of the last background probe of a leaf MCP
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthStatus {
+    pub last_probe_at: DateTime<Utc>,
+    pub healthy: bool,
+    pub last_error: Option<String>,
+}
+
+/// Health of leaf MCPs as of their last background probe, see [`watch`]
+#[derive(Debug, Default)]
+pub struct HealthStatuses {
+    statuses: RwLock<HashMap<String, HealthStatus>>,
+}
+
+impl HealthStatuses {
+    pub async fn record(&self, health: &McpHealth, probed_at: DateTime<Utc>) {
+        self.statuses.write().await.insert(
+            health.id.clone(),
+            HealthStatus {
+                last_probe_at: probed_at,
+                healthy: health.status == ProbeStatus::Healthy,
+                last_error: health.error.clone(),
+            },
+        );
+    }
+
+    /// Drop the status of a deleted leaf MCP
+    pub async fn forget(&self, leaf_id: &str) {
+        self.statuses.write().await.remove(leaf_id);
+    }
+
+    pub async fn get(&self, leaf_id: &str) -> Option<HealthStatus> {
+        self.statuses.read().await.get(leaf_id).cloned()
+    }
+
+    /// By id, leaving out leaf MCPs not probed yet
+    pub async fn all(&self) -> BTreeMap<String, HealthStatus> {
+        self.statuses
+            .read()
+            .await
+            .iter()
+            .map(|(id, status)| (id.clone(), status.clone()))
+            .collect()
+    }
+}
+
+/// Probe every leaf MCP every `interval`, recording the outcomes in the
+/// service's [`HealthStatuses`]. Runs until the server stops.
+pub async fn watch(service: Arc<ConfigService>, interval: Duration) {
+    let mut ticks = tokio::time::interval(interval);
+    // A round of probes taking longer than the interval delays the next one
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        let report = deep(service.clone(), DEFAULT_PROBE_TIMEOUT.min(interval)).await;
+        for health in &report.leaf_mcps {
+            let previous = service.health_statuses().get(&health.id).await;
+            let healthy = health.status == ProbeStatus::Healthy;
+            if !healthy && previous.is_none_or(|previous| previous.healthy) {
+                warn!(
+                    "Leaf MCP '{}' failed its health probe: {}",
+                    health.id,
+                    health.error.as_deref().unwrap_or_default()
+                );
+            }
+            service
+                .health_statuses()
+                .record(health, report.checked_at)
+                .await;
+        }
+    }
+}
+
+/// Seconds a client should wait before retrying a request refused while the
+/// configuration loads
+pub const LOADING_RETRY_AFTER_SECS: u64 = 1;
+
+/// Middleware refusing requests with 503 and `Retry-After` while the
+/// configuration is still loading, so none sees it partially loaded
+pub async fn reject_while_loading(
+    State(service): State<Arc<ConfigService>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !service.lifecycle().is_loading() {
+        return next.run(request).await;
+    }
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(header::RETRY_AFTER, LOADING_RETRY_AFTER_SECS.to_string())],
+        Json(error_body(
+            "loading",
+            "The server is still loading its configuration",
+        )),
+    )
+        .into_response()
+}
+
+/// Answer of the unauthenticated `GET /health` and `GET /ready`. The status
+/// is `degraded` while the audit storage fails, which the audit policy's
+/// `on_failure` decides the consequences of.
+pub fn liveness(service: &ConfigService) -> Value {
+    let lifecycle = service.lifecycle();
+    let started_at = lifecycle.started_at();
+    let audit_degraded = service.audit_buffer().is_degraded();
+    json!({
+        "status": if lifecycle.is_draining() {
+            "draining"
+        } else if lifecycle.is_loading() {
+            "loading"
+        } else if audit_degraded {
+            "degraded"
+        } else {
+            "ok"
+        },
+        "audit": if audit_degraded { "degraded" } else { "ok" },
+        "version": env!("CARGO_PKG_VERSION"),
+        "started_at": started_at,
+        "uptime_seconds": (Utc::now() - started_at).num_seconds(),
+    })
+}
@@ -0,0 +1,329 @@
+use crate::core::{
+    AgentConfig, AuditAction, AuditLogEntry, AuditTarget, BUNDLE_PREFIX, BundleConfig, GrantSource,
+    LeafMcpConfig, ServerConfig, ToolFilter, merge,
+};
+use crate::services::revision;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+/// An audit entry [`replay`] couldn't apply
+#[derive(Debug, Clone, Serialize)]
+pub struct UnappliedEntry {
+    pub id: String,
+    pub sequence: u64,
+    pub timestamp: DateTime<Utc>,
+    pub action: AuditAction,
+    pub target: AuditTarget,
+    pub reason: String,
+}
+
+/// A configuration reconstructed from an audit log alone
+#[derive(Debug, Clone, Serialize)]
+pub struct Replay {
+    pub until: Option<DateTime<Utc>>,
+    pub replayed_entries: usize,
+    pub last_entry_id: Option<String>,
+    /// Oldest first
+    pub unapplied: Vec<UnappliedEntry>,
+    #[serde(skip)]
+    pub config: ServerConfig,
+}
+
+/// Replay `entries` on an empty configuration, up to and including `until`.
+/// Backup restores and imports replace the configuration with one the audit
+/// log doesn't hold, so they are reported as unapplied like any entry that
+/// fails to apply.
+pub fn replay(entries: &[AuditLogEntry], until: Option<DateTime<Utc>>) -> Replay {
+    let mut replay = Replay {
+        until,
+        replayed_entries: 0,
+        last_entry_id: None,
+        unapplied: Vec::new(),
+        config: ServerConfig::default(),
+    };
+    if let Some(first) = entries.first() {
+        replay.config.metadata.created_at = first.timestamp;
+        replay.config.metadata.last_modified = first.timestamp;
+    }
+    let replayed = entries
+        .iter()
+        .filter(|entry| until.is_none_or(|until| entry.timestamp <= until));
+    for entry in replayed {
+        let applied = match (&entry.action, &entry.target) {
+            (AuditAction::Update, AuditTarget::Server) => {
+                Err("replacing the configuration needs the backup or import it names".to_string())
+            }
+            _ => apply_entry(&mut replay.config, entry),
+        };
+        match applied {
+            Ok(true) => {
+                replay.replayed_entries += 1;
+                replay.last_entry_id = Some(entry.id.clone());
+                replay.config.metadata.last_modified = entry.timestamp;
+                replay.config.metadata.revision += 1;
+            }
+            Ok(false) => {}
+            Err(reason) => replay.unapplied.push(UnappliedEntry {
+                id: entry.id.clone(),
+                sequence: entry.sequence,
+                timestamp: entry.timestamp,
+                action: entry.action.clone(),
+                target: entry.target.clone(),
+                reason,
+            }),
+        }
+    }
+    replay
+}
+
+/// Where `reconstructed` diverges from `live`, as a JSON Merge Patch turning
+/// `live` into `reconstructed`. Compares the configurations like the desired
+/// state hash does, so revisions and connection state don't count. `None`
+/// if they match.
+pub fn divergence(reconstructed: &ServerConfig, live: &ServerConfig) -> Option<Value> {
+    let canonical = |config| {
+        serde_json::from_str::<Value>(&revision::canonical_json(config)).unwrap_or_default()
+    };
+    let patch = merge::diff(&canonical(live), &canonical(reconstructed));
+    match &patch {
+        Value::Object(fields) if fields.is_empty() => None,
+        _ => Some(patch),
+    }
+}
+
+/// The `section.key` paths a [`divergence`] patch touches, e.g. `leaf_mcps.search`
+pub fn divergent_paths(patch: &Value) -> Vec<String> {
+    let Some(sections) = patch.as_object() else {
+        return Vec::new();
+    };
+    sections
+        .iter()
+        .flat_map(|(section, change)| match change.as_object() {
+            Some(keys) if !keys.is_empty() => keys
+                .keys()
+                .map(|key| format!("{}.{}", section, key))
+                .collect(),
+            _ => vec![section.clone()],
+        })
+        .collect()
+}
+
+/// Replay a single audit entry on top of a configuration.
+///
+/// Returns `Ok(true)` if the entry changed the configuration, `Ok(false)` for
+/// entries without effect (e.g. reads) and an error describing why the entry
+/// can't be replayed otherwise. Server-level entries (like backup restores)
+/// are not handled here since they need access to storage.
+pub fn apply_entry(config: &mut ServerConfig, entry: &AuditLogEntry) -> Result<bool, String> {
+    match (&entry.action, &entry.target) {
+        // Reads and operational actions don't change the configuration's content
+        (
+            AuditAction::Read
+            | AuditAction::Migrate
+            | AuditAction::EnableDebugCapture
+            | AuditAction::DisableDebugCapture
+            | AuditAction::InjectFaults
+            | AuditAction::ClearFaults
+            | AuditAction::SetLogLevel
+            | AuditAction::Discontinuity
+            | AuditAction::ConsistencyGap
+            | AuditAction::ConnectionChange
+            | AuditAction::DeprecatedUse
+            | AuditAction::IdempotentRetry
+            | AuditAction::Shutdown
+            | AuditAction::Confirmation
+            | AuditAction::RateLimited
+            | AuditAction::EntriesLost
+            | AuditAction::CircuitBreaker
+            | AuditAction::CallTool
+            | AuditAction::ExpirySummary
+            | AuditAction::AuditRepair,
+            _,
+        ) => Ok(false),
+
+        (AuditAction::Create, AuditTarget::LeafMcp { id }) => {
+            let mcp: LeafMcpConfig = serde_json::from_value(entry.details.clone())
+                .map_err(|e| format!("invalid leaf MCP details: {}", e))?;
+            config.leaf_mcps.insert(id.clone(), mcp);
+            Ok(true)
+        }
+        (AuditAction::Update, AuditTarget::LeafMcp { id }) => {
+            let mcp = config
+                .leaf_mcps
+                .get_mut(id)
+                .ok_or_else(|| format!("leaf MCP '{}' does not exist", id))?;
+            let previous = mcp.transport.clone();
+            *mcp = merge::apply_update(&*mcp, &entry.details, &[])
+                .map_err(|e| format!("invalid leaf MCP update: {}", e))?;
+            // Updates are audited with their secrets redacted
+            mcp.transport.restore_redacted(&previous);
+            if let Some(revision) = entry.revision {
+                mcp.revision = revision.to;
+            }
+            Ok(true)
+        }
+        (AuditAction::Delete, AuditTarget::LeafMcp { id }) => {
+            config
+                .leaf_mcps
+                .remove(id)
+                .ok_or_else(|| format!("leaf MCP '{}' does not exist", id))?;
+            for agent in config.agents.values_mut() {
+                agent.revoke(id);
+                agent.tool_filters.remove(id);
+            }
+            for bundle in config.bundles.values_mut() {
+                if bundle.members.contains(id) {
+                    bundle.members.retain(|member| member != id);
+                    bundle.version += 1;
+                }
+            }
+            Ok(true)
+        }
+
+        (AuditAction::Create, AuditTarget::Agent { id }) => {
+            let agent: AgentConfig = serde_json::from_value(entry.details.clone())
+                .map_err(|e| format!("invalid agent details: {}", e))?;
+            config.agents.insert(id.clone(), agent);
+            Ok(true)
+        }
+        (AuditAction::Update, AuditTarget::Agent { id }) => {
+            let agent = config
+                .agents
+                .get_mut(id)
+                .ok_or_else(|| format!("agent '{}' does not exist", id))?;
+            *agent = merge::apply_update(&*agent, &entry.details, &[])
+                .map_err(|e| format!("invalid agent update: {}", e))?;
+            agent.migrate_grant_sources();
+            if let Some(revision) = entry.revision {
+                agent.revision = revision.to;
+            }
+            Ok(true)
+        }
+        (AuditAction::Delete, AuditTarget::Agent { id }) => {
+            config
+                .agents
+                .remove(id)
+                .ok_or_else(|| format!("agent '{}' does not exist", id))?;
+            Ok(true)
+        }
+
+        (AuditAction::AddAllowedMcp, AuditTarget::AgentAllowedMcp { agent_id, mcp_id }) => {
+            let agent = config
+                .agents
+                .get_mut(agent_id)
+                .ok_or_else(|| format!("agent '{}' does not exist", agent_id))?;
+            agent.grant(mcp_id, grant_source(entry)?.unwrap_or(GrantSource::Direct));
+            if let Some(expires_at) = grant_expiry(entry)? {
+                agent.grant_expiry.insert(mcp_id.clone(), expires_at);
+            }
+            Ok(true)
+        }
+        // Grants of every leaf MCP matching a label selector
+        (AuditAction::AddAllowedMcp, AuditTarget::Agent { id }) => {
+            let mcp_ids: Vec<String> = serde_json::from_value(entry.details["mcp_ids"].clone())
+                .map_err(|e| format!("invalid granted MCP ids: {}", e))?;
+            let source = grant_source(entry)?.unwrap_or(GrantSource::Direct);
+            let agent = config
+                .agents
+                .get_mut(id)
+                .ok_or_else(|| format!("agent '{}' does not exist", id))?;
+            for mcp_id in &mcp_ids {
+                agent.grant(mcp_id, source.clone());
+            }
+            Ok(true)
+        }
+        (AuditAction::RemoveAllowedMcp, AuditTarget::AgentAllowedMcp { agent_id, mcp_id }) => {
+            let agent = config
+                .agents
+                .get_mut(agent_id)
+                .ok_or_else(|| format!("agent '{}' does not exist", agent_id))?;
+            // Entries written before grants had sources removed the grant
+            match grant_source(entry)? {
+                Some(source) => {
+                    agent.revoke_source(mcp_id, &source);
+                }
+                None => agent.revoke(mcp_id),
+            }
+            Ok(true)
+        }
+
+        (AuditAction::Update, AuditTarget::AgentToolFilter { agent_id, mcp_id }) => {
+            let filter: ToolFilter = serde_json::from_value(entry.details["filter"].clone())
+                .map_err(|e| format!("invalid tool filter: {}", e))?;
+            let agent = config
+                .agents
+                .get_mut(agent_id)
+                .ok_or_else(|| format!("agent '{}' does not exist", agent_id))?;
+            agent.tool_filters.insert(mcp_id.clone(), filter);
+            Ok(true)
+        }
+        (AuditAction::Delete, AuditTarget::AgentToolFilter { agent_id, mcp_id }) => {
+            let agent = config
+                .agents
+                .get_mut(agent_id)
+                .ok_or_else(|| format!("agent '{}' does not exist", agent_id))?;
+            agent.tool_filters.remove(mcp_id);
+            Ok(true)
+        }
+
+        (AuditAction::Create | AuditAction::Update, AuditTarget::Bundle { name }) => {
+            let bundle: BundleConfig = entry
+                .details
+                .get("bundle")
+                .cloned()
+                .ok_or_else(|| "bundle entry without bundle details".to_string())
+                .and_then(|bundle| {
+                    serde_json::from_value(bundle).map_err(|e| format!("invalid bundle: {}", e))
+                })?;
+            config.bundles.insert(name.clone(), bundle);
+            Ok(true)
+        }
+        (AuditAction::Update, AuditTarget::RegistrationPolicy) => {
+            config.registration_policy = serde_json::from_value(entry.details["policy"].clone())
+                .map_err(|e| format!("invalid registration policy: {}", e))?;
+            Ok(true)
+        }
+        (AuditAction::Update, AuditTarget::AuditPolicy) => {
+            config.audit = serde_json::from_value(entry.details["policy"].clone())
+                .map_err(|e| format!("invalid audit policy: {}", e))?;
+            Ok(true)
+        }
+        (AuditAction::Update, AuditTarget::ConfirmationPolicy) => {
+            config.confirmation = serde_json::from_value(entry.details["policy"].clone())
+                .map_err(|e| format!("invalid confirmation policy: {}", e))?;
+            Ok(true)
+        }
+        (AuditAction::Delete, AuditTarget::Bundle { name }) => {
+            config
+                .bundles
+                .remove(name)
+                .ok_or_else(|| format!("bundle '{}' does not exist", name))?;
+            let grant = format!("{}{}", BUNDLE_PREFIX, name);
+            for agent in config.agents.values_mut() {
+                agent.revoke(&grant);
+            }
+            Ok(true)
+        }
+
+        (action, target) => Err(format!("{:?} on {:?} can't be replayed", action, target)),
+    }
+}
+
+/// The grant source an allowed MCP entry names, if any
+fn grant_source(entry: &AuditLogEntry) -> Result<Option<GrantSource>, String> {
+    entry.details["source"].as_str().map(str::parse).transpose()
+}
+
+/// When a grant expires, `None` for grants that don't and ones written before
+/// grants could expire
+fn grant_expiry(entry: &AuditLogEntry) -> Result<Option<DateTime<Utc>>, String> {
+    entry.details["expires_at"]
+        .as_str()
+        .map(|at| {
+            DateTime::parse_from_rfc3339(at)
+                .map(|at| at.with_timezone(&Utc))
+                .map_err(|e| format!("invalid grant expiry: {}", e))
+        })
+        .transpose()
+}
@@ -0,0 +1,657 @@
+use crate::core::{HeaderPolicy, MceptionResult, NetworkError, interpolation, is_secret_key};
+use crate::services::deadline::DEADLINE_HEADER;
+use crate::services::stdio;
+use axum::body::Bytes;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header};
+use futures_util::Stream;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// Headers describing a single connection, which a proxy never passes on
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Session of a streamable HTTP MCP, assigned by the server on `initialize`
+pub(crate) const SESSION_HEADER: &str = "mcp-session-id";
+
+pub(crate) const PROTOCOL_VERSION_HEADER: &str = "mcp-protocol-version";
+
+/// A response to relay to the caller of the forwarding endpoint
+pub struct Forwarded {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl Forwarded {
+    /// A `200 OK` JSON response
+    pub fn json(value: &Value) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        Self {
+            status: StatusCode::OK,
+            headers,
+            body: Bytes::from(value.to_string()),
+        }
+    }
+
+    /// The body as JSON, if it is declared and parses as such
+    pub fn json_body(&self) -> Option<Value> {
+        let content_type = self.headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+        if !content_type.starts_with("application/json") {
+            return None;
+        }
+        serde_json::from_slice(&self.body).ok()
+    }
+
+    /// Replace the body with `value`
+    pub fn set_json_body(&mut self, value: &Value) {
+        self.body = Bytes::from(value.to_string());
+    }
+
+    /// The JSON-RPC response with `id` from an event stream body
+    fn event_stream_response(&self, id: &Value) -> Option<Value> {
+        event_stream_message(std::str::from_utf8(&self.body).ok()?, id)
+    }
+}
+
+/// The JSON-RPC message with `id` among the events of an event stream body
+pub(crate) fn event_stream_message(body: &str, id: &Value) -> Option<Value> {
+    body.split("\n\n")
+        .filter_map(|event| {
+            let data: Vec<&str> = event
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(str::trim_start)
+                .collect();
+            serde_json::from_str::<Value>(&data.join("\n")).ok()
+        })
+        .find(|message| message.get("id") == Some(id))
+}
+
+/// Proxies requests to HTTPS leaf MCPs, sharing connections between requests
+#[derive(Default)]
+pub struct HttpsForwarder {
+    client: reqwest::Client,
+}
+
+impl HttpsForwarder {
+    /// Send the incoming request to the leaf MCP at `url` and return its
+    /// response. `configured` headers replace incoming ones of the same name,
+    /// with `${NAME}` placeholders resolved from the environment, and the
+    /// remaining `budget_ms` is sent along as [`DEADLINE_HEADER`]. Errors
+    /// never contain the URL, as it may carry credentials.
+    pub async fn forward(
+        &self,
+        url: &str,
+        configured: Option<&BTreeMap<String, String>>,
+        method: Method,
+        incoming: &HeaderMap,
+        budget_ms: &str,
+        body: Bytes,
+    ) -> MceptionResult<Forwarded> {
+        let configured = resolved_headers(configured)?;
+        let headers = request_headers(incoming, configured.as_ref(), budget_ms)
+            .map_err(NetworkError::InvalidUrl)?;
+        let response = self
+            .client
+            .request(method, url)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        let status = response.status();
+        let headers = response_headers(response.headers());
+        let body = response.bytes().await.map_err(|e| {
+            NetworkError::ConnectionFailed(format!(
+                "Failed to read the response: {}",
+                error_chain(&e.without_url())
+            ))
+        })?;
+        Ok(Forwarded {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    /// Send a single JSON-RPC request of the server's own, e.g. `tools/list`,
+    /// and return the response from the JSON body or the event stream.
+    /// `session` is sent as `Mcp-Session-Id` and updated from the response.
+    pub async fn call(
+        &self,
+        url: &str,
+        configured: Option<&BTreeMap<String, String>>,
+        session: &mut Option<String>,
+        message: &Value,
+        timeout: Duration,
+    ) -> MceptionResult<Value> {
+        let headers = call_headers(configured, session.as_deref(), timeout)?;
+        let forwarded = tokio::time::timeout(
+            timeout,
+            self.forward(
+                url,
+                None,
+                Method::POST,
+                &headers,
+                &timeout.as_millis().to_string(),
+                Bytes::from(message.to_string()),
+            ),
+        )
+        .await
+        .map_err(|_| {
+            NetworkError::Timeout(format!("No response within {} ms", timeout.as_millis()))
+        })??;
+
+        if let Some(id) = forwarded
+            .headers
+            .get(SESSION_HEADER)
+            .and_then(|id| id.to_str().ok())
+        {
+            *session = Some(id.to_string());
+        }
+        if !forwarded.status.is_success() {
+            return Err(NetworkError::ConnectionFailed(format!(
+                "Leaf MCP answered with HTTP {}",
+                forwarded.status
+            ))
+            .into());
+        }
+        // Notifications are only acknowledged
+        let Some(id) = message.get("id") else {
+            return Ok(Value::Null);
+        };
+        forwarded
+            .json_body()
+            .or_else(|| forwarded.event_stream_response(id))
+            .ok_or_else(|| {
+                NetworkError::ConnectionFailed(
+                    "Leaf MCP answered without a JSON-RPC response".to_string(),
+                )
+                .into()
+            })
+    }
+
+    /// Send a single JSON-RPC request of the server's own like [`Self::call`],
+    /// but pass the `result` of a JSON response on as its bytes arrive,
+    /// without holding the response in memory. Everything before the
+    /// `result` is read first, to answer an `error` as a whole. Responses
+    /// sent as an event stream are read whole. `timeout` covers the whole
+    /// response, a result still arriving then ends with an error.
+    pub async fn call_streamed(
+        &self,
+        url: &str,
+        configured: Option<&BTreeMap<String, String>>,
+        session: Option<&str>,
+        message: &Value,
+        timeout: Duration,
+    ) -> MceptionResult<StreamedResponse> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let timed_out =
+            || NetworkError::Timeout(format!("No response within {} ms", timeout.as_millis()));
+        let headers = call_headers(configured, session, timeout)?;
+        let mut response = tokio::time::timeout_at(
+            deadline,
+            self.client
+                .post(url)
+                .headers(headers)
+                .body(message.to_string())
+                .send(),
+        )
+        .await
+        .map_err(|_| timed_out())?
+        .map_err(request_error)?;
+        if !response.status().is_success() {
+            return Err(NetworkError::ConnectionFailed(format!(
+                "Leaf MCP answered with HTTP {}",
+                response.status()
+            ))
+            .into());
+        }
+        let is_json = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/json"));
+
+        let mut scanner = ResultScanner::default();
+        let mut head = Vec::new();
+        loop {
+            let Some(chunk) = next_chunk(&mut response, deadline, timeout).await? else {
+                break;
+            };
+            let found = if is_json { scanner.find(&chunk) } else { None };
+            match found {
+                Some((ResultKey::Result, start)) => {
+                    let state = ResultStream {
+                        response,
+                        scanner,
+                        deadline,
+                        timeout,
+                        pending: Some(chunk.slice(start..)),
+                    };
+                    return Ok(StreamedResponse::Result(Box::pin(
+                        futures_util::stream::unfold(Some(state), ResultStream::next),
+                    )));
+                }
+                // Read whole, as is anything that isn't a JSON body
+                Some((ResultKey::Error, _)) | None => head.extend_from_slice(&chunk),
+            }
+        }
+
+        let id = message.get("id").unwrap_or(&Value::Null);
+        let response = if is_json {
+            serde_json::from_slice(&head).ok()
+        } else {
+            std::str::from_utf8(&head)
+                .ok()
+                .and_then(|body| event_stream_message(body, id))
+        };
+        response.map(StreamedResponse::Whole).ok_or_else(|| {
+            NetworkError::ConnectionFailed(
+                "Leaf MCP answered without a JSON-RPC response".to_string(),
+            )
+            .into()
+        })
+    }
+}
+
+/// The response to a request sent with [`HttpsForwarder::call_streamed`]
+pub enum StreamedResponse {
+    /// The bytes of the response's `result`, as the leaf MCP sends them
+    Result(Pin<Box<dyn Stream<Item = MceptionResult<Bytes>> + Send>>),
+    /// The whole JSON-RPC response, for one with an `error` or without a
+    /// `result`, or one that was sent as an event stream
+    Whole(Value),
+}
+
+/// Headers of a JSON-RPC request of the server's own within `session`
+fn call_headers(
+    configured: Option<&BTreeMap<String, String>>,
+    session: Option<&str>,
+    timeout: Duration,
+) -> MceptionResult<HeaderMap> {
+    let configured = resolved_headers(configured)?;
+    let mut headers = request_headers(
+        &HeaderMap::new(),
+        configured.as_ref(),
+        &timeout.as_millis().to_string(),
+    )
+    .map_err(NetworkError::InvalidUrl)?;
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    headers.insert(
+        header::ACCEPT,
+        HeaderValue::from_static("application/json, text/event-stream"),
+    );
+    headers.insert(
+        PROTOCOL_VERSION_HEADER,
+        HeaderValue::from_static(stdio::PROTOCOL_VERSION),
+    );
+    if let Some(session) = session {
+        headers.insert(
+            SESSION_HEADER,
+            HeaderValue::try_from(session).map_err(|e| {
+                NetworkError::ConnectionFailed(format!("Invalid session id: {}", e))
+            })?,
+        );
+    }
+    Ok(headers)
+}
+
+/// A failed request to a leaf MCP, without its URL
+fn request_error(e: reqwest::Error) -> NetworkError {
+    let e = e.without_url();
+    if e.is_builder() {
+        NetworkError::InvalidUrl(e.to_string())
+    } else if e.is_timeout() {
+        NetworkError::Timeout(e.to_string())
+    } else {
+        NetworkError::ConnectionFailed(error_chain(&e))
+    }
+}
+
+/// The next chunk of a leaf MCP's response body, `None` at its end
+async fn next_chunk(
+    response: &mut reqwest::Response,
+    deadline: tokio::time::Instant,
+    timeout: Duration,
+) -> MceptionResult<Option<Bytes>> {
+    tokio::time::timeout_at(deadline, response.chunk())
+        .await
+        .map_err(|_| {
+            NetworkError::Timeout(format!(
+                "Response not complete within {} ms",
+                timeout.as_millis()
+            ))
+        })?
+        .map_err(|e| {
+            NetworkError::ConnectionFailed(format!(
+                "Failed to read the response: {}",
+                error_chain(&e.without_url())
+            ))
+            .into()
+        })
+}
+
+/// The rest of a response whose `result` is passed on
+struct ResultStream {
+    response: reqwest::Response,
+    scanner: ResultScanner,
+    deadline: tokio::time::Instant,
+    timeout: Duration,
+    /// Part of the result read before, passed on first
+    pending: Option<Bytes>,
+}
+
+impl ResultStream {
+    async fn next(state: Option<Self>) -> Option<(MceptionResult<Bytes>, Option<Self>)> {
+        let mut state = state?;
+        let chunk = match state.pending.take() {
+            Some(chunk) => chunk,
+            None => match next_chunk(&mut state.response, state.deadline, state.timeout).await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => {
+                    let e = NetworkError::ConnectionFailed(
+                        "Leaf MCP's response ended within its result".to_string(),
+                    );
+                    return Some((Err(e.into()), None));
+                }
+                Err(e) => return Some((Err(e), None)),
+            },
+        };
+        match state.scanner.value_end(&chunk) {
+            // The rest of the response isn't needed
+            Some(end) => Some((Ok(chunk.slice(..end)), None)),
+            None => Some((Ok(chunk), Some(state))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResultKey {
+    Result,
+    Error,
+}
+
+/// Follows a JSON-RPC response's body as it arrives to find where its
+/// `result` starts and ends, without parsing it. Strings are only told
+/// apart from structure, keys with escapes aren't recognized.
+#[derive(Default)]
+struct ResultScanner {
+    /// Nesting of objects and arrays, 1 within the response object
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    /// Whether the next string in the response object is a key
+    expect_key: bool,
+    key: Vec<u8>,
+}
+
+impl ResultScanner {
+    /// Track `byte` within a string, returning whether the string went on
+    fn string_byte(&mut self, byte: u8) -> bool {
+        if self.escaped {
+            self.escaped = false;
+        } else if byte == b'\\' {
+            self.escaped = true;
+        } else if byte == b'"' {
+            self.in_string = false;
+            return false;
+        }
+        true
+    }
+
+    /// Scan `chunk` for the response's `result` or `error` key, returning
+    /// it and the offset just after its `:`, where the value starts
+    fn find(&mut self, chunk: &[u8]) -> Option<(ResultKey, usize)> {
+        for (at, &byte) in chunk.iter().enumerate() {
+            let is_key = self.depth == 1 && self.expect_key;
+            if self.in_string {
+                if self.string_byte(byte) && is_key {
+                    self.key.push(byte);
+                }
+                continue;
+            }
+            match byte {
+                b'"' => {
+                    self.in_string = true;
+                    if is_key {
+                        self.key.clear();
+                    }
+                }
+                b'{' | b'[' => {
+                    self.depth += 1;
+                    self.expect_key = self.depth == 1 && byte == b'{';
+                }
+                b'}' | b']' => self.depth = self.depth.saturating_sub(1),
+                b',' if self.depth == 1 => self.expect_key = true,
+                b':' if self.depth == 1 => {
+                    self.expect_key = false;
+                    match self.key.as_slice() {
+                        b"result" => return Some((ResultKey::Result, at + 1)),
+                        b"error" => return Some((ResultKey::Error, at + 1)),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Scan `chunk` within the value found by [`Self::find`], returning the
+    /// offset of the `,` or `}` ending it
+    fn value_end(&mut self, chunk: &[u8]) -> Option<usize> {
+        for (at, &byte) in chunk.iter().enumerate() {
+            if self.in_string {
+                self.string_byte(byte);
+                continue;
+            }
+            match byte {
+                b'"' => self.in_string = true,
+                b'{' | b'[' => self.depth += 1,
+                b',' | b'}' | b']' if self.depth == 1 => return Some(at),
+                b'}' | b']' => self.depth -= 1,
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/// The configured headers with their `${NAME}` placeholders resolved
+fn resolved_headers(
+    configured: Option<&BTreeMap<String, String>>,
+) -> MceptionResult<Option<BTreeMap<String, String>>> {
+    configured
+        .map(|headers| {
+            headers
+                .iter()
+                .map(|(name, value)| Ok((name.clone(), interpolation::resolve(value)?)))
+                .collect()
+        })
+        .transpose()
+}
+
+/// The configured headers, resolved, to send on requests of mception's own
+pub(crate) fn configured_headers(
+    configured: Option<&BTreeMap<String, String>>,
+) -> MceptionResult<HeaderMap> {
+    let resolved = resolved_headers(configured)?;
+    let mut headers = request_headers(&HeaderMap::new(), resolved.as_ref(), "0")
+        .map_err(NetworkError::InvalidUrl)?;
+    headers.remove(DEADLINE_HEADER);
+    Ok(headers)
+}
+
+/// Incoming headers of the MCP protocol, passed on to every HTTPS leaf MCP
+pub const PROTOCOL_HEADERS: &[&str] = &[
+    "accept",
+    "content-type",
+    SESSION_HEADER,
+    PROTOCOL_VERSION_HEADER,
+    "last-event-id",
+];
+
+/// The incoming headers to pass on to an HTTPS leaf MCP: those of the MCP
+/// protocol and the ones `policy` allows, so credentials agents send
+/// mception don't reach third parties. Headers describing the connection,
+/// including those its `connection` header names, are never passed on.
+pub fn passed_through(incoming: &HeaderMap, policy: Option<&HeaderPolicy>) -> HeaderMap {
+    let connection: Vec<String> = incoming
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .collect();
+    let allowed = |name: &HeaderName| {
+        if is_hop_by_hop(name) || connection.iter().any(|listed| listed == name.as_str()) {
+            return false;
+        }
+        PROTOCOL_HEADERS.contains(&name.as_str())
+            || policy.is_some_and(|policy| {
+                policy
+                    .passthrough
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(name.as_str()))
+            })
+    };
+    let mut headers = HeaderMap::new();
+    for (name, value) in incoming {
+        if allowed(name) {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+    headers
+}
+
+/// Check the headers a policy passes on before it is stored
+pub fn validate_header_policy(policy: &HeaderPolicy) -> Result<(), String> {
+    for name in &policy.passthrough {
+        let parsed = HeaderName::try_from(name.as_str())
+            .map_err(|_| format!("invalid header name '{}' in header_policy", name))?;
+        if is_hop_by_hop(&parsed) || parsed == header::HOST || parsed == header::CONTENT_LENGTH {
+            return Err(format!(
+                "header '{}' describes the connection and can't be passed through",
+                name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Incoming headers without the connection-specific ones, overridden by the
+/// configured headers
+fn request_headers(
+    incoming: &HeaderMap,
+    configured: Option<&BTreeMap<String, String>>,
+    budget_ms: &str,
+) -> Result<HeaderMap, String> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in incoming {
+        if is_hop_by_hop(name)
+            || name == header::HOST
+            || name == header::CONTENT_LENGTH
+            || name == DEADLINE_HEADER
+        {
+            continue;
+        }
+        headers.append(name.clone(), value.clone());
+    }
+    for (name, value) in configured.into_iter().flatten() {
+        let name = HeaderName::try_from(name.as_str())
+            .map_err(|_| format!("invalid configured header name '{}'", name))?;
+        let value = HeaderValue::try_from(value.as_str())
+            .map_err(|_| format!("invalid value of configured header '{}'", name))?;
+        headers.insert(name, value);
+    }
+    headers.insert(
+        DEADLINE_HEADER,
+        HeaderValue::try_from(budget_ms).map_err(|e| e.to_string())?,
+    );
+    Ok(headers)
+}
+
+/// Upstream response headers to relay. The length is set again for the
+/// relayed body.
+fn response_headers(upstream: &HeaderMap) -> HeaderMap {
+    let mut headers = upstream.clone();
+    headers.remove(header::CONTENT_LENGTH);
+    for name in HOP_BY_HOP {
+        headers.remove(*name);
+    }
+    headers
+}
+
+/// Whether the header describes a single connection, see [`HOP_BY_HOP`]
+pub(crate) fn is_hop_by_hop(name: &HeaderName) -> bool {
+    HOP_BY_HOP.contains(&name.as_str())
+}
+
+/// An error with its sources, e.g. the refused connection behind a request error
+pub(crate) fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(e) = source {
+        message.push_str(": ");
+        message.push_str(&e.to_string());
+        source = e.source();
+    }
+    message
+}
+
+/// `url` without credentials, to show in errors: user info is dropped and
+/// secret-looking query parameters are redacted
+pub fn redact_url(url: &str) -> String {
+    let Ok(mut url) = reqwest::Url::parse(url) else {
+        return "[invalid URL]".to_string();
+    };
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    if url.query().is_some() {
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(key, value)| {
+                let value = if is_secret_key(&key) {
+                    "REDACTED".to_string()
+                } else {
+                    value.into_owned()
+                };
+                (key.into_owned(), value)
+            })
+            .collect();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url.to_string()
+}
+
+/// Check the URL and headers of an HTTPS leaf MCP before they are stored
+pub fn validate(url: &str, headers: Option<&BTreeMap<String, String>>) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("invalid URL: {}", e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!(
+            "URL scheme must be http or https, not '{}'",
+            parsed.scheme()
+        ));
+    }
+    request_headers(&HeaderMap::new(), headers, "0").map(|_| ())
+}
@@ -0,0 +1,355 @@
+use crate::core::error_body;
+use crate::services::ConfigService;
+use crate::services::auth::{Actor, DEFAULT_ADMIN_ACTOR};
+use axum::body::{Body, Bytes};
+use axum::extract::{Request, State};
+use axum::http::{HeaderValue, Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Json, Response};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// Header a client names a mutating admin request with, to retry it safely
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Header marking an answer replayed for a retried request
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// How long an answer is kept for retries by default
+pub const DEFAULT_WINDOW: Duration = Duration::hours(24);
+
+/// Answers kept at most by default, the oldest is evicted beyond it
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+/// Longest key accepted
+const MAX_KEY_LEN: usize = 255;
+
+/// Largest request body hashed, axum's default limit of JSON bodies
+const MAX_BODY: usize = 2 * 1024 * 1024;
+
+tokio::task_local! {
+    /// Key of the admin request being handled, added to its audit entries
+    static CURRENT_KEY: String;
+}
+
+/// The idempotency key of the admin request being handled, if it has one
+pub fn current_key() -> Option<String> {
+    CURRENT_KEY.try_with(String::clone).ok()
+}
+
+/// The answer to a request with an idempotency key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredAnswer {
+    /// Digest of the method, path with query and body the key was first used with
+    request_hash: String,
+    /// `None` while the first request is still handled
+    status: Option<u16>,
+    content_type: Option<String>,
+    body: String,
+    stored_at: DateTime<Utc>,
+}
+
+/// What to do with a request carrying a key
+enum Lookup {
+    /// First use, handle it
+    New,
+    Replay(StoredAnswer),
+    /// The key was used with another request
+    Conflict,
+    InProgress,
+}
+
+/// Answers of admin requests by actor and idempotency key, kept for a
+/// window and bounded in number, optionally persisted across restarts
+#[derive(Debug)]
+pub struct IdempotencyStore {
+    window: Duration,
+    capacity: usize,
+    path: Option<PathBuf>,
+    answers: Mutex<HashMap<String, StoredAnswer>>,
+}
+
+impl Default for IdempotencyStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW, DEFAULT_CAPACITY)
+    }
+}
+
+impl IdempotencyStore {
+    pub fn new(window: Duration, capacity: usize) -> Self {
+        Self {
+            window,
+            capacity,
+            path: None,
+            answers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Persist the answers to `path`, starting from those saved there
+    pub fn persisted(mut self, path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let mut answers = read(&path)?;
+        // Requests cut off by a restart may be retried
+        answers.retain(|_, answer| answer.status.is_some());
+        self.answers = Mutex::new(answers);
+        self.path = Some(path);
+        Ok(self)
+    }
+
+    fn lookup(&self, key: &str, request_hash: &str) -> Lookup {
+        let now = Utc::now();
+        let mut answers = self.answers.lock().unwrap();
+        answers.retain(|_, answer| now - answer.stored_at < self.window);
+        if let Some(answer) = answers.get(key) {
+            return if answer.request_hash != request_hash {
+                Lookup::Conflict
+            } else if answer.status.is_none() {
+                Lookup::InProgress
+            } else {
+                Lookup::Replay(answer.clone())
+            };
+        }
+        if answers.len() >= self.capacity.max(1) {
+            let oldest = answers
+                .iter()
+                .min_by_key(|(_, answer)| answer.stored_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                answers.remove(&oldest);
+            }
+        }
+        answers.insert(
+            key.to_string(),
+            StoredAnswer {
+                request_hash: request_hash.to_string(),
+                status: None,
+                content_type: None,
+                body: String::new(),
+                stored_at: now,
+            },
+        );
+        Lookup::New
+    }
+
+    /// Keep the answer for retries, or forget the key if the request failed
+    /// on the server's side and may be retried for real
+    fn complete(&self, key: &str, status: StatusCode, content_type: Option<String>, body: &[u8]) {
+        let mut answers = self.answers.lock().unwrap();
+        if status.is_server_error() {
+            answers.remove(key);
+        } else if let Some(answer) = answers.get_mut(key) {
+            answer.status = Some(status.as_u16());
+            answer.content_type = content_type;
+            answer.body = String::from_utf8_lossy(body).into_owned();
+        }
+        self.save(&answers);
+    }
+
+    /// Forget the key of a request whose handling was cut off before it
+    /// answered, so it may be retried
+    fn abandon(&self, key: &str) {
+        let mut answers = self.answers.lock().unwrap();
+        if answers
+            .get(key)
+            .is_some_and(|answer| answer.status.is_none())
+        {
+            answers.remove(key);
+        }
+    }
+
+    fn save(&self, answers: &HashMap<String, StoredAnswer>) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Err(e) = write(path, answers) {
+            warn!(
+                "Failed to persist idempotency keys to {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+fn read(path: &Path) -> std::io::Result<HashMap<String, StoredAnswer>> {
+    match std::fs::read(path) {
+        Ok(content) => Ok(serde_json::from_slice(&content)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn write(path: &Path, answers: &HashMap<String, StoredAnswer>) -> std::io::Result<()> {
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, serde_json::to_vec(answers)?)?;
+    std::fs::rename(&temp, path)
+}
+
+/// Abandons the key of the request being handled when dropped before it
+/// answered, e.g. as the client disconnected or the handler panicked
+struct Pending<'a> {
+    store: &'a IdempotencyStore,
+    key: &'a str,
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        self.store.abandon(self.key);
+    }
+}
+
+fn request_hash(method: &Method, path_and_query: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_str().as_bytes());
+    hasher.update(b" ");
+    hasher.update(path_and_query.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body);
+    let digest = hasher.finalize();
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn error(status: StatusCode, kind: &str, message: String) -> Response {
+    (status, Json(error_body(kind, message))).into_response()
+}
+
+/// Middleware answering retries of mutating admin requests with the same
+/// [`IDEMPOTENCY_KEY_HEADER`] from the stored answer instead of handling
+/// them again. A key reused with another request, including another query,
+/// is refused with 422, one
+/// whose first request is still handled with 409. Answers with a server
+/// error aren't kept. Replays and refusals are audited as
+/// `idempotent_retry`, and the entries of the request itself carry the key.
+pub async fn deduplicate(
+    State(service): State<Arc<ConfigService>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let is_mutation = matches!(
+        *request.method(),
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    );
+    let Some(key) = request
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .filter(|_| is_mutation)
+    else {
+        return next.run(request).await;
+    };
+    let key = match key.to_str() {
+        Ok(key) if !key.trim().is_empty() && key.len() <= MAX_KEY_LEN => key.to_string(),
+        _ => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                "invalid_idempotency_key",
+                format!(
+                    "Idempotency keys must be 1 to {} visible ASCII characters",
+                    MAX_KEY_LEN
+                ),
+            );
+        }
+    };
+    let actor = request
+        .extensions()
+        .get::<Actor>()
+        .map_or(DEFAULT_ADMIN_ACTOR.to_string(), |actor| actor.0.clone());
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let path_and_query = request
+        .uri()
+        .path_and_query()
+        .map_or(path.clone(), |path_and_query| path_and_query.to_string());
+
+    let (parts, body) = request.into_parts();
+    let body = match axum::body::to_bytes(body, MAX_BODY).await {
+        Ok(body) => body,
+        Err(e) => {
+            return error(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "invalid_request",
+                format!("Failed to read the request body: {}", e),
+            );
+        }
+    };
+    let store = service.idempotency();
+    // Keys are per actor, so admins can't replay each other's answers
+    let scoped = format!("{}\n{}", actor, key);
+    let hash = request_hash(&method, &path_and_query, &body);
+    let (outcome, response) = match store.lookup(&scoped, &hash) {
+        Lookup::New => {
+            let pending = Pending {
+                store,
+                key: &scoped,
+            };
+            let request = Request::from_parts(parts, Body::from(body));
+            let response = CURRENT_KEY.scope(key, next.run(request)).await;
+            let (parts, body) = response.into_parts();
+            let body = axum::body::to_bytes(body, usize::MAX)
+                .await
+                .unwrap_or_default();
+            let content_type = parts
+                .headers
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            store.complete(&scoped, parts.status, content_type, &body);
+            drop(pending);
+            return Response::from_parts(parts, Body::from(body));
+        }
+        Lookup::Replay(answer) => ("replayed", replay(answer)),
+        Lookup::Conflict => (
+            "conflict",
+            error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "idempotency_key_reused",
+                format!(
+                    "Idempotency key '{}' was used with a different request",
+                    key
+                ),
+            ),
+        ),
+        Lookup::InProgress => (
+            "in_progress",
+            error(
+                StatusCode::CONFLICT,
+                "idempotency_key_in_progress",
+                format!(
+                    "The request with idempotency key '{}' is still handled",
+                    key
+                ),
+            ),
+        ),
+    };
+    let audit = service.audit_idempotent_retry(
+        &actor,
+        &key,
+        outcome,
+        &method,
+        &path,
+        response.status().as_u16(),
+    );
+    CURRENT_KEY.scope(key.clone(), audit).await;
+    response
+}
+
+fn replay(answer: StoredAnswer) -> Response {
+    let status = answer
+        .status
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .unwrap_or(StatusCode::OK);
+    let mut response = (status, Bytes::from(answer.body)).into_response();
+    let headers = response.headers_mut();
+    if let Some(content_type) = answer
+        .content_type
+        .and_then(|value| HeaderValue::from_str(&value).ok())
+    {
+        headers.insert(header::CONTENT_TYPE, content_type);
+    }
+    headers.insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
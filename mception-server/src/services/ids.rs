@@ -0,0 +1,167 @@
+use crate::core::ServerConfig;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Longest id accepted for leaf MCPs and agents
+pub const MAX_ID_LENGTH: usize = 64;
+
+/// Longest slug generated from a name, leaving room for a collision suffix
+const MAX_SLUG_LENGTH: usize = 48;
+
+/// Give up generating an id after this many collisions
+const MAX_ATTEMPTS: u32 = 10_000;
+
+/// What an id is generated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdKind {
+    Mcp,
+    Agent,
+}
+
+impl IdKind {
+    fn prefix(self) -> &'static str {
+        match self {
+            IdKind::Mcp => "mcp",
+            IdKind::Agent => "agent",
+        }
+    }
+}
+
+/// Scheme for generating ids of leaf MCPs and agents created without one
+pub trait IdGenerator: Send + Sync {
+    /// Name of the scheme, e.g. `slug`
+    fn scheme(&self) -> &'static str;
+
+    /// Candidate id for an object of `kind` with an optional display name.
+    /// `attempt` counts up from 0 while candidates collide with existing ids.
+    fn candidate(&self, kind: IdKind, name: Option<&str>, attempt: u32) -> String;
+}
+
+/// Slug of the name, e.g. `my-github-mcp`, suffixed `-2`, `-3`, ... on collisions
+#[derive(Debug, Default)]
+pub struct SlugIds;
+
+impl IdGenerator for SlugIds {
+    fn scheme(&self) -> &'static str {
+        "slug"
+    }
+
+    fn candidate(&self, kind: IdKind, name: Option<&str>, attempt: u32) -> String {
+        let slug = name.map(slugify).filter(|slug| !slug.is_empty());
+        let base = slug.as_deref().unwrap_or(kind.prefix());
+        match attempt {
+            0 => base.to_string(),
+            n => format!("{}-{}", base, n + 1),
+        }
+    }
+}
+
+/// Random v4 UUID
+#[derive(Debug, Default)]
+pub struct UuidIds;
+
+impl IdGenerator for UuidIds {
+    fn scheme(&self) -> &'static str {
+        "uuid"
+    }
+
+    fn candidate(&self, _kind: IdKind, _name: Option<&str>, _attempt: u32) -> String {
+        Uuid::new_v4().to_string()
+    }
+}
+
+/// Random 21 character id in the style of nanoid, restricted to lowercase
+/// letters and digits
+#[derive(Debug, Default)]
+pub struct NanoIds;
+
+impl IdGenerator for NanoIds {
+    fn scheme(&self) -> &'static str {
+        "nanoid"
+    }
+
+    fn candidate(&self, _kind: IdKind, _name: Option<&str>, _attempt: u32) -> String {
+        const ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+        (0..21)
+            .map(|_| ALPHABET[(Uuid::new_v4().as_u128() % ALPHABET.len() as u128) as usize] as char)
+            .collect()
+    }
+}
+
+/// Kind prefix and the lowest free counter, e.g. `mcp-3`
+#[derive(Debug, Default)]
+pub struct PrefixCounterIds;
+
+impl IdGenerator for PrefixCounterIds {
+    fn scheme(&self) -> &'static str {
+        "prefix-counter"
+    }
+
+    fn candidate(&self, kind: IdKind, _name: Option<&str>, attempt: u32) -> String {
+        format!("{}-{}", kind.prefix(), attempt + 1)
+    }
+}
+
+/// Generate an id not used by any leaf MCP or agent. Call with the
+/// configuration write lock held so a concurrent create can't take it.
+pub fn generate(
+    generator: &dyn IdGenerator,
+    config: &ServerConfig,
+    kind: IdKind,
+    name: Option<&str>,
+) -> Result<String, String> {
+    for attempt in 0..MAX_ATTEMPTS {
+        let id = generator.candidate(kind, name, attempt);
+        if validate(&id).is_ok() && !is_taken(config, &id) {
+            return Ok(id);
+        }
+    }
+    Err(format!(
+        "No free {} id found after {} attempts",
+        generator.scheme(),
+        MAX_ATTEMPTS
+    ))
+}
+
+/// Leaf MCPs and agents share a namespace, as both are MCPs to an agent
+pub fn is_taken(config: &ServerConfig, id: &str) -> bool {
+    config.leaf_mcps.contains_key(id) || config.agents.contains_key(id)
+}
+
+/// Check the id format: ASCII letters, digits, `-`, `_` and `.`, starting with
+/// a letter or digit, at most [`MAX_ID_LENGTH`] characters
+pub fn validate(id: &str) -> Result<(), String> {
+    if id.is_empty() || id.len() > MAX_ID_LENGTH {
+        return Err(format!(
+            "ID must be between 1 and {} characters long",
+            MAX_ID_LENGTH
+        ));
+    }
+    if !id.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return Err(format!("ID '{}' must start with a letter or digit", id));
+    }
+    if let Some(c) = id
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+    {
+        return Err(format!(
+            "ID '{}' contains '{}', only letters, digits, '-', '_' and '.' are allowed",
+            id, c
+        ));
+    }
+    Ok(())
+}
+
+/// Lowercase the name and join its runs of letters and digits with `-`
+fn slugify(name: &str) -> String {
+    let slug = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>()
+        .join("-");
+    slug[..slug.len().min(MAX_SLUG_LENGTH)]
+        .trim_end_matches('-')
+        .to_string()
+}
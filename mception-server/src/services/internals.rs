@@ -0,0 +1,264 @@
+use crate::services::audit_buffer::AuditBufferUsage;
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Default number of leaf MCP tool listings kept in memory
+pub const DEFAULT_TOOL_CACHE_CAPACITY: usize = 256;
+
+/// Default number of requests forwarded to agents that may wait for their
+/// answer at the same time
+pub const DEFAULT_MAX_PENDING_AGENT_REQUESTS: usize = 1024;
+
+/// Default number of those requests that may wait for the same agent
+pub const DEFAULT_MAX_PENDING_REQUESTS_PER_AGENT: usize = 256;
+
+/// Caps of the in-memory structures that would otherwise grow with use
+#[derive(Debug, Clone, Copy)]
+pub struct ResourceLimits {
+    /// Tool listings cached, the longest cached is evicted beyond it
+    pub tool_cache_entries: usize,
+    /// Requests waiting for agents, further ones are rejected as overloaded
+    pub pending_agent_requests: usize,
+    /// Requests waiting for the same agent, further ones are rejected as overloaded
+    pub pending_requests_per_agent: usize,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        Self {
+            tool_cache_entries: DEFAULT_TOOL_CACHE_CAPACITY,
+            pending_agent_requests: DEFAULT_MAX_PENDING_AGENT_REQUESTS,
+            pending_requests_per_agent: DEFAULT_MAX_PENDING_REQUESTS_PER_AGENT,
+        }
+    }
+}
+
+/// Usage of the leaf MCP tool cache
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolCacheUsage {
+    pub entries: usize,
+    pub capacity: usize,
+    /// Approximate size of the cached listings as JSON
+    pub approx_bytes: usize,
+    /// Listings evicted to stay within the capacity, since the start
+    pub evictions: u64,
+}
+
+/// Usage of the agent connection registry
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionUsage {
+    pub connections: usize,
+    /// Forwarded requests waiting for an agent's answer
+    pub pending_requests: usize,
+    pub capacity: usize,
+    pub capacity_per_connection: usize,
+    /// Requests rejected because `capacity` requests were pending, or
+    /// `capacity_per_connection` for their agent, since the start
+    pub rejected: u64,
+    /// Requests evicted unanswered once their deadline passed, since the start
+    pub evicted: u64,
+    /// Responses of agents to requests no longer waiting, e.g. evicted ones,
+    /// since the start
+    pub orphaned_responses: u64,
+}
+
+/// Usage of the debug capture ring buffers
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureUsage {
+    pub captures: usize,
+    pub payloads: usize,
+    /// Size of the captured bodies, each already truncated to its capture's `max_bytes`
+    pub approx_bytes: usize,
+}
+
+/// Usage of the stdio leaf MCP processes
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessUsage {
+    pub processes: usize,
+    /// Requests waiting for a process to answer
+    pub pending_requests: usize,
+}
+
+/// Agent tokens and grants about to expire, see [`crate::services::expiry`]
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpiryUsage {
+    /// Tokens expiring within the warning window
+    pub tokens_expiring: usize,
+    /// Tokens already expired and refused
+    pub tokens_expired: usize,
+    /// Grants expiring within the warning window
+    pub grants_expiring: usize,
+    /// Grants already expired, which no longer allow anything
+    pub grants_expired: usize,
+    pub window_seconds: i64,
+}
+
+/// Approximate usage of the server's in-memory structures
+#[derive(Debug, Clone, Serialize)]
+pub struct Internals {
+    pub tool_cache: ToolCacheUsage,
+    pub agent_connections: ConnectionUsage,
+    pub debug_captures: CaptureUsage,
+    pub stdio_processes: ProcessUsage,
+    pub audit: AuditBufferUsage,
+    pub expiry: ExpiryUsage,
+}
+
+impl Internals {
+    /// The usage in the Prometheus text format
+    pub fn metrics(&self) -> String {
+        let metrics: [(&str, &str, &str, u64); 24] = [
+            (
+                "mception_tool_cache_entries",
+                "gauge",
+                "Leaf MCP tool listings cached",
+                self.tool_cache.entries as u64,
+            ),
+            (
+                "mception_tool_cache_capacity",
+                "gauge",
+                "Tool listings cached at most",
+                self.tool_cache.capacity as u64,
+            ),
+            (
+                "mception_tool_cache_bytes",
+                "gauge",
+                "Approximate size of the cached tool listings",
+                self.tool_cache.approx_bytes as u64,
+            ),
+            (
+                "mception_tool_cache_evictions_total",
+                "counter",
+                "Tool listings evicted to stay within the capacity",
+                self.tool_cache.evictions,
+            ),
+            (
+                "mception_agent_connections",
+                "gauge",
+                "Agents connected over their forwarding WebSocket",
+                self.agent_connections.connections as u64,
+            ),
+            (
+                "mception_agent_pending_requests",
+                "gauge",
+                "Forwarded requests waiting for an agent's answer",
+                self.agent_connections.pending_requests as u64,
+            ),
+            (
+                "mception_agent_pending_requests_capacity",
+                "gauge",
+                "Forwarded requests that may wait for agents at most",
+                self.agent_connections.capacity as u64,
+            ),
+            (
+                "mception_agent_pending_requests_capacity_per_connection",
+                "gauge",
+                "Forwarded requests that may wait for one agent at most",
+                self.agent_connections.capacity_per_connection as u64,
+            ),
+            (
+                "mception_agent_pending_requests_rejected_total",
+                "counter",
+                "Forwarded requests rejected at the pending request capacity",
+                self.agent_connections.rejected,
+            ),
+            (
+                "mception_agent_pending_requests_evicted_total",
+                "counter",
+                "Forwarded requests evicted unanswered at their deadline",
+                self.agent_connections.evicted,
+            ),
+            (
+                "mception_agent_orphaned_responses_total",
+                "counter",
+                "Agent responses to requests no longer waiting, dropped",
+                self.agent_connections.orphaned_responses,
+            ),
+            (
+                "mception_debug_captures",
+                "gauge",
+                "Leaf MCPs with a debug capture",
+                self.debug_captures.captures as u64,
+            ),
+            (
+                "mception_debug_capture_payloads",
+                "gauge",
+                "Payloads held by debug captures",
+                self.debug_captures.payloads as u64,
+            ),
+            (
+                "mception_debug_capture_bytes",
+                "gauge",
+                "Size of the payloads held by debug captures",
+                self.debug_captures.approx_bytes as u64,
+            ),
+            (
+                "mception_stdio_processes",
+                "gauge",
+                "Running stdio leaf MCP processes",
+                self.stdio_processes.processes as u64,
+            ),
+            (
+                "mception_stdio_pending_requests",
+                "gauge",
+                "Requests waiting for a stdio leaf MCP process",
+                self.stdio_processes.pending_requests as u64,
+            ),
+            (
+                "mception_audit_degraded",
+                "gauge",
+                "1 while the audit storage fails or entries are held back",
+                self.audit.degraded.into(),
+            ),
+            (
+                "mception_audit_buffered_entries",
+                "gauge",
+                "Audit entries held back until the audit storage recovers",
+                self.audit.buffered as u64,
+            ),
+            (
+                "mception_audit_entries_buffered_total",
+                "counter",
+                "Audit entries held back because the audit storage failed",
+                self.audit.buffered_total,
+            ),
+            (
+                "mception_audit_entries_dropped_total",
+                "counter",
+                "Audit entries lost because the audit storage failed",
+                self.audit.dropped_total,
+            ),
+            (
+                "mception_agent_tokens_expiring",
+                "gauge",
+                "Agent tokens expiring within the warning window",
+                self.expiry.tokens_expiring as u64,
+            ),
+            (
+                "mception_agent_tokens_expired",
+                "gauge",
+                "Agent tokens expired and refused",
+                self.expiry.tokens_expired as u64,
+            ),
+            (
+                "mception_agent_grants_expiring",
+                "gauge",
+                "Agent grants expiring within the warning window",
+                self.expiry.grants_expiring as u64,
+            ),
+            (
+                "mception_agent_grants_expired",
+                "gauge",
+                "Agent grants expired and no longer allowing anything",
+                self.expiry.grants_expired as u64,
+            ),
+        ];
+        let mut text = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(text, "# HELP {} {}", name, help);
+            let _ = writeln!(text, "# TYPE {} {}", name, kind);
+            let _ = writeln!(text, "{} {}", name, value);
+        }
+        text
+    }
+}
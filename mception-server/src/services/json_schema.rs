@@ -0,0 +1,81 @@
+use jsonschema::ValidationError;
+use jsonschema::error::{TypeKind, ValidationErrorKind};
+use jsonschema::paths::LocationSegment;
+use serde_json::Value;
+
+/// Check `value` against a JSON Schema, returning every place it fails,
+/// each named by its path from `root`, e.g. `arguments.path`. Empty if it
+/// satisfies the schema.
+///
+/// The whole of JSON Schema is checked, including `format`; references can
+/// only point into the schema itself. A schema that isn't valid fails every
+/// value.
+pub fn errors(schema: &Value, value: &Value, root: &str) -> Vec<String> {
+    let validator = match jsonschema::options()
+        .should_validate_formats(true)
+        .build(schema)
+    {
+        Ok(validator) => validator,
+        Err(e) => {
+            return vec![format!(
+                "{} can't be checked, its schema is invalid: {}",
+                root, e
+            )];
+        }
+    };
+    validator
+        .iter_errors(value)
+        .flat_map(|error| describe(&error, root))
+        .collect()
+}
+
+fn describe(error: &ValidationError, root: &str) -> Vec<String> {
+    let mut path = root.to_string();
+    for segment in error.instance_path() {
+        match segment {
+            LocationSegment::Property(name) => {
+                path.push('.');
+                path.push_str(&name);
+            }
+            LocationSegment::Index(index) => path.push_str(&format!("[{}]", index)),
+        }
+    }
+
+    let message = match error.kind() {
+        ValidationErrorKind::Type { kind } => {
+            let allowed: Vec<&str> = match kind {
+                TypeKind::Single(single) => vec![single.as_str()],
+                TypeKind::Multiple(types) => types.iter().map(|single| single.as_str()).collect(),
+            };
+            format!("must be of type {}", allowed.join(" or "))
+        }
+        ValidationErrorKind::Required { property } => {
+            return vec![format!(
+                "{}.{} is required",
+                path,
+                property.as_str().unwrap_or_default()
+            )];
+        }
+        ValidationErrorKind::AdditionalProperties { unexpected } => {
+            return unexpected
+                .iter()
+                .map(|name| format!("{}.{} is not allowed", path, name))
+                .collect();
+        }
+        ValidationErrorKind::FalseSchema => "is not allowed".to_string(),
+        ValidationErrorKind::Enum { options } => format!("must be one of {}", options),
+        ValidationErrorKind::Constant { expected_value } => format!("must be {}", expected_value),
+        ValidationErrorKind::Minimum { limit } => format!("must be at least {}", limit),
+        ValidationErrorKind::Maximum { limit } => format!("must be at most {}", limit),
+        ValidationErrorKind::MinItems { limit } => format!("must have at least {} items", limit),
+        ValidationErrorKind::MaxItems { limit } => format!("must have at most {} items", limit),
+        ValidationErrorKind::MinLength { limit } => {
+            format!("must have at least {} characters", limit)
+        }
+        ValidationErrorKind::MaxLength { limit } => {
+            format!("must have at most {} characters", limit)
+        }
+        _ => error.to_string(),
+    };
+    vec![format!("{} {}", path, message)]
+}
@@ -0,0 +1,254 @@
+use crate::core::McpTransport;
+use crate::services::{ConfigService, deadline, https};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinSet;
+use tracing::{debug, warn};
+
+/// How long a session is offered for reuse after it was last used
+pub const SESSION_TTL: Duration = Duration::hours(1);
+
+/// A session a streamable HTTP leaf MCP assigned on `initialize`, as
+/// persisted. Holds the opaque session id but no credentials: the endpoint
+/// is redacted and the transport only kept as a digest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PersistedSession {
+    session_id: String,
+    /// The leaf MCP's URL, see [`https::redact_url`]
+    endpoint: String,
+    /// Digest of the transport the session was negotiated through
+    transport: String,
+    established_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+struct Session {
+    persisted: PersistedSession,
+    /// Carried over from a previous run
+    previous_run: bool,
+    /// Established by or accepted during this run
+    confirmed: bool,
+}
+
+/// The current session of a leaf MCP, without its id
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub endpoint: String,
+    pub established_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Carried over from a previous run instead of initialized by this one
+    pub resumed: bool,
+}
+
+/// Sessions of HTTPS leaf MCPs, reused so listing tools doesn't initialize
+/// anew each time. With a state file they survive restarts, which spares
+/// leaf MCPs rate-limiting `initialize` from re-initializing them all.
+#[derive(Debug, Default)]
+pub struct LeafSessions {
+    path: Option<PathBuf>,
+    sessions: Mutex<BTreeMap<String, Session>>,
+}
+
+impl LeafSessions {
+    /// Sessions persisted to `path`, starting from those saved by the
+    /// previous run. They count as resumed once the leaf MCP accepted them,
+    /// see [`Self::used`].
+    pub fn open(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let now = Utc::now();
+        let sessions = read(&path)?
+            .into_iter()
+            .filter(|(_, persisted)| persisted.expires_at > now)
+            .map(|(id, persisted)| {
+                let session = Session {
+                    persisted,
+                    previous_run: true,
+                    confirmed: false,
+                };
+                (id, session)
+            })
+            .collect();
+        Ok(Self {
+            path: Some(path),
+            sessions: Mutex::new(sessions),
+        })
+    }
+
+    /// The session id to try for a leaf MCP, if one was kept for its
+    /// current transport and hasn't expired. Others are dropped.
+    pub fn get(&self, leaf_id: &str, transport: &McpTransport) -> Option<String> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get(leaf_id)?;
+        if session.persisted.transport == digest(transport)
+            && session.persisted.expires_at > Utc::now()
+        {
+            return Some(session.persisted.session_id.clone());
+        }
+        sessions.remove(leaf_id);
+        self.save(&sessions);
+        None
+    }
+
+    /// Leaf MCPs with a session from the previous run not yet confirmed
+    pub fn unconfirmed(&self) -> Vec<String> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions
+            .iter()
+            .filter(|(_, session)| !session.confirmed)
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+
+    /// Keep the session a leaf MCP assigned on `initialize`
+    pub fn established(&self, leaf_id: &str, transport: &McpTransport, session_id: String) {
+        let McpTransport::Https { url, .. } = transport else {
+            return;
+        };
+        let now = Utc::now();
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.insert(
+            leaf_id.to_string(),
+            Session {
+                persisted: PersistedSession {
+                    session_id,
+                    endpoint: https::redact_url(url),
+                    transport: digest(transport),
+                    established_at: now,
+                    expires_at: now + SESSION_TTL,
+                },
+                previous_run: false,
+                confirmed: true,
+            },
+        );
+        self.save(&sessions);
+    }
+
+    /// Record that a leaf MCP accepted its kept session, extending it
+    pub fn used(&self, leaf_id: &str) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.get_mut(leaf_id) {
+            session.persisted.expires_at = Utc::now() + SESSION_TTL;
+            session.confirmed = true;
+            self.save(&sessions);
+        }
+    }
+
+    /// Drop a leaf MCP's session, e.g. because it was refused or the leaf
+    /// MCP removed
+    pub fn forget(&self, leaf_id: &str) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.remove(leaf_id).is_some() {
+            self.save(&sessions);
+        }
+    }
+
+    /// The session a leaf MCP is used with, not counting one of a previous
+    /// run it hasn't accepted yet
+    pub fn info(&self, leaf_id: &str) -> Option<SessionInfo> {
+        let sessions = self.sessions.lock().unwrap();
+        let session = sessions.get(leaf_id).filter(|session| session.confirmed)?;
+        Some(SessionInfo {
+            endpoint: session.persisted.endpoint.clone(),
+            established_at: session.persisted.established_at,
+            expires_at: session.persisted.expires_at,
+            resumed: session.previous_run,
+        })
+    }
+
+    /// Write the sessions to the state file. Failing to is only logged, the
+    /// sessions are then initialized again after a restart.
+    fn save(&self, sessions: &BTreeMap<String, Session>) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let persisted: BTreeMap<&String, &PersistedSession> = sessions
+            .iter()
+            .map(|(id, session)| (id, &session.persisted))
+            .collect();
+        if let Err(e) = write(path, &persisted) {
+            warn!(
+                "Failed to persist leaf MCP sessions to {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+fn digest(transport: &McpTransport) -> String {
+    let json = serde_json::to_string(transport).unwrap_or_default();
+    let digest = Sha256::digest(json.as_bytes());
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn read(path: &Path) -> std::io::Result<BTreeMap<String, PersistedSession>> {
+    match std::fs::read(path) {
+        Ok(content) => Ok(serde_json::from_slice(&content)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn write(path: &Path, sessions: &BTreeMap<&String, &PersistedSession>) -> std::io::Result<()> {
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, serde_json::to_vec_pretty(sessions)?)?;
+    std::fs::rename(&temp, path)
+}
+
+/// Offer every leaf MCP the session kept from the previous run with a
+/// `ping`, all at once. Sessions a leaf MCP refuses, or kept for a leaf MCP
+/// since removed or changed, are dropped and initialized anew when next
+/// needed. Returns the number of sessions resumed.
+pub async fn resume(service: Arc<ConfigService>) -> usize {
+    let config = service.get_configuration().await;
+    let mut pings = JoinSet::new();
+    for leaf_id in service.leaf_sessions().unconfirmed() {
+        let Some(leaf) = config.leaf_mcps.get(&leaf_id).cloned() else {
+            service.leaf_sessions().forget(&leaf_id);
+            continue;
+        };
+        let McpTransport::Https { url, headers } = &leaf.transport else {
+            service.leaf_sessions().forget(&leaf_id);
+            continue;
+        };
+        let Some(session) = service.leaf_sessions().get(&leaf_id, &leaf.transport) else {
+            continue;
+        };
+        let (url, headers, service) = (url.clone(), headers.clone(), service.clone());
+        pings.spawn(async move {
+            let ping = json!({ "jsonrpc": "2.0", "id": "resume", "method": "ping" });
+            let pinged = service
+                .https_forwarder()
+                .call(
+                    &url,
+                    headers.as_ref(),
+                    &mut Some(session),
+                    &ping,
+                    deadline::leaf_timeout(&leaf, service.default_leaf_timeout()),
+                )
+                .await;
+            match pinged {
+                Ok(response) if response.get("error").is_none() => {
+                    service.leaf_sessions().used(&leaf_id);
+                    true
+                }
+                Ok(_) | Err(_) => {
+                    debug!("Leaf MCP '{}' refused its kept session", leaf_id);
+                    service.leaf_sessions().forget(&leaf_id);
+                    false
+                }
+            }
+        });
+    }
+    let mut resumed = 0;
+    while let Some(pinged) = pings.join_next().await {
+        resumed += usize::from(pinged.unwrap_or(false));
+    }
+    resumed
+}
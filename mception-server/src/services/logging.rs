@@ -0,0 +1,84 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::RwLock;
+use tracing_subscriber::{EnvFilter, Registry, reload};
+use uuid::Uuid;
+
+/// Directives the server starts with and reverts to
+pub const DEFAULT_DIRECTIVES: &str = "info";
+
+/// Current log filter of the running server
+#[derive(Debug, Clone, Serialize)]
+pub struct LogSettings {
+    /// Active `EnvFilter` directives
+    pub directives: String,
+    pub default_directives: String,
+    /// When the directives automatically revert to the defaults
+    pub revert_at: Option<DateTime<Utc>>,
+    /// Identifies the change, so a later change isn't reverted by an earlier timer
+    pub session_id: Option<String>,
+}
+
+/// Runtime control over the tracing filter, backed by a reload layer
+pub struct LogControl {
+    handle: reload::Handle<EnvFilter, Registry>,
+    settings: RwLock<LogSettings>,
+}
+
+impl LogControl {
+    pub fn new(handle: reload::Handle<EnvFilter, Registry>) -> Self {
+        Self {
+            handle,
+            settings: RwLock::new(LogSettings {
+                directives: DEFAULT_DIRECTIVES.to_string(),
+                default_directives: DEFAULT_DIRECTIVES.to_string(),
+                revert_at: None,
+                session_id: None,
+            }),
+        }
+    }
+
+    pub async fn settings(&self) -> LogSettings {
+        self.settings.read().await.clone()
+    }
+
+    /// Replace the active filter directives
+    pub async fn set(
+        &self,
+        directives: &str,
+        revert_at: Option<DateTime<Utc>>,
+    ) -> Result<LogSettings, String> {
+        let filter = EnvFilter::try_new(directives).map_err(|e| e.to_string())?;
+        let mut settings = self.settings.write().await;
+        self.handle.reload(filter).map_err(|e| e.to_string())?;
+
+        settings.directives = directives.to_string();
+        settings.revert_at = revert_at;
+        settings.session_id = Some(Uuid::new_v4().to_string());
+        Ok(settings.clone())
+    }
+
+    /// Revert to the default directives if `session_id` is still the active change.
+    /// Returns whether the filter was reverted.
+    pub async fn revert(&self, session_id: &str) -> Result<bool, String> {
+        let mut settings = self.settings.write().await;
+        if settings.session_id.as_deref() != Some(session_id) {
+            return Ok(false);
+        }
+        let filter = EnvFilter::try_new(DEFAULT_DIRECTIVES).map_err(|e| e.to_string())?;
+        self.handle.reload(filter).map_err(|e| e.to_string())?;
+
+        settings.directives = DEFAULT_DIRECTIVES.to_string();
+        settings.revert_at = None;
+        settings.session_id = None;
+        Ok(true)
+    }
+}
+
+/// Build filter directives from a level and optional extra per-target directives
+pub fn directives(level: &str, filter: Option<&str>) -> String {
+    match filter {
+        Some(filter) if !filter.trim().is_empty() => format!("{},{}", level.trim(), filter.trim()),
+        _ => level.trim().to_string(),
+    }
+}
@@ -1,4 +1,52 @@
+pub mod access_log;
+pub mod agent_changes;
+pub mod api_versions;
+pub mod audit_buffer;
+pub mod audit_policy;
+pub mod auth;
+pub mod authorization;
+pub mod availability;
+pub mod builtin_mcp;
+pub mod bulk;
+pub mod circuit_breaker;
+pub mod concurrency;
 pub mod config;
+pub mod config_events;
+pub mod config_limits;
+pub mod confirmation;
+pub mod connections;
+pub mod consistency;
+pub mod cycles;
+pub mod deadline;
+pub mod debug_capture;
+pub mod discovery;
+pub mod expiry;
+pub mod fault_injection;
+pub mod forwarding_error;
+pub mod health;
+pub mod history;
+pub mod https;
+pub mod idempotency;
+pub mod ids;
+pub mod internals;
+pub mod json_schema;
+pub mod leaf_sessions;
+pub mod logging;
+pub mod rate_limit;
+pub mod registration_policy;
+pub mod replicas;
+pub mod retry;
+pub mod reverse_requests;
+pub mod revision;
+pub mod safety;
+pub mod sandbox;
+pub mod shutdown;
+pub mod sse;
+pub mod stdio;
+pub mod sync;
+pub mod tool_shaping;
+pub mod tools;
 
 // Re-export the main service
 pub use config::ConfigService;
+pub use connections::ConnectionService;
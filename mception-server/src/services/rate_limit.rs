@@ -0,0 +1,283 @@
+use crate::core::{AuditTarget, error_body};
+use crate::services::ConfigService;
+use crate::services::forwarding_error::{ForwardingError, ForwardingErrorCode};
+use crate::services::{auth, authorization};
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Json, Response};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Key in an agent's `config` overriding the requests per minute it may
+/// make, `0` for no limit
+pub const RATE_LIMIT_KEY: &str = "rate_limit";
+
+/// How long a caller has to stay within its limit before it is audited again
+/// for exceeding it
+const QUIET_PERIOD: Duration = Duration::from_secs(60);
+
+/// Buckets kept at most, the least recently used is forgotten beyond it
+const MAX_BUCKETS: usize = 1024;
+
+/// The requests per minute set in an agent's `config`, if any
+pub fn configured(config: &Value) -> Result<Option<u32>, String> {
+    let Some(value) = config.get(RATE_LIMIT_KEY) else {
+        return Ok(None);
+    };
+    value
+        .as_u64()
+        .and_then(|limit| u32::try_from(limit).ok())
+        .map(Some)
+        .ok_or_else(|| {
+            format!(
+                "config.{} must be a number of requests per minute, 0 for no limit",
+                RATE_LIMIT_KEY
+            )
+        })
+}
+
+/// Who a request is counted against
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Caller {
+    Agent(String),
+    Client(String),
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Requests per minute the bucket was last filled at
+    limit: u32,
+    tokens: f64,
+    refilled_at: Instant,
+    /// Last rejected request, `None` once the caller was within its limit
+    /// for [`QUIET_PERIOD`]
+    rejected_at: Option<Instant>,
+}
+
+/// Why a request was rejected
+struct Rejection {
+    retry_after: Duration,
+    /// The first rejection after a quiet period, which is audited
+    first: bool,
+}
+
+/// Token buckets of the callers of the agent and leaf MCP routes. Each
+/// allows a minute's worth of requests at once and refills evenly.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    /// Requests per minute of callers without their own limit, unlimited without
+    per_minute: Option<u32>,
+    buckets: Mutex<HashMap<Caller, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: Option<u32>) -> Self {
+        Self {
+            per_minute: per_minute.filter(|limit| *limit > 0),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn per_minute(&self) -> Option<u32> {
+        self.per_minute
+    }
+
+    fn take(&self, caller: &Caller, limit: u32) -> Result<(), Rejection> {
+        let now = Instant::now();
+        let per_second = f64::from(limit) / 60.0;
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(caller) {
+            let least_recent = buckets
+                .iter()
+                .min_by_key(|(_, bucket)| bucket.refilled_at)
+                .map(|(caller, _)| caller.clone());
+            if let Some(least_recent) = least_recent {
+                buckets.remove(&least_recent);
+            }
+        }
+        let bucket = buckets.entry(caller.clone()).or_insert_with(|| Bucket {
+            limit,
+            tokens: f64::from(limit),
+            refilled_at: now,
+            rejected_at: None,
+        });
+        if bucket.limit != limit {
+            bucket.limit = limit;
+            bucket.tokens = bucket.tokens.min(f64::from(limit));
+        }
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(f64::from(limit));
+        bucket.refilled_at = now;
+        if bucket
+            .rejected_at
+            .is_some_and(|rejected_at| now.duration_since(rejected_at) >= QUIET_PERIOD)
+        {
+            bucket.rejected_at = None;
+        }
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        let first = bucket.rejected_at.is_none();
+        bucket.rejected_at = Some(now);
+        Err(Rejection {
+            retry_after: Duration::from_secs_f64((1.0 - bucket.tokens) / per_second),
+            first,
+        })
+    }
+}
+
+/// Middleware limiting the requests to the agent routes per agent, named by
+/// the path. Requests for agents that don't exist or without the agent's
+/// token are counted per client address.
+pub async fn limit_agent_requests(
+    State(service): State<Arc<ConfigService>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let agent_id = first_segment(&request);
+    let authorized = {
+        let config = service.get_configuration().await;
+        config
+            .agents
+            .get(&agent_id)
+            .is_some_and(|agent| auth::agent_authorized(agent, request.headers()))
+    };
+    let caller = if authorized {
+        Caller::Agent(agent_id)
+    } else {
+        client(&request)
+    };
+    limit(&service, caller, request, next).await
+}
+
+/// Middleware limiting the requests to the leaf MCP routes per calling agent,
+/// or per client address for calls that don't name an existing agent they
+/// may act as
+pub async fn limit_leaf_requests(
+    State(service): State<Arc<ConfigService>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let agent_id = {
+        let config = service.get_configuration().await;
+        match auth::authenticated_agent(&config, request.headers()) {
+            Ok(Some(agent_id)) => Some(agent_id.to_string()),
+            // Agents aren't authenticated, only existing ones may be named
+            Ok(None) => authorization::caller(request.headers())
+                .filter(|agent_id| config.agents.contains_key(*agent_id))
+                .map(str::to_string),
+            Err(_) => None,
+        }
+    };
+    let caller = match agent_id {
+        Some(agent_id) => Caller::Agent(agent_id),
+        None => client(&request),
+    };
+    limit(&service, caller, request, next).await
+}
+
+/// The address a request came from
+fn client(request: &Request) -> Caller {
+    Caller::Client(
+        request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map_or("unknown".to_string(), |info| info.0.ip().to_string()),
+    )
+}
+
+async fn limit(service: &ConfigService, caller: Caller, request: Request, next: Next) -> Response {
+    let limiter = service.rate_limiter();
+    let limit = match &caller {
+        Caller::Agent(agent_id) => {
+            let config = service.get_configuration().await;
+            config
+                .agents
+                .get(agent_id)
+                .and_then(|agent| configured(&agent.config).ok().flatten())
+                .or(limiter.per_minute)
+        }
+        Caller::Client(_) => limiter.per_minute,
+    };
+    let Some(limit) = limit.filter(|limit| *limit > 0) else {
+        return next.run(request).await;
+    };
+    let Err(rejection) = limiter.take(&caller, limit) else {
+        return next.run(request).await;
+    };
+
+    let path = request.uri().path().to_string();
+    if rejection.first {
+        match &caller {
+            Caller::Agent(agent_id) => warn!(
+                "Agent '{}' exceeded {} requests per minute, rejecting its requests",
+                agent_id, limit
+            ),
+            Caller::Client(address) => warn!(
+                "Client {} exceeded {} requests per minute, rejecting its requests",
+                address, limit
+            ),
+        }
+        let (target, details) = match &caller {
+            Caller::Agent(agent_id) => (
+                AuditTarget::Agent {
+                    id: agent_id.clone(),
+                },
+                serde_json::json!({ "requests_per_minute": limit, "path": path }),
+            ),
+            Caller::Client(address) => (
+                AuditTarget::Server,
+                serde_json::json!({
+                    "requests_per_minute": limit,
+                    "path": path,
+                    "client": address,
+                }),
+            ),
+        };
+        service.audit_rate_limited(target, details).await;
+    }
+
+    let retry_after = (rejection.retry_after.as_secs_f64().ceil() as u64).max(1);
+    let detail = format!(
+        "More than {} requests per minute, retry in {}s",
+        limit, retry_after
+    );
+    let mut response = if path.ends_with("/forwarding") || path.ends_with("/forwarding_ws") {
+        ForwardingError::new(
+            ForwardingErrorCode::ProxyOverloaded,
+            &first_segment(&request),
+            detail,
+        )
+        .with_status(StatusCode::TOO_MANY_REQUESTS)
+        .into_response()
+    } else {
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(error_body("rate_limited", detail)),
+        )
+            .into_response()
+    };
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, retry_after.into());
+    response
+}
+
+/// The agent or leaf MCP id in the path, below the router's prefix
+fn first_segment(request: &Request) -> String {
+    request
+        .uri()
+        .path()
+        .trim_start_matches('/')
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
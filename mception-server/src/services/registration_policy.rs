@@ -0,0 +1,146 @@
+use crate::core::{LeafMcpConfig, McpTransport, RegistrationPolicy, ServerConfig};
+use serde::Serialize;
+
+/// Rule of a [`RegistrationPolicy`] a leaf MCP can violate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyRule {
+    StdioCommands,
+    HttpsDomains,
+}
+
+impl PolicyRule {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PolicyRule::StdioCommands => "stdio_commands",
+            PolicyRule::HttpsDomains => "https_domains",
+        }
+    }
+}
+
+/// Why a leaf MCP is not allowed by a registration policy
+#[derive(Debug, Clone, Serialize)]
+pub struct Violation {
+    pub rule: PolicyRule,
+    pub message: String,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rule `{}`: {}", self.rule.as_str(), self.message)
+    }
+}
+
+/// A registered leaf MCP a policy would not allow
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportEntry {
+    pub leaf_mcp_id: String,
+    #[serde(flatten)]
+    pub violation: Violation,
+}
+
+/// Check a leaf MCP against a policy
+pub fn check(policy: &RegistrationPolicy, leaf: &LeafMcpConfig) -> Result<(), Violation> {
+    match &leaf.transport {
+        McpTransport::Builtin { .. } => Ok(()),
+        McpTransport::Stdio { command, .. } => {
+            if policy
+                .stdio_commands
+                .iter()
+                .any(|allowed| command_matches(allowed, command))
+            {
+                return Ok(());
+            }
+            Err(Violation {
+                rule: PolicyRule::StdioCommands,
+                message: format!(
+                    "command '{}' is not allowed{}",
+                    command,
+                    allowed_list(&policy.stdio_commands)
+                ),
+            })
+        }
+        McpTransport::Https { url, .. } | McpTransport::Sse { url, .. } => {
+            let host = reqwest::Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+                .unwrap_or_default();
+            if policy
+                .https_domains
+                .iter()
+                .any(|allowed| domain_matches(allowed, &host))
+            {
+                return Ok(());
+            }
+            Err(Violation {
+                rule: PolicyRule::HttpsDomains,
+                message: format!(
+                    "host '{}' is not allowed{}",
+                    host,
+                    allowed_list(&policy.https_domains)
+                ),
+            })
+        }
+    }
+}
+
+/// The registered leaf MCPs `policy` would not allow, e.g. before enabling it
+pub fn report(policy: &RegistrationPolicy, config: &ServerConfig) -> Vec<ReportEntry> {
+    config
+        .leaf_mcps
+        .iter()
+        .filter_map(|(id, leaf)| {
+            check(policy, leaf).err().map(|violation| ReportEntry {
+                leaf_mcp_id: id.clone(),
+                violation,
+            })
+        })
+        .collect()
+}
+
+/// Check the patterns of a policy before it is stored
+pub fn validate(policy: &RegistrationPolicy) -> Result<(), String> {
+    for pattern in &policy.stdio_commands {
+        if pattern.is_empty() || pattern.trim_end_matches('*').contains('*') {
+            return Err(format!(
+                "stdio command '{}' must be a command or a prefix ending in a single `*`",
+                pattern
+            ));
+        }
+    }
+    for pattern in &policy.https_domains {
+        let domain = pattern.strip_prefix("*.").unwrap_or(pattern);
+        if domain.is_empty() || domain.contains(['*', '/', ':']) {
+            return Err(format!(
+                "HTTPS domain '{}' must be a host name, optionally starting with `*.`",
+                pattern
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn command_matches(allowed: &str, command: &str) -> bool {
+    match allowed.strip_suffix('*') {
+        Some(prefix) => command.starts_with(prefix),
+        None => allowed == command,
+    }
+}
+
+fn domain_matches(allowed: &str, host: &str) -> bool {
+    let allowed = allowed.to_ascii_lowercase();
+    match allowed.strip_prefix("*.") {
+        Some(parent) => host
+            .strip_suffix(parent)
+            .is_some_and(|sub| sub.ends_with('.') && sub.len() > 1),
+        None => allowed == host,
+    }
+}
+
+fn allowed_list(allowed: &[String]) -> String {
+    if allowed.is_empty() {
+        " (the policy allows none)".to_string()
+    } else {
+        format!(", allowed are {}", allowed.join(", "))
+    }
+}
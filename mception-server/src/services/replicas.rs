@@ -0,0 +1,119 @@
+use crate::core::ServerConfig;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use tokio::sync::RwLock;
+
+/// Response header naming the replica a forwarded call went to, when it's
+/// another one than requested
+pub const REPLICA_HEADER: &str = "x-mception-replica";
+
+/// How long a leaf MCP counts as unhealthy after a forward to it failed,
+/// unless a forward succeeds before
+pub const UNHEALTHY_FOR: Duration = Duration::seconds(30);
+
+/// Health of leaf MCPs as observed while forwarding to them. A leaf MCP is
+/// unhealthy for [`UNHEALTHY_FOR`] after it couldn't be reached or timed out.
+#[derive(Debug, Default)]
+pub struct ReplicaHealth {
+    /// Time of the last failed forward per leaf MCP
+    failures: RwLock<HashMap<String, DateTime<Utc>>>,
+}
+
+impl ReplicaHealth {
+    pub async fn record_success(&self, leaf_id: &str) {
+        // Most forwards succeed, so only take the write lock when needed
+        if self.failures.read().await.contains_key(leaf_id) {
+            self.failures.write().await.remove(leaf_id);
+        }
+    }
+
+    pub async fn record_failure(&self, leaf_id: &str) {
+        self.failures
+            .write()
+            .await
+            .insert(leaf_id.to_string(), Utc::now());
+    }
+
+    /// Leaf MCPs that are currently unhealthy, with the time of the failure
+    pub async fn unhealthy(&self) -> HashMap<String, DateTime<Utc>> {
+        let cutoff = Utc::now() - UNHEALTHY_FOR;
+        self.failures
+            .read()
+            .await
+            .iter()
+            .filter(|(_, failed_at)| **failed_at > cutoff)
+            .map(|(id, failed_at)| (id.clone(), *failed_at))
+            .collect()
+    }
+}
+
+/// A leaf MCP a replica is picked from
+#[derive(Debug, Clone, Copy)]
+pub struct Candidate<'a> {
+    pub id: &'a str,
+    pub region: Option<&'a str>,
+    pub healthy: bool,
+}
+
+/// Pick the replica to use: a healthy one in `region`, else any healthy one,
+/// else one in `region`, else any. Among equals the `preferred` replica wins,
+/// then the first one.
+pub fn select<'a>(
+    candidates: &[Candidate<'a>],
+    region: Option<&str>,
+    preferred: Option<&str>,
+) -> Option<&'a str> {
+    candidates
+        .iter()
+        .min_by_key(|candidate| {
+            let local = region.is_some() && candidate.region == region;
+            (!candidate.healthy, !local, Some(candidate.id) != preferred)
+        })
+        .map(|candidate| candidate.id)
+}
+
+/// A leaf MCP in a replica group, as shown to administrators
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplicaStatus {
+    pub id: String,
+    pub name: Option<String>,
+    pub region: Option<String>,
+    pub healthy: bool,
+    /// Last failed forward while unhealthy
+    pub failed_at: Option<DateTime<Utc>>,
+}
+
+/// Leaf MCPs sharing a `replica_group`
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplicaGroup {
+    pub name: String,
+    pub replicas: Vec<ReplicaStatus>,
+}
+
+/// Every replica group of the configuration by name, replicas by id
+pub fn groups(
+    config: &ServerConfig,
+    unhealthy: &HashMap<String, DateTime<Utc>>,
+) -> Vec<ReplicaGroup> {
+    let mut groups: BTreeMap<&str, Vec<ReplicaStatus>> = BTreeMap::new();
+    for (id, leaf) in &config.leaf_mcps {
+        if let Some(group) = &leaf.replica_group {
+            let failed_at = unhealthy.get(id).copied();
+            groups.entry(group).or_default().push(ReplicaStatus {
+                id: id.clone(),
+                name: leaf.name.clone(),
+                region: leaf.region.clone(),
+                healthy: failed_at.is_none(),
+                failed_at,
+            });
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(name, replicas)| ReplicaGroup {
+            name: name.to_string(),
+            replicas,
+        })
+        .collect()
+}
@@ -0,0 +1,105 @@
+use crate::core::{RetryOn, RetryPolicy};
+use crate::services::forwarding_error::{ForwardingError, ForwardingErrorCode};
+use std::future::Future;
+use std::time::Duration;
+use tracing::warn;
+
+/// Methods that can be sent again without changing anything on the leaf MCP
+pub const IDEMPOTENT_METHODS: &[&str] = &[
+    "ping",
+    "tools/list",
+    "resources/list",
+    "resources/templates/list",
+    "resources/read",
+    "prompts/list",
+    "prompts/get",
+    "completion/complete",
+];
+
+/// Upper bound for [`RetryPolicy::max_attempts`]
+pub const MAX_ATTEMPTS: u32 = 10;
+
+/// Upper bound for [`RetryPolicy::backoff_ms`]
+pub const MAX_BACKOFF_MS: u64 = 60_000;
+
+/// Check the bounds of a retry policy
+pub fn validate(policy: &RetryPolicy) -> Result<(), String> {
+    if !(1..=MAX_ATTEMPTS).contains(&policy.max_attempts) {
+        return Err(format!(
+            "retry.max_attempts must be between 1 and {}",
+            MAX_ATTEMPTS
+        ));
+    }
+    if policy.backoff_ms > MAX_BACKOFF_MS {
+        return Err(format!(
+            "retry.backoff_ms must be at most {}",
+            MAX_BACKOFF_MS
+        ));
+    }
+    Ok(())
+}
+
+/// Whether a request calling `method` may be retried under `policy`:
+/// idempotent methods, and `tools/call` if the policy opts in
+pub fn retries_method(policy: &RetryPolicy, method: Option<&str>) -> bool {
+    match method {
+        Some("tools/call") => policy.tool_calls,
+        Some(method) => IDEMPOTENT_METHODS.contains(&method),
+        None => false,
+    }
+}
+
+/// Whether `policy` retries a forward that failed with `error`
+pub fn retries_error(policy: &RetryPolicy, error: &ForwardingError) -> bool {
+    let class = match error.code {
+        ForwardingErrorCode::LeafUnreachable => RetryOn::LeafUnreachable,
+        ForwardingErrorCode::LeafTimeout => RetryOn::LeafTimeout,
+        _ => return false,
+    };
+    policy.retry_on.contains(&class)
+}
+
+/// Wait before the retry following attempt number `attempt`, counted from 1
+pub fn backoff(policy: &RetryPolicy, attempt: u32) -> Duration {
+    let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+    Duration::from_millis(policy.backoff_ms.saturating_mul(factor).min(MAX_BACKOFF_MS))
+}
+
+/// Forward with `attempt` until it succeeds, fails in a way `policy` doesn't
+/// retry, or the policy's attempts are used up. Without a policy, or for a
+/// `method` it doesn't retry, `attempt` runs once. The caller's deadline
+/// bounds all attempts together.
+pub async fn retried<T, F, Fut>(
+    policy: Option<&RetryPolicy>,
+    leaf_mcp_id: &str,
+    method: Option<&str>,
+    mut attempt: F,
+) -> Result<T, ForwardingError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, ForwardingError>>,
+{
+    let Some(policy) = policy.filter(|policy| retries_method(policy, method)) else {
+        return attempt().await;
+    };
+    let mut attempts = 1;
+    loop {
+        match attempt().await {
+            Err(e) if attempts < policy.max_attempts && retries_error(policy, &e) => {
+                let delay = backoff(policy, attempts);
+                warn!(
+                    "Retrying '{}' to leaf MCP '{}' in {} ms after attempt {} of {} failed: {}",
+                    method.unwrap_or_default(),
+                    leaf_mcp_id,
+                    delay.as_millis(),
+                    attempts,
+                    policy.max_attempts,
+                    e.detail
+                );
+                tokio::time::sleep(delay).await;
+                attempts += 1;
+            }
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use crate::core::ReverseRequestPolicy;
+use serde_json::Value;
+use std::future::Future;
+use std::time::Duration;
+
+/// JSON-RPC error code of a method the client doesn't implement
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+/// How long a relayed request may wait for the agent's answer
+pub const RELAY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A JSON-RPC message received from a leaf MCP
+#[derive(Debug, Clone, PartialEq)]
+pub enum LeafMessage {
+    /// Answer to a request forwarded to the leaf
+    Response,
+    /// Needs no answer
+    Notification,
+    /// Server-initiated request, e.g. `sampling/createMessage` or `roots/list`.
+    /// The leaf waits for its answer, so one must always be sent.
+    Request { id: Value, method: String },
+    /// Not a JSON-RPC message
+    Invalid,
+}
+
+pub fn classify(message: &Value) -> LeafMessage {
+    match (
+        message.get("method").and_then(Value::as_str),
+        message.get("id"),
+    ) {
+        (Some(method), Some(id)) if !id.is_null() => LeafMessage::Request {
+            id: id.clone(),
+            method: method.to_string(),
+        },
+        (Some(_), _) => LeafMessage::Notification,
+        (None, Some(_)) if message.get("result").is_some() || message.get("error").is_some() => {
+            LeafMessage::Response
+        }
+        _ => LeafMessage::Invalid,
+    }
+}
+
+/// Client capability an agent must declare to be relayed a request
+pub fn required_capability(method: &str) -> Option<&'static str> {
+    match method.split('/').next() {
+        Some("sampling") => Some("sampling"),
+        Some("roots") => Some("roots"),
+        Some("elicitation") => Some("elicitation"),
+        _ => None,
+    }
+}
+
+/// What to do with a server-initiated request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Handling {
+    Relay,
+    /// Answer with [`rejection`] for this reason
+    Reject(String),
+}
+
+/// Decide how to answer a server-initiated request. `agent_capabilities` are
+/// the client capabilities declared by the agent the forwarded call came from,
+/// `None` if it is not connected.
+pub fn handling(
+    policy: ReverseRequestPolicy,
+    method: &str,
+    agent_capabilities: Option<&Value>,
+) -> Handling {
+    if policy == ReverseRequestPolicy::Reject {
+        return Handling::Reject("server-initiated requests are not relayed".to_string());
+    }
+    let Some(capabilities) = agent_capabilities else {
+        return Handling::Reject("the agent is not connected".to_string());
+    };
+    match required_capability(method) {
+        Some(capability) if capabilities.get(capability).is_some() => Handling::Relay,
+        Some(capability) => Handling::Reject(format!(
+            "the agent did not declare the '{}' capability",
+            capability
+        )),
+        None => Handling::Reject(format!("'{}' is not a known client method", method)),
+    }
+}
+
+/// JSON-RPC error answering a server-initiated request that is not handled
+pub fn rejection(id: &Value, method: &str, reason: &str) -> Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {
+            "code": METHOD_NOT_FOUND,
+            "message": format!("'{}' is not supported by this client: {}", method, reason)
+        }
+    })
+}
+
+/// Wait for the agent's answer to a relayed request. Gives the rejection
+/// instead if the agent disconnects (`None`) or doesn't answer in time, so the
+/// leaf is never left waiting. Each relayed request is awaited on its own,
+/// other traffic of the leaf is not blocked meanwhile.
+pub async fn await_relayed(
+    id: &Value,
+    method: &str,
+    answer: impl Future<Output = Option<Value>>,
+    timeout: Duration,
+) -> Value {
+    match tokio::time::timeout(timeout, answer).await {
+        Ok(Some(answer)) => answer,
+        Ok(None) => rejection(id, method, "the agent disconnected"),
+        Err(_) => rejection(
+            id,
+            method,
+            &format!("the agent did not answer within {:?}", timeout),
+        ),
+    }
+}
@@ -0,0 +1,68 @@
+use crate::core::ServerConfig;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+/// How long a revision watch waits for a change by default
+pub const DEFAULT_WATCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Upper bound of a revision watch's timeout
+pub const MAX_WATCH_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Fields of agents describing their connection rather than their configuration
+const AGENT_RUNTIME_FIELDS: &[&str] = &["is_connected", "last_seen"];
+
+/// Where in the history a leaf MCP or agent was last changed, like `metadata`
+const ENTITY_REVISION_FIELD: &str = "revision";
+
+/// Where the running configuration is at, for comparing it with a desired state
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigRevision {
+    pub revision: u64,
+    pub last_modified: DateTime<Utc>,
+    /// `sha256:` and the hex digest of [`canonical_json`]
+    pub hash: String,
+}
+
+impl ConfigRevision {
+    pub fn of(config: &ServerConfig) -> Self {
+        Self {
+            revision: config.metadata.revision,
+            last_modified: config.metadata.last_modified,
+            hash: hash(config),
+        }
+    }
+}
+
+/// The configuration as compact JSON with sorted keys, without `metadata`,
+/// the revisions of leaf MCPs and agents and the connection state of agents.
+/// Equal configurations give the same text, whatever server wrote them.
+pub fn canonical_json(config: &ServerConfig) -> String {
+    let mut value = serde_json::to_value(config).unwrap_or_default();
+    if let Some(object) = value.as_object_mut() {
+        object.remove("metadata");
+    }
+    if let Some(leaf_mcps) = value.get_mut("leaf_mcps").and_then(Value::as_object_mut) {
+        for leaf in leaf_mcps.values_mut().filter_map(Value::as_object_mut) {
+            leaf.remove(ENTITY_REVISION_FIELD);
+        }
+    }
+    if let Some(agents) = value.get_mut("agents").and_then(Value::as_object_mut) {
+        for agent in agents.values_mut().filter_map(Value::as_object_mut) {
+            for field in AGENT_RUNTIME_FIELDS.iter().chain([&ENTITY_REVISION_FIELD]) {
+                agent.remove(*field);
+            }
+        }
+    }
+    // Objects are sorted maps, so keys serialize in order
+    value.to_string()
+}
+
+/// Digest of the configuration, see [`canonical_json`]
+pub fn hash(config: &ServerConfig) -> String {
+    let digest = Sha256::digest(canonical_json(config).as_bytes());
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256:{}", hex)
+}
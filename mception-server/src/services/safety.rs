@@ -0,0 +1,161 @@
+use crate::core::{BUNDLE_PREFIX, ServerConfig};
+use crate::services::access_log::CallerUsage;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// How far back usage counts for a delete's safety
+pub const USAGE_WINDOW: Duration = Duration::days(30);
+
+/// A leaf MCP used this recently is in active use
+pub const RECENT_USE: Duration = Duration::days(7);
+
+/// A leaf MCP called this often in the window is in active use
+pub const ACTIVE_CALLS: u64 = 10;
+
+/// How much deleting a leaf MCP would disrupt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteRisk {
+    /// Not called in the window
+    Unused,
+    /// Called a few times, none recently
+    Low,
+    Active,
+    /// No access log is enabled, so usage isn't known
+    Unknown,
+}
+
+impl DeleteRisk {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeleteRisk::Unused => "unused",
+            DeleteRisk::Low => "low",
+            DeleteRisk::Active => "active",
+            DeleteRisk::Unknown => "unknown",
+        }
+    }
+}
+
+/// An agent allowed to use a leaf MCP or seen calling it
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentUsage {
+    pub agent_id: String,
+    /// Entries of the agent's `allowed_mcp_ids` granting access, directly or
+    /// through a bundle. Empty for an agent that called it without being
+    /// allowed to any more.
+    pub granted_by: Vec<String>,
+    /// Calls in the window, `None` without an access log
+    pub calls: Option<u64>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Who references a leaf MCP and who used it lately, answer of
+/// `GET /admin/leaf/<id>/safety`
+#[derive(Debug, Clone, Serialize)]
+pub struct LeafMcpSafety {
+    pub leaf_mcp_id: String,
+    pub risk: DeleteRisk,
+    pub access_log_enabled: bool,
+    /// Start of the window usage is counted in
+    pub since: DateTime<Utc>,
+    /// Calls in the window, `None` without an access log
+    pub total_calls: Option<u64>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// Calls of callers that didn't identify as an agent
+    pub unidentified_calls: Option<u64>,
+    /// By id
+    pub agents: Vec<AgentUsage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Judge deleting `mcp_id` from the agents referencing it and its calls since
+/// `since`, `usage` being `None` without an access log
+pub fn assess(
+    config: &ServerConfig,
+    mcp_id: &str,
+    usage: Option<BTreeMap<Option<String>, CallerUsage>>,
+    since: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> LeafMcpSafety {
+    let mut agents: BTreeMap<String, AgentUsage> = BTreeMap::new();
+    for (agent_id, agent) in &config.agents {
+        let granted_by: Vec<String> = agent
+            .allowed_mcp_ids
+            .iter()
+            .filter(|allowed| match allowed.strip_prefix(BUNDLE_PREFIX) {
+                Some(bundle) => config
+                    .bundles
+                    .get(bundle)
+                    .is_some_and(|bundle| bundle.members.iter().any(|m| m == mcp_id)),
+                None => *allowed == mcp_id,
+            })
+            .cloned()
+            .collect();
+        if !granted_by.is_empty() {
+            agents.insert(
+                agent_id.clone(),
+                AgentUsage {
+                    agent_id: agent_id.clone(),
+                    granted_by,
+                    calls: usage.as_ref().map(|_| 0),
+                    last_used_at: None,
+                },
+            );
+        }
+    }
+
+    let Some(usage) = usage else {
+        return LeafMcpSafety {
+            leaf_mcp_id: mcp_id.to_string(),
+            risk: DeleteRisk::Unknown,
+            access_log_enabled: false,
+            since,
+            total_calls: None,
+            last_used_at: None,
+            unidentified_calls: None,
+            agents: agents.into_values().collect(),
+            note: Some(
+                "No access log is enabled (--access-log), so whether the leaf MCP is used is unknown"
+                    .to_string(),
+            ),
+        };
+    };
+
+    let mut unidentified = CallerUsage::default();
+    for (caller, used) in &usage {
+        let Some(agent_id) = caller else {
+            unidentified = used.clone();
+            continue;
+        };
+        let agent = agents
+            .entry(agent_id.clone())
+            .or_insert_with(|| AgentUsage {
+                agent_id: agent_id.clone(),
+                granted_by: Vec::new(),
+                calls: Some(0),
+                last_used_at: None,
+            });
+        agent.calls = Some(used.calls);
+        agent.last_used_at = used.last_used_at;
+    }
+    let total_calls: u64 = usage.values().map(|used| used.calls).sum();
+    let last_used_at = usage.values().filter_map(|used| used.last_used_at).max();
+    let risk = match last_used_at {
+        None => DeleteRisk::Unused,
+        Some(at) if now - at < RECENT_USE || total_calls >= ACTIVE_CALLS => DeleteRisk::Active,
+        Some(_) => DeleteRisk::Low,
+    };
+    LeafMcpSafety {
+        leaf_mcp_id: mcp_id.to_string(),
+        risk,
+        access_log_enabled: true,
+        since,
+        total_calls: Some(total_calls),
+        last_used_at,
+        unidentified_calls: Some(unidentified.calls),
+        agents: agents.into_values().collect(),
+        note: None,
+    }
+}
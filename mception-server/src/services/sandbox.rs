@@ -0,0 +1,313 @@
+use crate::core::{MceptionResult, StdioSandbox, ValidationError};
+use serde::Serialize;
+use std::path::Path;
+use tokio::process::Command;
+
+/// Delegated cgroup v2 subtree used for per-process CPU and memory limits.
+/// The operator creates it and hands it to the server user; when it is missing
+/// memory limits fall back to rlimits and CPU shares are unavailable.
+const CGROUP_ROOT: &str = "/sys/fs/cgroup/mception-server";
+
+/// Environment variable carrying `allowed_paths` to wrapper scripts
+pub const ALLOWED_PATHS_ENV: &str = "MCEPTION_ALLOWED_PATHS";
+
+/// A single restriction applied to a spawned stdio process
+#[derive(Debug, Clone, Serialize)]
+pub struct SandboxRestriction {
+    pub restriction: &'static str,
+    pub value: String,
+    pub mechanism: &'static str,
+}
+
+/// Check that the sandbox options can be honored on this platform
+pub fn validate(sandbox: &StdioSandbox) -> Result<(), ValidationError> {
+    if let Some(dir) = &sandbox.working_dir
+        && !Path::new(dir).is_dir()
+    {
+        return Err(ValidationError::InvalidFormat(format!(
+            "working_dir '{}' does not exist or is not a directory",
+            dir
+        )));
+    }
+
+    for path in &sandbox.allowed_paths {
+        if !Path::new(path).is_absolute() {
+            return Err(ValidationError::InvalidFormat(format!(
+                "allowed_paths entry '{}' must be an absolute path",
+                path
+            )));
+        }
+    }
+
+    if let Some(user) = &sandbox.run_as_user {
+        platform::lookup_user(user)?;
+        if !platform::is_privileged() {
+            return Err(ValidationError::InvalidFormat(format!(
+                "run_as_user '{}' requires the server to run as root",
+                user
+            )));
+        }
+    }
+
+    if sandbox.max_memory_mb == Some(0) {
+        return Err(ValidationError::ValueOutOfRange(
+            "max_memory_mb must be greater than 0".to_string(),
+        ));
+    }
+    if sandbox.max_memory_mb.is_some() && !cfg!(unix) {
+        return Err(ValidationError::InvalidFormat(
+            "max_memory_mb is only supported on unix".to_string(),
+        ));
+    }
+
+    if let Some(shares) = sandbox.cpu_shares {
+        if !(2..=262144).contains(&shares) {
+            return Err(ValidationError::ValueOutOfRange(
+                "cpu_shares must be between 2 and 262144".to_string(),
+            ));
+        }
+        if !cgroup_supports("cpu") {
+            return Err(ValidationError::InvalidFormat(format!(
+                "cpu_shares requires a delegated cgroup v2 hierarchy with the cpu controller at {}",
+                CGROUP_ROOT
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Describe the restrictions that `apply` puts in place, without spawning anything
+pub fn describe(sandbox: &StdioSandbox) -> Vec<SandboxRestriction> {
+    let mut restrictions = Vec::new();
+
+    if let Some(dir) = &sandbox.working_dir {
+        restrictions.push(SandboxRestriction {
+            restriction: "working_dir",
+            value: dir.clone(),
+            mechanism: "chdir",
+        });
+    }
+    if let Some(user) = &sandbox.run_as_user {
+        restrictions.push(SandboxRestriction {
+            restriction: "run_as_user",
+            value: user.clone(),
+            mechanism: "setuid",
+        });
+    }
+    if sandbox.clear_env {
+        restrictions.push(SandboxRestriction {
+            restriction: "clear_env",
+            value: "true".to_string(),
+            mechanism: "env_clear",
+        });
+    }
+    if let Some(memory) = sandbox.max_memory_mb {
+        restrictions.push(SandboxRestriction {
+            restriction: "max_memory_mb",
+            value: memory.to_string(),
+            mechanism: if cgroup_supports("memory") {
+                "cgroup memory.max"
+            } else {
+                "rlimit RLIMIT_AS"
+            },
+        });
+    }
+    if let Some(shares) = sandbox.cpu_shares {
+        restrictions.push(SandboxRestriction {
+            restriction: "cpu_shares",
+            value: shares.to_string(),
+            mechanism: "cgroup cpu.weight",
+        });
+    }
+    if !sandbox.allowed_paths.is_empty() {
+        restrictions.push(SandboxRestriction {
+            restriction: "allowed_paths",
+            value: sandbox.allowed_paths.join(":"),
+            mechanism: ALLOWED_PATHS_ENV,
+        });
+    }
+
+    restrictions
+}
+
+/// Apply the sandbox options to a command about to be spawned for the given leaf MCP.
+/// Must be called before the configured `env` is added so `clear_env` doesn't wipe it.
+pub fn apply(
+    leaf_id: &str,
+    sandbox: &StdioSandbox,
+    command: &mut Command,
+) -> MceptionResult<Vec<SandboxRestriction>> {
+    validate(sandbox)?;
+
+    if sandbox.clear_env {
+        command.env_clear();
+    }
+    if let Some(dir) = &sandbox.working_dir {
+        command.current_dir(dir);
+    }
+    if !sandbox.allowed_paths.is_empty() {
+        command.env(ALLOWED_PATHS_ENV, sandbox.allowed_paths.join(":"));
+    }
+
+    platform::apply(leaf_id, sandbox, command)?;
+
+    Ok(describe(sandbox))
+}
+
+fn cgroup_supports(controller: &str) -> bool {
+    std::fs::read_to_string(Path::new(CGROUP_ROOT).join("cgroup.controllers"))
+        .map(|controllers| controllers.split_whitespace().any(|c| c == controller))
+        .unwrap_or(false)
+}
+
+/// Convert cgroup v1 style shares (2..=262144, default 1024) to a cgroup v2 weight (1..=10000)
+fn shares_to_weight(shares: u64) -> u64 {
+    1 + ((shares.clamp(2, 262144) - 2) * 9999) / 262142
+}
+
+#[cfg(unix)]
+mod platform {
+    use super::{CGROUP_ROOT, cgroup_supports, shares_to_weight};
+    use crate::core::{MceptionResult, StdioSandbox, StorageError, ValidationError};
+    use std::ffi::CString;
+    use std::path::Path;
+    use tokio::process::Command;
+
+    pub fn is_privileged() -> bool {
+        // SAFETY: geteuid has no preconditions and cannot fail
+        unsafe { libc::geteuid() == 0 }
+    }
+
+    /// Resolve a user name (or numeric uid) to its uid and primary gid
+    pub fn lookup_user(user: &str) -> Result<(u32, u32), ValidationError> {
+        let name = CString::new(user).map_err(|_| {
+            ValidationError::InvalidFormat(format!(
+                "run_as_user '{}' is not a valid user name",
+                user
+            ))
+        })?;
+        // SAFETY: `name` is a valid NUL-terminated string; the returned record is
+        // copied out immediately, before any other getpw* call could overwrite it
+        let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+        if !passwd.is_null() {
+            // SAFETY: checked for null above
+            let passwd = unsafe { &*passwd };
+            return Ok((passwd.pw_uid, passwd.pw_gid));
+        }
+        if let Ok(uid) = user.parse::<u32>() {
+            // SAFETY: as above
+            let passwd = unsafe { libc::getpwuid(uid) };
+            if !passwd.is_null() {
+                let passwd = unsafe { &*passwd };
+                return Ok((passwd.pw_uid, passwd.pw_gid));
+            }
+            return Ok((uid, uid));
+        }
+        Err(ValidationError::InvalidFormat(format!(
+            "run_as_user '{}' does not exist on this system",
+            user
+        )))
+    }
+
+    pub fn apply(
+        leaf_id: &str,
+        sandbox: &StdioSandbox,
+        command: &mut Command,
+    ) -> MceptionResult<()> {
+        if let Some(user) = &sandbox.run_as_user {
+            let (uid, gid) = lookup_user(user)?;
+            command.gid(gid);
+            command.uid(uid);
+        }
+
+        let use_cgroup_memory = sandbox.max_memory_mb.is_some() && cgroup_supports("memory");
+        let cgroup_procs = if use_cgroup_memory || sandbox.cpu_shares.is_some() {
+            let group = Path::new(CGROUP_ROOT).join(leaf_id);
+            std::fs::create_dir_all(&group).map_err(StorageError::from)?;
+            if let (true, Some(memory)) = (use_cgroup_memory, sandbox.max_memory_mb) {
+                std::fs::write(group.join("memory.max"), (memory * 1024 * 1024).to_string())
+                    .map_err(StorageError::from)?;
+            }
+            if let Some(shares) = sandbox.cpu_shares {
+                std::fs::write(
+                    group.join("cpu.weight"),
+                    shares_to_weight(shares).to_string(),
+                )
+                .map_err(StorageError::from)?;
+            }
+            Some(
+                CString::new(group.join("cgroup.procs").to_string_lossy().into_owned()).map_err(
+                    |_| ValidationError::InvalidFormat("invalid cgroup path".to_string()),
+                )?,
+            )
+        } else {
+            None
+        };
+        let rlimit_memory = if use_cgroup_memory {
+            None
+        } else {
+            sandbox.max_memory_mb.map(|mb| mb * 1024 * 1024)
+        };
+
+        if cgroup_procs.is_none() && rlimit_memory.is_none() {
+            return Ok(());
+        }
+
+        // SAFETY: the closure runs in the forked child before exec and only calls
+        // async-signal-safe functions (open, write, close, setrlimit)
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(procs) = &cgroup_procs {
+                    let fd = libc::open(procs.as_ptr(), libc::O_WRONLY);
+                    if fd < 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    // Writing "0" moves the writing process into the cgroup
+                    let written = libc::write(fd, b"0".as_ptr().cast(), 1);
+                    libc::close(fd);
+                    if written != 1 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                if let Some(bytes) = rlimit_memory {
+                    let limit = libc::rlimit {
+                        rlim_cur: bytes as libc::rlim_t,
+                        rlim_max: bytes as libc::rlim_t,
+                    };
+                    if libc::setrlimit(libc::RLIMIT_AS, &limit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(not(unix))]
+mod platform {
+    use crate::core::{MceptionResult, StdioSandbox, ValidationError};
+    use tokio::process::Command;
+
+    pub fn is_privileged() -> bool {
+        false
+    }
+
+    pub fn lookup_user(user: &str) -> Result<(u32, u32), ValidationError> {
+        Err(ValidationError::InvalidFormat(format!(
+            "run_as_user '{}' is only supported on unix",
+            user
+        )))
+    }
+
+    pub fn apply(
+        _leaf_id: &str,
+        _sandbox: &StdioSandbox,
+        _command: &mut Command,
+    ) -> MceptionResult<()> {
+        Ok(())
+    }
+}
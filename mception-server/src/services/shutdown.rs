@@ -0,0 +1,249 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tokio::sync::watch;
+
+/// What the server was doing when it stopped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShutdownReport {
+    pub started_at: DateTime<Utc>,
+    pub stopped_at: DateTime<Utc>,
+    pub uptime_seconds: i64,
+    /// What triggered the shutdown, e.g. `SIGTERM`
+    pub reason: String,
+    /// Agents connected at shutdown, `None` without availability tracking
+    pub connected_agents: Option<usize>,
+    /// Forwarded requests still running when the drain timeout passed
+    pub in_flight_forwards_aborted: u64,
+    /// Whether requests were still running when the drain timeout passed
+    #[serde(default)]
+    pub drain_timed_out: bool,
+    /// Audit entries that could not be written: refused along with their
+    /// change, dropped, or still held back by the `buffer` failure policy
+    /// when the server stopped
+    pub unsent_audit_entries: u64,
+    /// Revision of the configuration as last saved
+    pub config_revision: u64,
+    /// Forwarded requests failed while draining, per cause
+    pub drain_failures: BTreeMap<String, u64>,
+    pub drain_milliseconds: i64,
+}
+
+/// Run record persisted next to the configuration. It reads `running` while
+/// the server runs and is replaced by the report on a clean shutdown, so a
+/// `running` record found on startup means the previous run crashed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum RunRecord {
+    Running { started_at: DateTime<Utc>, pid: u32 },
+    Stopped(ShutdownReport),
+}
+
+/// How the previous run ended, as found on startup
+#[derive(Debug, Clone, Serialize)]
+pub struct PreviousRun {
+    /// `clean_stop`, `crash` or `unknown` when there is no record
+    pub outcome: &'static str,
+    pub started_at: Option<DateTime<Utc>>,
+    pub report: Option<ShutdownReport>,
+}
+
+impl From<Option<RunRecord>> for PreviousRun {
+    fn from(record: Option<RunRecord>) -> Self {
+        match record {
+            Some(RunRecord::Stopped(report)) => PreviousRun {
+                outcome: "clean_stop",
+                started_at: Some(report.started_at),
+                report: Some(report),
+            },
+            Some(RunRecord::Running { started_at, .. }) => PreviousRun {
+                outcome: "crash",
+                started_at: Some(started_at),
+                report: None,
+            },
+            None => PreviousRun {
+                outcome: "unknown",
+                started_at: None,
+                report: None,
+            },
+        }
+    }
+}
+
+/// Read the record of the previous run, if any
+pub fn read_record(path: &Path) -> std::io::Result<Option<RunRecord>> {
+    match std::fs::read(path) {
+        Ok(content) => Ok(Some(serde_json::from_slice(&content)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn write_record(path: &Path, record: &RunRecord) -> std::io::Result<()> {
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, serde_json::to_vec_pretty(record)?)?;
+    std::fs::rename(&temp, path)
+}
+
+/// Uptime, draining state and in-flight forwards of the running server
+#[derive(Debug)]
+pub struct Lifecycle {
+    started_at: DateTime<Utc>,
+    /// Set while the configuration is loaded in the background on startup
+    loading: AtomicBool,
+    /// Watched by agent WebSockets, which close once it's set
+    draining: watch::Sender<bool>,
+    drain_started_at: Mutex<Option<DateTime<Utc>>>,
+    in_flight_forwards: AtomicU64,
+    unsent_audit_entries: AtomicU64,
+    clock_skew_events: AtomicU64,
+    drain_failures: Mutex<BTreeMap<String, u64>>,
+    previous_run: Mutex<Option<RunRecord>>,
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self {
+            started_at: Utc::now(),
+            loading: AtomicBool::new(false),
+            draining: watch::Sender::new(false),
+            drain_started_at: Mutex::new(None),
+            in_flight_forwards: AtomicU64::new(0),
+            unsent_audit_entries: AtomicU64::new(0),
+            clock_skew_events: AtomicU64::new(0),
+            drain_failures: Mutex::new(BTreeMap::new()),
+            previous_run: Mutex::new(None),
+        }
+    }
+}
+
+/// Counts a forwarded request as in flight until dropped
+pub struct ForwardGuard<'a> {
+    in_flight: &'a AtomicU64,
+}
+
+impl Drop for ForwardGuard<'_> {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Lifecycle {
+    pub fn started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+
+    /// Remember how the previous run ended
+    pub fn set_previous_run(&self, record: Option<RunRecord>) {
+        *self.previous_run.lock().unwrap() = record;
+    }
+
+    pub fn previous_run(&self) -> PreviousRun {
+        self.previous_run.lock().unwrap().clone().into()
+    }
+
+    /// Refuse requests needing the configuration until [`Self::finish_loading`]
+    pub fn begin_loading(&self) {
+        self.loading.store(true, Ordering::SeqCst);
+    }
+
+    pub fn finish_loading(&self) {
+        self.loading.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading.load(Ordering::SeqCst)
+    }
+
+    /// Stop accepting forwarded requests
+    pub fn begin_draining(&self) {
+        self.drain_started_at
+            .lock()
+            .unwrap()
+            .get_or_insert_with(Utc::now);
+        self.draining.send_replace(true);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        *self.draining.borrow()
+    }
+
+    /// Wait until draining begins
+    pub async fn drained(&self) {
+        let _ = self
+            .draining
+            .subscribe()
+            .wait_for(|draining| *draining)
+            .await;
+    }
+
+    /// Track a forwarded request, or `None` while draining
+    pub fn start_forward(&self) -> Option<ForwardGuard<'_>> {
+        if self.is_draining() {
+            return None;
+        }
+        self.in_flight_forwards.fetch_add(1, Ordering::SeqCst);
+        Some(ForwardGuard {
+            in_flight: &self.in_flight_forwards,
+        })
+    }
+
+    pub fn in_flight_forwards(&self) -> u64 {
+        self.in_flight_forwards.load(Ordering::SeqCst)
+    }
+
+    pub fn record_unsent_audit_entries(&self, count: u64) {
+        self.unsent_audit_entries.fetch_add(count, Ordering::SeqCst);
+    }
+
+    /// Count an audit entry timestamped too far before its predecessor
+    pub fn record_clock_skew(&self) {
+        self.clock_skew_events.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Audit entries timestamped too far before their predecessor since the start
+    pub fn clock_skew_events(&self) -> u64 {
+        self.clock_skew_events.load(Ordering::SeqCst)
+    }
+
+    /// Count a forwarded request that failed, if it failed while draining
+    pub fn record_failure(&self, cause: &str) {
+        if self.is_draining() {
+            *self
+                .drain_failures
+                .lock()
+                .unwrap()
+                .entry(cause.to_string())
+                .or_default() += 1;
+        }
+    }
+
+    /// The report for a shutdown that is now complete
+    pub fn report(
+        &self,
+        reason: String,
+        connected_agents: Option<usize>,
+        in_flight_forwards_aborted: u64,
+        drain_timed_out: bool,
+        config_revision: u64,
+    ) -> ShutdownReport {
+        let stopped_at = Utc::now();
+        let drain_started_at = self.drain_started_at.lock().unwrap().unwrap_or(stopped_at);
+        ShutdownReport {
+            started_at: self.started_at,
+            stopped_at,
+            uptime_seconds: (stopped_at - self.started_at).num_seconds(),
+            reason,
+            connected_agents,
+            in_flight_forwards_aborted,
+            drain_timed_out,
+            unsent_audit_entries: self.unsent_audit_entries.load(Ordering::SeqCst),
+            config_revision,
+            drain_failures: self.drain_failures.lock().unwrap().clone(),
+            drain_milliseconds: (stopped_at - drain_started_at).num_milliseconds(),
+        }
+    }
+}
@@ -0,0 +1,446 @@
+use crate::core::{
+    LeafMcpConfig, MceptionError, MceptionResult, McpTransport, NetworkError, ReverseRequestPolicy,
+};
+use crate::services::https;
+use crate::services::reverse_requests::{self, Handling, LeafMessage};
+use crate::services::stdio::PROTOCOL_VERSION;
+use axum::http::{HeaderMap, HeaderValue, header};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Connections to leaf MCPs speaking the SSE transport: an event stream
+/// opened with `GET` on the configured URL names the endpoint messages are
+/// `POST`ed to, and carries the responses. A connection is opened and
+/// initialized on the first forwarded request and shared by later ones until
+/// its stream closes or the leaf MCP's configuration changes.
+#[derive(Default, Clone)]
+pub struct SseConnections {
+    client: reqwest::Client,
+    /// Per leaf MCP, so a slow connect only holds up requests to that leaf
+    slots: Arc<Mutex<HashMap<String, Arc<ConnectionSlot>>>>,
+}
+
+type ConnectionSlot = tokio::sync::Mutex<Option<Arc<SseConnection>>>;
+
+/// Senders of the responses, or why a response couldn't be used
+type Pending = Mutex<HashMap<u64, oneshot::Sender<Result<Value, String>>>>;
+
+/// An open event stream of a leaf MCP with the endpoint to post to
+struct SseConnection {
+    /// Configuration the connection was opened with, to notice changes
+    fingerprint: Value,
+    outbox: Outbox,
+    /// Forwarded requests waiting for their response, by the id sent to the leaf MCP
+    pending: Arc<Pending>,
+    next_id: AtomicU64,
+    closed: Arc<AtomicBool>,
+    /// Result of the `initialize` handshake, answered to agents initializing
+    initialize_result: Mutex<Value>,
+    /// Stops reading the event stream when the connection is dropped
+    _reader: Reader,
+}
+
+/// The task reading the event stream, stopped along with its connection
+struct Reader(JoinHandle<()>);
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Where and with which headers messages are posted to a leaf MCP
+#[derive(Clone)]
+struct Outbox {
+    client: reqwest::Client,
+    endpoint: reqwest::Url,
+    headers: HeaderMap,
+}
+
+/// Removes a pending request when its caller gives up, e.g. on a deadline
+struct PendingGuard<'a> {
+    pending: &'a Pending,
+    id: u64,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl SseConnections {
+    /// Forward a JSON-RPC message to the leaf MCP over its connection.
+    /// Returns the response, or `None` for notifications.
+    pub async fn forward(
+        &self,
+        leaf_id: &str,
+        config: &LeafMcpConfig,
+        message: &Value,
+    ) -> MceptionResult<Option<Value>> {
+        let connection = self.connection(leaf_id, config).await?;
+
+        let Some(id) = message.get("id").filter(|id| !id.is_null()) else {
+            // The handshake already told the leaf MCP the client is initialized
+            if message["method"] != "notifications/initialized" {
+                connection.outbox.post(message).await?;
+            }
+            return Ok(None);
+        };
+
+        if message["method"] == "initialize" {
+            let result = connection.initialize_result.lock().unwrap().clone();
+            return Ok(Some(serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": result
+            })));
+        }
+
+        // Requests of all agents share the connection, so ids are replaced by
+        // the connection's own and restored in the response
+        let mut response = connection.request(message.clone()).await?;
+        response["id"] = id.clone();
+        Ok(Some(response))
+    }
+
+    /// Close a leaf MCP's connection, e.g. when the leaf MCP is deleted
+    pub async fn stop(&self, leaf_id: &str) {
+        let slot = self.slots.lock().unwrap().remove(leaf_id);
+        if let Some(slot) = slot
+            && slot.lock().await.take().is_some()
+        {
+            info!("Closed the event stream of leaf MCP '{}'", leaf_id);
+        }
+    }
+
+    /// Close all connections, e.g. on shutdown
+    pub async fn stop_all(&self) {
+        let slots: Vec<_> = self.slots.lock().unwrap().drain().collect();
+        for (_, slot) in slots {
+            slot.lock().await.take();
+        }
+    }
+
+    /// The open connection of a leaf MCP, opened if needed
+    async fn connection(
+        &self,
+        leaf_id: &str,
+        config: &LeafMcpConfig,
+    ) -> MceptionResult<Arc<SseConnection>> {
+        let fingerprint = serde_json::json!([config.transport, config.reverse_requests]);
+        let slot = self
+            .slots
+            .lock()
+            .unwrap()
+            .entry(leaf_id.to_string())
+            .or_default()
+            .clone();
+
+        let mut slot = slot.lock().await;
+        if let Some(connection) = slot.as_ref() {
+            if !connection.closed.load(Ordering::SeqCst) && connection.fingerprint == fingerprint {
+                return Ok(connection.clone());
+            }
+            *slot = None;
+        }
+
+        let connection = Arc::new(self.open(leaf_id, config, fingerprint).await?);
+        connection.initialize().await?;
+        info!("Connected to leaf MCP '{}' over SSE", leaf_id);
+        *slot = Some(connection.clone());
+        Ok(connection)
+    }
+
+    /// Open the event stream and wait for it to name the endpoint. Errors
+    /// never contain the URL, as it may carry credentials.
+    async fn open(
+        &self,
+        leaf_id: &str,
+        config: &LeafMcpConfig,
+        fingerprint: Value,
+    ) -> MceptionResult<SseConnection> {
+        let McpTransport::Sse { url, headers } = &config.transport else {
+            return Err(NetworkError::ConnectionFailed(format!(
+                "Leaf MCP '{}' does not use the SSE transport",
+                leaf_id
+            ))
+            .into());
+        };
+        let url = reqwest::Url::parse(url).map_err(|e| NetworkError::InvalidUrl(e.to_string()))?;
+        let headers = https::configured_headers(headers.as_ref())?;
+        let response = self
+            .client
+            .get(url.clone())
+            .headers(headers.clone())
+            .header(header::ACCEPT, "text/event-stream")
+            .send()
+            .await
+            .map_err(|e| NetworkError::ConnectionFailed(https::error_chain(&e.without_url())))?;
+        if !response.status().is_success() {
+            return Err(NetworkError::ConnectionFailed(format!(
+                "Leaf MCP answered with HTTP {}",
+                response.status()
+            ))
+            .into());
+        }
+
+        let pending = Arc::new(Pending::default());
+        let closed = Arc::new(AtomicBool::new(false));
+        let (endpoint, on_endpoint) = oneshot::channel();
+        let reader = Reader(tokio::spawn(read_events(
+            leaf_id.to_string(),
+            response,
+            Outbox {
+                client: self.client.clone(),
+                endpoint: url,
+                headers,
+            },
+            endpoint,
+            pending.clone(),
+            closed.clone(),
+            config.reverse_requests,
+        )));
+        let outbox = on_endpoint.await.map_err(|_| {
+            NetworkError::ConnectionFailed(
+                "Leaf MCP closed its event stream before naming its endpoint".to_string(),
+            )
+        })??;
+
+        Ok(SseConnection {
+            fingerprint,
+            outbox,
+            pending,
+            next_id: AtomicU64::new(1),
+            closed,
+            initialize_result: Mutex::new(Value::Null),
+            _reader: reader,
+        })
+    }
+}
+
+impl SseConnection {
+    /// Send `initialize` and `notifications/initialized`
+    async fn initialize(&self) -> MceptionResult<()> {
+        let response = self
+            .request(serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": "initialize",
+                "params": {
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": {
+                        "name": "mception-server",
+                        "version": env!("CARGO_PKG_VERSION")
+                    }
+                }
+            }))
+            .await?;
+        let Some(result) = response.get("result") else {
+            return Err(NetworkError::ConnectionFailed(format!(
+                "Leaf MCP refused to initialize: {}",
+                response["error"]
+            ))
+            .into());
+        };
+        *self.initialize_result.lock().unwrap() = result.clone();
+        self.outbox
+            .post(&serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": "notifications/initialized"
+            }))
+            .await
+    }
+
+    /// Post a request under a fresh id and wait for its response on the stream
+    async fn request(&self, mut message: Value) -> MceptionResult<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        message["id"] = Value::from(id);
+
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, sender);
+        let _guard = PendingGuard {
+            pending: &self.pending,
+            id,
+        };
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(closed_error());
+        }
+
+        self.outbox.post(&message).await?;
+        match receiver.await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(detail)) => Err(NetworkError::ConnectionFailed(detail).into()),
+            Err(_) => Err(closed_error()),
+        }
+    }
+}
+
+fn closed_error() -> MceptionError {
+    NetworkError::ConnectionFailed("Leaf MCP closed its event stream".to_string()).into()
+}
+
+impl Outbox {
+    /// Post a message. Its response, if any, arrives on the event stream.
+    async fn post(&self, message: &Value) -> MceptionResult<()> {
+        let response = self
+            .client
+            .post(self.endpoint.clone())
+            .headers(self.headers.clone())
+            .header(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            )
+            .body(message.to_string())
+            .send()
+            .await
+            .map_err(|e| NetworkError::ConnectionFailed(https::error_chain(&e.without_url())))?;
+        if !response.status().is_success() {
+            return Err(NetworkError::ConnectionFailed(format!(
+                "Leaf MCP answered with HTTP {}",
+                response.status()
+            ))
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Read the event stream until it ends: the `endpoint` event, resolved
+/// against the stream's URL, is sent to `endpoint` as the outbox to post to,
+/// and `message` events answer pending requests
+async fn read_events(
+    leaf_id: String,
+    mut response: reqwest::Response,
+    outbox: Outbox,
+    endpoint: oneshot::Sender<MceptionResult<Outbox>>,
+    pending: Arc<Pending>,
+    closed: Arc<AtomicBool>,
+    policy: ReverseRequestPolicy,
+) {
+    let mut endpoint = Some(endpoint);
+    let mut posting: Option<Outbox> = None;
+    let mut buffer = Vec::new();
+    while let Ok(Some(chunk)) = response.chunk().await {
+        // Line breaks may be `\r\n`, JSON data never holds a raw `\r`
+        buffer.extend(chunk.iter().filter(|&&byte| byte != b'\r'));
+        while let Some(end) = buffer.windows(2).position(|pair| pair == b"\n\n") {
+            let event: Vec<u8> = buffer.drain(..end + 2).collect();
+            let (name, data) = parse_event(&String::from_utf8_lossy(&event));
+            match name.as_str() {
+                "endpoint" => {
+                    let resolved = outbox.endpoint.join(data.trim()).map_err(|e| {
+                        MceptionError::from(NetworkError::InvalidUrl(format!(
+                            "Leaf MCP named an invalid endpoint: {}",
+                            e
+                        )))
+                    });
+                    let resolved = resolved.map(|url| Outbox {
+                        endpoint: url,
+                        ..outbox.clone()
+                    });
+                    if let Ok(resolved) = &resolved {
+                        posting = Some(resolved.clone());
+                    }
+                    if let Some(endpoint) = endpoint.take() {
+                        let _ = endpoint.send(resolved);
+                    }
+                }
+                "message" => {
+                    let Ok(message) = serde_json::from_str::<Value>(&data) else {
+                        warn!(
+                            "Leaf MCP '{}' sent an event that is not JSON: {}",
+                            leaf_id, data
+                        );
+                        continue;
+                    };
+                    handle_message(&leaf_id, message, posting.as_ref(), &pending, policy);
+                }
+                _ => debug!("Leaf MCP '{}' sent a '{}' event", leaf_id, name),
+            }
+        }
+    }
+    debug!("Event stream of leaf MCP '{}' ended", leaf_id);
+    closed.store(true, Ordering::SeqCst);
+    pending.lock().unwrap().clear();
+}
+
+/// Answer the pending request a message responds to, or reject a request
+/// of the leaf MCP's own
+fn handle_message(
+    leaf_id: &str,
+    message: Value,
+    outbox: Option<&Outbox>,
+    pending: &Pending,
+    policy: ReverseRequestPolicy,
+) {
+    match reverse_requests::classify(&message) {
+        LeafMessage::Response => {
+            let sender = message["id"]
+                .as_u64()
+                .and_then(|id| pending.lock().unwrap().remove(&id));
+            match sender {
+                Some(sender) => {
+                    let _ = sender.send(Ok(message));
+                }
+                None => debug!("Leaf MCP '{}' answered an abandoned request", leaf_id),
+            }
+        }
+        LeafMessage::Request { id, method } => {
+            // There is no agent connection to relay to yet
+            let reason = match reverse_requests::handling(policy, &method, None) {
+                Handling::Reject(reason) => reason,
+                Handling::Relay => "relaying is not available".to_string(),
+            };
+            let rejection = reverse_requests::rejection(&id, &method, &reason);
+            if let Some(outbox) = outbox.cloned() {
+                let leaf_id = leaf_id.to_string();
+                tokio::spawn(async move {
+                    if let Err(e) = outbox.post(&rejection).await {
+                        warn!(
+                            "Failed to answer '{}' of leaf MCP '{}': {}",
+                            method, leaf_id, e
+                        );
+                    }
+                });
+            }
+        }
+        LeafMessage::Notification => {
+            debug!("Leaf MCP '{}' notification: {}", leaf_id, message["method"]);
+        }
+        LeafMessage::Invalid => {
+            warn!(
+                "Leaf MCP '{}' sent an invalid JSON-RPC message: {}",
+                leaf_id, message
+            );
+            let sender = message["id"]
+                .as_u64()
+                .and_then(|id| pending.lock().unwrap().remove(&id));
+            if let Some(sender) = sender {
+                let _ = sender.send(Err(format!(
+                    "Leaf MCP answered with an invalid JSON-RPC response: {}",
+                    message
+                )));
+            }
+        }
+    }
+}
+
+/// Name (`message` unless given) and data lines of an event
+fn parse_event(event: &str) -> (String, String) {
+    let mut name = "message".to_string();
+    let mut data = Vec::new();
+    for line in event.lines() {
+        if let Some(value) = line.strip_prefix("event:") {
+            name = value.trim().to_string();
+        } else if let Some(value) = line.strip_prefix("data:") {
+            data.push(value.strip_prefix(' ').unwrap_or(value));
+        }
+    }
+    (name, data.join("\n"))
+}
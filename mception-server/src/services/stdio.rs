@@ -0,0 +1,684 @@
+use crate::core::{
+    LeafMcpConfig, MceptionError, MceptionResult, McpTransport, NetworkError, ReverseRequestPolicy,
+    interpolation,
+};
+use crate::services::internals::ProcessUsage;
+use crate::services::reverse_requests::{self, Handling, LeafMessage};
+use crate::services::sandbox;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::oneshot;
+use tracing::{debug, info, warn};
+
+/// Times the exit status of a process that closed its output is polled, 10ms apart
+const EXIT_WAIT_ATTEMPTS: usize = 20;
+
+/// Wait before restarting a process that exited, doubled for each further
+/// restart in a row
+const RESTART_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Longest wait before a restart
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Restarts in a row after which a process is left stopped as failed
+const MAX_RESTARTS: u32 = 5;
+
+/// A process that ran this long before exiting starts the backoff over
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// How long a stopped process has to exit after `SIGTERM` before it is killed
+const STOP_GRACE: Duration = Duration::from_secs(2);
+
+/// MCP protocol version requested from stdio leaf MCPs
+pub const PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// Running stdio leaf MCP processes. A process is spawned and initialized on
+/// the first forwarded request and reused by later ones until its
+/// configuration changes. A process that exits is restarted with a backoff,
+/// or right away by the next forwarded request.
+#[derive(Default, Clone)]
+pub struct StdioProcesses {
+    /// Per leaf MCP, so a slow start only holds up requests to that leaf
+    slots: Arc<Mutex<HashMap<String, Arc<ProcessSlot>>>>,
+    statuses: Arc<Mutex<HashMap<String, ProcessStatus>>>,
+}
+
+/// What a leaf MCP's process is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessState {
+    Running,
+    /// Exited, waiting for the backoff to start it again
+    Restarting,
+    /// Failed to start, or exited after [`MAX_RESTARTS`] restarts in a row.
+    /// The next forwarded request tries again.
+    Failed,
+}
+
+/// A leaf MCP's process, from its last start on
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessStatus {
+    pub state: ProcessState,
+    pub pid: Option<u32>,
+    pub started_at: Option<DateTime<Utc>>,
+    /// Restarts in a row after exits, reset once a process runs stable
+    pub restarts: u32,
+    /// `None` while it never exited, or if it was ended by a signal
+    pub last_exit_code: Option<i32>,
+    pub last_exited_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub next_restart_at: Option<DateTime<Utc>>,
+}
+
+type ProcessSlot = tokio::sync::Mutex<Option<Arc<StdioProcess>>>;
+
+/// Senders of the responses, or why a response couldn't be used
+type Pending = Mutex<HashMap<u64, oneshot::Sender<Result<Value, String>>>>;
+
+/// What a process depends on of its leaf MCP's configuration, a change
+/// replaces it
+fn fingerprint(config: &LeafMcpConfig) -> Value {
+    serde_json::json!([config.transport, config.reverse_requests])
+}
+
+/// A spawned leaf MCP process speaking newline-delimited JSON-RPC
+struct StdioProcess {
+    /// Configuration the process was started with, to notice changes
+    fingerprint: Value,
+    child: Mutex<Child>,
+    stdin: Arc<tokio::sync::Mutex<ChildStdin>>,
+    /// Forwarded requests waiting for their response, by the id sent to the process
+    pending: Arc<Pending>,
+    next_id: AtomicU64,
+    exited: Arc<AtomicBool>,
+    /// Result of the `initialize` handshake, answered to agents initializing
+    initialize_result: Mutex<Value>,
+    started: Instant,
+}
+
+/// Removes a pending request when its caller gives up, e.g. on a deadline
+struct PendingGuard<'a> {
+    pending: &'a Pending,
+    id: u64,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl StdioProcesses {
+    /// Forward a JSON-RPC message to the leaf MCP's process. Returns the
+    /// response, or `None` for notifications.
+    pub async fn forward(
+        &self,
+        leaf_id: &str,
+        config: &LeafMcpConfig,
+        message: &Value,
+    ) -> MceptionResult<Option<Value>> {
+        let process = self.process(leaf_id, config).await?;
+
+        let Some(id) = message.get("id").filter(|id| !id.is_null()) else {
+            // The handshake already told the process the client is initialized
+            if message["method"] != "notifications/initialized" {
+                process.write(message).await?;
+            }
+            return Ok(None);
+        };
+
+        if message["method"] == "initialize" {
+            let result = process.initialize_result.lock().unwrap().clone();
+            return Ok(Some(serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": result
+            })));
+        }
+
+        // Requests of all agents share the process, so ids are replaced by
+        // the process's own and restored in the response
+        let mut response = process.request(message.clone()).await?;
+        response["id"] = id.clone();
+        Ok(Some(response))
+    }
+
+    /// Running processes and their pending requests. Processes being
+    /// started or replaced right now are not counted.
+    pub fn usage(&self) -> ProcessUsage {
+        let slots: Vec<_> = self.slots.lock().unwrap().values().cloned().collect();
+        let running: Vec<_> = slots
+            .iter()
+            .filter_map(|slot| slot.try_lock().ok()?.clone())
+            .filter(|process| !process.exited.load(Ordering::SeqCst))
+            .collect();
+        ProcessUsage {
+            processes: running.len(),
+            pending_requests: running
+                .iter()
+                .map(|process| process.pending.lock().unwrap().len())
+                .sum(),
+        }
+    }
+
+    /// MCP protocol version the leaf MCP's running process answered
+    /// `initialize` with, `None` unless one runs with `config`
+    pub fn protocol_version(&self, leaf_id: &str, config: &LeafMcpConfig) -> Option<String> {
+        let slot = self.slots.lock().unwrap().get(leaf_id)?.clone();
+        // A process being started hasn't answered yet
+        let process = slot.try_lock().ok()?.clone()?;
+        if process.exited.load(Ordering::SeqCst) || process.fingerprint != fingerprint(config) {
+            return None;
+        }
+        let result = process.initialize_result.lock().unwrap();
+        result["protocolVersion"].as_str().map(str::to_string)
+    }
+
+    /// Status of a leaf MCP's process, `None` if none was started
+    pub fn status(&self, leaf_id: &str) -> Option<ProcessStatus> {
+        self.statuses.lock().unwrap().get(leaf_id).cloned()
+    }
+
+    /// Stop a leaf MCP's process without restarting it, e.g. when the leaf
+    /// MCP is deleted
+    pub async fn stop(&self, leaf_id: &str) {
+        let slot = self.slots.lock().unwrap().remove(leaf_id);
+        self.statuses.lock().unwrap().remove(leaf_id);
+        if let Some(slot) = slot
+            && let Some(process) = slot.lock().await.take()
+        {
+            stop_processes(vec![(leaf_id.to_string(), process)]).await;
+        }
+        // A restart that was under way recorded the process again
+        self.statuses.lock().unwrap().remove(leaf_id);
+    }
+
+    /// Stop all processes, e.g. on shutdown. Each gets `SIGTERM` and
+    /// [`STOP_GRACE`] to exit before it is killed.
+    pub async fn stop_all(&self) {
+        let slots: Vec<_> = self.slots.lock().unwrap().drain().collect();
+        self.statuses.lock().unwrap().clear();
+        let mut processes = Vec::new();
+        for (leaf_id, slot) in slots {
+            if let Some(process) = slot.lock().await.take() {
+                processes.push((leaf_id, process));
+            }
+        }
+        stop_processes(processes).await;
+    }
+
+    /// The running process of a leaf MCP, started if needed
+    async fn process(
+        &self,
+        leaf_id: &str,
+        config: &LeafMcpConfig,
+    ) -> MceptionResult<Arc<StdioProcess>> {
+        let fingerprint = fingerprint(config);
+        let slot = self
+            .slots
+            .lock()
+            .unwrap()
+            .entry(leaf_id.to_string())
+            .or_default()
+            .clone();
+
+        let mut slot = slot.lock().await;
+        if let Some(process) = slot.as_ref() {
+            if !process.exited.load(Ordering::SeqCst) && process.fingerprint == fingerprint {
+                return Ok(process.clone());
+            }
+            process.kill(leaf_id);
+            *slot = None;
+        }
+
+        let (process, on_close) = self.start(leaf_id, config, fingerprint, 0).await?;
+        *slot = Some(process.clone());
+        tokio::spawn(self.clone().supervise(
+            leaf_id.to_string(),
+            config.clone(),
+            process.clone(),
+            on_close,
+        ));
+        Ok(process)
+    }
+
+    /// Spawn and initialize a process, recording how that went. Returns it
+    /// with a receiver that completes when it closes its output.
+    async fn start(
+        &self,
+        leaf_id: &str,
+        config: &LeafMcpConfig,
+        fingerprint: Value,
+        restarts: u32,
+    ) -> MceptionResult<(Arc<StdioProcess>, oneshot::Receiver<()>)> {
+        let (closed, on_close) = oneshot::channel();
+        let started = match StdioProcess::spawn(leaf_id, config, fingerprint, closed) {
+            Ok(process) => match process.initialize().await {
+                Ok(()) => Ok(Arc::new(process)),
+                Err(e) => {
+                    process.kill(leaf_id);
+                    Err(e)
+                }
+            },
+            Err(e) => Err(e),
+        };
+        let mut statuses = self.statuses.lock().unwrap();
+        let status = statuses
+            .entry(leaf_id.to_string())
+            .or_insert_with(|| ProcessStatus {
+                state: ProcessState::Running,
+                pid: None,
+                started_at: None,
+                restarts: 0,
+                last_exit_code: None,
+                last_exited_at: None,
+                last_error: None,
+                next_restart_at: None,
+            });
+        status.restarts = restarts;
+        status.next_restart_at = None;
+        match &started {
+            Ok(process) => {
+                status.state = ProcessState::Running;
+                status.pid = process.child.lock().unwrap().id();
+                status.started_at = Some(Utc::now());
+            }
+            Err(e) => {
+                status.state = ProcessState::Failed;
+                status.pid = None;
+                status.last_error = Some(e.to_string());
+            }
+        }
+        started.map(|process| (process, on_close))
+    }
+
+    /// Wait for a process to exit and restart it with a backoff, until it is
+    /// stopped or replaced, or fails [`MAX_RESTARTS`] times in a row
+    async fn supervise(
+        self,
+        leaf_id: String,
+        config: LeafMcpConfig,
+        mut process: Arc<StdioProcess>,
+        mut on_close: oneshot::Receiver<()>,
+    ) {
+        loop {
+            let _ = on_close.await;
+            let status = process.exit_status().await;
+            self.update_status(&leaf_id, |entry| {
+                entry.last_exit_code = status.and_then(|status| status.code());
+                entry.last_exited_at = Some(Utc::now());
+            });
+            let Some(slot) = self.current_slot(&leaf_id) else {
+                return;
+            };
+            {
+                let mut slot = slot.lock().await;
+                if !slot
+                    .as_ref()
+                    .is_some_and(|current| Arc::ptr_eq(current, &process))
+                {
+                    return;
+                }
+                *slot = None;
+            }
+            warn!(
+                "Leaf MCP '{}' process exited ({})",
+                leaf_id,
+                status.map_or("status unknown".to_string(), |status| status.to_string())
+            );
+            let mut restarts = match self.status(&leaf_id) {
+                Some(status) if process.started.elapsed() < STABLE_AFTER => status.restarts,
+                _ => 0,
+            };
+            self.update_status(&leaf_id, |entry| entry.pid = None);
+
+            (process, on_close) = loop {
+                if restarts >= MAX_RESTARTS {
+                    warn!(
+                        "Leaf MCP '{}' exited after {} restarts in a row, not restarting it",
+                        leaf_id, restarts
+                    );
+                    self.update_status(&leaf_id, |entry| entry.state = ProcessState::Failed);
+                    return;
+                }
+                let delay = RESTART_BACKOFF
+                    .saturating_mul(2u32.saturating_pow(restarts))
+                    .min(MAX_RESTART_BACKOFF);
+                self.update_status(&leaf_id, |entry| {
+                    entry.state = ProcessState::Restarting;
+                    entry.next_restart_at = chrono::Duration::from_std(delay)
+                        .ok()
+                        .map(|delay| Utc::now() + delay);
+                });
+                tokio::time::sleep(delay).await;
+                restarts += 1;
+
+                // Stopped, or started again by a forwarded request meanwhile
+                let Some(slot) = self.current_slot(&leaf_id) else {
+                    return;
+                };
+                let mut slot = slot.lock().await;
+                if slot.is_some() {
+                    return;
+                }
+                let fingerprint = process.fingerprint.clone();
+                match self.start(&leaf_id, &config, fingerprint, restarts).await {
+                    Ok((restarted, on_close)) => {
+                        info!("Restarted leaf MCP '{}'", leaf_id);
+                        *slot = Some(restarted.clone());
+                        break (restarted, on_close);
+                    }
+                    Err(e) => warn!("Failed to restart leaf MCP '{}': {}", leaf_id, e),
+                }
+            };
+        }
+    }
+
+    fn current_slot(&self, leaf_id: &str) -> Option<Arc<ProcessSlot>> {
+        self.slots.lock().unwrap().get(leaf_id).cloned()
+    }
+
+    fn update_status(&self, leaf_id: &str, update: impl FnOnce(&mut ProcessStatus)) {
+        if let Some(status) = self.statuses.lock().unwrap().get_mut(leaf_id) {
+            update(status);
+        }
+    }
+}
+
+/// Send each process `SIGTERM`, and kill those still running after [`STOP_GRACE`]
+async fn stop_processes(processes: Vec<(String, Arc<StdioProcess>)>) {
+    for (leaf_id, process) in &processes {
+        process.terminate(leaf_id);
+    }
+    let deadline = Instant::now() + STOP_GRACE;
+    for (leaf_id, process) in processes {
+        while process.try_wait().is_none() && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        if process.try_wait().is_none() {
+            process.kill(&leaf_id);
+        } else {
+            info!("Stopped leaf MCP '{}'", leaf_id);
+        }
+    }
+}
+
+impl StdioProcess {
+    fn spawn(
+        leaf_id: &str,
+        config: &LeafMcpConfig,
+        fingerprint: Value,
+        closed: oneshot::Sender<()>,
+    ) -> MceptionResult<Self> {
+        let McpTransport::Stdio {
+            command,
+            args,
+            env,
+            sandbox,
+        } = &config.transport
+        else {
+            return Err(NetworkError::ConnectionFailed(format!(
+                "Leaf MCP '{}' does not use the stdio transport",
+                leaf_id
+            ))
+            .into());
+        };
+
+        let args = args
+            .iter()
+            .map(|arg| interpolation::resolve(arg))
+            .collect::<MceptionResult<Vec<_>>>()?;
+        let mut child_command = Command::new(command);
+        child_command
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        sandbox::apply(leaf_id, sandbox, &mut child_command)?;
+        if let Some(env) = env {
+            for (name, value) in env {
+                child_command.env(name, interpolation::resolve(value)?);
+            }
+        }
+
+        let mut child = child_command.spawn().map_err(|e| {
+            NetworkError::ConnectionFailed(format!(
+                "Failed to start leaf MCP '{}' ({}): {}",
+                leaf_id, command, e
+            ))
+        })?;
+        info!("Started leaf MCP '{}' (pid {:?})", leaf_id, child.id());
+
+        let stdin = Arc::new(tokio::sync::Mutex::new(
+            child.stdin.take().expect("stdin is piped"),
+        ));
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+        let pending = Arc::new(Pending::default());
+        let exited = Arc::new(AtomicBool::new(false));
+
+        tokio::spawn(read_output(
+            leaf_id.to_string(),
+            stdout,
+            stdin.clone(),
+            pending.clone(),
+            exited.clone(),
+            config.reverse_requests,
+            closed,
+        ));
+        let leaf = leaf_id.to_string();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                debug!("Leaf MCP '{}' stderr: {}", leaf, line);
+            }
+        });
+
+        Ok(Self {
+            fingerprint,
+            child: Mutex::new(child),
+            stdin,
+            pending,
+            next_id: AtomicU64::new(1),
+            exited,
+            initialize_result: Mutex::new(Value::Null),
+            started: Instant::now(),
+        })
+    }
+
+    /// Send `initialize` and `notifications/initialized`
+    async fn initialize(&self) -> MceptionResult<()> {
+        let response = self
+            .request(serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": "initialize",
+                "params": {
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": {
+                        "name": "mception-server",
+                        "version": env!("CARGO_PKG_VERSION")
+                    }
+                }
+            }))
+            .await?;
+        let Some(result) = response.get("result") else {
+            return Err(NetworkError::ConnectionFailed(format!(
+                "Leaf MCP refused to initialize: {}",
+                response["error"]
+            ))
+            .into());
+        };
+        *self.initialize_result.lock().unwrap() = result.clone();
+        self.write(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/initialized"
+        }))
+        .await
+    }
+
+    /// Send a request under a fresh id and wait for its response
+    async fn request(&self, mut message: Value) -> MceptionResult<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        message["id"] = Value::from(id);
+
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, sender);
+        let _guard = PendingGuard {
+            pending: &self.pending,
+            id,
+        };
+        if self.exited.load(Ordering::SeqCst) {
+            return Err(self.exit_error().await);
+        }
+
+        self.write(&message).await?;
+        match receiver.await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(detail)) => Err(NetworkError::ConnectionFailed(detail).into()),
+            Err(_) => Err(self.exit_error().await),
+        }
+    }
+
+    async fn write(&self, message: &Value) -> MceptionResult<()> {
+        write_message(&self.stdin, message).await.map_err(|e| {
+            self.exited.store(true, Ordering::SeqCst);
+            NetworkError::ConnectionFailed(format!("Failed to write to leaf MCP: {}", e)).into()
+        })
+    }
+
+    /// Error for a process that closed its output, naming its exit status if
+    /// it exits shortly after
+    async fn exit_error(&self) -> MceptionError {
+        NetworkError::ConnectionFailed(match self.exit_status().await {
+            Some(status) => format!("Leaf MCP process exited ({})", status),
+            None => "Leaf MCP process closed its output".to_string(),
+        })
+        .into()
+    }
+
+    /// Exit status of a process that closed its output, if it exits shortly after
+    async fn exit_status(&self) -> Option<ExitStatus> {
+        for _ in 0..EXIT_WAIT_ATTEMPTS {
+            if let Some(status) = self.try_wait() {
+                return Some(status);
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        None
+    }
+
+    fn try_wait(&self) -> Option<ExitStatus> {
+        self.child.lock().unwrap().try_wait().ok().flatten()
+    }
+
+    /// Ask the process to exit, killing it where signals aren't available
+    fn terminate(&self, leaf_id: &str) {
+        #[cfg(unix)]
+        if let Some(pid) = self.child.lock().unwrap().id() {
+            // SAFETY: signals the child process, which hasn't been reaped yet
+            unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) };
+            return;
+        }
+        self.kill(leaf_id);
+    }
+
+    fn kill(&self, leaf_id: &str) {
+        if let Err(e) = self.child.lock().unwrap().start_kill() {
+            debug!("Failed to kill leaf MCP '{}': {}", leaf_id, e);
+        }
+    }
+}
+
+async fn write_message(
+    stdin: &tokio::sync::Mutex<ChildStdin>,
+    message: &Value,
+) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    let mut stdin = stdin.lock().await;
+    stdin.write_all(&line).await?;
+    stdin.flush().await
+}
+
+/// Route the process's output: responses to their waiting request, and
+/// server-initiated requests to the leaf's reverse request policy. Waiting
+/// requests fail once the output closes.
+async fn read_output(
+    leaf_id: String,
+    stdout: ChildStdout,
+    stdin: Arc<tokio::sync::Mutex<ChildStdin>>,
+    pending: Arc<Pending>,
+    exited: Arc<AtomicBool>,
+    policy: ReverseRequestPolicy,
+    closed: oneshot::Sender<()>,
+) {
+    let mut lines = BufReader::new(stdout).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            warn!(
+                "Leaf MCP '{}' wrote a line that is not JSON: {}",
+                leaf_id, line
+            );
+            continue;
+        };
+        match reverse_requests::classify(&message) {
+            LeafMessage::Response => {
+                let sender = message["id"]
+                    .as_u64()
+                    .and_then(|id| pending.lock().unwrap().remove(&id));
+                match sender {
+                    Some(sender) => {
+                        let _ = sender.send(Ok(message));
+                    }
+                    None => debug!("Leaf MCP '{}' answered an abandoned request", leaf_id),
+                }
+            }
+            LeafMessage::Request { id, method } => {
+                // There is no agent connection to relay to yet
+                let reason = match reverse_requests::handling(policy, &method, None) {
+                    Handling::Reject(reason) => reason,
+                    Handling::Relay => "relaying is not available".to_string(),
+                };
+                let rejection = reverse_requests::rejection(&id, &method, &reason);
+                if let Err(e) = write_message(&stdin, &rejection).await {
+                    warn!(
+                        "Failed to answer '{}' of leaf MCP '{}': {}",
+                        method, leaf_id, e
+                    );
+                }
+            }
+            LeafMessage::Notification => {
+                debug!("Leaf MCP '{}' notification: {}", leaf_id, message["method"]);
+            }
+            LeafMessage::Invalid => {
+                warn!(
+                    "Leaf MCP '{}' wrote an invalid JSON-RPC message: {}",
+                    leaf_id, line
+                );
+                // Fail the request it was meant to answer instead of leaving it to time out
+                let sender = message["id"]
+                    .as_u64()
+                    .and_then(|id| pending.lock().unwrap().remove(&id));
+                if let Some(sender) = sender {
+                    let _ = sender.send(Err(format!(
+                        "Leaf MCP answered with an invalid JSON-RPC response: {}",
+                        line
+                    )));
+                }
+            }
+        }
+    }
+    exited.store(true, Ordering::SeqCst);
+    pending.lock().unwrap().clear();
+    let _ = closed.send(());
+}
@@ -0,0 +1,318 @@
+use crate::core::{MceptionError, MceptionResult, StorageError, SyncTarget};
+use crate::services::ConfigService;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// Header carrying `sha256=<hex HMAC-SHA256 of the body>` on signed pushes
+pub const SIGNATURE_HEADER: &str = "x-mception-signature";
+
+/// Tries of a single push before it is left for the next run
+const ATTEMPTS: u32 = 3;
+
+/// Wait before the first retry of a push, doubled for each further one
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// How soon a run that left pushes failed is repeated without a change
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Time an HTTP target has to answer a push
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How the last runs of a sync target went
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncStatus {
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// Last run that left nothing failed
+    pub last_success_at: Option<DateTime<Utc>>,
+    /// Revision of the configuration last run on
+    pub revision: Option<u64>,
+    /// Configurations pushed by the last run
+    pub pushed: usize,
+    /// Configurations of removed agents removed by the last run
+    pub removed: usize,
+    /// Why the last run failed to push or remove an agent's configuration
+    pub failed: BTreeMap<String, String>,
+}
+
+#[derive(Debug)]
+struct TargetState {
+    /// The target as last run, what was pushed is forgotten if it changes
+    target: SyncTarget,
+    /// Digest of each agent's configuration as last pushed
+    pushed: BTreeMap<String, String>,
+    status: SyncStatus,
+}
+
+/// What has been pushed to each of the configuration's sync targets. Runs
+/// are serialized, a run waits for the one in progress.
+#[derive(Debug, Default)]
+pub struct SyncTargets {
+    states: Mutex<BTreeMap<String, TargetState>>,
+    client: reqwest::Client,
+}
+
+impl SyncTargets {
+    /// Status of each target that has been run
+    pub async fn statuses(&self) -> BTreeMap<String, SyncStatus> {
+        let states = self.states.lock().await;
+        states
+            .iter()
+            .map(|(id, state)| (id.clone(), state.status.clone()))
+            .collect()
+    }
+}
+
+/// Push the remote configuration of every agent whose configuration changed
+/// since it was last pushed to a target, and remove those of removed agents.
+/// `only` limits the run to one target, `force` pushes every configuration.
+/// Failures are recorded in the targets' status and retried by the next run,
+/// they never fail the change that caused the run.
+pub async fn run(
+    service: &ConfigService,
+    only: Option<&str>,
+    force: bool,
+) -> MceptionResult<BTreeMap<String, SyncStatus>> {
+    let config = service.get_configuration().await;
+    if let Some(id) = only
+        && !config.sync_targets.contains_key(id)
+    {
+        return Err(MceptionError::Storage(StorageError::NotFound(format!(
+            "Sync target '{}' not found",
+            id
+        ))));
+    }
+
+    // Rendered once for all targets. Digests leave out the metadata, which
+    // changes with every revision, so only affected agents are pushed.
+    let mut rendered = BTreeMap::new();
+    for agent_id in config.agents.keys() {
+        let remote = service.get_agent_remote_config(agent_id).await?;
+        let digest = digest(
+            &serde_json::to_vec(&(&remote.mcps, &remote.bundles)).map_err(StorageError::from)?,
+        );
+        let body = serde_json::to_vec_pretty(&remote).map_err(StorageError::from)?;
+        rendered.insert(agent_id.clone(), (body, digest));
+    }
+
+    let sync = service.sync_targets();
+    let mut states = sync.states.lock().await;
+    states.retain(|id, _| config.sync_targets.contains_key(id));
+    let mut statuses = BTreeMap::new();
+    for (id, target) in &config.sync_targets {
+        if only.is_some_and(|only| only != id) {
+            continue;
+        }
+        let state = states.entry(id.clone()).or_insert_with(|| TargetState {
+            target: target.clone(),
+            pushed: BTreeMap::new(),
+            status: SyncStatus::default(),
+        });
+        if state.target != *target {
+            state.target = target.clone();
+            state.pushed.clear();
+        }
+
+        let mut status = SyncStatus {
+            last_run_at: Some(Utc::now()),
+            last_success_at: state.status.last_success_at,
+            revision: Some(config.metadata.revision),
+            ..SyncStatus::default()
+        };
+        for (agent_id, (body, digest)) in &rendered {
+            if !force && state.pushed.get(agent_id) == Some(digest) {
+                continue;
+            }
+            match retried(|| push(&sync.client, target, agent_id, Some(body))).await {
+                Ok(()) => {
+                    state.pushed.insert(agent_id.clone(), digest.clone());
+                    status.pushed += 1;
+                }
+                Err(e) => {
+                    status.failed.insert(agent_id.clone(), e);
+                }
+            }
+        }
+        let removed: Vec<String> = state
+            .pushed
+            .keys()
+            .filter(|agent_id| !rendered.contains_key(*agent_id))
+            .cloned()
+            .collect();
+        for agent_id in removed {
+            match retried(|| push(&sync.client, target, &agent_id, None)).await {
+                Ok(()) => {
+                    state.pushed.remove(&agent_id);
+                    status.removed += 1;
+                }
+                Err(e) => {
+                    status.failed.insert(agent_id, e);
+                }
+            }
+        }
+
+        if status.failed.is_empty() {
+            status.last_success_at = status.last_run_at;
+        } else {
+            warn!(
+                "Failed to sync {} agent configurations to '{}'",
+                status.failed.len(),
+                id
+            );
+        }
+        state.status = status.clone();
+        statuses.insert(id.clone(), status);
+    }
+    Ok(statuses)
+}
+
+/// Run the sync targets after loading and then whenever a revision is
+/// committed, repeating runs that left failures after [`RETRY_INTERVAL`]
+pub async fn watch(service: Arc<ConfigService>) {
+    let mut revisions = service.subscribe_revisions();
+    loop {
+        let failed = match run(&service, None, false).await {
+            Ok(statuses) => statuses.values().any(|status| !status.failed.is_empty()),
+            Err(e) => {
+                warn!("Failed to render agent configurations to sync: {}", e);
+                true
+            }
+        };
+        let changed = if failed {
+            match tokio::time::timeout(RETRY_INTERVAL, revisions.changed()).await {
+                Ok(changed) => changed,
+                Err(_) => Ok(()),
+            }
+        } else {
+            revisions.changed().await
+        };
+        if changed.is_err() {
+            info!("Configuration service stopped, no longer syncing");
+            return;
+        }
+    }
+}
+
+async fn retried<F, Fut>(mut attempt: F) -> Result<(), String>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), String>>,
+{
+    let mut delay = RETRY_DELAY;
+    let mut tries = 1;
+    loop {
+        match attempt().await {
+            Ok(()) => return Ok(()),
+            Err(e) if tries >= ATTEMPTS => return Err(e),
+            Err(_) => {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                tries += 1;
+            }
+        }
+    }
+}
+
+/// Write an agent's configuration to the target, or remove it without `body`
+async fn push(
+    client: &reqwest::Client,
+    target: &SyncTarget,
+    agent_id: &str,
+    body: Option<&[u8]>,
+) -> Result<(), String> {
+    match target {
+        SyncTarget::File { directory } => {
+            let directory = Path::new(directory);
+            let path = directory.join(format!("{}.json", agent_id));
+            let written = match body {
+                Some(body) => write_file(directory, &path, body).await,
+                None => match tokio::fs::remove_file(&path).await {
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                    removed => removed,
+                },
+            };
+            written.map_err(|e| format!("{}: {}", path.display(), e))
+        }
+        SyncTarget::Http {
+            url,
+            headers,
+            signing_secret,
+        } => {
+            let url = if url.contains("{agent_id}") {
+                url.replace("{agent_id}", agent_id)
+            } else {
+                format!("{}/{}", url.trim_end_matches('/'), agent_id)
+            };
+            let mut request = match body {
+                Some(body) => client
+                    .put(&url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(body.to_vec()),
+                None => client.delete(&url),
+            };
+            for (name, value) in headers.iter().flatten() {
+                request = request.header(name, value);
+            }
+            if let Some(secret) = signing_secret {
+                request = request.header(
+                    SIGNATURE_HEADER,
+                    format!("sha256={}", signature(secret, body.unwrap_or_default())),
+                );
+            }
+            let response = request
+                .timeout(HTTP_TIMEOUT)
+                .send()
+                .await
+                .map_err(|e| format!("{}: {}", url, e))?;
+            let status = response.status();
+            // Removing what was never there is fine
+            if status.is_success() || (body.is_none() && status.as_u16() == 404) {
+                Ok(())
+            } else {
+                Err(format!("{} answered {}", url, status))
+            }
+        }
+    }
+}
+
+async fn write_file(directory: &Path, path: &Path, body: &[u8]) -> std::io::Result<()> {
+    tokio::fs::create_dir_all(directory).await?;
+    let temp = path.with_extension("tmp");
+    tokio::fs::write(&temp, body).await?;
+    tokio::fs::rename(&temp, path).await
+}
+
+fn digest(content: &[u8]) -> String {
+    hex(&Sha256::digest(content))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Hex HMAC-SHA256 of `body` keyed with `secret`, as sent in [`SIGNATURE_HEADER`]
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    const BLOCK: usize = 64;
+    let mut key = [0u8; BLOCK];
+    if secret.len() > BLOCK {
+        key[..32].copy_from_slice(&Sha256::digest(secret.as_bytes()));
+    } else {
+        key[..secret.len()].copy_from_slice(secret.as_bytes());
+    }
+    let pad = |byte: u8| key.map(|k| k ^ byte);
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(body)
+        .finalize();
+    let outer = Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize();
+    hex(&outer)
+}
@@ -0,0 +1,179 @@
+use crate::core::McpTool;
+use crate::services::tools;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Key in a leaf MCP's `config` listing its tool names to keep first
+pub const TOOL_PRIORITY_KEY: &str = "tool_priority";
+
+/// Key in an agent's `config` capping the tools of its aggregated listing
+pub const MAX_TOOLS_KEY: &str = "max_tools";
+
+/// Key in an agent's `config` with `<mcp id>` or `<mcp id>/<tool>` entries
+/// to keep first, ahead of the MCPs' own priorities
+pub const PRIORITY_OVERRIDES_KEY: &str = "tool_priority_overrides";
+
+/// Key in an agent's `config` truncating longer tool descriptions to this
+/// many characters
+pub const MAX_DESCRIPTION_KEY: &str = "max_tool_description";
+
+/// How an agent's aggregated tool listing is cut down
+#[derive(Debug, Clone, Default)]
+pub struct Shaping {
+    pub max_tools: Option<usize>,
+    pub max_description: Option<usize>,
+    pub overrides: Vec<String>,
+    /// Glob over tool names, or over `<mcp id>/<tool>` if it contains a `/`
+    pub filter: Option<String>,
+}
+
+impl Shaping {
+    /// The shaping set in an agent's `config`
+    pub fn of_agent(config: &Value) -> Result<Self, String> {
+        let count = |key: &str| match config.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => value
+                .as_u64()
+                .filter(|count| *count > 0)
+                .map(|count| Some(count as usize))
+                .ok_or_else(|| format!("config.{} must be a positive integer", key)),
+        };
+        Ok(Self {
+            max_tools: count(MAX_TOOLS_KEY)?,
+            max_description: count(MAX_DESCRIPTION_KEY)?,
+            overrides: names(config, PRIORITY_OVERRIDES_KEY)?,
+            filter: None,
+        })
+    }
+
+    fn is_active(&self) -> bool {
+        self.max_tools.is_some() || self.max_description.is_some() || self.filter.is_some()
+    }
+}
+
+/// The tool names of a `tool_priority` or `tool_priority_overrides` list
+pub fn names(config: &Value, key: &str) -> Result<Vec<String>, String> {
+    let invalid = || format!("config.{} must be a list of strings", key);
+    match config.get(key) {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::Array(names)) => names
+            .iter()
+            .map(|name| name.as_str().map(str::to_string).ok_or_else(invalid))
+            .collect(),
+        Some(_) => Err(invalid()),
+    }
+}
+
+/// What shaping left out of a listing, so clients can ask for it specifically
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ShapingReport {
+    /// Tools listed by the MCPs
+    pub listed: usize,
+    pub returned: usize,
+    /// Left out tools by reason, `filter` or `max_tools`
+    pub omitted: BTreeMap<&'static str, usize>,
+    /// `<mcp id>/<tool>` of the tools beyond `max_tools`, lowest priority
+    /// last, for a follow-up request with `?filter=`
+    pub omitted_tools: Vec<String>,
+    /// Tools whose description was cut to `max_tool_description`
+    pub truncated_descriptions: usize,
+}
+
+/// Cut `tools` down as `shaping` says. Tools are ranked by the agent's
+/// overrides, then each MCP's `tool_priority` from `priorities`, then MCP id
+/// and listing order, and kept in that order. `None` if there was nothing to
+/// shape.
+pub fn shape(
+    tools: &mut BTreeMap<String, Vec<McpTool>>,
+    priorities: &BTreeMap<String, Vec<String>>,
+    shaping: &Shaping,
+) -> Option<ShapingReport> {
+    if !shaping.is_active() {
+        return None;
+    }
+    let mut report = ShapingReport::default();
+    let mut ranked = Vec::new();
+    for (mcp_id, listed) in std::mem::take(tools) {
+        report.listed += listed.len();
+        let priority = priorities.get(&mcp_id).map(Vec::as_slice).unwrap_or(&[]);
+        for (at, tool) in listed.into_iter().enumerate() {
+            let qualified = tools::namespaced(&mcp_id, &tool.name);
+            if let Some(filter) = &shaping.filter {
+                let subject = if filter.contains('/') {
+                    &qualified
+                } else {
+                    &tool.name
+                };
+                if !glob(filter, subject) {
+                    *report.omitted.entry("filter").or_default() += 1;
+                    continue;
+                }
+            }
+            let rank = (
+                rank(&shaping.overrides, |entry| {
+                    entry == &qualified || entry == &mcp_id
+                }),
+                rank(priority, |name| name == &tool.name),
+                mcp_id.clone(),
+                at,
+            );
+            ranked.push((rank, qualified, mcp_id.clone(), tool));
+        }
+    }
+    ranked.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let keep = shaping.max_tools.unwrap_or(usize::MAX);
+    for (at, (_, qualified, mcp_id, mut tool)) in ranked.into_iter().enumerate() {
+        if at >= keep {
+            *report.omitted.entry("max_tools").or_default() += 1;
+            report.omitted_tools.push(qualified);
+            continue;
+        }
+        if let Some(max) = shaping.max_description
+            && tool.description.chars().count() > max
+        {
+            tool.description = tool.description.chars().take(max).collect::<String>() + "…";
+            report.truncated_descriptions += 1;
+        }
+        report.returned += 1;
+        tools.entry(mcp_id).or_default().push(tool);
+    }
+    Some(report)
+}
+
+/// Position of the first entry `matches`, after every listed tool of any
+/// MCP if none does
+fn rank(entries: &[String], matches: impl Fn(&String) -> bool) -> usize {
+    entries.iter().position(matches).unwrap_or(usize::MAX)
+}
+
+/// Match `*` (any run of characters) and `?` (one character) glob patterns
+pub fn glob(pattern: &str, subject: &str) -> bool {
+    let (pattern, subject): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), subject.chars().collect());
+    let (mut p, mut s) = (0, 0);
+    // Where the last `*` was and how much of the subject it took so far
+    let mut star: Option<(usize, usize)> = None;
+    while s < subject.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, s));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == subject[s] => {
+                p += 1;
+                s += 1;
+            }
+            _ => match star {
+                Some((star_p, star_s)) => {
+                    p = star_p + 1;
+                    s = star_s + 1;
+                    star = Some((star_p, star_s + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
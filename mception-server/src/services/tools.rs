@@ -0,0 +1,487 @@
+use crate::core::{LeafMcpConfig, MceptionResult, McpTool, McpTransport, NetworkError};
+use crate::services::connections::ConnectionService;
+use crate::services::https::{HttpsForwarder, StreamedResponse};
+use crate::services::internals::{DEFAULT_TOOL_CACHE_CAPACITY, ToolCacheUsage};
+use crate::services::leaf_sessions::LeafSessions;
+use crate::services::sse::SseConnections;
+use crate::services::stdio::{self, StdioProcesses};
+use crate::services::tool_shaping::ShapingReport;
+use crate::services::{builtin_mcp, deadline, json_schema};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tracing::debug;
+
+/// How long a leaf MCP's tool list is served from memory
+pub const TOOL_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// How long listing a leaf MCP's tools may take unless its `config` sets a
+/// `timeout`, short so a hung leaf MCP doesn't hold up the admin API
+pub const DEFAULT_LIST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Upper bound of `tools/list` pages followed, against servers repeating a cursor
+const MAX_PAGES: usize = 100;
+
+/// Tools of a leaf MCP, as last listed
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolListing {
+    pub tools: Vec<McpTool>,
+    pub fetched_at: DateTime<Utc>,
+    /// Whether the listing was served from the cache
+    pub cached: bool,
+}
+
+/// Tools of the MCPs an agent may use, by MCP id
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AgentTools {
+    pub tools: BTreeMap<String, Vec<McpTool>>,
+    /// The tools of `tools` in one list, each named `<mcp_id>/<tool>` so
+    /// tools of the same name from different MCPs don't collide
+    pub merged: Vec<McpTool>,
+    /// MCPs whose tools could not be listed, with the reason. Their tools are
+    /// missing from `tools`.
+    pub errors: BTreeMap<String, String>,
+    /// What the agent's shaping settings or the filter left out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shaping: Option<ShapingReport>,
+}
+
+struct CachedListing {
+    /// Transport the tools were listed through, to notice configuration changes
+    transport: Value,
+    listing: ToolListing,
+    /// Size of the listing as JSON
+    approx_bytes: usize,
+}
+
+/// Tool lists of leaf MCPs, kept for [`TOOL_CACHE_TTL`] so repeated requests
+/// don't reach the leaf MCP each time. Beyond its capacity the longest cached
+/// listing is evicted.
+pub struct ToolCache {
+    entries: Mutex<HashMap<String, CachedListing>>,
+    capacity: usize,
+    evictions: AtomicU64,
+}
+
+impl Default for ToolCache {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_TOOL_CACHE_CAPACITY)
+    }
+}
+
+impl ToolCache {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            capacity,
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// The cached tools of a leaf MCP, if listed within the TTL with its
+    /// current transport
+    pub fn get(&self, leaf_id: &str, leaf: &LeafMcpConfig) -> Option<ToolListing> {
+        let transport = json!(leaf.transport);
+        let entries = self.entries.lock().unwrap();
+        let cached = entries.get(leaf_id)?;
+        let age = (Utc::now() - cached.listing.fetched_at).to_std().ok()?;
+        (cached.transport == transport && age < TOOL_CACHE_TTL).then(|| ToolListing {
+            cached: true,
+            ..cached.listing.clone()
+        })
+    }
+
+    pub fn insert(&self, leaf_id: &str, leaf: &LeafMcpConfig, listing: &ToolListing) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.contains_key(leaf_id) && entries.len() >= self.capacity {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, cached)| cached.listing.fetched_at)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        entries.insert(
+            leaf_id.to_string(),
+            CachedListing {
+                transport: json!(leaf.transport),
+                listing: listing.clone(),
+                approx_bytes: json!(listing.tools).to_string().len(),
+            },
+        );
+    }
+
+    pub fn usage(&self) -> ToolCacheUsage {
+        let entries = self.entries.lock().unwrap();
+        ToolCacheUsage {
+            entries: entries.len(),
+            capacity: self.capacity,
+            approx_bytes: entries.values().map(|cached| cached.approx_bytes).sum(),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// List the tools of a leaf MCP by asking it with `tools/list`, following
+/// pagination cursors, within the leaf MCP's timeout or [`DEFAULT_LIST_TIMEOUT`].
+/// An HTTPS leaf MCP is asked within the session kept in `sessions` if it
+/// still accepts it, otherwise it is initialized again.
+pub async fn fetch(
+    leaf_id: &str,
+    leaf: &LeafMcpConfig,
+    stdio_processes: &StdioProcesses,
+    sse_connections: &SseConnections,
+    https_forwarder: &HttpsForwarder,
+    sessions: &LeafSessions,
+) -> MceptionResult<ToolListing> {
+    list(Upstream::Leaf {
+        id: leaf_id,
+        leaf,
+        stdio_processes,
+        sse_connections,
+        https_forwarder,
+        sessions,
+        timeout: deadline::own_timeout(leaf).unwrap_or(DEFAULT_LIST_TIMEOUT),
+    })
+    .await
+}
+
+/// Send a JSON-RPC request of the server's own to a leaf MCP and return the
+/// response, `null` if it gave none, within the leaf MCP's timeout or
+/// [`deadline::DEFAULT_LEAF_TIMEOUT`]. An HTTPS leaf MCP is sent the request
+/// in a new session, so a kept one that expired can't make the request fail
+/// or be sent twice. A JSON-RPC error is returned as the response.
+pub async fn send(
+    leaf_id: &str,
+    leaf: &LeafMcpConfig,
+    stdio_processes: &StdioProcesses,
+    sse_connections: &SseConnections,
+    https_forwarder: &HttpsForwarder,
+    sessions: &LeafSessions,
+    request: &Value,
+) -> MceptionResult<Value> {
+    let upstream = Upstream::Leaf {
+        id: leaf_id,
+        leaf,
+        stdio_processes,
+        sse_connections,
+        https_forwarder,
+        sessions,
+        timeout: deadline::leaf_timeout(leaf, deadline::DEFAULT_LEAF_TIMEOUT),
+    };
+    let mut session = initialize(&upstream).await?;
+    let response = upstream.call(&mut session, request).await?;
+    Ok(response.unwrap_or(Value::Null))
+}
+
+/// Send a JSON-RPC request like [`send`], passing the `result` of an HTTPS
+/// leaf MCP on as it arrives, see [`HttpsForwarder::call_streamed`]. Other
+/// transports deliver their response as one message, it is returned whole.
+pub async fn send_streamed(
+    leaf_id: &str,
+    leaf: &LeafMcpConfig,
+    stdio_processes: &StdioProcesses,
+    sse_connections: &SseConnections,
+    https_forwarder: &HttpsForwarder,
+    sessions: &LeafSessions,
+    request: &Value,
+) -> MceptionResult<StreamedResponse> {
+    let timeout = deadline::leaf_timeout(leaf, deadline::DEFAULT_LEAF_TIMEOUT);
+    let upstream = Upstream::Leaf {
+        id: leaf_id,
+        leaf,
+        stdio_processes,
+        sse_connections,
+        https_forwarder,
+        sessions,
+        timeout,
+    };
+    let mut session = initialize(&upstream).await?;
+    match &leaf.transport {
+        McpTransport::Https { url, headers } => {
+            https_forwarder
+                .call_streamed(url, headers.as_ref(), session.as_deref(), request, timeout)
+                .await
+        }
+        _ => {
+            let response = upstream.call(&mut session, request).await?;
+            Ok(StreamedResponse::Whole(response.unwrap_or(Value::Null)))
+        }
+    }
+}
+
+impl AgentTools {
+    /// Fill `merged` from `tools`
+    pub fn merge(&mut self) {
+        self.merged = self
+            .tools
+            .iter()
+            .flat_map(|(mcp_id, tools)| {
+                tools.iter().map(move |tool| McpTool {
+                    name: namespaced(mcp_id, &tool.name),
+                    ..tool.clone()
+                })
+            })
+            .collect();
+    }
+}
+
+/// The name of `tool` of `mcp_id` among the tools of several MCPs
+pub fn namespaced(mcp_id: &str, tool: &str) -> String {
+    format!("{}/{}", mcp_id, tool)
+}
+
+/// The MCP id and tool name of a [`namespaced`] name. MCP ids don't contain
+/// `/`, tool names may.
+pub fn split_namespaced(name: &str) -> Option<(&str, &str)> {
+    name.split_once('/')
+        .filter(|(mcp_id, tool)| !mcp_id.is_empty() && !tool.is_empty())
+}
+
+/// A call of a tool by its name
+#[derive(Debug, Clone)]
+pub struct ToolCall {
+    pub name: String,
+    pub arguments: Value,
+}
+
+impl ToolCall {
+    /// The `tools/call` request making the call
+    pub fn request(&self) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": { "name": self.name, "arguments": self.arguments }
+        })
+    }
+}
+
+/// Where `arguments` don't satisfy the tool's `parameters` schema, see
+/// [`json_schema::errors`]. Empty if they do.
+pub fn argument_errors(tool: &McpTool, arguments: &Value) -> Vec<String> {
+    json_schema::errors(&tool.parameters, arguments, "arguments")
+}
+
+/// List the tools of the MCP an agent exposes, asking it over its WebSocket
+pub async fn fetch_agent(
+    agent_id: &str,
+    connections: &ConnectionService,
+    timeout: Duration,
+) -> MceptionResult<ToolListing> {
+    list(Upstream::Agent {
+        id: agent_id,
+        connections,
+        timeout,
+    })
+    .await
+}
+
+/// An MCP tools are listed from
+enum Upstream<'a> {
+    Leaf {
+        id: &'a str,
+        leaf: &'a LeafMcpConfig,
+        stdio_processes: &'a StdioProcesses,
+        sse_connections: &'a SseConnections,
+        https_forwarder: &'a HttpsForwarder,
+        sessions: &'a LeafSessions,
+        timeout: Duration,
+    },
+    Agent {
+        id: &'a str,
+        connections: &'a ConnectionService,
+        timeout: Duration,
+    },
+}
+
+impl Upstream<'_> {
+    /// Whether the MCP is reached over streamable HTTP, which needs the
+    /// `initialize` handshake and answers only within the session it assigns
+    fn is_http(&self) -> bool {
+        match self {
+            Upstream::Leaf { leaf, .. } => matches!(leaf.transport, McpTransport::Https { .. }),
+            Upstream::Agent { .. } => true,
+        }
+    }
+
+    /// Send a JSON-RPC message and return the response, if any
+    async fn call(
+        &self,
+        session: &mut Option<String>,
+        message: &Value,
+    ) -> MceptionResult<Option<Value>> {
+        match self {
+            Upstream::Leaf {
+                id,
+                leaf,
+                stdio_processes,
+                sse_connections,
+                https_forwarder,
+                timeout,
+                ..
+            } => match &leaf.transport {
+                McpTransport::Builtin { kind } => Ok(builtin_mcp::handle(*kind, message).await),
+                McpTransport::Stdio { .. } => {
+                    tokio::time::timeout(*timeout, stdio_processes.forward(id, leaf, message))
+                        .await
+                        .map_err(|_| {
+                            NetworkError::Timeout(format!(
+                                "No response within {} ms",
+                                timeout.as_millis()
+                            ))
+                        })?
+                }
+                McpTransport::Sse { .. } => {
+                    tokio::time::timeout(*timeout, sse_connections.forward(id, leaf, message))
+                        .await
+                        .map_err(|_| {
+                            NetworkError::Timeout(format!(
+                                "No response within {} ms",
+                                timeout.as_millis()
+                            ))
+                        })?
+                }
+                McpTransport::Https { url, headers } => https_forwarder
+                    .call(url, headers.as_ref(), session, message, *timeout)
+                    .await
+                    .map(Some),
+            },
+            Upstream::Agent {
+                id,
+                connections,
+                timeout,
+            } => connections
+                .call(id, session, message, *timeout)
+                .await
+                .map(Some),
+        }
+    }
+}
+
+async fn list(upstream: Upstream<'_>) -> MceptionResult<ToolListing> {
+    if let Upstream::Leaf {
+        id, leaf, sessions, ..
+    } = &upstream
+        && let Some(kept) = sessions.get(id, &leaf.transport)
+    {
+        match list_tools(&upstream, &mut Some(kept)).await {
+            Ok(tools) => {
+                sessions.used(id);
+                return Ok(ToolListing {
+                    tools,
+                    fetched_at: Utc::now(),
+                    cached: false,
+                });
+            }
+            Err(e) => {
+                debug!("Leaf MCP '{}' refused its kept session: {}", id, e);
+                sessions.forget(id);
+            }
+        }
+    }
+
+    let mut session = initialize(&upstream).await?;
+    Ok(ToolListing {
+        tools: list_tools(&upstream, &mut session).await?,
+        fetched_at: Utc::now(),
+        cached: false,
+    })
+}
+
+/// Open a new session with an MCP reached over streamable HTTP, kept in
+/// `sessions` for a leaf MCP. Other MCPs need none.
+async fn initialize(upstream: &Upstream<'_>) -> MceptionResult<Option<String>> {
+    let mut session = None;
+    if upstream.is_http() {
+        let initialize = json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": stdio::PROTOCOL_VERSION,
+                "capabilities": {},
+                "clientInfo": { "name": "mception-server", "version": env!("CARGO_PKG_VERSION") }
+            }
+        });
+        result(
+            upstream
+                .call(&mut session, &initialize)
+                .await?
+                .unwrap_or(Value::Null),
+        )?;
+        let initialized = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        upstream.call(&mut session, &initialized).await?;
+        if let Upstream::Leaf {
+            id, leaf, sessions, ..
+        } = &upstream
+            && let Some(session) = session.clone()
+        {
+            sessions.established(id, &leaf.transport, session);
+        }
+    }
+    Ok(session)
+}
+
+/// Follow the pages of `tools/list` within `session`
+async fn list_tools(
+    upstream: &Upstream<'_>,
+    session: &mut Option<String>,
+) -> MceptionResult<Vec<McpTool>> {
+    let mut tools = Vec::new();
+    let mut cursor: Option<String> = None;
+    for page in 1..=MAX_PAGES {
+        let mut request = json!({ "jsonrpc": "2.0", "id": page, "method": "tools/list" });
+        if let Some(cursor) = &cursor {
+            request["params"] = json!({ "cursor": cursor });
+        }
+        let response = upstream.call(session, &request).await?;
+        let result = result(response.unwrap_or(Value::Null))?;
+        let listed = result["tools"].as_array().ok_or_else(|| {
+            NetworkError::ConnectionFailed("tools/list result has no tools".to_string())
+        })?;
+        tools.extend(listed.iter().filter_map(tool));
+        cursor = result["nextCursor"].as_str().map(str::to_string);
+        if cursor.is_none() {
+            break;
+        }
+    }
+    Ok(tools)
+}
+
+/// The result of a JSON-RPC response, or its error
+pub fn result(mut response: Value) -> MceptionResult<Value> {
+    if let Some(error) = response.get("error") {
+        return Err(NetworkError::ConnectionFailed(format!(
+            "Leaf MCP answered with error {}: {}",
+            error["code"],
+            error["message"].as_str().unwrap_or_default()
+        ))
+        .into());
+    }
+    match response.get_mut("result") {
+        Some(result) => Ok(result.take()),
+        None => Err(NetworkError::ConnectionFailed(
+            "Leaf MCP answered without a result".to_string(),
+        )
+        .into()),
+    }
+}
+
+/// A tool of a `tools/list` result. Tools without a name are skipped.
+fn tool(tool: &Value) -> Option<McpTool> {
+    Some(McpTool {
+        name: tool["name"].as_str()?.to_string(),
+        description: tool["description"].as_str().unwrap_or_default().to_string(),
+        parameters: tool["inputSchema"].clone(),
+    })
+}
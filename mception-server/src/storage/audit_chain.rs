@@ -0,0 +1,237 @@
+use crate::core::{AuditAction, AuditLogEntry, AuditTarget};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+
+/// Digest of an entry chained to its predecessor: SHA-256 over the entry as
+/// compact JSON with sorted keys and without its `hash`, followed by the
+/// predecessor's hash
+pub fn entry_hash(entry: &Value, prev_hash: Option<&str>) -> String {
+    let mut entry = entry.clone();
+    if let Some(object) = entry.as_object_mut() {
+        object.remove("hash");
+    }
+    let mut hasher = Sha256::new();
+    // Objects are sorted maps, so keys serialize in order
+    hasher.update(entry.to_string().as_bytes());
+    hasher.update(prev_hash.unwrap_or_default().as_bytes());
+    let hex: String = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("sha256:{}", hex)
+}
+
+/// Link `entry`, numbered already, to the entry before it by setting its
+/// `prev_hash` and `hash`
+pub fn chain(entry: &mut AuditLogEntry, prev_hash: Option<String>) {
+    entry.prev_hash = prev_hash;
+    entry.hash = None;
+    let value = serde_json::to_value(&*entry).unwrap_or_default();
+    entry.hash = Some(entry_hash(&value, entry.prev_hash.as_deref()));
+}
+
+/// Result of walking the hash chain of an audit log
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditChainReport {
+    pub location: String,
+    pub entries: usize,
+    /// Entries written before the log was chained, at its start
+    pub legacy_entries: usize,
+    /// The first hashed entry, where the chain starts
+    pub chain_root: Option<ChainLink>,
+    /// The first entry that doesn't link to the one before, if any
+    pub broken_link: Option<BrokenLink>,
+    /// Repair markers where the chain starts anew, see [`reanchor`]
+    pub restarts: Vec<ChainLink>,
+}
+
+impl AuditChainReport {
+    pub fn is_intact(&self) -> bool {
+        self.broken_link.is_none()
+    }
+}
+
+/// Where an entry is in the audit log
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainLink {
+    /// Line of the entry, counted from 1 at the oldest rotated segment; the
+    /// row for SQLite
+    pub line: usize,
+    pub sequence: u64,
+    pub id: String,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+/// An entry breaking the chain and why
+#[derive(Debug, Clone, Serialize)]
+pub struct BrokenLink {
+    #[serde(flatten)]
+    pub entry: ChainLink,
+    pub reason: String,
+}
+
+/// Walk the chain of `lines`, the audit log's entries oldest first with
+/// their line numbers, up to the first broken link. Entries without a hash
+/// are legacy ones as long as no hashed entry came before, the first hashed
+/// entry starts the chain.
+pub fn verify(
+    location: String,
+    lines: impl IntoIterator<Item = (usize, String)>,
+) -> AuditChainReport {
+    let mut report = AuditChainReport {
+        location,
+        entries: 0,
+        legacy_entries: 0,
+        chain_root: None,
+        broken_link: None,
+        restarts: Vec::new(),
+    };
+    let mut previous: Option<(u64, String)> = None;
+    for (line, text) in lines {
+        let value: Value = match serde_json::from_str(&text) {
+            Ok(value) => value,
+            Err(e) => {
+                report.broken_link = Some(BrokenLink {
+                    entry: ChainLink {
+                        line,
+                        sequence: 0,
+                        id: String::new(),
+                        timestamp: None,
+                    },
+                    reason: format!("not an audit log entry: {}", e),
+                });
+                break;
+            }
+        };
+        let link = ChainLink {
+            line,
+            sequence: value["sequence"].as_u64().unwrap_or_default(),
+            id: value["id"].as_str().unwrap_or_default().to_string(),
+            timestamp: serde_json::from_value(value["timestamp"].clone()).ok(),
+        };
+        let hash = value["hash"].as_str().map(str::to_string);
+        let prev_hash = value["prev_hash"].as_str();
+
+        // An entry left twice by an interrupted rotation
+        if let (Some((sequence, previous_hash)), Some(hash)) = (&previous, &hash)
+            && *sequence == link.sequence
+            && previous_hash == hash
+        {
+            continue;
+        }
+        report.entries += 1;
+        // A repair marker is a new root, vouching for the entries after it
+        let restarts = is_repair_marker(&value) && prev_hash.is_none() && hash.is_some();
+        let expected = if restarts { None } else { previous.as_ref() };
+        let broken = match (&hash, expected) {
+            (None, None) => {
+                report.legacy_entries += 1;
+                None
+            }
+            (None, Some(_)) => Some("entry has no hash, but entries before it do".to_string()),
+            // Entries before the root may have been pruned with their segments
+            (Some(_), None) if report.legacy_entries > 0 && prev_hash.is_some() => {
+                Some("prev_hash names an entry that isn't in the log".to_string())
+            }
+            (Some(_), Some((_, previous_hash))) if prev_hash != Some(previous_hash.as_str()) => {
+                Some(format!(
+                    "prev_hash doesn't match the hash of the entry before, {}",
+                    previous_hash
+                ))
+            }
+            (Some(hash), _) if *hash != entry_hash(&value, prev_hash) => {
+                Some("hash doesn't match the entry's content".to_string())
+            }
+            (Some(_), _) => None,
+        };
+        if let Some(reason) = broken {
+            report.broken_link = Some(BrokenLink {
+                entry: link,
+                reason,
+            });
+            break;
+        }
+        if let Some(hash) = hash {
+            if previous.is_none() {
+                report.chain_root = Some(link.clone());
+            } else if restarts {
+                report.restarts.push(link.clone());
+            }
+            previous = Some((link.sequence, hash));
+        }
+    }
+    report
+}
+
+fn is_repair_marker(entry: &Value) -> bool {
+    entry["action"]["type"] == "audit_repair"
+}
+
+/// Chain the valid entries salvaged from the audit log at `location` again,
+/// after `repair` dropped the corrupt ones. Entries are kept as they are up
+/// to the first one no longer linking to the one before, where an
+/// [`AuditAction::AuditRepair`] marker starts a new chain that the entries
+/// after it are linked to anew. With nothing broken the marker is appended
+/// if entries were dropped. Logs without hashed entries are left alone.
+pub fn reanchor(location: &str, entries: Vec<String>, dropped: bool) -> Vec<String> {
+    let parsed: Vec<AuditLogEntry> = entries
+        .iter()
+        .filter_map(|entry| serde_json::from_str(entry).ok())
+        .collect();
+    if parsed.len() != entries.len() || parsed.iter().all(|entry| entry.hash.is_none()) {
+        return entries;
+    }
+    let report = verify(
+        location.to_string(),
+        entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| (index + 1, entry.clone())),
+    );
+    let kept = match &report.broken_link {
+        Some(broken) => broken.entry.line - 1,
+        None if dropped => entries.len(),
+        None => return entries,
+    };
+
+    let before = kept.checked_sub(1).map(|index| &parsed[index]);
+    let mut marker = AuditLogEntry {
+        id: uuid::Uuid::new_v4().to_string(),
+        sequence: before.map_or(1, |entry| entry.sequence + 1),
+        // Keeps the timestamps in order, when the repair happened is in
+        // the details
+        timestamp: before
+            .or(parsed.get(kept))
+            .map_or_else(Utc::now, |entry| entry.timestamp),
+        action: AuditAction::AuditRepair,
+        actor: Some("system".to_string()),
+        target: AuditTarget::Server,
+        reason: None,
+        details: json!({
+            "repaired_from": location,
+            "repaired_at": Utc::now(),
+            "broken_link": report.broken_link,
+            "relinked_entries": entries.len() - kept,
+        }),
+        correlation_id: None,
+        idempotency_key: None,
+        revision: None,
+        server_revision: None,
+        prev_hash: None,
+        hash: None,
+    };
+    chain(&mut marker, None);
+
+    let mut prev_hash = marker.hash.clone();
+    let mut repaired = entries[..kept].to_vec();
+    repaired.push(serde_json::to_string(&marker).unwrap_or_default());
+    for mut entry in parsed.into_iter().skip(kept) {
+        chain(&mut entry, prev_hash);
+        prev_hash = entry.hash.clone();
+        repaired.push(serde_json::to_string(&entry).unwrap_or_default());
+    }
+    repaired
+}
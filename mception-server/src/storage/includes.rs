@@ -0,0 +1,218 @@
+use crate::core::{AgentConfig, LeafMcpConfig, ServerConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The leaf MCPs and agents of a file included by the configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFragment {
+    #[serde(default)]
+    pub leaf_mcps: BTreeMap<String, LeafMcpConfig>,
+    #[serde(default)]
+    pub agents: BTreeMap<String, AgentConfig>,
+}
+
+/// Which included file each leaf MCP and agent of a resolved configuration
+/// came from. Anything not in here belongs to the configuration file itself.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    /// Every included file, in include order
+    pub fragments: Vec<PathBuf>,
+    pub leaf_mcps: BTreeMap<String, PathBuf>,
+    pub agents: BTreeMap<String, PathBuf>,
+}
+
+/// Result of checking one included file
+#[derive(Debug, Clone, Serialize)]
+pub struct FragmentCheck {
+    /// The file, relative to the configuration file's directory
+    pub file: String,
+    pub leaf_mcps: usize,
+    pub agents: usize,
+    /// Why the file can't be included, empty if it can
+    pub errors: Vec<String>,
+}
+
+/// The files matching the include `patterns`, relative to `base_dir`. Only the
+/// file name may contain the wildcards `*` and `?`. Matches of a pattern are
+/// ordered by name and patterns keep their order, so the result doesn't
+/// depend on the file system. A file matched twice is included once.
+pub fn expand(base_dir: &Path, patterns: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for pattern in patterns {
+        let relative = Path::new(pattern);
+        let Some(name_pattern) = relative.file_name().and_then(|name| name.to_str()) else {
+            return Err(format!("Include '{}' doesn't name a file", pattern));
+        };
+        let parent = relative.parent().unwrap_or(Path::new(""));
+        if parent.to_string_lossy().contains(['*', '?']) {
+            return Err(format!(
+                "Include '{}' may only have wildcards in its file name",
+                pattern
+            ));
+        }
+
+        let dir = base_dir.join(parent);
+        let mut matched = Vec::new();
+        if name_pattern.contains(['*', '?']) {
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("Failed to list {}: {}", dir.display(), e)),
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if entry.path().is_file() && wildcard_match(name_pattern, &name) {
+                    matched.push(entry.path());
+                }
+            }
+            matched.sort();
+        } else {
+            let path = dir.join(name_pattern);
+            if !path.is_file() {
+                return Err(format!("Included file '{}' does not exist", pattern));
+            }
+            matched.push(path);
+        }
+
+        for path in matched {
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Whether `name` matches `pattern`, where `*` stands for any run of
+/// characters and `?` for a single one
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was and how much of the name it covers so far
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Directory of the configuration file at `config_path`, which includes are
+/// relative to
+pub fn config_dir(config_path: &Path) -> PathBuf {
+    match config_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// `path` as written in messages, relative to the configuration file's directory
+pub fn display(base_dir: &Path, path: &Path) -> String {
+    path.strip_prefix(base_dir)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Merge the `fragments` into `config`, read from `config_path`. Fails
+/// naming both files if an id is defined twice.
+pub fn resolve(
+    config: &mut ServerConfig,
+    config_path: &Path,
+    fragments: Vec<(PathBuf, ConfigFragment)>,
+) -> Result<SourceMap, String> {
+    let base_dir = &config_dir(config_path);
+    let defined_in = |sources: &BTreeMap<String, PathBuf>, id: &str| {
+        display(
+            base_dir,
+            sources.get(id).map_or(config_path, PathBuf::as_path),
+        )
+    };
+
+    let mut sources = SourceMap::default();
+    for (path, fragment) in fragments {
+        for (id, leaf_mcp) in fragment.leaf_mcps {
+            if config.leaf_mcps.contains_key(&id) {
+                return Err(format!(
+                    "Leaf MCP '{}' is defined in both {} and {}",
+                    id,
+                    defined_in(&sources.leaf_mcps, &id),
+                    display(base_dir, &path)
+                ));
+            }
+            config.leaf_mcps.insert(id.clone(), leaf_mcp);
+            sources.leaf_mcps.insert(id, path.clone());
+        }
+        for (id, agent) in fragment.agents {
+            if config.agents.contains_key(&id) {
+                return Err(format!(
+                    "Agent '{}' is defined in both {} and {}",
+                    id,
+                    defined_in(&sources.agents, &id),
+                    display(base_dir, &path)
+                ));
+            }
+            config.agents.insert(id.clone(), agent);
+            sources.agents.insert(id, path.clone());
+        }
+        sources.fragments.push(path);
+    }
+    Ok(sources)
+}
+
+/// Split a resolved configuration into what goes back to the configuration
+/// file and to each included file. Leaf MCPs and agents go back where they
+/// came from, new ones to the configuration file.
+pub fn split(
+    config: &ServerConfig,
+    sources: &SourceMap,
+) -> (ServerConfig, Vec<(PathBuf, ConfigFragment)>) {
+    let mut main = config.clone();
+    let mut fragments: BTreeMap<&Path, ConfigFragment> = sources
+        .fragments
+        .iter()
+        .map(|path| (path.as_path(), ConfigFragment::default()))
+        .collect();
+    for (id, path) in &sources.leaf_mcps {
+        if let Some(fragment) = fragments.get_mut(path.as_path())
+            && let Some(leaf_mcp) = main.leaf_mcps.remove(id)
+        {
+            fragment.leaf_mcps.insert(id.clone(), leaf_mcp);
+        }
+    }
+    for (id, path) in &sources.agents {
+        if let Some(fragment) = fragments.get_mut(path.as_path())
+            && let Some(agent) = main.agents.remove(id)
+        {
+            fragment.agents.insert(id.clone(), agent);
+        }
+    }
+    let fragments = sources
+        .fragments
+        .iter()
+        .map(|path| {
+            let fragment = fragments.remove(path.as_path()).unwrap_or_default();
+            (path.clone(), fragment)
+        })
+        .collect();
+    (main, fragments)
+}
@@ -0,0 +1,255 @@
+use crate::core::{
+    AgentConfig, AuditPolicy, BundleConfig, LeafMcpConfig, MceptionResult, RegistrationPolicy,
+    ServerConfig, StorageError,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tracing::warn;
+
+/// A change to one configuration object. Puts carry the full new state, so
+/// applying a change twice has the same effect as applying it once. Leaf
+/// MCPs and agents are boxed, being by far the largest objects.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ConfigChange {
+    PutLeafMcp {
+        id: String,
+        config: Box<LeafMcpConfig>,
+    },
+    DeleteLeafMcp {
+        id: String,
+    },
+    PutAgent {
+        id: String,
+        config: Box<AgentConfig>,
+    },
+    DeleteAgent {
+        id: String,
+    },
+    PutBundle {
+        name: String,
+        config: BundleConfig,
+    },
+    DeleteBundle {
+        name: String,
+    },
+    SetRegistrationPolicy {
+        policy: Option<RegistrationPolicy>,
+    },
+    SetAuditPolicy {
+        policy: AuditPolicy,
+    },
+}
+
+impl ConfigChange {
+    pub fn apply(&self, config: &mut ServerConfig) {
+        match self {
+            ConfigChange::PutLeafMcp { id, config: leaf } => {
+                config.leaf_mcps.insert(id.clone(), leaf.as_ref().clone());
+            }
+            ConfigChange::DeleteLeafMcp { id } => {
+                config.leaf_mcps.remove(id);
+            }
+            ConfigChange::PutAgent { id, config: agent } => {
+                config.agents.insert(id.clone(), agent.as_ref().clone());
+            }
+            ConfigChange::DeleteAgent { id } => {
+                config.agents.remove(id);
+            }
+            ConfigChange::PutBundle {
+                name,
+                config: bundle,
+            } => {
+                config.bundles.insert(name.clone(), bundle.clone());
+            }
+            ConfigChange::DeleteBundle { name } => {
+                config.bundles.remove(name);
+            }
+            ConfigChange::SetRegistrationPolicy { policy } => {
+                config.registration_policy = policy.clone();
+            }
+            ConfigChange::SetAuditPolicy { policy } => {
+                config.audit = policy.clone();
+            }
+        }
+    }
+}
+
+/// One committed mutation, as a line of the journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Configuration revision after the mutation
+    pub revision: u64,
+    pub timestamp: DateTime<Utc>,
+    /// The mutation, e.g. `update_agent`
+    pub operation: String,
+    pub changes: Vec<ConfigChange>,
+}
+
+impl JournalEntry {
+    /// Apply the entry, moving the configuration to its revision
+    pub fn apply(&self, config: &mut ServerConfig) {
+        for change in &self.changes {
+            change.apply(config);
+        }
+        config.metadata.revision = self.revision;
+        config.metadata.last_modified = self.timestamp;
+    }
+}
+
+/// The changes turning `before` into `after`
+pub fn diff(before: &ServerConfig, after: &ServerConfig) -> Vec<ConfigChange> {
+    let mut changes = Vec::new();
+    changes.extend(
+        diff_map(&before.leaf_mcps, &after.leaf_mcps)
+            .into_iter()
+            .map(|(id, leaf)| match leaf {
+                Some(config) => ConfigChange::PutLeafMcp {
+                    id,
+                    config: Box::new(config),
+                },
+                None => ConfigChange::DeleteLeafMcp { id },
+            }),
+    );
+    changes.extend(diff_map(&before.agents, &after.agents).into_iter().map(
+        |(id, agent)| match agent {
+            Some(config) => ConfigChange::PutAgent {
+                id,
+                config: Box::new(config),
+            },
+            None => ConfigChange::DeleteAgent { id },
+        },
+    ));
+    changes.extend(
+        diff_map(&before.bundles, &after.bundles)
+            .into_iter()
+            .map(|(name, bundle)| match bundle {
+                Some(config) => ConfigChange::PutBundle { name, config },
+                None => ConfigChange::DeleteBundle { name },
+            }),
+    );
+    if before.registration_policy != after.registration_policy {
+        changes.push(ConfigChange::SetRegistrationPolicy {
+            policy: after.registration_policy.clone(),
+        });
+    }
+    if before.audit != after.audit {
+        changes.push(ConfigChange::SetAuditPolicy {
+            policy: after.audit.clone(),
+        });
+    }
+    changes
+}
+
+/// Entries added or changed in `after` with their new value, and removed ones with `None`
+fn diff_map<T: Clone + Serialize>(
+    before: &BTreeMap<String, T>,
+    after: &BTreeMap<String, T>,
+) -> Vec<(String, Option<T>)> {
+    let as_value = |value: &T| serde_json::to_value(value).ok();
+    let mut changes: Vec<(String, Option<T>)> = after
+        .iter()
+        .filter(|(key, value)| {
+            before
+                .get(*key)
+                .is_none_or(|previous| as_value(previous) != as_value(value))
+        })
+        .map(|(key, value)| (key.clone(), Some(value.clone())))
+        .collect();
+    changes.extend(
+        before
+            .keys()
+            .filter(|key| !after.contains_key(*key))
+            .map(|key| (key.clone(), None)),
+    );
+    changes
+}
+
+/// Append-only file of committed configuration mutations. Each mutation is
+/// synced to the journal before it is acknowledged, and the journal is
+/// truncated once the full configuration was saved.
+#[derive(Debug)]
+pub struct ConfigJournal {
+    path: PathBuf,
+}
+
+impl ConfigJournal {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn location(&self) -> String {
+        format!("file:{}", self.path.display())
+    }
+
+    /// Durably append an entry
+    pub async fn append(&self, entry: &JournalEntry) -> MceptionResult<()> {
+        let mut line = serde_json::to_vec(entry).map_err(StorageError::from)?;
+        line.push(b'\n');
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(StorageError::from)?;
+        file.write_all(&line).await.map_err(StorageError::from)?;
+        file.sync_data().await.map_err(StorageError::from)?;
+        Ok(())
+    }
+
+    /// All entries in the journal, oldest first
+    pub async fn entries(&self) -> MceptionResult<Vec<JournalEntry>> {
+        let content = match fs::read_to_string(&self.path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(StorageError::from(e).into()),
+        };
+
+        let mut entries = Vec::new();
+        let lines: Vec<&str> = content.split_inclusive('\n').collect();
+        for (index, line) in lines.iter().enumerate() {
+            // A crash while appending leaves a partial last line. Its mutation
+            // was never acknowledged, so it is dropped.
+            if index == lines.len() - 1 && !line.ends_with('\n') {
+                warn!(
+                    "Ignoring incomplete last line of {}: {}",
+                    self.location(),
+                    line
+                );
+                break;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(line).map_err(|e| {
+                StorageError::Corruption(format!(
+                    "Line {} of {} is not a valid journal entry: {}",
+                    index + 1,
+                    self.location(),
+                    e
+                ))
+            })?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// Drop all entries, once the configuration containing them was saved
+    pub async fn truncate(&self) -> MceptionResult<()> {
+        match fs::OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&self.path)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(StorageError::from(e).into()),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use crate::core::MigrationInfo;
+use serde_json::Value;
+
+/// A schema migration of the stored configuration JSON
+pub struct Migration {
+    /// Schema version the configuration has after this migration
+    pub version: u32,
+    pub description: &'static str,
+    /// Rewrite the configuration in place. The schema version itself is
+    /// recorded by the migration runner.
+    pub apply: fn(&mut Value) -> Result<(), String>,
+}
+
+impl Migration {
+    pub fn info(&self) -> MigrationInfo {
+        MigrationInfo {
+            version: self.version,
+            description: self.description.to_string(),
+        }
+    }
+}
+
+/// All configuration schema migrations, ordered by version
+pub const CONFIG_MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "Record the schema version in the configuration metadata",
+    apply: |_| Ok(()),
+}];
+
+/// Schema version stored in a configuration, 0 if it predates schema versioning
+pub fn schema_version(config: &Value) -> u32 {
+    config
+        .pointer("/metadata/schema_version")
+        .and_then(Value::as_u64)
+        .map(|version| version as u32)
+        .unwrap_or(0)
+}
+
+/// Migrations that have to be applied to a configuration at `version`
+pub fn pending(version: u32) -> impl Iterator<Item = &'static Migration> {
+    CONFIG_MIGRATIONS
+        .iter()
+        .filter(move |migration| migration.version > version)
+}
+
+/// Apply a migration and record the resulting schema version
+pub fn apply(migration: &Migration, config: &mut Value) -> Result<(), String> {
+    (migration.apply)(config)?;
+    let metadata = config
+        .get_mut("metadata")
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| "configuration has no metadata object".to_string())?;
+    metadata.insert("schema_version".to_string(), Value::from(migration.version));
+    Ok(())
+}
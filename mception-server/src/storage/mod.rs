@@ -1 +1,6 @@
+pub mod audit_chain;
+pub mod includes;
+pub mod journal;
+pub mod migrations;
 pub mod providers;
+pub mod sections;
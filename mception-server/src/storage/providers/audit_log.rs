@@ -1,12 +1,54 @@
-use crate::core::{AuditLogEntry, MceptionResult};
+use crate::core::{AuditLogEntry, AuditQuery, AuditScanReport, MceptionResult};
+use crate::storage::audit_chain::AuditChainReport;
 use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+
+/// Where an appended entry ended up in the audit log
+#[derive(Debug, Clone, Copy)]
+pub struct AppendedEntry {
+    /// Sequence number assigned to the entry
+    pub sequence: u64,
+    /// How much earlier the entry's timestamp is than its predecessor's, if it
+    /// is earlier, e.g. because the clock was set back
+    pub behind_predecessor: Option<Duration>,
+}
 
 /// Trait for audit log storage providers
 #[async_trait]
 pub trait AuditStorage: Send + Sync {
-    /// Append a new audit log entry
-    async fn append_entry(&self, entry: &AuditLogEntry) -> MceptionResult<()>;
+    /// Human-readable location of the audit log, e.g. `file:audit.log`
+    fn location(&self) -> String;
 
-    /// Load all audit log entries
+    /// Append a new audit log entry under the next sequence number, ignoring
+    /// the entry's own `sequence`
+    async fn append_entry(&self, entry: &AuditLogEntry) -> MceptionResult<AppendedEntry>;
+
+    /// Load all audit log entries, ordered by sequence
     async fn load_entries(&self) -> MceptionResult<Vec<AuditLogEntry>>;
+
+    /// Load the entries matching `query`, ordered by sequence. Backends that
+    /// can filter while reading should, instead of loading every entry first.
+    async fn load_entries_filtered(
+        &self,
+        query: &AuditQuery,
+    ) -> MceptionResult<Vec<AuditLogEntry>> {
+        let mut entries = self.load_entries().await?;
+        entries.retain(|entry| query.matches(entry));
+        Ok(entries)
+    }
+
+    /// Delete entries older than `before` and return how many were deleted.
+    /// File storage deletes rotated segments entirely older than `before`,
+    /// backends that can't delete entries refuse.
+    async fn prune_entries(&self, before: DateTime<Utc>) -> MceptionResult<usize>;
+
+    /// Scan the audit log for corrupt entries without modifying it
+    async fn verify(&self) -> MceptionResult<AuditScanReport>;
+
+    /// Walk the hash chain of the audit log, see [`crate::storage::audit_chain`]
+    async fn verify_chain(&self) -> MceptionResult<AuditChainReport>;
+
+    /// Write a copy of the audit log containing only its valid entries, in
+    /// order, to `output`. The audit log itself is never modified.
+    async fn repair(&self, output: &str) -> MceptionResult<AuditScanReport>;
 }
@@ -1,18 +1,58 @@
-use crate::core::{ServerConfig, MceptionResult};
+use crate::core::{BackupInfo, MceptionResult, MigrationInfo, MigrationStatus, ServerConfig};
+use crate::storage::includes::FragmentCheck;
 use async_trait::async_trait;
 
 /// Trait for configuration storage providers
 #[async_trait]
 pub trait ConfigStorage: Send + Sync {
+    /// Human-readable location of the stored configuration, e.g. `file:config.json`
+    fn location(&self) -> String;
+
+    /// File the configuration is stored in, for storage kept in one. Unlike
+    /// `location`, usable as a path.
+    fn path(&self) -> Option<std::path::PathBuf> {
+        None
+    }
+
     /// Load the server configuration from storage
     async fn load_config(&self) -> MceptionResult<ServerConfig>;
-    
+
+    /// Load the configuration as stored, without merging in what it `includes`
+    async fn load_unresolved_config(&self) -> MceptionResult<ServerConfig> {
+        self.load_config().await
+    }
+
     /// Save the server configuration to storage
     async fn save_config(&self, config: &ServerConfig) -> MceptionResult<()>;
-    
+
     /// Check if configuration exists in storage
     async fn config_exists(&self) -> MceptionResult<bool>;
-    
+
     /// Create a backup of the current configuration
     async fn backup_config(&self) -> MceptionResult<String>;
+
+    /// List all stored backups, oldest first
+    async fn list_backups(&self) -> MceptionResult<Vec<BackupInfo>>;
+
+    /// Reconstruct the configuration stored in a backup, following differential
+    /// backups back to their full backup
+    async fn load_backup(&self, name: &str) -> MceptionResult<ServerConfig>;
+
+    /// Reconstruct the configuration stored in a backup and make it the current configuration
+    async fn restore_backup(&self, name: &str) -> MceptionResult<ServerConfig>;
+
+    /// Delete all but the newest `keep` backups, never removing a full backup
+    /// that a remaining differential backup depends on. Returns the deleted names.
+    async fn prune_backups(&self, keep: usize) -> MceptionResult<Vec<String>>;
+
+    /// Report the stored schema version and the migrations pending against it
+    async fn migration_status(&self) -> MceptionResult<MigrationStatus>;
+
+    /// Apply all pending schema migrations. Returns the applied migrations in order.
+    async fn migrate(&self) -> MceptionResult<Vec<MigrationInfo>>;
+
+    /// Check each file the configuration `includes` on its own
+    async fn check_includes(&self) -> MceptionResult<Vec<FragmentCheck>> {
+        Ok(Vec::new())
+    }
 }
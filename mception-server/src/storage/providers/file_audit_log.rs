@@ -1,22 +1,66 @@
-use super::audit_log::AuditStorage;
-use crate::core::{AuditLogEntry, MceptionResult, StorageError};
+use super::audit_log::{AppendedEntry, AuditStorage};
+use crate::core::{
+    AuditLogEntry, AuditQuery, AuditScanReport, CorruptRegion, MceptionError, MceptionResult,
+    StorageError, ValidationError,
+};
+use crate::storage::audit_chain::{self, AuditChainReport};
 use async_trait::async_trait;
-use std::path::Path;
+use chrono::{DateTime, Duration, Utc};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::fs;
+use tokio::sync::Mutex;
+
+/// When the audit log file is rotated. Rotated segments are gzip-compressed
+/// next to it as `<file>.1.gz`, `<file>.2.gz` and so on, `.1.gz` the newest.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AuditRotation {
+    /// Rotate before an append would grow the file beyond this many bytes
+    pub max_bytes: Option<u64>,
+    /// Rotate once the file holds this many entries
+    pub max_entries: Option<usize>,
+}
 
 /// File-based audit log storage implementation
 #[derive(Debug, Clone)]
 pub struct FileAuditStorage {
     audit_log_path: String,
+    rotation: AuditRotation,
+    /// Last entry appended, read from the file when it changed since. Held
+    /// while appending, rotating and pruning, and while reading the segments,
+    /// so readers never see a rotation half done.
+    tail: Arc<Mutex<Option<Tail>>>,
+}
+
+/// The last entry of the audit log, to continue its sequence and chain
+#[derive(Debug, Clone)]
+struct Tail {
+    sequence: u64,
+    timestamp: DateTime<Utc>,
+    hash: Option<String>,
+    /// Length of the file after the entry, to notice appends by other processes
+    file_len: u64,
+    /// Entries in the file, not counting rotated segments
+    entries: usize,
 }
 
 impl FileAuditStorage {
     pub fn new(audit_log_path: impl Into<String>) -> Self {
         Self {
             audit_log_path: audit_log_path.into(),
+            rotation: AuditRotation::default(),
+            tail: Arc::new(Mutex::new(None)),
         }
     }
-    
+
+    /// Rotate the file as configured. Without this it grows forever.
+    pub fn with_rotation(mut self, rotation: AuditRotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
     /// Initialize the audit log file if it doesn't exist
     pub async fn initialize(&self) -> MceptionResult<()> {
         if !Path::new(&self.audit_log_path).exists() {
@@ -26,7 +70,7 @@ impl FileAuditStorage {
                     .await
                     .map_err(StorageError::from)?;
             }
-            
+
             // Create an empty audit log file
             fs::write(&self.audit_log_path, "")
                 .await
@@ -36,10 +80,300 @@ impl FileAuditStorage {
     }
 }
 
+/// Valid entries and corrupt regions found in an audit log
+struct AuditScan {
+    /// Raw text of each valid entry, in file order
+    entries: Vec<String>,
+    salvaged: usize,
+    corrupt: Vec<CorruptRegion>,
+}
+
+/// Scan audit log content line by line. Lines that don't parse are split at NUL
+/// bytes so entries next to NUL-padded regions can still be salvaged.
+fn scan(content: &[u8]) -> AuditScan {
+    let mut scan = AuditScan {
+        entries: Vec::new(),
+        salvaged: 0,
+        corrupt: Vec::new(),
+    };
+
+    let mut offset = 0;
+    for line in content.split(|&b| b == b'\n') {
+        let line_offset = offset;
+        offset += line.len() + 1;
+
+        if line.trim_ascii().is_empty() {
+            continue;
+        }
+        if serde_json::from_slice::<AuditLogEntry>(line).is_ok() {
+            scan.entries
+                .push(String::from_utf8_lossy(line.trim_ascii()).into_owned());
+            continue;
+        }
+
+        let mut segment_offset = line_offset;
+        for segment in line.split(|&b| b == 0) {
+            let start = segment_offset;
+            segment_offset += segment.len() + 1;
+            if segment.trim_ascii().is_empty() {
+                continue;
+            }
+            match serde_json::from_slice::<AuditLogEntry>(segment) {
+                Ok(_) => {
+                    scan.entries
+                        .push(String::from_utf8_lossy(segment.trim_ascii()).into_owned());
+                    scan.salvaged += 1;
+                }
+                Err(e) => scan.corrupt.push(CorruptRegion {
+                    offset: start as u64,
+                    length: segment.len() as u64,
+                    reason: e.to_string(),
+                }),
+            }
+        }
+
+        let nul_bytes = line.iter().filter(|&&b| b == 0).count();
+        if nul_bytes > 0 {
+            scan.corrupt.push(CorruptRegion {
+                offset: line_offset as u64,
+                length: line.len() as u64,
+                reason: format!("{} NUL bytes", nul_bytes),
+            });
+        }
+    }
+
+    scan.corrupt.sort_by_key(|region| region.offset);
+    scan
+}
+
+/// Give entries written before sequence numbers existed the number after
+/// their predecessor's
+fn assign_sequences(entries: &mut [AuditLogEntry]) {
+    let mut previous = 0;
+    for entry in entries {
+        if entry.sequence == 0 {
+            entry.sequence = previous + 1;
+        }
+        previous = entry.sequence;
+    }
+}
+
+impl FileAuditStorage {
+    /// The last valid entry of the file, skipping corrupt regions. Right
+    /// after a rotation the file is empty and the sequence continues from
+    /// the newest segment.
+    async fn read_tail(&self) -> MceptionResult<Option<Tail>> {
+        let content = self.read_current().await?;
+        let file_len = content.len() as u64;
+        let mut entries = valid_entries(&content);
+        let in_file = entries.len();
+        if entries.is_empty() && self.segment_path(1).exists() {
+            entries = valid_entries(&self.read_segment(1).await?);
+        }
+        Ok(entries
+            .iter()
+            .max_by_key(|entry| entry.sequence)
+            .map(|entry| Tail {
+                sequence: entry.sequence,
+                timestamp: entry.timestamp,
+                hash: entry.hash.clone(),
+                file_len,
+                entries: in_file,
+            }))
+    }
+
+    async fn file_len(&self) -> MceptionResult<u64> {
+        match fs::metadata(&self.audit_log_path).await {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(StorageError::from(e).into()),
+        }
+    }
+
+    async fn read_current(&self) -> MceptionResult<Vec<u8>> {
+        match fs::read(&self.audit_log_path).await {
+            Ok(content) => Ok(content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(StorageError::from(e).into()),
+        }
+    }
+
+    fn segment_path(&self, number: usize) -> PathBuf {
+        PathBuf::from(format!("{}.{}.gz", self.audit_log_path, number))
+    }
+
+    /// Numbers of the rotated segments, newest first
+    async fn segment_numbers(&self) -> MceptionResult<Vec<usize>> {
+        let path = Path::new(&self.audit_log_path);
+        let prefix = format!(
+            "{}.",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut listing = match fs::read_dir(dir).await {
+            Ok(listing) => listing,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(StorageError::from(e).into()),
+        };
+        let mut numbers = Vec::new();
+        while let Some(file) = listing.next_entry().await.map_err(StorageError::from)? {
+            if let Some(number) = file
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_prefix(&prefix))
+                .and_then(|rest| rest.strip_suffix(".gz"))
+                .and_then(|number| number.parse::<usize>().ok())
+            {
+                numbers.push(number);
+            }
+        }
+        numbers.sort_unstable();
+        Ok(numbers)
+    }
+
+    async fn read_segment(&self, number: usize) -> MceptionResult<Vec<u8>> {
+        let path = self.segment_path(number);
+        let compressed = fs::read(&path).await.map_err(StorageError::from)?;
+        let mut content = Vec::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut content)
+            .map_err(|e| {
+                StorageError::Corruption(format!(
+                    "Rotated audit log {} can't be decompressed: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+        Ok(content)
+    }
+
+    /// The rotated segments and the file, oldest first, as one log
+    async fn read_all(&self) -> MceptionResult<Vec<u8>> {
+        let _tail = self.tail.lock().await;
+        let mut content = Vec::new();
+        for number in self.segment_numbers().await?.into_iter().rev() {
+            content.extend(self.read_segment(number).await?);
+            if content.last().is_some_and(|&b| b != b'\n') {
+                content.push(b'\n');
+            }
+        }
+        content.extend(self.read_current().await?);
+        Ok(content)
+    }
+
+    /// Move the file's entries into a new newest segment and start it over.
+    /// Called with [`Self::tail`] held.
+    async fn rotate(&self) -> MceptionResult<()> {
+        let content = self.read_current().await?;
+        for number in self.segment_numbers().await?.into_iter().rev() {
+            fs::rename(self.segment_path(number), self.segment_path(number + 1))
+                .await
+                .map_err(StorageError::from)?;
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&content).map_err(StorageError::from)?;
+        let compressed = encoder.finish().map_err(StorageError::from)?;
+        // Complete before it takes the place of the newest segment
+        let partial = PathBuf::from(format!("{}.rotating", self.audit_log_path));
+        fs::write(&partial, compressed)
+            .await
+            .map_err(StorageError::from)?;
+        fs::rename(&partial, self.segment_path(1))
+            .await
+            .map_err(StorageError::from)?;
+        fs::write(&self.audit_log_path, "")
+            .await
+            .map_err(StorageError::from)?;
+        Ok(())
+    }
+
+    /// Scan the rotated segments and the file, oldest first. Offsets of
+    /// corrupt regions count from the start of the oldest segment.
+    async fn scan_file(&self) -> MceptionResult<(AuditScan, AuditScanReport)> {
+        let content = self.read_all().await?;
+        let scan = scan(&content);
+        let report = AuditScanReport {
+            path: self.audit_log_path.clone(),
+            total_bytes: content.len() as u64,
+            valid_entries: scan.entries.len(),
+            salvaged_entries: scan.salvaged,
+            corrupt_regions: scan.corrupt.clone(),
+            output: None,
+        };
+        Ok((scan, report))
+    }
+}
+
+/// Valid entries of audit log content, numbered
+fn valid_entries(content: &[u8]) -> Vec<AuditLogEntry> {
+    let mut entries: Vec<AuditLogEntry> = scan(content)
+        .entries
+        .iter()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    assign_sequences(&mut entries);
+    entries
+}
+
+/// Parse audit log content, numbering entries without a sequence. An entry
+/// found twice, left behind by a rotation that was interrupted, is kept once.
+fn parse_entries(content: &[u8]) -> MceptionResult<Vec<AuditLogEntry>> {
+    let content = std::str::from_utf8(content)
+        .map_err(|e| StorageError::Corruption(format!("Audit log is not valid UTF-8: {}", e)))?;
+    let mut logs = Vec::new();
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        let entry: AuditLogEntry = serde_json::from_str(line).map_err(StorageError::from)?;
+        logs.push(entry);
+    }
+    assign_sequences(&mut logs);
+    logs.sort_by_key(|entry| entry.sequence);
+    logs.dedup_by(|a, b| a.sequence == b.sequence && a.id == b.id);
+    Ok(logs)
+}
+
 #[async_trait]
 impl AuditStorage for FileAuditStorage {
-    async fn append_entry(&self, entry: &AuditLogEntry) -> MceptionResult<()> {
-        let content = serde_json::to_string(entry).map_err(StorageError::from)? + "\n";
+    fn location(&self) -> String {
+        format!("file:{}", self.audit_log_path)
+    }
+
+    async fn append_entry(&self, entry: &AuditLogEntry) -> MceptionResult<AppendedEntry> {
+        // Held until written, so concurrent appends get consecutive numbers
+        let mut tail = self.tail.lock().await;
+        let file_len = self.file_len().await?;
+        let last = match &*tail {
+            Some(last) if last.file_len == file_len => Some(last.clone()),
+            _ => self.read_tail().await?,
+        };
+
+        let mut entry = entry.clone();
+        entry.sequence = last.as_ref().map_or(1, |last| last.sequence + 1);
+        audit_chain::chain(&mut entry, last.as_ref().and_then(|last| last.hash.clone()));
+        let behind_predecessor = last
+            .as_ref()
+            .map(|last| last.timestamp - entry.timestamp)
+            .filter(|behind| *behind > Duration::zero());
+        let content = serde_json::to_string(&entry).map_err(StorageError::from)? + "\n";
+
+        let rotate = file_len > 0
+            && (self
+                .rotation
+                .max_bytes
+                .is_some_and(|max| file_len + content.len() as u64 > max)
+                || self
+                    .rotation
+                    .max_entries
+                    .zip(last.as_ref())
+                    .is_some_and(|(max, last)| last.entries >= max));
+        let (file_len, entries) = if rotate {
+            self.rotate().await?;
+            (0, 0)
+        } else {
+            (file_len, last.map_or(0, |last| last.entries))
+        };
 
         // Create directory if it doesn't exist
         if let Some(parent) = Path::new(&self.audit_log_path).parent() {
@@ -64,34 +398,98 @@ impl AuditStorage for FileAuditStorage {
             .map_err(StorageError::from)?;
         file.flush().await.map_err(StorageError::from)?;
 
-        Ok(())
+        *tail = Some(Tail {
+            sequence: entry.sequence,
+            timestamp: entry.timestamp,
+            hash: entry.hash,
+            file_len: file_len + content.len() as u64,
+            entries: entries + 1,
+        });
+        Ok(AppendedEntry {
+            sequence: entry.sequence,
+            behind_predecessor,
+        })
     }
 
     async fn load_entries(&self) -> MceptionResult<Vec<AuditLogEntry>> {
-        if !Path::new(&self.audit_log_path).exists() {
+        if !Path::new(&self.audit_log_path).exists() && self.segment_numbers().await?.is_empty() {
             // Initialize the audit log file
             self.initialize().await?;
             return Ok(Vec::new());
         }
+        parse_entries(&self.read_all().await?)
+    }
 
-        let content = fs::read_to_string(&self.audit_log_path)
-            .await
-            .map_err(StorageError::from)?;
-            
-        if content.trim().is_empty() {
-            return Ok(Vec::new());
-        }
-            
-        let mut logs = Vec::new();
+    async fn load_entries_filtered(
+        &self,
+        query: &AuditQuery,
+    ) -> MceptionResult<Vec<AuditLogEntry>> {
+        let mut logs = parse_entries(&self.read_all().await?)?;
+        logs.retain(|entry| query.matches(entry));
+        Ok(logs)
+    }
 
-        for line in content.lines() {
-            if !line.trim().is_empty() {
-                let entry: AuditLogEntry =
-                    serde_json::from_str(line).map_err(StorageError::from)?;
-                logs.push(entry);
+    async fn prune_entries(&self, before: DateTime<Utc>) -> MceptionResult<usize> {
+        let _tail = self.tail.lock().await;
+        let mut pruned = 0;
+        // Oldest first, each segment is newer than the one before
+        for number in self.segment_numbers().await?.into_iter().rev() {
+            let entries = valid_entries(&self.read_segment(number).await?);
+            if entries.iter().any(|entry| entry.timestamp >= before) {
+                break;
             }
+            fs::remove_file(self.segment_path(number))
+                .await
+                .map_err(StorageError::from)?;
+            pruned += entries.len();
         }
+        Ok(pruned)
+    }
 
-        Ok(logs)
+    async fn verify(&self) -> MceptionResult<AuditScanReport> {
+        Ok(self.scan_file().await?.1)
+    }
+
+    async fn verify_chain(&self) -> MceptionResult<AuditChainReport> {
+        let content = self.read_all().await?;
+        let lines = content
+            .split(|&b| b == b'\n')
+            .enumerate()
+            .filter(|(_, line)| !line.trim_ascii().is_empty())
+            .map(|(index, line)| (index + 1, String::from_utf8_lossy(line).into_owned()))
+            .collect::<Vec<_>>();
+        Ok(audit_chain::verify(self.location(), lines))
+    }
+
+    async fn repair(&self, output: &str) -> MceptionResult<AuditScanReport> {
+        let same_file = match (
+            fs::canonicalize(&self.audit_log_path).await,
+            fs::canonicalize(output).await,
+        ) {
+            (Ok(input), Ok(output)) => input == output,
+            _ => Path::new(&self.audit_log_path) == Path::new(output),
+        };
+        if same_file {
+            return Err(MceptionError::Validation(ValidationError::InvalidFormat(
+                "The repaired audit log must not overwrite the original".to_string(),
+            )));
+        }
+
+        let (scan, mut report) = self.scan_file().await?;
+        let entries = audit_chain::reanchor(
+            &self.location(),
+            scan.entries,
+            !report.corrupt_regions.is_empty(),
+        );
+        let mut content = entries.join("\n");
+        if !content.is_empty() {
+            content.push('\n');
+        }
+        fs::write(output, content)
+            .await
+            .map_err(StorageError::from)?;
+
+        report.output = Some(output.to_string());
+        Ok(report)
     }
 }
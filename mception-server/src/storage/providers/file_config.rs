@@ -1,31 +1,366 @@
 use super::config::ConfigStorage;
-use crate::core::{ServerConfig, StorageError, MceptionResult, MceptionError};
+use crate::core::{
+    BACKUP_TIMESTAMP_FORMAT, BackupInfo, BackupKind, CONFIG_SCHEMA_VERSION, ConfigurationError,
+    MceptionError, MceptionResult, MigrationInfo, MigrationStatus, ServerConfig, StorageError,
+    ValidationError, merge,
+};
+use crate::storage::includes::{self, ConfigFragment, FragmentCheck, SourceMap};
+use crate::storage::migrations;
+use crate::storage::sections;
 use async_trait::async_trait;
-use std::path::Path;
+use chrono::{DateTime, Utc};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tokio::fs;
-use chrono::Utc;
+use tracing::warn;
 
-/// File-based configuration storage implementation
+/// Options controlling how configuration backups are written
+#[derive(Debug, Clone)]
+pub struct BackupOptions {
+    /// Gzip-compress backup files
+    pub compress: bool,
+    /// Store only the JSON diff against the latest full backup
+    pub differential: bool,
+    /// Number of differential backups after which a new full backup is written
+    pub full_every: usize,
+    /// Prune old backups after each backup, keeping this many
+    pub keep: Option<usize>,
+}
+
+impl Default for BackupOptions {
+    fn default() -> Self {
+        Self {
+            compress: false,
+            differential: false,
+            full_every: 10,
+            keep: None,
+        }
+    }
+}
+
+/// File-based configuration storage implementation. Leaf MCPs and agents
+/// may be spread over the files the configuration `includes`, they are
+/// saved back to the file they were loaded from.
 #[derive(Debug, Clone)]
 pub struct FileConfigStorage {
     config_path: String,
+    backup_options: BackupOptions,
+    /// Write the configuration without indentation
+    compact: bool,
+    /// Where the included leaf MCPs and agents were loaded from
+    sources: Arc<Mutex<SourceMap>>,
 }
 
 impl FileConfigStorage {
     pub fn new(config_path: impl Into<String>) -> Self {
         Self {
             config_path: config_path.into(),
+            backup_options: BackupOptions::default(),
+            compact: false,
+            sources: Arc::default(),
+        }
+    }
+
+    pub fn with_compact(mut self, compact: bool) -> Self {
+        self.compact = compact;
+        self
+    }
+
+    pub fn with_backup_options(mut self, backup_options: BackupOptions) -> Self {
+        self.backup_options = backup_options;
+        self
+    }
+
+    /// Directory of the configuration file, which includes are relative to
+    /// and backups are written to
+    fn config_dir(&self) -> PathBuf {
+        includes::config_dir(Path::new(&self.config_path))
+    }
+
+    fn backup_dir(&self) -> PathBuf {
+        self.config_dir()
+    }
+
+    fn to_json(&self, value: &impl Serialize) -> MceptionResult<String> {
+        // Maps are ordered and fields serialize in declaration order, so the
+        // same configuration always produces the same bytes
+        Ok(if self.compact {
+            serde_json::to_string(value)
+        } else {
+            serde_json::to_string_pretty(value)
+        }
+        .map_err(StorageError::from)?)
+    }
+
+    /// Replace the file at `path` with `content`, leaving it alone if nothing changed
+    async fn write_file(&self, path: &Path, content: String) -> MceptionResult<()> {
+        if fs::read_to_string(path)
+            .await
+            .is_ok_and(|existing| existing == content)
+        {
+            return Ok(());
         }
+
+        // Create directory if it doesn't exist
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(StorageError::from)?;
+        }
+
+        // Write a temporary file and rename it, so a crash never leaves a partial file
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        fs::write(&temp, content)
+            .await
+            .map_err(StorageError::from)?;
+        fs::rename(&temp, path).await.map_err(StorageError::from)?;
+        Ok(())
+    }
+
+    /// The files `config` includes, relative to the configuration's directory
+    fn included_files(&self, config: &ServerConfig) -> MceptionResult<Vec<PathBuf>> {
+        includes::expand(&self.config_dir(), &config.includes)
+            .map_err(|e| MceptionError::Configuration(ConfigurationError::InvalidConfiguration(e)))
+    }
+
+    async fn read_fragment(&self, path: &Path) -> Result<ConfigFragment, String> {
+        let content = fs::read_to_string(path).await.map_err(|e| e.to_string())?;
+        serde_json::from_str(&content).map_err(|e| e.to_string())
     }
-    
-    fn backup_path(&self) -> String {
-        let timestamp = Utc::now().format("%Y%m%d_%H%M%S");
-        format!("{}.backup.{}", self.config_path, timestamp)
+
+    /// Merge the files `config` includes into it and remember where each
+    /// leaf MCP and agent came from
+    async fn resolve_includes(&self, config: &mut ServerConfig) -> MceptionResult<()> {
+        let mut fragments = Vec::new();
+        for path in self.included_files(config)? {
+            let fragment = self.read_fragment(&path).await.map_err(|e| {
+                ConfigurationError::InvalidConfiguration(format!(
+                    "Included file {} is invalid: {}",
+                    includes::display(&self.config_dir(), &path),
+                    e
+                ))
+            })?;
+            fragments.push((path, fragment));
+        }
+        let sources = includes::resolve(config, Path::new(&self.config_path), fragments)
+            .map_err(ConfigurationError::InvalidConfiguration)?;
+        *self.sources.lock().expect("source map lock poisoned") = sources;
+        Ok(())
+    }
+
+    /// The stored configuration without its includes, `None` if there is none yet
+    async fn read_unresolved_config(&self) -> MceptionResult<Option<ServerConfig>> {
+        let Some(value) = self.read_raw_config().await? else {
+            return Ok(None);
+        };
+        Ok(Some(
+            serde_json::from_value(value).map_err(StorageError::from)?,
+        ))
     }
+
+    /// File name prefix shared by all backups of this config file
+    fn backup_prefix(&self) -> String {
+        let file_name = Path::new(&self.config_path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.config_path.clone());
+        format!("{}.backup.", file_name)
+    }
+
+    /// Backup file name: `<config>.backup.<timestamp>[.gz]` for full backups,
+    /// `<config>.backup.<timestamp>.<base timestamp>.diff[.gz]` for
+    /// differential ones, naming the full backup they are based on so it is
+    /// known without reading them
+    fn backup_name(&self, base: Option<&str>) -> String {
+        let prefix = self.backup_prefix();
+        let timestamp = Utc::now().format(BACKUP_TIMESTAMP_FORMAT);
+        let mut name = format!("{}{}", prefix, timestamp);
+        if let Some(base) = base {
+            name.push('.');
+            name.push_str(backup_timestamp(&prefix, base));
+            name.push_str(".diff");
+        }
+        if self.backup_options.compress {
+            name.push_str(".gz");
+        }
+        name
+    }
+
+    /// Read the stored configuration as raw JSON, `None` if there is none yet
+    async fn read_raw_config(&self) -> MceptionResult<Option<Value>> {
+        if !Path::new(&self.config_path).exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&self.config_path)
+            .await
+            .map_err(StorageError::from)?;
+        if content.trim().is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(
+            serde_json::from_str(&content).map_err(StorageError::from)?,
+        ))
+    }
+
+    fn backup_file(&self, name: &str) -> MceptionResult<PathBuf> {
+        if !name.starts_with(&self.backup_prefix()) || name.contains(['/', '\\']) {
+            return Err(MceptionError::Validation(ValidationError::InvalidFormat(
+                format!("'{}' is not a backup of this configuration", name),
+            )));
+        }
+        Ok(Path::new(&self.config_path).with_file_name(name))
+    }
+
+    async fn read_backup_file(&self, name: &str) -> MceptionResult<Value> {
+        let path = self.backup_file(name)?;
+        if !path.exists() {
+            return Err(MceptionError::Storage(StorageError::NotFound(format!(
+                "Backup '{}' not found",
+                name
+            ))));
+        }
+
+        let bytes = fs::read(&path).await.map_err(StorageError::from)?;
+        let content = if name.ends_with(".gz") {
+            let mut content = String::new();
+            GzDecoder::new(bytes.as_slice())
+                .read_to_string(&mut content)
+                .map_err(StorageError::from)?;
+            content
+        } else {
+            String::from_utf8(bytes).map_err(|e| {
+                StorageError::Corruption(format!("Backup '{}' is not valid UTF-8: {}", name, e))
+            })?
+        };
+
+        Ok(serde_json::from_str(&content).map_err(StorageError::from)?)
+    }
+
+    async fn write_backup_file(&self, name: &str, value: &Value) -> MceptionResult<()> {
+        let content = serde_json::to_vec_pretty(value).map_err(StorageError::from)?;
+        let bytes = if self.backup_options.compress {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&content).map_err(StorageError::from)?;
+            encoder.finish().map_err(StorageError::from)?
+        } else {
+            content
+        };
+
+        fs::write(self.backup_file(name)?, bytes)
+            .await
+            .map_err(StorageError::from)?;
+        Ok(())
+    }
+
+    /// Reconstruct the configuration JSON stored in a backup
+    async fn read_backup(&self, name: &str) -> MceptionResult<Value> {
+        let value = self.read_backup_file(name).await?;
+        if !is_differential(name) {
+            return Ok(value);
+        }
+
+        let (base, patch) = split_differential(name, value)?;
+        let mut config = self.read_backup_file(&base).await?;
+        merge::merge_patch(&mut config, &patch);
+        Ok(config)
+    }
+
+    /// Try to express `current` as a diff against the latest full backup,
+    /// returning the name of that backup and the diff. Returns `None` when a
+    /// new full backup should be written instead.
+    async fn differential_backup(
+        &self,
+        backups: &[BackupInfo],
+        current: &Value,
+    ) -> MceptionResult<Option<(String, Value)>> {
+        let Some(base_index) = backups.iter().rposition(|b| b.kind == BackupKind::Full) else {
+            return Ok(None);
+        };
+        let diffs_since_full = backups.len() - (base_index + 1);
+        if diffs_since_full >= self.backup_options.full_every {
+            return Ok(None);
+        }
+
+        let base_name = &backups[base_index].name;
+        let mut reconstructed = self.read_backup_file(base_name).await?;
+        let patch = merge::diff(&reconstructed, current);
+
+        // Merge patches can't express every change (e.g. a field set to null),
+        // so only keep the diff if it round-trips to the same configuration
+        merge::merge_patch(&mut reconstructed, &patch);
+        match (normalize(&reconstructed), normalize(current)) {
+            (Some(reconstructed), Some(current)) if reconstructed == current => {}
+            _ => return Ok(None),
+        }
+
+        Ok(Some((
+            base_name.clone(),
+            serde_json::json!({
+                "base": base_name,
+                "patch": patch,
+            }),
+        )))
+    }
+}
+
+fn is_differential(name: &str) -> bool {
+    name.trim_end_matches(".gz").ends_with(".diff")
+}
+
+/// The timestamp a backup's name starts with after `prefix`
+fn backup_timestamp<'a>(prefix: &str, name: &'a str) -> &'a str {
+    name[prefix.len()..].split('.').next().unwrap_or_default()
+}
+
+/// The timestamp of the full backup a differential backup is based on, if
+/// its name has it. Differential backups written before only name it inside.
+fn base_timestamp<'a>(prefix: &str, name: &'a str) -> Option<&'a str> {
+    let mut parts = name[prefix.len()..].split('.').skip(1);
+    parts.next().filter(|base| *base != "diff")
+}
+
+fn split_differential(name: &str, value: Value) -> MceptionResult<(String, Value)> {
+    let corrupt = || {
+        MceptionError::Storage(StorageError::Corruption(format!(
+            "Differential backup '{}' is missing its base or patch",
+            name
+        )))
+    };
+    let Value::Object(mut envelope) = value else {
+        return Err(corrupt());
+    };
+    let base = envelope
+        .get("base")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(corrupt)?;
+    let patch = envelope.remove("patch").ok_or_else(corrupt)?;
+    Ok((base, patch))
+}
+
+/// Round-trip through `ServerConfig` so absent optional fields compare equal to nulls
+fn normalize(value: &Value) -> Option<Value> {
+    serde_json::from_value::<ServerConfig>(value.clone())
+        .ok()
+        .and_then(|config| serde_json::to_value(config).ok())
 }
 
 #[async_trait]
 impl ConfigStorage for FileConfigStorage {
+    fn location(&self) -> String {
+        format!("file:{}", self.config_path)
+    }
+
+    fn path(&self) -> Option<std::path::PathBuf> {
+        Some(Path::new(&self.config_path).to_path_buf())
+    }
+
     async fn load_config(&self) -> MceptionResult<ServerConfig> {
         if !Path::new(&self.config_path).exists() {
             // Create a default config and save it
@@ -37,54 +372,321 @@ impl ConfigStorage for FileConfigStorage {
         let content = fs::read_to_string(&self.config_path)
             .await
             .map_err(StorageError::from)?;
-            
+
         if content.trim().is_empty() {
             // If file exists but is empty, create default config
             let default_config = ServerConfig::default();
             self.save_config(&default_config).await?;
             return Ok(default_config);
         }
-            
-        let config: ServerConfig = serde_json::from_str(&content)
-            .map_err(StorageError::from)?;
-            
+
+        let mut config = sections::parse_config_blocking(content).await?;
+        if config.metadata.schema_version > CONFIG_SCHEMA_VERSION {
+            return Err(MceptionError::Configuration(
+                ConfigurationError::InvalidConfiguration(format!(
+                    "Configuration schema version {} is newer than the supported version {}",
+                    config.metadata.schema_version, CONFIG_SCHEMA_VERSION
+                )),
+            ));
+        }
+
+        self.resolve_includes(&mut config).await?;
         Ok(config)
     }
 
+    async fn load_unresolved_config(&self) -> MceptionResult<ServerConfig> {
+        match self.read_unresolved_config().await? {
+            Some(config) => Ok(config),
+            None => self.load_config().await,
+        }
+    }
+
     async fn save_config(&self, config: &ServerConfig) -> MceptionResult<()> {
-        let content = serde_json::to_string_pretty(config)
-            .map_err(StorageError::from)?;
-        
-        // Create directory if it doesn't exist
-        if let Some(parent) = Path::new(&self.config_path).parent() {
-            fs::create_dir_all(parent)
-                .await
-                .map_err(StorageError::from)?;
+        // Without includes everything belongs to the configuration file
+        let sources = if config.includes.is_empty() {
+            SourceMap::default()
+        } else {
+            self.sources
+                .lock()
+                .expect("source map lock poisoned")
+                .clone()
+        };
+        let (config, fragments) = includes::split(config, &sources);
+        for (path, fragment) in fragments {
+            self.write_file(&path, self.to_json(&fragment)?).await?;
         }
-        
-        fs::write(&self.config_path, content)
+        self.write_file(Path::new(&self.config_path), self.to_json(&config)?)
             .await
-            .map_err(StorageError::from)?;
-            
-        Ok(())
     }
-    
+
     async fn config_exists(&self) -> MceptionResult<bool> {
         Ok(Path::new(&self.config_path).exists())
     }
-    
+
     async fn backup_config(&self) -> MceptionResult<String> {
         if !self.config_exists().await? {
             return Err(MceptionError::Storage(StorageError::NotFound(
-                "Configuration file not found for backup".to_string()
+                "Configuration file not found for backup".to_string(),
             )));
         }
-        
-        let backup_path = self.backup_path();
-        fs::copy(&self.config_path, &backup_path)
+
+        let content = fs::read_to_string(&self.config_path)
             .await
             .map_err(StorageError::from)?;
-            
-        Ok(backup_path)
+        let mut current: Value = serde_json::from_str(&content).map_err(StorageError::from)?;
+        // Back up what the includes contribute too, restoring saves it back to them
+        let resolved = current
+            .get("includes")
+            .and_then(Value::as_array)
+            .is_some_and(|includes| !includes.is_empty());
+        if resolved {
+            current =
+                serde_json::to_value(self.load_config().await?).map_err(StorageError::from)?;
+        }
+
+        let diff = if self.backup_options.differential {
+            let backups = self.list_backups().await?;
+            self.differential_backup(&backups, &current).await?
+        } else {
+            None
+        };
+
+        let name = match diff {
+            Some((base, diff)) => {
+                let name = self.backup_name(Some(&base));
+                self.write_backup_file(&name, &diff).await?;
+                name
+            }
+            None => {
+                let name = self.backup_name(None);
+                if self.backup_options.compress || resolved {
+                    self.write_backup_file(&name, &current).await?;
+                } else {
+                    fs::copy(&self.config_path, self.backup_file(&name)?)
+                        .await
+                        .map_err(StorageError::from)?;
+                }
+                name
+            }
+        };
+
+        if let Some(keep) = self.backup_options.keep {
+            self.prune_backups(keep).await?;
+        }
+
+        Ok(self.backup_file(&name)?.to_string_lossy().into_owned())
+    }
+
+    async fn list_backups(&self) -> MceptionResult<Vec<BackupInfo>> {
+        let prefix = self.backup_prefix();
+        let mut backups = Vec::new();
+
+        let mut entries = match fs::read_dir(self.backup_dir()).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(backups),
+            Err(e) => return Err(StorageError::from(e).into()),
+        };
+
+        let mut files = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(StorageError::from)? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with(&prefix) {
+                let metadata = entry.metadata().await.map_err(StorageError::from)?;
+                files.push((name, metadata));
+            }
+        }
+        let full_backups: BTreeMap<&str, &str> = files
+            .iter()
+            .filter(|(name, _)| !is_differential(name))
+            .map(|(name, _)| (backup_timestamp(&prefix, name), name.as_str()))
+            .collect();
+
+        for (name, metadata) in &files {
+            let (kind, base) = if !is_differential(name) {
+                (BackupKind::Full, None)
+            } else if let Some(base) = base_timestamp(&prefix, name) {
+                let base = full_backups.get(base).map(|base| base.to_string());
+                (BackupKind::Differential, base)
+            } else {
+                let base = match self.read_backup_file(name).await {
+                    Ok(value) => split_differential(name, value).ok().map(|(base, _)| base),
+                    Err(_) => None,
+                }
+                .filter(|base| full_backups.values().any(|full| full == base));
+                (BackupKind::Differential, base)
+            };
+            // A differential backup whose base is unknown or gone can't be read
+            let corrupt = kind == BackupKind::Differential && base.is_none();
+            if corrupt {
+                warn!("Backup '{}' is corrupt, its base can't be found", name);
+            }
+
+            backups.push(BackupInfo {
+                compressed: name.ends_with(".gz"),
+                name: name.clone(),
+                kind,
+                base,
+                corrupt,
+                size_bytes: metadata.len(),
+                created_at: BackupInfo::taken_at(name).unwrap_or_else(|| {
+                    metadata
+                        .modified()
+                        .map(DateTime::<Utc>::from)
+                        .unwrap_or_else(|_| Utc::now())
+                }),
+            });
+        }
+
+        // Names embed the timestamp, so ordering by it is chronological
+        backups.sort_by(|a, b| {
+            backup_timestamp(&prefix, &a.name).cmp(backup_timestamp(&prefix, &b.name))
+        });
+
+        Ok(backups)
+    }
+
+    async fn load_backup(&self, name: &str) -> MceptionResult<ServerConfig> {
+        let value = self.read_backup(name).await?;
+        Ok(serde_json::from_value(value).map_err(StorageError::from)?)
+    }
+
+    async fn restore_backup(&self, name: &str) -> MceptionResult<ServerConfig> {
+        let config = self.load_backup(name).await?;
+        self.save_config(&config).await?;
+        Ok(config)
+    }
+
+    async fn prune_backups(&self, keep: usize) -> MceptionResult<Vec<String>> {
+        let backups = self.list_backups().await?;
+        let cutoff = backups.len().saturating_sub(keep);
+        let (candidates, retained) = backups.split_at(cutoff);
+
+        // Full backups that retained differential backups are based on must survive
+        let required: HashSet<&str> = retained
+            .iter()
+            .filter_map(|backup| backup.base.as_deref())
+            .collect();
+
+        let mut deleted = Vec::new();
+        for backup in candidates {
+            if required.contains(backup.name.as_str()) {
+                continue;
+            }
+            fs::remove_file(self.backup_file(&backup.name)?)
+                .await
+                .map_err(StorageError::from)?;
+            deleted.push(backup.name.clone());
+        }
+
+        Ok(deleted)
+    }
+
+    async fn migration_status(&self) -> MceptionResult<MigrationStatus> {
+        // A missing configuration is created at the current schema version
+        let current_version = match self.read_raw_config().await? {
+            Some(config) => migrations::schema_version(&config),
+            None => CONFIG_SCHEMA_VERSION,
+        };
+
+        Ok(MigrationStatus {
+            storage: self.location(),
+            current_version,
+            target_version: CONFIG_SCHEMA_VERSION,
+            pending: migrations::pending(current_version)
+                .map(migrations::Migration::info)
+                .collect(),
+        })
+    }
+
+    async fn migrate(&self) -> MceptionResult<Vec<MigrationInfo>> {
+        let Some(mut config) = self.read_raw_config().await? else {
+            return Ok(Vec::new());
+        };
+
+        let version = migrations::schema_version(&config);
+        if version > CONFIG_SCHEMA_VERSION {
+            return Err(MceptionError::Configuration(
+                ConfigurationError::InvalidConfiguration(format!(
+                    "Configuration schema version {} is newer than the supported version {}",
+                    version, CONFIG_SCHEMA_VERSION
+                )),
+            ));
+        }
+
+        let pending: Vec<_> = migrations::pending(version).collect();
+        if pending.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Keep the pre-migration configuration around in case a migration misbehaves
+        self.backup_config().await?;
+
+        for migration in &pending {
+            migrations::apply(migration, &mut config).map_err(|e| {
+                ConfigurationError::InvalidConfiguration(format!(
+                    "Migration to schema version {} failed: {}",
+                    migration.version, e
+                ))
+            })?;
+        }
+
+        let migrated: ServerConfig = serde_json::from_value(config).map_err(StorageError::from)?;
+        self.save_config(&migrated).await?;
+
+        Ok(pending.iter().map(|migration| migration.info()).collect())
+    }
+
+    async fn check_includes(&self) -> MceptionResult<Vec<FragmentCheck>> {
+        let Some(config) = self.read_unresolved_config().await? else {
+            return Ok(Vec::new());
+        };
+        let config_dir = self.config_dir();
+        let config_name = includes::display(&config_dir, Path::new(&self.config_path));
+        // Where each id was first defined, to report duplicates
+        let mut leaf_mcps: BTreeMap<String, String> = config
+            .leaf_mcps
+            .keys()
+            .map(|id| (id.clone(), config_name.clone()))
+            .collect();
+        let mut agents: BTreeMap<String, String> = config
+            .agents
+            .keys()
+            .map(|id| (id.clone(), config_name.clone()))
+            .collect();
+
+        let mut checks = Vec::new();
+        for path in self.included_files(&config)? {
+            let file = includes::display(&config_dir, &path);
+            let fragment = match self.read_fragment(&path).await {
+                Ok(fragment) => fragment,
+                Err(e) => {
+                    checks.push(FragmentCheck {
+                        file,
+                        leaf_mcps: 0,
+                        agents: 0,
+                        errors: vec![e],
+                    });
+                    continue;
+                }
+            };
+            let mut errors = Vec::new();
+            for id in fragment.leaf_mcps.keys() {
+                if let Some(first) = leaf_mcps.insert(id.clone(), file.clone()) {
+                    errors.push(format!("Leaf MCP '{}' is already defined in {}", id, first));
+                }
+            }
+            for id in fragment.agents.keys() {
+                if let Some(first) = agents.insert(id.clone(), file.clone()) {
+                    errors.push(format!("Agent '{}' is already defined in {}", id, first));
+                }
+            }
+            checks.push(FragmentCheck {
+                file,
+                leaf_mcps: fragment.leaf_mcps.len(),
+                agents: fragment.agents.len(),
+                errors,
+            });
+        }
+        Ok(checks)
     }
 }
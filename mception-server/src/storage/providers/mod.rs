@@ -1,12 +1,22 @@
-pub mod config;
 pub mod audit_log;
-pub mod file_config;
+pub mod config;
 pub mod file_audit_log;
+pub mod file_config;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_audit_log;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_config;
 
 // Re-export the main traits
+pub use audit_log::{AppendedEntry, AuditStorage};
 pub use config::ConfigStorage;
-pub use audit_log::AuditStorage;
 
 // Re-export the implementations
-pub use file_config::FileConfigStorage;
-pub use file_audit_log::FileAuditStorage;
+pub use file_audit_log::{AuditRotation, FileAuditStorage};
+pub use file_config::{BackupOptions, FileConfigStorage};
+#[cfg(feature = "sqlite")]
+pub use sqlite_audit_log::SqliteAuditStorage;
+#[cfg(feature = "sqlite")]
+pub use sqlite_config::SqliteConfigStorage;
@@ -0,0 +1,423 @@
+use crate::core::{MceptionError, StorageError};
+use std::ffi::{CStr, CString, c_char, c_int, c_void};
+use std::path::Path;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+/// The parts of the SQLite C API the storage providers use, linked against
+/// the system's `libsqlite3`
+mod ffi {
+    use std::ffi::{c_char, c_int, c_uchar, c_void};
+
+    pub enum Sqlite3 {}
+    pub enum Stmt {}
+
+    pub const OK: c_int = 0;
+    pub const ROW: c_int = 100;
+    pub const DONE: c_int = 101;
+    pub const OPEN_READWRITE: c_int = 0x02;
+    pub const OPEN_CREATE: c_int = 0x04;
+    pub const OPEN_FULLMUTEX: c_int = 0x10000;
+    pub const NULL: c_int = 5;
+    /// `SQLITE_TRANSIENT`, SQLite copies bound values
+    pub const TRANSIENT: isize = -1;
+
+    #[link(name = "sqlite3")]
+    unsafe extern "C" {
+        pub fn sqlite3_open_v2(
+            filename: *const c_char,
+            db: *mut *mut Sqlite3,
+            flags: c_int,
+            vfs: *const c_char,
+        ) -> c_int;
+        pub fn sqlite3_close_v2(db: *mut Sqlite3) -> c_int;
+        pub fn sqlite3_errmsg(db: *mut Sqlite3) -> *const c_char;
+        pub fn sqlite3_busy_timeout(db: *mut Sqlite3, ms: c_int) -> c_int;
+        pub fn sqlite3_exec(
+            db: *mut Sqlite3,
+            sql: *const c_char,
+            callback: *const c_void,
+            arg: *mut c_void,
+            errmsg: *mut *mut c_char,
+        ) -> c_int;
+        pub fn sqlite3_free(ptr: *mut c_void);
+        pub fn sqlite3_changes(db: *mut Sqlite3) -> c_int;
+        pub fn sqlite3_prepare_v2(
+            db: *mut Sqlite3,
+            sql: *const c_char,
+            len: c_int,
+            stmt: *mut *mut Stmt,
+            tail: *mut *const c_char,
+        ) -> c_int;
+        pub fn sqlite3_bind_text(
+            stmt: *mut Stmt,
+            index: c_int,
+            text: *const c_char,
+            len: c_int,
+            destructor: isize,
+        ) -> c_int;
+        pub fn sqlite3_bind_int64(stmt: *mut Stmt, index: c_int, value: i64) -> c_int;
+        pub fn sqlite3_bind_null(stmt: *mut Stmt, index: c_int) -> c_int;
+        pub fn sqlite3_step(stmt: *mut Stmt) -> c_int;
+        pub fn sqlite3_column_count(stmt: *mut Stmt) -> c_int;
+        pub fn sqlite3_column_type(stmt: *mut Stmt, column: c_int) -> c_int;
+        pub fn sqlite3_column_int64(stmt: *mut Stmt, column: c_int) -> i64;
+        pub fn sqlite3_column_text(stmt: *mut Stmt, column: c_int) -> *const c_uchar;
+        pub fn sqlite3_column_bytes(stmt: *mut Stmt, column: c_int) -> c_int;
+        pub fn sqlite3_finalize(stmt: *mut Stmt) -> c_int;
+    }
+}
+
+/// Schema of the database, one step per version recorded in
+/// `PRAGMA user_version`. Applied in order when a database is opened; append
+/// new steps, never change released ones.
+const SCHEMA: &[&str] = &[
+    // 1: configuration, its backups and the append-only audit log
+    "CREATE TABLE config (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        revision INTEGER NOT NULL,
+        content TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+    CREATE TABLE config_backups (
+        name TEXT PRIMARY KEY,
+        created_at TEXT NOT NULL,
+        content TEXT NOT NULL
+    );
+    CREATE TABLE audit_log (
+        sequence INTEGER PRIMARY KEY,
+        id TEXT NOT NULL,
+        timestamp TEXT NOT NULL,
+        action TEXT NOT NULL,
+        target_type TEXT NOT NULL,
+        actor TEXT,
+        entry TEXT NOT NULL
+    );
+    CREATE INDEX audit_log_timestamp ON audit_log (timestamp);
+    CREATE INDEX audit_log_action ON audit_log (action);
+    CREATE INDEX audit_log_target_type ON audit_log (target_type);
+    CREATE INDEX audit_log_actor ON audit_log (actor);
+    CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON audit_log
+        BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;
+    CREATE TRIGGER audit_log_no_delete BEFORE DELETE ON audit_log
+        BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;",
+    // 2: action and target type columns as named in the entries' JSON,
+    // they held lowercased Rust names before
+    "DROP TRIGGER audit_log_no_update;
+    UPDATE audit_log SET
+        action = json_extract(entry, '$.action.type'),
+        target_type = json_extract(entry, '$.target.type')
+    WHERE json_valid(entry);
+    CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON audit_log
+        BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;",
+];
+
+/// A value bound to or read from a statement
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlValue {
+    Null,
+    Integer(i64),
+    Text(String),
+}
+
+impl SqlValue {
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            SqlValue::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            SqlValue::Text(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for SqlValue {
+    fn from(value: &str) -> Self {
+        SqlValue::Text(value.to_string())
+    }
+}
+
+impl From<String> for SqlValue {
+    fn from(value: String) -> Self {
+        SqlValue::Text(value)
+    }
+}
+
+impl From<i64> for SqlValue {
+    fn from(value: i64) -> Self {
+        SqlValue::Integer(value)
+    }
+}
+
+impl<T: Into<SqlValue>> From<Option<T>> for SqlValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(SqlValue::Null, Into::into)
+    }
+}
+
+/// An open SQLite database
+pub struct Connection {
+    db: *mut ffi::Sqlite3,
+}
+
+// Opened in serialized mode, and only ever used behind a mutex
+unsafe impl Send for Connection {}
+
+impl Connection {
+    /// Open or create the database at `path` and bring its schema up to date
+    pub fn open(path: &str) -> Result<Self, String> {
+        if let Some(parent) = Path::new(path).parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let filename = CString::new(path).map_err(|e| e.to_string())?;
+        let mut db = ptr::null_mut();
+        let flags = ffi::OPEN_READWRITE | ffi::OPEN_CREATE | ffi::OPEN_FULLMUTEX;
+        let code = unsafe { ffi::sqlite3_open_v2(filename.as_ptr(), &mut db, flags, ptr::null()) };
+        let connection = Connection { db };
+        if code != ffi::OK {
+            return Err(format!("Failed to open {}: {}", path, connection.error()));
+        }
+        // Other processes sharing the database hold its lock only briefly
+        unsafe { ffi::sqlite3_busy_timeout(db, 5000) };
+        connection.execute_batch("PRAGMA journal_mode = WAL;")?;
+        connection.migrate()?;
+        Ok(connection)
+    }
+
+    /// Apply the schema steps the database is missing
+    fn migrate(&self) -> Result<(), String> {
+        self.transaction(|connection| {
+            let version = connection
+                .query("PRAGMA user_version", &[])?
+                .first()
+                .and_then(|row| row[0].as_i64())
+                .unwrap_or(0) as usize;
+            if version > SCHEMA.len() {
+                return Err(format!(
+                    "Database schema version {} is newer than the supported version {}",
+                    version,
+                    SCHEMA.len()
+                ));
+            }
+            for (index, step) in SCHEMA.iter().enumerate().skip(version) {
+                connection.execute_batch(step)?;
+                connection.execute_batch(&format!("PRAGMA user_version = {};", index + 1))?;
+            }
+            Ok(())
+        })
+    }
+
+    fn error(&self) -> String {
+        if self.db.is_null() {
+            return "out of memory".to_string();
+        }
+        unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(self.db)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Run statements without parameters or results
+    pub fn execute_batch(&self, sql: &str) -> Result<(), String> {
+        let sql = CString::new(sql).map_err(|e| e.to_string())?;
+        let mut message: *mut c_char = ptr::null_mut();
+        let code = unsafe {
+            ffi::sqlite3_exec(
+                self.db,
+                sql.as_ptr(),
+                ptr::null(),
+                ptr::null_mut(),
+                &mut message,
+            )
+        };
+        if code == ffi::OK {
+            return Ok(());
+        }
+        if message.is_null() {
+            return Err(self.error());
+        }
+        let error = unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { ffi::sqlite3_free(message as *mut c_void) };
+        Err(error)
+    }
+
+    /// Run `f` in a transaction taking the write lock right away, so that
+    /// reads in it see no concurrent writes. Rolled back if `f` fails.
+    pub fn transaction<T>(
+        &self,
+        f: impl FnOnce(&Connection) -> Result<T, String>,
+    ) -> Result<T, String> {
+        self.execute_batch("BEGIN IMMEDIATE;")?;
+        match f(self) {
+            Ok(value) => {
+                self.execute_batch("COMMIT;")?;
+                Ok(value)
+            }
+            Err(e) => {
+                let _ = self.execute_batch("ROLLBACK;");
+                Err(e)
+            }
+        }
+    }
+
+    /// Run a statement, returning the number of rows it changed
+    pub fn execute(&self, sql: &str, params: &[SqlValue]) -> Result<usize, String> {
+        let statement = self.prepare(sql, params)?;
+        while statement.step()? {}
+        Ok(unsafe { ffi::sqlite3_changes(self.db) } as usize)
+    }
+
+    /// Run a query, returning all rows
+    pub fn query(&self, sql: &str, params: &[SqlValue]) -> Result<Vec<Vec<SqlValue>>, String> {
+        let statement = self.prepare(sql, params)?;
+        let mut rows = Vec::new();
+        while statement.step()? {
+            rows.push(statement.row());
+        }
+        Ok(rows)
+    }
+
+    fn prepare(&self, sql: &str, params: &[SqlValue]) -> Result<Statement<'_>, String> {
+        let sql = CString::new(sql).map_err(|e| e.to_string())?;
+        let mut stmt = ptr::null_mut();
+        let code = unsafe {
+            ffi::sqlite3_prepare_v2(self.db, sql.as_ptr(), -1, &mut stmt, ptr::null_mut())
+        };
+        if code != ffi::OK {
+            return Err(self.error());
+        }
+        let statement = Statement {
+            connection: self,
+            stmt,
+        };
+        for (index, param) in params.iter().enumerate() {
+            let index = index as c_int + 1;
+            let code = match param {
+                SqlValue::Null => unsafe { ffi::sqlite3_bind_null(stmt, index) },
+                SqlValue::Integer(value) => unsafe { ffi::sqlite3_bind_int64(stmt, index, *value) },
+                SqlValue::Text(value) => unsafe {
+                    ffi::sqlite3_bind_text(
+                        stmt,
+                        index,
+                        value.as_ptr() as *const c_char,
+                        value.len() as c_int,
+                        ffi::TRANSIENT,
+                    )
+                },
+            };
+            if code != ffi::OK {
+                return Err(self.error());
+            }
+        }
+        Ok(statement)
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        unsafe { ffi::sqlite3_close_v2(self.db) };
+    }
+}
+
+/// A prepared statement, finalized when dropped
+struct Statement<'a> {
+    connection: &'a Connection,
+    stmt: *mut ffi::Stmt,
+}
+
+impl Statement<'_> {
+    /// Advance to the next row, `false` once the statement is done
+    fn step(&self) -> Result<bool, String> {
+        match unsafe { ffi::sqlite3_step(self.stmt) } {
+            ffi::ROW => Ok(true),
+            ffi::DONE => Ok(false),
+            _ => Err(self.connection.error()),
+        }
+    }
+
+    fn row(&self) -> Vec<SqlValue> {
+        let columns = unsafe { ffi::sqlite3_column_count(self.stmt) };
+        (0..columns)
+            .map(|column| unsafe {
+                match ffi::sqlite3_column_type(self.stmt, column) {
+                    ffi::NULL => SqlValue::Null,
+                    1 => SqlValue::Integer(ffi::sqlite3_column_int64(self.stmt, column)),
+                    _ => {
+                        let text = ffi::sqlite3_column_text(self.stmt, column);
+                        let len = ffi::sqlite3_column_bytes(self.stmt, column) as usize;
+                        if text.is_null() {
+                            SqlValue::Text(String::new())
+                        } else {
+                            let bytes = std::slice::from_raw_parts(text, len);
+                            SqlValue::Text(String::from_utf8_lossy(bytes).into_owned())
+                        }
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+impl Drop for Statement<'_> {
+    fn drop(&mut self) {
+        unsafe { ffi::sqlite3_finalize(self.stmt) };
+    }
+}
+
+/// A connection shared by the clones of a storage provider. Calls block, so
+/// they run on tokio's blocking threads.
+#[derive(Clone)]
+pub struct SharedConnection {
+    path: String,
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SharedConnection {
+    pub fn open(path: &str) -> Result<Self, MceptionError> {
+        let connection = Connection::open(path).map_err(StorageError::Database)?;
+        Ok(Self {
+            path: path.to_string(),
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Run `f` with the connection
+    pub async fn run<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Connection) -> Result<T, MceptionError> + Send + 'static,
+    ) -> Result<T, MceptionError> {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            let connection = connection.lock().expect("sqlite connection lock poisoned");
+            f(&connection)
+        })
+        .await
+        .map_err(|e| StorageError::Database(e.to_string()))?
+    }
+}
+
+impl std::fmt::Debug for SharedConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedConnection")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+/// Convert an SQLite error message to a storage error
+pub fn database_error(message: String) -> MceptionError {
+    StorageError::Database(message).into()
+}
@@ -0,0 +1,308 @@
+use super::audit_log::{AppendedEntry, AuditStorage};
+use super::sqlite::{SharedConnection, SqlValue, database_error};
+use crate::core::audit_query::{action_name, target_type_name};
+use crate::core::{
+    AuditLogEntry, AuditQuery, AuditScanReport, CorruptRegion, MceptionError, MceptionResult,
+    StorageError, ValidationError,
+};
+use crate::storage::audit_chain::{self, AuditChainReport};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use tokio::fs;
+
+/// Audit log in an append-only SQLite table, which several servers can
+/// share. Timestamp, action, target type and actor have indexed columns, so
+/// filtered queries don't read every entry.
+#[derive(Debug, Clone)]
+pub struct SqliteAuditStorage {
+    connection: SharedConnection,
+}
+
+impl SqliteAuditStorage {
+    /// Open or create the database at `path`
+    pub fn open(path: &str) -> MceptionResult<Self> {
+        Ok(Self {
+            connection: SharedConnection::open(path)?,
+        })
+    }
+
+    /// Every stored entry as `(sequence, JSON)`, in order
+    async fn rows(&self) -> MceptionResult<Vec<(u64, String)>> {
+        let rows = self
+            .connection
+            .run(|connection| {
+                connection
+                    .query(
+                        "SELECT sequence, entry FROM audit_log ORDER BY sequence",
+                        &[],
+                    )
+                    .map_err(database_error)
+            })
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                (
+                    row[0].as_i64().unwrap_or_default() as u64,
+                    row[1].as_str().unwrap_or_default().to_string(),
+                )
+            })
+            .collect())
+    }
+
+    async fn scan(&self) -> MceptionResult<(Vec<String>, AuditScanReport)> {
+        let integrity = self
+            .connection
+            .run(|connection| {
+                let check = connection
+                    .query("PRAGMA integrity_check", &[])
+                    .map_err(database_error)?;
+                let size = connection
+                    .query(
+                        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+                        &[],
+                    )
+                    .map_err(database_error)?;
+                Ok((check, size))
+            })
+            .await?;
+        let (check, size) = integrity;
+
+        let mut entries = Vec::new();
+        let mut corrupt_regions = Vec::new();
+        for (sequence, entry) in self.rows().await? {
+            match serde_json::from_str::<AuditLogEntry>(&entry) {
+                Ok(_) => entries.push(entry),
+                // Rows are located by sequence rather than byte offset
+                Err(e) => corrupt_regions.push(CorruptRegion {
+                    offset: sequence,
+                    length: entry.len() as u64,
+                    reason: e.to_string(),
+                }),
+            }
+        }
+        for row in check {
+            if let Some(message) = row[0].as_str()
+                && message != "ok"
+            {
+                corrupt_regions.push(CorruptRegion {
+                    offset: 0,
+                    length: 0,
+                    reason: message.to_string(),
+                });
+            }
+        }
+
+        let report = AuditScanReport {
+            path: self.connection.path().to_string(),
+            total_bytes: size
+                .first()
+                .and_then(|row| row[0].as_i64())
+                .unwrap_or_default() as u64,
+            valid_entries: entries.len(),
+            salvaged_entries: 0,
+            corrupt_regions,
+            output: None,
+        };
+        Ok((entries, report))
+    }
+}
+
+/// Timestamps as stored in the `timestamp` column, ordered like the times
+fn column_timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp.format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string()
+}
+
+/// `LIKE` pattern matching `value` anywhere, with its wildcards escaped
+fn contains_pattern(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+fn parse_entry(sequence: u64, entry: &str) -> MceptionResult<AuditLogEntry> {
+    let mut entry: AuditLogEntry = serde_json::from_str(entry).map_err(StorageError::from)?;
+    entry.sequence = sequence;
+    Ok(entry)
+}
+
+#[async_trait]
+impl AuditStorage for SqliteAuditStorage {
+    fn location(&self) -> String {
+        format!("sqlite:{}", self.connection.path())
+    }
+
+    async fn append_entry(&self, entry: &AuditLogEntry) -> MceptionResult<AppendedEntry> {
+        let entry = entry.clone();
+        self.connection
+            .run(move |connection| {
+                connection
+                    .transaction(|connection| {
+                        let last = connection
+                            .query(
+                                "SELECT sequence, entry FROM audit_log ORDER BY sequence DESC LIMIT 1",
+                                &[],
+                            )?
+                            .into_iter()
+                            .next();
+                        let last = match last {
+                            Some(row) => {
+                                let last = serde_json::from_str::<AuditLogEntry>(
+                                    row[1].as_str().unwrap_or_default(),
+                                )
+                                .map_err(|e| e.to_string())?;
+                                Some((
+                                    row[0].as_i64().unwrap_or_default() as u64,
+                                    last.timestamp,
+                                    last.hash,
+                                ))
+                            }
+                            None => None,
+                        };
+
+                        let mut entry = entry;
+                        entry.sequence = last.as_ref().map_or(1, |(sequence, ..)| sequence + 1);
+                        audit_chain::chain(
+                            &mut entry,
+                            last.as_ref().and_then(|(.., hash)| hash.clone()),
+                        );
+                        let behind_predecessor = last
+                            .map(|(_, timestamp, _)| timestamp - entry.timestamp)
+                            .filter(|behind| *behind > Duration::zero());
+                        let json = serde_json::to_string(&entry).map_err(|e| e.to_string())?;
+                        connection.execute(
+                            "INSERT INTO audit_log
+                                 (sequence, id, timestamp, action, target_type, actor, entry)
+                             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                            &[
+                                (entry.sequence as i64).into(),
+                                entry.id.as_str().into(),
+                                column_timestamp(&entry.timestamp).into(),
+                                action_name(&entry.action).into(),
+                                target_type_name(&entry.target).into(),
+                                entry.actor.clone().into(),
+                                json.into(),
+                            ],
+                        )?;
+                        Ok(AppendedEntry {
+                            sequence: entry.sequence,
+                            behind_predecessor,
+                        })
+                    })
+                    .map_err(database_error)
+            })
+            .await
+    }
+
+    async fn load_entries(&self) -> MceptionResult<Vec<AuditLogEntry>> {
+        self.rows()
+            .await?
+            .iter()
+            .map(|(sequence, entry)| parse_entry(*sequence, entry))
+            .collect()
+    }
+
+    async fn load_entries_filtered(
+        &self,
+        query: &AuditQuery,
+    ) -> MceptionResult<Vec<AuditLogEntry>> {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+        let mut condition = |sql: &str, param: SqlValue| {
+            params.push(param);
+            conditions.push(sql.replace('?', &format!("?{}", params.len())));
+        };
+        if let Some(since) = &query.since {
+            condition("timestamp >= ?", column_timestamp(since).into());
+        }
+        if let Some(until) = &query.until {
+            condition("timestamp < ?", column_timestamp(until).into());
+        }
+        // `LIKE` only ignores the case of ASCII letters, other filters are
+        // left to `AuditQuery::matches` below
+        for (column, filter) in [
+            ("action", &query.action),
+            ("target_type", &query.target_type),
+            ("actor", &query.actor),
+        ] {
+            if let Some(filter) = filter
+                && filter.is_ascii()
+            {
+                condition(
+                    &format!("{} LIKE ? ESCAPE '\\'", column),
+                    contains_pattern(filter).into(),
+                );
+            }
+        }
+        let sql = if conditions.is_empty() {
+            "SELECT sequence, entry FROM audit_log ORDER BY sequence".to_string()
+        } else {
+            format!(
+                "SELECT sequence, entry FROM audit_log WHERE {} ORDER BY sequence",
+                conditions.join(" AND ")
+            )
+        };
+
+        let rows = self
+            .connection
+            .run(move |connection| connection.query(&sql, &params).map_err(database_error))
+            .await?;
+        let mut entries = Vec::new();
+        for row in rows {
+            let entry = parse_entry(
+                row[0].as_i64().unwrap_or_default() as u64,
+                row[1].as_str().unwrap_or_default(),
+            )?;
+            if query.matches(&entry) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// The table is append-only, see the schema, so nothing is ever pruned
+    async fn prune_entries(&self, _before: DateTime<Utc>) -> MceptionResult<usize> {
+        Err(MceptionError::Validation(ValidationError::InvalidFormat(
+            "The SQLite audit log is append-only and can't be pruned".to_string(),
+        )))
+    }
+
+    async fn verify(&self) -> MceptionResult<AuditScanReport> {
+        Ok(self.scan().await?.1)
+    }
+
+    async fn verify_chain(&self) -> MceptionResult<AuditChainReport> {
+        let rows = self.rows().await?;
+        let lines = rows
+            .into_iter()
+            .enumerate()
+            .map(|(index, (_, entry))| (index + 1, entry));
+        Ok(audit_chain::verify(self.location(), lines))
+    }
+
+    async fn repair(&self, output: &str) -> MceptionResult<AuditScanReport> {
+        if output == self.connection.path() {
+            return Err(MceptionError::Validation(ValidationError::InvalidFormat(
+                "The repaired copy must not overwrite the database".to_string(),
+            )));
+        }
+        // The copy is an audit log file, usable with file storage
+        let (entries, mut report) = self.scan().await?;
+        let entries = audit_chain::reanchor(
+            &self.location(),
+            entries,
+            !report.corrupt_regions.is_empty(),
+        );
+        let mut content = entries.join("\n");
+        if !content.is_empty() {
+            content.push('\n');
+        }
+        fs::write(output, content)
+            .await
+            .map_err(StorageError::from)?;
+        report.output = Some(output.to_string());
+        Ok(report)
+    }
+}
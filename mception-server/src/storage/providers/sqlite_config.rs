@@ -0,0 +1,324 @@
+use super::config::ConfigStorage;
+use super::sqlite::{SharedConnection, SqlValue, database_error};
+use crate::core::{
+    BACKUP_TIMESTAMP_FORMAT, BackupInfo, BackupKind, CONFIG_SCHEMA_VERSION, ConfigurationError,
+    MceptionError, MceptionResult, MigrationInfo, MigrationStatus, ServerConfig, StorageError,
+};
+use crate::storage::migrations;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+
+/// Configuration storage in an SQLite database, which several servers can
+/// share. A save is refused with a conflict if another server saved since
+/// this one last loaded or saved the configuration, instead of overwriting
+/// its changes. Backups are stored in the database too, always in full.
+#[derive(Debug, Clone)]
+pub struct SqliteConfigStorage {
+    connection: SharedConnection,
+    /// Prune old backups after each backup, keeping this many
+    keep_backups: Option<usize>,
+    /// Stored revision this server last loaded or saved, `None` before
+    base_revision: Arc<Mutex<Option<u64>>>,
+}
+
+impl SqliteConfigStorage {
+    /// Open or create the database at `path`
+    pub fn open(path: &str) -> MceptionResult<Self> {
+        Ok(Self {
+            connection: SharedConnection::open(path)?,
+            keep_backups: None,
+            base_revision: Arc::default(),
+        })
+    }
+
+    pub fn with_keep_backups(mut self, keep: Option<usize>) -> Self {
+        self.keep_backups = keep;
+        self
+    }
+
+    /// Read the stored configuration as raw JSON, `None` if there is none yet
+    async fn read_raw_config(&self) -> MceptionResult<Option<Value>> {
+        let row = self
+            .connection
+            .run(|connection| {
+                let rows = connection
+                    .query("SELECT revision, content FROM config WHERE id = 1", &[])
+                    .map_err(database_error)?;
+                Ok(rows.into_iter().next())
+            })
+            .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        *self.base_revision.lock().expect("revision lock poisoned") =
+            row[0].as_i64().map(|revision| revision as u64);
+        let content = row[1].as_str().unwrap_or_default();
+        Ok(Some(
+            serde_json::from_str(content).map_err(StorageError::from)?,
+        ))
+    }
+
+    fn not_found(name: &str) -> MceptionError {
+        MceptionError::Storage(StorageError::NotFound(format!(
+            "Backup '{}' not found",
+            name
+        )))
+    }
+}
+
+#[async_trait]
+impl ConfigStorage for SqliteConfigStorage {
+    fn location(&self) -> String {
+        format!("sqlite:{}", self.connection.path())
+    }
+
+    async fn load_config(&self) -> MceptionResult<ServerConfig> {
+        let Some(value) = self.read_raw_config().await? else {
+            let default_config = ServerConfig::default();
+            self.save_config(&default_config).await?;
+            return Ok(default_config);
+        };
+
+        let config: ServerConfig = serde_json::from_value(value).map_err(StorageError::from)?;
+        if config.metadata.schema_version > CONFIG_SCHEMA_VERSION {
+            return Err(MceptionError::Configuration(
+                ConfigurationError::InvalidConfiguration(format!(
+                    "Configuration schema version {} is newer than the supported version {}",
+                    config.metadata.schema_version, CONFIG_SCHEMA_VERSION
+                )),
+            ));
+        }
+        if !config.includes.is_empty() {
+            return Err(MceptionError::Configuration(
+                ConfigurationError::InvalidConfiguration(
+                    "includes are only supported by file storage".to_string(),
+                ),
+            ));
+        }
+        Ok(config)
+    }
+
+    async fn save_config(&self, config: &ServerConfig) -> MceptionResult<()> {
+        let content = serde_json::to_string_pretty(config).map_err(StorageError::from)?;
+        let revision = config.metadata.revision as i64;
+        let base = *self.base_revision.lock().expect("revision lock poisoned");
+        self.connection
+            .run(move |connection| {
+                connection
+                    .transaction(|connection| {
+                        let stored = connection
+                            .query("SELECT revision FROM config WHERE id = 1", &[])?
+                            .first()
+                            .and_then(|row| row[0].as_i64());
+                        if let (Some(base), Some(stored)) = (base, stored)
+                            && stored as u64 != base
+                        {
+                            return Ok(Err(StorageError::Conflict(format!(
+                                "the stored configuration is at revision {}, not {} as last \
+                                 loaded; reload it before saving",
+                                stored, base
+                            ))));
+                        }
+                        connection.execute(
+                            "INSERT INTO config (id, revision, content, updated_at)
+                             VALUES (1, ?1, ?2, ?3)
+                             ON CONFLICT (id) DO UPDATE SET
+                                 revision = excluded.revision,
+                                 content = excluded.content,
+                                 updated_at = excluded.updated_at",
+                            &[
+                                revision.into(),
+                                content.into(),
+                                Utc::now().to_rfc3339().into(),
+                            ],
+                        )?;
+                        Ok(Ok(()))
+                    })
+                    .map_err(database_error)?
+                    .map_err(MceptionError::from)
+            })
+            .await?;
+        *self.base_revision.lock().expect("revision lock poisoned") = Some(revision as u64);
+        Ok(())
+    }
+
+    async fn config_exists(&self) -> MceptionResult<bool> {
+        self.connection
+            .run(|connection| {
+                let rows = connection
+                    .query("SELECT 1 FROM config WHERE id = 1", &[])
+                    .map_err(database_error)?;
+                Ok(!rows.is_empty())
+            })
+            .await
+    }
+
+    async fn backup_config(&self) -> MceptionResult<String> {
+        let Some(current) = self.read_raw_config().await? else {
+            return Err(MceptionError::Storage(StorageError::NotFound(
+                "Configuration not found for backup".to_string(),
+            )));
+        };
+        let created_at = Utc::now();
+        let name = format!("backup.{}", created_at.format(BACKUP_TIMESTAMP_FORMAT));
+        let content = serde_json::to_string_pretty(&current).map_err(StorageError::from)?;
+        let row_name = name.clone();
+        self.connection
+            .run(move |connection| {
+                connection
+                    .execute(
+                        "INSERT INTO config_backups (name, created_at, content) VALUES (?1, ?2, ?3)",
+                        &[
+                            row_name.into(),
+                            created_at.to_rfc3339().into(),
+                            content.into(),
+                        ],
+                    )
+                    .map_err(database_error)
+            })
+            .await?;
+
+        if let Some(keep) = self.keep_backups {
+            self.prune_backups(keep).await?;
+        }
+        Ok(name)
+    }
+
+    async fn list_backups(&self) -> MceptionResult<Vec<BackupInfo>> {
+        let rows = self
+            .connection
+            .run(|connection| {
+                connection
+                    .query(
+                        "SELECT name, created_at, length(CAST(content AS BLOB))
+                         FROM config_backups ORDER BY name",
+                        &[],
+                    )
+                    .map_err(database_error)
+            })
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| BackupInfo {
+                name: row[0].as_str().unwrap_or_default().to_string(),
+                kind: BackupKind::Full,
+                base: None,
+                compressed: false,
+                corrupt: false,
+                size_bytes: row[2].as_i64().unwrap_or_default() as u64,
+                created_at: row[1]
+                    .as_str()
+                    .and_then(|created_at| DateTime::parse_from_rfc3339(created_at).ok())
+                    .map_or_else(Utc::now, |created_at| created_at.with_timezone(&Utc)),
+            })
+            .collect())
+    }
+
+    async fn load_backup(&self, name: &str) -> MceptionResult<ServerConfig> {
+        let row_name = name.to_string();
+        let content = self
+            .connection
+            .run(move |connection| {
+                let rows = connection
+                    .query(
+                        "SELECT content FROM config_backups WHERE name = ?1",
+                        &[row_name.into()],
+                    )
+                    .map_err(database_error)?;
+                Ok(rows
+                    .into_iter()
+                    .next()
+                    .and_then(|row| row[0].as_str().map(str::to_string)))
+            })
+            .await?
+            .ok_or_else(|| Self::not_found(name))?;
+        Ok(serde_json::from_str(&content).map_err(StorageError::from)?)
+    }
+
+    async fn restore_backup(&self, name: &str) -> MceptionResult<ServerConfig> {
+        let config = self.load_backup(name).await?;
+        self.save_config(&config).await?;
+        Ok(config)
+    }
+
+    async fn prune_backups(&self, keep: usize) -> MceptionResult<Vec<String>> {
+        let backups = self.list_backups().await?;
+        let cutoff = backups.len().saturating_sub(keep);
+        let deleted: Vec<String> = backups[..cutoff]
+            .iter()
+            .map(|backup| backup.name.clone())
+            .collect();
+        let names = deleted.clone();
+        self.connection
+            .run(move |connection| {
+                for name in names {
+                    connection
+                        .execute(
+                            "DELETE FROM config_backups WHERE name = ?1",
+                            &[SqlValue::from(name)],
+                        )
+                        .map_err(database_error)?;
+                }
+                Ok(())
+            })
+            .await?;
+        Ok(deleted)
+    }
+
+    async fn migration_status(&self) -> MceptionResult<MigrationStatus> {
+        // A missing configuration is created at the current schema version
+        let current_version = match self.read_raw_config().await? {
+            Some(config) => migrations::schema_version(&config),
+            None => CONFIG_SCHEMA_VERSION,
+        };
+
+        Ok(MigrationStatus {
+            storage: self.location(),
+            current_version,
+            target_version: CONFIG_SCHEMA_VERSION,
+            pending: migrations::pending(current_version)
+                .map(migrations::Migration::info)
+                .collect(),
+        })
+    }
+
+    async fn migrate(&self) -> MceptionResult<Vec<MigrationInfo>> {
+        let Some(mut config) = self.read_raw_config().await? else {
+            return Ok(Vec::new());
+        };
+
+        let version = migrations::schema_version(&config);
+        if version > CONFIG_SCHEMA_VERSION {
+            return Err(MceptionError::Configuration(
+                ConfigurationError::InvalidConfiguration(format!(
+                    "Configuration schema version {} is newer than the supported version {}",
+                    version, CONFIG_SCHEMA_VERSION
+                )),
+            ));
+        }
+
+        let pending: Vec<_> = migrations::pending(version).collect();
+        if pending.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Keep the pre-migration configuration around in case a migration misbehaves
+        self.backup_config().await?;
+
+        for migration in &pending {
+            migrations::apply(migration, &mut config).map_err(|e| {
+                ConfigurationError::InvalidConfiguration(format!(
+                    "Migration to schema version {} failed: {}",
+                    migration.version, e
+                ))
+            })?;
+        }
+
+        let migrated: ServerConfig = serde_json::from_value(config).map_err(StorageError::from)?;
+        self.save_config(&migrated).await?;
+
+        Ok(pending.iter().map(|migration| migration.info()).collect())
+    }
+}
@@ -0,0 +1,61 @@
+//! Parsing of configuration documents with thousands of leaf MCPs and agents
+
+use crate::core::{MceptionResult, ServerConfig, StorageError};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::value::RawValue;
+use serde_json::{Map, Value};
+
+/// The sections of a configuration document, the large ones left unparsed
+#[derive(Deserialize)]
+struct Sections<'a> {
+    #[serde(borrow, default)]
+    leaf_mcps: Option<&'a RawValue>,
+    #[serde(borrow, default)]
+    agents: Option<&'a RawValue>,
+    #[serde(flatten)]
+    rest: Map<String, Value>,
+}
+
+/// Parse a configuration document like `serde_json::from_str`, but with the
+/// leaf MCPs and the agents deserialized on separate threads. The small
+/// sections, metadata included, are parsed first.
+pub fn parse_config(content: &str) -> serde_json::Result<ServerConfig> {
+    let sections: Sections = serde_json::from_str(content)?;
+    let mut rest = sections.rest;
+    // Placeholders, so a missing section is reported like before
+    for (name, section) in [
+        ("leaf_mcps", sections.leaf_mcps),
+        ("agents", sections.agents),
+    ] {
+        if section.is_some() {
+            rest.insert(name.to_string(), Value::Object(Map::new()));
+        }
+    }
+    let mut config: ServerConfig = serde_json::from_value(Value::Object(rest))?;
+
+    let (leaf_mcps, agents) = std::thread::scope(|scope| {
+        let leaf_mcps = scope.spawn(|| parse_section(sections.leaf_mcps));
+        let agents = parse_section(sections.agents);
+        (leaf_mcps.join().expect("leaf MCP parser panicked"), agents)
+    });
+    config.leaf_mcps = leaf_mcps?;
+    config.agents = agents?;
+    Ok(config)
+}
+
+fn parse_section<T: DeserializeOwned + Default>(
+    section: Option<&RawValue>,
+) -> serde_json::Result<T> {
+    section.map_or(Ok(T::default()), |section| {
+        serde_json::from_str(section.get())
+    })
+}
+
+/// [`parse_config`] on a blocking thread, off the async runtime
+pub async fn parse_config_blocking(content: String) -> MceptionResult<ServerConfig> {
+    tokio::task::spawn_blocking(move || parse_config(&content))
+        .await
+        .map_err(|e| StorageError::Corruption(format!("Configuration parser failed: {}", e)))?
+        .map_err(|e| StorageError::from(e).into())
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::leaf_mcp;
+use mception_server::core::{LeafMcpConfig, McpTransport, StdioSandbox};
+use mception_server::services::ConfigService;
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::{RouterOptions, build_router};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+fn service() -> Arc<ConfigService> {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ))
+}
+
+fn leaf() -> LeafMcpConfig {
+    leaf_mcp(McpTransport::Stdio {
+        command: "npx".to_string(),
+        args: vec![],
+        env: None,
+        sandbox: StdioSandbox::default(),
+    })
+}
+
+async fn serve(service: Arc<ConfigService>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/admin", listener.local_addr().unwrap());
+    let router = build_router(service, RouterOptions::default());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    url
+}
+
+async fn answer(response: reqwest::Response) -> (u16, Value) {
+    let status = response.status().as_u16();
+    (status, response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn errors_have_their_status_and_kind() {
+    let url = serve(service()).await;
+    let client = reqwest::Client::new();
+    let create = |should_create: bool| {
+        client
+            .post(format!("{}/leaf", url))
+            .json(&json!({
+                "id": "files",
+                "config": leaf(),
+                "reason": null,
+                "should_create": should_create
+            }))
+            .send()
+    };
+
+    let (status, _) = answer(create(true).await.unwrap()).await;
+    assert_eq!(status, 200);
+
+    let (status, body) = answer(create(true).await.unwrap()).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["error"]["kind"], "already_exists");
+    assert!(body["error"]["message"].as_str().unwrap().contains("files"));
+
+    let (status, body) = answer(create(false).await.unwrap()).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["kind"], "required_field_missing");
+
+    let (status, body) = answer(
+        client
+            .get(format!("{}/agent/missing/config", url))
+            .send()
+            .await
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"]["kind"], "not_found");
+
+    let (status, body) = answer(
+        client
+            .get(format!("{}/bundle/missing", url))
+            .send()
+            .await
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"]["kind"], "not_found");
+}
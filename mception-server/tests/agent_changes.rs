@@ -0,0 +1,203 @@
+mod common;
+
+use common::{TestServer, answer, leaf_mcp};
+use mception_server::core::{BuiltinMcpKind, McpTransport};
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+use std::time::Duration;
+
+async fn add_leaf(server: &TestServer, id: &str) {
+    let leaf = leaf_mcp(McpTransport::Builtin {
+        kind: BuiltinMcpKind::Echo,
+    });
+    server
+        .service
+        .create_leaf_mcp(Some(id.to_string()), leaf, None, None)
+        .await
+        .unwrap();
+}
+
+async fn add_agent(server: &TestServer, id: &str, allowed: &[&str]) {
+    server
+        .service
+        .create_agent(
+            Some(id.to_string()),
+            None,
+            allowed.iter().map(|id| id.to_string()).collect(),
+            None,
+        )
+        .await
+        .unwrap();
+}
+
+fn kinds(feed: &Value) -> Vec<(String, String)> {
+    feed["changes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|change| {
+            (
+                change["kind"].as_str().unwrap().to_string(),
+                change["mcp_id"].as_str().unwrap_or_default().to_string(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn feed_shows_only_the_agents_own_changes() {
+    let server = TestServer::start().await;
+    add_leaf(&server, "search").await;
+    add_leaf(&server, "mail").await;
+    add_agent(&server, "alice", &["search"]).await;
+    add_agent(&server, "bob", &["mail"]).await;
+    let service = &server.service;
+    let ops = || Some("ops".to_string());
+
+    service
+        .update_leaf_mcp(
+            "search",
+            json!({ "config": { "api_key": "s3cret-value" } }),
+            ops(),
+            Some("new key".to_string()),
+        )
+        .await
+        .unwrap();
+    // Before alice was allowed to use it
+    service
+        .update_leaf_mcp("mail", json!({ "description": "Mail" }), ops(), None)
+        .await
+        .unwrap();
+    service
+        .add_agent_allowed_mcp("alice", "mail", ops(), Some("needs mail".to_string()))
+        .await
+        .unwrap();
+    service
+        .remove_agent_allowed_mcp("bob", "mail", ops(), None)
+        .await
+        .unwrap();
+    service
+        .update_agent(
+            "alice",
+            json!({ "auth_token": "alice-token", "description": "Assistant" }),
+            ops(),
+            Some("rotation".to_string()),
+        )
+        .await
+        .unwrap();
+
+    let alice = |path: &str| {
+        server
+            .request(Method::GET, &format!("/agent/alice/changes{}", path))
+            .bearer_auth("alice-token")
+    };
+    let (status, feed) = answer(alice("")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        kinds(&feed),
+        [
+            ("grant_added", "search"),
+            ("mcp_updated", "search"),
+            ("grant_added", "mail"),
+            ("token_rotated", ""),
+            ("agent_updated", ""),
+        ]
+        .map(|(kind, mcp)| (kind.to_string(), mcp.to_string()))
+    );
+    let changes = &feed["changes"];
+    assert_eq!(changes[1]["fields"], json!(["config.api_key"]));
+    assert_eq!(changes[1]["reason"], "new key");
+    assert_eq!(changes[1]["actor"], "ops");
+    assert_eq!(changes[4]["fields"], json!(["description"]));
+    assert_eq!(feed["actors_masked"], false);
+    // Neither secrets nor other agents' changes
+    let text = feed.to_string();
+    assert!(!text.contains("s3cret-value"), "{}", text);
+    assert!(!text.contains("alice-token"), "{}", text);
+    assert!(!text.contains("bob"), "{}", text);
+
+    let (_, bob) = answer(server.request(Method::GET, "/agent/bob/changes")).await;
+    assert_eq!(
+        kinds(&bob),
+        [
+            ("grant_added", "mail"),
+            ("mcp_updated", "mail"),
+            ("grant_removed", "mail")
+        ]
+        .map(|(kind, mcp)| (kind.to_string(), mcp.to_string()))
+    );
+    assert!(!bob.to_string().contains("search"), "{}", bob);
+
+    // Paginated like the audit log
+    let (_, first) = answer(alice("?limit=3")).await;
+    assert_eq!(first["returned"], 3);
+    assert_eq!(first["total"], 5);
+    let cursor = first["next_cursor"].as_str().unwrap();
+    let (_, rest) = answer(alice(&format!("?limit=3&cursor={}", cursor))).await;
+    assert_eq!(rest["changes"][0]["id"], changes[3]["id"]);
+    assert!(rest["next_cursor"].is_null());
+
+    let since = feed["changes"][2]["at"]
+        .as_str()
+        .unwrap()
+        .replace('+', "%2B");
+    let (_, recent) = answer(alice(&format!("?since={}", since))).await;
+    assert_eq!(recent["total"], 3);
+
+    // The agent's own token is required
+    let (status, _) = answer(server.request(Method::GET, "/agent/alice/changes")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = answer(
+        server
+            .request(Method::GET, "/agent/missing/changes")
+            .bearer_auth("alice-token"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn streams_masked_changes_as_they_are_committed() {
+    let server = TestServer::builder().mask_change_actors().start().await;
+    add_leaf(&server, "search").await;
+    add_leaf(&server, "mail").await;
+    add_agent(&server, "alice", &["search"]).await;
+
+    let (_, feed) = answer(server.request(Method::GET, "/agent/alice/changes")).await;
+    assert_eq!(feed["actors_masked"], true);
+    assert!(feed["changes"][0]["actor"].is_null());
+
+    let mut stream = server
+        .request(Method::GET, "/agent/alice/changes/stream")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stream.status(), StatusCode::OK);
+    assert_eq!(stream.headers()["content-type"], "text/event-stream");
+    let mut received = String::new();
+    let mut read_until = async |needle: &str| {
+        while !received.contains(needle) {
+            let chunk = tokio::time::timeout(Duration::from_secs(10), stream.chunk())
+                .await
+                .expect("no change streamed")
+                .unwrap()
+                .unwrap();
+            received.push_str(&String::from_utf8_lossy(&chunk));
+        }
+        received.clone()
+    };
+    assert!(
+        read_until("\"grant_added\"")
+            .await
+            .contains("event: change")
+    );
+
+    server
+        .service
+        .add_agent_allowed_mcp("alice", "mail", Some("ops".to_string()), None)
+        .await
+        .unwrap();
+    let events = read_until("\"mcp_id\":\"mail\"").await;
+    assert!(!events.contains("ops"), "{}", events);
+    assert_eq!(events.matches("event: change").count(), 2);
+}
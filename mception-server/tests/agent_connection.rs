@@ -0,0 +1,152 @@
+use futures_util::StreamExt;
+use mception_server::core::{AuditAction, AuditLogEntry};
+use mception_server::services::ConfigService;
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::{RouterOptions, build_router};
+use std::sync::Arc;
+use std::time::Duration;
+
+async fn service(staleness: chrono::Duration) -> Arc<ConfigService> {
+    pinged_service(staleness, chrono::Duration::seconds(30)).await
+}
+
+async fn pinged_service(
+    staleness: chrono::Duration,
+    ping_interval: chrono::Duration,
+) -> Arc<ConfigService> {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let service = Arc::new(
+        ConfigService::new(
+            Arc::new(FileConfigStorage::new(
+                dir.join("config.json").to_string_lossy(),
+            )),
+            Arc::new(FileAuditStorage::new(
+                dir.join("audit.log").to_string_lossy(),
+            )),
+        )
+        .with_agent_staleness(staleness)
+        .with_agent_ping_interval(ping_interval),
+    );
+    service
+        .create_agent(Some("writer".to_string()), None, vec![], None)
+        .await
+        .unwrap();
+    service
+}
+
+async fn is_connected(service: &ConfigService) -> bool {
+    service.get_configuration().await.agents["writer"].is_connected
+}
+
+async fn connection_changes(service: &ConfigService) -> Vec<AuditLogEntry> {
+    service
+        .get_audit_logs()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|entry| matches!(entry.action, AuditAction::ConnectionChange))
+        .collect()
+}
+
+#[tokio::test]
+async fn polling_marks_connected_and_audits_the_transition_once() {
+    let service = service(chrono::Duration::seconds(90)).await;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!(
+        "http://{}/agent/writer/config",
+        listener.local_addr().unwrap()
+    );
+    let router = build_router(service.clone(), RouterOptions::default());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    for _ in 0..3 {
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let agent = &service.get_configuration().await.agents["writer"];
+    assert!(agent.is_connected);
+    assert!(agent.last_seen.is_some());
+    let changes = connection_changes(&service).await;
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].details["connected"], true);
+}
+
+#[tokio::test]
+async fn stale_agents_are_swept() {
+    let service = service(chrono::Duration::milliseconds(50)).await;
+    service.mark_agent_seen("writer").await;
+    assert!(service.sweep_stale_agents().await.is_empty());
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(service.sweep_stale_agents().await, vec!["writer"]);
+    assert!(!is_connected(&service).await);
+    assert!(service.sweep_stale_agents().await.is_empty());
+
+    let changes = connection_changes(&service).await;
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[1].details["connected"], false);
+    assert_eq!(changes[1].details["cause"], "stale");
+}
+
+#[tokio::test]
+async fn closing_the_websocket_disconnects() {
+    let service = service(chrono::Duration::seconds(90)).await;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!(
+        "ws://{}/agent/writer/forwarding_ws",
+        listener.local_addr().unwrap()
+    );
+    let router = build_router(service.clone(), RouterOptions::default());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(is_connected(&service).await);
+
+    socket.close(None).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!is_connected(&service).await);
+    let changes = connection_changes(&service).await;
+    assert_eq!(changes.last().unwrap().details["cause"], "disconnected");
+}
+
+#[tokio::test]
+async fn agents_missing_pongs_are_disconnected() {
+    let service = pinged_service(
+        chrono::Duration::seconds(90),
+        chrono::Duration::milliseconds(100),
+    )
+    .await;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!(
+        "ws://{}/agent/writer/forwarding_ws",
+        listener.local_addr().unwrap()
+    );
+    let router = build_router(service.clone(), RouterOptions::default());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    // Reading the socket answers pings
+    let (mut responsive, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+    let reader = tokio::spawn(async move { while let Some(Ok(_)) = responsive.next().await {} });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let connected_at = service.get_configuration().await.agents["writer"].last_seen;
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    let agent = &service.get_configuration().await.agents["writer"];
+    assert!(agent.is_connected);
+    assert!(agent.last_seen > connected_at);
+    reader.abort();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // A socket nobody reads never answers
+    let (_silent, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(is_connected(&service).await);
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert!(!is_connected(&service).await);
+    let changes = connection_changes(&service).await;
+    let last = changes.last().unwrap();
+    assert_eq!(last.details["connected"], false);
+    assert_eq!(last.details["cause"], "unresponsive");
+}
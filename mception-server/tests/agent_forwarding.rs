@@ -0,0 +1,303 @@
+use futures_util::{SinkExt, StreamExt};
+use mception_server::core::{BodyEncoding, ForwardingMessage};
+use mception_server::services::ConfigService;
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::{RouterOptions, build_router};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+
+type Socket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// A server with the agent `writer`, returning its address
+async fn serve() -> String {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let service = Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ));
+    service
+        .create_agent(Some("writer".to_string()), None, vec![], None)
+        .await
+        .unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let router = build_router(service, RouterOptions::default());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    address
+}
+
+/// Connect as the agent and give the server a moment to register the socket
+async fn connect(address: &str) -> Socket {
+    let (socket, _) =
+        tokio_tungstenite::connect_async(format!("ws://{}/agent/writer/forwarding_ws", address))
+            .await
+            .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    socket
+}
+
+/// Answer every forwarded request with `201` and the request in the body
+async fn echo_requests(mut socket: Socket) {
+    while let Some(Ok(Message::Text(text))) = socket.next().await {
+        let Ok(ForwardingMessage::Request {
+            request_id,
+            url_params,
+            headers,
+            body,
+            deadline_ms,
+            ..
+        }) = serde_json::from_str(&text)
+        else {
+            continue;
+        };
+        let response = ForwardingMessage::Response {
+            request_id,
+            status_code: 201,
+            headers: BTreeMap::from([
+                ("content-type".to_string(), "application/json".to_string()),
+                ("x-agent".to_string(), "writer".to_string()),
+            ]),
+            body: Some(
+                json!({
+                    "url_params": url_params,
+                    "headers": headers,
+                    "body": body,
+                    "deadline_ms": deadline_ms,
+                })
+                .to_string(),
+            ),
+            encoding: BodyEncoding::Utf8,
+        };
+        let text = serde_json::to_string(&response).unwrap();
+        socket.send(Message::Text(text.into())).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn requests_are_relayed_over_the_socket() {
+    let address = serve().await;
+    tokio::spawn(echo_requests(connect(&address).await));
+
+    let response = reqwest::Client::new()
+        .post(format!(
+            "http://{}/agent/writer/forwarding?task=summarize",
+            address
+        ))
+        .header("x-request-origin", "test")
+        .header("x-mception-deadline-ms", "5000")
+        .body("hello")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 201);
+    assert_eq!(response.headers()["x-agent"], "writer");
+    let received: Value = response.json().await.unwrap();
+    assert_eq!(received["url_params"], "task=summarize");
+    assert_eq!(received["body"], "hello");
+    assert_eq!(received["headers"]["x-request-origin"], "test");
+    assert!(received["headers"].get("x-mception-deadline-ms").is_none());
+    assert!(received["deadline_ms"].as_u64().unwrap() <= 5000);
+}
+
+#[tokio::test]
+async fn unconnected_and_unknown_agents_are_refused() {
+    let address = serve().await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("http://{}/agent/writer/forwarding", address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 503);
+
+    let response = client
+        .post(format!("http://{}/agent/nobody/forwarding", address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn unanswered_requests_time_out() {
+    let address = serve().await;
+    // Connected, but never answering
+    let _socket = connect(&address).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/agent/writer/forwarding", address))
+        .header("x-mception-deadline-ms", "200")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 504);
+}
+
+#[tokio::test]
+async fn disconnect_fails_waiting_requests() {
+    let address = serve().await;
+    let mut socket = connect(&address).await;
+    // Close the socket once the request arrived
+    tokio::spawn(async move {
+        socket.next().await;
+        socket.close(None).await.unwrap();
+    });
+
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/agent/writer/forwarding", address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 503);
+}
+
+/// The response to the request `text` with its body echoed back
+fn echo_response(text: &str) -> ForwardingMessage {
+    let ForwardingMessage::Request {
+        request_id,
+        body,
+        encoding,
+        ..
+    } = serde_json::from_str(text).unwrap()
+    else {
+        panic!("not a request: {}", text);
+    };
+    ForwardingMessage::Response {
+        request_id,
+        status_code: 200,
+        headers: BTreeMap::new(),
+        body,
+        encoding,
+    }
+}
+
+#[tokio::test]
+async fn concurrent_requests_share_the_socket() {
+    const CALLERS: usize = 60;
+    let address = serve().await;
+    let mut socket = connect(&address).await;
+    // Collect every request, then answer them in reverse, after a response
+    // to a request nobody waits for
+    tokio::spawn(async move {
+        let mut requests = Vec::new();
+        while requests.len() < CALLERS {
+            if let Some(Ok(Message::Text(text))) = socket.next().await {
+                requests.push(text.to_string());
+            }
+        }
+        let mut responses = vec![ForwardingMessage::Response {
+            request_id: "unknown".to_string(),
+            status_code: 200,
+            headers: BTreeMap::new(),
+            body: Some("stray".to_string()),
+            encoding: BodyEncoding::Utf8,
+        }];
+        responses.extend(requests.iter().rev().map(|text| echo_response(text)));
+        for response in responses {
+            let text = serde_json::to_string(&response).unwrap();
+            socket.send(Message::Text(text.into())).await.unwrap();
+        }
+        // Keep the socket open until the callers are done
+        while socket.next().await.is_some() {}
+    });
+
+    let client = reqwest::Client::new();
+    let callers: Vec<_> = (0..CALLERS)
+        .map(|caller| {
+            let client = client.clone();
+            let url = format!("http://{}/agent/writer/forwarding", address);
+            tokio::spawn(async move {
+                let response = client
+                    .post(url)
+                    .header("x-mception-deadline-ms", "10000")
+                    .body(format!("caller {}", caller))
+                    .send()
+                    .await
+                    .unwrap();
+                (response.status().as_u16(), response.text().await.unwrap())
+            })
+        })
+        .collect();
+    for (caller, handle) in callers.into_iter().enumerate() {
+        let (status, body) = handle.await.unwrap();
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body, format!("caller {}", caller));
+    }
+}
+
+#[tokio::test]
+async fn binary_bodies_are_relayed_unchanged() {
+    let address = serve().await;
+    let mut socket = connect(&address).await;
+    let payload: Vec<u8> = vec![0x89, b'P', b'N', b'G', 0, 0, 0xff, 0xfe, b'\n', 0xc3];
+    let answer = payload.clone();
+    tokio::spawn(async move {
+        while let Some(Ok(Message::Text(text))) = socket.next().await {
+            // Over the socket the body is base64, as it isn't UTF-8
+            let raw: Value = serde_json::from_str(&text).unwrap();
+            assert_eq!(raw["encoding"], "base64", "{}", raw);
+            assert_eq!(raw["body"], "iVBORwAA//4Kww==");
+            let response = echo_response(&text);
+            socket
+                .send(Message::Text(
+                    serde_json::to_string(&response).unwrap().into(),
+                ))
+                .await
+                .unwrap();
+        }
+    });
+
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/agent/writer/forwarding", address))
+        .body(payload)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.bytes().await.unwrap().to_vec(), answer);
+}
+
+#[test]
+fn bodies_are_encoded_as_text_when_they_can_be() {
+    let (body, encoding) = BodyEncoding::encode(b"{\"jsonrpc\":\"2.0\"}".to_vec());
+    assert_eq!(encoding, BodyEncoding::Utf8);
+    assert_eq!(body, "{\"jsonrpc\":\"2.0\"}");
+
+    for bytes in [vec![0xff], vec![0, 0xc3], vec![b'a', 0, 0x80, 0xfe, 0xfd]] {
+        let (body, encoding) = BodyEncoding::encode(bytes.clone());
+        assert_eq!(encoding, BodyEncoding::Base64);
+        assert_eq!(encoding.decode(body).unwrap(), bytes);
+    }
+
+    // Agents that don't know about encodings send and expect plain text
+    let message: ForwardingMessage = serde_json::from_value(json!({
+        "type": "response",
+        "request_id": "1",
+        "status_code": 200,
+        "headers": {},
+        "body": "plain"
+    }))
+    .unwrap();
+    let ForwardingMessage::Response { encoding, .. } = &message else {
+        panic!("not a response");
+    };
+    assert_eq!(*encoding, BodyEncoding::Utf8);
+    assert!(
+        serde_json::to_value(&message)
+            .unwrap()
+            .get("encoding")
+            .is_none()
+    );
+}
@@ -0,0 +1,466 @@
+mod common;
+
+use common::TestServer;
+use mception_server::core::{AuditAction, AuditLogEntry, AuditTarget, McpTransport, REDACTED};
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+
+fn create_leaf(id: &str, url: &str) -> Value {
+    json!({
+        "id": id,
+        "config": {
+            "name": id,
+            "description": null,
+            "transport": { "type": "https", "url": url, "headers": null },
+            "is_local": false,
+            "reachable_by_agent": false,
+            "config": {}
+        },
+        "reason": "onboarding"
+    })
+}
+
+#[tokio::test]
+async fn walks_through_the_admin_and_agent_api() {
+    let server = TestServer::builder()
+        .admin_token("ops=secret")
+        .start()
+        .await;
+
+    for (id, url) in [
+        ("search", "https://search.example.com/mcp"),
+        ("files", "https://files.example.com/mcp"),
+    ] {
+        let (status, body) = server
+            .admin_json(Method::POST, "/leaf", &create_leaf(id, url))
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["id"], id);
+    }
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/agent",
+            &json!({
+                "agent_id": "bot",
+                "name": "Bot",
+                "allowed_mcp_ids": ["search"]
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["agent_id"], "bot");
+
+    let grant = json!({ "mcp_id": "files", "reason": "needs files" });
+    let (status, _) = server
+        .admin_json(Method::POST, "/agent/bot/allowed_mcps", &grant)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let revoke = json!({ "mcp_id": "search", "reason": "moved" });
+    let (status, _) = server
+        .admin_json(Method::DELETE, "/agent/bot/allowed_mcps", &revoke)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, agent) = server.admin_get("/agent/bot/config").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(agent["allowed_mcp_ids"], json!(["files"]));
+
+    let remote: Value = server
+        .request(Method::GET, "/agent/bot/config")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let mcps: Vec<&String> = remote["mcps"].as_object().unwrap().keys().collect();
+    assert_eq!(mcps, ["files"]);
+
+    let (status, _) = server
+        .admin_json(
+            Method::DELETE,
+            "/leaf/search",
+            &json!({ "reason": "retired" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Everything is on disk, as a restart would load it
+    let saved = server.saved_config();
+    assert_eq!(saved.leaf_mcps.keys().collect::<Vec<_>>(), ["files"]);
+    assert_eq!(saved.agents["bot"].allowed_mcp_ids, ["files".to_string()]);
+
+    let entries = server.audit_entries().await;
+    let actions: Vec<_> = entries
+        .iter()
+        .map(|entry| (&entry.action, &entry.target))
+        .collect();
+    assert_eq!(actions.len(), 8, "{:?}", actions);
+    assert!(
+        matches!(actions[0], (AuditAction::Create, AuditTarget::LeafMcp { id }) if id == "search")
+    );
+    assert!(matches!(actions[2], (AuditAction::Create, AuditTarget::Agent { id }) if id == "bot"));
+    assert!(matches!(
+        actions[3],
+        (AuditAction::AddAllowedMcp, AuditTarget::AgentAllowedMcp { mcp_id, .. }) if mcp_id == "files"
+    ));
+    assert!(matches!(actions[4], (AuditAction::RemoveAllowedMcp, _)));
+    assert!(matches!(actions[5], (AuditAction::Read, _)));
+    // Fetching its remote configuration marks the agent as seen
+    assert!(matches!(actions[6], (AuditAction::ConnectionChange, _)));
+    assert!(
+        matches!(actions[7], (AuditAction::Delete, AuditTarget::LeafMcp { id }) if id == "search")
+    );
+    assert!(
+        entries
+            .iter()
+            .filter(|entry| !matches!(entry.action, AuditAction::ConnectionChange))
+            .all(|entry| entry.actor.as_deref() == Some("ops"))
+    );
+    assert_eq!(entries[3].reason.as_deref(), Some("needs files"));
+    assert!(
+        entries
+            .windows(2)
+            .all(|pair| pair[0].sequence < pair[1].sequence)
+    );
+}
+
+#[tokio::test]
+async fn answers_bad_requests_with_errors() {
+    let server = TestServer::builder().admin_token("secret").start().await;
+    let leaf = create_leaf("search", "https://search.example.com/mcp");
+    let (status, _) = server.admin_json(Method::POST, "/leaf", &leaf).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = server.admin_json(Method::POST, "/leaf", &leaf).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"]["kind"], "already_exists");
+
+    let (status, _) = server.admin_get("/leaf/missing/config").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = server.admin_get("/agent/missing/config").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = common::answer(server.request(Method::GET, "/agent/missing/config")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = common::answer(
+        server
+            .admin(Method::POST, "/leaf")
+            .header("content-type", "application/json")
+            .body("{\"id\": "),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = server
+        .admin_json(
+            Method::POST,
+            "/agent",
+            &json!({ "name": "without allowed MCPs" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, _) = common::answer(server.request(Method::GET, "/admin/status")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Rejected requests change nothing
+    assert_eq!(server.saved_config().leaf_mcps.len(), 1);
+    assert!(server.saved_config().agents.is_empty());
+    assert_eq!(server.audit_entries().await.len(), 1);
+}
+
+#[tokio::test]
+async fn validates_leaf_mcps_on_create_and_update() {
+    let server = TestServer::start().await;
+    let leaf = create_leaf("search", "https://search.example.com/mcp");
+    let (status, _) = server.admin_json(Method::POST, "/leaf", &leaf).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let mut stdio = create_leaf("local", "unused");
+    stdio["config"]["transport"] =
+        json!({ "type": "stdio", "command": " ", "args": [], "env": null });
+    for (leaf, message) in [
+        (stdio, "needs a command"),
+        (create_leaf("files", "ftp://files.example.com"), "scheme"),
+        (create_leaf("search/admin", "https://a.example.com"), "'/'"),
+        (
+            create_leaf(&"a".repeat(65), "https://a.example.com"),
+            "between 1 and 64",
+        ),
+    ] {
+        let (status, body) = server.admin_json(Method::POST, "/leaf", &leaf).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        let error = body["error"]["message"].as_str().unwrap();
+        assert!(error.contains(message), "{}", error);
+    }
+
+    // The merged result is checked before anything is saved
+    for (updates, message) in [
+        (
+            json!({ "transport": { "type": "https", "url": "not a url" } }),
+            "invalid URL",
+        ),
+        (
+            json!({ "transport": { "type": "carrier-pigeon" } }),
+            "unknown variant",
+        ),
+        (json!({ "id": "renamed" }), "id can't be changed"),
+    ] {
+        let (status, body) = server
+            .admin_json(
+                Method::PUT,
+                "/leaf/search/config",
+                &json!({ "config": updates }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        let error = body["error"]["message"].as_str().unwrap();
+        assert!(error.contains(message), "{}", error);
+    }
+    let saved = server.saved_config();
+    assert_eq!(saved.leaf_mcps.len(), 1);
+    assert_eq!(saved.leaf_mcps["search"].id, "search");
+    assert_eq!(server.audit_entries().await.len(), 1);
+}
+
+#[tokio::test]
+async fn applies_updates_as_merge_patches() {
+    let server = TestServer::start().await;
+    let mut leaf = create_leaf("search", "https://search.example.com/mcp");
+    leaf["config"]["config"] = json!({ "limits": { "rps": 5, "burst": 10 }, "tags": ["a", "b"] });
+    server.admin_json(Method::POST, "/leaf", &leaf).await;
+    server
+        .admin_json(
+            Method::POST,
+            "/agent",
+            &json!({ "agent_id": "bot", "name": "Bot", "allowed_mcp_ids": ["search"] }),
+        )
+        .await;
+
+    let update = json!({ "config": {
+        "id": "search",
+        "name": null,
+        "config": { "limits": { "rps": 1, "burst": null }, "tags": ["c"], "region": "eu" }
+    } });
+    let (status, body) = server
+        .admin_json(Method::PUT, "/leaf/search/config", &update)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let saved = server.saved_config().leaf_mcps.remove("search").unwrap();
+    assert_eq!(saved.name, None);
+    assert_eq!(
+        saved.config,
+        json!({ "limits": { "rps": 1 }, "tags": ["c"], "region": "eu" })
+    );
+    assert!(matches!(saved.transport, McpTransport::Https { .. }));
+
+    let (status, body) = server
+        .admin_json(
+            Method::PUT,
+            "/agent/bot/config",
+            &json!({ "config": { "agent_id": "other", "name": null } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("agent_id")
+    );
+    let (status, _) = server
+        .admin_json(
+            Method::PUT,
+            "/agent/bot/config",
+            &json!({ "config": { "name": null } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(server.saved_config().agents["bot"].name, None);
+}
+
+#[tokio::test]
+async fn lists_leaf_mcps_and_agents() {
+    let server = TestServer::start().await;
+    let mut local = create_leaf("local", "unused");
+    local["config"]["transport"] =
+        json!({ "type": "stdio", "command": "mcp", "args": [], "env": null });
+    local["config"]["is_local"] = json!(true);
+    for leaf in [
+        create_leaf("search", "https://search.example.com/mcp"),
+        local,
+        create_leaf("files", "https://files.example.com/mcp"),
+    ] {
+        server.admin_json(Method::POST, "/leaf", &leaf).await;
+    }
+    for id in ["writer", "reader"] {
+        server
+            .admin_json(
+                Method::POST,
+                "/agent",
+                &json!({ "agent_id": id, "name": id, "allowed_mcp_ids": ["search"] }),
+            )
+            .await;
+    }
+    server
+        .service
+        .update_agent("reader", json!({ "auth_token": "token" }), None, None)
+        .await
+        .unwrap();
+    server.service.mark_agent_seen("reader").await;
+
+    let (status, body) = server.admin_get("/leaf").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["count"], 3);
+    let ids: Vec<&String> = body["leaf_mcps"].as_object().unwrap().keys().collect();
+    assert_eq!(ids, ["files", "local", "search"]);
+    assert_eq!(body["leaf_mcps"]["search"]["transport"]["type"], "https");
+
+    let (_, body) = server
+        .admin_get("/leaf?transport=https&fields=id,name")
+        .await;
+    assert_eq!(body["count"], 2);
+    assert_eq!(
+        body["leaf_mcps"]["files"],
+        json!({ "id": "files", "name": "files" })
+    );
+    let (_, body) = server.admin_get("/leaf?is_local=true").await;
+    assert_eq!(body["count"], 1);
+    assert!(body["leaf_mcps"]["local"].is_object());
+    let (status, _) = server.admin_get("/leaf?transport=carrier-pigeon").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, body) = server.admin_get("/agent").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["count"], 2);
+    assert_eq!(body["agents"]["reader"]["auth_token"], REDACTED);
+    let (_, body) = server
+        .admin_get("/agent?connected=true&fields=agent_id,is_connected")
+        .await;
+    assert_eq!(
+        body,
+        json!({
+            "count": 1,
+            "agents": { "reader": { "agent_id": "reader", "is_connected": true } }
+        })
+    );
+}
+
+#[tokio::test]
+async fn restores_an_exported_configuration() {
+    let server = TestServer::start().await;
+    for (id, url) in [
+        ("search", "https://search.example.com/mcp"),
+        ("files", "https://files.example.com/mcp"),
+    ] {
+        server
+            .admin_json(Method::POST, "/leaf", &create_leaf(id, url))
+            .await;
+    }
+    server
+        .admin_json(
+            Method::POST,
+            "/agent",
+            &json!({ "agent_id": "bot", "name": "Bot", "allowed_mcp_ids": ["search"] }),
+        )
+        .await;
+    let (_, exported) = server.admin_get("/config").await;
+    let audited = |entries: Vec<AuditLogEntry>| {
+        entries
+            .iter()
+            .filter(|entry| !matches!(entry.action, AuditAction::Confirmation))
+            .count()
+    };
+    let before = audited(server.audit_entries().await);
+
+    // An agent allowed an MCP that isn't imported fails the whole import
+    let mut invalid = exported.clone();
+    invalid["leaf_mcps"]
+        .as_object_mut()
+        .unwrap()
+        .remove("search");
+    let (status, body) = server
+        .admin_confirmed(
+            Method::POST,
+            "/config/restore",
+            &json!({ "config": invalid }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    assert_eq!(server.saved_config().leaf_mcps.len(), 2);
+    assert_eq!(audited(server.audit_entries().await), before);
+    let (_, backups) = server.admin_get("/config/backups").await;
+    assert!(backups["backups"].as_array().unwrap().is_empty());
+
+    let mut replacement = exported.clone();
+    replacement["leaf_mcps"]
+        .as_object_mut()
+        .unwrap()
+        .remove("search");
+    replacement["agents"]["bot"]["allowed_mcp_ids"] = json!(["files"]);
+    let (status, body) = server
+        .admin_confirmed(
+            Method::POST,
+            "/config/restore",
+            &json!({ "config": replacement, "reason": "move to files" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let summary = &body["summary"];
+    assert_eq!(summary["merge"], false);
+    assert_eq!(
+        summary["leaf_mcps"],
+        json!({ "added": 0, "changed": 0, "removed": 1 })
+    );
+    assert_eq!(
+        summary["agents"],
+        json!({ "added": 0, "changed": 1, "removed": 0 })
+    );
+    let saved = server.saved_config();
+    assert_eq!(saved.leaf_mcps.keys().collect::<Vec<_>>(), ["files"]);
+    assert_eq!(saved.agents["bot"].allowed_mcp_ids, ["files".to_string()]);
+
+    // The configuration before is kept as a backup
+    let (_, backups) = server.admin_get("/config/backups").await;
+    assert_eq!(backups["backups"].as_array().unwrap().len(), 1);
+
+    let entries = server.audit_entries().await;
+    let imported = entries.last().unwrap();
+    assert!(matches!(
+        (&imported.action, &imported.target),
+        (AuditAction::Update, AuditTarget::Server)
+    ));
+    assert_eq!(imported.reason.as_deref(), Some("move to files"));
+    assert_eq!(imported.details["leaf_mcps"]["removed"], 1);
+    assert!(matches!(
+        entries[entries.len() - 2].action,
+        AuditAction::Discontinuity
+    ));
+
+    // Merging only adds and overwrites
+    let (status, body) = server
+        .admin_confirmed(
+            Method::POST,
+            "/config/restore",
+            &json!({ "config": exported, "merge": true }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(
+        body["summary"]["leaf_mcps"],
+        json!({ "added": 1, "changed": 0, "removed": 0 })
+    );
+    let saved = server.saved_config();
+    assert_eq!(saved.leaf_mcps.len(), 2);
+    assert_eq!(saved.agents["bot"].allowed_mcp_ids, ["search".to_string()]);
+
+    let (status, _) = server
+        .admin_confirmed(
+            Method::POST,
+            "/config/restore",
+            &json!({ "config": saved, "should_restore": false }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,150 @@
+mod common;
+
+use common::TestServer;
+use mception_server::RouterOptions;
+use mception_server::core::AuditAction;
+use mception_server::services::api_versions::API_VERSION_HEADER;
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+
+fn leaf(id: &str) -> Value {
+    json!({
+        "id": id,
+        "config": {
+            "name": id,
+            "description": null,
+            "transport": { "type": "https", "url": "https://example.com/mcp", "headers": null },
+            "is_local": false,
+            "reachable_by_agent": false,
+            "config": {}
+        },
+        "reason": null
+    })
+}
+
+fn with_flag(mut request: Value, confirmed: bool) -> Value {
+    request["should_create"] = json!(confirmed);
+    request
+}
+
+fn header(response: &reqwest::Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .map(|value| value.to_str().unwrap().to_string())
+}
+
+#[tokio::test]
+async fn new_clients_leave_out_the_confirmation_flags() {
+    let server = TestServer::start().await;
+    let response = server
+        .admin(Method::POST, "/leaf")
+        .json(&leaf("search"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(header(&response, API_VERSION_HEADER).as_deref(), Some("2"));
+    assert_eq!(header(&response, "deprecation"), None);
+
+    // A flag sent anyway is still honored
+    let (status, _) = server
+        .admin_json(Method::POST, "/leaf", &with_flag(leaf("files"), false))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(!server.saved_config().leaf_mcps.contains_key("files"));
+
+    let (status, versions) = server.admin_get("/versions").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(versions["current"], 2);
+    assert_eq!(versions["default"], 2);
+    assert_eq!(versions["supported"], json!([1, 2]));
+    assert_eq!(versions["deprecations"][0]["id"], "confirmation_flags");
+    assert_eq!(versions["deprecations"][0]["replaced_in"], 2);
+
+    let (status, body) = common::answer(
+        server
+            .admin(Method::GET, "/versions")
+            .header(API_VERSION_HEADER, "7"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["kind"], "unsupported_api_version");
+}
+
+#[tokio::test]
+async fn old_clients_get_deprecation_headers_and_one_warning() {
+    let server = TestServer::builder()
+        .admin_token("ops=secret")
+        .start()
+        .await;
+    for id in ["search", "files"] {
+        let response = server
+            .admin(Method::POST, "/leaf")
+            .header(API_VERSION_HEADER, "1")
+            .json(&with_flag(leaf(id), true))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, API_VERSION_HEADER).as_deref(), Some("1"));
+        assert!(header(&response, "deprecation").unwrap().starts_with('@'));
+        assert_eq!(
+            header(&response, "sunset").as_deref(),
+            Some("Thu, 15 Apr 2027 00:00:00 GMT")
+        );
+    }
+
+    // Version 1 still requires the flag
+    let (status, body) = common::answer(
+        server
+            .admin(Method::POST, "/leaf")
+            .header(API_VERSION_HEADER, "1")
+            .json(&leaf("fetch")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["kind"], "required_field_missing");
+    assert_eq!(server.saved_config().leaf_mcps.len(), 2);
+
+    let warnings: Vec<_> = server
+        .audit_entries()
+        .await
+        .into_iter()
+        .filter(|entry| matches!(entry.action, AuditAction::DeprecatedUse))
+        .collect();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].actor.as_deref(), Some("ops"));
+    assert_eq!(warnings[0].details["deprecation"], "confirmation_flags");
+    assert_eq!(warnings[0].details["api_version"], 1);
+}
+
+#[tokio::test]
+async fn api_compat_pins_the_default_version() {
+    let server = TestServer::builder()
+        .router_options(RouterOptions {
+            api_version: 1,
+            ..RouterOptions::default()
+        })
+        .start()
+        .await;
+    let (status, _) = server
+        .admin_json(Method::POST, "/leaf", &leaf("search"))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = server
+        .admin_json(Method::POST, "/leaf", &with_flag(leaf("search"), true))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Clients asking for the current version still get it
+    let (status, versions) = common::answer(
+        server
+            .admin(Method::GET, "/versions")
+            .header(API_VERSION_HEADER, "2"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(versions["default"], 1);
+    assert_eq!(versions["requested"], 2);
+}
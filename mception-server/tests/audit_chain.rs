@@ -0,0 +1,267 @@
+mod common;
+
+use chrono::Utc;
+use common::{TestServer, temp_dir};
+use mception_server::core::{AuditAction, AuditLogEntry, AuditTarget};
+use mception_server::storage::audit_chain::{self, AuditChainReport};
+#[cfg(feature = "sqlite")]
+use mception_server::storage::providers::SqliteAuditStorage;
+use mception_server::storage::providers::{AuditStorage, FileAuditStorage};
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+
+fn entry(id: &str) -> AuditLogEntry {
+    AuditLogEntry {
+        id: id.to_string(),
+        sequence: 0,
+        timestamp: Utc::now(),
+        action: AuditAction::Update,
+        actor: Some("admin".to_string()),
+        target: AuditTarget::Server,
+        reason: None,
+        details: json!({ "entry": id }),
+        correlation_id: None,
+        idempotency_key: None,
+        revision: None,
+        server_revision: None,
+        prev_hash: None,
+        hash: None,
+    }
+}
+
+/// A file audit log holding the entries `ids`, in order
+async fn file_log(ids: &[&str]) -> (PathBuf, FileAuditStorage) {
+    let path = temp_dir().join("audit.log");
+    let storage = FileAuditStorage::new(path.to_string_lossy());
+    for id in ids {
+        storage.append_entry(&entry(id)).await.unwrap();
+    }
+    (path, storage)
+}
+
+/// Replace the lines of the audit log at `path` by what `change` makes of them
+fn rewrite(path: &Path, change: impl FnOnce(&mut Vec<String>)) {
+    let content = std::fs::read_to_string(path).unwrap();
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    change(&mut lines);
+    std::fs::write(path, lines.join("\n") + "\n").unwrap();
+}
+
+fn broken_at(report: &AuditChainReport) -> (usize, &str) {
+    let broken = report.broken_link.as_ref().expect("chain is intact");
+    (broken.entry.line, broken.entry.id.as_str())
+}
+
+#[tokio::test]
+async fn an_untouched_log_is_intact() {
+    let (path, storage) = file_log(&["a", "b", "c"]).await;
+    let entries = storage.load_entries().await.unwrap();
+    assert_eq!(entries[0].prev_hash, None);
+    assert_eq!(entries[1].prev_hash, entries[0].hash);
+    assert_eq!(entries[2].prev_hash, entries[1].hash);
+    assert!(entries[2].hash.as_deref().unwrap().starts_with("sha256:"));
+
+    let report = storage.verify_chain().await.unwrap();
+    assert!(report.is_intact(), "{:?}", report);
+    assert_eq!(report.entries, 3);
+    assert_eq!(report.legacy_entries, 0);
+    assert_eq!(report.chain_root.unwrap().line, 1);
+
+    // A new instance continues the chain where the file ends
+    let reopened = FileAuditStorage::new(path.to_string_lossy());
+    reopened.append_entry(&entry("d")).await.unwrap();
+    let report = reopened.verify_chain().await.unwrap();
+    assert!(report.is_intact(), "{:?}", report);
+    assert_eq!(report.entries, 4);
+}
+
+#[tokio::test]
+async fn tampering_with_an_entry_breaks_the_chain_there() {
+    let (path, storage) = file_log(&["a", "b", "c"]).await;
+    rewrite(&path, |lines| {
+        let mut tampered: Value = serde_json::from_str(&lines[1]).unwrap();
+        tampered["actor"] = json!("someone-else");
+        lines[1] = tampered.to_string();
+    });
+
+    let report = storage.verify_chain().await.unwrap();
+    assert_eq!(broken_at(&report), (2, "b"));
+    let broken = report.broken_link.unwrap();
+    assert_eq!(broken.entry.sequence, 2);
+    assert!(broken.entry.timestamp.is_some());
+    assert!(broken.reason.contains("content"), "{}", broken.reason);
+}
+
+#[tokio::test]
+async fn deleting_an_entry_breaks_the_chain_at_the_next() {
+    let (path, storage) = file_log(&["a", "b", "c", "d"]).await;
+    rewrite(&path, |lines| {
+        lines.remove(1);
+    });
+
+    let report = storage.verify_chain().await.unwrap();
+    assert_eq!(broken_at(&report), (2, "c"));
+    let reason = report.broken_link.unwrap().reason;
+    assert!(reason.contains("prev_hash"), "{}", reason);
+}
+
+#[tokio::test]
+async fn a_repaired_log_restarts_the_chain_after_the_dropped_entries() {
+    let (path, storage) = file_log(&["a", "b", "c", "d"]).await;
+    rewrite(&path, |lines| lines[1] = "\0\0\0\0garbage".to_string());
+    assert_eq!(broken_at(&storage.verify_chain().await.unwrap()).0, 2);
+
+    let output = path.with_extension("repaired");
+    let report = storage.repair(&output.to_string_lossy()).await.unwrap();
+    assert!(!report.is_clean());
+
+    let repaired = FileAuditStorage::new(output.to_string_lossy());
+    let chain = repaired.verify_chain().await.unwrap();
+    assert!(chain.is_intact(), "{:?}", chain);
+    assert_eq!(chain.entries, 4);
+    assert_eq!(chain.restarts.len(), 1);
+    assert_eq!(chain.restarts[0].line, 2);
+
+    let entries = repaired.load_entries().await.unwrap();
+    let ids: Vec<&str> = entries.iter().map(|entry| entry.id.as_str()).collect();
+    assert_eq!((ids[0], ids[2], ids[3]), ("a", "c", "d"));
+    let marker = &entries[1];
+    assert!(matches!(marker.action, AuditAction::AuditRepair));
+    assert_eq!(marker.prev_hash, None);
+    assert_eq!(marker.details["broken_link"]["id"], "c");
+    assert_eq!(entries[2].prev_hash, marker.hash);
+    // The entries keep their content
+    assert_eq!(entries[3].details, json!({ "entry": "d" }));
+
+    // Appending continues the restarted chain
+    repaired.append_entry(&entry("e")).await.unwrap();
+    assert!(repaired.verify_chain().await.unwrap().is_intact());
+}
+
+#[tokio::test]
+async fn a_repair_dropping_the_last_entries_appends_the_marker() {
+    let (path, storage) = file_log(&["a", "b", "c"]).await;
+    rewrite(&path, |lines| {
+        lines[2] = "{\"id\": \"c\", trunc".to_string()
+    });
+
+    let output = path.with_extension("repaired");
+    storage.repair(&output.to_string_lossy()).await.unwrap();
+    let repaired = FileAuditStorage::new(output.to_string_lossy());
+    let chain = repaired.verify_chain().await.unwrap();
+    assert!(chain.is_intact(), "{:?}", chain);
+    assert_eq!(chain.restarts.len(), 1);
+    assert_eq!(chain.restarts[0].line, 3);
+    assert_eq!(chain.restarts[0].sequence, 3);
+
+    // A clean log is copied as it is
+    let (path, storage) = file_log(&["a", "b"]).await;
+    let output = path.with_extension("repaired");
+    storage.repair(&output.to_string_lossy()).await.unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&output).unwrap(),
+        std::fs::read_to_string(&path).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn entries_from_before_the_chain_are_legacy() {
+    let path = temp_dir().join("audit.log");
+    let legacy: Vec<String> = ["old-1", "old-2"]
+        .iter()
+        .enumerate()
+        .map(|(index, id)| {
+            let mut entry = entry(id);
+            entry.sequence = index as u64 + 1;
+            serde_json::to_string(&entry).unwrap()
+        })
+        .collect();
+    std::fs::write(&path, legacy.join("\n") + "\n").unwrap();
+    let storage = FileAuditStorage::new(path.to_string_lossy());
+    storage.append_entry(&entry("a")).await.unwrap();
+    storage.append_entry(&entry("b")).await.unwrap();
+
+    let report = storage.verify_chain().await.unwrap();
+    assert!(report.is_intact(), "{:?}", report);
+    assert_eq!(report.entries, 4);
+    assert_eq!(report.legacy_entries, 2);
+    let root = report.chain_root.unwrap();
+    assert_eq!((root.line, root.id.as_str()), (3, "a"));
+
+    // Once chained, an entry without a hash is a broken link
+    rewrite(&path, |lines| {
+        let mut stripped: Value = serde_json::from_str(&lines[3]).unwrap();
+        stripped.as_object_mut().unwrap().remove("hash");
+        lines[3] = stripped.to_string();
+    });
+    let report = storage.verify_chain().await.unwrap();
+    assert_eq!(broken_at(&report), (4, "b"));
+}
+
+#[test]
+fn hashes_cover_the_entry_and_its_predecessor() {
+    let value = json!({ "id": "a", "sequence": 1, "prev_hash": "sha256:00" });
+    let hash = audit_chain::entry_hash(&value, Some("sha256:00"));
+    assert_eq!(hash, audit_chain::entry_hash(&value, Some("sha256:00")));
+    assert_ne!(hash, audit_chain::entry_hash(&value, Some("sha256:01")));
+    // The stored hash itself is not part of what's hashed
+    let mut stored = value.clone();
+    stored["hash"] = json!(hash);
+    assert_eq!(hash, audit_chain::entry_hash(&stored, Some("sha256:00")));
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn sqlite_entries_are_chained() {
+    let database = temp_dir().join("mception.db");
+    let storage = SqliteAuditStorage::open(&database.to_string_lossy()).unwrap();
+    for id in ["a", "b", "c"] {
+        storage.append_entry(&entry(id)).await.unwrap();
+    }
+    let entries = storage.load_entries().await.unwrap();
+    assert_eq!(entries[2].prev_hash, entries[1].hash);
+    let report = storage.verify_chain().await.unwrap();
+    assert!(report.is_intact(), "{:?}", report);
+    assert_eq!(report.entries, 3);
+}
+
+#[tokio::test]
+async fn the_admin_api_reports_the_first_broken_link() {
+    let server = TestServer::start().await;
+    for id in ["search", "mail", "files"] {
+        let (status, body) = server
+            .admin_json(
+                Method::POST,
+                "/leaf",
+                &json!({
+                    "id": id,
+                    "config": {
+                        "transport": { "type": "builtin", "kind": "echo" },
+                        "is_local": false,
+                        "reachable_by_agent": false,
+                        "config": {}
+                    },
+                    "reason": null
+                }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+    let (status, body) = server.admin_get("/audit/verify").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["intact"], true);
+    assert!(body["broken_link"].is_null());
+
+    rewrite(&server.audit_log_path, |lines| {
+        let mut tampered: Value = serde_json::from_str(&lines[1]).unwrap();
+        tampered["reason"] = json!("nothing to see");
+        lines[1] = tampered.to_string();
+    });
+    let (status, body) = server.admin_get("/audit/verify").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["intact"], false);
+    assert_eq!(body["broken_link"]["line"], 2);
+    assert_eq!(body["broken_link"]["sequence"], 2);
+    assert!(body["broken_link"]["timestamp"].is_string());
+}
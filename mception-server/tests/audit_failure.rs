@@ -0,0 +1,163 @@
+mod common;
+
+use common::{TestServer, answer};
+use mception_server::core::{AuditAction, AuditLogEntry, AuditTarget};
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+
+async fn set_on_failure(server: &TestServer, on_failure: &str) {
+    let (status, body) = server
+        .admin_json(
+            Method::PUT,
+            "/policy/audit",
+            &json!({ "policy": { "on_failure": on_failure }, "reason": null }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+async fn create_leaf(server: &TestServer, id: &str) -> (StatusCode, Value) {
+    server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({
+                "id": id,
+                "config": {
+                    "transport": { "type": "builtin", "kind": "echo" },
+                    "is_local": false,
+                    "reachable_by_agent": false,
+                    "config": {}
+                },
+                "reason": null
+            }),
+        )
+        .await
+}
+
+/// Make the audit log unwritable by putting a directory in its place
+fn break_audit_log(server: &TestServer) {
+    std::fs::rename(
+        &server.audit_log_path,
+        server.audit_log_path.with_extension("moved"),
+    )
+    .unwrap();
+    std::fs::create_dir(&server.audit_log_path).unwrap();
+}
+
+fn repair_audit_log(server: &TestServer) {
+    std::fs::remove_dir(&server.audit_log_path).unwrap();
+    std::fs::rename(
+        server.audit_log_path.with_extension("moved"),
+        &server.audit_log_path,
+    )
+    .unwrap();
+}
+
+async fn health(server: &TestServer) -> (StatusCode, Value) {
+    answer(server.request(Method::GET, "/ready")).await
+}
+
+fn created(entries: &[AuditLogEntry], leaf_mcp_id: &str) -> bool {
+    entries.iter().any(|entry| {
+        matches!(entry.action, AuditAction::Create)
+            && matches!(&entry.target, AuditTarget::LeafMcp { id } if id == leaf_mcp_id)
+    })
+}
+
+#[tokio::test]
+async fn changes_are_refused_while_the_audit_log_fails_by_default() {
+    let server = TestServer::start().await;
+    let (status, _) = create_leaf(&server, "search").await;
+    assert_eq!(status, StatusCode::OK);
+    break_audit_log(&server);
+    let (status, body) = create_leaf(&server, "files").await;
+    assert!(status.is_server_error(), "{} {}", status, body);
+    let (status, body) = health(&server).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "degraded");
+    assert_eq!(body["audit"], "degraded");
+
+    repair_audit_log(&server);
+    let (status, body) = create_leaf(&server, "mail").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (_, body) = health(&server).await;
+    assert_eq!(body["status"], "ok");
+    assert!(created(&server.audit_entries().await, "mail"));
+}
+
+#[tokio::test]
+async fn buffered_entries_are_written_once_the_audit_log_recovers() {
+    let server = TestServer::builder().audit_buffer_capacity(2).start().await;
+    set_on_failure(&server, "buffer").await;
+    break_audit_log(&server);
+    for id in ["search", "mail", "files"] {
+        let (status, body) = create_leaf(&server, id).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+    let (_, body) = health(&server).await;
+    assert_eq!(body["status"], "degraded");
+    let (_, policy) = server.admin_get("/policy/audit").await;
+    assert_eq!(policy["policy"]["on_failure"], "buffer");
+    assert_eq!(policy["storage"]["buffered"], 2);
+    assert_eq!(policy["storage"]["dropped_total"], 1);
+    let metrics = server
+        .request(Method::GET, "/metrics")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(
+        metrics.contains("mception_audit_buffered_entries 2"),
+        "{}",
+        metrics
+    );
+    assert!(metrics.contains("mception_audit_entries_dropped_total 1"));
+    assert!(server.service.flush_audit_buffer().await.is_err());
+
+    repair_audit_log(&server);
+    assert_eq!(server.service.flush_audit_buffer().await.unwrap(), 2);
+    let (_, body) = health(&server).await;
+    assert_eq!(body["status"], "ok");
+
+    // The held back entries kept their order, the lost one is accounted for
+    let entries = server.audit_entries().await;
+    assert!(created(&entries, "search") && created(&entries, "mail"));
+    assert!(!created(&entries, "files"));
+    let lost = entries.last().unwrap();
+    assert!(matches!(lost.action, AuditAction::EntriesLost));
+    assert_eq!(lost.details["lost_entries"], 1);
+    let sequences: Vec<u64> = entries.iter().map(|entry| entry.sequence).collect();
+    assert!(sequences.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[tokio::test]
+async fn dropped_entries_are_counted_and_recorded() {
+    let server = TestServer::start().await;
+    set_on_failure(&server, "drop").await;
+    break_audit_log(&server);
+    let (status, body) = create_leaf(&server, "search").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (_, body) = health(&server).await;
+    assert_eq!(body["status"], "degraded");
+    let (_, policy) = server.admin_get("/policy/audit").await;
+    assert_eq!(policy["storage"]["buffered"], 0);
+    assert_eq!(policy["storage"]["dropped_total"], 1);
+
+    // The next entry written reports the loss
+    repair_audit_log(&server);
+    let (status, _) = create_leaf(&server, "mail").await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = health(&server).await;
+    assert_eq!(body["status"], "ok");
+    let entries = server.audit_entries().await;
+    assert!(!created(&entries, "search"));
+    assert!(created(&entries, "mail"));
+    let lost = entries
+        .iter()
+        .find(|entry| matches!(entry.action, AuditAction::EntriesLost))
+        .unwrap();
+    assert_eq!(lost.details["lost_entries"], 1);
+}
@@ -0,0 +1,220 @@
+mod common;
+
+use chrono::{DateTime, Duration, Utc};
+use common::temp_dir;
+use mception_server::core::{AuditAction, AuditLogEntry, AuditQuery, AuditTarget};
+use mception_server::storage::providers::{AuditRotation, AuditStorage, FileAuditStorage};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+fn entry(id: &str, timestamp: DateTime<Utc>) -> AuditLogEntry {
+    AuditLogEntry {
+        id: id.to_string(),
+        sequence: 0,
+        timestamp,
+        action: AuditAction::Update,
+        actor: Some("admin".to_string()),
+        target: AuditTarget::Server,
+        reason: None,
+        details: json!({}),
+        correlation_id: None,
+        idempotency_key: None,
+        revision: None,
+        server_revision: None,
+        prev_hash: None,
+        hash: None,
+    }
+}
+
+fn rotating(path: &Path, rotation: AuditRotation) -> FileAuditStorage {
+    FileAuditStorage::new(path.to_string_lossy()).with_rotation(rotation)
+}
+
+fn segment(path: &Path, number: usize) -> PathBuf {
+    PathBuf::from(format!("{}.{}.gz", path.display(), number))
+}
+
+async fn ids(storage: &dyn AuditStorage) -> Vec<(u64, String)> {
+    storage
+        .load_entries()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| (entry.sequence, entry.id))
+        .collect()
+}
+
+#[tokio::test]
+async fn rotates_by_entry_count_and_reads_across_segments() {
+    let dir = temp_dir();
+    let path = dir.join("audit.log");
+    let storage = rotating(
+        &path,
+        AuditRotation {
+            max_entries: Some(2),
+            ..AuditRotation::default()
+        },
+    );
+    let now = Utc::now();
+    for id in ["a", "b", "c", "d", "e"] {
+        storage.append_entry(&entry(id, now)).await.unwrap();
+    }
+
+    assert!(segment(&path, 1).exists());
+    assert!(segment(&path, 2).exists());
+    assert!(!segment(&path, 3).exists());
+    // Segments are gzip-compressed
+    assert_eq!(
+        &std::fs::read(segment(&path, 2)).unwrap()[..2],
+        &[0x1f, 0x8b]
+    );
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+
+    let expected: Vec<(u64, String)> = ["a", "b", "c", "d", "e"]
+        .iter()
+        .enumerate()
+        .map(|(at, id)| (at as u64 + 1, id.to_string()))
+        .collect();
+    assert_eq!(ids(&storage).await, expected);
+    let query = AuditQuery {
+        actor: Some("admin".to_string()),
+        ..AuditQuery::default()
+    };
+    assert_eq!(
+        storage.load_entries_filtered(&query).await.unwrap().len(),
+        5
+    );
+    let report = storage.verify().await.unwrap();
+    assert_eq!(report.valid_entries, 5);
+    assert!(report.corrupt_regions.is_empty());
+
+    // Another instance continues the sequence
+    let other = FileAuditStorage::new(path.to_string_lossy());
+    assert_eq!(
+        other.append_entry(&entry("f", now)).await.unwrap().sequence,
+        6
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn rotates_by_size() {
+    let dir = temp_dir();
+    let path = dir.join("audit.log");
+    let line_len = serde_json::to_string(&entry("a", Utc::now()))
+        .unwrap()
+        .len() as u64;
+    let storage = rotating(
+        &path,
+        AuditRotation {
+            max_bytes: Some(line_len * 3),
+            ..AuditRotation::default()
+        },
+    );
+    for id in ["a", "b", "c", "d", "e", "f", "g"] {
+        storage.append_entry(&entry(id, Utc::now())).await.unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() <= line_len * 3 + 16);
+    }
+    assert!(segment(&path, 2).exists());
+    assert_eq!(ids(&storage).await.len(), 7);
+    assert_eq!(ids(&storage).await[6], (7, "g".to_string()));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn concurrent_appends_survive_rotation() {
+    let dir = temp_dir();
+    let path = dir.join("audit.log");
+    let storage = Arc::new(rotating(
+        &path,
+        AuditRotation {
+            max_entries: Some(7),
+            ..AuditRotation::default()
+        },
+    ));
+    let mut appends = tokio::task::JoinSet::new();
+    for at in 0..50 {
+        let storage = storage.clone();
+        appends.spawn(async move {
+            storage
+                .append_entry(&entry(&format!("entry-{}", at), Utc::now()))
+                .await
+                .unwrap()
+        });
+    }
+    while let Some(appended) = appends.join_next().await {
+        appended.unwrap();
+    }
+
+    let sequences: Vec<u64> = ids(storage.as_ref())
+        .await
+        .into_iter()
+        .map(|(sequence, _)| sequence)
+        .collect();
+    assert_eq!(sequences, (1..=50).collect::<Vec<u64>>());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn prunes_segments_older_than_the_retention() {
+    let dir = temp_dir();
+    let path = dir.join("audit.log");
+    let storage = rotating(
+        &path,
+        AuditRotation {
+            max_entries: Some(2),
+            ..AuditRotation::default()
+        },
+    );
+    let now = Utc::now();
+    let days_ago = |days| now - Duration::days(days);
+    for (id, timestamp) in [
+        ("a", days_ago(50)),
+        ("b", days_ago(45)),
+        // Straddles the retention, so it's kept whole
+        ("c", days_ago(40)),
+        ("d", days_ago(5)),
+        ("e", days_ago(1)),
+    ] {
+        storage.append_entry(&entry(id, timestamp)).await.unwrap();
+    }
+
+    assert_eq!(storage.prune_entries(days_ago(30)).await.unwrap(), 2);
+    assert!(!segment(&path, 2).exists());
+    assert!(segment(&path, 1).exists());
+    let remaining = ids(&storage).await;
+    assert_eq!(remaining.first(), Some(&(3, "c".to_string())));
+    assert_eq!(remaining.len(), 3);
+    assert_eq!(storage.prune_entries(days_ago(30)).await.unwrap(), 0);
+
+    // The file itself is never pruned, and the sequence goes on
+    assert_eq!(storage.prune_entries(now).await.unwrap(), 2);
+    assert_eq!(ids(&storage).await, vec![(5, "e".to_string())]);
+    assert_eq!(
+        storage
+            .append_entry(&entry("f", now))
+            .await
+            .unwrap()
+            .sequence,
+        6
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn sqlite_audit_log_is_never_pruned() {
+    use mception_server::storage::providers::SqliteAuditStorage;
+
+    let dir = temp_dir();
+    let storage = SqliteAuditStorage::open(&dir.join("mception.db").to_string_lossy()).unwrap();
+    let now = Utc::now();
+    storage
+        .append_entry(&entry("a", now - Duration::days(50)))
+        .await
+        .unwrap();
+    assert!(storage.prune_entries(now).await.is_err());
+    assert_eq!(ids(&storage).await.len(), 1);
+    std::fs::remove_dir_all(dir).unwrap();
+}
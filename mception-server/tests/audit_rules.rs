@@ -0,0 +1,168 @@
+use mception_server::core::{ActorAuditRule, AuditAction, AuditPolicy, AuditTarget};
+use mception_server::services::ConfigService;
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::{RouterOptions, build_router};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+const BOT: &str = "monitor-bot";
+
+fn service() -> Arc<ConfigService> {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ))
+}
+
+/// Skip the bot's reads and write a tenth of its mutations
+fn policy() -> AuditPolicy {
+    AuditPolicy {
+        actor_rules: [(
+            BOT.to_string(),
+            ActorAuditRule {
+                log_reads: false,
+                sample_mutations: 0.1,
+            },
+        )]
+        .into(),
+        ..AuditPolicy::default()
+    }
+}
+
+async fn entries_of(service: &ConfigService, actor: &str) -> Vec<(AuditAction, AuditTarget)> {
+    service
+        .get_audit_logs()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|entry| entry.actor.as_deref() == Some(actor))
+        .map(|entry| (entry.action, entry.target))
+        .collect()
+}
+
+#[tokio::test]
+async fn actor_rules_skip_reads_and_sample_mutations() {
+    let service = service();
+    service
+        .set_audit_policy(policy(), Some("admin".to_string()), None)
+        .await
+        .unwrap();
+
+    for i in 0..12 {
+        let id = format!("agent-{}", i);
+        service
+            .create_agent(Some(id.clone()), None, vec![], Some(BOT.to_string()))
+            .await
+            .unwrap();
+        service.get_agent(&id, Some(BOT.to_string())).await.unwrap();
+    }
+    let logged = entries_of(&service, BOT).await;
+    let ids: Vec<_> = logged
+        .iter()
+        .map(|(action, target)| match (action, target) {
+            (AuditAction::Create, AuditTarget::Agent { id }) => id.as_str(),
+            other => panic!("unexpected entry {:?}", other),
+        })
+        .collect();
+    assert_eq!(ids, ["agent-0", "agent-10"]);
+    assert_eq!(service.skipped_audit_entries(), 22);
+
+    // Other actors are unaffected
+    service
+        .get_agent("agent-1", Some("alice".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(entries_of(&service, "alice").await.len(), 1);
+}
+
+#[tokio::test]
+async fn deletions_and_policy_changes_are_always_logged() {
+    let service = service();
+    let mut policy = policy();
+    policy.actor_rules.get_mut(BOT).unwrap().sample_mutations = 0.0;
+    service
+        .set_audit_policy(policy.clone(), Some(BOT.to_string()), None)
+        .await
+        .unwrap();
+
+    service
+        .create_agent(
+            Some("doomed".to_string()),
+            None,
+            vec![],
+            Some(BOT.to_string()),
+        )
+        .await
+        .unwrap();
+    service
+        .delete_agent("doomed", Some(BOT.to_string()), None)
+        .await
+        .unwrap();
+    service
+        .set_audit_policy(AuditPolicy::default(), Some(BOT.to_string()), None)
+        .await
+        .unwrap();
+
+    let logged = entries_of(&service, BOT).await;
+    assert_eq!(logged.len(), 3);
+    assert!(matches!(
+        logged[0],
+        (AuditAction::Update, AuditTarget::AuditPolicy)
+    ));
+    assert!(matches!(
+        logged[1],
+        (AuditAction::Delete, AuditTarget::Agent { .. })
+    ));
+    assert!(matches!(
+        logged[2],
+        (AuditAction::Update, AuditTarget::AuditPolicy)
+    ));
+    let entries = service.get_audit_logs().await.unwrap();
+    assert_eq!(
+        entries.last().unwrap().details["previous"],
+        serde_json::to_value(&policy).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn audit_policy_endpoints() {
+    let service = service();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!(
+        "http://{}/admin/policy/audit",
+        listener.local_addr().unwrap()
+    );
+    let router = build_router(service.clone(), RouterOptions::default());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    let client = reqwest::Client::new();
+
+    let response = client
+        .put(&url)
+        .json(&json!({
+            "policy": { "actor_rules": { BOT: { "log_reads": false, "sample_mutations": 0.1 } } },
+            "reason": "quieter monitoring"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let body: Value = reqwest::get(&url).await.unwrap().json().await.unwrap();
+    assert_eq!(body["policy"]["actor_rules"][BOT]["sample_mutations"], 0.1);
+    assert_eq!(body["skipped_entries"], 0);
+    assert_eq!(service.audit_policy().await, policy());
+
+    let response = client
+        .put(&url)
+        .json(&json!({ "policy": { "actor_rules": { BOT: { "sample_mutations": 2.0 } } } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(service.audit_policy().await, policy());
+}
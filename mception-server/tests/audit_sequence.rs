@@ -0,0 +1,138 @@
+use chrono::{Duration, Utc};
+use mception_server::core::{AuditAction, AuditLogEntry, AuditTarget};
+use mception_server::storage::providers::{AuditStorage, FileAuditStorage};
+use serde_json::json;
+use std::path::PathBuf;
+
+fn audit_path() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("audit.log")
+}
+
+fn entry(id: &str, timestamp: chrono::DateTime<Utc>) -> AuditLogEntry {
+    AuditLogEntry {
+        id: id.to_string(),
+        sequence: 0,
+        timestamp,
+        action: AuditAction::Update,
+        actor: Some("admin".to_string()),
+        target: AuditTarget::Server,
+        reason: None,
+        details: json!({}),
+        correlation_id: None,
+        idempotency_key: None,
+        revision: None,
+        server_revision: None,
+        prev_hash: None,
+        hash: None,
+    }
+}
+
+#[tokio::test]
+async fn sequences_continue_across_instances() {
+    let path = audit_path();
+    let storage = FileAuditStorage::new(path.to_string_lossy());
+    let now = Utc::now();
+    assert_eq!(
+        storage
+            .append_entry(&entry("a", now))
+            .await
+            .unwrap()
+            .sequence,
+        1
+    );
+    assert_eq!(
+        storage
+            .append_entry(&entry("b", now))
+            .await
+            .unwrap()
+            .sequence,
+        2
+    );
+
+    // Another writer of the same file continues where the first one stopped
+    let other = FileAuditStorage::new(path.to_string_lossy());
+    assert_eq!(
+        other.append_entry(&entry("c", now)).await.unwrap().sequence,
+        3
+    );
+    assert_eq!(
+        storage
+            .append_entry(&entry("d", now))
+            .await
+            .unwrap()
+            .sequence,
+        4
+    );
+
+    let ids: Vec<_> = storage
+        .load_entries()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| (entry.sequence, entry.id))
+        .collect();
+    assert_eq!(
+        ids,
+        vec![
+            (1, "a".to_string()),
+            (2, "b".to_string()),
+            (3, "c".to_string()),
+            (4, "d".to_string())
+        ]
+    );
+}
+
+#[tokio::test]
+async fn legacy_entries_are_numbered_in_file_order() {
+    let path = audit_path();
+    let now = Utc::now();
+    let legacy: String = ["old-1", "old-2"]
+        .iter()
+        .map(|id| {
+            let mut value = serde_json::to_value(entry(id, now)).unwrap();
+            value.as_object_mut().unwrap().remove("sequence");
+            value.to_string() + "\n"
+        })
+        .collect();
+    std::fs::write(&path, legacy).unwrap();
+
+    let storage = FileAuditStorage::new(path.to_string_lossy());
+    assert_eq!(
+        storage
+            .append_entry(&entry("new", now))
+            .await
+            .unwrap()
+            .sequence,
+        3
+    );
+    let sequences: Vec<_> = storage
+        .load_entries()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.sequence)
+        .collect();
+    assert_eq!(sequences, vec![1, 2, 3]);
+}
+
+#[tokio::test]
+async fn backwards_clock_is_reported_and_order_kept() {
+    let storage = FileAuditStorage::new(audit_path().to_string_lossy());
+    let now = Utc::now();
+    let first = storage.append_entry(&entry("first", now)).await.unwrap();
+    assert_eq!(first.behind_predecessor, None);
+
+    // The clock was set back by five minutes between the two writes
+    let second = storage
+        .append_entry(&entry("second", now - Duration::minutes(5)))
+        .await
+        .unwrap();
+    assert_eq!(second.behind_predecessor, Some(Duration::minutes(5)));
+
+    let entries = storage.load_entries().await.unwrap();
+    assert_eq!(entries[0].id, "first");
+    assert_eq!(entries[1].id, "second");
+    assert!(entries[1].timestamp < entries[0].timestamp);
+}
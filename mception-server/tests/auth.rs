@@ -0,0 +1,195 @@
+mod common;
+
+use common::leaf_mcp;
+use mception_server::core::McpTransport;
+use mception_server::services::ConfigService;
+use mception_server::services::auth::AdminToken;
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::{RouterOptions, build_router};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+const ADMIN_TOKEN: &str = "admin-secret";
+const AGENT_TOKEN: &str = "agent-secret";
+
+/// A server whose admin API requires `alice=admin-secret`, with the leaf MCP
+/// `down`, whose upstream doesn't listen, and the agent `writer` allowed to
+/// use it
+async fn serve() -> String {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let service = Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ));
+
+    let port = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+    let leaf = leaf_mcp(McpTransport::Https {
+        url: format!("http://127.0.0.1:{}/mcp", port),
+        headers: None,
+    });
+    service
+        .create_leaf_mcp(Some("down".to_string()), leaf, None, None)
+        .await
+        .unwrap();
+    service
+        .create_agent(
+            Some("writer".to_string()),
+            None,
+            vec!["down".to_string()],
+            None,
+        )
+        .await
+        .unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let options = RouterOptions {
+        admin_tokens: vec![AdminToken::parse(&format!("alice={}", ADMIN_TOKEN)).unwrap()],
+        ..RouterOptions::default()
+    };
+    let router = build_router(service, options);
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    url
+}
+
+/// Give `writer` its token through the admin API
+async fn set_agent_token(url: &str) {
+    let response = reqwest::Client::new()
+        .put(format!("{}/admin/agent/writer/config", url))
+        .bearer_auth(ADMIN_TOKEN)
+        .json(&json!({
+            "config": { "auth_token": AGENT_TOKEN },
+            "should_update": true
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+async fn get(url: &str, path: &str, token: Option<&str>) -> (u16, String) {
+    let mut request = reqwest::Client::new().get(format!("{}{}", url, path));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await.unwrap();
+    (response.status().as_u16(), response.text().await.unwrap())
+}
+
+#[tokio::test]
+async fn admin_api_requires_a_token() {
+    let url = serve().await;
+
+    let response = reqwest::get(format!("{}/admin/config", url)).await.unwrap();
+    assert_eq!(response.status(), 401);
+    assert_eq!(response.headers()["www-authenticate"], "Bearer");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["kind"], "unauthorized");
+
+    let (status, _) = get(&url, "/admin/config", Some("wrong")).await;
+    assert_eq!(status, 401);
+    let (status, _) = get(&url, "/metrics", None).await;
+    assert_eq!(status, 401);
+
+    let (status, _) = get(&url, "/admin/config", Some(ADMIN_TOKEN)).await;
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn audit_log_records_the_token_actor() {
+    let url = serve().await;
+    set_agent_token(&url).await;
+
+    let (status, body) = get(&url, "/admin/audit", Some(ADMIN_TOKEN)).await;
+    assert_eq!(status, 200);
+    let body: Value = serde_json::from_str(&body).unwrap();
+    let update = body["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|entry| entry["action"]["type"] == "update")
+        .unwrap();
+    assert_eq!(update["actor"], "alice");
+}
+
+#[tokio::test]
+async fn agent_tokens_are_never_shown() {
+    let url = serve().await;
+    set_agent_token(&url).await;
+
+    let (_, agent) = get(&url, "/admin/agent/writer/config", Some(ADMIN_TOKEN)).await;
+    assert!(!agent.contains(AGENT_TOKEN));
+    let agent: Value = serde_json::from_str(&agent).unwrap();
+    assert_eq!(agent["has_auth_token"], true);
+
+    for path in ["/admin/config", "/admin/audit"] {
+        let (status, body) = get(&url, path, Some(ADMIN_TOKEN)).await;
+        assert_eq!(status, 200);
+        assert!(!body.contains(AGENT_TOKEN), "{} shows the token", path);
+    }
+}
+
+#[tokio::test]
+async fn agent_routes_require_the_agent_token() {
+    let url = serve().await;
+
+    // Open until the agent has a token
+    let (status, _) = get(&url, "/agent/writer/config", None).await;
+    assert_eq!(status, 200);
+
+    set_agent_token(&url).await;
+    let (status, _) = get(&url, "/agent/writer/config", None).await;
+    assert_eq!(status, 401);
+    let (status, _) = get(&url, "/agent/writer/config", Some(ADMIN_TOKEN)).await;
+    assert_eq!(status, 401);
+    let (status, _) = get(&url, "/agent/writer/config", Some(AGENT_TOKEN)).await;
+    assert_eq!(status, 200);
+    let ws_url = format!("{}/agent/writer/forwarding_ws", url.replace("http", "ws"));
+    match tokio_tungstenite::connect_async(ws_url).await {
+        Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+            assert_eq!(response.status(), 401)
+        }
+        other => panic!("expected 401, got {:?}", other.map(|_| ())),
+    }
+
+    let forward = |token: Option<&str>| {
+        let mut request = reqwest::Client::new()
+            .post(format!("{}/leaf/down/forwarding", url))
+            .body(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        async move {
+            let response = request.send().await.unwrap();
+            let status = response.status().as_u16();
+            (status, response.json::<Value>().await.unwrap())
+        }
+    };
+    let (status, body) = forward(None).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["code"], "not_allowed");
+    let (status, _) = forward(Some("wrong")).await;
+    assert_eq!(status, 401);
+    // Authenticated as `writer`, which may use the leaf MCP that is down
+    let (status, body) = forward(Some(AGENT_TOKEN)).await;
+    assert_eq!(status, 502);
+    assert_eq!(body["error"]["code"], "leaf_unreachable");
+}
+
+#[test]
+fn admin_tokens_parse_with_an_optional_actor() {
+    let token = AdminToken::parse("ci=abc").unwrap();
+    assert_eq!(token.actor, "ci");
+    assert!(!format!("{:?}", token).contains("abc"));
+    assert_eq!(AdminToken::parse("abc").unwrap().actor, "admin");
+    assert!(AdminToken::parse("ci=").is_err());
+    assert!(AdminToken::parse("=abc").is_err());
+}
@@ -0,0 +1,188 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{TestServer, answer, temp_dir};
+use mception_server::services::availability::{self, AvailabilityTracker};
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+use std::path::Path;
+use std::process::Command;
+
+/// Write availability data as the server persists it: spans of unix
+/// seconds during which the server ran and each agent was connected
+fn write_availability(path: &Path, sessions: Value, agents: Value) {
+    std::fs::write(
+        path,
+        json!({ "sessions": sessions, "agents": agents }).to_string(),
+    )
+    .unwrap();
+}
+
+/// Two earlier server runs an hour ago, `alpha` connected for half of the time
+/// they ran and `beta` never
+fn earlier_runs(path: &Path, now: i64) {
+    write_availability(
+        path,
+        json!([[now - 3600, now - 1800], [now - 1200, now - 600]]),
+        json!({
+            "alpha": [[now - 3600, now - 3000], [now - 1200, now - 600]],
+            "beta": []
+        }),
+    );
+}
+
+#[tokio::test]
+async fn time_the_server_was_down_is_unknown_not_an_outage() {
+    let dir = temp_dir();
+    let path = dir.join("availability.json");
+    let now = Utc::now().timestamp();
+    earlier_runs(&path, now);
+    let tracker = AvailabilityTracker::open(&path, availability::DEFAULT_RETENTION).unwrap();
+
+    let alpha = tracker.agent("alpha", Duration::hours(2)).await;
+    assert_eq!(alpha.connected_seconds, 1200);
+    assert_eq!(alpha.disconnected_seconds, 1200);
+    assert_eq!(alpha.uptime_percent, Some(50.0));
+    assert_eq!(alpha.unknown_seconds, 7200 - 2400);
+    let outage = alpha.longest_outage.unwrap();
+    assert_eq!(
+        (outage.start.timestamp(), outage.seconds),
+        (now - 3000, 1200)
+    );
+    let unknown: Vec<(i64, i64)> = alpha
+        .unknown
+        .iter()
+        .map(|interval| (interval.start.timestamp(), interval.end.timestamp()))
+        .collect();
+    assert_eq!(unknown.len(), 3, "{:?}", unknown);
+    assert_eq!(unknown[1], (now - 1800, now - 1200));
+
+    let fleet = tracker
+        .fleet(
+            &["alpha".to_string(), "beta".to_string()],
+            Duration::hours(2),
+        )
+        .await;
+    assert_eq!(fleet.agents[1].uptime_percent, Some(0.0));
+    assert_eq!(fleet.agents[1].disconnected_seconds, 2400);
+    assert_eq!(fleet.mean_uptime_percent, Some(25.0));
+
+    // No earlier run falls into the window
+    let recent = tracker.agent("alpha", Duration::minutes(5)).await;
+    assert_eq!(recent.uptime_percent, None);
+    assert_eq!(recent.connected_seconds, 0);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn checkpoints_persist_and_prune_the_data() {
+    let dir = temp_dir();
+    let path = dir.join("availability.json");
+    let now = Utc::now().timestamp();
+    write_availability(
+        &path,
+        json!([[now - 7200, now - 5400], [now - 1200, now - 600]]),
+        json!({
+            "alpha": [[now - 1200, now - 600]],
+            "gone": [[now - 7200, now - 5400]]
+        }),
+    );
+
+    let tracker = AvailabilityTracker::open(&path, Duration::hours(1)).unwrap();
+    tracker.touch("beta").await;
+    assert_eq!(tracker.connected_agents().await, 1);
+    tracker.checkpoint().await.unwrap();
+
+    let saved: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(saved["sessions"].as_array().unwrap().len(), 2, "{}", saved);
+    let agents: Vec<&String> = saved["agents"].as_object().unwrap().keys().collect();
+    assert_eq!(agents, ["alpha", "beta"]);
+
+    // What the CLI reports while the server isn't running
+    let fleet = availability::read_fleet(&path, Duration::hours(1)).unwrap();
+    let reported: Vec<(&str, Option<f64>)> = fleet
+        .agents
+        .iter()
+        .map(|agent| (agent.agent_id.as_str(), agent.uptime_percent))
+        .collect();
+    assert_eq!(reported[0], ("alpha", Some(100.0)));
+    assert_eq!(reported[1].0, "beta");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mception-server"))
+        .arg("--config")
+        .arg(dir.join("config.json"))
+        .arg("--availability-file")
+        .arg(&path)
+        .args(["availability", "--since", "1h", "--format", "json"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let printed: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(printed["agents"][0]["agent_id"], "alpha");
+    assert_eq!(printed["agents"][0]["connected_seconds"], 600);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn agents_contacting_the_server_are_reported_connected() {
+    let dir = temp_dir();
+    let tracker =
+        AvailabilityTracker::open(dir.join("availability.json"), Duration::days(1)).unwrap();
+    let server = TestServer::builder().availability(tracker).start().await;
+    for agent_id in ["alpha", "beta"] {
+        let (status, body) = server
+            .admin_json(
+                Method::POST,
+                "/agent",
+                &json!({ "agent_id": agent_id, "allowed_mcp_ids": [] }),
+            )
+            .await;
+        assert!(status.is_success(), "{}", body);
+    }
+    // Spans have a resolution of seconds, so two contacts a second apart
+    for _ in 0..2 {
+        let (status, _) = answer(server.request(Method::GET, "/agent/alpha/config")).await;
+        assert_eq!(status, StatusCode::OK);
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    }
+
+    let (status, body) = server.admin_get("/agent/alpha/availability?since=1h").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["agent_id"], "alpha");
+    assert_eq!(body["connected"].as_array().unwrap().len(), 1, "{}", body);
+    assert!(body["connected_seconds"].as_i64().unwrap() >= 1, "{}", body);
+    assert!(body["uptime_percent"].as_f64().unwrap() > 0.0);
+
+    let (status, body) = server.admin_get("/availability?since=1h").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let agents: Vec<&Value> = body["agents"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|agent| &agent["agent_id"])
+        .collect();
+    assert_eq!(agents, [&json!("alpha"), &json!("beta")]);
+    assert!(
+        body["agents"][1]["connected"]
+            .as_array()
+            .unwrap()
+            .is_empty()
+    );
+
+    let (status, _) = server.admin_get("/agent/missing/availability").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = server.admin_get("/availability?since=soon").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn without_tracking_availability_is_not_reported() {
+    let server = TestServer::start().await;
+    let (status, _) = server.admin_get("/availability").await;
+    assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+}
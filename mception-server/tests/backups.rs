@@ -0,0 +1,326 @@
+mod common;
+
+use common::temp_dir;
+use mception_server::core::testing::load_fixture;
+use mception_server::core::{BackupInfo, BackupKind, MceptionError, StorageError};
+use mception_server::storage::providers::{BackupOptions, ConfigStorage, FileConfigStorage};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Fresh directory holding a copy of the fixture configuration
+fn fixture_dir() -> PathBuf {
+    let dir = temp_dir();
+    std::fs::write(dir.join("config.json"), load_fixture("config.json")).unwrap();
+    dir
+}
+
+fn storage(dir: &Path, backup_options: BackupOptions) -> FileConfigStorage {
+    FileConfigStorage::new(dir.join("config.json").to_string_lossy())
+        .with_backup_options(backup_options)
+}
+
+/// Change the search leaf MCP's description and return the saved configuration
+async fn change(storage: &FileConfigStorage, description: &str) -> Value {
+    let mut config = storage.load_config().await.unwrap();
+    config.leaf_mcps.get_mut("search").unwrap().description = Some(description.to_string());
+    storage.save_config(&config).await.unwrap();
+    serde_json::to_value(storage.load_config().await.unwrap()).unwrap()
+}
+
+/// Back up the configuration, returning the backup's name. Names carry the
+/// time in milliseconds, so backups are a little apart.
+async fn backup(storage: &FileConfigStorage) -> String {
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    let path = storage.backup_config().await.unwrap();
+    Path::new(&path)
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// A full backup followed by three differential ones, with the configuration
+/// each of them holds
+async fn chain(storage: &FileConfigStorage) -> Vec<(String, Value)> {
+    let mut backups = Vec::new();
+    let first = serde_json::to_value(storage.load_config().await.unwrap()).unwrap();
+    backups.push((backup(storage).await, first));
+    for description in ["first change", "second change", "third change"] {
+        let config = change(storage, description).await;
+        backups.push((backup(storage).await, config));
+    }
+    backups
+}
+
+fn differential() -> BackupOptions {
+    BackupOptions {
+        differential: true,
+        ..BackupOptions::default()
+    }
+}
+
+#[tokio::test]
+async fn a_full_backup_and_three_diffs_restore_each_state() {
+    let dir = fixture_dir();
+    let storage = storage(&dir, differential());
+    let backups = chain(&storage).await;
+    change(&storage, "after the backups").await;
+
+    let listed = storage.list_backups().await.unwrap();
+    let kinds: Vec<BackupKind> = listed.iter().map(|backup| backup.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            BackupKind::Full,
+            BackupKind::Differential,
+            BackupKind::Differential,
+            BackupKind::Differential
+        ]
+    );
+    let full = &backups[0].0;
+    assert_eq!(listed[0].base, None);
+    for backup in &listed[1..] {
+        assert_eq!(backup.base.as_ref(), Some(full));
+        // Only the change is stored
+        assert!(backup.size_bytes < listed[0].size_bytes, "{:?}", backup);
+    }
+
+    for (name, config) in &backups {
+        let loaded = storage.load_backup(name).await.unwrap();
+        assert_eq!(&serde_json::to_value(loaded).unwrap(), config, "{}", name);
+    }
+
+    // Restoring a diff makes its state the current configuration
+    let (name, config) = &backups[2];
+    storage.restore_backup(name).await.unwrap();
+    let current = storage.load_config().await.unwrap();
+    assert_eq!(&serde_json::to_value(&current).unwrap(), config);
+    assert_eq!(
+        current.leaf_mcps["search"].description.as_deref(),
+        Some("second change")
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn compressed_backups_are_gzip_files_that_restore() {
+    let dir = fixture_dir();
+    let storage = storage(
+        &dir,
+        BackupOptions {
+            compress: true,
+            ..differential()
+        },
+    );
+    let backups = chain(&storage).await;
+
+    let listed = storage.list_backups().await.unwrap();
+    assert_eq!(listed.len(), 4);
+    for backup in &listed {
+        assert!(backup.compressed, "{:?}", backup);
+        assert!(backup.name.ends_with(".gz"), "{}", backup.name);
+        let bytes = std::fs::read(dir.join(&backup.name)).unwrap();
+        assert_eq!(bytes[..2], [0x1f, 0x8b], "{} is not gzip", backup.name);
+    }
+    assert!(listed[1].name.ends_with(".diff.gz"));
+
+    let (name, config) = &backups[3];
+    storage.restore_backup(name).await.unwrap();
+    let current = storage.load_config().await.unwrap();
+    assert_eq!(&serde_json::to_value(current).unwrap(), config);
+
+    std::fs::remove_dir_all(dir).unwrap();
+
+    // A full backup alone is compressed too
+    let dir = fixture_dir();
+    let storage = self::storage(
+        &dir,
+        BackupOptions {
+            compress: true,
+            ..BackupOptions::default()
+        },
+    );
+    let name = backup(&storage).await;
+    let listed = storage.list_backups().await.unwrap();
+    assert_eq!(
+        (listed[0].kind, listed[0].compressed),
+        (BackupKind::Full, true)
+    );
+    let original = std::fs::metadata(dir.join("config.json")).unwrap().len();
+    assert!(listed[0].size_bytes < original);
+    let loaded = storage.load_backup(&name).await.unwrap();
+    assert_eq!(
+        serde_json::to_value(loaded).unwrap(),
+        serde_json::to_value(storage.load_config().await.unwrap()).unwrap()
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn a_new_full_backup_follows_full_every_diffs() {
+    let dir = fixture_dir();
+    let storage = storage(
+        &dir,
+        BackupOptions {
+            full_every: 2,
+            ..differential()
+        },
+    );
+    let mut names = Vec::new();
+    for description in ["a", "b", "c", "d"] {
+        change(&storage, description).await;
+        names.push(backup(&storage).await);
+    }
+
+    let listed = storage.list_backups().await.unwrap();
+    let kinds: Vec<BackupKind> = listed.iter().map(|backup| backup.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            BackupKind::Full,
+            BackupKind::Differential,
+            BackupKind::Differential,
+            BackupKind::Full
+        ]
+    );
+    let loaded = storage.load_backup(&names[3]).await.unwrap();
+    assert_eq!(loaded.leaf_mcps["search"].description.as_deref(), Some("d"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn pruning_keeps_full_backups_later_diffs_need() {
+    let dir = fixture_dir();
+    let storage = storage(&dir, differential());
+    let backups = chain(&storage).await;
+    let names: Vec<&str> = backups.iter().map(|(name, _)| name.as_str()).collect();
+
+    // The two newest diffs are kept, and with them the full backup they are based on
+    let deleted = storage.prune_backups(2).await.unwrap();
+    assert_eq!(deleted, [names[1]]);
+    let remaining: Vec<String> = storage
+        .list_backups()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|backup| backup.name)
+        .collect();
+    assert_eq!(remaining, [names[0], names[2], names[3]]);
+    let loaded = storage.load_backup(names[3]).await.unwrap();
+    assert_eq!(
+        serde_json::to_value(loaded).unwrap(),
+        backups[3].1,
+        "a kept diff no longer restores"
+    );
+
+    // Once no kept diff needs it, the full backup goes too
+    let storage = self::storage(
+        &dir,
+        BackupOptions {
+            full_every: 0,
+            ..differential()
+        },
+    );
+    let newest = backup(&storage).await;
+    let deleted = storage.prune_backups(1).await.unwrap();
+    assert_eq!(deleted, [names[0], names[2], names[3]]);
+    let listed = storage.list_backups().await.unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(
+        (listed[0].name.as_str(), listed[0].kind),
+        (newest.as_str(), BackupKind::Full)
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn pruning_after_each_backup_keeps_the_chain_restorable() {
+    let dir = fixture_dir();
+    let storage = storage(
+        &dir,
+        BackupOptions {
+            keep: Some(1),
+            ..differential()
+        },
+    );
+    let backups = chain(&storage).await;
+
+    let listed = storage.list_backups().await.unwrap();
+    let names: Vec<&str> = listed.iter().map(|backup| backup.name.as_str()).collect();
+    assert_eq!(names, [backups[0].0.as_str(), backups[3].0.as_str()]);
+    let loaded = storage.load_backup(&backups[3].0).await.unwrap();
+    assert_eq!(serde_json::to_value(loaded).unwrap(), backups[3].1);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn a_diff_without_its_full_backup_is_not_restored() {
+    let dir = fixture_dir();
+    let storage = storage(&dir, differential());
+    let backups = chain(&storage).await;
+    std::fs::remove_file(dir.join(&backups[0].0)).unwrap();
+    let before = std::fs::read_to_string(dir.join("config.json")).unwrap();
+
+    let error = storage.restore_backup(&backups[1].0).await.unwrap_err();
+    assert!(
+        matches!(error, MceptionError::Storage(StorageError::NotFound(_))),
+        "{:?}",
+        error
+    );
+    // The configuration is left as it was
+    assert_eq!(
+        std::fs::read_to_string(dir.join("config.json")).unwrap(),
+        before
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn diffs_are_listed_without_reading_them() {
+    let dir = fixture_dir();
+    let storage = storage(&dir, differential());
+    let backups = chain(&storage).await;
+    // The name says what the diff is based on, its content isn't needed
+    std::fs::write(dir.join(&backups[2].0), "not a backup").unwrap();
+    // Copying a backup changes its modification time, not its name
+    std::fs::File::options()
+        .write(true)
+        .open(dir.join(&backups[0].0))
+        .unwrap()
+        .set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(3600))
+        .unwrap();
+
+    let listed = storage.list_backups().await.unwrap();
+    assert_eq!(listed.len(), 4);
+    assert_eq!(listed[2].base.as_deref(), Some(backups[0].0.as_str()));
+    assert!(!listed[2].corrupt);
+    for backup in &listed {
+        assert_eq!(
+            Some(backup.created_at),
+            BackupInfo::taken_at(&backup.name),
+            "{}",
+            backup.name
+        );
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn unreadable_diffs_are_listed_as_corrupt() {
+    let dir = fixture_dir();
+    let storage = storage(&dir, differential());
+    let backups = chain(&storage).await;
+    std::fs::remove_file(dir.join(&backups[0].0)).unwrap();
+    // Diffs written before their names carried the base name it inside
+    let legacy = "config.json.backup.20200101_000000_000.diff";
+    std::fs::write(dir.join(legacy), "not a backup").unwrap();
+
+    let listed = storage.list_backups().await.unwrap();
+    assert_eq!(listed.len(), 4);
+    assert_eq!(listed[0].name, legacy);
+    for backup in &listed {
+        assert!(backup.corrupt, "{}", backup.name);
+        assert_eq!(backup.base, None);
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,264 @@
+mod common;
+
+use common::leaf_mcp;
+use mception_server::core::{AuditAction, AuditTarget, LeafMcpConfig, McpTransport};
+use mception_server::services::ConfigService;
+use mception_server::services::bulk::{BulkDeleteOutcome, BulkKind, BulkSelection};
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use serde_json::json;
+use std::sync::Arc;
+
+async fn service() -> Arc<ConfigService> {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let service = Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ));
+    for (id, tags) in [
+        ("alpha", json!(["project-x"])),
+        ("beta", json!(["project-x", "search"])),
+        ("gamma", json!([])),
+    ] {
+        let leaf = LeafMcpConfig {
+            reachable_by_agent: true,
+            config: json!({ "tags": tags }),
+            ..leaf_mcp(McpTransport::Https {
+                url: format!("https://{}.example.com", id),
+                headers: None,
+            })
+        };
+        service
+            .create_leaf_mcp(Some(id.to_string()), leaf, None, None)
+            .await
+            .unwrap();
+    }
+    service
+        .create_bundle(
+            "tools".to_string(),
+            None,
+            vec!["beta".to_string(), "gamma".to_string()],
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    service
+        .create_agent(
+            Some("reviewer".to_string()),
+            None,
+            vec!["alpha".to_string(), "bundle:tools".to_string()],
+            None,
+        )
+        .await
+        .unwrap();
+    service
+        .create_agent(
+            Some("writer".to_string()),
+            None,
+            vec!["gamma".to_string()],
+            None,
+        )
+        .await
+        .unwrap();
+    service
+}
+
+#[tokio::test]
+async fn dry_run_lists_deletions_and_affected_agents() {
+    let service = service().await;
+    let tag = BulkSelection::Tag("project-x".to_string());
+
+    let plan = service
+        .plan_bulk_delete(BulkKind::LeafMcp, &tag)
+        .await
+        .unwrap();
+    assert_eq!(plan.ids, vec!["alpha", "beta"]);
+    assert_eq!(plan.affected_agents.len(), 1);
+    assert_eq!(plan.affected_agents["reviewer"], vec!["alpha", "beta"]);
+    assert_eq!(plan.affected_bundles, vec!["tools"]);
+
+    // A dry run changes nothing, each gives its own token
+    let again = service
+        .plan_bulk_delete(BulkKind::LeafMcp, &tag)
+        .await
+        .unwrap();
+    assert_eq!(again.ids, plan.ids);
+    assert_ne!(again.confirmation_token, plan.confirmation_token);
+    assert_eq!(service.list_leaf_mcps().await.unwrap().len(), 3);
+
+    let unknown = BulkSelection::Ids(vec!["alpha".to_string(), "missing".to_string()]);
+    assert!(
+        service
+            .plan_bulk_delete(BulkKind::LeafMcp, &unknown)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn confirmed_delete_is_atomic_and_correlated() {
+    let service = service().await;
+    let tag = BulkSelection::Tag("project-x".to_string());
+    let plan = service
+        .plan_bulk_delete(BulkKind::LeafMcp, &tag)
+        .await
+        .unwrap();
+    let revision = service.revision().await;
+
+    let outcome = service
+        .bulk_delete(
+            BulkKind::LeafMcp,
+            &tag,
+            &plan.confirmation_token,
+            Some("admin".to_string()),
+            Some("project-x decommissioned".to_string()),
+        )
+        .await
+        .unwrap();
+    let BulkDeleteOutcome::Deleted { correlation_id, .. } = outcome else {
+        panic!("expected the deletion to be confirmed");
+    };
+    // The dry run's correlation id, which its token carries
+    assert!(plan.confirmation_token.starts_with(&correlation_id));
+
+    let config = service.get_configuration().await;
+    assert_eq!(config.metadata.revision, revision + 1);
+    assert_eq!(config.leaf_mcps.keys().collect::<Vec<_>>(), vec!["gamma"]);
+    assert_eq!(config.bundles["tools"].members, vec!["gamma"]);
+    assert_eq!(
+        config.agents["reviewer"].allowed_mcp_ids,
+        vec!["bundle:tools"]
+    );
+
+    let deletes: Vec<_> = service
+        .get_audit_logs()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|entry| matches!(entry.action, AuditAction::Delete))
+        .collect();
+    assert_eq!(deletes.len(), 2);
+    for entry in &deletes {
+        assert_eq!(
+            entry.correlation_id.as_deref(),
+            Some(correlation_id.as_str())
+        );
+        assert!(matches!(entry.target, AuditTarget::LeafMcp { .. }));
+    }
+}
+
+#[tokio::test]
+async fn stale_token_returns_the_current_plan() {
+    let service = service().await;
+    let ids = BulkSelection::Ids(vec!["reviewer".to_string(), "writer".to_string()]);
+    let plan = service
+        .plan_bulk_delete(BulkKind::Agent, &ids)
+        .await
+        .unwrap();
+
+    // Any change in between invalidates the token
+    service
+        .create_agent(Some("auditor".to_string()), None, Vec::new(), None)
+        .await
+        .unwrap();
+    let outcome = service
+        .bulk_delete(BulkKind::Agent, &ids, &plan.confirmation_token, None, None)
+        .await
+        .unwrap();
+    let BulkDeleteOutcome::PlanChanged {
+        plan: current,
+        reason,
+    } = outcome
+    else {
+        panic!("expected the stale token to be refused");
+    };
+    assert_eq!(reason, "the configuration changed since the dry run");
+    assert_eq!(current.ids, plan.ids);
+    assert_ne!(current.confirmation_token, plan.confirmation_token);
+    assert_eq!(service.get_configuration().await.agents.len(), 3);
+
+    let outcome = service
+        .bulk_delete(
+            BulkKind::Agent,
+            &ids,
+            &current.confirmation_token,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    assert!(matches!(outcome, BulkDeleteOutcome::Deleted { .. }));
+    let agents = service.get_configuration().await.agents;
+    assert_eq!(agents.keys().collect::<Vec<_>>(), vec!["auditor"]);
+}
+
+#[tokio::test]
+async fn only_signed_unexpired_tokens_of_the_plan_confirm_it() {
+    let service = service().await;
+    let writer = BulkSelection::Ids(vec!["writer".to_string()]);
+    let reviewer = BulkSelection::Ids(vec!["reviewer".to_string()]);
+    let plan = service
+        .plan_bulk_delete(BulkKind::Agent, &writer)
+        .await
+        .unwrap();
+    let refused = |outcome: BulkDeleteOutcome| match outcome {
+        BulkDeleteOutcome::PlanChanged { reason, .. } => reason,
+        BulkDeleteOutcome::Deleted { .. } => panic!("expected the token to be refused"),
+    };
+
+    let token = &plan.confirmation_token;
+    let last = if token.ends_with('0') { "1" } else { "0" };
+    let forged = format!("{}{}", &token[..token.len() - 1], last);
+    for (selection, token, reason) in [
+        (
+            &reviewer,
+            token.as_str(),
+            "the configuration changed since the dry run",
+        ),
+        (
+            &writer,
+            forged.as_str(),
+            "the configuration changed since the dry run",
+        ),
+        (
+            &writer,
+            "0123456789abcdef",
+            "the confirmation token is malformed",
+        ),
+    ] {
+        let outcome = service
+            .bulk_delete(BulkKind::Agent, selection, token, None, None)
+            .await
+            .unwrap();
+        assert_eq!(refused(outcome), reason);
+    }
+
+    let mut policy = service.confirmation_policy().await;
+    policy.token_ttl_secs = 1;
+    service
+        .set_confirmation_policy(policy, None, None)
+        .await
+        .unwrap();
+    let plan = service
+        .plan_bulk_delete(BulkKind::Agent, &writer)
+        .await
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(2100)).await;
+    let outcome = service
+        .bulk_delete(
+            BulkKind::Agent,
+            &writer,
+            &plan.confirmation_token,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(refused(outcome), "the confirmation token expired");
+    assert_eq!(service.get_configuration().await.agents.len(), 2);
+}
@@ -0,0 +1,193 @@
+mod common;
+
+use axum::Router;
+use axum::routing::post;
+use common::{TestServer, answer};
+use mception_server::core::AuditAction;
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+use std::time::{Duration, Instant};
+
+const COOLDOWN: Duration = Duration::from_millis(300);
+
+/// A server whose leaf MCP `flaky` is reached on a port nothing listens on
+/// yet, with breakers opening after 3 failures. Returns the port.
+async fn serve() -> (TestServer, u16) {
+    let port = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+    let server = TestServer::builder()
+        .circuit_breakers(3, COOLDOWN)
+        .start()
+        .await;
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({
+                "id": "flaky",
+                "config": {
+                    "transport": { "type": "https", "url": format!("http://127.0.0.1:{}/mcp", port) },
+                    "is_local": false,
+                    "reachable_by_agent": false,
+                    "config": {}
+                },
+                "reason": null
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    (server, port)
+}
+
+/// Start answering on `port`
+async fn bring_up(port: u16) {
+    let app = Router::new().route(
+        "/mcp",
+        post(|| async { axum::Json(json!({ "jsonrpc": "2.0", "id": 1, "result": {} })) }),
+    );
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port))
+        .await
+        .unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+}
+
+async fn forward(server: &TestServer) -> (StatusCode, Value) {
+    answer(
+        server
+            .request(Method::POST, "/leaf/flaky/forwarding")
+            .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" })),
+    )
+    .await
+}
+
+async fn breaker(server: &TestServer) -> Value {
+    let (status, body) = server.admin_get("/leaf/flaky/status").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    body["circuit_breaker"].clone()
+}
+
+async fn fail(server: &TestServer, times: usize) {
+    for _ in 0..times {
+        let (status, body) = forward(server).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY, "{}", body);
+    }
+}
+
+/// `(from, to, cause)` of the circuit breaker audit entries, in order
+async fn transitions(server: &TestServer) -> Vec<(String, String, String)> {
+    server
+        .audit_entries()
+        .await
+        .into_iter()
+        .filter(|entry| matches!(entry.action, AuditAction::CircuitBreaker))
+        .map(|entry| {
+            let field = |name: &str| entry.details[name].as_str().unwrap().to_string();
+            (field("from"), field("to"), field("cause"))
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn repeated_failures_open_the_breaker() {
+    let (server, _) = serve().await;
+    assert_eq!(breaker(&server).await["state"], "closed");
+    fail(&server, 2).await;
+    assert_eq!(breaker(&server).await["consecutive_failures"], 2);
+    fail(&server, 1).await;
+
+    let started = Instant::now();
+    let (status, body) = forward(&server).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{}", body);
+    assert_eq!(body["error"]["code"], "circuit_open");
+    assert_eq!(body["error"]["retryable"], true);
+    assert!(body["retry_in_ms"].as_u64().unwrap() <= COOLDOWN.as_millis() as u64);
+    assert!(started.elapsed() < COOLDOWN);
+
+    let state = breaker(&server).await;
+    assert_eq!(state["state"], "open", "{}", state);
+    assert_eq!(state["consecutive_failures"], 3);
+    assert_eq!(state["failure_threshold"], 3);
+    assert!(state["opened_at"].is_string());
+    assert_eq!(
+        transitions(&server).await,
+        [("closed".into(), "open".into(), "failure_threshold".into())]
+    );
+}
+
+#[tokio::test]
+async fn a_successful_probe_closes_the_breaker() {
+    let (server, port) = serve().await;
+    fail(&server, 3).await;
+    bring_up(port).await;
+    // Still refused during the cooldown, although the leaf MCP is back
+    assert_eq!(forward(&server).await.0, StatusCode::SERVICE_UNAVAILABLE);
+
+    tokio::time::sleep(COOLDOWN).await;
+    let (status, body) = forward(&server).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let state = breaker(&server).await;
+    assert_eq!(state["state"], "closed", "{}", state);
+    assert_eq!(state["consecutive_failures"], 0);
+    assert_eq!(
+        transitions(&server).await,
+        [
+            ("closed".into(), "open".into(), "failure_threshold".into()),
+            ("open".into(), "half_open".into(), "cooldown_elapsed".into()),
+            (
+                "half_open".into(),
+                "closed".into(),
+                "probe_succeeded".into()
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn a_failed_probe_opens_the_breaker_again() {
+    let (server, _) = serve().await;
+    fail(&server, 3).await;
+    tokio::time::sleep(COOLDOWN).await;
+
+    fail(&server, 1).await;
+    assert_eq!(breaker(&server).await["state"], "open");
+    assert_eq!(forward(&server).await.0, StatusCode::SERVICE_UNAVAILABLE);
+    let causes: Vec<_> = transitions(&server)
+        .await
+        .into_iter()
+        .map(|(_, to, cause)| (to, cause))
+        .collect();
+    assert_eq!(
+        causes.last().unwrap(),
+        &("open".into(), "probe_failed".into())
+    );
+}
+
+#[tokio::test]
+async fn breakers_are_reset_by_admins() {
+    let (server, _) = serve().await;
+    fail(&server, 3).await;
+
+    let (status, body) = server
+        .admin_json(Method::POST, "/leaf/flaky/status/reset", &json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["circuit_breaker"]["state"], "closed");
+    // Forwarded again right away, failing as before
+    assert_eq!(forward(&server).await.0, StatusCode::BAD_GATEWAY);
+
+    let reset = server
+        .audit_entries()
+        .await
+        .into_iter()
+        .rfind(|entry| matches!(entry.action, AuditAction::CircuitBreaker))
+        .unwrap();
+    assert_eq!(reset.details["cause"], "reset");
+    assert_eq!(reset.actor.as_deref(), Some("admin"));
+
+    let (status, _) = server
+        .admin_json(Method::POST, "/leaf/missing/status/reset", &json!({}))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,556 @@
+mod common;
+
+use common::temp_dir;
+use mception_server::core::{AuditAction, ServerConfig};
+use mception_server::storage::providers::{AuditStorage, FileAuditStorage};
+use serde_json::Value;
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Run `mception-server` against the storage in `dir`
+fn run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_mception-server"))
+        .arg("--config")
+        .arg(dir.join("config.json"))
+        .arg("--audit-log")
+        .arg(dir.join("audit.log"))
+        .arg("--journal")
+        .arg(dir.join("config.journal"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn json(output: &Output) -> Value {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+/// Everything printed, errors are logged to stdout
+fn printed(output: &Output) -> String {
+    format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+}
+
+fn config(dir: &Path) -> ServerConfig {
+    serde_json::from_str(&std::fs::read_to_string(dir.join("config.json")).unwrap()).unwrap()
+}
+
+#[test]
+fn bootstraps_mcps_and_agents() {
+    let dir = temp_dir();
+    let created = json(&run(
+        &dir,
+        &[
+            "add-mcp",
+            "fetch",
+            "--command",
+            "uvx",
+            "--arg",
+            "mcp-server-fetch",
+            "--arg",
+            "--ignore-robots-txt",
+            "--description",
+            "Fetches web pages",
+            "--reason",
+            "bootstrap",
+            "--format",
+            "json",
+        ],
+    ));
+    assert_eq!(created["id"], "fetch");
+    json(&run(
+        &dir,
+        &[
+            "add-mcp",
+            "--name",
+            "Search",
+            "--url",
+            "https://search.example.com/mcp",
+            "-f",
+            "json",
+        ],
+    ));
+    let agent = json(&run(
+        &dir,
+        &[
+            "add-agent",
+            "--name",
+            "Research Bot",
+            "--allow",
+            "fetch",
+            "-f",
+            "json",
+        ],
+    ));
+    let agent_id = agent["agent_id"].as_str().unwrap();
+    json(&run(&dir, &["allow-mcp", agent_id, "search", "-f", "json"]));
+
+    let saved = config(&dir);
+    let fetch = &saved.leaf_mcps["fetch"];
+    assert_eq!(fetch.description.as_deref(), Some("Fetches web pages"));
+    assert_eq!(
+        serde_json::to_value(&fetch.transport).unwrap()["args"],
+        serde_json::json!(["mcp-server-fetch", "--ignore-robots-txt"])
+    );
+    assert_eq!(
+        saved.agents[agent_id].allowed_mcp_ids,
+        ["fetch".to_string(), "search".to_string()]
+    );
+
+    json(&run(
+        &dir,
+        &["disallow-mcp", agent_id, "fetch", "-f", "json"],
+    ));
+    json(&run(
+        &dir,
+        &["remove-mcp", "search", "--reason", "moved", "-f", "json"],
+    ));
+    assert!(config(&dir).agents[agent_id].allowed_mcp_ids.is_empty());
+    let removed = run(&dir, &["remove-agent", agent_id]);
+    assert_eq!(
+        String::from_utf8_lossy(&removed.stdout).trim(),
+        format!("Agent '{}' deleted successfully", agent_id)
+    );
+    assert!(config(&dir).agents.is_empty());
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let entries = runtime
+        .block_on(FileAuditStorage::new(dir.join("audit.log").to_string_lossy()).load_entries())
+        .unwrap();
+    assert_eq!(entries.len(), 7);
+    assert!(matches!(entries[0].action, AuditAction::Create));
+    assert_eq!(entries[0].reason.as_deref(), Some("bootstrap"));
+    assert_eq!(entries[0].actor.as_deref(), Some("admin"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn bulk_deletes_are_confirmed_in_a_later_run() {
+    let dir = temp_dir();
+    for id in ["alpha", "beta"] {
+        json(&run(
+            &dir,
+            &[
+                "add-mcp",
+                id,
+                "--url",
+                "https://mcp.example.com",
+                "-f",
+                "json",
+            ],
+        ));
+    }
+    let delete = |extra: &[&str]| {
+        let mut args = vec!["delete-mcps", "--ids", "alpha,beta", "-f", "json"];
+        args.extend(extra);
+        run(&dir, &args)
+    };
+    let plan = json(&delete(&[]));
+    assert_eq!(plan["ids"], serde_json::json!(["alpha", "beta"]));
+    let token = plan["confirmation_token"].as_str().unwrap();
+
+    let forged = format!("{}.{}", token.rsplit_once('.').unwrap().0, "0".repeat(64));
+    let refused = delete(&["--confirm", &forged]);
+    assert!(!refused.status.success());
+    assert!(
+        printed(&refused).contains("the configuration changed since the dry run"),
+        "{}",
+        printed(&refused)
+    );
+    assert_eq!(config(&dir).leaf_mcps.len(), 2);
+
+    let deleted = delete(&["--confirm", token]);
+    assert!(deleted.status.success(), "{}", printed(&deleted));
+    assert!(config(&dir).leaf_mcps.is_empty());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn lists_mcps_and_agents_by_label() {
+    let dir = temp_dir();
+    for (id, labels) in [
+        ("checkout", ["team=payments", "env=staging"]),
+        ("ledger", ["team=payments", "env=prod"]),
+        ("search", ["team=search", "env=staging"]),
+    ] {
+        json(&run(
+            &dir,
+            &[
+                "add-mcp",
+                id,
+                "--url",
+                "https://mcp.example.com",
+                "--label",
+                labels[0],
+                "--label",
+                labels[1],
+                "-f",
+                "json",
+            ],
+        ));
+    }
+    json(&run(
+        &dir,
+        &[
+            "add-agent",
+            "billing-bot",
+            "--label",
+            "team=payments",
+            "-f",
+            "json",
+        ],
+    ));
+    json(&run(&dir, &["add-agent", "crawler", "-f", "json"]));
+
+    let listed = json(&run(
+        &dir,
+        &[
+            "list-mcps",
+            "--label",
+            "team=payments,env!=prod",
+            "-f",
+            "json",
+        ],
+    ));
+    assert_eq!(listed["count"], 1);
+    assert_eq!(
+        listed["leaf_mcps"]["checkout"]["labels"],
+        serde_json::json!({ "team": "payments", "env": "staging" })
+    );
+    let listed = json(&run(
+        &dir,
+        &["list-agents", "--label", "team=payments", "-f", "json"],
+    ));
+    assert_eq!(listed["count"], 1);
+    assert!(listed["agents"]["billing-bot"].is_object());
+
+    let printed_list = printed(&run(&dir, &["list-mcps", "--label", "env=staging"]));
+    assert!(printed_list.contains("Leaf MCPs (2):"), "{}", printed_list);
+    assert!(printed_list.contains("checkout: (no name) [env=staging, team=payments]"));
+    assert!(!run(&dir, &["list-mcps", "--label", "env"]).status.success());
+    assert!(
+        !run(
+            &dir,
+            &[
+                "add-mcp",
+                "x",
+                "--url",
+                "https://x.example.com",
+                "--label",
+                "team"
+            ]
+        )
+        .status
+        .success()
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn rejects_what_the_admin_api_rejects() {
+    let dir = temp_dir();
+    let invalid = run(
+        &dir,
+        &["add-mcp", "ftp", "--url", "ftp://files.example.com"],
+    );
+    assert!(!invalid.status.success());
+    assert!(printed(&invalid).contains("URL scheme must be http or https, not 'ftp'"));
+
+    let unknown = run(&dir, &["add-agent", "bot", "--allow", "missing"]);
+    assert!(!unknown.status.success());
+    assert!(printed(&unknown).contains("MCP with ID 'missing' does not exist"));
+
+    // Exactly one transport
+    assert!(!run(&dir, &["add-mcp", "none"]).status.success());
+    assert!(
+        !run(
+            &dir,
+            &[
+                "add-mcp",
+                "both",
+                "--command",
+                "x",
+                "--url",
+                "https://example.com"
+            ]
+        )
+        .status
+        .success()
+    );
+    assert!(config(&dir).leaf_mcps.is_empty());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "yaml")]
+#[test]
+fn exports_the_configuration_as_yaml_or_json() {
+    let dir = temp_dir();
+    json(&run(
+        &dir,
+        &[
+            "add-mcp",
+            "fetch",
+            "--command",
+            "uvx",
+            "--arg",
+            "mcp-server-fetch",
+            "-f",
+            "json",
+        ],
+    ));
+    json(&run(
+        &dir,
+        &["add-agent", "bot", "--allow", "fetch", "-f", "json"],
+    ));
+
+    let shown: ServerConfig =
+        serde_yaml::from_slice(&run(&dir, &["show-config", "--format", "yaml"]).stdout).unwrap();
+    assert_eq!(shown.agents["bot"].allowed_mcp_ids, ["fetch".to_string()]);
+
+    for file in ["export.yaml", "export.yml", "export.json"] {
+        let path = dir.join(file);
+        let written = run(&dir, &["show-config", "--output", path.to_str().unwrap()]);
+        assert!(written.status.success(), "{}", printed(&written));
+        let content = std::fs::read_to_string(&path).unwrap();
+        let exported: ServerConfig = if file.ends_with(".json") {
+            serde_json::from_str(&content).unwrap()
+        } else {
+            assert!(serde_json::from_str::<Value>(&content).is_err());
+            serde_yaml::from_str(&content).unwrap()
+        };
+        assert_eq!(
+            serde_json::to_value(&exported).unwrap(),
+            serde_json::to_value(config(&dir)).unwrap()
+        );
+    }
+
+    // An explicit format wins over the extension
+    let path = dir.join("export.txt");
+    let written = run(
+        &dir,
+        &["show-config", "-o", path.to_str().unwrap(), "-f", "yaml"],
+    );
+    assert!(written.status.success());
+    let exported: ServerConfig =
+        serde_yaml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert!(exported.leaf_mcps.contains_key("fetch"));
+
+    let table = run(
+        &dir,
+        &["show-config", "-o", path.to_str().unwrap(), "-f", "table"],
+    );
+    assert!(!table.status.success());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(not(feature = "yaml"))]
+#[test]
+fn yaml_needs_the_yaml_feature() {
+    let dir = temp_dir();
+    let path = dir.join("export.yaml");
+    let written = run(&dir, &["show-config", "-o", path.to_str().unwrap()]);
+    assert!(!written.status.success());
+    assert!(
+        printed(&written).contains("writing YAML requires the `yaml` feature"),
+        "{}",
+        printed(&written)
+    );
+    assert!(!path.exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn imports_an_exported_configuration() {
+    let dir = temp_dir();
+    json(&run(
+        &dir,
+        &["add-mcp", "fetch", "--command", "uvx", "-f", "json"],
+    ));
+    json(&run(
+        &dir,
+        &["add-agent", "bot", "--allow", "fetch", "-f", "json"],
+    ));
+    // YAML is only read with the `yaml` feature
+    let export = dir.join(if cfg!(feature = "yaml") {
+        "export.yaml"
+    } else {
+        "export.json"
+    });
+    assert!(
+        run(&dir, &["show-config", "-o", export.to_str().unwrap()])
+            .status
+            .success()
+    );
+    json(&run(&dir, &["remove-agent", "bot", "-f", "json"]));
+    json(&run(&dir, &["remove-mcp", "fetch", "-f", "json"]));
+
+    let imported = json(&run(
+        &dir,
+        &["import", export.to_str().unwrap(), "-f", "json"],
+    ));
+    assert_eq!(imported["summary"]["leaf_mcps"]["added"], 1);
+    assert_eq!(imported["summary"]["agents"]["added"], 1);
+    assert_eq!(
+        config(&dir).agents["bot"].allowed_mcp_ids,
+        ["fetch".to_string()]
+    );
+
+    // Nothing is applied from an invalid file
+    let invalid = dir.join("invalid.json");
+    let mut content: Value =
+        serde_json::from_str(&std::fs::read_to_string(dir.join("config.json")).unwrap()).unwrap();
+    content["agents"]["bot"]["allowed_mcp_ids"] = serde_json::json!(["missing"]);
+    content["leaf_mcps"] = serde_json::json!({});
+    std::fs::write(&invalid, content.to_string()).unwrap();
+    let failed = run(&dir, &["import", invalid.to_str().unwrap(), "--merge"]);
+    assert!(!failed.status.success());
+    assert!(printed(&failed).contains("MCP with ID 'missing' does not exist"));
+    assert!(config(&dir).leaf_mcps.contains_key("fetch"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn shows_usage_before_deleting_mcps() {
+    let dir = temp_dir();
+    json(&run(
+        &dir,
+        &["add-mcp", "fetch", "--command", "uvx", "-f", "json"],
+    ));
+    let shown = printed(&run(&dir, &["delete-mcps", "--ids", "fetch"]));
+    assert!(
+        shown.contains("Usage of 'fetch': unknown, no access log is enabled"),
+        "{}",
+        shown
+    );
+
+    let access_log = dir.join("access.log");
+    std::fs::write(
+        &access_log,
+        format!(
+            "{{\"at\":\"{}\",\"mcp\":\"fetch\",\"agent\":\"writer\",\"status\":200}}\n",
+            chrono::Utc::now().to_rfc3339()
+        ),
+    )
+    .unwrap();
+    let shown = printed(&run(
+        &dir,
+        &[
+            "--access-log",
+            access_log.to_str().unwrap(),
+            "delete-mcps",
+            "--ids",
+            "fetch",
+        ],
+    ));
+    assert!(shown.contains(": active, 1 calls, last used"), "{}", shown);
+    assert!(
+        shown.contains("  Agent 'writer' (no longer allowed): 1 calls"),
+        "{}",
+        shown
+    );
+    assert!(shown.contains("Confirm with --confirm"), "{}", shown);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn replays_the_audit_log_into_the_configuration() {
+    let dir = temp_dir();
+    json(&run(
+        &dir,
+        &["add-mcp", "fetch", "--command", "uvx", "-f", "json"],
+    ));
+    json(&run(
+        &dir,
+        &[
+            "add-mcp",
+            "search",
+            "--url",
+            "https://search.example.com/mcp",
+            "-f",
+            "json",
+        ],
+    ));
+    json(&run(
+        &dir,
+        &["add-agent", "bot", "--allow", "fetch", "-f", "json"],
+    ));
+    json(&run(&dir, &["allow-mcp", "bot", "search", "-f", "json"]));
+    json(&run(&dir, &["remove-mcp", "search", "-f", "json"]));
+
+    let output = dir.join("reconstructed.json");
+    let output = output.to_str().unwrap();
+    let report = json(&run(
+        &dir,
+        &["replay", "-o", output, "--verify", "-f", "json"],
+    ));
+    assert_eq!(report["verify"]["diverged"], false, "{}", report);
+    assert_eq!(report["unapplied"], serde_json::json!([]));
+    let reconstructed: ServerConfig =
+        serde_json::from_str(&std::fs::read_to_string(output).unwrap()).unwrap();
+    assert_eq!(
+        reconstructed.agents["bot"].allowed_mcp_ids,
+        ["fetch".to_string()]
+    );
+    assert!(!reconstructed.leaf_mcps.contains_key("search"));
+
+    // Only up to the first change
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let entries = runtime
+        .block_on(FileAuditStorage::new(dir.join("audit.log").to_string_lossy()).load_entries())
+        .unwrap();
+    let until = entries[0].timestamp.to_rfc3339();
+    let report = json(&run(
+        &dir,
+        &["replay", "-o", output, "--until", &until, "-f", "json"],
+    ));
+    assert_eq!(report["replayed_entries"], 1);
+    let reconstructed: ServerConfig =
+        serde_json::from_str(&std::fs::read_to_string(output).unwrap()).unwrap();
+    assert!(reconstructed.agents.is_empty());
+
+    // A change the audit log doesn't know of
+    let mut stored: Value =
+        serde_json::from_str(&std::fs::read_to_string(dir.join("config.json")).unwrap()).unwrap();
+    stored["leaf_mcps"]["fetch"]["description"] = "Edited by hand".into();
+    std::fs::write(dir.join("config.json"), stored.to_string()).unwrap();
+    let verified = run(&dir, &["replay", "-o", output, "--verify"]);
+    assert_eq!(verified.status.code(), Some(2), "{}", printed(&verified));
+    assert!(printed(&verified).contains("leaf_mcps.fetch"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn verify_audit_reports_a_broken_hash_chain() {
+    let dir = temp_dir();
+    for id in ["fetch", "search", "mail"] {
+        json(&run(
+            &dir,
+            &["add-mcp", id, "--command", "echo", "-f", "json"],
+        ));
+    }
+    let report = json(&run(&dir, &["verify-audit", "-f", "json"]));
+    assert!(report["chain"]["broken_link"].is_null());
+    assert_eq!(report["chain"]["entries"], 3);
+
+    let audit_log = dir.join("audit.log");
+    let content = std::fs::read_to_string(&audit_log).unwrap();
+    let mut lines: Vec<&str> = content.lines().collect();
+    lines.remove(1);
+    std::fs::write(&audit_log, lines.join("\n") + "\n").unwrap();
+    let verified = run(&dir, &["verify-audit"]);
+    assert_eq!(verified.status.code(), Some(4), "{}", printed(&verified));
+    assert!(
+        printed(&verified).contains("Hash chain: broken at line 2 (sequence 3"),
+        "{}",
+        printed(&verified)
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
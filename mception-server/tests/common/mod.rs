@@ -0,0 +1,351 @@
+//! A real server on an ephemeral port with file storage in a temp directory,
+//! for tests going through the HTTP API. Use it with `mod common;`.
+#![allow(dead_code)]
+
+use mception_server::core::{
+    AuditLogEntry, LeafMcpConfig, McpTransport, ReverseRequestPolicy, ServerConfig,
+};
+use mception_server::services::ConfigService;
+use mception_server::services::access_log::AccessLog;
+use mception_server::services::auth::AdminToken;
+use mception_server::services::availability::AvailabilityTracker;
+use mception_server::services::config_limits::ConfigLimits;
+use mception_server::services::idempotency::IdempotencyStore;
+use mception_server::services::internals::ResourceLimits;
+use mception_server::services::logging::LogControl;
+use mception_server::storage::providers::{AuditStorage, FileAuditStorage, FileConfigStorage};
+use mception_server::{RouterOptions, build_router};
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde_json::{Value, json};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// What the server under test is started with
+#[derive(Default)]
+pub struct TestServerBuilder {
+    config: Option<String>,
+    admin_tokens: Vec<String>,
+    limits: Option<ResourceLimits>,
+    config_limits: Option<ConfigLimits>,
+    fault_injection: bool,
+    access_log: bool,
+    mask_change_actors: bool,
+    idempotency: Option<IdempotencyStore>,
+    agent_rate_limit: Option<u32>,
+    audit_buffer_capacity: Option<usize>,
+    circuit_breakers: Option<(u32, Duration)>,
+    options: Option<RouterOptions>,
+    log_control: Option<LogControl>,
+    availability: Option<AvailabilityTracker>,
+    expiry_webhook: Option<String>,
+}
+
+impl TestServerBuilder {
+    /// Start from this configuration file content instead of an empty one
+    pub fn config(mut self, content: &str) -> Self {
+        self.config = Some(content.to_string());
+        self
+    }
+
+    /// Require this admin token, as `<token>` or `<actor>=<token>`. The
+    /// first one is sent with [`TestServer::admin`] requests.
+    pub fn admin_token(mut self, token: &str) -> Self {
+        self.admin_tokens.push(token.to_string());
+        self
+    }
+
+    pub fn limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    pub fn config_limits(mut self, config_limits: ConfigLimits) -> Self {
+        self.config_limits = Some(config_limits);
+        self
+    }
+
+    pub fn fault_injection(mut self) -> Self {
+        self.fault_injection = true;
+        self
+    }
+
+    /// Record forwarded requests in [`TestServer::access_log_path`]
+    pub fn access_log(mut self) -> Self {
+        self.access_log = true;
+        self
+    }
+
+    pub fn mask_change_actors(mut self) -> Self {
+        self.mask_change_actors = true;
+        self
+    }
+
+    /// Keep answers to requests with an `Idempotency-Key` in `store`
+    pub fn idempotency(mut self, store: IdempotencyStore) -> Self {
+        self.idempotency = Some(store);
+        self
+    }
+
+    /// Limit each agent's requests to the agent and leaf MCP routes
+    pub fn agent_rate_limit(mut self, per_minute: u32) -> Self {
+        self.agent_rate_limit = Some(per_minute);
+        self
+    }
+
+    /// Post expiry events to `url`
+    pub fn expiry_webhook(mut self, url: &str) -> Self {
+        self.expiry_webhook = Some(url.to_string());
+        self
+    }
+
+    /// Hold back at most `capacity` audit entries while the audit storage fails
+    pub fn audit_buffer_capacity(mut self, capacity: usize) -> Self {
+        self.audit_buffer_capacity = Some(capacity);
+        self
+    }
+
+    /// Open leaf MCPs' circuit breakers after `failure_threshold` failed
+    /// forwards, for `cooldown`
+    pub fn circuit_breakers(mut self, failure_threshold: u32, cooldown: Duration) -> Self {
+        self.circuit_breakers = Some((failure_threshold, cooldown));
+        self
+    }
+
+    /// Mount only some parts of the API; admin tokens are added to them
+    pub fn router_options(mut self, options: RouterOptions) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// Change the log filter through `control` at runtime
+    pub fn log_control(mut self, control: LogControl) -> Self {
+        self.log_control = Some(control);
+        self
+    }
+
+    /// Track agent availability in `tracker`
+    pub fn availability(mut self, tracker: AvailabilityTracker) -> Self {
+        self.availability = Some(tracker);
+        self
+    }
+
+    pub async fn start(self) -> TestServer {
+        let dir = temp_dir();
+        let config_path = dir.join("config.json");
+        let audit_log_path = dir.join("audit.log");
+        let access_log_path = dir.join("access.log");
+        if let Some(content) = &self.config {
+            std::fs::write(&config_path, content).unwrap();
+        }
+
+        let mut service = ConfigService::new(
+            Arc::new(FileConfigStorage::new(config_path.to_string_lossy())),
+            Arc::new(FileAuditStorage::new(audit_log_path.to_string_lossy())),
+        );
+        if let Some(limits) = self.limits {
+            service = service.with_limits(limits);
+        }
+        if let Some(config_limits) = self.config_limits {
+            service = service.with_config_limits(config_limits);
+        }
+        if self.fault_injection {
+            service = service.with_fault_injection();
+        }
+        if self.access_log {
+            service = service.with_access_log(AccessLog::new(&access_log_path));
+        }
+        if self.mask_change_actors {
+            service = service.with_masked_change_actors();
+        }
+        if let Some(store) = self.idempotency {
+            service = service.with_idempotency(store);
+        }
+        if self.agent_rate_limit.is_some() {
+            service = service.with_agent_rate_limit(self.agent_rate_limit);
+        }
+        if let Some(capacity) = self.audit_buffer_capacity {
+            service = service.with_audit_buffer_capacity(capacity);
+        }
+        if let Some((failure_threshold, cooldown)) = self.circuit_breakers {
+            service = service.with_circuit_breakers(failure_threshold, cooldown);
+        }
+        if let Some(control) = self.log_control {
+            service = service.with_log_control(control);
+        }
+        if let Some(tracker) = self.availability {
+            service = service.with_availability(tracker);
+        }
+        if self.expiry_webhook.is_some() {
+            service = service.with_expiry_webhook(self.expiry_webhook);
+        }
+        let service = Arc::new(service);
+        service.load_configuration().await.unwrap();
+
+        let admin_tokens: Vec<AdminToken> = self
+            .admin_tokens
+            .iter()
+            .map(|token| AdminToken::parse(token).unwrap())
+            .collect();
+        let options = RouterOptions {
+            admin_tokens: admin_tokens.clone(),
+            ..self.options.unwrap_or_default()
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let router = build_router(service.clone(), options);
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                router.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+            )
+            .await
+            .unwrap()
+        });
+
+        TestServer {
+            url,
+            dir,
+            config_path,
+            audit_log_path,
+            access_log_path,
+            service,
+            client: reqwest::Client::new(),
+            admin_token: admin_tokens.into_iter().next(),
+        }
+    }
+}
+
+/// A running server and the files it stores its configuration and audit
+/// log in. The files are removed when it's dropped.
+pub struct TestServer {
+    pub url: String,
+    pub dir: PathBuf,
+    pub config_path: PathBuf,
+    pub audit_log_path: PathBuf,
+    /// Written only if the server was built with an access log
+    pub access_log_path: PathBuf,
+    pub service: Arc<ConfigService>,
+    client: reqwest::Client,
+    admin_token: Option<AdminToken>,
+}
+
+impl TestServer {
+    pub fn builder() -> TestServerBuilder {
+        TestServerBuilder::default()
+    }
+
+    /// A server with an empty configuration and the whole API open
+    pub async fn start() -> Self {
+        Self::builder().start().await
+    }
+
+    /// A request to `path` without credentials
+    pub fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client.request(method, format!("{}{}", self.url, path))
+    }
+
+    /// A request to the admin API at `/admin{path}`, with the admin token
+    pub fn admin(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self.request(method, &format!("/admin{}", path));
+        match &self.admin_token {
+            Some(token) => token.authorize(request),
+            None => request,
+        }
+    }
+
+    /// Send an admin request with a JSON body and read the JSON answer
+    pub async fn admin_json(
+        &self,
+        method: Method,
+        path: &str,
+        body: &Value,
+    ) -> (StatusCode, Value) {
+        answer(self.admin(method, path).json(body)).await
+    }
+
+    /// Send an admin request with a JSON body, repeating it with the
+    /// confirmation token it is challenged with
+    pub async fn admin_confirmed(
+        &self,
+        method: Method,
+        path: &str,
+        body: &Value,
+    ) -> (StatusCode, Value) {
+        let (status, answered) = self.admin_json(method.clone(), path, body).await;
+        if status != StatusCode::PRECONDITION_REQUIRED {
+            return (status, answered);
+        }
+        let token = answered["confirmation"]["token"].as_str().unwrap();
+        answer(
+            self.admin(method, path)
+                .header("x-confirm-token", token)
+                .json(body),
+        )
+        .await
+    }
+
+    /// Send an admin `GET` and read the JSON answer
+    pub async fn admin_get(&self, path: &str) -> (StatusCode, Value) {
+        answer(self.admin(Method::GET, path)).await
+    }
+
+    /// The configuration file as saved on disk
+    pub fn saved_config(&self) -> ServerConfig {
+        let content = std::fs::read_to_string(&self.config_path).unwrap();
+        serde_json::from_str(&content).unwrap()
+    }
+
+    /// The audit log as written to disk
+    pub async fn audit_entries(&self) -> Vec<AuditLogEntry> {
+        FileAuditStorage::new(self.audit_log_path.to_string_lossy())
+            .load_entries()
+            .await
+            .unwrap()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Status and JSON body of the response, `Value::Null` for a body that isn't JSON
+pub async fn answer(request: RequestBuilder) -> (StatusCode, Value) {
+    let response = request.send().await.unwrap();
+    let status = response.status();
+    let text = response.text().await.unwrap();
+    (status, serde_json::from_str(&text).unwrap_or(Value::Null))
+}
+
+/// A new, empty directory under the system's temp directory
+pub fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A leaf MCP using `transport`, neither local nor reachable by agents and
+/// with every optional setting unset. Change fields with `..leaf_mcp(..)`.
+pub fn leaf_mcp(transport: McpTransport) -> LeafMcpConfig {
+    LeafMcpConfig {
+        id: String::new(),
+        name: None,
+        description: None,
+        instructions: None,
+        transport,
+        is_local: false,
+        reachable_by_agent: false,
+        config: json!({}),
+        reverse_requests: ReverseRequestPolicy::default(),
+        region: None,
+        replica_group: None,
+        timeout_ms: None,
+        retry: None,
+        header_policy: None,
+        max_concurrent_requests: None,
+        labels: Default::default(),
+        revision: 0,
+    }
+}
@@ -0,0 +1,200 @@
+mod common;
+
+use axum::Router;
+use axum::extract::State;
+use axum::routing::post;
+use common::{TestServer, answer};
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Requests the upstream is answering, and the most it answered at once
+#[derive(Default)]
+struct InFlight {
+    current: AtomicUsize,
+    most: AtomicUsize,
+}
+
+/// An HTTPS leaf MCP holding every request until `release` is sent `true`
+async fn serve_held_upstream() -> (String, Arc<InFlight>, watch::Sender<bool>) {
+    type Upstream = (Arc<InFlight>, watch::Receiver<bool>);
+    async fn hold(State((in_flight, mut released)): State<Upstream>) -> axum::Json<Value> {
+        let current = in_flight.current.fetch_add(1, Ordering::SeqCst) + 1;
+        in_flight.most.fetch_max(current, Ordering::SeqCst);
+        let _ = released.wait_for(|released| *released).await;
+        in_flight.current.fetch_sub(1, Ordering::SeqCst);
+        axum::Json(json!({ "jsonrpc": "2.0", "id": 1, "result": {} }))
+    }
+
+    let in_flight = Arc::new(InFlight::default());
+    let (release, released) = watch::channel(false);
+    let app = Router::new()
+        .route("/mcp", post(hold))
+        .with_state((in_flight.clone(), released));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (url, in_flight, release)
+}
+
+async fn create_leaf(server: &TestServer, id: &str, transport: Value, extra: Value) -> StatusCode {
+    let mut config = json!({
+        "transport": transport,
+        "is_local": false,
+        "reachable_by_agent": false,
+        "config": {}
+    });
+    config
+        .as_object_mut()
+        .unwrap()
+        .extend(extra.as_object().unwrap().clone());
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({ "id": id, "config": config, "reason": null }),
+        )
+        .await;
+    assert!(
+        status == StatusCode::OK || status.is_client_error(),
+        "{}",
+        body
+    );
+    status
+}
+
+fn forward(server: &TestServer, leaf: &str) -> reqwest::RequestBuilder {
+    server
+        .request(Method::POST, &format!("/leaf/{}/forwarding", leaf))
+        .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" }))
+}
+
+/// Wait until the leaf MCP's status shows the requests in flight and waiting
+async fn wait_for_concurrency(server: &TestServer, leaf: &str, in_flight: u64, waiting: u64) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let (_, status) = server.admin_get(&format!("/leaf/{}/status", leaf)).await;
+        let concurrency = &status["concurrency"];
+        if concurrency["in_flight"] == in_flight && concurrency["waiting"] == waiting {
+            return;
+        }
+        assert!(Instant::now() < deadline, "{}", status);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test]
+async fn requests_beyond_the_limit_wait_for_a_slot() {
+    let (url, in_flight, release) = serve_held_upstream().await;
+    let server = Arc::new(TestServer::start().await);
+    let status = create_leaf(
+        &server,
+        "held",
+        json!({ "type": "https", "url": url }),
+        json!({ "max_concurrent_requests": 2, "timeout_ms": 5000 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let calls: Vec<_> = (0..3)
+        .map(|_| {
+            let server = server.clone();
+            tokio::spawn(async move { answer(forward(&server, "held")).await })
+        })
+        .collect();
+    wait_for_concurrency(&server, "held", 2, 1).await;
+    let (_, status) = server.admin_get("/leaf/held/status").await;
+    assert_eq!(status["concurrency"]["limit"], 2);
+
+    release.send(true).unwrap();
+    for call in calls {
+        let (status, body) = call.await.unwrap();
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+    assert_eq!(in_flight.most.load(Ordering::SeqCst), 2);
+    wait_for_concurrency(&server, "held", 0, 0).await;
+}
+
+#[tokio::test]
+async fn requests_waiting_past_their_deadline_are_refused() {
+    let (url, _, release) = serve_held_upstream().await;
+    let server = Arc::new(TestServer::start().await);
+    create_leaf(
+        &server,
+        "held",
+        json!({ "type": "https", "url": url }),
+        json!({ "max_concurrent_requests": 1, "timeout_ms": 5000 }),
+    )
+    .await;
+
+    let first = {
+        let server = server.clone();
+        tokio::spawn(async move { answer(forward(&server, "held")).await })
+    };
+    wait_for_concurrency(&server, "held", 1, 0).await;
+
+    let started = Instant::now();
+    let (status, body) =
+        answer(forward(&server, "held").header("x-mception-deadline-ms", "200")).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS, "{}", body);
+    assert!(started.elapsed() >= Duration::from_millis(200));
+    assert_eq!(body["error"]["code"], "proxy_overloaded");
+    assert_eq!(body["error"]["retryable"], true);
+    assert_eq!(body["max_concurrent_requests"], 1);
+
+    release.send(true).unwrap();
+    let (status, body) = first.await.unwrap();
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+#[tokio::test]
+async fn limits_default_by_transport() {
+    let server = TestServer::start().await;
+    create_leaf(
+        &server,
+        "local",
+        json!({ "type": "stdio", "command": "cat", "args": [] }),
+        json!({}),
+    )
+    .await;
+    create_leaf(
+        &server,
+        "remote",
+        json!({ "type": "https", "url": "https://mcp.example.com/mcp" }),
+        json!({}),
+    )
+    .await;
+    create_leaf(
+        &server,
+        "echo",
+        json!({ "type": "builtin", "kind": "echo" }),
+        json!({}),
+    )
+    .await;
+
+    for (leaf, limit) in [
+        ("local", json!(4)),
+        ("remote", json!(64)),
+        ("echo", Value::Null),
+    ] {
+        let (_, status) = server.admin_get(&format!("/leaf/{}/status", leaf)).await;
+        assert_eq!(
+            status["concurrency"],
+            json!({ "in_flight": 0, "waiting": 0, "limit": limit }),
+            "{}",
+            leaf
+        );
+    }
+
+    let status = create_leaf(
+        &server,
+        "none",
+        json!({ "type": "https", "url": "https://mcp.example.com/mcp" }),
+        json!({ "max_concurrent_requests": 0 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::TestServer;
+use mception_server::core::BACKUP_TIMESTAMP_FORMAT;
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+async fn create_leaf(server: &TestServer, id: &str) {
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({
+                "id": id,
+                "config": {
+                    "transport": { "type": "builtin", "kind": "echo" },
+                    "is_local": false,
+                    "reachable_by_agent": false,
+                    "config": {}
+                },
+                "reason": null
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap().to_string_lossy().into_owned()
+}
+
+#[tokio::test]
+async fn snapshots_are_picked_by_the_time_in_their_name() {
+    let server = TestServer::start().await;
+    create_leaf(&server, "first").await;
+    let backup = PathBuf::from(server.service.backup_configuration().await.unwrap());
+    // Copying a backup around changes its modification time, not its name
+    std::fs::File::options()
+        .write(true)
+        .open(&backup)
+        .unwrap()
+        .set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(3600))
+        .unwrap();
+    create_leaf(&server, "second").await;
+
+    let historical = server
+        .service
+        .configuration_as_of(Utc::now())
+        .await
+        .unwrap();
+    assert_eq!(historical.source.snapshot, Some(file_name(&backup)));
+    assert_eq!(historical.source.replayed_entries, 1);
+    assert!(historical.config.leaf_mcps.contains_key("first"));
+    assert!(historical.config.leaf_mcps.contains_key("second"));
+}
+
+#[tokio::test]
+async fn entries_are_replayed_by_revision_not_by_time() {
+    let server = TestServer::start().await;
+    create_leaf(&server, "first").await;
+    let backup = PathBuf::from(server.service.backup_configuration().await.unwrap());
+    // A snapshot named later than the entries after it, as when the clock
+    // was set back since
+    let name = file_name(&backup);
+    let prefix = &name[..name.rfind("backup.").unwrap() + "backup.".len()];
+    let later = backup.with_file_name(format!(
+        "{}{}",
+        prefix,
+        (Utc::now() + Duration::seconds(2)).format(BACKUP_TIMESTAMP_FORMAT)
+    ));
+    std::fs::rename(&backup, &later).unwrap();
+    create_leaf(&server, "second").await;
+
+    let historical = server
+        .service
+        .configuration_as_of(Utc::now() + Duration::seconds(5))
+        .await
+        .unwrap();
+    assert_eq!(historical.source.snapshot, Some(file_name(&later)));
+    assert_eq!(historical.source.replayed_entries, 1);
+    assert!(historical.source.skipped_entry_ids.is_empty());
+    assert!(historical.config.leaf_mcps.contains_key("first"));
+    assert!(historical.config.leaf_mcps.contains_key("second"));
+}
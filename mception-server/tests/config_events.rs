@@ -0,0 +1,134 @@
+mod common;
+
+use common::{TestServer, answer, leaf_mcp};
+use futures_util::StreamExt;
+use mception_server::core::{BuiltinMcpKind, McpTransport};
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+
+async fn add_leaf(server: &TestServer, id: &str) {
+    let leaf = leaf_mcp(McpTransport::Builtin {
+        kind: BuiltinMcpKind::Echo,
+    });
+    server
+        .service
+        .create_leaf_mcp(Some(id.to_string()), leaf, None, None)
+        .await
+        .unwrap();
+}
+
+async fn add_agent(server: &TestServer, id: &str, allowed: &[&str]) {
+    server
+        .service
+        .create_agent(
+            Some(id.to_string()),
+            None,
+            allowed.iter().map(|id| id.to_string()).collect(),
+            None,
+        )
+        .await
+        .unwrap();
+}
+
+/// `alice` may use `search`, `bob` may use `mail`
+async fn server_with_agents() -> TestServer {
+    let server = TestServer::start().await;
+    add_leaf(&server, "search").await;
+    add_leaf(&server, "mail").await;
+    add_agent(&server, "alice", &["search"]).await;
+    add_agent(&server, "bob", &["mail"]).await;
+    server
+}
+
+async fn describe(server: &TestServer, leaf_mcp_id: &str, description: &str) -> u64 {
+    server
+        .service
+        .update_leaf_mcp(
+            leaf_mcp_id,
+            json!({ "description": description }),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    server.service.revision().await
+}
+
+#[tokio::test]
+async fn agents_are_sent_only_the_changes_concerning_them() {
+    let server = server_with_agents().await;
+    let mut stream = server
+        .request(Method::GET, "/agent/alice/events")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stream.status(), StatusCode::OK);
+    assert_eq!(stream.headers()["content-type"], "text/event-stream");
+
+    describe(&server, "mail", "Mail").await;
+    let revision = describe(&server, "search", "Search").await;
+    let mut received = String::new();
+    while !received.contains("\n\n") {
+        let chunk = tokio::time::timeout(Duration::from_secs(10), stream.chunk())
+            .await
+            .expect("no event streamed")
+            .unwrap()
+            .unwrap();
+        received.push_str(&String::from_utf8_lossy(&chunk));
+    }
+    let data = received
+        .lines()
+        .find_map(|line| line.strip_prefix("data: "))
+        .unwrap();
+    assert!(received.contains("event: config_changed"), "{}", received);
+    let event: Value = serde_json::from_str(data).unwrap();
+    assert_eq!(event, json!({ "agent_id": "alice", "revision": revision }));
+
+    // The configuration fetched next is at least as recent
+    let (_, config) = answer(server.request(Method::GET, "/agent/alice/config")).await;
+    assert_eq!(config["metadata"]["revision"], revision);
+
+    let (status, _) = answer(server.request(Method::GET, "/agent/carol/events")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn connected_agents_are_sent_changes_over_their_socket() {
+    let server = server_with_agents().await;
+    let url = format!(
+        "{}/agent/alice/forwarding_ws",
+        server.url.replace("http://", "ws://")
+    );
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Neither bob's grants nor an MCP alice doesn't use concern alice
+    server
+        .service
+        .add_agent_allowed_mcp("bob", "search", None, None)
+        .await
+        .unwrap();
+    describe(&server, "mail", "Mail").await;
+    server
+        .service
+        .add_agent_allowed_mcp("alice", "mail", None, None)
+        .await
+        .unwrap();
+    let revision = server.service.revision().await;
+
+    let message = tokio::time::timeout(Duration::from_secs(10), socket.next())
+        .await
+        .expect("no change sent")
+        .unwrap()
+        .unwrap();
+    let Message::Text(text) = message else {
+        panic!("unexpected message {:?}", message);
+    };
+    let message: Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(
+        message,
+        json!({ "type": "config_changed", "revision": revision })
+    );
+}
@@ -0,0 +1,200 @@
+mod common;
+
+use common::TestServer;
+use mception_server::services::config_limits::ConfigLimits;
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+
+fn create_leaf(id: &str, config: Value) -> Value {
+    json!({
+        "id": id,
+        "config": {
+            "transport": { "type": "https", "url": "https://search.example.com/mcp", "headers": null },
+            "is_local": false,
+            "reachable_by_agent": false,
+            "config": config
+        },
+        "reason": null
+    })
+}
+
+fn update(config: Value) -> Value {
+    json!({ "config": { "config": config }, "reason": null, "should_update": true })
+}
+
+/// An object nested `depth` levels deep
+fn nested(depth: usize) -> Value {
+    (0..depth).fold(json!(1), |inner, _| json!({ "a": inner }))
+}
+
+fn limits() -> ConfigLimits {
+    ConfigLimits {
+        max_bytes: 256,
+        max_depth: 4,
+        max_keys: 8,
+    }
+}
+
+#[tokio::test]
+async fn oversized_and_deeply_nested_configs_are_refused() {
+    let server = TestServer::builder().config_limits(limits()).start().await;
+
+    let (status, body) = server
+        .admin_json(Method::POST, "/leaf", &create_leaf("deep", nested(5)))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+    assert_eq!(body["error"]["kind"], "limit_exceeded");
+    let large = json!({ "blob": "x".repeat(300) });
+    let (status, body) = server
+        .admin_json(Method::POST, "/leaf", &create_leaf("large", large.clone()))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("at most 256")
+    );
+    assert!(server.saved_config().leaf_mcps.is_empty());
+
+    let (status, _) = server
+        .admin_json(Method::POST, "/leaf", &create_leaf("search", nested(4)))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = server
+        .admin_json(Method::PUT, "/leaf/search/config", &update(nested(6)))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+    assert_eq!(body["error"]["kind"], "limit_exceeded");
+
+    let (status, _) = server
+        .admin_json(
+            Method::POST,
+            "/agent",
+            &json!({ "agent_id": "writer", "allowed_mcp_ids": [] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let keys: serde_json::Map<String, Value> =
+        (0..9).map(|at| (format!("k{}", at), json!(at))).collect();
+    let (status, body) = server
+        .admin_json(Method::PUT, "/agent/writer/config", &update(keys.into()))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("9 keys")
+    );
+    let (status, body) = server
+        .admin_json(Method::PUT, "/agent/writer/config", &update(large))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+
+    // Nothing is truncated, the stored configs are as they were
+    let saved = server.saved_config();
+    assert_eq!(saved.leaf_mcps["search"].config, nested(4));
+    assert_eq!(saved.agents["writer"].config, json!({}));
+}
+
+#[tokio::test]
+async fn configs_loaded_beyond_the_limits_may_shrink_but_not_grow() {
+    let mut config: Value =
+        serde_json::to_value(mception_server::core::ServerConfig::default()).unwrap();
+    config["leaf_mcps"] = json!({
+        "search": {
+            "id": "search",
+            "transport": { "type": "https", "url": "https://search.example.com/mcp", "headers": null },
+            "is_local": false,
+            "reachable_by_agent": false,
+            "config": nested(6)
+        }
+    });
+    let server = TestServer::builder()
+        .config(&config.to_string())
+        .config_limits(limits())
+        .start()
+        .await;
+    let (status, _) = server.admin_get("/leaf/search/config").await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Other fields can still be changed
+    let (status, body) = server
+        .admin_json(
+            Method::PUT,
+            "/leaf/search/config",
+            &json!({ "config": { "name": "Search" }, "reason": null, "should_update": true }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, _) = server
+        .admin_json(Method::PUT, "/leaf/search/config", &update(nested(7)))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, body) = server
+        .admin_json(Method::PUT, "/leaf/search/config", &update(json!(null)))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+#[tokio::test]
+async fn configs_are_checked_against_the_referenced_schema() {
+    let mut config: Value =
+        serde_json::to_value(mception_server::core::ServerConfig::default()).unwrap();
+    config["config_schemas"] = json!({
+        "leaf-v1": {
+            "type": "object",
+            "required": ["region"],
+            "properties": {
+                "region": { "type": "string", "enum": ["eu", "us"] },
+                "retries": { "type": "integer", "minimum": 0, "maximum": 5 }
+            },
+            "additionalProperties": false
+        }
+    });
+    config["config_schema"] = json!({ "leaf_mcps": "leaf-v1" });
+    let server = TestServer::builder()
+        .config(&config.to_string())
+        .start()
+        .await;
+
+    for (blob, message) in [
+        (json!({}), "config.region is required"),
+        (json!({ "region": "ap" }), "config.region must be one of"),
+        (
+            json!({ "region": "eu", "retries": 9 }),
+            "config.retries must be at most 5",
+        ),
+        (
+            json!({ "region": "eu", "color": "red" }),
+            "config.color is not allowed",
+        ),
+    ] {
+        let (status, body) = server
+            .admin_json(Method::POST, "/leaf", &create_leaf("search", blob))
+            .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+        assert_eq!(body["error"]["kind"], "schema_violation");
+        let error = body["error"]["message"].as_str().unwrap();
+        assert!(error.contains(message), "{}", error);
+    }
+
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &create_leaf("search", json!({ "region": "eu" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, body) = server
+        .admin_json(
+            Method::PUT,
+            "/leaf/search/config",
+            &update(json!({ "retries": -1 })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+    assert_eq!(body["error"]["kind"], "schema_violation");
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use common::temp_dir;
+use mception_server::core::testing::load_fixture;
+use mception_server::services::ConfigService;
+use mception_server::storage::providers::{
+    AuditStorage, ConfigStorage, FileAuditStorage, FileConfigStorage,
+};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+fn fixture() -> String {
+    load_fixture("config.json")
+}
+
+/// Fresh directory holding a copy of the fixture configuration
+fn fixture_dir() -> PathBuf {
+    let dir = temp_dir();
+    std::fs::write(dir.join("config.json"), fixture()).unwrap();
+    dir
+}
+
+#[tokio::test]
+async fn save_without_changes_is_byte_stable() {
+    let dir = fixture_dir();
+    let storage = FileConfigStorage::new(dir.join("config.json").to_string_lossy());
+
+    let config = storage.load_config().await.unwrap();
+    storage.save_config(&config).await.unwrap();
+
+    let saved = std::fs::read_to_string(dir.join("config.json")).unwrap();
+    assert_eq!(saved, fixture());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn update_only_changes_touched_lines() {
+    let dir = fixture_dir();
+    let service = ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    );
+    service.load_configuration().await.unwrap();
+
+    service
+        .update_leaf_mcp(
+            "search",
+            serde_json::json!({ "description": "Web and news search" }),
+            Some("test".to_string()),
+            None,
+        )
+        .await
+        .unwrap();
+
+    let saved = std::fs::read_to_string(dir.join("config.json")).unwrap();
+    let fixture = fixture();
+    let before: Vec<&str> = fixture.lines().collect();
+    let after: Vec<&str> = saved.lines().collect();
+    assert_eq!(before.len(), after.len());
+
+    let changed: Vec<&str> = after
+        .iter()
+        .zip(&before)
+        .filter(|(after, before)| after != before)
+        .map(|(after, _)| after.trim())
+        .collect();
+    assert_eq!(changed.len(), 4, "unexpected changes: {:?}", changed);
+    assert_eq!(changed[0], r#""description": "Web and news search","#);
+    // The leaf MCP's own revision and the configuration's
+    assert_eq!(changed[1], r#""revision": 4"#);
+    assert!(changed[2].starts_with(r#""last_modified":"#));
+    assert_eq!(changed[3], r#""revision": 4"#);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn saves_the_golden_configuration_format() {
+    let dir = temp_dir();
+    std::fs::write(
+        dir.join("config.json"),
+        load_fixture("golden/server_config.json"),
+    )
+    .unwrap();
+    let storage = FileConfigStorage::new(dir.join("config.json").to_string_lossy());
+
+    let config = storage.load_config().await.unwrap();
+    storage.save_config(&config).await.unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join("config.json")).unwrap(),
+        load_fixture("golden/server_config.expected.json")
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn loads_the_golden_audit_log() {
+    let dir = temp_dir();
+    std::fs::write(
+        dir.join("audit.log"),
+        load_fixture("golden/audit_log.jsonl"),
+    )
+    .unwrap();
+    let storage = FileAuditStorage::new(dir.join("audit.log").to_string_lossy());
+
+    let entries = storage.load_entries().await.unwrap();
+    let sequences: Vec<u64> = entries.iter().map(|entry| entry.sequence).collect();
+    // The entry from before sequence numbers is numbered on load
+    assert_eq!(sequences, [1, 2, 3, 4, 5, 6, 7, 8]);
+    assert!(storage.verify().await.unwrap().is_clean());
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,258 @@
+mod common;
+
+use common::TestServer;
+use mception_server::core::{AuditAction, AuditLogEntry};
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+
+fn create_leaf(id: &str) -> Value {
+    json!({
+        "id": id,
+        "config": {
+            "transport": { "type": "https", "url": format!("https://{}.example.com/mcp", id), "headers": null },
+            "is_local": false,
+            "reachable_by_agent": false,
+            "config": {}
+        },
+        "reason": null
+    })
+}
+
+async fn server_with_leaves(ids: &[&str]) -> TestServer {
+    let server = TestServer::start().await;
+    for id in ids {
+        let (status, body) = server
+            .admin_json(Method::POST, "/leaf", &create_leaf(id))
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+    server
+}
+
+fn confirmations(entries: &[AuditLogEntry]) -> Vec<&AuditLogEntry> {
+    entries
+        .iter()
+        .filter(|entry| matches!(entry.action, AuditAction::Confirmation))
+        .collect()
+}
+
+async fn repeat(
+    server: &TestServer,
+    method: Method,
+    path: &str,
+    body: &Value,
+    token: &str,
+) -> (StatusCode, Value) {
+    let response = server
+        .admin(method, path)
+        .header("x-confirm-token", token)
+        .json(body)
+        .send()
+        .await
+        .unwrap();
+    let status = response.status();
+    (status, response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn restores_wait_for_the_confirmation_token() {
+    let server = server_with_leaves(&["search"]).await;
+    let (_, exported) = server.admin_get("/config").await;
+    let mut replacement = exported.clone();
+    replacement["leaf_mcps"] = json!({});
+    let request = json!({ "config": replacement, "reason": "start over" });
+
+    let (status, body) = server
+        .admin_json(Method::POST, "/config/restore", &request)
+        .await;
+    assert_eq!(status, StatusCode::PRECONDITION_REQUIRED, "{}", body);
+    assert_eq!(body["error"]["kind"], "confirmation_required");
+    let challenge = &body["confirmation"];
+    assert_eq!(challenge["action"], "restore");
+    assert_eq!(challenge["path"], "/config/restore");
+    assert_eq!(server.saved_config().leaf_mcps.len(), 1);
+
+    let token = challenge["token"].as_str().unwrap();
+    let (status, body) = repeat(&server, Method::POST, "/config/restore", &request, token).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(server.saved_config().leaf_mcps.is_empty());
+
+    // The challenge, its confirmation and the restore share a correlation id
+    let correlation_id = challenge["correlation_id"].as_str().unwrap();
+    let entries = server.audit_entries().await;
+    let confirmed = confirmations(&entries);
+    assert_eq!(confirmed.len(), 2);
+    assert_eq!(confirmed[0].details["outcome"], "challenged");
+    assert_eq!(confirmed[1].details["outcome"], "confirmed");
+    assert!(
+        confirmed
+            .iter()
+            .all(|entry| entry.correlation_id.as_deref() == Some(correlation_id))
+    );
+    let restored = entries.last().unwrap();
+    assert_eq!(restored.reason.as_deref(), Some("start over"));
+    assert_eq!(restored.correlation_id.as_deref(), Some(correlation_id));
+}
+
+#[tokio::test]
+async fn tokens_are_bound_to_one_request_and_used_once() {
+    let server = server_with_leaves(&["search", "files"]).await;
+    let (status, body) = server
+        .admin_json(
+            Method::PUT,
+            "/policy/confirmation",
+            &json!({
+                "policy": { "actions": ["delete_leaf_mcp"], "token_ttl_secs": 120 },
+                "reason": null
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let (status, body) = server
+        .admin_json(Method::DELETE, "/leaf/search", &json!({}))
+        .await;
+    assert_eq!(status, StatusCode::PRECONDITION_REQUIRED, "{}", body);
+    assert_eq!(body["confirmation"]["entities"], json!(["search"]));
+    let token = body["confirmation"]["token"].as_str().unwrap().to_string();
+
+    // Not for another leaf MCP, nor with another body
+    let (status, body) = repeat(&server, Method::DELETE, "/leaf/files", &json!({}), &token).await;
+    assert_eq!(status, StatusCode::PRECONDITION_REQUIRED);
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("issued for another request")
+    );
+    let other = json!({ "reason": "cleanup" });
+    let (status, _) = repeat(&server, Method::DELETE, "/leaf/search", &other, &token).await;
+    assert_eq!(status, StatusCode::PRECONDITION_REQUIRED);
+    assert_eq!(server.saved_config().leaf_mcps.len(), 2);
+
+    let (status, body) = repeat(&server, Method::DELETE, "/leaf/search", &json!({}), &token).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, body) = repeat(&server, Method::DELETE, "/leaf/search", &json!({}), &token).await;
+    assert_eq!(status, StatusCode::PRECONDITION_REQUIRED);
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("already used")
+    );
+
+    // Tokens expire after the policy's TTL
+    server
+        .admin_json(
+            Method::PUT,
+            "/policy/confirmation",
+            &json!({
+                "policy": { "actions": ["delete_leaf_mcp"], "token_ttl_secs": 1 },
+                "reason": null
+            }),
+        )
+        .await;
+    let (_, body) = server
+        .admin_json(Method::DELETE, "/leaf/files", &json!({}))
+        .await;
+    let token = body["confirmation"]["token"].as_str().unwrap().to_string();
+    tokio::time::sleep(std::time::Duration::from_millis(2100)).await;
+    let (status, body) = repeat(&server, Method::DELETE, "/leaf/files", &json!({}), &token).await;
+    assert_eq!(status, StatusCode::PRECONDITION_REQUIRED);
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("expired")
+    );
+    assert!(server.saved_config().leaf_mcps.contains_key("files"));
+}
+
+#[tokio::test]
+async fn bulk_delete_dry_runs_are_not_challenged() {
+    let server = server_with_leaves(&["search", "files"]).await;
+    let (status, dry_run) = server
+        .admin_json(
+            Method::POST,
+            "/leaf/bulk_delete",
+            &json!({ "ids": ["search", "files"] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", dry_run);
+    let plan_token = dry_run["plan"]["confirmation_token"].as_str().unwrap();
+
+    let request = json!({ "ids": ["search", "files"], "confirm": plan_token });
+    let (status, body) = server
+        .admin_json(Method::POST, "/leaf/bulk_delete", &request)
+        .await;
+    assert_eq!(status, StatusCode::PRECONDITION_REQUIRED, "{}", body);
+    assert_eq!(body["confirmation"]["action"], "bulk_delete");
+    assert_eq!(body["confirmation"]["entities"], json!(["search", "files"]));
+    let (status, body) = server
+        .admin_confirmed(Method::POST, "/leaf/bulk_delete", &request)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(server.saved_config().leaf_mcps.is_empty());
+
+    // The deletions continue the challenge's correlation id
+    let correlation_id = body["correlation_id"].as_str().unwrap();
+    let entries = server.audit_entries().await;
+    assert_eq!(
+        entries
+            .iter()
+            .filter(|entry| entry.correlation_id.as_deref() == Some(correlation_id))
+            .count(),
+        4
+    );
+}
+
+#[tokio::test]
+async fn the_policy_can_be_changed() {
+    let server = server_with_leaves(&[]).await;
+    let (status, body) = server.admin_get("/policy/confirmation").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["policy"],
+        json!({ "actions": ["restore", "bulk_delete"], "token_ttl_secs": 120 })
+    );
+
+    let (status, body) = server
+        .admin_json(
+            Method::PUT,
+            "/policy/confirmation",
+            &json!({ "policy": { "actions": [], "token_ttl_secs": 0 }, "reason": null }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+
+    let (status, body) = server
+        .admin_json(
+            Method::PUT,
+            "/policy/confirmation",
+            &json!({
+                "policy": { "actions": ["delete_agent"], "token_ttl_secs": 30 },
+                "reason": "agents are expensive to set up"
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(server.saved_config().confirmation.token_ttl_secs, 30);
+
+    let (status, _) = server
+        .admin_json(
+            Method::POST,
+            "/agent",
+            &json!({ "agent_id": "writer", "allowed_mcp_ids": [] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = server
+        .admin_json(Method::DELETE, "/agent/writer", &json!({}))
+        .await;
+    assert_eq!(status, StatusCode::PRECONDITION_REQUIRED);
+    let (status, body) = server
+        .admin_confirmed(Method::DELETE, "/agent/writer", &json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(server.saved_config().agents.is_empty());
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use common::leaf_mcp;
+use mception_server::core::{AuditAction, AuditTarget, LeafMcpConfig, McpTransport};
+use mception_server::services::ConfigService;
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+fn service(dir: &Path) -> ConfigService {
+    ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    )
+}
+
+fn leaf(id: &str) -> LeafMcpConfig {
+    LeafMcpConfig {
+        reachable_by_agent: true,
+        ..leaf_mcp(McpTransport::Https {
+            url: format!("https://{}.example.com", id),
+            headers: None,
+        })
+    }
+}
+
+async fn service_with_leafs(ids: &[&str]) -> (PathBuf, ConfigService) {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let service = service(&dir);
+    for id in ids {
+        service
+            .create_leaf_mcp(Some(id.to_string()), leaf(id), None, None)
+            .await
+            .unwrap();
+    }
+    (dir, service)
+}
+
+#[tokio::test]
+async fn audited_mutations_are_consistent() {
+    let (_, service) = service_with_leafs(&["alpha", "beta"]).await;
+    service.delete_leaf_mcp("beta", None, None).await.unwrap();
+
+    let report = service.check_consistency().await.unwrap();
+    assert!(report.consistent, "{:?}", report);
+    assert!(report.newest_change.is_some());
+}
+
+#[tokio::test]
+async fn stale_configuration_is_reported_until_acknowledged() {
+    let (dir, service) = service_with_leafs(&["alpha", "beta"]).await;
+
+    // Replace the configuration with an older one behind the server's back
+    let path = dir.join("config.json");
+    let mut config: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    config["leaf_mcps"].as_object_mut().unwrap().remove("beta");
+    config["metadata"]["last_modified"] = json!(chrono::Utc::now() - chrono::Duration::hours(1));
+    std::fs::write(&path, config.to_string()).unwrap();
+
+    let service = {
+        drop(service);
+        let service = self::service(&dir);
+        service.load_configuration().await.unwrap();
+        service
+    };
+    let report = service.check_consistency().await.unwrap();
+    assert!(!report.consistent);
+    assert_eq!(report.gap.len(), 2);
+    assert!(matches!(report.gap[1].target, AuditTarget::LeafMcp { ref id } if id == "beta"));
+
+    // The same gap is recorded once, however often the server starts
+    assert!(service.record_consistency_gap(&report).await.unwrap());
+    let again = service.check_consistency().await.unwrap();
+    assert_eq!(again.gap.len(), 2);
+    assert!(!service.record_consistency_gap(&again).await.unwrap());
+
+    let acknowledged = service
+        .acknowledge_consistency_gap(Some("admin".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(acknowledged.gap.len(), 2);
+    let report = service.check_consistency().await.unwrap();
+    assert!(report.consistent);
+    assert!(report.marker_id.is_some());
+}
+
+#[tokio::test]
+async fn restore_marks_the_discontinuity() {
+    let (_, service) = service_with_leafs(&["alpha"]).await;
+    service.save_configuration().await.unwrap();
+    service.backup_configuration().await.unwrap();
+    let backup = service.list_backups().await.unwrap().remove(0).name;
+    service
+        .create_leaf_mcp(Some("beta".to_string()), leaf("beta"), None, None)
+        .await
+        .unwrap();
+
+    service
+        .restore_backup(&backup, Some("admin".to_string()), None)
+        .await
+        .unwrap();
+
+    let entries = service.get_audit_logs().await.unwrap();
+    let marker = entries
+        .iter()
+        .position(|entry| matches!(entry.action, AuditAction::Discontinuity))
+        .expect("restore writes a marker");
+    assert_eq!(entries[marker].details["discontinuity"], "backup_restore");
+    assert_eq!(entries[marker].details["backup"], backup.as_str());
+    assert!(matches!(
+        (&entries[marker + 1].action, &entries[marker + 1].target),
+        (AuditAction::Update, AuditTarget::Server)
+    ));
+
+    let report = service.check_consistency().await.unwrap();
+    assert!(report.consistent);
+    assert_eq!(
+        report.marker_id.as_deref(),
+        Some(entries[marker].id.as_str())
+    );
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use common::temp_dir;
+use mception_server::core::{MceptionError, ValidationError};
+use mception_server::services::ConfigService;
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use std::path::Path;
+use std::sync::Arc;
+
+fn service(dir: &Path) -> Arc<ConfigService> {
+    Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ))
+}
+
+/// Agents created in order, each allowed to use the ones given
+async fn agents(service: &ConfigService, agents: &[(&str, &[&str])]) {
+    for (agent_id, allowed) in agents {
+        service
+            .create_agent(
+                Some(agent_id.to_string()),
+                None,
+                allowed.iter().map(|id| id.to_string()).collect(),
+                None,
+            )
+            .await
+            .unwrap();
+    }
+}
+
+fn chain(result: Result<(), MceptionError>) -> String {
+    match result {
+        Err(MceptionError::Validation(ValidationError::CircularReference(chain))) => chain,
+        other => panic!("expected a circular reference, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn self_reference_is_refused() {
+    let dir = temp_dir();
+    let service = service(&dir);
+    agents(&service, &[("a", &[])]).await;
+
+    let result = service.add_agent_allowed_mcp("a", "a", None, None).await;
+    assert_eq!(chain(result), "a → a");
+    assert!(
+        service
+            .get_agent("a", None)
+            .await
+            .unwrap()
+            .allowed_mcp_ids
+            .is_empty()
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn two_agent_cycle_is_refused() {
+    let dir = temp_dir();
+    let service = service(&dir);
+    agents(&service, &[("a", &[]), ("b", &["a"])]).await;
+
+    let result = service.add_agent_allowed_mcp("a", "b", None, None).await;
+    assert_eq!(chain(result), "a → b → a");
+
+    // Also when the allowed MCPs are replaced by an update
+    let result = service
+        .update_agent(
+            "a",
+            serde_json::json!({ "allowed_mcp_ids": ["b"] }),
+            None,
+            None,
+        )
+        .await;
+    assert_eq!(chain(result), "a → b → a");
+    assert!(
+        service
+            .get_agent("a", None)
+            .await
+            .unwrap()
+            .allowed_mcp_ids
+            .is_empty()
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn longer_chains_are_followed() {
+    let dir = temp_dir();
+    let service = service(&dir);
+    agents(
+        &service,
+        &[("d", &[]), ("c", &["d"]), ("b", &["c"]), ("a", &["b"])],
+    )
+    .await;
+
+    let result = service.add_agent_allowed_mcp("d", "a", None, None).await;
+    assert_eq!(chain(result), "d → a → b → c → d");
+
+    // Sharing an agent is not a cycle
+    service
+        .add_agent_allowed_mcp("a", "d", None, None)
+        .await
+        .unwrap();
+    agents(&service, &[("e", &["a", "c"])]).await;
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn loaded_cycles_are_reported() {
+    let dir = temp_dir();
+    let service = service(&dir);
+    agents(
+        &service,
+        &[("c", &[]), ("b", &["c"]), ("a", &["b"]), ("x", &[])],
+    )
+    .await;
+
+    // Edited by hand: c → a closes a cycle, x references itself
+    let mut config = service.get_configuration().await;
+    config.agents.get_mut("c").unwrap().allowed_mcp_ids = vec!["a".to_string()];
+    config.agents.get_mut("x").unwrap().allowed_mcp_ids = vec!["x".to_string()];
+    std::fs::write(
+        dir.join("config.json"),
+        serde_json::to_string(&config).unwrap(),
+    )
+    .unwrap();
+
+    let reloaded = self::service(&dir);
+    reloaded.load_configuration().await.unwrap();
+    assert_eq!(
+        reloaded.validate_configuration().await,
+        vec![vec!["a", "b", "c", "a"], vec!["x", "x"],]
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use common::{TestServer, answer};
+use mception_server::core::{AuditAction, REDACTED};
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+use std::time::Duration;
+
+async fn server_with_echo_leaf() -> TestServer {
+    let server = TestServer::start().await;
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({ "id": "echo", "config": {
+                "transport": { "type": "builtin", "kind": "echo" },
+                "is_local": false,
+                "reachable_by_agent": false,
+                "config": {}
+            }}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    server
+}
+
+async fn echo(server: &TestServer, text: &str) -> Value {
+    let (status, body) = answer(server.request(Method::POST, "/leaf/echo/forwarding").json(
+        &json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": {
+                "name": "echo",
+                "arguments": { "text": text, "api_key": "sk-live-123" }
+            }
+        }),
+    ))
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    body
+}
+
+async fn capture(server: &TestServer) -> (StatusCode, Value) {
+    server.admin_get("/leaf/echo/debug/capture").await
+}
+
+#[tokio::test]
+async fn forwarded_payloads_are_captured_redacted_while_enabled() {
+    let server = server_with_echo_leaf().await;
+    echo(&server, "before the capture").await;
+
+    let (status, enabled) = server
+        .admin_json(Method::POST, "/leaf/echo/debug?duration=5m", &Value::Null)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", enabled);
+    echo(&server, "captured").await;
+
+    let (status, body) = capture(&server).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let capture = &body["capture"];
+    assert_eq!(capture["session_id"], enabled["session_id"]);
+    assert_eq!(capture["enabled"], true);
+    assert_eq!(capture["max_bytes"], 4096);
+    let payloads = capture["payloads"].as_array().unwrap();
+    let directions: Vec<&Value> = payloads
+        .iter()
+        .map(|payload| &payload["direction"])
+        .collect();
+    assert_eq!(directions, [&json!("request"), &json!("response")]);
+
+    let request: Value = serde_json::from_str(payloads[0]["body"].as_str().unwrap()).unwrap();
+    assert_eq!(request["params"]["arguments"]["text"], "captured");
+    assert_eq!(request["params"]["arguments"]["api_key"], REDACTED);
+    assert!(
+        !payloads[0]["body"]
+            .as_str()
+            .unwrap()
+            .contains("sk-live-123")
+    );
+    assert_eq!(payloads[0]["status"], Value::Null);
+    assert_eq!(payloads[1]["status"], 200);
+    assert!(payloads[1]["body"].as_str().unwrap().contains("captured"));
+
+    // Only what was forwarded while enabled
+    assert!(!capture.to_string().contains("before the capture"));
+}
+
+#[tokio::test]
+async fn payloads_are_truncated_to_max_bytes() {
+    let server = server_with_echo_leaf().await;
+    let (status, _) = server
+        .admin_json(Method::POST, "/leaf/echo/debug?max_bytes=32", &Value::Null)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    echo(&server, &"x".repeat(100)).await;
+
+    let (_, body) = capture(&server).await;
+    for payload in body["capture"]["payloads"].as_array().unwrap() {
+        assert_eq!(payload["truncated"], true, "{}", payload);
+        assert_eq!(payload["body"].as_str().unwrap().len(), 32);
+        assert!(payload["size_bytes"].as_u64().unwrap() > 100);
+    }
+
+    for query in [
+        "max_bytes=0",
+        "max_bytes=65537",
+        "duration=2h",
+        "duration=soon",
+    ] {
+        let (status, body) = server
+            .admin_json(
+                Method::POST,
+                &format!("/leaf/echo/debug?{}", query),
+                &Value::Null,
+            )
+            .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}: {}", query, body);
+    }
+    let (status, _) = server
+        .admin_json(Method::POST, "/leaf/missing/debug", &Value::Null)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn capture_ends_with_its_window_and_disabling_discards_it() {
+    let server = server_with_echo_leaf().await;
+    let (status, _) = server
+        .admin_json(Method::POST, "/leaf/echo/debug?duration=1s", &Value::Null)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    echo(&server, "inside the window").await;
+    tokio::time::sleep(Duration::from_millis(1300)).await;
+    echo(&server, "after the window").await;
+
+    // The expired capture is kept for reading, without later payloads
+    let (_, body) = capture(&server).await;
+    assert_eq!(body["capture"]["enabled"], false);
+    let payloads = body["capture"]["payloads"].to_string();
+    assert!(payloads.contains("inside the window"));
+    assert!(!payloads.contains("after the window"));
+
+    let (status, body) = answer(server.admin(Method::DELETE, "/leaf/echo/debug")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["was_active"], false);
+    let (status, _) = capture(&server).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let audited: Vec<(AuditAction, Option<String>, Value)> = server
+        .audit_entries()
+        .await
+        .into_iter()
+        .filter(|entry| {
+            matches!(
+                entry.action,
+                AuditAction::EnableDebugCapture | AuditAction::DisableDebugCapture
+            )
+        })
+        .map(|entry| (entry.action, entry.actor, entry.details["expired"].clone()))
+        .collect();
+    assert_eq!(audited.len(), 3, "{:?}", audited);
+    assert!(matches!(audited[0].0, AuditAction::EnableDebugCapture));
+    assert!(matches!(audited[1].0, AuditAction::DisableDebugCapture));
+    assert_eq!(
+        (audited[1].1.as_deref(), &audited[1].2),
+        (Some("system"), &json!(true))
+    );
+    assert_eq!(audited[2].2, json!(false));
+}
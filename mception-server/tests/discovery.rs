@@ -0,0 +1,123 @@
+use mception_server::core::McpTransport;
+use mception_server::services::ConfigService;
+use mception_server::services::discovery::{self, DISCOVERED_TAG};
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use std::sync::Arc;
+
+const CLAUDE: &str = r#"{
+  "mcpServers": {
+    "filesystem": {
+      "command": "npx",
+      "args": ["-y", "@modelcontextprotocol/server-filesystem", "/home/me"],
+      "env": { "DEBUG": "1" }
+    },
+    "remote": { "url": "https://mcp.example.com/" },
+    "broken": { "args": ["no-command"] }
+  }
+}"#;
+
+const VSCODE_SETTINGS: &str = r#"{
+  // Editor settings
+  "editor.fontSize": 14,
+  "mcp.servers": {
+    /* the same filesystem server as in Claude */
+    "fs": {
+      "type": "stdio",
+      "command": "npx",
+      "args": ["-y", "@modelcontextprotocol/server-filesystem", "/home/me"],
+    },
+    "docs": { "type": "http", "url": "https://mcp.example.com" },
+  },
+}"#;
+
+fn service() -> Arc<ConfigService> {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ))
+}
+
+#[test]
+fn converts_client_configs() {
+    let discovery = discovery::parse("claude_desktop_config.json", CLAUDE).unwrap();
+    assert_eq!(discovery.candidates.len(), 2);
+    assert_eq!(discovery.skipped.len(), 1);
+    assert_eq!(discovery.skipped[0].name, "broken");
+
+    let filesystem = &discovery.candidates[0].config;
+    assert_eq!(filesystem.name.as_deref(), Some("filesystem"));
+    assert_eq!(filesystem.config["tags"][0], DISCOVERED_TAG);
+    match &filesystem.transport {
+        McpTransport::Stdio {
+            command, args, env, ..
+        } => {
+            assert_eq!(command, "npx");
+            assert_eq!(args.len(), 3);
+            assert_eq!(env.as_ref().unwrap()["DEBUG"], "1");
+        }
+        other => panic!("unexpected transport {:?}", other),
+    }
+    assert!(matches!(
+        &discovery.candidates[1].config.transport,
+        McpTransport::Https { url, .. } if url == "https://mcp.example.com/"
+    ));
+
+    // VS Code settings with comments and trailing commas
+    let discovery = discovery::parse("settings.json", VSCODE_SETTINGS).unwrap();
+    let names: Vec<_> = discovery
+        .candidates
+        .iter()
+        .map(|c| c.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["docs", "fs"]);
+
+    assert!(discovery::parse("mcp.json", "{ not json").is_err());
+    assert!(
+        discovery::parse("settings.json", r#"{ "editor.fontSize": 14 }"#)
+            .unwrap()
+            .candidates
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn registers_each_server_once() {
+    let service = service();
+
+    let mut found = discovery::parse("claude_desktop_config.json", CLAUDE).unwrap();
+    let vscode = discovery::parse("settings.json", VSCODE_SETTINGS).unwrap();
+    found.candidates.extend(vscode.candidates);
+    let found = service.check_discovered(found).await;
+    // The VS Code entries repeat the Claude ones
+    assert_eq!(found.new_candidates().count(), 2);
+
+    let ids = service
+        .register_discovered(found.clone(), Some("admin".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(ids, vec!["filesystem", "remote"]);
+    let leaf = service.get_leaf_mcp("filesystem", None).await.unwrap();
+    assert_eq!(leaf.config["tags"][0], DISCOVERED_TAG);
+
+    // Discovering again finds nothing new
+    let again = service.check_discovered(found).await;
+    assert_eq!(again.new_candidates().count(), 0);
+    assert_eq!(
+        again.candidates[0].duplicate_of.as_deref(),
+        Some("filesystem")
+    );
+    assert!(
+        service
+            .register_discovered(again, None)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(service.list_leaf_mcps().await.unwrap().len(), 2);
+}
@@ -0,0 +1,298 @@
+mod common;
+
+use common::{TestServer, temp_dir};
+use reqwest::Method;
+use serde_json::{Value, json};
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// A port no server answers on
+fn closed_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Run `mception-server doctor` against the storage in `dir` and a server
+/// expected at `port`
+fn doctor(dir: &Path, port: u16, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_mception-server"))
+        .arg("--config")
+        .arg(dir.join("config.json"))
+        .arg("--audit-log")
+        .arg(dir.join("audit.log"))
+        .arg("--journal")
+        .arg(dir.join("config.journal"))
+        .args(["--host", "127.0.0.1", "--port", &port.to_string()])
+        .args(args)
+        .args(["doctor", "--format", "json"])
+        .output()
+        .unwrap()
+}
+
+fn report(output: &Output) -> Value {
+    serde_json::from_slice(&output.stdout).unwrap_or_else(|e| {
+        panic!(
+            "{}: {}{}",
+            e,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        )
+    })
+}
+
+/// Status and detail of the check named `name`
+fn check<'a>(report: &'a Value, name: &str) -> (&'a str, &'a str) {
+    let check = report["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|check| check["name"] == name)
+        .unwrap_or_else(|| panic!("no {} check in {}", name, report));
+    (
+        check["status"].as_str().unwrap(),
+        check["detail"].as_str().unwrap(),
+    )
+}
+
+fn metadata(schema_version: u32) -> Value {
+    json!({
+        "version": "0.1.0",
+        "schema_version": schema_version,
+        "created_at": "2025-01-01T00:00:00Z",
+        "last_modified": "2025-01-01T00:00:00Z"
+    })
+}
+
+fn setting<'a>(report: &'a Value, name: &str) -> (&'a str, &'a str) {
+    let setting = report["settings"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|setting| setting["name"] == name)
+        .unwrap();
+    (
+        setting["value"].as_str().unwrap(),
+        setting["source"].as_str().unwrap(),
+    )
+}
+
+#[test]
+fn files_to_be_created_are_warnings_and_nothing_is_created() {
+    let dir = temp_dir();
+    let output = doctor(&dir, closed_port(), &[]);
+    let report = report(&output);
+
+    assert_eq!(output.status.code(), Some(1), "{}", report);
+    assert_eq!(report["worst"], "warn");
+    for name in ["config_file", "journal", "audit_log"] {
+        let (status, detail) = check(&report, name);
+        assert_eq!(status, "warn", "{}", name);
+        assert!(
+            detail.ends_with("does not exist yet and will be created"),
+            "{}",
+            detail
+        );
+    }
+    assert_eq!(check(&report, "running_server").0, "skip");
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+    // Where each setting came from
+    assert_eq!(setting(&report, "port").1, "command line");
+    assert_eq!(setting(&report, "backup_mode"), ("full", "default"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn a_newer_schema_fails_and_a_corrupt_audit_log_warns() {
+    let dir = temp_dir();
+    std::fs::write(
+        dir.join("config.json"),
+        json!({ "leaf_mcps": {}, "agents": {}, "metadata": metadata(999) }).to_string(),
+    )
+    .unwrap();
+    std::fs::write(dir.join("audit.log"), "not an audit entry\n").unwrap();
+    let output = doctor(&dir, closed_port(), &["--enable-fault-injection"]);
+    let report = report(&output);
+
+    assert_eq!(output.status.code(), Some(2), "{}", report);
+    assert_eq!(report["worst"], "fail");
+    let (status, detail) = check(&report, "config_schema");
+    assert_eq!(status, "fail");
+    assert!(
+        detail.contains("newer than this server supports"),
+        "{}",
+        detail
+    );
+    assert_eq!(check(&report, "config_file").0, "pass");
+    assert_eq!(check(&report, "audit_integrity").0, "warn");
+    assert_eq!(check(&report, "fault_injection").0, "warn");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn each_leaf_mcp_is_checked() {
+    let dir = temp_dir();
+    let leaf = |id: &str, transport: Value| {
+        json!({
+            "id": id,
+            "transport": transport,
+            "is_local": false,
+            "reachable_by_agent": false,
+            "config": {}
+        })
+    };
+    std::fs::write(
+        dir.join("config.json"),
+        json!({
+            "leaf_mcps": {
+                "echo": leaf("echo", json!({ "type": "builtin", "kind": "echo" })),
+                "shell": leaf("shell", json!({ "type": "stdio", "command": "sh", "args": [] })),
+                "missing": leaf("missing", json!({ "type": "stdio", "command": "no-such-command-mception", "args": [] })),
+                "plain": leaf("plain", json!({ "type": "https", "url": "http://mcp.example.com" })),
+                "unsandboxable": leaf("unsandboxable", json!({
+                    "type": "stdio",
+                    "command": "sh",
+                    "args": [],
+                    "allowed_paths": ["relative/path"]
+                }))
+            },
+            "agents": {},
+            "metadata": metadata(1)
+        })
+        .to_string(),
+    )
+    .unwrap();
+    let report = report(&doctor(&dir, closed_port(), &[]));
+
+    assert_eq!(check(&report, "config_schema").0, "pass");
+    assert_eq!(check(&report, "leaf:echo").0, "pass");
+    assert_eq!(check(&report, "leaf:shell"), ("pass", "stdio: sh"));
+    assert_eq!(
+        check(&report, "leaf:missing"),
+        ("fail", "command 'no-such-command-mception' not found")
+    );
+    assert_eq!(
+        check(&report, "leaf:plain"),
+        ("warn", "http://mcp.example.com does not use https")
+    );
+    let (status, detail) = check(&report, "leaf:unsandboxable");
+    assert_eq!(status, "fail");
+    assert!(detail.starts_with("sandbox: "), "{}", detail);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn a_running_server_is_compared_with_the_flags() {
+    let server = TestServer::start().await;
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({ "id": "echo", "config": {
+                "transport": { "type": "builtin", "kind": "echo" },
+                "is_local": false,
+                "reachable_by_agent": false,
+                "config": {}
+            }}),
+        )
+        .await;
+    assert!(status.is_success(), "{}", body);
+    let port: u16 = server.url.rsplit(':').next().unwrap().parse().unwrap();
+
+    // The same files as the server
+    let dir = server.dir.clone();
+    let matched = tokio::task::spawn_blocking(move || report(&doctor(&dir, port, &[])))
+        .await
+        .unwrap();
+    let (status, detail) = check(&matched, "running_server");
+    assert_eq!(status, "pass");
+    assert!(detail.starts_with("version "), "{}", detail);
+    assert_eq!(check(&matched, "server_config_path").0, "pass");
+    let revision = server.saved_config().metadata.revision;
+    assert_eq!(
+        check(&matched, "server_revision"),
+        (
+            "pass",
+            format!("server and file are at revision {}", revision).as_str()
+        )
+    );
+
+    // Other files than the server's
+    let other = temp_dir();
+    std::fs::copy(&server.config_path, other.join("config.json")).unwrap();
+    let config_path = other.join("config.json");
+    let mut config: Value =
+        serde_json::from_str(&std::fs::read_to_string(&config_path).unwrap()).unwrap();
+    config["metadata"]["revision"] = json!(revision + 5);
+    std::fs::write(&config_path, config.to_string()).unwrap();
+    let other_dir = other.clone();
+    let mismatched = tokio::task::spawn_blocking(move || report(&doctor(&other_dir, port, &[])))
+        .await
+        .unwrap();
+    let (status, detail) = check(&mismatched, "server_config_path");
+    assert_eq!(status, "fail");
+    assert!(detail.contains("but --config points at"), "{}", detail);
+    assert_eq!(check(&mismatched, "server_revision").0, "warn");
+    std::fs::remove_dir_all(other).unwrap();
+}
+
+#[tokio::test]
+async fn a_relative_config_is_reported_absolute() {
+    // The server and doctor both run in `dir` with the default --config
+    let dir = temp_dir();
+    let port = closed_port();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mception-server"))
+        .current_dir(&dir)
+        .args(["--host", "127.0.0.1", "--port", &port.to_string()])
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let url = format!("http://127.0.0.1:{}/admin/status", port);
+    let mut status = None;
+    for _ in 0..100 {
+        if let Ok(response) = reqwest::get(&url).await {
+            status = Some(response.json::<Value>().await.unwrap());
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    let Some(status) = status else {
+        child.kill().unwrap();
+        child.wait().unwrap();
+        panic!("the server didn't start");
+    };
+
+    let config_path = Path::new(status["config_path"].as_str().unwrap()).to_path_buf();
+    assert!(config_path.is_absolute(), "{}", config_path.display());
+    assert_eq!(
+        config_path.canonicalize().unwrap(),
+        dir.join("config.json").canonicalize().unwrap()
+    );
+
+    let doctor_dir = dir.clone();
+    let output = tokio::task::spawn_blocking(move || {
+        Command::new(env!("CARGO_BIN_EXE_mception-server"))
+            .current_dir(doctor_dir)
+            .args(["--host", "127.0.0.1", "--port", &port.to_string()])
+            .args(["doctor", "--format", "json"])
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap();
+    let report = report(&output);
+    assert_eq!(
+        check(&report, "server_config_path"),
+        (
+            "pass",
+            format!("server uses {}", config_path.display()).as_str()
+        )
+    );
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,298 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{TestServer, answer};
+use mception_server::core::AuditAction;
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+
+const AGENT_TOKEN: &str = "agent-secret";
+
+/// A server with the agent `writer`, whose token expires `expires_in` from
+/// now, and the leaf MCP `search`
+async fn serve(expires_in: Duration) -> TestServer {
+    serve_with(TestServer::builder(), expires_in).await
+}
+
+async fn serve_with(builder: common::TestServerBuilder, expires_in: Duration) -> TestServer {
+    let server = builder.admin_token("admin-secret").start().await;
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({
+                "id": "search",
+                "config": {
+                    "transport": { "type": "builtin", "kind": "echo" },
+                    "is_local": false,
+                    "reachable_by_agent": false,
+                    "config": {}
+                },
+                "reason": null
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, _) = server
+        .admin_json(
+            Method::POST,
+            "/agent",
+            &json!({ "agent_id": "writer", "allowed_mcp_ids": [], "should_create": true }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    expire_in(&server, expires_in).await;
+    server
+}
+
+async fn expire_in(server: &TestServer, expires_in: Duration) {
+    let (status, _) = server
+        .admin_json(
+            Method::PUT,
+            "/agent/writer/config",
+            &json!({
+                "config": {
+                    "auth_token": AGENT_TOKEN,
+                    "auth_token_expires_at": Utc::now() + expires_in
+                },
+                "should_update": true
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+/// Grant `writer` the `search` MCP until `expires_in` from now
+async fn grant_for(server: &TestServer, expires_in: Duration) -> (StatusCode, Value) {
+    server
+        .admin_json(
+            Method::POST,
+            "/agent/writer/allowed_mcps",
+            &json!({
+                "mcp_id": "search",
+                "reason": null,
+                "expires_at": Utc::now() + expires_in
+            }),
+        )
+        .await
+}
+
+async fn agent_config(server: &TestServer) -> (StatusCode, Value) {
+    answer(
+        server
+            .request(Method::GET, "/agent/writer/config")
+            .bearer_auth(AGENT_TOKEN),
+    )
+    .await
+}
+
+async fn metric(server: &TestServer, name: &str) -> u64 {
+    let metrics = server
+        .request(Method::GET, "/metrics")
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(&format!("{} ", name)))
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn tokens_expiring_within_the_window_are_reported() {
+    let server = serve(Duration::days(30)).await;
+
+    let (status, config) = agent_config(&server).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(config["auth_token"]["expires_soon"], false);
+    let (_, expiring) = server.admin_get("/expiring").await;
+    assert_eq!(expiring["window_seconds"], 72 * 60 * 60);
+    assert_eq!(expiring["tokens"], json!([]));
+    assert_eq!(metric(&server, "mception_agent_tokens_expiring").await, 0);
+
+    expire_in(&server, Duration::hours(1)).await;
+    let (status, config) = agent_config(&server).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(config["auth_token"]["expires_soon"], true);
+    let (_, expiring) = server.admin_get("/expiring").await;
+    assert_eq!(expiring["tokens"][0]["agent_id"], "writer");
+    assert_eq!(expiring["tokens"][0]["expired"], false);
+    assert_eq!(metric(&server, "mception_agent_tokens_expiring").await, 1);
+    assert_eq!(metric(&server, "mception_agent_tokens_expired").await, 0);
+    let (_, agent) = server.admin_get("/agent/writer/config").await;
+    assert!(agent["auth_token_expires_at"].is_string());
+}
+
+#[tokio::test]
+async fn expired_tokens_are_refused() {
+    let server = serve(Duration::minutes(-1)).await;
+
+    let (status, _) = agent_config(&server).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, body) = answer(
+        server
+            .request(Method::POST, "/leaf/anything/forwarding")
+            .bearer_auth(AGENT_TOKEN)
+            .body(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(
+        body["error"]["detail"]
+            .as_str()
+            .unwrap()
+            .contains("expired")
+    );
+
+    let (_, expiring) = server.admin_get("/expiring").await;
+    assert_eq!(expiring["tokens"][0]["expired"], true);
+    assert_eq!(metric(&server, "mception_agent_tokens_expired").await, 1);
+
+    // Renewing the token lets the agent in again
+    expire_in(&server, Duration::days(30)).await;
+    let (status, _) = agent_config(&server).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn expiring_tokens_are_summarized_in_the_audit_log() {
+    let server = serve(Duration::days(30)).await;
+    assert!(server.service.audit_expiring().await.is_empty());
+
+    expire_in(&server, Duration::hours(1)).await;
+    grant_for(&server, Duration::hours(2)).await;
+    let expiring = server.service.audit_expiring().await;
+    assert_eq!(expiring.tokens.len(), 1);
+    assert_eq!(expiring.grants.len(), 1);
+
+    let summaries: Vec<_> = server
+        .audit_entries()
+        .await
+        .into_iter()
+        .filter(|entry| matches!(entry.action, AuditAction::ExpirySummary))
+        .collect();
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(summaries[0].details["tokens"][0]["agent_id"], "writer");
+    assert_eq!(summaries[0].details["grants"][0]["grant"], "search");
+    assert!(!summaries[0].details.to_string().contains(AGENT_TOKEN));
+}
+
+#[tokio::test]
+async fn grants_expiring_within_the_window_are_reported() {
+    let server = serve(Duration::days(30)).await;
+    let (status, body) = grant_for(&server, Duration::hours(1)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let (_, config) = agent_config(&server).await;
+    assert_eq!(config["mcps"]["search"]["expiry"]["expires_soon"], true);
+    let (_, expiring) = server.admin_get("/expiring").await;
+    assert_eq!(expiring["grants"][0]["agent_id"], "writer");
+    assert_eq!(expiring["grants"][0]["grant"], "search");
+    assert_eq!(expiring["grants"][0]["expired"], false);
+    assert_eq!(metric(&server, "mception_agent_grants_expiring").await, 1);
+    let (_, agent) = server.admin_get("/agent/writer/config").await;
+    assert!(agent["grant_expiry"]["search"].is_string());
+
+    // Moving the expiry out of the window
+    let (status, body) = grant_for(&server, Duration::days(30)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (_, config) = agent_config(&server).await;
+    assert_eq!(config["mcps"]["search"]["expiry"]["expires_soon"], false);
+    let (_, expiring) = server.admin_get("/expiring").await;
+    assert_eq!(expiring["grants"], json!([]));
+}
+
+#[tokio::test]
+async fn expired_grants_allow_nothing() {
+    let server = serve(Duration::days(30)).await;
+    let (status, body) = grant_for(&server, Duration::minutes(-1)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let (status, config) = agent_config(&server).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(config["mcps"].get("search").is_none(), "{}", config);
+    let (_, expiring) = server.admin_get("/expiring").await;
+    assert_eq!(expiring["grants"][0]["expired"], true);
+    assert_eq!(metric(&server, "mception_agent_grants_expired").await, 1);
+
+    // The expiry is kept across restarts
+    let saved = server.saved_config();
+    assert!(saved.agents["writer"].grant_expiry["search"] < Utc::now());
+
+    // Granting it again for longer allows it again
+    let (status, body) = grant_for(&server, Duration::days(30)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (_, config) = agent_config(&server).await;
+    assert!(config["mcps"]["search"].is_object(), "{}", config);
+
+    // Granting it with the same expiry changes nothing
+    let expires_at = server.saved_config().agents["writer"].grant_expiry["search"];
+    let (status, _) = server
+        .admin_json(
+            Method::POST,
+            "/agent/writer/allowed_mcps",
+            &json!({ "mcp_id": "search", "reason": null, "expires_at": expires_at }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+/// A webhook receiver forwarding the events it's posted
+async fn webhook() -> (String, mpsc::UnboundedReceiver<Value>) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let router = axum::Router::new().route(
+        "/events",
+        axum::routing::post(move |axum::Json(event): axum::Json<Value>| {
+            let sender = sender.clone();
+            async move {
+                sender.send(event).unwrap();
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/events", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    (url, receiver)
+}
+
+#[tokio::test]
+async fn expiry_events_are_posted_to_the_webhook_once() {
+    let (url, mut events) = webhook().await;
+    let server = serve_with(
+        TestServer::builder().expiry_webhook(&url),
+        Duration::hours(1),
+    )
+    .await;
+    grant_for(&server, Duration::minutes(-1)).await;
+
+    let sent = server.service.check_expiry().await;
+    assert_eq!(sent.len(), 2);
+    let token = events.recv().await.unwrap();
+    assert_eq!(token["event"], "expiring");
+    assert_eq!(token["kind"], "token");
+    assert_eq!(token["agent_id"], "writer");
+    assert!(token.get("grant").is_none());
+    let grant = events.recv().await.unwrap();
+    assert_eq!(grant["event"], "expired");
+    assert_eq!(grant["kind"], "grant");
+    assert_eq!(grant["grant"], "search");
+
+    // Nothing new to tell
+    assert!(server.service.check_expiry().await.is_empty());
+
+    // The token expiring is told again
+    expire_in(&server, Duration::minutes(-1)).await;
+    let sent = server.service.check_expiry().await;
+    assert_eq!(sent.len(), 1);
+    let token = events.recv().await.unwrap();
+    assert_eq!(token["event"], "expired");
+    assert_eq!(token["kind"], "token");
+    assert!(!token.to_string().contains(AGENT_TOKEN));
+}
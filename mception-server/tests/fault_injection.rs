@@ -0,0 +1,186 @@
+mod common;
+
+use common::{TestServer, answer};
+use mception_server::core::AuditAction;
+use mception_server::services::fault_injection::FAULT_HEADER;
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+use std::time::{Duration, Instant};
+
+async fn server_with_echo_leaf(fault_injection: bool) -> TestServer {
+    let builder = TestServer::builder();
+    let builder = if fault_injection {
+        builder.fault_injection()
+    } else {
+        builder
+    };
+    let server = builder.start().await;
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({ "id": "echo", "config": {
+                "transport": { "type": "builtin", "kind": "echo" },
+                "is_local": false,
+                "reachable_by_agent": false,
+                "config": {}
+            }}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    server
+}
+
+/// Forward an echo call, returning the status, the fault header and the body
+async fn echo(server: &TestServer) -> (StatusCode, Option<String>, Value) {
+    let response = server
+        .request(Method::POST, "/leaf/echo/forwarding")
+        .json(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": { "name": "echo", "arguments": { "text": "hello" } }
+        }))
+        .send()
+        .await
+        .unwrap();
+    let status = response.status();
+    let fault = response
+        .headers()
+        .get(FAULT_HEADER)
+        .map(|value| value.to_str().unwrap().to_string());
+    (status, fault, response.json().await.unwrap())
+}
+
+async fn inject(server: &TestServer, faults: Value) -> (StatusCode, Value) {
+    server
+        .admin_json(Method::POST, "/leaf/echo/faults", &faults)
+        .await
+}
+
+#[tokio::test]
+async fn injected_errors_fail_requests_until_cleared() {
+    let server = server_with_echo_leaf(true).await;
+    let (status, body) = inject(
+        &server,
+        json!({ "error_rate": 1.0, "duration": "5m", "reason": "failover drill" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["faults"]["error_rate"], 1.0);
+
+    for _ in 0..2 {
+        let (status, fault, body) = echo(&server).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{}", body);
+        assert_eq!(fault.as_deref(), Some("error"));
+        assert_eq!(body["injected"], true);
+        assert_eq!(body["error"]["leaf_mcp_id"], "echo");
+    }
+    let (status, body) = server.admin_get("/leaf/echo/faults").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["faults"]["failed_requests"], 2);
+    assert_eq!(body["faults"]["delayed_requests"], 0);
+
+    let (status, body) = answer(server.admin(Method::DELETE, "/leaf/echo/faults")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["was_active"], true);
+    let (status, fault, body) = echo(&server).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(fault, None);
+    let (_, body) = server.admin_get("/leaf/echo/faults").await;
+    assert_eq!(body["faults"], Value::Null);
+
+    let audited: Vec<(String, Option<String>)> = server
+        .audit_entries()
+        .await
+        .into_iter()
+        .filter(|entry| {
+            matches!(
+                entry.action,
+                AuditAction::InjectFaults | AuditAction::ClearFaults
+            )
+        })
+        .map(|entry| (format!("{:?}", entry.action), entry.reason))
+        .collect();
+    assert_eq!(
+        audited,
+        [
+            (
+                "InjectFaults".to_string(),
+                Some("failover drill".to_string())
+            ),
+            ("ClearFaults".to_string(), None)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn injected_latency_delays_requests_that_still_succeed() {
+    let server = server_with_echo_leaf(true).await;
+    let (status, body) = inject(&server, json!({ "latency_ms": 300 })).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let started = Instant::now();
+    let (status, fault, body) = echo(&server).await;
+    assert!(started.elapsed() >= Duration::from_millis(300));
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(fault.as_deref(), Some("latency"));
+    assert_eq!(body["result"]["content"][0]["text"], "hello");
+
+    let (_, body) = server.admin_get("/leaf/echo/faults").await;
+    assert_eq!(body["faults"]["delayed_requests"], 1);
+    assert_eq!(body["faults"]["failed_requests"], 0);
+}
+
+#[tokio::test]
+async fn injections_end_with_their_window() {
+    let server = server_with_echo_leaf(true).await;
+    let (status, _) = inject(&server, json!({ "error_rate": 1.0, "duration": "1s" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(echo(&server).await.0, StatusCode::SERVICE_UNAVAILABLE);
+    tokio::time::sleep(Duration::from_millis(1300)).await;
+
+    let (status, fault, _) = echo(&server).await;
+    assert_eq!((status, fault), (StatusCode::OK, None));
+    let expired = server
+        .audit_entries()
+        .await
+        .into_iter()
+        .find(|entry| matches!(entry.action, AuditAction::ClearFaults))
+        .unwrap();
+    assert_eq!(expired.actor.as_deref(), Some("system"));
+    assert_eq!(expired.details["expired"], true);
+}
+
+#[tokio::test]
+async fn faults_out_of_range_are_refused() {
+    let server = server_with_echo_leaf(true).await;
+    for faults in [
+        json!({ "latency_ms": 60001 }),
+        json!({ "error_rate": 1.5 }),
+        json!({ "error_rate": -0.1 }),
+        json!({ "error_rate": 0.5, "duration": "2h" }),
+    ] {
+        let (status, body) = inject(&server, faults.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}: {}", faults, body);
+    }
+    let (status, _) = server
+        .admin_json(
+            Method::POST,
+            "/leaf/missing/faults",
+            &json!({ "error_rate": 0.5 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(echo(&server).await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn fault_injection_needs_to_be_enabled_at_startup() {
+    let server = server_with_echo_leaf(false).await;
+    let (status, _) = inject(&server, json!({ "error_rate": 1.0 })).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = server.admin_get("/leaf/echo/faults").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(echo(&server).await.0, StatusCode::OK);
+}
@@ -0,0 +1,173 @@
+mod common;
+
+use common::leaf_mcp;
+use mception_server::core::{BuiltinMcpKind, LeafMcpConfig, McpTransport};
+use mception_server::services::ConfigService;
+use mception_server::services::deadline::DEFAULT_LEAF_TIMEOUT;
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::{RouterOptions, build_router};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Serve the router on an ephemeral port with an `echo` built-in leaf MCP,
+/// returning the base URL
+async fn serve_with_echo_leaf() -> String {
+    serve_echo_leaf(DEFAULT_LEAF_TIMEOUT).await
+}
+
+/// Same as [`serve_with_echo_leaf`], bounding forwarded calls by `leaf_timeout`
+async fn serve_echo_leaf(leaf_timeout: Duration) -> String {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let service = Arc::new(
+        ConfigService::new(
+            Arc::new(FileConfigStorage::new(
+                dir.join("config.json").to_string_lossy(),
+            )),
+            Arc::new(FileAuditStorage::new(
+                dir.join("audit.log").to_string_lossy(),
+            )),
+        )
+        .with_default_leaf_timeout(leaf_timeout),
+    );
+    service
+        .create_leaf_mcp(
+            Some("echo".to_string()),
+            LeafMcpConfig {
+                id: "echo".to_string(),
+                ..leaf_mcp(McpTransport::Builtin {
+                    kind: BuiltinMcpKind::Echo,
+                })
+            },
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        axum::serve(listener, build_router(service, RouterOptions::default()))
+            .await
+            .unwrap();
+    });
+    url
+}
+
+fn call(tool: &str, arguments: Value) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "tools/call",
+        "params": { "name": tool, "arguments": arguments }
+    })
+}
+
+#[tokio::test]
+async fn forwards_to_builtin_leaf() {
+    let url = serve_with_echo_leaf().await;
+    let client = reqwest::Client::new();
+
+    let response: Value = client
+        .post(format!("{}/leaf/echo/forwarding", url))
+        .json(&call("echo", json!({ "text": "hello" })))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(response["result"]["content"][0]["text"], "hello");
+
+    let response: Value = client
+        .post(format!("{}/leaf/echo/forwarding", url))
+        .json(&call(
+            "fail_with",
+            json!({ "code": -32001, "message": "boom" }),
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(response["error"]["code"], -32001);
+    assert_eq!(response["error"]["message"], "boom");
+
+    let tools: Value = client
+        .get(format!("{}/admin/leaf/echo/tools", url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(tools["tools"].as_array().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn agent_deadline_cancels_slow_leaf_call() {
+    let url = serve_with_echo_leaf().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/leaf/echo/forwarding", url))
+        .header("x-mception-deadline-ms", "50")
+        .json(&call("sleep_ms", json!({ "ms": 5000 })))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::GATEWAY_TIMEOUT);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["bound"], "agent_deadline");
+}
+
+#[tokio::test]
+async fn leaf_timeout_bounds_forwarded_calls() {
+    let url = serve_echo_leaf(Duration::from_millis(50)).await;
+    let client = reqwest::Client::new();
+    let sleep = || {
+        client
+            .post(format!("{}/leaf/echo/forwarding", url))
+            .json(&call("sleep_ms", json!({ "ms": 300 })))
+            .send()
+    };
+
+    // The server-wide default applies to a leaf MCP without its own timeout
+    let response = sleep().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::GATEWAY_TIMEOUT);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "leaf_timeout");
+    assert_eq!(body["error"]["leaf_mcp_id"], "echo");
+    assert_eq!(body["bound"], "leaf_timeout");
+    assert_eq!(body["budget_ms"], 50);
+
+    let response = client
+        .put(format!("{}/admin/leaf/echo/config", url))
+        .json(&json!({ "config": { "timeout_ms": 5000 } }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let config: Value = client
+        .get(format!("{}/admin/leaf/echo/config", url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(config["timeout_ms"], 5000, "{}", config);
+
+    let response = sleep().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    let response = client
+        .put(format!("{}/admin/leaf/echo/config", url))
+        .json(&json!({ "config": { "timeout_ms": 0 } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
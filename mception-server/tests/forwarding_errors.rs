@@ -0,0 +1,132 @@
+mod common;
+
+use common::leaf_mcp;
+use mception_server::core::McpTransport;
+use mception_server::services::ConfigService;
+use mception_server::services::authorization::AGENT_HEADER;
+use mception_server::services::forwarding_error::{ForwardingError, ForwardingErrorCode};
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::{RouterOptions, build_router};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+/// A server with the leaf MCP `down`, whose upstream doesn't listen, and the
+/// agents `writer`, allowed to use it, and `reader`, which is not
+async fn serve() -> (Arc<ConfigService>, String) {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let service = Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ));
+
+    let port = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+    let leaf = leaf_mcp(McpTransport::Https {
+        url: format!("http://127.0.0.1:{}/mcp", port),
+        headers: None,
+    });
+    service
+        .create_leaf_mcp(Some("down".to_string()), leaf, None, None)
+        .await
+        .unwrap();
+    for (agent_id, allowed) in [("writer", vec!["down".to_string()]), ("reader", vec![])] {
+        service
+            .create_agent(Some(agent_id.to_string()), None, allowed, None)
+            .await
+            .unwrap();
+    }
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let router = build_router(service.clone(), RouterOptions::default());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    (service, url)
+}
+
+async fn forward(url: &str, path: &str, agent_id: Option<&str>) -> (u16, Value) {
+    let mut request = reqwest::Client::new()
+        .post(format!("{}{}", url, path))
+        .body(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    if let Some(agent_id) = agent_id {
+        request = request.header(AGENT_HEADER, agent_id);
+    }
+    let response = request.send().await.unwrap();
+    let status = response.status().as_u16();
+    (status, response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn dead_upstream_is_unreachable() {
+    let (_, url) = serve().await;
+
+    let (status, body) = forward(&url, "/leaf/down/forwarding", Some("writer")).await;
+    assert_eq!(status, 502);
+    assert_eq!(body["error"]["code"], "leaf_unreachable");
+    assert_eq!(body["error"]["retryable"], true);
+    assert_eq!(body["error"]["leaf_mcp_id"], "down");
+
+    // An agent that is registered but not connected is just as unreachable
+    let (status, body) = forward(&url, "/agent/reader/forwarding", None).await;
+    assert_eq!(status, 503);
+    assert_eq!(body["error"]["code"], "leaf_unreachable");
+    assert_eq!(body["error"]["leaf_mcp_id"], "reader");
+}
+
+#[tokio::test]
+async fn disallowed_mcps_are_not_allowed() {
+    let (_, url) = serve().await;
+
+    let (status, body) = forward(&url, "/leaf/down/forwarding", Some("reader")).await;
+    assert_eq!(status, 403);
+    assert_eq!(body["error"]["code"], "not_allowed");
+    assert_eq!(body["error"]["retryable"], false);
+    assert!(
+        body["error"]["detail"]
+            .as_str()
+            .unwrap()
+            .contains("'reader' may not use 'down'")
+    );
+
+    let (status, body) = forward(&url, "/leaf/down/forwarding", Some("stranger")).await;
+    assert_eq!(status, 403);
+    assert_eq!(body["error"]["code"], "not_allowed");
+
+    let (status, body) = forward(&url, "/agent/writer/forwarding", Some("reader")).await;
+    assert_eq!(status, 403);
+    assert_eq!(body["error"]["code"], "not_allowed");
+
+    let (status, body) = forward(&url, "/leaf/missing/forwarding", None).await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"]["code"], "not_allowed");
+}
+
+#[tokio::test]
+async fn draining_server_is_overloaded() {
+    let (service, url) = serve().await;
+    service.lifecycle().begin_draining();
+
+    for path in ["/leaf/down/forwarding", "/agent/writer/forwarding"] {
+        let (status, body) = forward(&url, path, None).await;
+        assert_eq!(status, 503);
+        assert_eq!(body["error"]["code"], "proxy_overloaded");
+        assert_eq!(body["error"]["retryable"], true);
+    }
+}
+
+#[test]
+fn json_rpc_errors_carry_the_code() {
+    let error = ForwardingError::new(ForwardingErrorCode::LeafTimeout, "slow", "too slow");
+    let response = error.json_rpc(json!(7));
+    assert_eq!(response["id"], 7);
+    assert_eq!(response["error"]["code"], -32011);
+    assert_eq!(response["error"]["message"], "too slow");
+    assert_eq!(response["error"]["data"]["code"], "leaf_timeout");
+    assert_eq!(response["error"]["data"]["retryable"], true);
+}
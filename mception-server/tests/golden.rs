@@ -0,0 +1,103 @@
+use mception_server::core::testing::{fixture_path, load_fixture};
+use mception_server::core::{AuditLogEntry, CONFIG_SCHEMA_VERSION, ServerConfig};
+use mception_server::services::ConfigService;
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Compare `actual` with the golden file `name`, or rewrite the golden file
+/// when `UPDATE_GOLDEN` is set. Intentional format changes have to update
+/// the golden files and add a migration.
+fn assert_golden(name: &str, actual: &str) {
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(fixture_path(name), actual).unwrap();
+        return;
+    }
+    let expected = load_fixture(name);
+    assert!(
+        expected == actual,
+        "{} differs from the serialized output, run with UPDATE_GOLDEN=1 if the change is intended\n--- expected\n{}\n--- actual\n{}",
+        name,
+        expected,
+        actual
+    );
+}
+
+fn round_trip_config(input: &str, golden: &str) {
+    let config: ServerConfig = serde_json::from_str(&load_fixture(input)).unwrap();
+    // Pretty-printed as the file storage writes it
+    let serialized = serde_json::to_string_pretty(&config).unwrap();
+    assert_golden(golden, &serialized);
+
+    // The golden output reads back to itself
+    let reread: ServerConfig = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(serde_json::to_string_pretty(&reread).unwrap(), serialized);
+}
+
+#[test]
+fn server_config_round_trips() {
+    round_trip_config(
+        "golden/server_config.json",
+        "golden/server_config.expected.json",
+    );
+}
+
+#[test]
+fn configs_from_before_schema_versions_round_trip() {
+    round_trip_config(
+        "golden/minimal_config.json",
+        "golden/minimal_config.expected.json",
+    );
+}
+
+#[test]
+fn golden_config_has_the_current_schema_version() {
+    let config: Value = serde_json::from_str(&load_fixture("golden/server_config.json")).unwrap();
+    assert_eq!(
+        config["metadata"]["schema_version"], CONFIG_SCHEMA_VERSION,
+        "a new schema version needs a golden configuration in its format"
+    );
+}
+
+#[test]
+fn audit_log_entries_round_trip() {
+    let mut serialized = String::new();
+    for line in load_fixture("golden/audit_log.jsonl").lines() {
+        let entry: AuditLogEntry = serde_json::from_str(line).unwrap();
+        serialized += &serde_json::to_string(&entry).unwrap();
+        serialized.push('\n');
+    }
+    assert_golden("golden/audit_log.expected.jsonl", &serialized);
+}
+
+/// The `connection` of each kind, `direct_stdio`, `direct_http` and
+/// `proxied` to leaf MCPs and agents, which agents of other implementations
+/// rely on
+#[tokio::test]
+async fn remote_config_entries_keep_their_shape() {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("config.json"),
+        load_fixture("golden/remote_connections.json"),
+    )
+    .unwrap();
+    let service = ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    );
+    service.load_configuration().await.unwrap();
+
+    let remote = service.get_agent_remote_config("assistant").await.unwrap();
+    let serialized = serde_json::to_string_pretty(&remote.mcps).unwrap() + "\n";
+    assert_golden("golden/remote_connections.expected.json", &serialized);
+    // Secrets and placeholders don't reach agents
+    for secret in ["files-secret", "search-secret", "weather-secret", "${"] {
+        assert!(!serialized.contains(secret), "{}", secret);
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
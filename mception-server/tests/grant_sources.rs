@@ -0,0 +1,215 @@
+mod common;
+
+use common::TestServer;
+use mception_server::core::{AuditAction, GrantSource, ServerConfig};
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+use std::collections::BTreeSet;
+
+/// A `writer` agent that may use the `search` leaf MCP directly
+async fn server_with_grant() -> TestServer {
+    let server = TestServer::start().await;
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({
+                "id": "search",
+                "config": {
+                    "transport": { "type": "builtin", "kind": "echo" },
+                    "is_local": false,
+                    "reachable_by_agent": false,
+                    "config": {}
+                },
+                "reason": null
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/agent",
+            &json!({ "agent_id": "writer", "allowed_mcp_ids": ["search"] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    server
+}
+
+async fn grant(server: &TestServer, source: Option<&str>) -> (StatusCode, Value) {
+    server
+        .admin_json(
+            Method::POST,
+            "/agent/writer/allowed_mcps",
+            &json!({ "mcp_id": "search", "reason": null, "source": source }),
+        )
+        .await
+}
+
+async fn revoke(server: &TestServer, source: Option<&str>) -> (StatusCode, Value) {
+    server
+        .admin_confirmed(
+            Method::DELETE,
+            "/agent/writer/allowed_mcps",
+            &json!({ "mcp_id": "search", "reason": null, "source": source }),
+        )
+        .await
+}
+
+#[tokio::test]
+async fn grants_through_several_sources_outlive_the_direct_one() {
+    let server = server_with_grant().await;
+    let (status, body) = grant(&server, Some("group:ops")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, _) = grant(&server, Some("group:ops")).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = grant(&server, Some("team:ops")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (_, view) = server.admin_get("/agent/writer/config").await;
+    assert_eq!(view["allowed_mcp_ids"], json!(["search"]));
+    assert_eq!(
+        view["grant_sources"],
+        json!({ "search": ["direct", "group:ops"] })
+    );
+
+    // Removing the direct grant leaves the group's, which is explained
+    let (status, body) = revoke(&server, None).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["remaining_sources"], json!(["group:ops"]));
+    assert_eq!(body["access"]["allowed"], true);
+    let trace = body["access"]["trace"].as_array().unwrap();
+    assert!(
+        trace
+            .iter()
+            .any(|rule| rule["rule"] == "search" && rule["sources"] == json!(["group:ops"])),
+        "{}",
+        body
+    );
+    let (status, body) = revoke(&server, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("through group:ops, not direct"),
+        "{}",
+        body
+    );
+
+    // The last source takes the grant with it
+    let (status, body) = revoke(&server, Some("group:ops")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["remaining_sources"], json!([]));
+    let saved = server.saved_config();
+    assert!(saved.agents["writer"].allowed_mcp_ids.is_empty());
+    assert!(saved.agents["writer"].grant_sources.is_empty());
+
+    // The agent's change feed only shows the grant itself coming and going
+    let (status, feed) = common::answer(server.request(Method::GET, "/agent/writer/changes")).await;
+    assert_eq!(status, StatusCode::OK, "{}", feed);
+    let kinds: Vec<&str> = feed["changes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|change| change["kind"].as_str().unwrap())
+        .collect();
+    assert_eq!(kinds, ["grant_added", "grant_removed"]);
+
+    let entries = server.audit_entries().await;
+    let removals: Vec<_> = entries
+        .iter()
+        .filter(|entry| matches!(entry.action, AuditAction::RemoveAllowedMcp))
+        .collect();
+    assert_eq!(removals.len(), 2);
+    assert_eq!(removals[0].details["source"], "direct");
+    assert_eq!(
+        removals[0].details["remaining_sources"],
+        json!(["group:ops"])
+    );
+    assert_eq!(removals[1].details["source"], "group:ops");
+}
+
+#[tokio::test]
+async fn history_replays_grant_sources() {
+    let server = server_with_grant().await;
+    let (status, _) = grant(&server, Some("approval:42")).await;
+    assert_eq!(status, StatusCode::OK);
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    let at = chrono::Utc::now();
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    let (status, _) = revoke(&server, None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let request = server
+        .admin(Method::GET, "/config/asof/agent/writer")
+        .query(&[("at", at.to_rfc3339())]);
+    let (status, body) = common::answer(request).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(
+        body["agent"]["grant_sources"],
+        json!({ "search": ["direct", "approval:42"] })
+    );
+}
+
+#[tokio::test]
+async fn legacy_allow_lists_are_loaded_as_direct_grants() {
+    let mut config = serde_json::to_value(ServerConfig::default()).unwrap();
+    config["agents"] = json!({
+        "writer": {
+            "agent_id": "writer",
+            "allowed_mcp_ids": ["reader"],
+            "is_connected": false,
+            "last_seen": null,
+            "config": {}
+        },
+        "reader": {
+            "agent_id": "reader",
+            "allowed_mcp_ids": [],
+            "is_connected": false,
+            "last_seen": null,
+            "config": {}
+        }
+    });
+    let server = TestServer::builder()
+        .config(&config.to_string())
+        .start()
+        .await;
+
+    let (_, view) = server.admin_get("/agent/writer/config").await;
+    assert_eq!(view["grant_sources"], json!({ "reader": ["direct"] }));
+    let (_, decision) = server.admin_get("/agent/writer/explain?mcp=reader").await;
+    assert_eq!(decision["trace"][1]["sources"], json!(["direct"]));
+
+    // Granting another source keeps the direct one
+    server
+        .service
+        .grant_agent_mcp(
+            "writer",
+            "reader",
+            GrantSource::Tag("ops".to_string()),
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    let agent = server.service.get_agent("writer", None).await.unwrap();
+    assert_eq!(
+        agent.grant_sources.get("reader"),
+        Some(&BTreeSet::from([
+            GrantSource::Direct,
+            GrantSource::Tag("ops".to_string())
+        ]))
+    );
+    let remaining = server
+        .service
+        .remove_agent_allowed_mcp("writer", "reader", None, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        remaining,
+        BTreeSet::from([GrantSource::Tag("ops".to_string())])
+    );
+}
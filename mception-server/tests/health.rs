@@ -0,0 +1,248 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{TestServer, leaf_mcp};
+use mception_server::core::{BuiltinMcpKind, McpTransport, StdioSandbox};
+use mception_server::services::health;
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+use std::time::{Duration, Instant};
+
+/// Answers `initialize` and `ping`, nothing else
+const PING_SCRIPT: &str = r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | grep -o '"id":[0-9]*' | head -n 1 | cut -d: -f2)
+  case "$line" in
+    *'"method":"initialize"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"protocolVersion":"2025-06-18","capabilities":{},"serverInfo":{"name":"script","version":"1"}}}\n' "$id" ;;
+    *'"method":"ping"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id" ;;
+  esac
+done
+"#;
+
+fn stdio(command: &str, args: &[&str]) -> McpTransport {
+    McpTransport::Stdio {
+        command: command.to_string(),
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        env: None,
+        sandbox: StdioSandbox::default(),
+    }
+}
+
+fn https(url: &str) -> McpTransport {
+    McpTransport::Https {
+        url: url.to_string(),
+        headers: None,
+    }
+}
+
+#[tokio::test]
+async fn health_reports_version_and_uptime() {
+    let server = TestServer::builder().admin_token("secret").start().await;
+    let (status, body) = common::answer(server.request(Method::GET, "/health")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ok");
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    assert!(body["uptime_seconds"].as_i64().unwrap() >= 0);
+
+    let (status, _) = common::answer(server.request(Method::GET, "/ready")).await;
+    assert_eq!(status, StatusCode::OK);
+    server.service.lifecycle().begin_draining();
+    let (status, body) = common::answer(server.request(Method::GET, "/ready")).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "draining");
+
+    // The deep check is part of the admin API
+    let (status, _) = common::answer(server.request(Method::GET, "/admin/health/deep")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn deep_health_probes_every_leaf_mcp() {
+    let server = TestServer::start().await;
+    let script = server.dir.join("ping.sh");
+    std::fs::write(&script, PING_SCRIPT).unwrap();
+    // Accepts connections but never answers
+    let silent = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let silent_url = format!("http://{}/mcp", silent.local_addr().unwrap());
+
+    let leaves = [
+        (
+            "echo",
+            McpTransport::Builtin {
+                kind: BuiltinMcpKind::Echo,
+            },
+        ),
+        ("script", stdio("sh", &[script.to_str().unwrap()])),
+        ("missing", stdio("/nonexistent/mcp-server", &[])),
+        ("closed", https("http://127.0.0.1:9/mcp")),
+        ("silent", https(&silent_url)),
+    ];
+    for (id, transport) in leaves {
+        server
+            .service
+            .create_leaf_mcp(Some(id.to_string()), leaf_mcp(transport), None, None)
+            .await
+            .unwrap();
+    }
+    for agent in ["one", "two"] {
+        server
+            .service
+            .create_agent(Some(agent.to_string()), None, vec![], None)
+            .await
+            .unwrap();
+    }
+    server.service.mark_agent_seen("one").await;
+
+    let started = Instant::now();
+    let (status, health) = server.admin_get("/health/deep?timeout=500ms").await;
+    assert_eq!(status, StatusCode::OK, "{}", health);
+    // The silent MCP is given up on without holding up the others
+    assert!(started.elapsed() < Duration::from_secs(3));
+
+    let statuses: Vec<(&str, &str)> = health["leaf_mcps"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|mcp: &Value| (mcp["id"].as_str().unwrap(), mcp["status"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        statuses,
+        [
+            ("closed", "unreachable"),
+            ("echo", "healthy"),
+            ("missing", "unreachable"),
+            ("script", "healthy"),
+            ("silent", "timeout"),
+        ]
+    );
+    assert_eq!(health["status"], "degraded");
+    assert_eq!(health["probe_timeout_ms"], 500);
+    assert_eq!(
+        health["counts"],
+        json!({ "healthy": 2, "unreachable": 2, "timeout": 1 })
+    );
+    assert_eq!(health["agents"], json!({ "total": 2, "connected": 1 }));
+    assert_eq!(health["leaf_mcps"][1]["transport"], "builtin");
+    assert!(health["leaf_mcps"][0]["error"].is_string());
+
+    let (status, _) = server.admin_get("/health/deep?timeout=soon").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    drop(silent);
+}
+
+/// Wait until every leaf MCP was probed in the background
+async fn wait_for_probes(server: &TestServer, count: usize) -> Value {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let (_, status) = server.admin_get("/status").await;
+        if status["health"]["leaf_mcps"].as_object().unwrap().len() == count {
+            return status;
+        }
+        assert!(Instant::now() < deadline, "{}", status);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test]
+async fn leaf_mcps_are_probed_in_the_background() {
+    let server = TestServer::start().await;
+    let leaves = [
+        (
+            "echo",
+            McpTransport::Builtin {
+                kind: BuiltinMcpKind::Echo,
+            },
+        ),
+        ("closed", https("http://127.0.0.1:9/mcp")),
+    ];
+    for (id, transport) in leaves {
+        server
+            .service
+            .create_leaf_mcp(Some(id.to_string()), leaf_mcp(transport), None, None)
+            .await
+            .unwrap();
+    }
+    let (_, status) = server.admin_get("/status").await;
+    assert_eq!(status["health"]["leaf_mcps"], json!({}));
+
+    tokio::spawn(health::watch(
+        server.service.clone(),
+        Duration::from_millis(100),
+    ));
+    let status = wait_for_probes(&server, 2).await;
+    let probed = &status["health"]["leaf_mcps"];
+    assert_eq!(probed["echo"]["healthy"], true, "{}", probed);
+    assert_eq!(probed["echo"]["last_error"], Value::Null);
+    assert!(probed["echo"]["last_probe_at"].is_string());
+    assert_eq!(probed["closed"]["healthy"], false, "{}", probed);
+    assert!(probed["closed"]["last_error"].is_string());
+
+    let (_, leaf_status) = server.admin_get("/leaf/closed/status").await;
+    assert_eq!(leaf_status["health"]["healthy"], false, "{}", leaf_status);
+
+    // A deleted leaf MCP's health goes with it
+    server
+        .service
+        .delete_leaf_mcp("closed", None, None)
+        .await
+        .unwrap();
+    let (_, status) = server.admin_get("/status").await;
+    assert!(status["health"]["leaf_mcps"].get("closed").is_none());
+}
+
+#[tokio::test]
+async fn list_mcps_shows_the_health_from_a_running_server() {
+    let server = TestServer::start().await;
+    for id in ["echo", "fresh"] {
+        server
+            .service
+            .create_leaf_mcp(
+                Some(id.to_string()),
+                leaf_mcp(McpTransport::Builtin {
+                    kind: BuiltinMcpKind::Echo,
+                }),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+    }
+    let echo = leaf_mcp(McpTransport::Builtin {
+        kind: BuiltinMcpKind::Echo,
+    });
+    let report = health::probe(
+        &server.service,
+        "echo",
+        &echo,
+        health::DEFAULT_PROBE_TIMEOUT,
+    )
+    .await;
+    server
+        .service
+        .health_statuses()
+        .record(&report, chrono::Utc::now())
+        .await;
+
+    let listed = tokio::process::Command::new(env!("CARGO_BIN_EXE_mception-server"))
+        .arg("--config")
+        .arg(&server.config_path)
+        .arg("--audit-log")
+        .arg(&server.audit_log_path)
+        .arg("--journal")
+        .arg(server.dir.join("config.journal"))
+        .args(["list-mcps", "--server", &server.url])
+        .output()
+        .await
+        .unwrap();
+    let printed = String::from_utf8_lossy(&listed.stdout);
+    assert!(listed.status.success(), "{:?}", listed);
+    assert!(printed.contains("echo: (no name) (healthy)"), "{}", printed);
+    assert!(
+        printed.contains("fresh: (no name) (not probed yet)"),
+        "{}",
+        printed
+    );
+}
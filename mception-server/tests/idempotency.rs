@@ -0,0 +1,199 @@
+mod common;
+
+use common::{TestServer, answer};
+use mception_server::core::AuditAction;
+use mception_server::services::idempotency::IdempotencyStore;
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+use std::time::Duration;
+
+fn create_leaf(id: &str) -> Value {
+    json!({
+        "id": id,
+        "config": {
+            "name": id,
+            "description": null,
+            "transport": { "type": "builtin", "kind": "echo" },
+            "is_local": false,
+            "reachable_by_agent": false,
+            "config": {}
+        }
+    })
+}
+
+async fn create(server: &TestServer, key: &str, leaf: &Value) -> (StatusCode, Value) {
+    answer(
+        server
+            .admin(Method::POST, "/leaf")
+            .header("Idempotency-Key", key)
+            .json(leaf),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn retries_are_answered_from_the_first_answer() {
+    let server = TestServer::builder()
+        .admin_token("ops=secret")
+        .start()
+        .await;
+    let leaf = create_leaf("search");
+    let (status, first) = create(&server, "create-search", &leaf).await;
+    assert_eq!(status, StatusCode::OK, "{}", first);
+
+    let response = server
+        .admin(Method::POST, "/leaf")
+        .header("Idempotency-Key", "create-search")
+        .json(&leaf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["idempotent-replayed"], "true");
+    assert_eq!(response.json::<Value>().await.unwrap(), first);
+
+    // Without the key the retry fails as before
+    let (status, _) = server.admin_json(Method::POST, "/leaf", &leaf).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // The same key with another request is refused
+    let (status, body) = create(&server, "create-search", &create_leaf("files")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"]["kind"], "idempotency_key_reused");
+    assert_eq!(server.saved_config().leaf_mcps.len(), 1);
+
+    let entries = server.audit_entries().await;
+    let created = &entries[0];
+    assert!(matches!(created.action, AuditAction::Create));
+    assert_eq!(created.idempotency_key.as_deref(), Some("create-search"));
+    let retries: Vec<_> = entries
+        .iter()
+        .filter(|entry| matches!(entry.action, AuditAction::IdempotentRetry))
+        .collect();
+    assert_eq!(retries.len(), 2);
+    assert_eq!(retries[0].details["outcome"], "replayed");
+    assert_eq!(retries[0].details["status"], 200);
+    assert_eq!(retries[1].details["outcome"], "conflict");
+    assert!(retries.iter().all(|entry| {
+        entry.idempotency_key.as_deref() == Some("create-search")
+            && entry.actor.as_deref() == Some("ops")
+    }));
+}
+
+#[tokio::test]
+async fn answers_expire_and_are_evicted() {
+    let server = TestServer::builder()
+        .idempotency(IdempotencyStore::new(
+            chrono::Duration::milliseconds(300),
+            2,
+        ))
+        .start()
+        .await;
+    let (status, _) = create(&server, "search", &create_leaf("search")).await;
+    assert_eq!(status, StatusCode::OK);
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    // Handled again once the window passed
+    let (status, _) = create(&server, "search", &create_leaf("search")).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // The oldest answer makes room beyond the capacity
+    for id in ["a", "b", "c"] {
+        let (status, _) = create(&server, id, &create_leaf(id)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, _) = create(&server, "c", &create_leaf("c")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = create(&server, "a", &create_leaf("a")).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // Reads ignore the header
+    let (status, _) = answer(
+        server
+            .admin(Method::GET, "/leaf/a/config")
+            .header("Idempotency-Key", "c"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn persisted_answers_survive_a_restart() {
+    let path = std::env::temp_dir().join(format!("mception-idempotency-{}", uuid::Uuid::new_v4()));
+    let store = || {
+        IdempotencyStore::new(chrono::Duration::hours(1), 10)
+            .persisted(&path)
+            .unwrap()
+    };
+    let server = TestServer::builder().idempotency(store()).start().await;
+    let (_, first) = create(&server, "search", &create_leaf("search")).await;
+    drop(server);
+
+    let server = TestServer::builder().idempotency(store()).start().await;
+    let (status, body) = create(&server, "search", &create_leaf("search")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, first);
+    assert!(server.saved_config().leaf_mcps.is_empty());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn keys_are_bound_to_the_query_too() {
+    let server = TestServer::start().await;
+    let delete = |tag: &str| {
+        answer(
+            server
+                .admin(Method::DELETE, &format!("/leaf?tag={}", tag))
+                .header("Idempotency-Key", "cleanup"),
+        )
+    };
+    let (status, _) = delete("a").await;
+    assert!(status.is_success(), "{}", status);
+    let (status, body) = delete("b").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"]["kind"], "idempotency_key_reused");
+}
+
+#[tokio::test]
+async fn requests_cut_off_before_answering_may_be_retried() {
+    // A leaf MCP accepting connections but never answering
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((socket, _)) = listener.accept().await {
+            held.push(socket);
+        }
+    });
+    let server = TestServer::start().await;
+    let (status, _) = server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({
+                "id": "silent",
+                "config": {
+                    "name": "silent",
+                    "description": null,
+                    "transport": { "type": "https", "url": url },
+                    "is_local": false,
+                    "reachable_by_agent": false,
+                    "config": {}
+                }
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let call = || {
+        server
+            .admin(Method::POST, "/leaf/silent/tools/search/call")
+            .header("Idempotency-Key", "call")
+            .timeout(Duration::from_millis(300))
+            .json(&json!({}))
+            .send()
+    };
+    assert!(call().await.unwrap_err().is_timeout());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    // Handled again rather than refused as still in progress
+    assert!(call().await.unwrap_err().is_timeout());
+}
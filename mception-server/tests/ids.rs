@@ -0,0 +1,90 @@
+mod common;
+
+use common::leaf_mcp;
+use mception_server::core::{LeafMcpConfig, McpTransport};
+use mception_server::services::ConfigService;
+use mception_server::services::ids::IdKind;
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+fn service() -> Arc<ConfigService> {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ))
+}
+
+fn leaf(name: &str) -> LeafMcpConfig {
+    LeafMcpConfig {
+        name: Some(name.to_string()),
+        reachable_by_agent: true,
+        ..leaf_mcp(McpTransport::Https {
+            url: "https://mcp.example.com".to_string(),
+            headers: None,
+        })
+    }
+}
+
+#[tokio::test]
+async fn generates_slug_ids_with_collision_suffixes() {
+    let service = service();
+
+    let first = service
+        .create_leaf_mcp(None, leaf("My GitHub MCP"), None, None)
+        .await
+        .unwrap();
+    let second = service
+        .create_leaf_mcp(None, leaf("My GitHub MCP"), None, None)
+        .await
+        .unwrap();
+    assert_eq!(first, "my-github-mcp");
+    assert_eq!(second, "my-github-mcp-2");
+    assert_eq!(
+        service.get_leaf_mcp(&second, None).await.unwrap().id,
+        second
+    );
+
+    // Agents share the namespace of leaf MCPs
+    let suggested = service
+        .suggest_id(IdKind::Agent, Some("My GitHub MCP"))
+        .await
+        .unwrap();
+    assert_eq!(suggested, "my-github-mcp-3");
+
+    assert!(
+        service
+            .create_leaf_mcp(Some("bad id".to_string()), leaf("Bad"), None, None)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn concurrent_creates_get_distinct_ids() {
+    let service = service();
+
+    let creates: Vec<_> = (0..10)
+        .map(|_| {
+            let service = service.clone();
+            tokio::spawn(async move {
+                service
+                    .create_leaf_mcp(None, leaf("Search"), None, None)
+                    .await
+                    .unwrap()
+            })
+        })
+        .collect();
+    let mut ids = BTreeSet::new();
+    for create in creates {
+        ids.insert(create.await.unwrap());
+    }
+    assert_eq!(ids.len(), 10);
+    assert_eq!(service.list_leaf_mcps().await.unwrap().len(), 10);
+}
@@ -0,0 +1,187 @@
+use mception_server::core::testing::load_fixture;
+use mception_server::services::ConfigService;
+use mception_server::storage::providers::{ConfigStorage, FileAuditStorage, FileConfigStorage};
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+fn fixture() -> String {
+    load_fixture("config.json")
+}
+
+/// The fixture configuration split up: `mcps/files.json` has the leaf MCP
+/// `filesystem` and the agent `assistant`, `mcps/search.json` the leaf MCP
+/// `search`, and `config.json` the agent `builder`
+fn split_fixture() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(dir.join("mcps")).unwrap();
+
+    let mut config: Value = serde_json::from_str(&fixture()).unwrap();
+    let leaf_mcps = config["leaf_mcps"].as_object_mut().unwrap();
+    let filesystem = leaf_mcps.remove("filesystem").unwrap();
+    let search = leaf_mcps.remove("search").unwrap();
+    let assistant = config["agents"]
+        .as_object_mut()
+        .unwrap()
+        .remove("assistant")
+        .unwrap();
+    config["includes"] = json!(["mcps/*.json"]);
+
+    write(&dir.join("config.json"), &config);
+    write(
+        &dir.join("mcps/files.json"),
+        &json!({ "leaf_mcps": { "filesystem": filesystem }, "agents": { "assistant": assistant } }),
+    );
+    write(
+        &dir.join("mcps/search.json"),
+        &json!({ "leaf_mcps": { "search": search } }),
+    );
+    // Not matched by the include
+    std::fs::write(dir.join("mcps/notes.txt"), "not a fragment").unwrap();
+    dir
+}
+
+fn write(path: &Path, value: &Value) {
+    std::fs::write(path, serde_json::to_string_pretty(value).unwrap()).unwrap();
+}
+
+fn read(path: &Path) -> Value {
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+fn service(dir: &Path) -> ConfigService {
+    ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    )
+}
+
+#[tokio::test]
+async fn includes_are_merged_on_load() {
+    let dir = split_fixture();
+    let storage = FileConfigStorage::new(dir.join("config.json").to_string_lossy());
+
+    let resolved = storage.load_config().await.unwrap();
+    assert_eq!(
+        resolved.leaf_mcps.keys().collect::<Vec<_>>(),
+        ["filesystem", "search"]
+    );
+    assert_eq!(
+        resolved.agents.keys().collect::<Vec<_>>(),
+        ["assistant", "builder"]
+    );
+
+    let unresolved = storage.load_unresolved_config().await.unwrap();
+    assert!(unresolved.leaf_mcps.is_empty());
+    assert_eq!(unresolved.includes, ["mcps/*.json"]);
+
+    // Saving the unchanged configuration again leaves every file as it was
+    storage.save_config(&resolved).await.unwrap();
+    let files = std::fs::read_to_string(dir.join("mcps/files.json")).unwrap();
+    let reloaded = storage.load_config().await.unwrap();
+    storage.save_config(&reloaded).await.unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join("mcps/files.json")).unwrap(),
+        files
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn changes_are_saved_to_the_file_they_came_from() {
+    let dir = split_fixture();
+    let service = service(&dir);
+    service.load_configuration().await.unwrap();
+
+    service
+        .update_leaf_mcp(
+            "search",
+            json!({ "description": "Web and news search" }),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    service.delete_agent("assistant", None, None).await.unwrap();
+    service
+        .create_agent(Some("reviewer".to_string()), None, vec![], None)
+        .await
+        .unwrap();
+
+    let search = read(&dir.join("mcps/search.json"));
+    assert_eq!(
+        search["leaf_mcps"]["search"]["description"],
+        "Web and news search"
+    );
+    let files = read(&dir.join("mcps/files.json"));
+    assert!(files["leaf_mcps"]["filesystem"].is_object());
+    assert_eq!(files["agents"], json!({}));
+    // New entities go to the configuration file itself
+    let config = read(&dir.join("config.json"));
+    assert!(config["agents"]["reviewer"].is_object());
+    assert!(config["leaf_mcps"]["search"].is_null());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn duplicate_ids_name_both_files() {
+    let dir = split_fixture();
+    write(
+        &dir.join("mcps/zz-copy.json"),
+        &json!({ "leaf_mcps": { "search": read(&dir.join("mcps/search.json"))["leaf_mcps"]["search"] } }),
+    );
+    let storage = FileConfigStorage::new(dir.join("config.json").to_string_lossy());
+
+    let error = storage.load_config().await.unwrap_err().to_string();
+    assert!(error.contains("'search'"), "{}", error);
+    assert!(error.contains("mcps/search.json"), "{}", error);
+    assert!(error.contains("mcps/zz-copy.json"), "{}", error);
+
+    let checks = storage.check_includes().await.unwrap();
+    let files: Vec<_> = checks.iter().map(|check| check.file.as_str()).collect();
+    assert_eq!(
+        files,
+        ["mcps/files.json", "mcps/search.json", "mcps/zz-copy.json"]
+    );
+    assert!(checks[0].errors.is_empty());
+    assert_eq!(checks[0].leaf_mcps, 1);
+    assert_eq!(checks[0].agents, 1);
+    assert_eq!(
+        checks[2].errors,
+        ["Leaf MCP 'search' is already defined in mcps/search.json"]
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn invalid_fragments_are_reported_with_their_file() {
+    let dir = split_fixture();
+    std::fs::write(dir.join("mcps/broken.json"), r#"{"bundles": {}}"#).unwrap();
+    let storage = FileConfigStorage::new(dir.join("config.json").to_string_lossy());
+
+    let error = storage.load_config().await.unwrap_err().to_string();
+    assert!(error.contains("mcps/broken.json"), "{}", error);
+
+    let checks = storage.check_includes().await.unwrap();
+    assert_eq!(checks[0].file, "mcps/broken.json");
+    assert_eq!(checks[0].errors.len(), 1);
+    assert!(checks[1..].iter().all(|check| check.errors.is_empty()));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn missing_literal_includes_fail() {
+    let dir = split_fixture();
+    let mut config = read(&dir.join("config.json"));
+    config["includes"] = json!(["mcps/*.json", "extra.json"]);
+    write(&dir.join("config.json"), &config);
+    let storage = FileConfigStorage::new(dir.join("config.json").to_string_lossy());
+
+    let error = storage.load_config().await.unwrap_err().to_string();
+    assert!(error.contains("'extra.json' does not exist"), "{}", error);
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use common::leaf_mcp;
+use mception_server::core::{BuiltinMcpKind, LeafMcpConfig, MAX_INSTRUCTIONS_LEN, McpTransport};
+use mception_server::services::ConfigService;
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::{RouterOptions, build_router};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+const INSTRUCTIONS: &str = "Use for echoing text back. Prefer it over guessing.";
+const DESCRIPTION: &str = "Owned by the platform team, scheduled for removal";
+
+async fn service() -> Arc<ConfigService> {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let service = Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ));
+    service
+        .create_leaf_mcp(
+            Some("echo".to_string()),
+            LeafMcpConfig {
+                name: Some("Echo".to_string()),
+                description: Some(DESCRIPTION.to_string()),
+                instructions: Some(INSTRUCTIONS.to_string()),
+                ..leaf_mcp(McpTransport::Builtin {
+                    kind: BuiltinMcpKind::Echo,
+                })
+            },
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    service
+        .create_agent(
+            Some("writer".to_string()),
+            None,
+            vec!["echo".to_string()],
+            None,
+        )
+        .await
+        .unwrap();
+    service
+}
+
+#[tokio::test]
+async fn agents_get_instructions_but_not_descriptions() {
+    let service = service().await;
+
+    let remote = service.get_agent_remote_config("writer").await.unwrap();
+    assert_eq!(
+        remote.mcps["echo"].instructions.as_deref(),
+        Some(INSTRUCTIONS)
+    );
+    let serialized = serde_json::to_string(&remote).unwrap();
+    assert!(serialized.contains(INSTRUCTIONS));
+    assert!(!serialized.contains(DESCRIPTION));
+}
+
+#[tokio::test]
+async fn initialize_result_carries_instructions() {
+    let service = service().await;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!(
+        "http://{}/leaf/echo/forwarding",
+        listener.local_addr().unwrap()
+    );
+    tokio::spawn(async move {
+        axum::serve(listener, build_router(service, RouterOptions::default()))
+            .await
+            .unwrap();
+    });
+
+    let response: Value = reqwest::Client::new()
+        .post(&url)
+        .json(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": { "protocolVersion": "2025-06-18", "capabilities": {} }
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(response["result"]["instructions"], INSTRUCTIONS);
+}
+
+#[tokio::test]
+async fn instructions_are_size_capped() {
+    let service = service().await;
+
+    let too_long = "x".repeat(MAX_INSTRUCTIONS_LEN + 1);
+    assert!(
+        service
+            .update_leaf_mcp("echo", json!({ "instructions": too_long }), None, None)
+            .await
+            .is_err()
+    );
+
+    service
+        .update_leaf_mcp("echo", json!({ "instructions": null }), None, None)
+        .await
+        .unwrap();
+    let remote = service.get_agent_remote_config("writer").await.unwrap();
+    assert_eq!(remote.mcps["echo"].instructions, None);
+}
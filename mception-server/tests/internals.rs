@@ -0,0 +1,230 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::leaf_mcp;
+use futures_util::FutureExt;
+use mception_server::core::{
+    BodyEncoding, ForwardingMessage, LeafMcpConfig, MceptionError, McpTool, McpTransport,
+    NetworkError,
+};
+use mception_server::services::connections::AgentRequest;
+use mception_server::services::internals::ResourceLimits;
+use mception_server::services::tools::{ToolCache, ToolListing};
+use mception_server::services::{ConfigService, ConnectionService};
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::{RouterOptions, build_router};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+fn leaf() -> LeafMcpConfig {
+    leaf_mcp(McpTransport::Https {
+        url: "https://mcp.example.com/mcp".to_string(),
+        headers: None,
+    })
+}
+
+fn listing(age: i64) -> ToolListing {
+    ToolListing {
+        tools: vec![McpTool {
+            name: "search".to_string(),
+            description: "Search the web".to_string(),
+            parameters: json!({ "type": "object" }),
+        }],
+        fetched_at: Utc::now() - Duration::seconds(age),
+        cached: false,
+    }
+}
+
+#[test]
+fn tool_cache_evicts_beyond_its_capacity() {
+    let cache = ToolCache::with_capacity(3);
+    let leaf = leaf();
+    // Older listings are inserted later, so eviction doesn't just follow insertion
+    for (i, age) in [(0, 5), (1, 1), (2, 4), (3, 2), (4, 3), (5, 0)] {
+        cache.insert(&format!("leaf-{}", i), &leaf, &listing(age));
+    }
+
+    let usage = cache.usage();
+    assert_eq!(usage.entries, 3);
+    assert_eq!(usage.capacity, 3);
+    assert_eq!(usage.evictions, 3);
+    assert!(usage.approx_bytes > 0);
+    for kept in ["leaf-1", "leaf-3", "leaf-5"] {
+        assert!(cache.get(kept, &leaf).is_some(), "{}", kept);
+    }
+
+    // Refreshing a cached listing doesn't evict another
+    cache.insert("leaf-5", &leaf, &listing(0));
+    assert_eq!(cache.usage().evictions, 3);
+}
+
+#[tokio::test]
+async fn pending_agent_requests_are_capped() {
+    let connections = Arc::new(ConnectionService::with_max_pending(1));
+    let _registration = connections.register("writer");
+    let request = || AgentRequest {
+        url_params: String::new(),
+        headers: BTreeMap::new(),
+        body: None,
+    };
+
+    let waiting = connections.clone();
+    let unanswered = tokio::spawn(async move {
+        waiting
+            .forward("writer", request(), std::time::Duration::from_secs(5))
+            .await
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(connections.usage().pending_requests, 1);
+
+    let rejected = connections
+        .forward("writer", request(), std::time::Duration::from_secs(5))
+        .await;
+    assert!(matches!(
+        rejected,
+        Err(MceptionError::Network(NetworkError::Overloaded(_)))
+    ));
+    assert_eq!(connections.usage().rejected, 1);
+    unanswered.abort();
+}
+
+#[tokio::test]
+async fn unanswered_agent_requests_stay_bounded() {
+    let connections =
+        Arc::new(ConnectionService::with_max_pending(64).with_max_pending_per_connection(16));
+    let mut writer = connections.register("writer");
+    let _reader = connections.register("reader");
+    let request = || AgentRequest {
+        url_params: String::new(),
+        headers: BTreeMap::new(),
+        body: None,
+    };
+
+    // Neither agent ever answers
+    let mut waiting = Vec::new();
+    for i in 0..400 {
+        let connections = connections.clone();
+        let agent = if i % 2 == 0 { "writer" } else { "reader" };
+        waiting.push(tokio::spawn(async move {
+            connections
+                .forward(agent, request(), std::time::Duration::from_millis(300))
+                .await
+        }));
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    let usage = connections.usage();
+    assert_eq!(usage.pending_requests, 32);
+    assert_eq!(usage.capacity_per_connection, 16);
+
+    let (mut timed_out, mut overloaded) = (0, 0);
+    for result in futures_util::future::join_all(waiting).await {
+        match result.unwrap() {
+            Err(MceptionError::Network(NetworkError::Timeout(message))) => {
+                assert!(
+                    message.contains("did not answer within 300 ms"),
+                    "{}",
+                    message
+                );
+                timed_out += 1;
+            }
+            Err(MceptionError::Network(NetworkError::Overloaded(_))) => overloaded += 1,
+            other => panic!(
+                "unexpected {:?}",
+                other.map(|response| response.status_code)
+            ),
+        }
+    }
+    assert_eq!((timed_out, overloaded), (32, 368));
+    let usage = connections.usage();
+    assert_eq!(usage.pending_requests, 0);
+    assert_eq!(usage.evicted, 32);
+    assert_eq!(usage.rejected, 368);
+
+    // A late answer is dropped
+    let Ok(ForwardingMessage::Request { request_id, .. }) = writer.outgoing.try_recv() else {
+        panic!("no request was sent to the writer");
+    };
+    connections.deliver(
+        "writer",
+        writer.connection_id,
+        ForwardingMessage::Response {
+            request_id,
+            status_code: 200,
+            headers: BTreeMap::new(),
+            body: None,
+            encoding: BodyEncoding::Utf8,
+        },
+    );
+    assert_eq!(connections.usage().orphaned_responses, 1);
+}
+
+#[tokio::test]
+async fn expired_agent_requests_are_evicted_without_their_caller() {
+    let connections = ConnectionService::with_max_pending(8);
+    let _registration = connections.register("writer");
+    let request = AgentRequest {
+        url_params: String::new(),
+        headers: BTreeMap::new(),
+        body: None,
+    };
+    let mut forward =
+        Box::pin(connections.forward("writer", request, std::time::Duration::from_millis(50)));
+    assert!((&mut forward).now_or_never().is_none());
+
+    // The caller isn't polled past the deadline, the next look evicts it
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    let usage = connections.usage();
+    assert_eq!(usage.pending_requests, 0);
+    assert_eq!(usage.evicted, 1);
+    assert!(matches!(
+        forward.await,
+        Err(MceptionError::Network(NetworkError::Timeout(_)))
+    ));
+}
+
+#[tokio::test]
+async fn internals_endpoints() {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let service = ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    )
+    .with_limits(ResourceLimits {
+        tool_cache_entries: 8,
+        pending_agent_requests: 16,
+        pending_requests_per_agent: 4,
+    });
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let router = build_router(Arc::new(service), RouterOptions::default());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    let body: Value = reqwest::get(format!("{}/admin/internals", url))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["tool_cache"]["entries"], 0);
+    assert_eq!(body["tool_cache"]["capacity"], 8);
+    assert_eq!(body["agent_connections"]["capacity"], 16);
+    assert_eq!(body["stdio_processes"]["processes"], 0);
+    assert_eq!(body["debug_captures"]["payloads"], 0);
+
+    let metrics = reqwest::get(format!("{}/metrics", url))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(metrics.contains("# TYPE mception_tool_cache_evictions_total counter\n"));
+    assert!(metrics.contains("\nmception_agent_pending_requests_capacity 16\n"));
+    assert!(metrics.contains("\nmception_agent_pending_requests_capacity_per_connection 4\n"));
+    assert!(metrics.contains("\nmception_agent_orphaned_responses_total 0\n"));
+}
@@ -0,0 +1,224 @@
+mod common;
+
+use async_trait::async_trait;
+use common::temp_dir;
+use mception_server::core::testing::load_fixture;
+use mception_server::core::{
+    BackupInfo, MceptionError, MceptionResult, MigrationInfo, MigrationStatus, ServerConfig,
+    StorageError,
+};
+use mception_server::services::ConfigService;
+use mception_server::storage::journal::ConfigJournal;
+use mception_server::storage::providers::{ConfigStorage, FileAuditStorage, FileConfigStorage};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+fn fixture() -> String {
+    load_fixture("config.json")
+}
+
+/// File storage whose saves fail once crashed, as if the process died
+/// between journaling a mutation and saving the configuration
+struct CrashingStorage {
+    inner: FileConfigStorage,
+    crashed: AtomicBool,
+}
+
+#[async_trait]
+impl ConfigStorage for CrashingStorage {
+    fn location(&self) -> String {
+        self.inner.location()
+    }
+
+    async fn load_config(&self) -> MceptionResult<ServerConfig> {
+        self.inner.load_config().await
+    }
+
+    async fn save_config(&self, config: &ServerConfig) -> MceptionResult<()> {
+        if self.crashed.load(Ordering::SeqCst) {
+            return Err(MceptionError::Storage(StorageError::Io(
+                std::io::Error::other("crashed before saving"),
+            )));
+        }
+        self.inner.save_config(config).await
+    }
+
+    async fn config_exists(&self) -> MceptionResult<bool> {
+        self.inner.config_exists().await
+    }
+
+    async fn backup_config(&self) -> MceptionResult<String> {
+        self.inner.backup_config().await
+    }
+
+    async fn list_backups(&self) -> MceptionResult<Vec<BackupInfo>> {
+        self.inner.list_backups().await
+    }
+
+    async fn load_backup(&self, name: &str) -> MceptionResult<ServerConfig> {
+        self.inner.load_backup(name).await
+    }
+
+    async fn restore_backup(&self, name: &str) -> MceptionResult<ServerConfig> {
+        self.inner.restore_backup(name).await
+    }
+
+    async fn prune_backups(&self, keep: usize) -> MceptionResult<Vec<String>> {
+        self.inner.prune_backups(keep).await
+    }
+
+    async fn migration_status(&self) -> MceptionResult<MigrationStatus> {
+        self.inner.migration_status().await
+    }
+
+    async fn migrate(&self) -> MceptionResult<Vec<MigrationInfo>> {
+        self.inner.migrate().await
+    }
+}
+
+/// Fresh directory holding a copy of the fixture configuration
+fn fixture_dir() -> PathBuf {
+    let dir = temp_dir();
+    std::fs::write(dir.join("config.json"), fixture()).unwrap();
+    dir
+}
+
+fn service(dir: &Path, config_storage: Arc<dyn ConfigStorage>) -> ConfigService {
+    ConfigService::new(
+        config_storage,
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    )
+    .with_journal(ConfigJournal::new(dir.join("config.journal")))
+}
+
+fn file_storage(dir: &Path) -> Arc<FileConfigStorage> {
+    Arc::new(FileConfigStorage::new(
+        dir.join("config.json").to_string_lossy(),
+    ))
+}
+
+/// Apply two mutations whose saves are lost, leaving them only in the journal
+async fn crash_after_mutations(dir: &Path) {
+    let storage = Arc::new(CrashingStorage {
+        inner: FileConfigStorage::new(dir.join("config.json").to_string_lossy()),
+        crashed: AtomicBool::new(false),
+    });
+    let service = service(dir, storage.clone());
+    service.load_configuration().await.unwrap();
+
+    storage.crashed.store(true, Ordering::SeqCst);
+    service
+        .create_agent(
+            Some("reviewer".to_string()),
+            None,
+            vec!["search".to_string()],
+            None,
+        )
+        .await
+        .unwrap();
+    service
+        .update_leaf_mcp(
+            "search",
+            serde_json::json!({ "description": "Web and news search" }),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn recovers_mutations_lost_before_save() {
+    let dir = fixture_dir();
+    crash_after_mutations(&dir).await;
+    assert_eq!(
+        std::fs::read_to_string(dir.join("config.json")).unwrap(),
+        fixture()
+    );
+
+    let service = service(&dir, file_storage(&dir));
+    service.load_configuration().await.unwrap();
+
+    let config = service.get_configuration().await;
+    assert_eq!(config.metadata.revision, 5);
+    assert_eq!(config.agents["reviewer"].allowed_mcp_ids, vec!["search"]);
+    assert_eq!(
+        config.leaf_mcps["search"].description.as_deref(),
+        Some("Web and news search")
+    );
+
+    // Recovered mutations are saved and the journal is truncated
+    let saved = file_storage(&dir).load_config().await.unwrap();
+    assert_eq!(saved.metadata.revision, 5);
+    assert!(saved.agents.contains_key("reviewer"));
+    assert!(
+        std::fs::read_to_string(dir.join("config.journal"))
+            .unwrap()
+            .is_empty()
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn replaying_the_journal_twice_is_idempotent() {
+    let dir = fixture_dir();
+    crash_after_mutations(&dir).await;
+    let journal = std::fs::read(dir.join("config.journal")).unwrap();
+
+    service(&dir, file_storage(&dir))
+        .load_configuration()
+        .await
+        .unwrap();
+    let recovered = std::fs::read_to_string(dir.join("config.json")).unwrap();
+
+    std::fs::write(dir.join("config.journal"), journal).unwrap();
+    service(&dir, file_storage(&dir))
+        .load_configuration()
+        .await
+        .unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join("config.json")).unwrap(),
+        recovered
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn unreplayable_entry_aborts_loading() {
+    let dir = fixture_dir();
+    let entry = serde_json::json!({
+        "revision": 4,
+        "timestamp": "2026-01-03T00:00:00Z",
+        "operation": "create_agent",
+        "changes": [{
+            "op": "put_agent",
+            "id": "reviewer",
+            "config": {
+                "agent_id": "reviewer",
+                "name": null,
+                "description": null,
+                "allowed_mcp_ids": ["missing"],
+                "is_connected": false,
+                "last_seen": null,
+                "config": {}
+            }
+        }]
+    });
+    std::fs::write(dir.join("config.journal"), format!("{}\n", entry)).unwrap();
+
+    let error = service(&dir, file_storage(&dir))
+        .load_configuration()
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("can't be replayed"), "{}", error);
+    assert!(error.contains("'missing'"), "{}", error);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("config.json")).unwrap(),
+        fixture()
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
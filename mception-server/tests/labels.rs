@@ -0,0 +1,268 @@
+mod common;
+
+use common::TestServer;
+use mception_server::core::AuditAction;
+use mception_server::core::labels::{LabelSelector, LabelTerm, parse_label};
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+#[test]
+fn selectors_parse_equality_and_inequality_terms() {
+    let selector: LabelSelector = " team=payments , env!=prod".parse().unwrap();
+    assert_eq!(
+        selector.terms,
+        vec![
+            LabelTerm::Equals("team".to_string(), "payments".to_string()),
+            LabelTerm::NotEquals("env".to_string(), "prod".to_string()),
+        ]
+    );
+    assert_eq!(selector.to_string(), "team=payments,env!=prod");
+    assert_eq!(
+        "tier=".parse::<LabelSelector>().unwrap().terms,
+        vec![LabelTerm::Equals("tier".to_string(), String::new())]
+    );
+
+    for invalid in ["", "team", "=payments", "team=payments,", "a!b=c"] {
+        assert!(
+            invalid.parse::<LabelSelector>().is_err(),
+            "'{}' parsed",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn selectors_match_when_every_term_holds() {
+    let staging = labels(&[("team", "payments"), ("env", "staging")]);
+    let prod = labels(&[("team", "payments"), ("env", "prod")]);
+    let unlabeled = labels(&[]);
+    let matches = |selector: &str, labels: &BTreeMap<String, String>| {
+        selector.parse::<LabelSelector>().unwrap().matches(labels)
+    };
+
+    assert!(matches("env=staging", &staging));
+    assert!(!matches("env=staging", &prod));
+    assert!(!matches("env=staging", &unlabeled));
+    // An unset label is not equal to any value
+    assert!(matches("env!=prod", &staging));
+    assert!(!matches("env!=prod", &prod));
+    assert!(matches("env!=prod", &unlabeled));
+    assert!(matches("team=payments,env!=prod", &staging));
+    assert!(!matches("team=payments,env!=prod", &prod));
+    assert!(!matches("team=search,env=staging", &staging));
+}
+
+#[test]
+fn single_labels_parse_as_key_value() {
+    assert_eq!(
+        parse_label("team=payments").unwrap(),
+        ("team".to_string(), "payments".to_string())
+    );
+    assert!(parse_label("team").is_err());
+    assert!(parse_label("team=a,b").is_err());
+    assert!(parse_label("=payments").is_err());
+}
+
+async fn create_leaf(server: &TestServer, id: &str, labels: Value) -> (StatusCode, Value) {
+    server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({
+                "id": id,
+                "config": {
+                    "transport": { "type": "builtin", "kind": "echo" },
+                    "is_local": false,
+                    "reachable_by_agent": false,
+                    "config": {},
+                    "labels": labels
+                },
+                "reason": null
+            }),
+        )
+        .await
+}
+
+async fn create_agent(server: &TestServer, id: &str, labels: Value) {
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/agent",
+            &json!({ "agent_id": id, "allowed_mcp_ids": [], "labels": labels }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+fn listed(body: &Value, key: &str) -> Vec<String> {
+    body[key].as_object().unwrap().keys().cloned().collect()
+}
+
+#[tokio::test]
+async fn lists_filter_by_label_selectors() {
+    let server = TestServer::start().await;
+    for (id, labels) in [
+        ("checkout", json!({ "team": "payments", "env": "staging" })),
+        ("refunds", json!({ "team": "payments", "env": "prod" })),
+        ("search", json!({ "team": "search", "env": "staging" })),
+        ("echo", json!({})),
+    ] {
+        let (status, body) = create_leaf(&server, id, labels).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    let (_, body) = server.admin_get("/leaf?label=env=staging").await;
+    assert_eq!(listed(&body, "leaf_mcps"), ["checkout", "search"]);
+    let (_, body) = server
+        .admin_get("/leaf?label=team=payments,env!=prod")
+        .await;
+    assert_eq!(body["count"], 1);
+    assert_eq!(
+        body["leaf_mcps"]["checkout"]["labels"],
+        json!({ "env": "staging", "team": "payments" })
+    );
+    let (_, body) = server.admin_get("/leaf?label=env!=prod").await;
+    assert_eq!(listed(&body, "leaf_mcps"), ["checkout", "echo", "search"]);
+    let (status, body) = server.admin_get("/leaf?label=env").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+
+    create_agent(&server, "billing-bot", json!({ "team": "payments" })).await;
+    create_agent(&server, "crawler", json!({ "team": "search" })).await;
+    let (_, body) = server.admin_get("/agent?label=team=payments").await;
+    assert_eq!(listed(&body, "agents"), ["billing-bot"]);
+    let (_, agent) = server.admin_get("/agent/billing-bot/config").await;
+    assert_eq!(agent["labels"], json!({ "team": "payments" }));
+
+    // Labels are changed like any other field, `null` removes one
+    let (status, body) = server
+        .admin_json(
+            Method::PUT,
+            "/leaf/search/config",
+            &json!({ "config": { "labels": { "env": null, "tier": "gold" } } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let saved = server.saved_config();
+    assert_eq!(
+        saved.leaf_mcps["search"].labels,
+        labels(&[("team", "search"), ("tier", "gold")])
+    );
+    let (status, _) = server
+        .admin_json(
+            Method::PUT,
+            "/agent/crawler/config",
+            &json!({ "config": { "labels": { "bad,key": "x" } } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn invalid_labels_are_refused() {
+    let server = TestServer::start().await;
+    let (status, body) = create_leaf(&server, "checkout", json!({ "": "x" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    let (status, body) = create_leaf(&server, "checkout", json!({ "team": "a,b" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    assert!(server.saved_config().leaf_mcps.is_empty());
+}
+
+#[tokio::test]
+async fn grants_every_matching_leaf_mcp_at_once() {
+    let server = TestServer::start().await;
+    for (id, labels) in [
+        ("checkout", json!({ "team": "payments", "env": "staging" })),
+        ("refunds", json!({ "team": "payments", "env": "staging" })),
+        ("ledger", json!({ "team": "payments", "env": "prod" })),
+        ("search", json!({ "team": "search", "env": "staging" })),
+    ] {
+        create_leaf(&server, id, labels).await;
+    }
+    create_agent(&server, "billing-bot", json!({})).await;
+    let (status, _) = server
+        .admin_json(
+            Method::POST,
+            "/agent/billing-bot/allowed_mcps",
+            &json!({ "mcp_id": "refunds", "reason": null }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let before = server.audit_entries().await.len();
+
+    let grant = json!({ "selector": "team=payments,env=staging", "reason": "billing rollout" });
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/agent/billing-bot/allowed_mcps/by_label",
+            &grant,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    // Already granted directly, so only checkout is new
+    assert_eq!(body["granted"], json!(["checkout"]));
+    let saved = server.saved_config();
+    assert_eq!(
+        saved.agents["billing-bot"].allowed_mcp_ids,
+        ["refunds", "checkout"]
+    );
+
+    let entries = server.audit_entries().await;
+    assert_eq!(entries.len(), before + 1);
+    let granted = entries.last().unwrap();
+    assert!(matches!(granted.action, AuditAction::AddAllowedMcp));
+    assert_eq!(granted.details["mcp_ids"], json!(["checkout"]));
+    assert_eq!(granted.details["selector"], "team=payments,env=staging");
+    assert_eq!(granted.reason.as_deref(), Some("billing rollout"));
+    // The audit entry alone reconstructs the grants
+    let request = server
+        .admin(Method::GET, "/config/asof/agent/billing-bot")
+        .query(&[("at", chrono::Utc::now().to_rfc3339())]);
+    let (status, body) = common::answer(request).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(
+        body["agent"]["allowed_mcp_ids"],
+        json!(["refunds", "checkout"])
+    );
+
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/agent/billing-bot/allowed_mcps/by_label",
+            &json!({ "selector": "team=payments", "reason": null }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["granted"], json!(["ledger"]));
+    // Nothing left to grant changes nothing
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/agent/billing-bot/allowed_mcps/by_label",
+            &json!({ "selector": "team=payments", "reason": null }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["granted"], json!([]));
+    assert_eq!(server.audit_entries().await.len(), before + 2);
+
+    let (status, _) = server
+        .admin_json(Method::POST, "/agent/missing/allowed_mcps/by_label", &grant)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = server
+        .admin_json(
+            Method::POST,
+            "/agent/billing-bot/allowed_mcps/by_label",
+            &json!({ "selector": "team", "reason": null }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,146 @@
+mod common;
+
+use common::TestServer;
+use mception_server::core::interpolation::{has_placeholder, interpolate};
+use mception_server::core::{AuditAction, ConfigurationError, MceptionError, McpTransport};
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+
+const TOKEN: &str = "Bearer ${MCEPTION_SEARCH_TOKEN}";
+
+async fn create_search(server: &TestServer) {
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({
+                "id": "search",
+                "config": {
+                    "transport": {
+                        "type": "https",
+                        "url": "https://search.example.com/mcp",
+                        "headers": { "Authorization": TOKEN, "X-Region": "eu" }
+                    },
+                    "is_local": false,
+                    "reachable_by_agent": false,
+                    "config": {}
+                },
+                "reason": null
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+#[tokio::test]
+async fn secrets_are_redacted_unless_revealed() {
+    let server = TestServer::start().await;
+    create_search(&server).await;
+
+    let (_, leaf) = server.admin_get("/leaf/search/config").await;
+    let headers = &leaf["transport"]["headers"];
+    assert_eq!(headers["Authorization"], "[REDACTED]");
+    assert_eq!(headers["X-Region"], "eu");
+    let (_, listed) = server.admin_get("/leaf").await;
+    assert_eq!(
+        listed["leaf_mcps"]["search"]["transport"]["headers"]["Authorization"],
+        "[REDACTED]"
+    );
+    let (_, exported) = server.admin_get("/config").await;
+    assert_eq!(
+        exported["leaf_mcps"]["search"]["transport"]["headers"]["Authorization"],
+        "[REDACTED]"
+    );
+    let created = &server.audit_entries().await[0];
+    assert!(
+        !created
+            .details
+            .to_string()
+            .contains("MCEPTION_SEARCH_TOKEN")
+    );
+
+    // Revealing shows the stored placeholder, never its value, and is audited
+    let (status, leaf) = server.admin_get("/leaf/search/config?reveal=true").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(leaf["transport"]["headers"]["Authorization"], TOKEN);
+    let revealed = server.audit_entries().await.pop().unwrap();
+    assert!(matches!(revealed.action, AuditAction::Read));
+    assert_eq!(revealed.details["revealed"], true);
+}
+
+#[tokio::test]
+async fn sending_back_redacted_values_keeps_the_stored_ones() {
+    let server = TestServer::start().await;
+    create_search(&server).await;
+    let (_, mut leaf) = server.admin_get("/leaf/search/config").await;
+    leaf["transport"]["headers"]["X-Region"] = "us".into();
+    let transport = leaf["transport"].clone();
+    let (status, body) = server
+        .admin_json(
+            Method::PUT,
+            "/leaf/search/config",
+            &json!({ "config": { "transport": transport } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let saved = server.saved_config();
+    let McpTransport::Https { headers, .. } = &saved.leaf_mcps["search"].transport else {
+        panic!("search isn't an https leaf");
+    };
+    let headers = headers.as_ref().unwrap();
+    assert_eq!(headers["Authorization"], TOKEN);
+    assert_eq!(headers["X-Region"], "us");
+    let updated = server.audit_entries().await.pop().unwrap();
+    assert!(matches!(updated.action, AuditAction::Update));
+    assert!(
+        !updated
+            .details
+            .to_string()
+            .contains("MCEPTION_SEARCH_TOKEN")
+    );
+}
+
+#[test]
+fn placeholders_are_interpolated() {
+    let lookup = |name: &str| match name {
+        "TOKEN" => Some("abc".to_string()),
+        "INNER" => Some("${TOKEN}".to_string()),
+        _ => None,
+    };
+    assert_eq!(
+        interpolate("Bearer ${TOKEN}", lookup).unwrap(),
+        "Bearer abc"
+    );
+    assert_eq!(
+        interpolate("$5 and $TOKEN", lookup).unwrap(),
+        "$5 and $TOKEN"
+    );
+
+    // Looked up values aren't interpolated again, and a placeholder around
+    // another one isn't one
+    assert_eq!(interpolate("${INNER}", lookup).unwrap(), "${TOKEN}");
+    assert_eq!(
+        interpolate("${OUTER_${TOKEN}}", lookup).unwrap(),
+        "${OUTER_abc}"
+    );
+
+    // Escaped placeholders are kept literally, without being looked up
+    assert_eq!(
+        interpolate("$${MISSING} is ${TOKEN}", lookup).unwrap(),
+        "${MISSING} is abc"
+    );
+    assert!(!has_placeholder("$${MISSING}"));
+    assert!(has_placeholder("$${MISSING} ${TOKEN}"));
+
+    let missing = interpolate("${TOKEN}:${MISSING}", lookup).unwrap_err();
+    assert!(
+        matches!(
+            &missing,
+            MceptionError::Configuration(ConfigurationError::MissingRequiredField(field))
+                if field.contains("MISSING")
+        ),
+        "{}",
+        missing
+    );
+}
@@ -0,0 +1,195 @@
+mod common;
+
+use axum::Router;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::post;
+use common::leaf_mcp;
+use mception_server::core::McpTransport;
+use mception_server::services::ConfigService;
+use mception_server::services::leaf_sessions::{self, LeafSessions};
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::{RouterOptions, build_router};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Sessions a fake streamable HTTP MCP issued and still accepts, and how
+/// often it was initialized
+#[derive(Default)]
+struct Upstream {
+    sessions: Mutex<BTreeSet<String>>,
+    initialized: Mutex<usize>,
+}
+
+impl Upstream {
+    fn initialized(&self) -> usize {
+        *self.initialized.lock().unwrap()
+    }
+}
+
+async fn handle(
+    State(upstream): State<Arc<Upstream>>,
+    headers: HeaderMap,
+    body: String,
+) -> axum::response::Response {
+    let message: Value = serde_json::from_str(&body).unwrap();
+    let id = &message["id"];
+    if message["method"] == "initialize" {
+        let mut initialized = upstream.initialized.lock().unwrap();
+        *initialized += 1;
+        let session = format!("session-{}", initialized);
+        upstream.sessions.lock().unwrap().insert(session.clone());
+        let response = json!({ "jsonrpc": "2.0", "id": id, "result": {
+            "protocolVersion": "2025-06-18", "capabilities": { "tools": {} }
+        }});
+        return ([("mcp-session-id", session)], axum::Json(response)).into_response();
+    }
+    let session = headers
+        .get("mcp-session-id")
+        .and_then(|session| session.to_str().ok())
+        .unwrap_or_default();
+    if !upstream.sessions.lock().unwrap().contains(session) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let result = match message["method"].as_str() {
+        Some("tools/list") => json!({ "tools": [{ "name": "search", "inputSchema": {} }] }),
+        Some("ping") => json!({}),
+        _ => return StatusCode::ACCEPTED.into_response(),
+    };
+    axum::Json(json!({ "jsonrpc": "2.0", "id": id, "result": result })).into_response()
+}
+
+async fn serve_upstream() -> (String, Arc<Upstream>) {
+    let upstream = Arc::new(Upstream::default());
+    let app = Router::new()
+        .route("/mcp", post(handle))
+        .with_state(upstream.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (url, upstream)
+}
+
+fn https(url: &str, token: &str) -> McpTransport {
+    McpTransport::Https {
+        url: url.to_string(),
+        headers: Some(BTreeMap::from([(
+            "authorization".to_string(),
+            format!("Bearer {}", token),
+        )])),
+    }
+}
+
+/// A server run on the configuration in `dir`, as started again after a restart
+async fn run(dir: &Path) -> Arc<ConfigService> {
+    let service = ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    )
+    .with_leaf_sessions(LeafSessions::open(dir.join("leaf-sessions.json")).unwrap());
+    service.load_configuration().await.unwrap();
+    Arc::new(service)
+}
+
+async fn setup(url: &str) -> (PathBuf, Arc<ConfigService>) {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let service = run(&dir).await;
+    let leaf = leaf_mcp(https(url, "s3cret"));
+    service
+        .create_leaf_mcp(Some("search".to_string()), leaf, None, None)
+        .await
+        .unwrap();
+    (dir, service)
+}
+
+#[tokio::test]
+async fn sessions_are_resumed_after_a_restart() {
+    let (url, upstream) = serve_upstream().await;
+    let (dir, service) = setup(&url).await;
+    service.leaf_mcp_tools("search", true).await.unwrap();
+    service.leaf_mcp_tools("search", true).await.unwrap();
+    assert_eq!(upstream.initialized(), 1);
+    let session = service.leaf_mcp_session("search").await.unwrap().unwrap();
+    assert!(!session.resumed);
+
+    // Only the opaque session id is persisted, no credentials
+    let persisted = std::fs::read_to_string(dir.join("leaf-sessions.json")).unwrap();
+    assert!(persisted.contains("session-1"), "{}", persisted);
+    assert!(!persisted.contains("s3cret"), "{}", persisted);
+    drop(service);
+
+    let service = run(&dir).await;
+    assert!(service.leaf_mcp_session("search").await.unwrap().is_none());
+    assert_eq!(leaf_sessions::resume(service.clone()).await, 1);
+    service.leaf_mcp_tools("search", true).await.unwrap();
+    assert_eq!(upstream.initialized(), 1);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let admin = format!("http://{}/admin", listener.local_addr().unwrap());
+    let router = build_router(service.clone(), RouterOptions::default());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    let body: Value = reqwest::get(format!("{}/leaf/search/session", admin))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["session"]["resumed"], true);
+    assert_eq!(body["session"]["endpoint"], url);
+    let status = reqwest::get(format!("{}/leaf/missing/session", admin))
+        .await
+        .unwrap()
+        .status();
+    assert_eq!(status, 404);
+
+    // A session the leaf MCP forgot meanwhile is initialized anew
+    upstream.sessions.lock().unwrap().clear();
+    drop(service);
+    let service = run(&dir).await;
+    assert_eq!(leaf_sessions::resume(service.clone()).await, 0);
+    service.leaf_mcp_tools("search", true).await.unwrap();
+    assert_eq!(upstream.initialized(), 2);
+    let session = service.leaf_mcp_session("search").await.unwrap().unwrap();
+    assert!(!session.resumed);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn sessions_are_dropped_when_the_transport_changes() {
+    let (url, upstream) = serve_upstream().await;
+    let (dir, service) = setup(&url).await;
+    service.leaf_mcp_tools("search", true).await.unwrap();
+
+    // A refused session falls back to a fresh initialize
+    upstream.sessions.lock().unwrap().clear();
+    service.leaf_mcp_tools("search", true).await.unwrap();
+    assert_eq!(upstream.initialized(), 2);
+
+    service
+        .update_leaf_mcp(
+            "search",
+            json!({ "transport": https(&url, "rotated") }),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    service.leaf_mcp_tools("search", true).await.unwrap();
+    assert_eq!(upstream.initialized(), 3);
+    drop(service);
+
+    // Removing the leaf MCP removes its session
+    let service = run(&dir).await;
+    service.delete_leaf_mcp("search", None, None).await.unwrap();
+    let persisted = std::fs::read_to_string(dir.join("leaf-sessions.json")).unwrap();
+    assert_eq!(persisted.trim(), "{}");
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use common::TestServer;
+use mception_server::core::AuditAction;
+use mception_server::services::logging::{DEFAULT_DIRECTIVES, LogControl};
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+use std::time::Duration;
+use tracing::{Dispatch, Level};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::{EnvFilter, Registry, reload};
+
+/// A server whose log filter is the one of the returned dispatcher
+async fn server_with_log_control() -> (TestServer, Dispatch) {
+    let (filter, handle) = reload::Layer::new(EnvFilter::new(DEFAULT_DIRECTIVES));
+    let subscriber = Registry::default().with(filter);
+    let server = TestServer::builder()
+        .log_control(LogControl::new(handle))
+        .start()
+        .await;
+    (server, Dispatch::new(subscriber))
+}
+
+async fn set_logging(server: &TestServer, request: Value) -> (StatusCode, Value) {
+    server.admin_json(Method::PUT, "/logging", &request).await
+}
+
+/// Details of the audited log filter changes
+async fn audited_changes(server: &TestServer) -> Vec<(Option<String>, Value)> {
+    server
+        .audit_entries()
+        .await
+        .into_iter()
+        .filter(|entry| matches!(entry.action, AuditAction::SetLogLevel))
+        .map(|entry| (entry.actor, entry.details))
+        .collect()
+}
+
+#[tokio::test]
+async fn the_log_filter_changes_at_runtime() {
+    let (server, dispatch) = server_with_log_control().await;
+    let debug_enabled =
+        || tracing::dispatcher::with_default(&dispatch, || tracing::enabled!(Level::DEBUG));
+    assert!(!debug_enabled());
+
+    let (status, body) = server.admin_get("/logging").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["directives"], DEFAULT_DIRECTIVES);
+    assert_eq!(body["revert_at"], Value::Null);
+
+    let (status, body) = set_logging(
+        &server,
+        json!({
+            "level": "debug",
+            "filter": "mception_server::services=trace",
+            "reason": "chasing a leak"
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["directives"], "debug,mception_server::services=trace");
+    assert_eq!(body["default_directives"], DEFAULT_DIRECTIVES);
+    assert!(debug_enabled());
+    let (_, body) = server.admin_get("/logging").await;
+    assert_eq!(body["directives"], "debug,mception_server::services=trace");
+
+    // Invalid directives leave the filter as it was
+    let (status, body) = set_logging(&server, json!({ "level": "debug,[unclosed" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    let (status, body) = set_logging(&server, json!({ "level": "info", "duration": "0s" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    assert!(debug_enabled());
+
+    let changes = audited_changes(&server).await;
+    assert_eq!(changes.len(), 1, "{:?}", changes);
+    assert_eq!(changes[0].0.as_deref(), Some("admin"));
+    assert_eq!(
+        changes[0].1,
+        json!({
+            "previous": "info",
+            "directives": "debug,mception_server::services=trace",
+            "revert_at": null
+        })
+    );
+}
+
+#[tokio::test]
+async fn a_timed_change_reverts_unless_replaced() {
+    let (server, dispatch) = server_with_log_control().await;
+    let debug_enabled =
+        || tracing::dispatcher::with_default(&dispatch, || tracing::enabled!(Level::DEBUG));
+
+    let (status, body) = set_logging(&server, json!({ "level": "debug", "duration": "1s" })).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(body["revert_at"].is_string());
+    assert!(debug_enabled());
+    tokio::time::sleep(Duration::from_millis(1300)).await;
+
+    let (_, body) = server.admin_get("/logging").await;
+    assert_eq!(body["directives"], DEFAULT_DIRECTIVES);
+    assert_eq!(body["revert_at"], Value::Null);
+    assert!(!debug_enabled());
+    let changes = audited_changes(&server).await;
+    assert_eq!(changes.len(), 2, "{:?}", changes);
+    assert_eq!(changes[1].0.as_deref(), Some("system"));
+    assert_eq!(changes[1].1["directives"], DEFAULT_DIRECTIVES);
+
+    // The timer of an earlier change doesn't revert a later one
+    let (status, _) = set_logging(&server, json!({ "level": "debug", "duration": "1s" })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = set_logging(&server, json!({ "level": "trace" })).await;
+    assert_eq!(status, StatusCode::OK);
+    tokio::time::sleep(Duration::from_millis(1300)).await;
+    let (_, body) = server.admin_get("/logging").await;
+    assert_eq!(body["directives"], "trace");
+    assert_eq!(audited_changes(&server).await.len(), 4);
+}
+
+#[tokio::test]
+async fn without_log_control_the_filter_cannot_be_changed() {
+    let server = TestServer::start().await;
+    let (status, _) = server.admin_get("/logging").await;
+    assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+    let (status, _) = set_logging(&server, json!({ "level": "debug" })).await;
+    assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+    assert!(audited_changes(&server).await.is_empty());
+}
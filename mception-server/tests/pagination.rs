@@ -0,0 +1,386 @@
+mod common;
+
+use common::{TestServer, leaf_mcp};
+use mception_server::core::pagination::{
+    Cursor, MAX_LIMIT, MAX_REVISION_LAG, PageError, PageQuery, paginate,
+};
+use mception_server::core::{BuiltinMcpKind, GrantSource, McpTransport};
+use mception_server::services::ConfigService;
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::{RouterOptions, build_router};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+fn key(item: &(u32, &'static str)) -> (String, String) {
+    (format!("{:04}", item.0), item.1.to_string())
+}
+
+fn page(
+    items: &[(u32, &'static str)],
+    revision: u64,
+    query: PageQuery,
+) -> Result<(Vec<&'static str>, Option<String>), PageError> {
+    paginate("items", items.to_vec(), key, revision, &query).map(|page| {
+        (
+            page.items.iter().map(|item| item.1).collect(),
+            page.next_cursor,
+        )
+    })
+}
+
+fn limit(limit: usize) -> PageQuery {
+    PageQuery {
+        limit: Some(limit),
+        ..PageQuery::default()
+    }
+}
+
+fn after(cursor: &str, limit: usize) -> PageQuery {
+    PageQuery {
+        limit: Some(limit),
+        offset: None,
+        cursor: Some(cursor.to_string()),
+    }
+}
+
+#[test]
+fn cursor_round_trips_and_rejects_garbage() {
+    let cursor = Cursor {
+        list: "audit".to_string(),
+        key: "2026-01-01T00:00:00Z".to_string(),
+        id: "ä/\"id\"".to_string(),
+        revision: 7,
+    };
+    assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+
+    for garbage in ["", "abc", "zz", "7b7d", "é0", "not a cursor"] {
+        assert!(
+            matches!(Cursor::decode(garbage), Err(PageError::InvalidCursor(_))),
+            "{}",
+            garbage
+        );
+    }
+}
+
+#[test]
+fn pages_through_a_list_in_key_order() {
+    let items = [(3, "c"), (1, "a"), (2, "b2"), (2, "b1"), (4, "d")];
+
+    let (first, cursor) = page(&items, 1, limit(2)).unwrap();
+    assert_eq!(first, vec!["a", "b1"]);
+    let (second, cursor) = page(&items, 1, after(&cursor.unwrap(), 2)).unwrap();
+    assert_eq!(second, vec!["b2", "c"]);
+    let (last, cursor) = page(&items, 1, after(&cursor.unwrap(), 2)).unwrap();
+    assert_eq!(last, vec!["d"]);
+    assert_eq!(cursor, None);
+
+    // An exactly full last page has no cursor either
+    let (all, cursor) = page(&items, 1, limit(5)).unwrap();
+    assert_eq!(all.len(), 5);
+    assert_eq!(cursor, None);
+}
+
+#[test]
+fn returns_everything_without_parameters() {
+    let items = [(2, "b"), (1, "a")];
+    let (all, cursor) = page(&items, 1, PageQuery::default()).unwrap();
+    assert_eq!(all, vec!["a", "b"]);
+    assert_eq!(cursor, None);
+
+    let (empty, cursor) = page(&[], 1, limit(10)).unwrap();
+    assert!(empty.is_empty());
+    assert_eq!(cursor, None);
+}
+
+#[test]
+fn changes_between_pages_are_neither_skipped_nor_repeated() {
+    let items = vec![(1, "a"), (2, "b"), (3, "c"), (4, "d")];
+    let (first, cursor) = page(&items, 4, limit(2)).unwrap();
+    assert_eq!(first, vec!["a", "b"]);
+
+    // The last item of the page is deleted and items are inserted on both sides
+    let items = vec![(0, "z"), (1, "a"), (3, "c"), (4, "d"), (5, "e")];
+    let (rest, cursor) = page(&items, 7, after(&cursor.unwrap(), 10)).unwrap();
+    assert_eq!(rest, vec!["c", "d", "e"]);
+    assert_eq!(cursor, None);
+}
+
+#[test]
+fn offset_and_limit_keep_working() {
+    let items = [(1, "a"), (2, "b"), (3, "c")];
+    let query = PageQuery {
+        limit: Some(1),
+        offset: Some(1),
+        cursor: None,
+    };
+    let (middle, cursor) = page(&items, 1, query).unwrap();
+    assert_eq!(middle, vec!["b"]);
+    let (rest, _) = page(&items, 1, after(&cursor.unwrap(), 5)).unwrap();
+    assert_eq!(rest, vec!["c"]);
+
+    let query = PageQuery {
+        offset: Some(10),
+        ..PageQuery::default()
+    };
+    assert!(page(&items, 1, query).unwrap().0.is_empty());
+
+    let (_, cursor) = page(&items, 1, limit(1)).unwrap();
+    let query = PageQuery {
+        limit: None,
+        offset: Some(1),
+        cursor,
+    };
+    assert!(matches!(
+        page(&items, 1, query),
+        Err(PageError::InvalidQuery(_))
+    ));
+}
+
+#[test]
+fn rejects_out_of_range_limits() {
+    let items = [(1, "a")];
+    assert!(matches!(
+        page(&items, 1, limit(0)),
+        Err(PageError::InvalidQuery(_))
+    ));
+    assert!(matches!(
+        page(&items, 1, limit(MAX_LIMIT + 1)),
+        Err(PageError::InvalidQuery(_))
+    ));
+    assert!(page(&items, 1, limit(MAX_LIMIT)).is_ok());
+}
+
+#[test]
+fn rejects_stale_and_foreign_cursors() {
+    let items = [(1, "a"), (2, "b")];
+    let (_, cursor) = page(&items, 10, limit(1)).unwrap();
+    let cursor = cursor.unwrap();
+
+    assert!(page(&items, 10 + MAX_REVISION_LAG, after(&cursor, 1)).is_ok());
+    assert_eq!(
+        page(&items, 11 + MAX_REVISION_LAG, after(&cursor, 1)),
+        Err(PageError::StaleCursor {
+            cursor_revision: 10,
+            current_revision: 11 + MAX_REVISION_LAG,
+        })
+    );
+    // The list was reset to an older revision
+    assert!(matches!(
+        page(&items, 9, after(&cursor, 1)),
+        Err(PageError::StaleCursor { .. })
+    ));
+
+    let other = paginate("other", items.to_vec(), key, 10, &after(&cursor, 1));
+    assert!(matches!(other, Err(PageError::InvalidCursor(_))));
+}
+
+#[tokio::test]
+async fn pages_the_audit_log_over_http() {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let service = Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ));
+    for name in ["one", "two", "three"] {
+        service
+            .create_agent(Some(name.to_string()), None, Vec::new(), None)
+            .await
+            .unwrap();
+    }
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/admin/audit", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        axum::serve(listener, build_router(service, RouterOptions::default()))
+            .await
+            .unwrap();
+    });
+    let client = reqwest::Client::new();
+
+    let mut ids = Vec::new();
+    let mut query = vec![("limit", "2".to_string())];
+    loop {
+        let response: Value = client
+            .get(&url)
+            .query(&query)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        for entry in response["entries"].as_array().unwrap() {
+            ids.push(entry["id"].as_str().unwrap().to_string());
+        }
+        match response["next_cursor"].as_str() {
+            Some(cursor) => query = vec![("limit", "2".to_string()), ("cursor", cursor.into())],
+            None => break,
+        }
+    }
+    assert_eq!(ids.len(), 3);
+    assert_eq!(ids.iter().collect::<BTreeSet<_>>().len(), 3);
+
+    let response = client
+        .get(&url)
+        .query(&[("cursor", "garbage")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("Invalid cursor")
+    );
+
+    let stale = Cursor {
+        list: "audit".to_string(),
+        key: String::new(),
+        id: String::new(),
+        revision: 100,
+    };
+    let response = client
+        .get(&url)
+        .query(&[("cursor", stale.encode())])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 410);
+    let body: Value = response.json().await.unwrap();
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("restart")
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn filters_the_audit_log_over_http() {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let service = Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ));
+    for (name, actor) in [("one", "alice"), ("two", "bob"), ("three", "alice")] {
+        service
+            .create_agent(
+                Some(name.to_string()),
+                None,
+                Vec::new(),
+                Some(actor.to_string()),
+            )
+            .await
+            .unwrap();
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    let middle = chrono::Utc::now();
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    service
+        .delete_agent("two", Some("bob".to_string()), None)
+        .await
+        .unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/admin/audit", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        axum::serve(listener, build_router(service, RouterOptions::default()))
+            .await
+            .unwrap();
+    });
+    let get = |query: Vec<(&'static str, String)>| {
+        let request = reqwest::Client::new().get(&url).query(&query);
+        async move { request.send().await.unwrap().json::<Value>().await.unwrap() }
+    };
+    let actors = |response: &Value| -> Vec<String> {
+        response["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["actor"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let response = get(vec![("actor", "ALICE".into())]).await;
+    assert_eq!(actors(&response), ["alice", "alice"]);
+    assert_eq!(response["total"], 2);
+
+    let response = get(vec![
+        ("action", "delete".into()),
+        ("target_type", "agent".into()),
+    ])
+    .await;
+    assert_eq!(response["total"], 1);
+    assert_eq!(response["entries"][0]["target"]["id"], "two");
+
+    let response = get(vec![("since", middle.to_rfc3339())]).await;
+    assert_eq!(actors(&response), ["bob"]);
+    let response = get(vec![("until", middle.to_rfc3339())]).await;
+    assert_eq!(actors(&response), ["alice", "bob", "alice"]);
+
+    let response = get(vec![("limit", "2".into()), ("offset", "1".into())]).await;
+    assert_eq!(response["total"], 4);
+    assert_eq!(response["returned"], 2);
+    assert_eq!(response["offset"], 1);
+    assert_eq!(actors(&response), ["bob", "alice"]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn filters_match_the_names_entries_are_listed_with() {
+    let server = TestServer::start().await;
+    let service = &server.service;
+    service
+        .create_leaf_mcp(
+            Some("search".to_string()),
+            leaf_mcp(McpTransport::Builtin {
+                kind: BuiltinMcpKind::Echo,
+            }),
+            Some("alice".to_string()),
+            None,
+        )
+        .await
+        .unwrap();
+    service
+        .create_agent(Some("bot".to_string()), None, Vec::new(), None)
+        .await
+        .unwrap();
+    service
+        .grant_agent_mcp("bot", "search", GrantSource::Direct, None, None, None)
+        .await
+        .unwrap();
+
+    let (_, listed) = server.admin_get("/audit").await;
+    let entries = listed["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 3, "{}", listed);
+    for entry in entries {
+        let (action, target_type) = (&entry["action"]["type"], &entry["target"]["type"]);
+        let (_, body) = server
+            .admin_get(&format!(
+                "/audit?action={}&target_type={}",
+                action.as_str().unwrap(),
+                target_type.as_str().unwrap()
+            ))
+            .await;
+        assert_eq!(body["total"], 1, "{} {}: {}", action, target_type, body);
+        assert_eq!(body["entries"][0]["id"], entry["id"]);
+    }
+    let (_, body) = server.admin_get("/audit?action=ADD_ALLOWED").await;
+    assert_eq!(body["entries"][0]["target"]["type"], "agent_allowed_mcp");
+    let (_, body) = server.admin_get("/audit?target_type=leafmcp").await;
+    assert_eq!(body["total"], 0);
+}
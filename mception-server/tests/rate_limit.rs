@@ -0,0 +1,165 @@
+mod common;
+
+use common::TestServer;
+use mception_server::core::{AuditAction, AuditTarget};
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+
+async fn create_agent(server: &TestServer, agent_id: &str) {
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/agent",
+            &json!({ "agent_id": agent_id, "allowed_mcp_ids": [] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+async fn fetch_config(server: &TestServer, agent_id: &str) -> reqwest::Response {
+    server
+        .request(Method::GET, &format!("/agent/{}/config", agent_id))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn agents_over_their_limit_are_rejected_and_audited_once() {
+    let server = TestServer::builder().agent_rate_limit(3).start().await;
+    create_agent(&server, "writer").await;
+    create_agent(&server, "reader").await;
+
+    for _ in 0..3 {
+        assert_eq!(
+            fetch_config(&server, "writer").await.status(),
+            StatusCode::OK
+        );
+    }
+    for _ in 0..5 {
+        let response = fetch_config(&server, "writer").await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = response.headers()["retry-after"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=20).contains(&retry_after), "{}", retry_after);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["kind"], "rate_limited");
+    }
+    // Every agent has its own limit
+    assert_eq!(
+        fetch_config(&server, "reader").await.status(),
+        StatusCode::OK
+    );
+
+    let entries = server.audit_entries().await;
+    let limited: Vec<_> = entries
+        .iter()
+        .filter(|entry| matches!(entry.action, AuditAction::RateLimited))
+        .collect();
+    assert_eq!(limited.len(), 1);
+    assert!(matches!(&limited[0].target, AuditTarget::Agent { id } if id == "writer"));
+    assert_eq!(limited[0].details["requests_per_minute"], 3);
+}
+
+#[tokio::test]
+async fn agents_can_have_their_own_limit() {
+    let server = TestServer::start().await;
+    create_agent(&server, "writer").await;
+
+    let (status, body) = server
+        .admin_json(
+            Method::PUT,
+            "/agent/writer/config",
+            &json!({ "config": { "config": { "rate_limit": "fast" } }, "reason": null }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    let (status, body) = server
+        .admin_json(
+            Method::PUT,
+            "/agent/writer/config",
+            &json!({ "config": { "config": { "rate_limit": 2 } }, "reason": null }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    for _ in 0..2 {
+        assert_eq!(
+            fetch_config(&server, "writer").await.status(),
+            StatusCode::OK
+        );
+    }
+    assert_eq!(
+        fetch_config(&server, "writer").await.status(),
+        StatusCode::TOO_MANY_REQUESTS
+    );
+}
+
+#[tokio::test]
+async fn leaf_calls_naming_no_agent_are_limited_per_client() {
+    let server = TestServer::builder().agent_rate_limit(2).start().await;
+    create_agent(&server, "writer").await;
+    let call = |agent_id: Option<&str>| {
+        let request = server.request(Method::POST, "/leaf/search/forwarding");
+        let request = match agent_id {
+            Some(agent_id) => request.header("x-mception-agent-id", agent_id),
+            None => request,
+        };
+        request
+            .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" }))
+            .send()
+    };
+
+    for _ in 0..2 {
+        let status = call(None).await.unwrap().status();
+        assert_ne!(status, StatusCode::TOO_MANY_REQUESTS);
+    }
+    let response = call(None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers().contains_key("retry-after"));
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "proxy_overloaded");
+    assert_eq!(body["error"]["leaf_mcp_id"], "search");
+
+    // Calls naming an agent count against the agent instead
+    let status = call(Some("writer")).await.unwrap().status();
+    assert_ne!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    let entries = server.audit_entries().await;
+    let limited = entries
+        .iter()
+        .find(|entry| matches!(entry.action, AuditAction::RateLimited))
+        .unwrap();
+    assert!(matches!(limited.target, AuditTarget::Server));
+    assert_eq!(limited.details["client"], "127.0.0.1");
+}
+
+#[tokio::test]
+async fn requests_for_unknown_agents_count_against_the_client() {
+    let server = TestServer::builder().agent_rate_limit(2).start().await;
+
+    // Each new id would otherwise get a full bucket of its own
+    for agent_id in ["ghost-1", "ghost-2"] {
+        let status = fetch_config(&server, agent_id).await.status();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+    let status = fetch_config(&server, "ghost-3").await.status();
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    let status = server
+        .request(Method::POST, "/leaf/search/forwarding")
+        .header("x-mception-agent-id", "ghost-4")
+        .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" }))
+        .send()
+        .await
+        .unwrap()
+        .status();
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    // Agents that exist still have their own bucket
+    create_agent(&server, "writer").await;
+    let status = fetch_config(&server, "writer").await.status();
+    assert_eq!(status, StatusCode::OK);
+}
@@ -0,0 +1,244 @@
+mod common;
+
+use common::leaf_mcp;
+use mception_server::core::{
+    AuditTarget, LeafMcpConfig, McpTransport, RegistrationPolicy, StdioSandbox,
+};
+use mception_server::services::{ConfigService, discovery};
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::{RouterOptions, build_router};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+fn service() -> Arc<ConfigService> {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ))
+}
+
+fn stdio(command: &str) -> LeafMcpConfig {
+    leaf_mcp(McpTransport::Stdio {
+        command: command.to_string(),
+        args: vec![],
+        env: None,
+        sandbox: StdioSandbox::default(),
+    })
+}
+
+fn https(url: &str) -> LeafMcpConfig {
+    leaf_mcp(McpTransport::Https {
+        url: url.to_string(),
+        headers: None,
+    })
+}
+
+fn policy() -> RegistrationPolicy {
+    RegistrationPolicy {
+        stdio_commands: vec!["npx".to_string(), "/opt/mcp/bin/*".to_string()],
+        https_domains: vec!["*.example.com".to_string()],
+    }
+}
+
+async fn serve(service: Arc<ConfigService>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/admin", listener.local_addr().unwrap());
+    let router = build_router(service, RouterOptions::default());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    url
+}
+
+#[tokio::test]
+async fn disallowed_leaf_mcps_are_unprocessable() {
+    let service = service();
+    service
+        .set_registration_policy(Some(policy()), None, None)
+        .await
+        .unwrap();
+    let url = serve(service.clone()).await;
+    let client = reqwest::Client::new();
+    let create = |config: LeafMcpConfig| {
+        client
+            .post(format!("{}/leaf", url))
+            .json(&json!({ "config": config, "reason": null, "should_create": true }))
+            .send()
+    };
+
+    let response = create(stdio("/opt/mcp/bin/files")).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let response = create(https("https://mcp.example.com/mcp")).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = create(stdio("/usr/bin/curl")).await.unwrap();
+    assert_eq!(response.status().as_u16(), 422);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["kind"], "policy_violation");
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("rule `stdio_commands`")
+    );
+
+    // The bare parent domain is not a subdomain
+    let response = create(https("https://example.com/mcp")).await.unwrap();
+    assert_eq!(response.status().as_u16(), 422);
+    let body: Value = response.json().await.unwrap();
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("rule `https_domains`")
+    );
+
+    // Moving an allowed leaf MCP to a disallowed command is refused as well
+    service
+        .create_leaf_mcp(Some("node".to_string()), stdio("npx"), None, None)
+        .await
+        .unwrap();
+    let response = client
+        .put(format!("{}/leaf/node/config", url))
+        .json(&json!({
+            "config": { "transport": { "type": "stdio", "command": "bash", "args": [], "env": null } },
+            "reason": null,
+            "should_update": true
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 422);
+    let leaf = service.get_leaf_mcp("node", None).await.unwrap();
+    assert!(matches!(leaf.transport, McpTransport::Stdio { command, .. } if command == "npx"));
+}
+
+#[tokio::test]
+async fn setting_a_policy_reports_and_audits_violations() {
+    let service = service();
+    service
+        .create_leaf_mcp(Some("shell".to_string()), stdio("bash"), None, None)
+        .await
+        .unwrap();
+    service
+        .create_leaf_mcp(Some("node".to_string()), stdio("npx"), None, None)
+        .await
+        .unwrap();
+
+    let report = service.registration_policy_report(&policy()).await.unwrap();
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].leaf_mcp_id, "shell");
+    // Only a report, nothing is set yet
+    assert!(service.registration_policy().await.is_none());
+
+    let violations = service
+        .set_registration_policy(Some(policy()), Some("admin".to_string()), None)
+        .await
+        .unwrap();
+    assert_eq!(violations.len(), 1);
+    assert_eq!(service.registration_policy().await, Some(policy()));
+    // Registered leaf MCPs are kept
+    assert_eq!(service.list_leaf_mcps().await.unwrap().len(), 2);
+
+    let entries = service.get_audit_logs().await.unwrap();
+    let entry = entries.last().unwrap();
+    assert!(matches!(entry.target, AuditTarget::RegistrationPolicy));
+    assert_eq!(entry.details["violations"][0]["leaf_mcp_id"], "shell");
+    assert_eq!(entry.details["violations"][0]["rule"], "stdio_commands");
+
+    let invalid = RegistrationPolicy {
+        stdio_commands: vec!["/opt/*/bin".to_string()],
+        https_domains: vec![],
+    };
+    assert!(
+        service
+            .set_registration_policy(Some(invalid), None, None)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn policy_endpoints() {
+    let service = service();
+    service
+        .create_leaf_mcp(
+            Some("elsewhere".to_string()),
+            https("https://mcp.other.org/mcp"),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    let url = serve(service).await;
+    let client = reqwest::Client::new();
+
+    let body: Value = client
+        .post(format!("{}/policy/report", url))
+        .json(&policy())
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["violations"][0]["leaf_mcp_id"], "elsewhere");
+    assert_eq!(body["violations"][0]["rule"], "https_domains");
+
+    let response = client
+        .put(format!("{}/policy", url))
+        .json(&json!({ "policy": policy(), "reason": "lock down" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let body: Value = reqwest::get(format!("{}/policy", url))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["policy"]["https_domains"], json!(["*.example.com"]));
+
+    let response = client
+        .put(format!("{}/policy", url))
+        .json(&json!({ "policy": null }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let body: Value = reqwest::get(format!("{}/policy", url))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(body["policy"].is_null());
+}
+
+#[tokio::test]
+async fn discovered_candidates_outside_the_policy_are_skipped() {
+    let service = service();
+    service
+        .set_registration_policy(Some(policy()), None, None)
+        .await
+        .unwrap();
+    let found = discovery::parse(
+        "claude_desktop_config.json",
+        r#"{ "mcpServers": {
+            "files": { "command": "npx", "args": ["-y", "@modelcontextprotocol/server-filesystem"] },
+            "shell": { "command": "bash", "args": [] }
+        } }"#,
+    )
+    .unwrap();
+
+    let checked = service.check_discovered(found).await;
+    assert_eq!(checked.candidates.len(), 1);
+    assert_eq!(checked.candidates[0].name, "files");
+    assert_eq!(checked.skipped.len(), 1);
+    assert!(checked.skipped[0].reason.contains("rule `stdio_commands`"));
+}
@@ -0,0 +1,194 @@
+mod common;
+
+use common::leaf_mcp;
+use mception_server::core::{BuiltinMcpKind, LeafMcpConfig, McpTransport};
+use mception_server::services::ConfigService;
+use mception_server::services::authorization::AGENT_HEADER;
+use mception_server::services::replicas::REPLICA_HEADER;
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::{RouterOptions, build_router};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+fn replica(id: &str, region: &str, transport: McpTransport) -> LeafMcpConfig {
+    LeafMcpConfig {
+        id: id.to_string(),
+        region: Some(region.to_string()),
+        replica_group: Some("search".to_string()),
+        ..leaf_mcp(transport)
+    }
+}
+
+fn echo() -> McpTransport {
+    McpTransport::Builtin {
+        kind: BuiltinMcpKind::Echo,
+    }
+}
+
+/// A service with the `search` replicas `search-eu` and `search-us`, and the
+/// agents `agent-eu`, `agent-us` and `agent-none` allowed to use both
+async fn service_with_replicas(us: McpTransport) -> Arc<ConfigService> {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let service = Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ));
+    for (id, region, transport) in [("search-eu", "eu", echo()), ("search-us", "us", us)] {
+        service
+            .create_leaf_mcp(
+                Some(id.to_string()),
+                replica(id, region, transport),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+    }
+    for (agent, region) in [
+        ("agent-eu", Some("eu")),
+        ("agent-us", Some("us")),
+        ("agent-none", None),
+    ] {
+        service
+            .create_agent(
+                Some(agent.to_string()),
+                None,
+                vec!["search-eu".to_string(), "search-us".to_string()],
+                None,
+            )
+            .await
+            .unwrap();
+        service
+            .update_agent(agent, json!({ "region": region }), None, None)
+            .await
+            .unwrap();
+    }
+    service
+}
+
+/// Ids of the leaf MCPs in an agent's remote configuration
+async fn remote_mcps(service: &ConfigService, agent_id: &str) -> Vec<String> {
+    let remote = service.get_agent_remote_config(agent_id).await.unwrap();
+    for entry in remote.mcps.values() {
+        assert_eq!(entry.replica_group.as_deref(), Some("search"));
+    }
+    remote.mcps.keys().cloned().collect()
+}
+
+async fn serve(service: Arc<ConfigService>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        axum::serve(listener, build_router(service, RouterOptions::default()))
+            .await
+            .unwrap();
+    });
+    url
+}
+
+/// Call `echo` on `leaf` as `agent`, returning the replica header, if any
+async fn forward(url: &str, leaf: &str, agent: &str) -> (u16, Option<String>) {
+    let response = reqwest::Client::new()
+        .post(format!("{}/leaf/{}/forwarding", url, leaf))
+        .header(AGENT_HEADER, agent)
+        .json(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": { "name": "echo", "arguments": { "text": "hello" } }
+        }))
+        .send()
+        .await
+        .unwrap();
+    let replica = response
+        .headers()
+        .get(REPLICA_HEADER)
+        .map(|value| value.to_str().unwrap().to_string());
+    (response.status().as_u16(), replica)
+}
+
+#[tokio::test]
+async fn agents_prefer_the_replica_in_their_region() {
+    let service = service_with_replicas(echo()).await;
+    assert_eq!(remote_mcps(&service, "agent-eu").await, ["search-eu"]);
+    assert_eq!(remote_mcps(&service, "agent-us").await, ["search-us"]);
+
+    let url = serve(service).await;
+    assert_eq!(
+        forward(&url, "search-us", "agent-eu").await,
+        (200, Some("search-eu".to_string()))
+    );
+    assert_eq!(forward(&url, "search-eu", "agent-eu").await, (200, None));
+}
+
+#[tokio::test]
+async fn falls_back_to_a_healthy_replica() {
+    let service = service_with_replicas(echo()).await;
+    service.replica_health().record_failure("search-eu").await;
+    assert_eq!(remote_mcps(&service, "agent-eu").await, ["search-us"]);
+
+    let url = serve(service.clone()).await;
+    assert_eq!(
+        forward(&url, "search-eu", "agent-eu").await,
+        (200, Some("search-us".to_string()))
+    );
+
+    let groups = serde_json::to_value(service.replica_groups().await).unwrap();
+    assert_eq!(groups[0]["name"], "search");
+    assert_eq!(groups[0]["replicas"][0]["id"], "search-eu");
+    assert_eq!(groups[0]["replicas"][0]["healthy"], false);
+    assert_eq!(groups[0]["replicas"][1]["healthy"], true);
+
+    service.replica_health().record_success("search-eu").await;
+    assert_eq!(remote_mcps(&service, "agent-eu").await, ["search-eu"]);
+}
+
+#[tokio::test]
+async fn failed_forwards_mark_a_replica_unhealthy() {
+    // Nothing listens on the discard port
+    let unreachable = McpTransport::Https {
+        url: "http://127.0.0.1:9/mcp".to_string(),
+        headers: None,
+    };
+    let service = service_with_replicas(unreachable).await;
+    let url = serve(service.clone()).await;
+
+    assert_eq!(forward(&url, "search-us", "agent-us").await, (502, None));
+    assert_eq!(remote_mcps(&service, "agent-us").await, ["search-eu"]);
+    assert_eq!(
+        forward(&url, "search-us", "agent-us").await,
+        (200, Some("search-eu".to_string()))
+    );
+}
+
+#[tokio::test]
+async fn agents_without_region_use_any_healthy_replica() {
+    let service = service_with_replicas(echo()).await;
+    assert_eq!(remote_mcps(&service, "agent-none").await, ["search-eu"]);
+
+    let url = serve(service.clone()).await;
+    // The requested replica is as good as any
+    assert_eq!(forward(&url, "search-us", "agent-none").await, (200, None));
+
+    service.replica_health().record_failure("search-us").await;
+    assert_eq!(
+        forward(&url, "search-us", "agent-none").await,
+        (200, Some("search-eu".to_string()))
+    );
+
+    let admin: Value = reqwest::get(format!("{}/admin/replicas", url))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let replicas = &admin["groups"][0]["replicas"];
+    assert_eq!(replicas[1]["id"], "search-us");
+    assert_eq!(replicas[1]["region"], "us");
+    assert_eq!(replicas[1]["healthy"], false);
+}
@@ -0,0 +1,242 @@
+mod common;
+
+use axum::Router;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::post;
+use common::{TestServer, answer};
+use reqwest::Method;
+use serde_json::{Value, json};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Lets the upstream finish the response to `file:///stream.txt`
+static RELEASE: Notify = Notify::const_new();
+
+/// A streamable HTTP MCP with two pages of resources, answering only within
+/// the session it assigned
+async fn serve_upstream() -> String {
+    async fn handle(
+        headers: HeaderMap,
+        axum::Json(message): axum::Json<Value>,
+    ) -> impl IntoResponse {
+        let id = message["id"].clone();
+        let params = &message["params"];
+        let answer = |outcome: Value| {
+            let mut response = json!({ "jsonrpc": "2.0", "id": id });
+            response
+                .as_object_mut()
+                .unwrap()
+                .extend(outcome.as_object().unwrap().clone());
+            axum::Json(response)
+        };
+        match message["method"].as_str().unwrap() {
+            "initialize" => {
+                let result = json!({ "result": {
+                    "protocolVersion": "2025-06-18", "capabilities": { "resources": {} }
+                }});
+                ([("mcp-session-id", "session-1")], answer(result)).into_response()
+            }
+            _ if headers.get("mcp-session-id").is_none() => StatusCode::BAD_REQUEST.into_response(),
+            "resources/list" => {
+                let result = match params["cursor"].as_str() {
+                    None => json!({
+                        "resources": [{ "uri": "file:///readme.md", "name": "readme.md" }],
+                        "nextCursor": "page-2"
+                    }),
+                    Some(_) => {
+                        json!({ "resources": [{ "uri": "file:///big.txt", "name": "big.txt" }] })
+                    }
+                };
+                answer(json!({ "result": result })).into_response()
+            }
+            "resources/read" => match params["uri"].as_str().unwrap() {
+                "file:///big.txt" => {
+                    let contents: Vec<_> = (0..3)
+                        .map(|part| {
+                            json!({
+                                "uri": "file:///big.txt",
+                                "mimeType": "text/plain",
+                                "text": format!("{}", part).repeat(100_000)
+                            })
+                        })
+                        .collect();
+                    answer(json!({ "result": { "contents": contents, "_meta": { "parts": 3 } } }))
+                        .into_response()
+                }
+                // Half of the response, the rest once released
+                "file:///stream.txt" => {
+                    let head = format!(
+                        r#"{{"jsonrpc":"2.0","_meta":{{"result":"}}"}},"id":{},"result":{{"contents":[{{"uri":"file:///stream.txt","text":"first \"}}"}}"#,
+                        id
+                    );
+                    let tail = r#",{"uri":"file:///stream.txt","text":"second"}]},"extra":true}"#;
+                    let body = futures_util::stream::unfold(0, move |part| {
+                        let head = head.clone();
+                        async move {
+                            let chunk = match part {
+                                0 => head,
+                                1 => {
+                                    RELEASE.notified().await;
+                                    tail.to_string()
+                                }
+                                _ => return None,
+                            };
+                            Some((Ok::<_, std::convert::Infallible>(chunk), part + 1))
+                        }
+                    });
+                    (
+                        [("content-type", "application/json")],
+                        axum::body::Body::from_stream(body),
+                    )
+                        .into_response()
+                }
+                "file:///slow.txt" => {
+                    tokio::time::sleep(Duration::from_secs(2)).await;
+                    answer(json!({ "result": { "contents": [] } })).into_response()
+                }
+                uri => answer(json!({ "error": {
+                    "code": -32002, "message": "Resource not found", "data": { "uri": uri }
+                }}))
+                .into_response(),
+            },
+            _ => StatusCode::ACCEPTED.into_response(),
+        }
+    }
+
+    let app = Router::new().route("/mcp", post(handle));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    url
+}
+
+async fn server_with_upstream() -> TestServer {
+    let server = TestServer::start().await;
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({ "id": "files", "reason": null, "config": {
+                "transport": { "type": "https", "url": serve_upstream().await },
+                "is_local": false,
+                "reachable_by_agent": false,
+                "config": {},
+                "timeout_ms": 500
+            }}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    server
+}
+
+fn read(server: &TestServer, leaf: &str, uri: &str) -> reqwest::RequestBuilder {
+    server
+        .admin(Method::POST, &format!("/leaf/{}/resources/read", leaf))
+        .json(&json!({ "uri": uri }))
+}
+
+#[tokio::test]
+async fn resources_are_listed_page_by_page() {
+    let server = server_with_upstream().await;
+
+    let (status, body) = server.admin_get("/leaf/files/resources").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["resources"][0]["uri"], "file:///readme.md");
+    assert_eq!(body["nextCursor"], "page-2");
+
+    let (status, body) = server
+        .admin_get("/leaf/files/resources?cursor=page-2")
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["resources"][0]["name"], "big.txt");
+    assert!(body.get("nextCursor").is_none());
+
+    let (status, _) = server.admin_get("/leaf/missing/resources").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn resources_are_read_whole() {
+    let server = server_with_upstream().await;
+
+    let (status, body) = answer(read(&server, "files", "file:///big.txt")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["_meta"]["parts"], 3);
+    let contents = body["contents"].as_array().unwrap();
+    assert_eq!(contents.len(), 3);
+    for (part, entry) in contents.iter().enumerate() {
+        assert_eq!(entry["text"], format!("{}", part).repeat(100_000));
+        assert_eq!(entry["mimeType"], "text/plain");
+    }
+}
+
+#[tokio::test]
+async fn resources_are_passed_on_as_they_arrive() {
+    let server = server_with_upstream().await;
+
+    let mut response = read(&server, "files", "file:///stream.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    // The first entry arrives while the upstream holds back the rest
+    let mut body = Vec::new();
+    while !String::from_utf8_lossy(&body).contains("first") {
+        let chunk = tokio::time::timeout(Duration::from_millis(400), response.chunk())
+            .await
+            .expect("no part of the result before the upstream finished")
+            .unwrap()
+            .unwrap();
+        body.extend_from_slice(&chunk);
+    }
+    RELEASE.notify_one();
+    while let Some(chunk) = response.chunk().await.unwrap() {
+        body.extend_from_slice(&chunk);
+    }
+
+    // Only the result, without the rest of the JSON-RPC response
+    let result: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        result,
+        json!({ "contents": [
+            { "uri": "file:///stream.txt", "text": "first \"}" },
+            { "uri": "file:///stream.txt", "text": "second" }
+        ]})
+    );
+}
+
+#[tokio::test]
+async fn resource_errors_are_relayed() {
+    let server = server_with_upstream().await;
+
+    let (status, body) = answer(read(&server, "files", "file:///gone.txt")).await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(body["error"]["kind"], "leaf_error");
+    assert_eq!(
+        body["error"]["jsonrpc_error"],
+        json!({ "code": -32002, "message": "Resource not found", "data": { "uri": "file:///gone.txt" } })
+    );
+
+    // Within the leaf MCP's `timeout_ms`
+    let (status, body) = answer(read(&server, "files", "file:///slow.txt")).await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT, "{}", body);
+
+    // The built-in MCP has no resources
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({ "id": "echo", "reason": null, "config": {
+                "transport": { "type": "builtin", "kind": "echo" },
+                "is_local": true,
+                "reachable_by_agent": false,
+                "config": {}
+            }}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, body) = server.admin_get("/leaf/echo/resources").await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(body["error"]["jsonrpc_error"]["code"], -32601);
+}
@@ -0,0 +1,179 @@
+mod common;
+
+use common::{TestServer, answer};
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// An HTTPS leaf MCP that resets its first `resets` connections, as during a
+/// deploy, and answers every later request with an empty result. Returns its
+/// URL and the number of connections it accepted.
+async fn serve_flaky_upstream(resets: usize) -> (String, Arc<AtomicUsize>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+    let connections = Arc::new(AtomicUsize::new(0));
+    let accepted = connections.clone();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            if accepted.fetch_add(1, Ordering::SeqCst) < resets {
+                drop(stream);
+                continue;
+            }
+            tokio::spawn(async move {
+                // The whole request, so answering doesn't reset it
+                let mut request = Vec::new();
+                let mut buffer = [0; 4096];
+                loop {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                    let text = String::from_utf8_lossy(&request);
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let length = text[..end]
+                            .lines()
+                            .find_map(|line| {
+                                let (name, value) = line.split_once(':')?;
+                                name.eq_ignore_ascii_case("content-length")
+                                    .then(|| value.trim().parse::<usize>().ok())?
+                            })
+                            .unwrap_or(0);
+                        if request.len() >= end + 4 + length {
+                            break;
+                        }
+                    }
+                    if read == 0 {
+                        return;
+                    }
+                }
+                let body = json!({ "jsonrpc": "2.0", "id": 1, "result": {} }).to_string();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            });
+        }
+    });
+    (url, connections)
+}
+
+async fn create_leaf(server: &TestServer, url: &str, retry: Value) -> (StatusCode, Value) {
+    server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({
+                "id": "flaky",
+                "config": {
+                    "transport": { "type": "https", "url": url },
+                    "is_local": false,
+                    "reachable_by_agent": false,
+                    "config": {},
+                    "retry": retry
+                },
+                "reason": null
+            }),
+        )
+        .await
+}
+
+async fn forward(server: &TestServer, method: &str) -> (StatusCode, Value) {
+    answer(
+        server
+            .request(Method::POST, "/leaf/flaky/forwarding")
+            .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": {} })),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn idempotent_requests_are_retried_with_backoff() {
+    let (url, connections) = serve_flaky_upstream(2).await;
+    let server = TestServer::start().await;
+    let (status, body) = create_leaf(&server, &url, json!({ "backoff_ms": 10 })).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (_, config) = server.admin_get("/leaf/flaky/config").await;
+    assert_eq!(
+        config["retry"],
+        json!({
+            "max_attempts": 3,
+            "backoff_ms": 10,
+            "retry_on": ["leaf_unreachable"],
+            "tool_calls": false
+        })
+    );
+
+    let (status, body) = forward(&server, "tools/list").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(connections.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn attempts_are_bounded() {
+    let (url, connections) = serve_flaky_upstream(5).await;
+    let server = TestServer::start().await;
+    create_leaf(&server, &url, json!({ "max_attempts": 2, "backoff_ms": 0 })).await;
+
+    let (status, body) = forward(&server, "resources/read").await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY, "{}", body);
+    assert_eq!(body["error"]["code"], "leaf_unreachable");
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn tool_calls_are_only_retried_when_opted_in() {
+    let (url, connections) = serve_flaky_upstream(1).await;
+    let server = TestServer::start().await;
+    create_leaf(&server, &url, json!({ "backoff_ms": 0 })).await;
+
+    let (status, body) = forward(&server, "tools/call").await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY, "{}", body);
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+    let (status, body) = server
+        .admin_json(
+            Method::PUT,
+            "/leaf/flaky/config",
+            &json!({ "config": { "retry": { "tool_calls": true } } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (_, config) = server.admin_get("/leaf/flaky/config").await;
+    assert_eq!(config["retry"]["tool_calls"], true);
+    let (status, body) = forward(&server, "tools/call").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+#[tokio::test]
+async fn leaf_mcps_without_a_policy_are_not_retried() {
+    let (url, connections) = serve_flaky_upstream(1).await;
+    let server = TestServer::start().await;
+    create_leaf(&server, &url, Value::Null).await;
+
+    let (status, _) = forward(&server, "tools/list").await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn retry_policies_are_validated() {
+    let server = TestServer::start().await;
+    let (status, body) = create_leaf(
+        &server,
+        "https://mcp.example.com/mcp",
+        json!({ "max_attempts": 0 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    let (status, body) = create_leaf(
+        &server,
+        "https://mcp.example.com/mcp",
+        json!({ "retry_on": ["leaf_error"] }),
+    )
+    .await;
+    // Not a class of failures that can be retried
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+}
@@ -0,0 +1,117 @@
+use mception_server::core::{LeafMcpConfig, ReverseRequestPolicy};
+use mception_server::services::reverse_requests::{self, Handling, LeafMessage, METHOD_NOT_FOUND};
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+#[test]
+fn classifies_leaf_messages() {
+    assert_eq!(
+        reverse_requests::classify(&json!({
+            "jsonrpc": "2.0", "id": 7, "method": "sampling/createMessage", "params": {}
+        })),
+        LeafMessage::Request {
+            id: json!(7),
+            method: "sampling/createMessage".to_string()
+        }
+    );
+    assert_eq!(
+        reverse_requests::classify(&json!({
+            "jsonrpc": "2.0", "method": "notifications/tools/list_changed"
+        })),
+        LeafMessage::Notification
+    );
+    assert_eq!(
+        reverse_requests::classify(&json!({ "jsonrpc": "2.0", "id": 1, "result": {} })),
+        LeafMessage::Response
+    );
+    assert_eq!(
+        reverse_requests::classify(&json!({ "id": 1 })),
+        LeafMessage::Invalid
+    );
+}
+
+#[test]
+fn rejects_unless_relaying_to_a_capable_agent() {
+    let capabilities = json!({ "roots": { "listChanged": true } });
+
+    assert!(matches!(
+        reverse_requests::handling(
+            ReverseRequestPolicy::Reject,
+            "roots/list",
+            Some(&capabilities)
+        ),
+        Handling::Reject(_)
+    ));
+    assert_eq!(
+        reverse_requests::handling(
+            ReverseRequestPolicy::Relay,
+            "roots/list",
+            Some(&capabilities)
+        ),
+        Handling::Relay
+    );
+    assert!(matches!(
+        reverse_requests::handling(ReverseRequestPolicy::Relay, "roots/list", None),
+        Handling::Reject(reason) if reason.contains("not connected")
+    ));
+    assert!(matches!(
+        reverse_requests::handling(
+            ReverseRequestPolicy::Relay,
+            "sampling/createMessage",
+            Some(&capabilities)
+        ),
+        Handling::Reject(reason) if reason.contains("'sampling'")
+    ));
+
+    let rejection = reverse_requests::rejection(&json!("abc"), "roots/list", "not relayed");
+    assert_eq!(rejection["id"], "abc");
+    assert_eq!(rejection["error"]["code"], METHOD_NOT_FOUND);
+}
+
+#[tokio::test]
+async fn unanswered_relays_time_out_independently() {
+    let timeout = Duration::from_millis(50);
+    let ids = [json!(1), json!(2), json!(3)];
+    let started = Instant::now();
+
+    let (stuck, answered, disconnected) = tokio::join!(
+        reverse_requests::await_relayed(&ids[0], "roots/list", std::future::pending(), timeout),
+        reverse_requests::await_relayed(
+            &ids[1],
+            "roots/list",
+            async { Some(json!({ "jsonrpc": "2.0", "id": 2, "result": { "roots": [] } })) },
+            timeout
+        ),
+        reverse_requests::await_relayed(&ids[2], "roots/list", async { None }, timeout),
+    );
+
+    assert_eq!(stuck["error"]["code"], METHOD_NOT_FOUND);
+    assert_eq!(answered["result"]["roots"], json!([]));
+    assert!(
+        disconnected["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("disconnected")
+    );
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn reject_is_the_default_and_not_serialized() {
+    let leaf: LeafMcpConfig = serde_json::from_value(json!({
+        "name": null,
+        "description": null,
+        "transport": { "type": "https", "url": "https://mcp.example.com", "headers": null },
+        "is_local": false,
+        "reachable_by_agent": true,
+        "config": {}
+    }))
+    .unwrap();
+    assert_eq!(leaf.reverse_requests, ReverseRequestPolicy::Reject);
+    assert!(
+        serde_json::to_value(&leaf)
+            .unwrap()
+            .get("reverse_requests")
+            .is_none()
+    );
+}
@@ -0,0 +1,113 @@
+use mception_server::services::ConfigService;
+use mception_server::services::revision;
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::{RouterOptions, build_router};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn service() -> Arc<ConfigService> {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ))
+}
+
+async fn serve(service: Arc<ConfigService>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!(
+        "http://{}/admin/config/revision",
+        listener.local_addr().unwrap()
+    );
+    let router = build_router(service, RouterOptions::default());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    url
+}
+
+async fn get(url: &str) -> Value {
+    reqwest::get(url).await.unwrap().json().await.unwrap()
+}
+
+#[tokio::test]
+async fn hash_covers_only_the_configuration() {
+    let first = service();
+    let empty = first.get_configuration().await;
+    let expected = format!(
+        "sha256:{:x}",
+        Sha256::digest(r#"{"agents":{},"bundles":{},"leaf_mcps":{}}"#)
+    );
+    assert_eq!(revision::hash(&empty), expected);
+
+    // Created at different times, with equal contents
+    let second = service();
+    for service in [&first, &second] {
+        service
+            .create_agent(Some("writer".to_string()), None, vec![], None)
+            .await
+            .unwrap();
+    }
+    let first_hash = first.config_revision().await.hash;
+    assert_ne!(first_hash, expected);
+    assert_eq!(first_hash, second.config_revision().await.hash);
+
+    // Connection state is not configuration
+    first.mark_agent_seen("writer").await;
+    assert_eq!(first.config_revision().await.hash, first_hash);
+}
+
+#[tokio::test]
+async fn revision_endpoint() {
+    let service = service();
+    service
+        .create_agent(Some("writer".to_string()), None, vec![], None)
+        .await
+        .unwrap();
+    let url = serve(service.clone()).await;
+
+    let body = get(&url).await;
+    assert_eq!(body["revision"], 1);
+    assert_eq!(body["hash"], service.config_revision().await.hash);
+    assert!(body["last_modified"].is_string());
+}
+
+#[tokio::test]
+async fn watch_answers_on_change_or_timeout() {
+    let service = service();
+    let url = serve(service.clone()).await;
+
+    let started = Instant::now();
+    let body = get(&format!("{}/watch?since=0&timeout=200ms", url)).await;
+    assert_eq!(body["changed"], false);
+    assert_eq!(body["revision"], 0);
+    assert!(started.elapsed() >= Duration::from_millis(200));
+
+    let creator = service.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        creator
+            .create_agent(Some("writer".to_string()), None, vec![], None)
+            .await
+            .unwrap();
+    });
+    let body = get(&format!("{}/watch?since=0&timeout=10s", url)).await;
+    assert_eq!(body["changed"], true);
+    assert_eq!(body["revision"], 1);
+
+    // A caller behind the current revision is answered right away
+    let started = Instant::now();
+    let body = get(&format!("{}/watch?since=0&timeout=10s", url)).await;
+    assert_eq!(body["changed"], true);
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let response = reqwest::get(format!("{}/watch?timeout=soon", url))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use common::TestServer;
+use mception_server::core::{AuditAction, AuditTarget};
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+
+async fn create_leaf(server: &TestServer) {
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({
+                "id": "search",
+                "config": {
+                    "transport": { "type": "builtin", "kind": "echo" },
+                    "is_local": false,
+                    "reachable_by_agent": false,
+                    "config": {}
+                },
+                "reason": null
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+async fn update(
+    server: &TestServer,
+    path: &str,
+    config: Value,
+    expected: Option<u64>,
+) -> (StatusCode, Value) {
+    server
+        .admin_json(
+            Method::PUT,
+            path,
+            &json!({ "config": config, "reason": null, "expected_revision": expected }),
+        )
+        .await
+}
+
+#[tokio::test]
+async fn updates_based_on_an_old_revision_are_refused() {
+    let server = TestServer::start().await;
+    create_leaf(&server).await;
+    let (status, leaf) = server.admin_get("/leaf/search/config").await;
+    assert_eq!(status, StatusCode::OK);
+    let read = leaf["revision"].as_u64().unwrap();
+    assert_eq!(read, server.service.revision().await);
+
+    // The first admin's update goes through
+    let (status, body) = update(
+        &server,
+        "/leaf/search/config",
+        json!({ "description": "Web search" }),
+        Some(read),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let updated = body["revision"].as_u64().unwrap();
+    assert!(updated > read);
+
+    // The second one based on the same revision gets the current state
+    let (status, body) = update(
+        &server,
+        "/leaf/search/config",
+        json!({ "description": "Search" }),
+        Some(read),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT, "{}", body);
+    assert_eq!(body["error"]["kind"], "stale_revision");
+    assert_eq!(body["current"]["description"], "Web search");
+    assert_eq!(body["current"]["revision"], updated);
+    let saved = server.saved_config();
+    assert_eq!(
+        saved.leaf_mcps["search"].description.as_deref(),
+        Some("Web search")
+    );
+
+    // Rebased on the current revision, or without one, it goes through
+    let (status, _) = update(
+        &server,
+        "/leaf/search/config",
+        json!({ "description": "Search" }),
+        Some(updated),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = update(
+        &server,
+        "/leaf/search/config",
+        json!({ "name": "Search" }),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The revision itself can't be set
+    let (status, _) = update(
+        &server,
+        "/leaf/search/config",
+        json!({ "revision": 1 }),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn agents_are_revised_and_audited_with_their_revisions() {
+    let server = TestServer::start().await;
+    create_leaf(&server).await;
+    let (status, _) = server
+        .admin_json(
+            Method::POST,
+            "/agent",
+            &json!({ "agent_id": "writer", "allowed_mcp_ids": [] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, agent) = server.admin_get("/agent/writer/config").await;
+    let created = agent["revision"].as_u64().unwrap();
+
+    // Other changes of the agent revise it as well
+    server
+        .service
+        .add_agent_allowed_mcp("writer", "search", None, None)
+        .await
+        .unwrap();
+    let (_, agent) = server.admin_get("/agent/writer/config").await;
+    let granted = agent["revision"].as_u64().unwrap();
+    assert!(granted > created);
+
+    let (status, body) = update(
+        &server,
+        "/agent/writer/config",
+        json!({ "auth_token": "secret" }),
+        Some(created),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["current"]["allowed_mcp_ids"], json!(["search"]));
+    assert_eq!(body["current"]["revision"], granted);
+    let (status, body) = update(
+        &server,
+        "/agent/writer/config",
+        json!({ "description": "Writes" }),
+        Some(granted),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let entries = server.audit_entries().await;
+    let update = entries
+        .iter()
+        .find(|entry| {
+            matches!(entry.action, AuditAction::Update)
+                && matches!(&entry.target, AuditTarget::Agent { id } if id == "writer")
+        })
+        .unwrap();
+    let revision = update.revision.unwrap();
+    assert_eq!(revision.from, granted);
+    assert_eq!(revision.to, body["revision"].as_u64().unwrap());
+}
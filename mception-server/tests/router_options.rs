@@ -0,0 +1,99 @@
+mod common;
+
+use common::TestServer;
+use mception_server::RouterOptions;
+use reqwest::{Method, StatusCode};
+
+async fn status(server: &TestServer, path: &str) -> StatusCode {
+    server
+        .request(Method::GET, path)
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn only_the_selected_parts_are_mounted() {
+    let server = TestServer::builder()
+        .router_options(RouterOptions {
+            agent_api: false,
+            leaf_forwarding: false,
+            ..RouterOptions::default()
+        })
+        .start()
+        .await;
+    assert_eq!(status(&server, "/admin/config").await, StatusCode::OK);
+    assert_eq!(
+        status(&server, "/agent/bot/config").await,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        status(&server, "/leaf/echo/forwarding").await,
+        StatusCode::NOT_FOUND
+    );
+
+    let server = TestServer::builder()
+        .router_options(RouterOptions {
+            admin_api: false,
+            ..RouterOptions::default()
+        })
+        .start()
+        .await;
+    assert_eq!(
+        status(&server, "/admin/config").await,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn the_dashboard_is_only_served_with_the_admin_ui_feature() {
+    let server = TestServer::start().await;
+    let response = server.admin(Method::GET, "/ui").send().await.unwrap();
+    if cfg!(feature = "admin-ui") {
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(
+            response.headers()["content-type"]
+                .to_str()
+                .unwrap()
+                .starts_with("text/html")
+        );
+    } else {
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
+
+#[cfg(feature = "admin-ui")]
+#[tokio::test]
+async fn the_dashboard_can_be_left_out() {
+    let server = TestServer::builder()
+        .router_options(RouterOptions {
+            admin_ui: false,
+            ..RouterOptions::default()
+        })
+        .start()
+        .await;
+    assert_eq!(status(&server, "/admin/ui").await, StatusCode::NOT_FOUND);
+    assert_eq!(status(&server, "/admin/config").await, StatusCode::OK);
+}
+
+#[cfg(feature = "admin-ui")]
+#[tokio::test]
+async fn the_dashboard_needs_an_admin_token() {
+    let server = TestServer::builder()
+        .admin_token("admin-secret")
+        .start()
+        .await;
+    assert_eq!(status(&server, "/admin/ui").await, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        status(&server, "/admin/ui/app.js").await,
+        StatusCode::UNAUTHORIZED
+    );
+    let response = server
+        .request(Method::GET, "/admin/ui")
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{TestServer, leaf_mcp};
+use mception_server::core::{BuiltinMcpKind, McpTransport};
+use mception_server::services::access_log::{AccessEntry, AccessLog};
+use mception_server::services::authorization::AGENT_HEADER;
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+
+async fn add_leaf(server: &TestServer, id: &str) {
+    let leaf = leaf_mcp(McpTransport::Builtin {
+        kind: BuiltinMcpKind::Echo,
+    });
+    server
+        .service
+        .create_leaf_mcp(Some(id.to_string()), leaf, None, None)
+        .await
+        .unwrap();
+}
+
+/// Leaf MCPs `echo`, `rare` and `idle`, agent `writer` allowed `echo`
+/// directly and agent `reader` through the bundle `tools`
+async fn fleet(server: &TestServer) {
+    for id in ["echo", "rare", "idle"] {
+        add_leaf(server, id).await;
+    }
+    server
+        .service
+        .create_bundle(
+            "tools".to_string(),
+            None,
+            vec!["echo".to_string(), "idle".to_string()],
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    server
+        .service
+        .create_agent(
+            Some("writer".to_string()),
+            None,
+            vec!["echo".to_string()],
+            None,
+        )
+        .await
+        .unwrap();
+    server
+        .service
+        .create_agent(
+            Some("reader".to_string()),
+            None,
+            vec!["bundle:tools".to_string()],
+            None,
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn says_so_without_an_access_log() {
+    let server = TestServer::start().await;
+    fleet(&server).await;
+
+    let (status, safety) = server.admin_get("/leaf/echo/safety").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(safety["risk"], "unknown");
+    assert_eq!(safety["access_log_enabled"], false);
+    assert!(safety["total_calls"].is_null());
+    assert!(safety["note"].as_str().unwrap().contains("--access-log"));
+    assert_eq!(
+        safety["agents"],
+        json!([
+            { "agent_id": "reader", "granted_by": ["bundle:tools"], "calls": null, "last_used_at": null },
+            { "agent_id": "writer", "granted_by": ["echo"], "calls": null, "last_used_at": null },
+        ])
+    );
+
+    let (status, _) = server.admin_get("/leaf/missing/safety").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn classifies_leaf_mcps_by_recent_calls() {
+    let server = TestServer::builder().access_log().start().await;
+    fleet(&server).await;
+
+    // Written before the window, a few recent ones, and one of a removed agent
+    let log = AccessLog::new(&server.access_log_path);
+    let entry = |mcp: &str, agent: Option<&str>, days_ago: i64| AccessEntry {
+        at: Utc::now() - Duration::days(days_ago),
+        mcp: mcp.to_string(),
+        agent: agent.map(str::to_string),
+        method: Some("tools/call".to_string()),
+        status: 200,
+    };
+    for written in [
+        entry("idle", Some("reader"), 45),
+        entry("rare", None, 12),
+        entry("rare", Some("gone"), 10),
+    ] {
+        log.record(&written).await.unwrap();
+    }
+
+    let response = server
+        .request(Method::POST, "/leaf/echo/forwarding")
+        .header(AGENT_HEADER, "writer")
+        .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let (_, echo) = server.admin_get("/leaf/echo/safety").await;
+    assert_eq!(echo["risk"], "active");
+    assert_eq!(echo["total_calls"], 1);
+    assert_eq!(echo["agents"][0]["calls"], 0);
+    assert_eq!(echo["agents"][1]["agent_id"], "writer");
+    assert_eq!(echo["agents"][1]["calls"], 1);
+    assert!(echo["agents"][1]["last_used_at"].is_string());
+    assert!(echo.get("note").is_none());
+    let logged: AccessEntry = serde_json::from_str(
+        std::fs::read_to_string(&server.access_log_path)
+            .unwrap()
+            .lines()
+            .last()
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(logged.agent.as_deref(), Some("writer"));
+    assert_eq!(logged.method.as_deref(), Some("tools/list"));
+
+    let (_, rare) = server.admin_get("/leaf/rare/safety").await;
+    assert_eq!(rare["risk"], "low");
+    assert_eq!(rare["total_calls"], 2);
+    assert_eq!(rare["unidentified_calls"], 1);
+    assert_eq!(
+        rare["agents"][0]["granted_by"],
+        json!([]),
+        "{}",
+        rare["agents"]
+    );
+
+    let (_, idle) = server.admin_get("/leaf/idle/safety").await;
+    assert_eq!(idle["risk"], "unused");
+    assert_eq!(idle["total_calls"], 0);
+
+    // The delete dry run judges each leaf MCP it would delete
+    let (status, dry_run) = server
+        .admin_json(
+            Method::POST,
+            "/leaf/bulk_delete",
+            &json!({ "ids": ["echo", "idle"] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(dry_run["plan"]["safety"]["echo"]["risk"], "active");
+    assert_eq!(dry_run["plan"]["safety"]["idle"]["risk"], "unused");
+}
@@ -0,0 +1,217 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{TestServer, answer};
+use mception_server::core::{StdioSandbox, ValidationError};
+use mception_server::services::sandbox;
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+
+/// A stdio MCP whose only tool tells whether the variables `VISIBLE` and
+/// the one it was generated for are set in its environment
+fn env_script(inherited: &str) -> String {
+    format!(
+        r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | grep -o '"id":[0-9]*' | head -n 1 | cut -d: -f2)
+  case "$line" in
+    *'"method":"initialize"'*)
+      printf '{{"jsonrpc":"2.0","id":%s,"result":{{"protocolVersion":"2025-06-18","capabilities":{{"tools":{{}}}}}}}}\n' "$id" ;;
+    *'"method":"tools/call"'*)
+      printf '{{"jsonrpc":"2.0","id":%s,"result":{{"configured":"%s","inherited":"%s"}}}}\n' "$id" "${{VISIBLE:-unset}}" "${{{}:+set}}" ;;
+  esac
+done
+"#,
+        inherited
+    )
+}
+
+fn out_of_range(result: Result<(), ValidationError>) -> String {
+    match result {
+        Err(ValidationError::ValueOutOfRange(message)) => message,
+        other => panic!("not out of range: {:?}", other),
+    }
+}
+
+fn invalid(result: Result<(), ValidationError>) -> String {
+    match result {
+        Err(ValidationError::InvalidFormat(message)) => message,
+        other => panic!("not refused: {:?}", other),
+    }
+}
+
+#[test]
+fn validation_refuses_what_cannot_be_honored() {
+    let valid = StdioSandbox {
+        working_dir: Some(std::env::temp_dir().to_string_lossy().into_owned()),
+        clear_env: true,
+        max_memory_mb: Some(256),
+        allowed_paths: vec!["/srv/data".to_string()],
+        ..StdioSandbox::default()
+    };
+    sandbox::validate(&valid).unwrap();
+
+    let message = invalid(sandbox::validate(&StdioSandbox {
+        allowed_paths: vec!["/srv/data".to_string(), "data/uploads".to_string()],
+        ..StdioSandbox::default()
+    }));
+    assert!(
+        message.contains("'data/uploads' must be an absolute path"),
+        "{}",
+        message
+    );
+
+    let message = invalid(sandbox::validate(&StdioSandbox {
+        working_dir: Some("/does/not/exist".to_string()),
+        ..StdioSandbox::default()
+    }));
+    assert!(message.contains("/does/not/exist"), "{}", message);
+
+    for shares in [0, 1, 262145] {
+        let message = out_of_range(sandbox::validate(&StdioSandbox {
+            cpu_shares: Some(shares),
+            ..StdioSandbox::default()
+        }));
+        assert_eq!(message, "cpu_shares must be between 2 and 262144");
+    }
+    let message = out_of_range(sandbox::validate(&StdioSandbox {
+        max_memory_mb: Some(0),
+        ..StdioSandbox::default()
+    }));
+    assert_eq!(message, "max_memory_mb must be greater than 0");
+}
+
+#[test]
+fn running_as_another_user_needs_root() {
+    let sandbox_of = |user: &str| StdioSandbox {
+        run_as_user: Some(user.to_string()),
+        ..StdioSandbox::default()
+    };
+
+    let message = invalid(sandbox::validate(&sandbox_of("no-such-user-mception")));
+    assert!(message.contains("no-such-user-mception"), "{}", message);
+
+    // SAFETY: geteuid has no preconditions and cannot fail
+    let privileged = unsafe { libc::geteuid() } == 0;
+    let result = sandbox::validate(&sandbox_of("root"));
+    if privileged {
+        result.unwrap();
+    } else {
+        assert_eq!(
+            invalid(result),
+            "run_as_user 'root' requires the server to run as root"
+        );
+    }
+}
+
+#[test]
+fn described_restrictions_name_their_mechanism() {
+    let restrictions = sandbox::describe(&StdioSandbox {
+        working_dir: Some("/srv".to_string()),
+        run_as_user: Some("mcp".to_string()),
+        clear_env: true,
+        max_memory_mb: Some(512),
+        cpu_shares: Some(256),
+        allowed_paths: vec!["/srv/a".to_string(), "/srv/b".to_string()],
+    });
+    let mut described = serde_json::to_value(&restrictions).unwrap();
+    // Depends on whether the host delegates a cgroup to the server
+    let memory = described[3]["mechanism"].take();
+    assert!(
+        memory == "cgroup memory.max" || memory == "rlimit RLIMIT_AS",
+        "{}",
+        memory
+    );
+    assert_eq!(
+        described,
+        json!([
+            { "restriction": "working_dir", "value": "/srv", "mechanism": "chdir" },
+            { "restriction": "run_as_user", "value": "mcp", "mechanism": "setuid" },
+            { "restriction": "clear_env", "value": "true", "mechanism": "env_clear" },
+            { "restriction": "max_memory_mb", "value": "512", "mechanism": null },
+            { "restriction": "cpu_shares", "value": "256", "mechanism": "cgroup cpu.weight" },
+            {
+                "restriction": "allowed_paths",
+                "value": "/srv/a:/srv/b",
+                "mechanism": "MCEPTION_ALLOWED_PATHS"
+            }
+        ])
+    );
+
+    assert!(sandbox::describe(&StdioSandbox::default()).is_empty());
+}
+
+#[tokio::test]
+async fn cleared_environments_only_hold_the_configured_variables() {
+    // Any variable of the server's own environment a shell doesn't set itself
+    let inherited = std::env::vars()
+        .map(|(name, _)| name)
+        .find(|name| {
+            !["PATH", "PWD", "OLDPWD", "SHLVL", "HOME", "IFS"].contains(&name.as_str())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+        })
+        .expect("the test runs with some environment");
+    let server = TestServer::start().await;
+    let script = server.dir.join("env.sh");
+    std::fs::write(&script, env_script(&inherited)).unwrap();
+
+    for (id, clear_env) in [("isolated", true), ("inheriting", false)] {
+        let (status, body) = server
+            .admin_json(
+                Method::POST,
+                "/leaf",
+                &json!({ "id": id, "reason": null, "config": {
+                    "transport": {
+                        "type": "stdio",
+                        "command": "/bin/sh",
+                        "args": [script],
+                        "env": { "VISIBLE": "configured" },
+                        "clear_env": clear_env,
+                        "allowed_paths": ["/srv/data"]
+                    },
+                    "is_local": false,
+                    "reachable_by_agent": false,
+                    "config": {}
+                }}),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    let call = |id: &str| {
+        server
+            .request(Method::POST, &format!("/leaf/{}/forwarding", id))
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "tools/call",
+                "params": { "name": "env", "arguments": {} }
+            }))
+    };
+    let (status, body) = answer(call("isolated")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(
+        body["result"],
+        json!({ "configured": "configured", "inherited": "" }),
+        "{} leaked into the cleared environment",
+        inherited
+    );
+    let (_, body) = answer(call("inheriting")).await;
+    assert_eq!(
+        body["result"],
+        json!({ "configured": "configured", "inherited": "set" })
+    );
+
+    // The admin view lists what was applied
+    let (status, body) = server.admin_get("/leaf/isolated/sandbox").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let applied: Vec<&Value> = body["restrictions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|restriction| &restriction["restriction"])
+        .collect();
+    assert_eq!(applied, [&json!("clear_env"), &json!("allowed_paths")]);
+}
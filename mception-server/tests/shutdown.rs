@@ -0,0 +1,216 @@
+mod common;
+
+use common::{TestServer, answer};
+use futures_util::StreamExt;
+use mception_server::core::{AuditAction, AuditTarget};
+use mception_server::routes::agent::SHUTDOWN_CLOSE_REASON;
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+#[cfg(unix)]
+use std::path::Path;
+#[cfg(unix)]
+use std::process::{Child, Command};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+
+#[tokio::test]
+async fn shutdown_closes_agent_sockets_and_flushes_the_configuration() {
+    let server = TestServer::start().await;
+    let (status, _) = server
+        .admin_json(
+            Method::POST,
+            "/agent",
+            &json!({ "agent_id": "writer", "allowed_mcp_ids": [] }),
+        )
+        .await;
+    assert!(status.is_success());
+    let ws_url = server.url.replacen("http", "ws", 1);
+    let (mut socket, _) =
+        tokio_tungstenite::connect_async(format!("{}/agent/writer/forwarding_ws", ws_url))
+            .await
+            .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    server.service.lifecycle().begin_draining();
+    let close = loop {
+        match tokio::time::timeout(Duration::from_secs(5), socket.next()).await {
+            Ok(Some(Ok(Message::Close(frame)))) => break frame.unwrap(),
+            Ok(Some(Ok(_))) => continue,
+            other => panic!("expected a close frame, got {:?}", other),
+        }
+    };
+    assert_eq!(close.code, CloseCode::Away);
+    assert_eq!(close.reason.as_str(), SHUTDOWN_CLOSE_REASON);
+
+    let report = server
+        .service
+        .shutdown("SIGTERM".to_string(), 0, false)
+        .await;
+    assert!(!report.drain_timed_out);
+    assert!(
+        server
+            .saved_config()
+            .agents
+            .values()
+            .all(|agent| !agent.is_connected)
+    );
+
+    let entries = server.audit_entries().await;
+    let entry = entries
+        .iter()
+        .find(|entry| matches!(entry.action, AuditAction::Shutdown))
+        .unwrap();
+    assert!(matches!(entry.target, AuditTarget::Server));
+    assert_eq!(entry.details["reason"], "SIGTERM");
+    assert_eq!(entry.details["drain_timed_out"], false);
+}
+
+#[tokio::test]
+async fn forwards_refused_while_draining_are_reported() {
+    let server = TestServer::start().await;
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({ "id": "echo", "config": {
+                "transport": { "type": "builtin", "kind": "echo" },
+                "is_local": false,
+                "reachable_by_agent": false,
+                "config": {}
+            }}),
+        )
+        .await;
+    assert!(status.is_success(), "{}", body);
+    let call = |tool: &str, arguments: Value| {
+        server
+            .request(Method::POST, "/leaf/echo/forwarding")
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "tools/call",
+                "params": { "name": tool, "arguments": arguments }
+            }))
+    };
+
+    // A forward running when draining begins is let finish
+    let running = tokio::spawn(answer(call("sleep_ms", json!({ "ms": 300 }))));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let lifecycle = server.service.lifecycle();
+    assert_eq!(lifecycle.in_flight_forwards(), 1);
+    lifecycle.begin_draining();
+
+    let (status, body) = answer(call("echo", json!({ "text": "late" }))).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{}", body);
+    assert_eq!(body["error"]["detail"], "Server is shutting down");
+    let (status, body) = running.await.unwrap();
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(lifecycle.in_flight_forwards(), 0);
+
+    let report = server
+        .service
+        .shutdown("SIGTERM".to_string(), 0, false)
+        .await;
+    assert_eq!(report.reason, "SIGTERM");
+    assert_eq!(
+        report.drain_failures.into_iter().collect::<Vec<_>>(),
+        [("shutting_down".to_string(), 1)]
+    );
+    assert_eq!(
+        report.config_revision,
+        server.saved_config().metadata.revision
+    );
+    assert!(report.drain_milliseconds >= 0);
+}
+
+/// Start the server binary in `dir`, where it keeps its files, and wait
+/// until it answers
+#[cfg(unix)]
+async fn start_binary(dir: &Path) -> (Child, String) {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mception-server"))
+        .current_dir(dir)
+        .args(["--host", "127.0.0.1", "--port", &port.to_string()])
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let url = format!("http://127.0.0.1:{}", port);
+    for _ in 0..100 {
+        if reqwest::get(format!("{}/admin/last-shutdown", url))
+            .await
+            .is_ok()
+        {
+            return (child, url);
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    child.kill().unwrap();
+    child.wait().unwrap();
+    panic!("the server didn't start");
+}
+
+#[cfg(unix)]
+async fn last_shutdown(url: &str) -> Value {
+    let (status, body) =
+        answer(reqwest::Client::new().get(format!("{}/admin/last-shutdown", url))).await;
+    assert_eq!(status, StatusCode::OK);
+    body
+}
+
+#[cfg(unix)]
+fn signal(child: &Child, signal: libc::c_int) {
+    // SAFETY: kill has no memory safety preconditions
+    assert_eq!(unsafe { libc::kill(child.id() as libc::pid_t, signal) }, 0);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn the_next_start_reports_how_the_previous_run_ended() {
+    let dir = common::temp_dir();
+    let record_path = dir.join("last-shutdown.json");
+    let record = || -> Value {
+        serde_json::from_str(&std::fs::read_to_string(&record_path).unwrap()).unwrap()
+    };
+
+    let (mut child, url) = start_binary(&dir).await;
+    assert_eq!(last_shutdown(&url).await["outcome"], "unknown");
+    assert_eq!(record()["status"], "running");
+    assert_eq!(record()["pid"], child.id());
+
+    signal(&child, libc::SIGTERM);
+    let exit = tokio::task::spawn_blocking(move || child.wait().unwrap())
+        .await
+        .unwrap();
+    assert!(exit.success(), "{:?}", exit);
+    let stopped = record();
+    assert_eq!(stopped["status"], "stopped");
+    assert_eq!(stopped["reason"], "SIGTERM");
+    assert_eq!(stopped["drain_timed_out"], false);
+    assert_eq!(stopped["in_flight_forwards_aborted"], 0);
+
+    let (mut child, url) = start_binary(&dir).await;
+    let previous = last_shutdown(&url).await;
+    assert_eq!(previous["outcome"], "clean_stop");
+    assert_eq!(previous["report"]["reason"], "SIGTERM");
+    assert_eq!(previous["started_at"], stopped["started_at"]);
+
+    // Killed without a chance to write its report
+    signal(&child, libc::SIGKILL);
+    let started_at = record()["started_at"].clone();
+    tokio::task::spawn_blocking(move || child.wait().unwrap())
+        .await
+        .unwrap();
+    let (mut child, url) = start_binary(&dir).await;
+    let previous = last_shutdown(&url).await;
+    assert_eq!(previous["outcome"], "crash");
+    assert_eq!(previous["started_at"], started_at);
+    assert_eq!(previous["report"], Value::Null);
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
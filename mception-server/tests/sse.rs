@@ -0,0 +1,167 @@
+mod common;
+
+use axum::Router;
+use axum::extract::State;
+use axum::http::StatusCode as UpstreamStatus;
+use axum::response::sse::{Event, Sse};
+use axum::routing::{get, post};
+use common::{TestServer, answer};
+use futures_util::{Stream, StreamExt, stream};
+use reqwest::{Method, StatusCode};
+use serde_json::{Value, json};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+/// Where the upstream sends what it answers: the event stream opened last
+type Outbox = Arc<Mutex<Option<mpsc::UnboundedSender<Value>>>>;
+
+/// An upstream MCP speaking the legacy SSE transport, with the tool `echo`.
+/// Its event stream is at `/sse`, messages are posted to `/messages`.
+async fn serve_upstream() -> String {
+    async fn open(
+        State(outbox): State<Outbox>,
+    ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        *outbox.lock().unwrap() = Some(sender);
+        let endpoint = Event::default()
+            .event("endpoint")
+            .data("/messages?session=1");
+        let messages = stream::unfold(receiver, |mut receiver| async move {
+            let message: Value = receiver.recv().await?;
+            Some((
+                Event::default().event("message").data(message.to_string()),
+                receiver,
+            ))
+        });
+        Sse::new(stream::once(async { endpoint }).chain(messages).map(Ok))
+    }
+
+    async fn receive(State(outbox): State<Outbox>, body: String) -> UpstreamStatus {
+        let message: Value = serde_json::from_str(&body).unwrap();
+        let result = match message["method"].as_str() {
+            Some("initialize") => json!({
+                "protocolVersion": "2024-11-05",
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "legacy", "version": "1" }
+            }),
+            Some("tools/list") => json!({
+                "tools": [{ "name": "echo", "inputSchema": { "type": "object" } }]
+            }),
+            Some("tools/call") => json!({
+                "content": [{ "type": "text", "text": message["params"]["arguments"]["text"] }]
+            }),
+            Some("ping") => json!({}),
+            _ => return UpstreamStatus::ACCEPTED,
+        };
+        let response = json!({ "jsonrpc": "2.0", "id": message["id"], "result": result });
+        if let Some(sender) = outbox.lock().unwrap().as_ref() {
+            let _ = sender.send(response);
+        }
+        UpstreamStatus::ACCEPTED
+    }
+
+    let app = Router::new()
+        .route("/sse", get(open))
+        .route("/messages", post(receive))
+        .with_state(Outbox::default());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    url
+}
+
+async fn create_sse_leaf(server: &TestServer, url: &str) -> (StatusCode, Value) {
+    server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({
+                "id": "legacy",
+                "config": {
+                    "transport": { "type": "sse", "url": url },
+                    "is_local": false,
+                    "reachable_by_agent": false,
+                    "config": {}
+                },
+                "reason": null
+            }),
+        )
+        .await
+}
+
+async fn forward(server: &TestServer, message: &Value) -> (StatusCode, Value) {
+    answer(
+        server
+            .request(Method::POST, "/leaf/legacy/forwarding")
+            .json(message),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn sse_leaf_mcps_are_created_and_forwarded_to() {
+    let upstream = serve_upstream().await;
+    let server = TestServer::start().await;
+    let (status, body) = create_sse_leaf(&server, &format!("{}/sse", upstream)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (_, leaf) = server.admin_get("/leaf/legacy/config").await;
+    assert_eq!(leaf["transport"]["type"], "sse", "{}", leaf);
+
+    // Answered from the connection's own handshake
+    let (status, response) = forward(
+        &server,
+        &json!({ "jsonrpc": "2.0", "id": "init", "method": "initialize", "params": {} }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", response);
+    assert_eq!(response["id"], "init");
+    assert_eq!(response["result"]["serverInfo"]["name"], "legacy");
+
+    let (status, response) = forward(
+        &server,
+        &json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "method": "tools/call",
+            "params": { "name": "echo", "arguments": { "text": "over sse" } }
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", response);
+    assert_eq!(response["id"], 7);
+    assert_eq!(response["result"]["content"][0]["text"], "over sse");
+
+    let (status, listing) = server.admin_get("/leaf/legacy/tools").await;
+    assert_eq!(status, StatusCode::OK, "{}", listing);
+    assert_eq!(listing["tools"][0]["name"], "echo", "{}", listing);
+}
+
+#[tokio::test]
+async fn unreachable_event_stream_is_a_bad_gateway() {
+    let upstream = serve_upstream().await;
+    let server = TestServer::start().await;
+    let (status, body) = create_sse_leaf(&server, &format!("{}/missing", upstream)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let (status, response) = forward(
+        &server,
+        &json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY, "{}", response);
+    assert_eq!(
+        response["error"]["code"], "leaf_unreachable",
+        "{}",
+        response
+    );
+}
+
+#[tokio::test]
+async fn sse_urls_must_be_http() {
+    let server = TestServer::start().await;
+    let (status, body) = create_sse_leaf(&server, "ftp://example.com/sse").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    let (status, _) = server.admin_get("/leaf/legacy/config").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use common::{leaf_mcp, temp_dir};
+use mception_server::build_router;
+use mception_server::core::{AgentConfig, LeafMcpConfig, McpTransport, ServerConfig};
+use mception_server::services::ConfigService;
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::storage::sections::parse_config;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// A configuration with `mcps` leaf MCPs and as many agents, each allowed
+/// its own leaf MCP
+fn large_config(mcps: usize) -> String {
+    let mut config = ServerConfig::default();
+    for at in 0..mcps {
+        let id = format!("mcp-{:05}", at);
+        config.leaf_mcps.insert(
+            id.clone(),
+            LeafMcpConfig {
+                id: id.clone(),
+                name: Some(format!("Leaf MCP {}", at)),
+                description: Some("Generated for the startup benchmark".to_string()),
+                config: json!({ "tags": ["generated", format!("shard-{}", at % 16)] }),
+                ..leaf_mcp(McpTransport::Https {
+                    url: format!("https://mcp-{}.example.com/mcp", at),
+                    headers: None,
+                })
+            },
+        );
+        let agent_id = format!("agent-{:05}", at);
+        config.agents.insert(
+            agent_id.clone(),
+            AgentConfig {
+                agent_id,
+                name: None,
+                description: None,
+                allowed_mcp_ids: vec![id],
+                is_connected: false,
+                last_seen: None,
+                config: json!({}),
+                auth_token: None,
+                auth_token_expires_at: None,
+                region: None,
+                tool_filters: Default::default(),
+                grant_sources: Default::default(),
+                grant_expiry: Default::default(),
+                labels: Default::default(),
+                revision: 0,
+            },
+        );
+    }
+    serde_json::to_string_pretty(&config).unwrap()
+}
+
+#[test]
+fn sectioned_parsing_matches_serde() {
+    let content = large_config(50);
+    let parsed = parse_config(&content).unwrap();
+    let expected: ServerConfig = serde_json::from_str(&content).unwrap();
+    assert_eq!(
+        serde_json::to_value(&parsed).unwrap(),
+        serde_json::to_value(&expected).unwrap()
+    );
+
+    // A missing section is still an error
+    let mut document: Value = serde_json::from_str(&content).unwrap();
+    document.as_object_mut().unwrap().remove("agents");
+    let missing = parse_config(&document.to_string()).unwrap_err();
+    assert!(missing.to_string().contains("agents"), "{}", missing);
+    // So is an invalid leaf MCP
+    document["agents"] = json!({});
+    document["leaf_mcps"]["mcp-00007"]["transport"] = json!({ "type": "carrier-pigeon" });
+    assert!(parse_config(&document.to_string()).is_err());
+}
+
+/// Run with `cargo test --release --test startup_load -- --ignored --nocapture`
+#[test]
+#[ignore = "benchmark"]
+fn benchmark_parsing_10k_mcps() {
+    let content = large_config(10_000);
+    let runs = 5;
+    let time = |parse: &dyn Fn() -> ServerConfig| {
+        let started = Instant::now();
+        for _ in 0..runs {
+            assert_eq!(parse().leaf_mcps.len(), 10_000);
+        }
+        started.elapsed() / runs
+    };
+    let whole = time(&|| serde_json::from_str(&content).unwrap());
+    let sectioned = time(&|| parse_config(&content).unwrap());
+    println!(
+        "{} KiB with 10k leaf MCPs and 10k agents: {:?} as one document, {:?} by section on {} threads",
+        content.len() / 1024,
+        whole,
+        sectioned,
+        std::thread::available_parallelism().map_or(1, |threads| threads.get())
+    );
+}
+
+#[tokio::test]
+async fn requests_never_observe_a_half_loaded_config() {
+    let dir = temp_dir();
+    let config_path = dir.join("config.json");
+    std::fs::write(&config_path, large_config(2_000)).unwrap();
+    let service = Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(config_path.to_string_lossy())),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ));
+    service.lifecycle().begin_loading();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let router = build_router(service.clone(), Default::default());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    let client = reqwest::Client::new();
+    let get = |path: &str| client.get(format!("{}{}", url, path)).send();
+
+    // Probes answer while loading, everything else is refused
+    let health: Value = get("/health").await.unwrap().json().await.unwrap();
+    assert_eq!(health["status"], "loading");
+    assert_eq!(
+        get("/ready").await.unwrap().status(),
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+    let refused = get("/admin/config/revision").await.unwrap();
+    assert_eq!(refused.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(refused.headers()["retry-after"], "1");
+    let body: Value = refused.json().await.unwrap();
+    assert_eq!(body["error"]["kind"], "loading");
+
+    let loader = service.clone();
+    let loading = tokio::spawn(async move {
+        loader.load_configuration().await.unwrap();
+        loader.lifecycle().finish_loading();
+    });
+
+    // Every answer is either a refusal or the whole configuration
+    let mut refusals = 0;
+    loop {
+        let response = get("/admin/leaf/mcp-01999/config").await.unwrap();
+        match response.status() {
+            StatusCode::SERVICE_UNAVAILABLE => {
+                assert!(response.headers().contains_key("retry-after"));
+                refusals += 1;
+            }
+            StatusCode::OK => break,
+            status => panic!("{} while loading", status),
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    }
+    loading.await.unwrap();
+    assert!(refusals < 10_000);
+    let agents: Value = get("/admin/agent/agent-01999/config")
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(agents["allowed_mcp_ids"], json!(["mcp-01999"]));
+    assert_eq!(get("/ready").await.unwrap().status(), StatusCode::OK);
+    std::fs::remove_dir_all(dir).unwrap();
+}
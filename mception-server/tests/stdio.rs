@@ -0,0 +1,340 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::leaf_mcp;
+use mception_server::core::{LeafMcpConfig, McpTransport, ReverseRequestPolicy, StdioSandbox};
+use mception_server::services::ConfigService;
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::{RouterOptions, build_router};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A minimal stdio MCP server. Its tools are `pid`, `crash` (exits without
+/// answering), `hang` (never answers), `malformed` (answers with neither a
+/// result nor an error) and `ask_roots` (asks the client for its roots and
+/// returns the answer it got).
+const LEAF_SCRIPT: &str = r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | grep -o '"id":[0-9]*' | head -n 1 | cut -d: -f2)
+  case "$line" in
+    *'"method":"initialize"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"protocolVersion":"2025-03-26","capabilities":{"tools":{}},"serverInfo":{"name":"script","version":"1"}}}\n' "$id" ;;
+    *'"name":"pid"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"content":[{"type":"text","text":"%s"}]}}\n' "$id" "$$" ;;
+    *'"name":"crash"'*)
+      exit 3 ;;
+    *'"name":"hang"'*)
+      ;;
+    *'"name":"malformed"'*)
+      printf '{"jsonrpc":"2.0","id":%s}\n' "$id" ;;
+    *'"name":"ask_roots"'*)
+      printf '{"jsonrpc":"2.0","id":"roots-1","method":"roots/list"}\n'
+      IFS= read -r answer
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"answer":%s}}\n' "$id" "$answer" ;;
+  esac
+done
+"#;
+
+async fn serve_with_script_leaf() -> String {
+    serve_script_leaf().await.0
+}
+
+/// The server and the URL of its admin API besides the forwarding URL
+async fn serve_script_leaf() -> (String, String, Arc<ConfigService>) {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("leaf.sh");
+    std::fs::write(&script, LEAF_SCRIPT).unwrap();
+
+    let service = Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ));
+    service
+        .create_leaf_mcp(
+            Some("script".to_string()),
+            LeafMcpConfig {
+                reverse_requests: ReverseRequestPolicy::Reject,
+                ..leaf_mcp(McpTransport::Stdio {
+                    command: "sh".to_string(),
+                    args: vec![script.to_string_lossy().into_owned()],
+                    env: None,
+                    sandbox: StdioSandbox::default(),
+                })
+            },
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!(
+        "http://{}/leaf/script/forwarding",
+        listener.local_addr().unwrap()
+    );
+    let admin = format!("http://{}/admin", listener.local_addr().unwrap());
+    let router = build_router(service.clone(), RouterOptions::default());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    (url, admin, service)
+}
+
+fn call(id: Value, tool: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": "tools/call",
+        "params": { "name": tool, "arguments": {} }
+    })
+}
+
+async fn post(url: &str, message: &Value) -> (u16, Value) {
+    let response = reqwest::Client::new()
+        .post(url)
+        .header("x-mception-deadline-ms", "2000")
+        .json(message)
+        .send()
+        .await
+        .unwrap();
+    (response.status().as_u16(), response.json().await.unwrap())
+}
+
+async fn pid(url: &str) -> String {
+    let (status, response) = post(url, &call(json!("pid-call"), "pid")).await;
+    assert_eq!(status, 200, "{}", response);
+    assert_eq!(response["id"], "pid-call");
+    response["result"]["content"][0]["text"]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+#[tokio::test]
+async fn forwards_to_a_reused_process() {
+    let url = serve_with_script_leaf().await;
+
+    // The server already initialized the process and answers from the handshake
+    let (status, response) = post(
+        &url,
+        &json!({
+            "jsonrpc": "2.0",
+            "id": 42,
+            "method": "initialize",
+            "params": { "protocolVersion": "2025-06-18", "capabilities": {} }
+        }),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(response["id"], 42);
+    assert_eq!(response["result"]["serverInfo"]["name"], "script");
+
+    let (status, response) = post(
+        &url,
+        &json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(response, Value::Null);
+
+    let first = pid(&url).await;
+    let calls: Vec<_> = (0..5).map(|_| pid(&url)).collect();
+    for call in calls {
+        assert_eq!(call.await, first);
+    }
+}
+
+#[tokio::test]
+async fn remote_configs_hint_the_protocol_version_the_process_answered() {
+    let (url, _, service) = serve_script_leaf().await;
+    service
+        .create_agent(
+            Some("assistant".to_string()),
+            None,
+            vec!["script".to_string()],
+            None,
+        )
+        .await
+        .unwrap();
+    let hint = || async {
+        service
+            .get_agent_remote_config("assistant")
+            .await
+            .unwrap()
+            .mcps["script"]
+            .protocol_hint
+            .clone()
+    };
+
+    // Unknown until the process runs
+    assert_eq!(hint().await, None);
+    pid(&url).await;
+    assert_eq!(hint().await.as_deref(), Some("2025-03-26"));
+
+    // Not the one of a process another configuration started
+    service
+        .update_leaf_mcp("script", json!({ "reverse_requests": "relay" }), None, None)
+        .await
+        .unwrap();
+    assert_eq!(hint().await, None);
+}
+
+#[tokio::test]
+async fn exited_process_is_a_bad_gateway_and_restarted() {
+    let url = serve_with_script_leaf().await;
+    let first = pid(&url).await;
+
+    let (status, response) = post(&url, &call(json!(1), "crash")).await;
+    assert_eq!(status, 502, "{}", response);
+    assert_eq!(response["error"]["code"], "leaf_unreachable");
+    assert!(
+        response["error"]["detail"]
+            .as_str()
+            .unwrap()
+            .contains("process exited")
+    );
+
+    assert_ne!(pid(&url).await, first);
+}
+
+#[tokio::test]
+async fn malformed_response_is_a_bad_gateway() {
+    let url = serve_with_script_leaf().await;
+    let first = pid(&url).await;
+
+    let (status, response) = post(&url, &call(json!(1), "malformed")).await;
+    assert_eq!(status, 502, "{}", response);
+    assert_eq!(response["error"]["code"], "leaf_unreachable");
+    assert!(
+        response["error"]["detail"]
+            .as_str()
+            .unwrap()
+            .contains("invalid JSON-RPC response")
+    );
+
+    // The process keeps serving
+    assert_eq!(pid(&url).await, first);
+}
+
+#[tokio::test]
+async fn unanswered_request_times_out_without_blocking_others() {
+    let url = serve_with_script_leaf().await;
+    let first = pid(&url).await;
+
+    let response = reqwest::Client::new()
+        .post(&url)
+        .header("x-mception-deadline-ms", "200")
+        .json(&call(json!(1), "hang"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 504);
+
+    assert_eq!(pid(&url).await, first);
+}
+
+#[tokio::test]
+async fn server_initiated_requests_are_rejected() {
+    let url = serve_with_script_leaf().await;
+
+    let (status, response) = post(&url, &call(json!(7), "ask_roots")).await;
+    assert_eq!(status, 200, "{}", response);
+    let answer = &response["result"]["answer"];
+    assert_eq!(answer["id"], "roots-1");
+    assert_eq!(answer["error"]["code"], -32601);
+}
+
+async fn process_status(admin: &str) -> Value {
+    let body: Value = reqwest::get(format!("{}/leaf/script/status", admin))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    body["process"].clone()
+}
+
+fn is_running(pid: &str) -> bool {
+    std::process::Command::new("kill")
+        .args(["-0", pid])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .unwrap()
+        .success()
+}
+
+#[tokio::test]
+async fn exited_processes_are_restarted_in_the_background() {
+    let (url, admin, _service) = serve_script_leaf().await;
+    assert_eq!(process_status(&admin).await, Value::Null);
+    let first = pid(&url).await;
+    let status = process_status(&admin).await;
+    assert_eq!(status["state"], "running");
+    assert_eq!(status["pid"].to_string(), first);
+
+    let (status, _) = post(&url, &call(json!(1), "crash")).await;
+    assert_eq!(status, 502);
+    let mut status = Value::Null;
+    for _ in 0..50 {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        status = process_status(&admin).await;
+        if status["state"] == "running" && status["restarts"] == 1 {
+            break;
+        }
+    }
+    assert_eq!(status["state"], "running", "{}", status);
+    assert_eq!(status["restarts"], 1);
+    assert_eq!(status["last_exit_code"], 3);
+    let restarted = status["pid"].to_string();
+    assert_ne!(restarted, first);
+    assert!(is_running(&restarted));
+    assert_eq!(pid(&url).await, restarted);
+
+    let response = reqwest::get(format!("{}/leaf/missing/status", admin))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn deleting_a_leaf_mcp_stops_its_process() {
+    let (url, admin, service) = serve_script_leaf().await;
+    let running = pid(&url).await;
+    assert!(is_running(&running));
+
+    service.delete_leaf_mcp("script", None, None).await.unwrap();
+    assert!(!is_running(&running));
+    assert!(service.stdio_processes().status("script").is_none());
+    let response = reqwest::get(format!("{}/leaf/script/status", admin))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn updating_a_leaf_mcp_replaces_its_process() {
+    let (url, _admin, service) = serve_script_leaf().await;
+    let running = pid(&url).await;
+
+    // Changes the process doesn't see keep it running
+    service
+        .update_leaf_mcp("script", json!({ "description": "Scripted" }), None, None)
+        .await
+        .unwrap();
+    assert!(is_running(&running));
+    assert_eq!(pid(&url).await, running);
+
+    service
+        .update_leaf_mcp("script", json!({ "reverse_requests": "relay" }), None, None)
+        .await
+        .unwrap();
+    assert!(!is_running(&running));
+    assert!(service.stdio_processes().status("script").is_none());
+    let restarted = pid(&url).await;
+    assert_ne!(restarted, running);
+}
@@ -0,0 +1,312 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use chrono::{Duration, Utc};
+use common::temp_dir;
+use mception_server::core::testing::load_fixture;
+use mception_server::core::{
+    AuditAction, AuditLogEntry, AuditQuery, AuditTarget, MceptionError, ServerConfig, StorageError,
+};
+use mception_server::services::ConfigService;
+use mception_server::storage::providers::sqlite::Connection;
+use mception_server::storage::providers::{
+    AuditStorage, ConfigStorage, FileAuditStorage, FileConfigStorage, SqliteAuditStorage,
+    SqliteConfigStorage,
+};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Both backends, storing in `dir`
+fn backends(dir: &Path) -> Vec<(Arc<dyn ConfigStorage>, Arc<dyn AuditStorage>)> {
+    let database = dir.join("mception.db").to_string_lossy().into_owned();
+    vec![
+        (
+            Arc::new(FileConfigStorage::new(
+                dir.join("config.json").to_string_lossy(),
+            )),
+            Arc::new(FileAuditStorage::new(
+                dir.join("audit.log").to_string_lossy(),
+            )),
+        ),
+        (
+            Arc::new(SqliteConfigStorage::open(&database).unwrap()),
+            Arc::new(SqliteAuditStorage::open(&database).unwrap()),
+        ),
+    ]
+}
+
+fn entry(action: AuditAction, target: AuditTarget, actor: &str) -> AuditLogEntry {
+    AuditLogEntry {
+        id: uuid::Uuid::new_v4().to_string(),
+        sequence: 0,
+        timestamp: Utc::now(),
+        action,
+        actor: Some(actor.to_string()),
+        target,
+        reason: None,
+        details: serde_json::json!({}),
+        correlation_id: None,
+        idempotency_key: None,
+        revision: None,
+        server_revision: None,
+        prev_hash: None,
+        hash: None,
+    }
+}
+
+#[tokio::test]
+async fn configurations_are_stored_alike() {
+    let dir = temp_dir();
+    for (storage, _) in backends(&dir) {
+        let location = storage.location();
+        assert!(!storage.config_exists().await.unwrap(), "{}", location);
+        // Created on first load
+        let created = storage.load_config().await.unwrap();
+        assert!(created.leaf_mcps.is_empty(), "{}", location);
+        assert!(storage.config_exists().await.unwrap(), "{}", location);
+
+        let fixture: ServerConfig = serde_json::from_str(&load_fixture("config.json")).unwrap();
+        storage.save_config(&fixture).await.unwrap();
+        let loaded = storage.load_config().await.unwrap();
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&fixture).unwrap(),
+            "{}",
+            location
+        );
+
+        let backup = storage.backup_config().await.unwrap();
+        let mut changed = fixture.clone();
+        changed.agents.clear();
+        changed.metadata.revision += 1;
+        storage.save_config(&changed).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        storage.backup_config().await.unwrap();
+
+        let backups = storage.list_backups().await.unwrap();
+        assert_eq!(backups.len(), 2, "{}", location);
+        let name = &backups[0].name;
+        assert!(backup.ends_with(name.as_str()), "{}", location);
+        let restored = storage.restore_backup(name).await.unwrap();
+        assert_eq!(restored.agents.len(), 2, "{}", location);
+        assert_eq!(storage.load_config().await.unwrap().agents.len(), 2);
+        assert!(
+            storage.load_backup("missing").await.is_err(),
+            "{}",
+            location
+        );
+
+        assert_eq!(
+            storage.prune_backups(1).await.unwrap(),
+            std::slice::from_ref(name)
+        );
+        assert_eq!(storage.list_backups().await.unwrap().len(), 1);
+        assert!(storage.migration_status().await.unwrap().pending.is_empty());
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn audit_logs_are_stored_alike() {
+    let dir = temp_dir();
+    for (_, storage) in backends(&dir) {
+        let location = storage.location();
+        let agent = |id: &str| AuditTarget::Agent { id: id.to_string() };
+        let first = storage
+            .append_entry(&entry(AuditAction::Create, agent("one"), "alice"))
+            .await
+            .unwrap();
+        assert_eq!(first.sequence, 1, "{}", location);
+        storage
+            .append_entry(&entry(AuditAction::Update, agent("one"), "monitor_bot"))
+            .await
+            .unwrap();
+        let mut late = entry(AuditAction::Delete, agent("one"), "alice");
+        late.timestamp -= Duration::seconds(5);
+        let appended = storage.append_entry(&late).await.unwrap();
+        assert_eq!(appended.sequence, 3, "{}", location);
+        assert!(appended.behind_predecessor.is_some(), "{}", location);
+
+        let entries = storage.load_entries().await.unwrap();
+        let sequences: Vec<u64> = entries.iter().map(|entry| entry.sequence).collect();
+        assert_eq!(sequences, [1, 2, 3], "{}", location);
+
+        let filtered = |query: AuditQuery| {
+            let storage = storage.clone();
+            async move {
+                storage
+                    .load_entries_filtered(&query)
+                    .await
+                    .unwrap()
+                    .iter()
+                    .map(|entry| entry.sequence)
+                    .collect::<Vec<_>>()
+            }
+        };
+        let by_actor = AuditQuery {
+            actor: Some("ALICE".to_string()),
+            ..AuditQuery::default()
+        };
+        assert_eq!(filtered(by_actor).await, [1, 3], "{}", location);
+        // `_` is matched literally, not as a wildcard
+        let underscore = AuditQuery {
+            actor: Some("r_b".to_string()),
+            ..AuditQuery::default()
+        };
+        assert_eq!(filtered(underscore).await, [2], "{}", location);
+        let deletes = AuditQuery {
+            action: Some("delete".to_string()),
+            target_type: Some("agent".to_string()),
+            ..AuditQuery::default()
+        };
+        assert_eq!(filtered(deletes).await, [3], "{}", location);
+        let since = AuditQuery {
+            since: Some(entries[0].timestamp),
+            ..AuditQuery::default()
+        };
+        assert_eq!(filtered(since).await, [1, 2], "{}", location);
+
+        let report = storage.verify().await.unwrap();
+        assert!(report.is_clean(), "{}", location);
+        assert_eq!(report.valid_entries, 3, "{}", location);
+        let output = dir.join(format!("repaired-{}.log", entries[0].id));
+        storage.repair(&output.to_string_lossy()).await.unwrap();
+        let repaired = FileAuditStorage::new(output.to_string_lossy());
+        assert_eq!(
+            repaired.load_entries().await.unwrap().len(),
+            3,
+            "{}",
+            location
+        );
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn audit_filters_match_the_names_entries_are_listed_with() {
+    let dir = temp_dir();
+    for (_, storage) in backends(&dir) {
+        let location = storage.location();
+        let target = AuditTarget::AgentAllowedMcp {
+            agent_id: "bot".to_string(),
+            mcp_id: "search".to_string(),
+        };
+        storage
+            .append_entry(&entry(AuditAction::AddAllowedMcp, target, "alice"))
+            .await
+            .unwrap();
+        let listed = serde_json::to_value(&storage.load_entries().await.unwrap()[0]).unwrap();
+        let query = AuditQuery {
+            action: listed["action"]["type"].as_str().map(str::to_string),
+            target_type: listed["target"]["type"].as_str().map(str::to_string),
+            ..AuditQuery::default()
+        };
+        assert_eq!(
+            query.action.as_deref(),
+            Some("add_allowed_mcp"),
+            "{}",
+            location
+        );
+        let filtered = storage.load_entries_filtered(&query).await.unwrap();
+        assert_eq!(filtered.len(), 1, "{}", location);
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn sqlite_audit_columns_written_by_the_first_schema_are_renamed() {
+    let dir = temp_dir();
+    let database = dir.join("mception.db").to_string_lossy().into_owned();
+    let target = AuditTarget::LeafMcp {
+        id: "search".to_string(),
+    };
+    SqliteAuditStorage::open(&database)
+        .unwrap()
+        .append_entry(&entry(AuditAction::ClearFaults, target, "alice"))
+        .await
+        .unwrap();
+    // Back to the lowercased Rust names the first schema version stored
+    Connection::open(&database)
+        .unwrap()
+        .execute_batch(
+            "DROP TRIGGER audit_log_no_update;
+            UPDATE audit_log SET action = 'clearfaults', target_type = 'leafmcp';
+            CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON audit_log
+                BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;
+            PRAGMA user_version = 1;",
+        )
+        .unwrap();
+
+    let storage = SqliteAuditStorage::open(&database).unwrap();
+    let query = AuditQuery {
+        action: Some("clear_faults".to_string()),
+        target_type: Some("leaf_mcp".to_string()),
+        ..AuditQuery::default()
+    };
+    assert_eq!(
+        storage.load_entries_filtered(&query).await.unwrap().len(),
+        1
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn sqlite_refuses_to_overwrite_changes_of_another_server() {
+    let dir = temp_dir();
+    let database = dir.join("shared.db").to_string_lossy().into_owned();
+    let service = |database: &str| {
+        ConfigService::new(
+            Arc::new(SqliteConfigStorage::open(database).unwrap()),
+            Arc::new(SqliteAuditStorage::open(database).unwrap()),
+        )
+    };
+    let first = service(&database);
+    let second = service(&database);
+    first.load_configuration().await.unwrap();
+    second.load_configuration().await.unwrap();
+
+    first
+        .create_agent(Some("one".to_string()), None, vec![], None)
+        .await
+        .unwrap();
+    let error = second
+        .create_agent(Some("two".to_string()), None, vec![], None)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(error, MceptionError::Storage(StorageError::Conflict(_))),
+        "{}",
+        error
+    );
+
+    // A server loading afterwards sees the first server's change
+    let third = service(&database);
+    third.load_configuration().await.unwrap();
+    assert!(third.get_agent("one", None).await.is_ok());
+    assert!(third.get_agent("two", None).await.is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn sqlite_audit_log_is_append_only() {
+    let dir = temp_dir();
+    let database = dir.join("mception.db").to_string_lossy().into_owned();
+    let storage = SqliteAuditStorage::open(&database).unwrap();
+    storage
+        .append_entry(&entry(AuditAction::Create, AuditTarget::Server, "alice"))
+        .await
+        .unwrap();
+
+    let connection = Connection::open(&database).unwrap();
+    let error = connection
+        .execute("DELETE FROM audit_log", &[])
+        .unwrap_err();
+    assert!(error.contains("append-only"), "{}", error);
+    let error = connection
+        .execute("UPDATE audit_log SET actor = 'mallory'", &[])
+        .unwrap_err();
+    assert!(error.contains("append-only"), "{}", error);
+    assert_eq!(storage.load_entries().await.unwrap().len(), 1);
+    std::fs::remove_dir_all(dir).unwrap();
+}
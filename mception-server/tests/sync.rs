@@ -0,0 +1,198 @@
+mod common;
+
+use axum::Router;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, Method as HttpMethod, StatusCode};
+use axum::routing::any;
+use common::TestServer;
+use mception_server::core::{ServerConfig, SyncTarget};
+use mception_server::services::sync::{self, SIGNATURE_HEADER};
+use reqwest::Method;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn config_with(targets: Vec<(&str, SyncTarget)>) -> String {
+    let mut config = ServerConfig::default();
+    for (id, target) in targets {
+        config.sync_targets.insert(id.to_string(), target);
+    }
+    serde_json::to_string(&config).unwrap()
+}
+
+async fn create_agent(server: &TestServer, id: &str, allowed: &[&str]) {
+    let (status, body) = server
+        .admin_json(
+            Method::POST,
+            "/agent",
+            &json!({ "agent_id": id, "allowed_mcp_ids": allowed }),
+        )
+        .await;
+    assert!(status.is_success(), "{}", body);
+}
+
+#[tokio::test]
+async fn file_targets_mirror_changed_agents() {
+    let mirror = std::env::temp_dir().join(format!("mception-sync-{}", uuid::Uuid::new_v4()));
+    let target = SyncTarget::File {
+        directory: mirror.to_string_lossy().into_owned(),
+    };
+    let server = TestServer::builder()
+        .config(&config_with(vec![("mirror", target)]))
+        .start()
+        .await;
+    let (status, _) = server
+        .admin_json(
+            Method::POST,
+            "/leaf",
+            &json!({ "id": "echo", "config": {
+                "transport": { "type": "builtin", "kind": "echo" },
+                "is_local": true,
+                "reachable_by_agent": false,
+                "config": {}
+            }}),
+        )
+        .await;
+    assert!(status.is_success());
+    create_agent(&server, "writer", &["echo"]).await;
+    create_agent(&server, "reader", &[]).await;
+
+    let statuses = sync::run(&server.service, None, false).await.unwrap();
+    assert_eq!(statuses["mirror"].pushed, 2);
+    let written: Value =
+        serde_json::from_slice(&std::fs::read(mirror.join("writer.json")).unwrap()).unwrap();
+    assert_eq!(written["agent_id"], "writer");
+    assert!(written["mcps"].get("echo").is_some());
+
+    // Only agents whose configuration changed are pushed again
+    let (status, _) = server
+        .admin_json(
+            Method::POST,
+            "/agent/reader/allowed_mcps",
+            &json!({ "mcp_id": "echo" }),
+        )
+        .await;
+    assert!(status.is_success());
+    let statuses = sync::run(&server.service, None, false).await.unwrap();
+    assert_eq!(statuses["mirror"].pushed, 1);
+
+    let (status, _) = server
+        .admin_json(Method::DELETE, "/agent/writer", &json!({}))
+        .await;
+    assert!(status.is_success());
+    let statuses = sync::run(&server.service, None, false).await.unwrap();
+    assert_eq!(statuses["mirror"].removed, 1);
+    assert!(!mirror.join("writer.json").exists());
+    assert!(mirror.join("reader.json").exists());
+
+    // A manual run pushes everything
+    let (status, body) = server
+        .admin_json(Method::POST, "/sync/mirror/run", &json!({}))
+        .await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["status"]["pushed"], 1);
+    let (status, _) = server
+        .admin_json(Method::POST, "/sync/missing/run", &json!({}))
+        .await;
+    assert_eq!(status, 404);
+    let _ = std::fs::remove_dir_all(&mirror);
+}
+
+/// Requests a fake config store got, by agent
+#[derive(Default)]
+struct Store {
+    requests: Mutex<Vec<(String, String, HeaderMap, String)>>,
+    failing: Mutex<bool>,
+}
+
+async fn store(
+    State(store): State<Arc<Store>>,
+    method: HttpMethod,
+    Path(agent_id): Path<String>,
+    headers: HeaderMap,
+    body: String,
+) -> StatusCode {
+    if *store.failing.lock().unwrap() {
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+    store
+        .requests
+        .lock()
+        .unwrap()
+        .push((method.to_string(), agent_id, headers, body));
+    StatusCode::OK
+}
+
+#[tokio::test]
+async fn http_targets_get_signed_pushes_and_report_failures() {
+    let fake = Arc::new(Store::default());
+    let app = Router::new()
+        .route("/kv/agents/{agent_id}", any(store))
+        .with_state(fake.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!(
+        "http://{}/kv/agents/{{agent_id}}",
+        listener.local_addr().unwrap()
+    );
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    let target = SyncTarget::Http {
+        url,
+        headers: Some(BTreeMap::from([(
+            "authorization".to_string(),
+            "Bearer consul-token".to_string(),
+        )])),
+        signing_secret: Some("s3cret".to_string()),
+    };
+    let server = TestServer::builder()
+        .config(&config_with(vec![("consul", target)]))
+        .start()
+        .await;
+    tokio::spawn(sync::watch(server.service.clone()));
+
+    create_agent(&server, "writer", &[]).await;
+    let mut pushed = None;
+    for _ in 0..50 {
+        pushed = fake.requests.lock().unwrap().first().cloned();
+        if pushed.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    let (method, agent_id, headers, body) = pushed.expect("the change was pushed");
+    assert_eq!((method.as_str(), agent_id.as_str()), ("PUT", "writer"));
+    assert_eq!(headers["authorization"], "Bearer consul-token");
+    assert_eq!(
+        headers[SIGNATURE_HEADER].to_str().unwrap(),
+        format!("sha256={}", sync::signature("s3cret", body.as_bytes()))
+    );
+    let body: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["agent_id"], "writer");
+
+    // A failing store doesn't fail the change, it shows in the status
+    *fake.failing.lock().unwrap() = true;
+    create_agent(&server, "reader", &[]).await;
+    let statuses = sync::run(&server.service, None, false).await.unwrap();
+    assert!(statuses["consul"].failed.contains_key("reader"));
+    let (status, body) = server.admin_get("/sync").await;
+    assert_eq!(status, 200);
+    let consul = &body["targets"]["consul"];
+    assert_eq!(consul["target"]["signing_secret"], "[REDACTED]");
+    assert_eq!(consul["target"]["headers"]["authorization"], "[REDACTED]");
+    assert!(consul["status"]["failed"].get("reader").is_some());
+
+    // The next run retries it
+    *fake.failing.lock().unwrap() = false;
+    let statuses = sync::run(&server.service, None, false).await.unwrap();
+    assert_eq!(statuses["consul"].pushed, 1);
+    assert!(statuses["consul"].failed.is_empty());
+}
+
+#[test]
+fn signatures_are_hmac_sha256() {
+    // RFC 4231, test case 2
+    assert_eq!(
+        sync::signature("Jefe", b"what do ya want for nothing?"),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+}
@@ -0,0 +1,767 @@
+mod common;
+
+use axum::Router;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use common::leaf_mcp;
+use futures_util::{SinkExt, StreamExt};
+use mception_server::core::{BodyEncoding, BuiltinMcpKind, ForwardingMessage, McpTransport};
+use mception_server::services::ConfigService;
+use mception_server::storage::providers::{FileAuditStorage, FileConfigStorage};
+use mception_server::{RouterOptions, build_router};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+
+/// A streamable HTTP MCP with two pages of tools, answering `tools/list` as
+/// an event stream and only within the session it assigned. Returns its URL
+/// and the number of `tools/list` requests it answered.
+async fn serve_upstream() -> (String, Arc<AtomicUsize>) {
+    let listed = Arc::new(AtomicUsize::new(0));
+    let counter = listed.clone();
+    let handler = move |headers: HeaderMap, body: String| {
+        let counter = counter.clone();
+        async move {
+            let message: Value = serde_json::from_str(&body).unwrap();
+            let method = message["method"].as_str().unwrap();
+            if method == "initialize" {
+                let response = json!({ "jsonrpc": "2.0", "id": message["id"], "result": {
+                    "protocolVersion": "2025-06-18", "capabilities": { "tools": {} }
+                }});
+                return ([("mcp-session-id", "session-1")], axum::Json(response)).into_response();
+            }
+            if headers
+                .get("mcp-session-id")
+                .and_then(|id| id.to_str().ok())
+                != Some("session-1")
+            {
+                return StatusCode::BAD_REQUEST.into_response();
+            }
+            if method != "tools/list" {
+                return StatusCode::ACCEPTED.into_response();
+            }
+            counter.fetch_add(1, Ordering::SeqCst);
+            let result = match message["params"]["cursor"].as_str() {
+                None => json!({
+                    "tools": [{ "name": "search", "description": "Search documents",
+                                "inputSchema": { "type": "object" } }],
+                    "nextCursor": "page-2"
+                }),
+                Some(_) => json!({ "tools": [{ "name": "fetch", "inputSchema": {} }] }),
+            };
+            let response = json!({ "jsonrpc": "2.0", "id": message["id"], "result": result });
+            let stream = format!("event: message\ndata: {}\n\n", response);
+            Response::builder()
+                .header("content-type", "text/event-stream")
+                .body(axum::body::Body::from(stream))
+                .unwrap()
+        }
+    };
+
+    let app = Router::new().route("/mcp", post(handler));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (url, listed)
+}
+
+async fn service(leafs: Vec<(&str, McpTransport)>) -> Arc<ConfigService> {
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let service = Arc::new(ConfigService::new(
+        Arc::new(FileConfigStorage::new(
+            dir.join("config.json").to_string_lossy(),
+        )),
+        Arc::new(FileAuditStorage::new(
+            dir.join("audit.log").to_string_lossy(),
+        )),
+    ));
+    for (id, transport) in leafs {
+        service
+            .create_leaf_mcp(Some(id.to_string()), leaf_mcp(transport), None, None)
+            .await
+            .unwrap();
+    }
+    service
+}
+
+async fn serve(leafs: Vec<(&str, McpTransport)>) -> String {
+    listen(service(leafs).await).await
+}
+
+async fn listen(service: Arc<ConfigService>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let router = build_router(service, RouterOptions::default());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    url
+}
+
+async fn get_tools(url: &str) -> (u16, Value) {
+    let response = reqwest::get(url).await.unwrap();
+    (response.status().as_u16(), response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn builtin_tools_are_listed() {
+    let url = serve(vec![(
+        "echo",
+        McpTransport::Builtin {
+            kind: BuiltinMcpKind::Echo,
+        },
+    )])
+    .await;
+
+    let (status, body) = get_tools(&format!("{}/admin/leaf/echo/tools", url)).await;
+    assert_eq!(status, 200);
+    let names: Vec<_> = body["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tool| tool["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["echo", "sleep_ms", "fail_with"]);
+    assert_eq!(body["tools"][0]["parameters"]["required"], json!(["text"]));
+}
+
+#[tokio::test]
+async fn https_tools_are_listed_and_cached() {
+    let (upstream, listed) = serve_upstream().await;
+    let url = serve(vec![(
+        "docs",
+        McpTransport::Https {
+            url: upstream,
+            headers: None,
+        },
+    )])
+    .await;
+    let tools_url = format!("{}/admin/leaf/docs/tools", url);
+
+    let (status, body) = get_tools(&tools_url).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["cached"], false);
+    assert_eq!(body["tools"][0]["name"], "search");
+    assert_eq!(body["tools"][0]["description"], "Search documents");
+    assert_eq!(body["tools"][1]["name"], "fetch");
+    assert_eq!(body["tools"][1]["description"], "");
+    // One request per page
+    assert_eq!(listed.load(Ordering::SeqCst), 2);
+
+    let (_, body) = get_tools(&tools_url).await;
+    assert_eq!(body["cached"], true);
+    assert_eq!(body["tools"].as_array().unwrap().len(), 2);
+    assert_eq!(listed.load(Ordering::SeqCst), 2);
+
+    let (_, body) = get_tools(&format!("{}?refresh=true", tools_url)).await;
+    assert_eq!(body["cached"], false);
+    assert_eq!(listed.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn unreachable_leaf_is_a_bad_gateway() {
+    // Bind and drop a listener to get a port nothing listens on
+    let port = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+    let url = serve(vec![(
+        "down",
+        McpTransport::Https {
+            url: format!("http://127.0.0.1:{}/mcp", port),
+            headers: None,
+        },
+    )])
+    .await;
+
+    let (status, body) = get_tools(&format!("{}/admin/leaf/down/tools", url)).await;
+    assert_eq!(status, 502);
+    assert!(!body["error"]["message"].as_str().unwrap().is_empty());
+
+    let (status, _) = get_tools(&format!("{}/admin/leaf/missing/tools", url)).await;
+    assert_eq!(status, 404);
+}
+
+async fn call_tool(url: &str, leaf: &str, tool: &str, arguments: Value) -> (u16, Value) {
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/leaf/{}/tools/{}/call", url, leaf, tool))
+        .json(&arguments)
+        .send()
+        .await
+        .unwrap();
+    (response.status().as_u16(), response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn tools_are_called_with_valid_arguments() {
+    let url = serve(vec![(
+        "echo",
+        McpTransport::Builtin {
+            kind: BuiltinMcpKind::Echo,
+        },
+    )])
+    .await;
+
+    let (status, body) = call_tool(&url, "echo", "echo", json!({ "text": "hi" })).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["tool"], "echo");
+    assert_eq!(body["result"]["content"][0]["text"], "hi");
+    assert_eq!(body["result"]["isError"], false);
+
+    let (status, body) = call_tool(&url, "echo", "sleep_ms", json!({ "ms": -1, "extra": 1 })).await;
+    assert_eq!(status, 422, "{}", body);
+    assert_eq!(body["error"]["kind"], "schema_violation");
+    assert_eq!(
+        body["error"]["errors"],
+        json!(["arguments.ms must be at least 0"])
+    );
+
+    let (status, body) = call_tool(&url, "echo", "echo", json!({ "text": 1, "other": [] })).await;
+    assert_eq!(status, 422);
+    assert_eq!(
+        body["error"]["errors"],
+        json!(["arguments.text must be of type string"])
+    );
+    let (status, body) = call_tool(&url, "echo", "echo", json!([])).await;
+    assert_eq!(status, 422);
+    assert_eq!(
+        body["error"]["errors"],
+        json!(["arguments must be of type object"])
+    );
+
+    // A JSON-RPC error of the leaf MCP
+    let (status, body) = call_tool(
+        &url,
+        "echo",
+        "fail_with",
+        json!({ "code": -32001, "message": "broken" }),
+    )
+    .await;
+    assert_eq!(status, 502);
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("broken")
+    );
+
+    let (status, _) = call_tool(&url, "echo", "missing", json!({})).await;
+    assert_eq!(status, 404);
+    let (status, _) = call_tool(&url, "missing", "echo", json!({})).await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn tool_calls_report_every_invalid_argument() {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "query": { "type": "string", "minLength": 1 },
+            "limit": { "type": "integer", "maximum": 10 },
+            "kinds": { "type": "array", "items": { "enum": ["pdf", "html"] } }
+        },
+        "required": ["query", "scope"],
+        "additionalProperties": false
+    });
+    let tool = mception_server::core::McpTool {
+        name: "search".to_string(),
+        description: String::new(),
+        parameters: schema,
+    };
+    let errors = mception_server::services::tools::argument_errors(
+        &tool,
+        &json!({ "query": "", "limit": 11, "kinds": ["pdf", "doc"], "page": 2 }),
+    );
+    assert_eq!(
+        errors,
+        vec![
+            "arguments.scope is required",
+            "arguments.kinds[1] must be one of [\"pdf\",\"html\"]",
+            "arguments.limit must be at most 10",
+            "arguments.query must have at least 1 characters",
+            "arguments.page is not allowed",
+        ]
+    );
+
+    // Composition, references, patterns and formats are checked too
+    let tool = mception_server::core::McpTool {
+        name: "notify".to_string(),
+        description: String::new(),
+        parameters: json!({
+            "type": "object",
+            "$defs": { "address": { "type": "string", "format": "email" } },
+            "properties": {
+                "to": { "$ref": "#/$defs/address" },
+                "code": { "type": "string", "pattern": "^[A-Z]{3}$" },
+                "delay": { "type": "number", "exclusiveMinimum": 0 },
+                "channel": { "anyOf": [{ "const": "mail" }, { "const": "sms" }] },
+                "priority": { "oneOf": [{ "type": "integer" }, { "type": "number" }] },
+                "tags": { "allOf": [{ "type": "array" }, { "maxItems": 1 }] }
+            }
+        }),
+    };
+    let valid = json!({ "to": "a@example.com", "code": "ABC", "delay": 1, "channel": "sms" });
+    assert!(mception_server::services::tools::argument_errors(&tool, &valid).is_empty());
+    let errors = mception_server::services::tools::argument_errors(
+        &tool,
+        &json!({
+            "to": "nobody",
+            "code": "abc",
+            "delay": 0,
+            "channel": "fax",
+            "priority": 1,
+            "tags": ["a", "b"]
+        }),
+    );
+    let failed: Vec<&str> = errors
+        .iter()
+        .map(|error| error.split(' ').next().unwrap())
+        .collect();
+    for path in [
+        "arguments.to",
+        "arguments.code",
+        "arguments.delay",
+        "arguments.channel",
+        "arguments.priority",
+        "arguments.tags",
+    ] {
+        assert!(failed.contains(&path), "{} in {:?}", path, errors);
+    }
+
+    // A schema that isn't valid accepts nothing
+    let tool = mception_server::core::McpTool {
+        name: "broken".to_string(),
+        description: String::new(),
+        parameters: json!({ "type": "object", "properties": { "a": { "type": "text" } } }),
+    };
+    let errors = mception_server::services::tools::argument_errors(&tool, &json!({}));
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains("schema is invalid"), "{:?}", errors);
+
+    // Tools without a schema of their own take any object
+    let (upstream, _) = serve_upstream().await;
+    let url = serve(vec![(
+        "docs",
+        McpTransport::Https {
+            url: upstream,
+            headers: None,
+        },
+    )])
+    .await;
+    let (status, body) = call_tool(&url, "docs", "fetch", json!({ "anything": 1 })).await;
+    // Reached the upstream, which only answers `tools/list`
+    assert_eq!(status, 502, "{}", body);
+    let (status, body) = call_tool(&url, "docs", "search", json!("x")).await;
+    assert_eq!(status, 422, "{}", body);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn hung_leaf_times_out() {
+    // Completes the handshake, then never answers
+    let script = r#"
+while IFS= read -r line; do
+  case "$line" in
+    *'"method":"initialize"'*)
+      id=$(printf '%s' "$line" | grep -o '"id":[0-9]*' | head -n 1 | cut -d: -f2)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"protocolVersion":"2025-06-18","capabilities":{"tools":{}}}}\n' "$id" ;;
+  esac
+done
+"#;
+    let dir = std::env::temp_dir().join(format!("mception-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("hung.sh");
+    std::fs::write(&path, script).unwrap();
+    let service = service(vec![]).await;
+    let mut hung = leaf_mcp(McpTransport::Stdio {
+        command: "sh".to_string(),
+        args: vec![path.to_string_lossy().into_owned()],
+        env: None,
+        sandbox: Default::default(),
+    });
+    hung.config = json!({ "timeout": "300ms" });
+    service
+        .create_leaf_mcp(Some("hung".to_string()), hung, None, None)
+        .await
+        .unwrap();
+    let url = listen(service).await;
+
+    let started = std::time::Instant::now();
+    let (status, body) = get_tools(&format!("{}/admin/leaf/hung/tools", url)).await;
+    assert_eq!(status, 502, "{}", body);
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("No response within 300 ms"),
+        "{}",
+        body
+    );
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+/// Connect as `agent_id` and answer forwarded MCP requests with the tool `draft`
+async fn serve_agent_mcp(url: &str, agent_id: &str) {
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!(
+        "{}/agent/{}/forwarding_ws",
+        url.replace("http://", "ws://"),
+        agent_id
+    ))
+    .await
+    .unwrap();
+    tokio::spawn(async move {
+        while let Some(Ok(Message::Text(text))) = socket.next().await {
+            let Ok(ForwardingMessage::Request {
+                request_id, body, ..
+            }) = serde_json::from_str(&text)
+            else {
+                continue;
+            };
+            let message: Value = serde_json::from_str(&body.unwrap()).unwrap();
+            let result = match message["method"].as_str().unwrap() {
+                "initialize" => {
+                    Some(json!({ "protocolVersion": "2025-06-18", "capabilities": {} }))
+                }
+                "tools/list" => Some(json!({ "tools": [{ "name": "draft", "inputSchema": {} }] })),
+                _ => None,
+            };
+            let response = ForwardingMessage::Response {
+                request_id,
+                status_code: if result.is_some() { 200 } else { 202 },
+                headers: BTreeMap::from([
+                    ("Content-Type".to_string(), "application/json".to_string()),
+                    ("Mcp-Session-Id".to_string(), "agent-session".to_string()),
+                ]),
+                body: result.map(|result| {
+                    json!({ "jsonrpc": "2.0", "id": message["id"], "result": result }).to_string()
+                }),
+                encoding: BodyEncoding::Utf8,
+            };
+            let text = serde_json::to_string(&response).unwrap();
+            socket.send(Message::Text(text.into())).await.unwrap();
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+}
+
+#[tokio::test]
+async fn agent_tools_are_grouped_by_mcp() {
+    let port = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+    let service = service(vec![
+        (
+            "echo",
+            McpTransport::Builtin {
+                kind: BuiltinMcpKind::Echo,
+            },
+        ),
+        (
+            "down",
+            McpTransport::Https {
+                url: format!("http://127.0.0.1:{}/mcp", port),
+                headers: None,
+            },
+        ),
+    ])
+    .await;
+    for (id, allowed) in [
+        ("helper", vec![]),
+        ("offline", vec![]),
+        ("writer", vec!["echo", "down", "helper", "offline"]),
+    ] {
+        service
+            .create_agent(
+                Some(id.to_string()),
+                None,
+                allowed.into_iter().map(str::to_string).collect(),
+                None,
+            )
+            .await
+            .unwrap();
+    }
+    let url = listen(service).await;
+    serve_agent_mcp(&url, "helper").await;
+
+    let (status, body) = get_tools(&format!("{}/admin/agent/writer/tools", url)).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["tools"]["echo"].as_array().unwrap().len(), 3);
+    assert_eq!(body["tools"]["helper"][0]["name"], "draft");
+    // Failing MCPs are reported without failing the others
+    assert!(body["tools"].get("down").is_none());
+    assert!(!body["errors"]["down"].as_str().unwrap().is_empty());
+    assert!(
+        body["errors"]["offline"]
+            .as_str()
+            .unwrap()
+            .contains("not connected")
+    );
+
+    let (status, _) = get_tools(&format!("{}/admin/agent/nobody/tools", url)).await;
+    assert_eq!(status, 404);
+}
+
+async fn call_agent_tool(url: &str, agent: &str, name: &str, arguments: Value) -> (u16, Value) {
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/agent/{}/tools/call", url, agent))
+        .json(&json!({ "name": name, "arguments": arguments }))
+        .send()
+        .await
+        .unwrap();
+    (response.status().as_u16(), response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn agent_tools_are_named_and_called_by_their_mcp() {
+    let echo = || McpTransport::Builtin {
+        kind: BuiltinMcpKind::Echo,
+    };
+    let service = service(vec![("alpha", echo()), ("beta", echo()), ("other", echo())]).await;
+    service
+        .create_agent(
+            Some("writer".to_string()),
+            None,
+            vec!["alpha".to_string(), "beta".to_string()],
+            None,
+        )
+        .await
+        .unwrap();
+    let url = listen(service).await;
+
+    let (status, body) = get_tools(&format!("{}/admin/agent/writer/tools", url)).await;
+    assert_eq!(status, 200, "{}", body);
+    let names: Vec<&str> = body["merged"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tool| tool["name"].as_str().unwrap())
+        .collect();
+    // Both MCPs' `echo` tools are listed, apart
+    assert!(names.contains(&"alpha/echo"), "{:?}", names);
+    assert!(names.contains(&"beta/echo"), "{:?}", names);
+    assert_eq!(
+        names.len(),
+        body["tools"]["alpha"].as_array().unwrap().len() * 2
+    );
+
+    let (status, body) =
+        call_agent_tool(&url, "writer", "beta/echo", json!({ "text": "hi" })).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["leaf_mcp_id"], "beta");
+    assert_eq!(body["tool"], "echo");
+    assert_eq!(body["result"]["content"][0]["text"], "hi");
+
+    // MCPs the agent may not use and names without an MCP are refused
+    let (status, body) =
+        call_agent_tool(&url, "writer", "other/echo", json!({ "text": "hi" })).await;
+    assert_eq!(status, 403, "{}", body);
+    let (status, _) = call_agent_tool(&url, "writer", "echo", json!({ "text": "hi" })).await;
+    assert_eq!(status, 400);
+    let (status, _) = call_agent_tool(&url, "nobody", "alpha/echo", json!({})).await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn agent_tools_are_shaped_by_the_agents_settings() {
+    let echo = || McpTransport::Builtin {
+        kind: BuiltinMcpKind::Echo,
+    };
+    let service = service(vec![("alpha", echo()), ("beta", echo())]).await;
+    service
+        .update_leaf_mcp(
+            "beta",
+            json!({ "config": { "tool_priority": ["fail_with"] } }),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    service
+        .create_agent(
+            Some("writer".to_string()),
+            None,
+            vec!["alpha".to_string(), "beta".to_string()],
+            None,
+        )
+        .await
+        .unwrap();
+    service
+        .update_agent(
+            "writer",
+            json!({ "config": {
+                "max_tools": 3,
+                "tool_priority_overrides": ["beta/sleep_ms"],
+                "max_tool_description": 10
+            } }),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    let url = listen(service.clone()).await;
+    let names = |body: &Value, mcp: &str| -> Vec<String> {
+        body["tools"][mcp]
+            .as_array()
+            .map(|tools| {
+                tools
+                    .iter()
+                    .map(|tool| tool["name"].as_str().unwrap().to_string())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    // The agent's override first, then beta's priority, then by MCP id
+    let (status, body) = get_tools(&format!("{}/admin/agent/writer/tools", url)).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(names(&body, "beta"), ["sleep_ms", "fail_with"]);
+    assert_eq!(names(&body, "alpha"), ["echo"]);
+    assert_eq!(body["tools"]["alpha"][0]["description"], "Return the…");
+    let shaping = &body["shaping"];
+    assert_eq!(shaping["listed"], 6);
+    assert_eq!(shaping["returned"], 3);
+    assert_eq!(shaping["omitted"], json!({ "max_tools": 3 }));
+    assert_eq!(
+        shaping["omitted_tools"],
+        json!(["alpha/sleep_ms", "alpha/fail_with", "beta/echo"])
+    );
+    assert_eq!(shaping["truncated_descriptions"], 3);
+
+    // Omitted tools can be asked for specifically
+    let (_, body) = get_tools(&format!("{}/admin/agent/writer/tools?filter=*_*", url)).await;
+    assert_eq!(names(&body, "alpha"), ["sleep_ms"]);
+    assert_eq!(
+        body["shaping"]["omitted"],
+        json!({ "filter": 2, "max_tools": 1 })
+    );
+    let (_, body) = get_tools(&format!("{}/admin/agent/writer/tools?filter=alpha/*", url)).await;
+    assert_eq!(names(&body, "alpha"), ["echo", "sleep_ms", "fail_with"]);
+    assert!(body["tools"].get("beta").is_none());
+    assert_eq!(body["shaping"]["omitted"], json!({ "filter": 3 }));
+
+    // Unshaped listings stay as they were
+    service
+        .update_agent("writer", json!({ "config": null }), None, None)
+        .await
+        .unwrap();
+    let (_, body) = get_tools(&format!("{}/admin/agent/writer/tools", url)).await;
+    assert!(body.get("shaping").is_none());
+    assert_eq!(names(&body, "beta"), ["echo", "sleep_ms", "fail_with"]);
+
+    // Settings are checked when they are stored
+    assert!(
+        service
+            .update_agent(
+                "writer",
+                json!({ "config": { "max_tools": 0 } }),
+                None,
+                None
+            )
+            .await
+            .is_err()
+    );
+    assert!(
+        service
+            .update_leaf_mcp(
+                "alpha",
+                json!({ "config": { "tool_priority": "echo" } }),
+                None,
+                None
+            )
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn agent_tool_filters_hide_and_refuse_tools() {
+    let echo = || McpTransport::Builtin {
+        kind: BuiltinMcpKind::Echo,
+    };
+    let service = service(vec![("alpha", echo()), ("beta", echo())]).await;
+    service
+        .create_agent(
+            Some("writer".to_string()),
+            None,
+            vec!["alpha".to_string()],
+            None,
+        )
+        .await
+        .unwrap();
+    let url = listen(service.clone()).await;
+    let client = reqwest::Client::new();
+    let set_filter = |mcp: &str, filter: Value| {
+        client
+            .put(format!(
+                "{}/admin/agent/writer/allowed_mcps/{}/tools",
+                url, mcp
+            ))
+            .json(&json!({ "filter": filter, "reason": "no failures" }))
+            .send()
+    };
+    let response = set_filter("alpha", json!({ "deny": ["fail_*"] }))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    // Only MCPs the agent may use can be filtered
+    let response = set_filter("beta", json!({ "allow": ["echo"] }))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+
+    let (_, body) = get_tools(&format!("{}/admin/agent/writer/tools", url)).await;
+    let names: Vec<&str> = body["tools"]["alpha"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tool| tool["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["echo", "sleep_ms"]);
+    let (_, remote) = get_tools(&format!("{}/agent/writer/config", url)).await;
+    assert_eq!(
+        remote["mcps"]["alpha"]["tool_filter"],
+        json!({ "deny": ["fail_*"] })
+    );
+
+    let call = |tool: &str| {
+        client
+            .post(format!("{}/leaf/alpha/forwarding", url))
+            .header("x-mception-agent-id", "writer")
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 7,
+                "method": "tools/call",
+                "params": { "name": tool, "arguments": { "message": "hi" } }
+            }))
+            .send()
+    };
+    let response = call("fail_with").await.unwrap();
+    assert_eq!(response.status(), 403);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["id"], 7);
+    assert_eq!(body["error"]["code"], -32012);
+    assert_eq!(body["error"]["data"]["code"], "not_allowed");
+    assert_eq!(call("echo").await.unwrap().status(), 200);
+
+    let decision = service
+        .explain_access("writer", "alpha", Some("fail_with"))
+        .await
+        .unwrap();
+    assert!(!decision.allowed);
+    let entries = service.get_audit_logs().await.unwrap();
+    let target = json!({ "type": "agent_tool_filter", "agent_id": "writer", "mcp_id": "alpha" });
+    assert!(
+        entries
+            .iter()
+            .any(|entry| serde_json::to_value(&entry.target).unwrap() == target)
+    );
+
+    // A null filter allows every tool again
+    let response = set_filter("alpha", Value::Null).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(call("fail_with").await.unwrap().status(), 200);
+}